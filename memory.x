@@ -1,12 +1,18 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 28K
+    /* Last 28K of the flash reserved for persistent storage - see system/flash.rs */
     RAM   : ORIGIN = 0x20000000, LENGTH = 255K
     PANDUMP : ORIGIN = 0x2003FC00, LENGTH = 1K
 }
 _panic_dump_start = ORIGIN(PANDUMP);
 _panic_dump_end   = ORIGIN(PANDUMP) + LENGTH(PANDUMP);
 
+/* Bottom of the main stack, see system/stack_guard.rs. flip-link (see .cargo/config.toml) moves
+   the RAM region up to the statics at the top of the RAM and lets the stack grow down from them,
+   ORIGIN(RAM) is then the start of the statics: the real RAM origin is repeated here. */
+_stack_bottom = 0x20000000;
+
 EXTERN(BOOT2_FIRMWARE)
 
 SECTIONS {
@@ -17,3 +23,13 @@ SECTIONS {
     } > BOOT2
 } INSERT BEFORE .text;
 
+SECTIONS {
+    /* ### Commands registered with register_command!, see cli/commands.rs */
+    .cli_commands : ALIGN(4)
+    {
+        __start_cli_commands = .;
+        KEEP(*(.cli_commands .cli_commands.*));
+        __stop_cli_commands = .;
+    } > FLASH
+} INSERT AFTER .rodata;
+
@@ -1,44 +1,136 @@
 //! Commands Module
 
 pub mod base;
+pub mod buses;
 pub mod examples;
+pub mod files;
+pub mod instrumentation;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod security;
+pub mod storage;
 
 pub use base::*;
+pub use buses::*;
 pub use examples::*;
+pub use files::*;
+pub use instrumentation::*;
+#[cfg(feature = "mock")]
+pub use mock::*;
+pub use security::*;
+pub use storage::*;
 
 pub use super::*;
 
-// —————————————————————————————————————————————————————————————————————————————————————————————————
-//                                             Globals
-// —————————————————————————————————————————————————————————————————————————————————————————————————
-
-const MAX_CMDS: usize = 20;
+use crate::system::cmd_timeout::CMD_TIMEOUT;
+use crate::system::settings::{self, SETTINGS};
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                      Command List Builder
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
 /// Command List builder
-/// Register new commands in the function below.
+/// Register new commands in the function below, or from their own file with `register_command!`.
 pub fn build() -> CommandList {
     let mut command_list = CommandList::default();
 
     // Base
     command_list.register_command(build_reset_cmd());
     command_list.register_command(build_flash_cmd());
+    command_list.register_command(build_arm_cmd());
+    command_list.register_command(build_estop_cmd());
+    command_list.register_command(build_board_cmd());
+    command_list.register_command(build_power_mode_cmd());
+    command_list.register_command(build_button_map_cmd());
+    command_list.register_command(build_bootsel_cmd());
+    command_list.register_command(build_identify_cmd());
+    command_list.register_command(build_idn_cmd());
+    command_list.register_command(build_hello_cmd());
+    command_list.register_command(build_set_cmd());
+    command_list.register_command(build_delay_cmd());
     command_list.register_command(build_pin_cmd());
-    command_list.register_command(build_read_adc_cmd());
-    command_list.register_command(build_sample_adc_cmd());
+    command_list.register_command(build_txn_cmd());
+    command_list.register_command(build_setup_cmd());
+    command_list.register_command(build_guard_cmd());
+    command_list.register_command(build_mirror_cmd());
+    command_list.register_command(build_tpo_cmd());
+    command_list.register_command(build_encoder_sim_cmd());
+    command_list.register_command(build_playback_cmd());
+    command_list.register_command(build_play_cmd());
+    command_list.register_command(build_choreo_cmd());
+    command_list.register_command(build_led_cmd());
+    command_list.register_command(build_statusled_cmd());
     command_list.register_command(build_pwm_cmd());
+    command_list.register_command(build_pwm_status_cmd());
+    command_list.register_command(build_pwm_sync_cmd());
     command_list.register_command(build_log_cmd());
+    command_list.register_command(build_random_cmd());
+    command_list.register_command(build_echo_cmd());
+    command_list.register_command(build_jobs_cmd());
+    command_list.register_command(build_fg_cmd());
+    command_list.register_command(build_kill_cmd());
+    command_list.register_command(build_soak_cmd());
+
+    // Instrumentation
+    command_list.register_command(build_pinstats_cmd());
+    command_list.register_command(build_capture_cmd());
+    command_list.register_command(build_read_adc_cmd());
+    command_list.register_command(build_adc_cfg_cmd());
+    command_list.register_command(build_sample_adc_cmd());
+    command_list.register_command(build_stream_adc_cmd());
+    command_list.register_command(build_scope_cmd());
+    command_list.register_command(build_plot_adc_cmd());
+    command_list.register_command(build_comparator_cmd());
+    command_list.register_command(build_snapshot_cmd());
+    command_list.register_command(build_freq_sweep_cmd());
+    command_list.register_command(build_clkout_cmd());
+    command_list.register_command(build_fc_cmd());
+    command_list.register_command(build_clocks_cmd());
+    command_list.register_command(build_tacho_cmd());
+    command_list.register_command(build_trace_cmd());
+    command_list.register_command(build_mem_cmd());
+    command_list.register_command(build_cpu_cmd());
+    command_list.register_command(build_events_cmd());
+    command_list.register_command(build_eventlog_cmd());
+    command_list.register_command(build_watch_cmd());
+
+    // Storage
+    command_list.register_command(build_profile_cmd());
+    command_list.register_command(build_config_cmd());
+    command_list.register_command(build_kv_cmd());
+
+    // Security
+    command_list.register_command(build_secure_cmd());
+    command_list.register_command(build_auth_cmd());
+
+    // Buses
+    command_list.register_command(build_i2c_cmd());
+    command_list.register_command(build_spi_cmd());
+    command_list.register_command(build_dev_cmd());
+    command_list.register_command(build_bus_trace_cmd());
+    command_list.register_command(build_uart_bridge_cmd());
+    command_list.register_command(build_sbus_cmd());
+
+    // Files
+    command_list.register_command(build_files_cmd());
+    command_list.register_command(build_cat_cmd());
+    command_list.register_command(build_download_cmd());
+    command_list.register_command(build_crc_cmd());
+
+    // Mock
+    #[cfg(feature = "mock")]
+    command_list.register_command(build_mock_cmd());
 
     // Examples
-    command_list.register_command(build_example_cmd());
     command_list.register_command(build_blink_cmd());
+    command_list.register_command(build_morse_cmd());
     command_list.register_command(build_blink_multicore_cmd());
     command_list.register_command(build_sleep_multicore_cmd());
     command_list.register_command(build_servo_cmd());
+    command_list.register_command(build_drivers_cmd());
+    command_list.register_command(build_drv_cmd());
     command_list.register_command(build_dht22_cmd());
+    command_list.register_command(build_scale_cmd());
 
     // Test
     command_list.register_command(build_test_gpio_cmd());
@@ -47,21 +139,75 @@ pub fn build() -> CommandList {
     command_list.register_command(build_test_log_cmd());
     command_list.register_command(build_serial_bench_cmd());
 
+    // Registered from their own files
+    for command in registered_commands() {
+        command_list.register_command(*command);
+    }
+
     command_list
 }
 
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                       Registered Commands
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Places a command in the `.cli_commands` linker section, picked up by `build()`.
+/// Adding a command becomes a change local to its file.
+///
+/// Example:
+/// ```rust
+/// register_command!(Command {
+///     name:     "hello",
+///     desc:     "Says hello",
+///     help:     "hello [help]",
+///     category: Category::Dev,
+///     requires: &[],
+///     func:     hello_cmd,
+///     timeout:  None,
+/// });
+/// ```
+#[macro_export]
+macro_rules! register_command {
+    ($command:expr) => {
+        const _: () = {
+            #[used]
+            #[unsafe(link_section = ".cli_commands")]
+            static COMMAND: $crate::cli::commands::Command = $command;
+        };
+    };
+}
+
+unsafe extern "C" {
+    // Bounds of the .cli_commands section, see memory.x
+    static __start_cli_commands: u8;
+    static __stop_cli_commands: u8;
+}
+
+/// Commands placed by `register_command!`, in link order
+fn registered_commands() -> &'static [Command] {
+    let start = (&raw const __start_cli_commands).cast::<Command>();
+    let stop = (&raw const __stop_cli_commands).cast::<Command>();
+
+    // Safety: the section only holds Command statics, laid out as an array by the linker
+    unsafe { core::slice::from_raw_parts(start, stop.offset_from(start) as usize) }
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                          Command List
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
+/// Holds up to N commands, MAX_CMDS by default
 #[derive(Default, Debug)]
-pub struct CommandList {
-    pub commands: Vec<Command, MAX_CMDS>,
+pub struct CommandList<const N: usize = MAX_CMDS> {
+    pub commands: Vec<Command, N>,
 }
 
-impl CommandList {
+impl<const N: usize> CommandList<N> {
+    /// Registers a command, reported and skipped when the list is full
     pub fn register_command(&mut self, command: Command) {
-        let _ = self.commands.push(command);
+        if let Err(command) = self.commands.push(command) {
+            crate::error!("Command list full (max {N}), {} not registered", command.name);
+        }
     }
 
     pub fn get_command(&self, name: &str) -> Result<&Command> {
@@ -73,8 +219,40 @@ impl CommandList {
             Ok(cmd)
         }
         else {
-            Err(Error::CmdNotFound(name.into_truncate()))
+            Err(Error::CmdNotFound(name.into_truncate(), self.suggestions(name)))
+        }
+    }
+
+    /// Parses and runs a command line, for commands invoking other commands
+    pub fn execute(&self, input: &str, context: &mut Context) -> Result<()> {
+        let (cmd_name, input_args) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+
+        // The parser works in place on a copy of the arguments
+        let mut args_buf: Vec<u8, LINE_BUFFER_LENGTH> = Vec::from_slice(input_args.as_bytes())
+            .map_err(|_| Error::CommandTooLong(LINE_BUFFER_LENGTH))?;
+        let cmd_args = parser::parse::<MAX_ARGS>(&mut args_buf)?;
+        self.get_command(cmd_name)?.run(&cmd_args, context)
+    }
+
+    /// Up to 3 names within an edit distance of 2 of the mistyped one, or starting with it
+    fn suggestions(&self, name: &str) -> Suggestions {
+        // (distance, registration index), prefix matches first
+        let mut candidates: Vec<(usize, usize), N> = Vec::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            let prefix = name.len() >= 2 && command.name.starts_with(name);
+            let distance = if prefix { 0 } else { edit_distance(name, command.name) };
+            if distance <= 2 {
+                let _ = candidates.push((distance, index));
+            }
+        }
+        candidates.sort_unstable();
+
+        let mut suggestions = Suggestions::default();
+        for (i, &(_, index)) in candidates.iter().take(3).enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            let _ = write!(suggestions.0, "{separator}{}", self.commands[index].name);
         }
+        suggestions
     }
 
     pub fn get_description(&self, cmd_name: &str) -> Result<&'static str> {
@@ -89,25 +267,131 @@ impl CommandList {
 
 type FunctionCmd = fn(&Command, &[Argument], &mut Context) -> Result<()>;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Category {
+    /// Device, settings, profiles and files
+    Base,
+    /// GPIO, ADC, PWM and buses
+    Io,
+    /// External devices and sensors
+    Drivers,
+    /// Diagnostics, examples and tests
+    Dev,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Base,
+        Category::Io,
+        Category::Drivers,
+        Category::Dev,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Base => "base",
+            Category::Io => "io",
+            Category::Drivers => "drivers",
+            Category::Dev => "dev",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct Command {
-    pub name: &'static str,
-    pub desc: &'static str,
-    pub help: &'static str,
-    pub func: FunctionCmd,
+    pub name:     &'static str,
+    pub desc:     &'static str,
+    pub help:     &'static str,
+    /// Group listed by the built-in help
+    pub category: Category,
+    /// Resources checked before running, see cli::requirements
+    pub requires: &'static [Requirement],
+    pub func:     FunctionCmd,
+    /// Default timeout in ms, aborting the interruptible loops of a stuck command
+    pub timeout:  Option<u32>,
 }
 
 impl Command {
+    /// Runs the command under its timeout guard.
+    /// Nested commands (scripts) run under the guard of the outer one.
     pub fn run(&self, args: &[Argument], context: &mut Context) -> Result<()> {
-        (self.func)(self, args, context)
+        if !args.contains_param("help") {
+            requirements::check(self.requires, args, context)?;
+        }
+
+        let timeout = self.timeout_ms().filter(|_| !CMD_TIMEOUT.is_armed());
+        if let Some(ms) = timeout {
+            CMD_TIMEOUT.arm(ms)?;
+        }
+
+        let result = (self.func)(self, args, context);
+
+        match timeout {
+            Some(ms) if CMD_TIMEOUT.disarm() => Err(Error::TimedOut(ms)),
+            _ => result,
+        }
+    }
+
+    /// Timeout from the "timeout.<cmd>" setting or the default one, 0 disables it
+    pub fn timeout_ms(&self) -> Option<u32> {
+        let mut key = settings::Key::new();
+        let _ = key.push_str("timeout.");
+        let _ = key.push_str(self.name);
+
+        match SETTINGS.get_parsed::<u32>(&key) {
+            Some(0) => None,
+            Some(ms) => Some(ms),
+            None => self.timeout,
+        }
     }
 
     pub fn print_help(&self) {
-        println!("{}", self.desc);
-        println!("{}", self.help)
+        TERM.print_wrapped(self.desc);
+        TERM.print_wrapped(self.help);
+        if let Some(ms) = self.timeout_ms() {
+            println!("Timeout: {ms}ms (setting \"timeout.{}\", 0 disables it)", self.name);
+        }
     }
 
     pub fn print_description(&self) {
         println!("{}", self.desc);
     }
 }
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Levenshtein distance, case insensitive. Names longer than 32 chars are never close.
+fn edit_distance(a: &str, b: &str) -> usize {
+    const MAX_LEN: usize = 32;
+
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() > MAX_LEN || b.len() > MAX_LEN {
+        return usize::MAX;
+    }
+
+    // Single row of the distance matrix
+    let mut row = [0usize; MAX_LEN + 1];
+    for (j, cell) in row.iter_mut().enumerate().take(b.len() + 1) {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        let mut diagonal = row[0];
+        row[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(!a[i - 1].eq_ignore_ascii_case(&b[j - 1]));
+            let value = (row[j] + 1).min(row[j - 1] + 1).min(diagonal + cost);
+            diagonal = row[j];
+            row[j] = value;
+        }
+    }
+    row[b.len()]
+}
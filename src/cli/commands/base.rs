@@ -1,9 +1,49 @@
 //! Core commands
 // Register new commands in commands.rs > Command List Builder
 
+use core::fmt::Write;
+
 use super::*;
+use crate::hal::pwm;
+use crate::main_core1;
 use crate::prelude::*;
-use rp2040_hal::pwm;
+
+use crate::cli::{confirm, jobs, soak};
+use crate::system::audio::{self, Player};
+use crate::system::board::{Board, DEFAULT_BOARD};
+use crate::system::button::{self, BUTTON, Press, Threshold};
+use crate::system::choreo::{self, CHOREO, Keyframe, Sequence, Servo};
+use crate::system::config::PinId;
+use crate::system::dimmer::{self, DIMMER};
+use crate::system::encoder_sim::{self, ENCODER_SIM};
+use crate::system::files::{self, FILES};
+use crate::system::gpios::{self, PinMode, Pull};
+use crate::system::led::{LedMode, Pattern};
+use crate::system::mirror::{MIRRORS, Mirror};
+use crate::system::output_guard::{self, GUARD};
+#[cfg(feature = "panic-persist")]
+use crate::system::panic;
+use crate::system::playback::{self, Event, PLAYBACK, Timeline};
+use crate::system::pwms::{self, Channel};
+use crate::system::rgb_led::Color;
+use crate::system::shutdown::{Reason, SHUTDOWN};
+use crate::system::tpo::TPO;
+use crate::system::txn::{PwmChange, TXN};
+use crate::system::{address,
+                    bootsel,
+                    dry_run,
+                    identity,
+                    power_on,
+                    psu,
+                    safe_mode,
+                    scheduler,
+                    serial_io,
+                    stack_guard,
+                    status};
+use crate::utils::crc::{CRC32, Crc};
+use crate::utils::encoding;
+use crate::utils::time::{Duration, Instant};
+use crate::utils::units::{self, TempUnit};
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                              Reset
@@ -11,10 +51,14 @@ use rp2040_hal::pwm;
 
 pub fn build_reset_cmd() -> Command {
     Command {
-        name: "reset",
-        desc: "Resets Device",
-        help: "reset [help]",
-        func: reset_cmd,
+        name:     "reset",
+        desc:     "Resets Device",
+        help:     "reset [confirm=yes] [help]\n
+    confirm : needed with set confirm=on, unless armed, see arm",
+        category: Category::Base,
+        requires: &[Requirement::Confirmed],
+        func:     reset_cmd,
+        timeout:  None,
     }
 }
 
@@ -25,8 +69,10 @@ pub fn reset_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
         return Ok(());
     }
 
+    SHUTDOWN.run(Reason::Reset);
     print!("\nResetting...\n");
     device.timer.delay_ms(500); // Waiting for msg to appear
+    safe_mode::command_finished(&mut device.watchdog); // Intended reset
     device_reset();
     Ok(())
 }
@@ -37,10 +83,14 @@ pub fn reset_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
 
 pub fn build_flash_cmd() -> Command {
     Command {
-        name: "flash",
-        desc: "Restart device in USB Flash mode",
-        help: "flash [help]",
-        func: flash_cmd,
+        name:     "flash",
+        desc:     "Restart device in USB Flash mode",
+        help:     "flash [confirm=yes] [help]\n
+    confirm : needed with set confirm=on, unless armed, see arm",
+        category: Category::Base,
+        requires: &[Requirement::Confirmed],
+        func:     flash_cmd,
+        timeout:  None,
     }
 }
 
@@ -51,203 +101,2004 @@ pub fn flash_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
         return Ok(());
     }
 
+    SHUTDOWN.run(Reason::Flash);
     print!("\nRestarting in USB Flash mode!...\n");
+    safe_mode::command_finished(&mut device.watchdog); // Intended reset
     device_reset_to_usb();
 
     Ok(())
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
-//                                             Set Pin
+//                                               Arm
 // —————————————————————————————————————————————————————————————————————————————————————————————————
+// Allows one destructive command (reset, flash) in the next 10s with set confirm=on
+// ex: arm
+// ex: arm off
 
-pub fn build_pin_cmd() -> Command {
+pub fn build_arm_cmd() -> Command {
     Command {
-        name: "pin",
-        desc: "Read or Set the GPIO Pin State",
-        help: "pin [alias=OUT_A(str)] / [gpio=..(u8)] [read(default)] [toggle] [high] [low] [help]",
-        func: pin_cmd,
+        name:     "arm",
+        desc:     "Allows the next reset or flash when confirmations are on",
+        help:     "arm [off] [help]\n
+    With set confirm=on, reset and flash need confirm=yes or an arm in the previous 10s.
+    The arm is used up by the first of them
+    off : cancels a pending arm",
+        category: Category::Base,
+        requires: &[],
+        func:     arm_cmd,
+        timeout:  None,
     }
 }
 
-pub fn pin_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+pub fn arm_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
     if args.contains_param("help") {
         cmd.print_help();
         return Ok(());
     }
 
-    const DEFAULT_PIN: &str = "OUT_A";
+    if args.contains_param("off") {
+        confirm::disarm();
+        println!("Disarmed");
+        return Ok(());
+    }
 
-    // Getting Alias or GPIO input -----------
-    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
-    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    confirm::arm();
+    println!("Armed for {}s: reset, flash", confirm::ARM_WINDOW.as_secs_f32());
+    if !confirm::is_enabled() {
+        println!("Confirmations are off, see set confirm");
+    }
+    Ok(())
+}
 
-    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
-    // -------------------------------------
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Emergency Stop
+// —————————————————————————————————————————————————————————————————————————————————————————————————
 
-    let toggle = args.contains_param("toggle");
-    let high = args.contains_param("high");
-    let low = args.contains_param("low");
+pub fn build_estop_cmd() -> Command {
+    Command {
+        name:     "estop",
+        desc:     "Stops every output and service, without resetting",
+        help:     "estop [list] [help]\n
+    Runs the shutdown hooks also run by reset and flash: services stopped, outputs back to
+    their power-on level, PWM slices disabled. Background jobs are killed.
+    list : shows the hooks in run order without running them",
+        category: Category::Base,
+        requires: &[],
+        func:     estop_cmd,
+        timeout:  None,
+    }
+}
 
-    // Setting pin Mode
-    if high || low || toggle {
-        let pin = device.outputs.get(gpio)?;
+pub fn estop_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
 
-        // Set mode
-        if high {
-            println!("> Output Pin: GPIO {gpio} - {alias}: set HIGH");
-            pin.set_high().unwrap();
+    if !args.contains_param("list") {
+        jobs::kill_all();
+        SHUTDOWN.run(Reason::Estop);
+        device.led.set_mode(LedMode::Estop);
+        println!("Emergency stop, hooks run:");
+    }
+
+    for hook in SHUTDOWN.hooks().iter() {
+        println!("> {:<8}: {}", hook.stage, hook.name);
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Board
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: board set=weact
+
+pub fn build_board_cmd() -> Command {
+    Command {
+        name:     "board",
+        desc:     "Shows or selects the board profile",
+        help:     "board [set=pico|pico_w|weact|default(str)] [help]\n
+    set : stores the board used at boot, applied after a reset
+          default restores the board selected at build time",
+        category: Category::Base,
+        requires: &[],
+        func:     board_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn board_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("set") {
+        if name == "default" {
+            SETTINGS.remove("board");
         }
-        else if low {
-            println!("> Output Pin: GPIO {gpio}: set LOW");
-            pin.set_low().unwrap();
+        else {
+            let board: Board = name
+                .parse()
+                .map_err(|_| Error::Parse("set".into_truncate()))?;
+            SETTINGS.set("board", board)?;
         }
-        else if toggle {
-            print!("> Output Pin: GPIO {gpio}: Toggled ");
-            pin.toggle().unwrap();
-            if pin.is_set_high().unwrap() {
-                println!("HIGH")
-            }
-            else {
-                println!("LOW")
+        SETTINGS.save()?;
+        println!("Board saved, reset to apply");
+        return Ok(());
+    }
+
+    println!("Board: {}", *BOARD);
+    println!("Build default: {DEFAULT_BOARD}");
+
+    let vsys = if BOARD.vsys_sense { "ADC3 (VSYS/3)" } else { "-" };
+    println!("VSYS sense: {vsys}");
+    match BOARD.vbus_sense {
+        Some(gpio) => println!("VBUS sense: GPIO {gpio}"),
+        None => println!("VBUS sense: -"),
+    }
+    for button in BOARD.buttons {
+        println!("Button: {button}");
+    }
+    if let Some(gpio) = BOARD.psu_mode {
+        println!("PSU mode: GPIO {gpio}, see power_mode");
+    }
+
+    println!("\nOnboard pins:");
+    for def in BOARD.pins {
+        if let PinId::Gpio(gpio) = def.id {
+            println!("  GPIO {gpio:>2} - {} ({})", def.alias, def.group);
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Power Mode
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Onboard regulator mode, see system::psu
+// ex: power_mode pwm
+
+pub fn build_power_mode_cmd() -> Command {
+    Command {
+        name:     "power_mode",
+        desc:     "Onboard regulator mode, efficiency or low ripple",
+        help:     "power_mode [pfm|pwm] [help]\n
+    pfm : power saving (default), best efficiency at light load, larger ripple on 3V3
+    pwm : fixed frequency, lower ripple on 3V3 and the ADC reference, a few mA more
+    Pico only (GP23, RT6150B P-Select). Saved to flash and applied at boot",
+        category: Category::Base,
+        requires: &[],
+        func:     power_mode_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn power_mode_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(arg) = args.first() {
+        psu::set(arg.param.parse()?)?;
+    }
+
+    match psu::mode() {
+        Some(mode) => {
+            let source = if psu::is_saved() { "saved" } else { "regulator default" };
+            println!("Power mode: {mode} ({source})");
+        }
+        None => println!("Power mode: - ({})", psu::Error::Unsupported),
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Button Map
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Command lines run by the board key, see system::button
+// ex: button_map short="pin alias=OUT_A toggle"
+// ex: button_map long=off
+// ex: button_map rescue_ms=5000 debounce_ms=50
+
+pub fn build_button_map_cmd() -> Command {
+    Command {
+        name:     "button_map",
+        desc:     "Maps the board button presses to command lines",
+        help:     "button_map [short=\"..\"|off|default] [long=\"..\"|off|default] \
+                   [double=\"..\"|off|default] [debounce_ms=30(10-200)|default] \
+                   [long_ms=1500(300-10000)|default] [rescue_ms=3000(1000-30000|0)|default] \
+                   [help]\n
+    short     : released before long_ms, ex: short=\"pin alias=OUT_A toggle\"
+    long      : held for long_ms, default \"flash confirm=yes\" (USB flash mode)
+    double    : two presses within 400ms, default \"identify\"
+    rescue_ms : held this long enters the USB flash mode from the interrupt, even with the CLI
+                stuck in a loop, above long_ms or 0 to disable it
+    Lines run as if typed, also without a host connection, 32 chars at most
+    WeAct board only, the Pico and Pico W have no user key on GP23",
+        category: Category::Base,
+        requires: &[],
+        func:     button_map_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn button_map_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let Some(gpio) = BUTTON.gpio()
+    else {
+        return Err("no BUTTON pin on this board, the user key is on the WeAct board".into());
+    };
+
+    let mut changed = false;
+    for press in Press::ALL {
+        match args.get_str_param(press.name()) {
+            Some("default") => button::reset_action(press),
+            Some("off") => button::set_action(press, None)?,
+            Some(line) => button::set_action(press, Some(line.trim()))?,
+            None => continue,
+        }
+        changed = true;
+    }
+    let mut thresholds = heapless::Vec::<_, { Threshold::ALL.len() }>::new();
+    for threshold in Threshold::ALL {
+        let ms = match args.get_str_param(threshold.name()) {
+            Some("default") => None,
+            Some("0") if threshold == Threshold::Rescue => Some(0),
+            Some(_) => Some(args.get_ranged_param(threshold.name(), threshold.range())?),
+            None => continue,
+        };
+        let _ = thresholds.push((threshold, ms));
+    }
+    if !thresholds.is_empty() {
+        button::set_thresholds(&thresholds)?;
+        changed = true;
+    }
+    if changed {
+        SETTINGS.save()?;
+    }
+
+    println!("Button: GPIO {gpio} ({})", button::ALIAS);
+    for press in Press::ALL {
+        match button::action(press) {
+            Some(line) => println!("  {:<6} : {line}", press.name()),
+            None => println!("  {:<6} : off", press.name()),
+        }
+    }
+    for threshold in Threshold::ALL {
+        match button::threshold(threshold) {
+            0 => println!("  {:<11} : off", threshold.name()),
+            ms => println!("  {:<11} : {ms}ms", threshold.name()),
+        }
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Bootsel
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// The BOOTSEL key as a second button, see system::bootsel
+// ex: bootsel
+// ex: bootsel wait timeout=30s
+
+const BOOTSEL_POLL_MS: u32 = 20;
+
+pub fn build_bootsel_cmd() -> Command {
+    Command {
+        name:     "bootsel",
+        desc:     "Reads the BOOTSEL button",
+        help:     "bootsel [wait [timeout=..(ms|s)]] [help]\n
+    Each read parks core1 and the interrupts for about 20us, the flash being deselected
+    wait    : until pressed, checked every 20ms, send '~' to exit
+    timeout : error if not pressed in time, ex: 30s
+    Mirror rules take input=BOOTSEL as well",
+        category: Category::Io,
+        requires: &[],
+        func:     bootsel_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn bootsel_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if !args.contains_param("wait") {
+        let state = if bootsel::is_pressed()? { "pressed" } else { "released" };
+        println!("BOOTSEL: {state}");
+        return Ok(());
+    }
+
+    let timeout = match args.get_str_param("timeout") {
+        Some(value) => {
+            let ms = parse_duration_ms(value).ok_or(Error::Parse("timeout".into_truncate()))?;
+            Some(Duration::from_millis(ms as u64))
+        }
+        None => None,
+    };
+
+    println!("Waiting for BOOTSEL, send '~' to exit");
+    SERIAL.clear_interrupt_cmd();
+    let start = Instant::now();
+    loop {
+        if bootsel::is_pressed()? {
+            println!("BOOTSEL pressed after {}", start.elapsed());
+            return Ok(());
+        }
+        if timeout.is_some_and(|timeout| start.has_elapsed(timeout)) {
+            return Err("BOOTSEL not pressed in time".into());
+        }
+        if SERIAL.interrupt_cmd_triggered() {
+            println!("Interrupted");
+            return Ok(());
+        }
+        device.timer.delay_ms(BOOTSEL_POLL_MS);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Identify
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Finds the board behind a port: the LED flashes and the board id matches the USB serial number
+// Host tools can send *IDN? to every port, see system::identity
+
+pub fn build_identify_cmd() -> Command {
+    Command {
+        name:     "identify",
+        desc:     "Blinks the LED and prints the board id",
+        help:     "identify [time=10s] [stop] [help]\n
+    Overlays a triple flash on the LED for the given time, 0 or stop ends it
+    The board id is the flash unique id, also the USB serial number",
+        category: Category::Base,
+        requires: &[],
+        func:     identify_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn identify_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_MS: u32 = 10_000;
+
+    let time_ms = match args.get_str_param("time") {
+        Some(value) => parse_duration_ms(value).ok_or(Error::Parse("time".into_truncate()))?,
+        None => DEFAULT_MS,
+    };
+    let secs = if args.contains_param("stop") { 0 } else { time_ms.div_ceil(1000) };
+
+    device.led.identify(secs);
+
+    println!("Board id: {}", identity::serial_number());
+    println!("Board: {} | v{}", *BOARD, identity::VERSION);
+    if !device.led.is_available() {
+        println!("No LED on this board, only the id is reported");
+    }
+    else if secs > 0 {
+        println!("Identifying for {secs}s");
+    }
+    Ok(())
+}
+
+/// Instrument identification query, one line: manufacturer,product,board id,version
+pub fn build_idn_cmd() -> Command {
+    Command {
+        name:     "*idn?",
+        desc:     "Identification query for host tools",
+        help:     "*idn? [help]\n
+    Prints \"<manufacturer>,<product>,<board id>,<version>\" as SCPI instruments do",
+        category: Category::Base,
+        requires: &[],
+        func:     idn_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn idn_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    println!(
+        "{},{},{},{}",
+        identity::MANUFACTURER,
+        identity::PRODUCT,
+        identity::serial_number(),
+        identity::VERSION
+    );
+    Ok(())
+}
+
+/// Handshake of scripted hosts, one line then the quiet mode for the rest of the connection
+pub fn build_hello_cmd() -> Command {
+    Command {
+        name:     "hello",
+        desc:     "Handshake for host scripts, sets the quiet mode of the connection",
+        help:     "hello [quiet|verbose] [help]\n
+    Prints \"HELLO <product>,<version> quiet=on|off\"
+    quiet   : command output then #OK or #ERR <code>, no banners or prompt until the connection \
+                   ends
+    verbose : the usual banners and prompt until the connection ends
+    Without argument the quiet setting applies (set quiet=on|off)",
+        category: Category::Base,
+        requires: &[],
+        func:     hello_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn hello_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("quiet") {
+        status::set_session_quiet(Some(true));
+    }
+    else if args.contains_param("verbose") {
+        status::set_session_quiet(Some(false));
+    }
+
+    let quiet = if status::is_quiet() { "on" } else { "off" };
+    println!("HELLO {},{} quiet={quiet}", identity::PRODUCT, identity::VERSION);
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Set
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Terminal, unit, status line and prompt preferences, saved to the settings
+// ex: set width=60
+// ex: set width=auto
+// ex: set temp=f mv=off
+// ex: set hostname=bench-1 prompt="%h [%n]>"
+
+pub fn build_set_cmd() -> Command {
+    Command {
+        name:     "set",
+        desc:     "Shows or changes the terminal, unit, status line and prompt preferences",
+        help:     "set [width=..(u16)|auto] [temp=c|f] [mv=on|off] [ohm=on|off] \
+                   [status=\"..\"|off|default] [prompt=\"..\"|default] [hostname=..(str)] \
+                   [address=1-99|off] [dtrgrace=..(ms)] [regreet=..(s)] [quiet=on|off] \
+                   [guardreset=on|off] [panicmorse=on|off] [confirm=on|off] [core1=boot|lazy|off] \
+                   [help]\n
+    width    : fixed terminal width used to wrap the help and tables
+               auto queries the terminal size (ANSI cursor position report)
+    temp     : temperature unit
+    mv       : voltages below 1V displayed in mV
+    ohm      : resistances scaled to kohm/Mohm
+    status   : fields of the line printed before the prompt, space separated
+               temp a3 adc0-adc2 vbus gpioN time, ex: status=\"temp adc0 time\"
+    prompt   : prompt text, %h hostname, %t uptime, %n command number, %% a literal %
+               ex: prompt=\"%h [%n]>\"
+    hostname : board name shown by %h, letters, digits, '-', '_' and '.'
+    address  : bus address, lines prefixed \"@NN \" only run on the board with address NN,
+               \"@* \" on all boards, ex: @03 pin alias=LED toggle
+    dtrgrace : ms DTR can drop without ending the connection, 0 follows DTR directly
+    regreet  : s after a greeting during which a reconnection is not greeted again,
+               0 greets every connection
+    quiet    : for scripts, no greeting, status line, prompt, echo or RUNNING/DONE banners,
+               a command prints its output then #OK or #ERR <code>, see also hello
+    guardreset : reset on a stack overflow or a hung core1, see mem
+    panicmorse : a panic blinks the start of its message in Morse on the LED instead of SOS,
+                 panic-persist builds
+    confirm  : reset and flash need confirm=yes or a prior arm, against misfiring scripts
+    core1    : started at boot, by the first command needing it, or never,
+               applied at the next boot
+    dryrun   : on/off, actuator commands (pin, pwm, servo) only print what they would do,
+               not saved, per command with the dryrun flag",
+        category: Category::Base,
+        requires: &[],
+        func:     set_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn set_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(width) = args.get_str_param("width") {
+        if width == "auto" {
+            TERM.set_fixed_width(None)?;
+            TERM.detect();
+        }
+        else {
+            let width = width
+                .parse::<u16>()
+                .map_err(|_| Error::Parse("width".into_truncate()))?;
+            TERM.set_fixed_width(Some(width))?;
+        }
+    }
+
+    // Units and quiet mode
+    let mut changed = false;
+    if let Some(unit) = args.get_str_param("temp") {
+        let unit: TempUnit = unit
+            .parse()
+            .map_err(|_| Error::Parse("temp".into_truncate()))?;
+        SETTINGS.set(units::TEMP_KEY, unit)?;
+        changed = true;
+    }
+    for (param, key) in [
+        ("mv", units::MILLIVOLTS_KEY),
+        ("ohm", units::OHM_SCALE_KEY),
+        ("quiet", status::QUIET_KEY),
+        ("guardreset", stack_guard::RESET_KEY),
+        ("confirm", confirm::KEY),
+        #[cfg(feature = "panic-persist")]
+        ("panicmorse", panic::MORSE_KEY),
+    ] {
+        if let Some(state) = args.get_str_param(param) {
+            let enabled = match state {
+                "on" | "true" | "1" => true,
+                "off" | "false" | "0" => false,
+                _ => return Err(Error::Parse(param.into_truncate())),
+            };
+            SETTINGS.set(key, enabled)?;
+            changed = true;
+        }
+    }
+    // The setting replaces the mode of a hello handshake
+    if args.contains_param("quiet") {
+        status::set_session_quiet(None);
+    }
+    if args.contains_param("guardreset") {
+        stack_guard::set_reset_on_fault(SETTINGS.get_parsed(stack_guard::RESET_KEY) == Some(true));
+    }
+    #[cfg(feature = "panic-persist")]
+    if args.contains_param("panicmorse") {
+        panic::set_morse(SETTINGS.get_parsed(panic::MORSE_KEY) == Some(true));
+    }
+
+    if let Some(mode) = args.get_str_param("core1") {
+        let mode: main_core1::Mode = mode
+            .parse()
+            .map_err(|_| Error::Parse("core1".into_truncate()))?;
+        SETTINGS.set(main_core1::MODE_KEY, mode)?;
+        changed = true;
+    }
+
+    // Status line
+    if let Some(template) = args.get_str_param("status") {
+        let template = template.trim();
+        if template == "default" {
+            SETTINGS.remove(status::STATUS_KEY);
+        }
+        else if status::is_valid(template) {
+            SETTINGS.set(status::STATUS_KEY, template)?;
+        }
+        else {
+            return Err(Error::Parse("status".into_truncate()));
+        }
+        changed = true;
+    }
+
+    // Prompt
+    if let Some(prompt) = args.get_str_param("prompt") {
+        match prompt {
+            "default" => {
+                SETTINGS.remove(status::PROMPT_KEY);
             }
+            prompt => SETTINGS.set(status::PROMPT_KEY, prompt)?,
         }
+        changed = true;
     }
-    // Reading Pin Mode
-    // Input Pin Check
-    else if let Ok(pin) = device.inputs.get(gpio) {
-        println!(
-            "> Input Pin: GPIO {gpio} - {alias}: {}",
-            if pin.is_high().unwrap() { "HIGH" } else { "LOW" }
-        )
+    if let Some(hostname) = args.get_str_param("hostname") {
+        if !status::is_valid_hostname(hostname) {
+            return Err(Error::Parse("hostname".into_truncate()));
+        }
+        SETTINGS.set(status::HOSTNAME_KEY, hostname)?;
+        changed = true;
     }
-    // Output Pin Check
-    else if let Ok(pin) = device.outputs.get(gpio) {
-        println!(
-            "> Output Pin: GPIO {gpio} - {alias}: {}",
-            if pin.is_set_high().unwrap() { "HIGH" } else { "LOW" }
-        )
+    if let Some(value) = args.get_str_param("address") {
+        let value = match value {
+            "off" => None,
+            value => Some(
+                value
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|a| (address::MIN_ADDRESS..=address::MAX_ADDRESS).contains(a))
+                    .ok_or(Error::Parse("address".into_truncate()))?,
+            ),
+        };
+        address::set_address(value)?;
+        changed = true;
+    }
+
+    // Connection
+    if args.contains_param("dtrgrace") {
+        let ms = args.get_parsed_param::<u32>("dtrgrace")?;
+        SETTINGS.set(serial_io::DTR_GRACE_KEY, ms)?;
+        SERIAL.set_dtr_grace_ms(ms);
+        changed = true;
+    }
+    if args.contains_param("regreet") {
+        let secs = args.get_parsed_param::<u32>("regreet")?;
+        SETTINGS.set(status::GREET_QUIET_KEY, secs)?;
+        changed = true;
+    }
+
+    if changed {
+        SETTINGS.save()?;
+    }
+
+    // Dry run, RAM only
+    if let Some(state) = args.get_str_param("dryrun") {
+        match state {
+            "on" | "true" | "1" => dry_run::set_enabled(true),
+            "off" | "false" | "0" => dry_run::set_enabled(false),
+            _ => return Err(Error::Parse("dryrun".into_truncate())),
+        }
+    }
+
+    let mode = if TERM.fixed_width().is_some() { "fixed" } else { "auto" };
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    println!("width    : {} ({mode})", TERM.width());
+    println!("temp     : {}", TempUnit::current());
+    println!("mv       : {}", on_off(units::millivolts()));
+    println!("ohm      : {}", on_off(units::ohm_scale()));
+    println!("status   : {}", status::template());
+    println!("prompt   : {:?}", status::prompt().as_str());
+    println!("hostname : {}", status::hostname());
+    match address::address() {
+        Some(address) => println!("address  : @{address:02}"),
+        None => println!("address  : off"),
+    }
+    println!("dryrun   : {}", on_off(dry_run::is_enabled()));
+    println!("dtrgrace : {}ms", SERIAL.dtr_grace_ms());
+    println!("regreet  : {}s", status::greet_quiet_s());
+    println!("quiet    : {}", on_off(status::is_quiet()));
+    println!("guardreset : {}", on_off(stack_guard::status().reset_on_err));
+    println!("confirm  : {}", on_off(confirm::is_enabled()));
+    #[cfg(feature = "panic-persist")]
+    println!("panicmorse : {}", on_off(panic::is_morse()));
+    println!("core1    : {} ({})", main_core1::mode(), main_core1::state());
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Delay
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn build_delay_cmd() -> Command {
+    Command {
+        name:     "delay",
+        desc:     "Waits for the given time",
+        help:     "delay [ms=1000(ms)] [help]\n
+    Interrupt with char \"~\"",
+        category: Category::Base,
+        requires: &[],
+        func:     delay_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn delay_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let ms: u32 = args.get_parsed_param("ms").unwrap_or(1000); // 1s default
+
+    println!("Waiting {ms}ms... Send '~' to exit");
+
+    let start_time = Instant::now();
+    let elapsed_ms = || start_time.elapsed().as_millis();
+    let mut progress = Progress::new("Delay", ms);
+
+    SERIAL.clear_interrupt_cmd();
+    while elapsed_ms() < ms as u64 {
+        if SERIAL.interrupt_cmd_triggered() {
+            println!("\nDelay interrupted after {}ms", elapsed_ms());
+            return Err(Error::Interrupted);
+        }
+        progress.update(elapsed_ms() as u32);
+    }
+
+    progress.finish();
+    println!("Done!");
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Set Pin
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn build_pin_cmd() -> Command {
+    Command {
+        name:     "pin",
+        desc:     "Read or Set the GPIO Pin State",
+        help:     "pin [alias=OUT_A(str)] / [gpio=..(u8)] [read(default)] [toggle] [high] [low] \
+                   [dryrun] [help]\n        Within a transaction the levels are staged until txn \
+                   commit, see txn",
+        category: Category::Io,
+        requires: &[],
+        func:     pin_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn pin_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "OUT_A";
+
+    // Getting Alias or GPIO input -----------
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+
+    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    // -------------------------------------
+
+    let toggle = args.contains_param("toggle");
+    let high = args.contains_param("high");
+    let low = args.contains_param("low");
+
+    // Setting pin Mode
+    if high || low || toggle {
+        let pin = device.outputs.get(gpio)?;
+
+        // Dry run, the output state is only read
+        if dry_run::is_active(args.contains_param("dryrun")) {
+            let level = |high: bool| if high { "HIGH" } else { "LOW" };
+            let target = if toggle { !pin.is_set_high().unwrap() } else { high };
+            println!("[dryrun] Output Pin: GPIO {gpio} - {alias}: would set {}", level(target));
+            return Ok(());
+        }
+
+        // Transaction, applied by txn commit
+        if TXN.is_open() {
+            let high = if toggle { !TXN.staged_level(gpio) } else { high };
+            TXN.stage_level(gpio, high)?;
+            let level = if high { "HIGH" } else { "LOW" };
+            println!("[txn] Output Pin: GPIO {gpio} - {alias}: {level} staged");
+            return Ok(());
+        }
+
+        // Set mode, within the output guard limits
+        if high {
+            device.outputs.set_output(gpio, true)?;
+            println!("> Output Pin: GPIO {gpio} - {alias}: set HIGH");
+        }
+        else if low {
+            device.outputs.set_output(gpio, false)?;
+            println!("> Output Pin: GPIO {gpio}: set LOW");
+        }
+        else if toggle {
+            let high = device.outputs.toggle_output(gpio)?;
+            println!("> Output Pin: GPIO {gpio}: Toggled {}", if high { "HIGH" } else { "LOW" });
+        }
+    }
+    // Reading Pin Mode
+    // Input Pin Check
+    else if let Ok(pin) = device.inputs.get(gpio) {
+        println!(
+            "> Input Pin: GPIO {gpio} - {alias}: {}",
+            if pin.is_high().unwrap() { "HIGH" } else { "LOW" }
+        )
+    }
+    // Output Pin Check
+    else if let Ok(pin) = device.outputs.get(gpio) {
+        println!(
+            "> Output Pin: GPIO {gpio} - {alias}: {}",
+            if pin.is_set_high().unwrap() { "HIGH" } else { "LOW" }
+        )
+    }
+    else {
+        return Err(Error::Configuration(ConfigError::GpioNotFound));
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Transaction
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Stages pin and pwm changes, applied together on commit, see system::txn
+// ex: txn begin
+// ex: txn commit
+
+pub fn build_txn_cmd() -> Command {
+    Command {
+        name:     "txn",
+        desc:     "Stages pin and PWM changes, applied all at once",
+        help:     "txn [begin] / [commit] / [abort] [help]\n
+    begin  : stages the following pin and pwm changes instead of applying them
+    commit : stops and programs the staged PWM slices, writes the output levels at once, then
+             starts the slices together. The output guard refusing a level applies nothing
+    abort  : drops the staged changes
+    Lists the staged changes when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     txn_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn txn_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("begin") {
+        TXN.begin()?;
+        println!("Transaction open, pin and pwm changes are staged until txn commit");
+        return Ok(());
+    }
+
+    if args.contains_param("abort") {
+        let dropped = TXN.abort()?;
+        println!("Transaction aborted, {dropped} change(s) dropped");
+        return Ok(());
+    }
+
+    if args.contains_param("commit") {
+        let (pins, slices) = TXN.commit(&mut device.pwms)?;
+        println!("Transaction committed: {pins} pin(s), {slices} pwm slice(s) started");
+        return Ok(());
+    }
+
+    if !TXN.is_open() {
+        println!("No open transaction");
+        return Ok(());
+    }
+
+    let (mut levels, mut pwms) = (0, 0);
+    TXN.for_each(
+        |gpio, high| {
+            let alias = CONFIG.get_alias(gpio).unwrap_or("?");
+            println!("  GPIO {gpio:>2} - {alias:<8} | {}", if high { "HIGH" } else { "LOW" });
+            levels += 1;
+        },
+        |change| {
+            let alias = CONFIG.get_alias(change.gpio).unwrap_or("?");
+            match change.cc {
+                Some(cc) => println!(
+                    "  GPIO {:>2} - {alias:<8} | pwm {}hz top {} phase {} cc {cc}",
+                    change.gpio, change.freq, change.top, change.phase
+                ),
+                None => println!("  GPIO {:>2} - {alias:<8} | pwm disable", change.gpio),
+            }
+            pwms += 1;
+        },
+    );
+    println!("\n{levels} level(s), {pwms} pwm change(s) staged");
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Setup Pins
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Moves pins of the Inputs and Outputs groups between input and output in one go
+// ex: setup pins="0:out:low,1:out:high,9:in:pullup"
+// ex: setup pins="OUT_A:in:float,IN_A:out"
+
+pub fn build_setup_cmd() -> Command {
+    Command {
+        name:     "setup",
+        desc:     "Configures several GPIO pins from a descriptor",
+        help:     "setup pins=..(str) / poweron[=<pin>:<state>] [help]\n
+    pins    : comma separated <gpio or alias>:<mode>
+              out[:low|high] (low default) or in[:pullup|pulldown|float] (pullup default)
+    poweron : state applied at boot before any command runs, saved to flash
+              outputs: low|high, PWM pins: pwm:<freq>:<duty %>[:off], default: back to
+              pin_config.rs, ex: poweron=OUT_A:high, poweron=PWM4_A:pwm:50:0:off
+              Lists the power-on states without a value
+    Only the pins of the Inputs and Outputs groups can be configured",
+        category: Category::Base,
+        requires: &[],
+        func:     setup_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn setup_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("poweron") {
+        return setup_power_on(args.get_str_param("poweron"));
+    }
+
+    let descriptor = args
+        .get_str_param("pins")
+        .ok_or(Error::MissingArg("pins".into_truncate()))?;
+
+    // Every entry is validated before touching a pin
+    let entries = gpios::parse_setup(descriptor)?;
+    gpios::configure_all(&mut device.inputs, &mut device.outputs, &entries)?;
+
+    for (gpio, mode) in entries {
+        let alias = CONFIG.get_alias(gpio).unwrap_or("?");
+        match mode {
+            PinMode::Output(high) => {
+                let level = if high { "HIGH" } else { "LOW" };
+                println!("> GPIO {gpio:>2} - {alias:<8}: output {level}");
+            }
+            PinMode::Input(pull) => {
+                let pull = match pull {
+                    Pull::Up => "pull up",
+                    Pull::Down => "pull down",
+                    Pull::Float => "floating",
+                };
+                println!("> GPIO {gpio:>2} - {alias:<8}: input {pull}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the power-on state of a pin from "<pin>:<state>", lists them without a value
+fn setup_power_on(entry: Option<&str>) -> Result<()> {
+    if let Some(entry) = entry {
+        let (pin, state) = entry
+            .split_once(':')
+            .ok_or(Error::Parse("poweron".into_truncate()))?;
+        let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+        let state = match state {
+            "default" => None,
+            state => Some(state.parse()?),
+        };
+        power_on::set(gpio, state)?;
+        SETTINGS.save()?;
+    }
+
+    println!("Power-on states:");
+    power_on::for_each(|gpio, state, source| {
+        let alias = CONFIG.get_alias(gpio).unwrap_or("?");
+        println!("> GPIO {gpio:>2} - {alias:<8}: {state} ({source})");
+    });
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Output Guard
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Limits of the outputs declared in pin_config.rs (OUTPUT_LIMITS)
+// ex: guard
+// ex: guard reset alias=OUT_C
+
+pub fn build_guard_cmd() -> Command {
+    Command {
+        name:     "guard",
+        desc:     "Shows the output protection limits and trips",
+        help:     "guard [reset] [alias=..(str)] / [gpio=..(u8)] [help]\n
+    Relay outputs refuse level changes faster than their limit, a runaway toggling
+    trips them: driven low and held low until reset
+    Motor outputs ramp their PWM duty at their maximum rate
+    reset : clears the trip and the counters of the pin, or of every pin",
+        category: Category::Io,
+        requires: &[],
+        func:     guard_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn guard_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let pin = match args.contains_param("alias") || args.contains_param("gpio") {
+        true => {
+            let alias = args.get_str_param("alias");
+            let gpio = args.get_parsed_param::<u8>("gpio").ok();
+            Some(CONFIG.get_gpio_alias_pair(gpio, alias)?.0)
+        }
+        false => None,
+    };
+
+    if args.contains_param("reset") {
+        GUARD.reset(pin);
+        println!("Output guard reset");
+    }
+
+    let mut count = 0;
+    GUARD.for_each(|status| {
+        if pin.is_some_and(|pin| pin != status.gpio) {
+            return;
+        }
+        count += 1;
+        let alias = CONFIG.get_alias(status.gpio).unwrap_or("?");
+        let state = match (status.tripped, status.ramping) {
+            (true, _) => "TRIPPED",
+            (false, Some(_)) => "ramping",
+            (false, None) => "ok",
+        };
+        println!(
+            "> GPIO {:>2} - {alias:<8}: {} | refused: {} | {state}",
+            status.gpio, status.limit, status.refused
+        );
+    });
+    if count == 0 {
+        println!("No output limits, declare them in pin_config.rs (OUTPUT_LIMITS)");
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Mirror
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Background input to output copy, polled every 1ms until removed or reset
+// ex: mirror input=IN_A output=OUT_B invert=true
+// ex: mirror input=IN_B output=OUT_C debounce=20
+// ex: mirror remove=OUT_B
+
+pub fn build_mirror_cmd() -> Command {
+    Command {
+        name:     "mirror",
+        desc:     "Mirrors input pins to output pins in the background",
+        help:     "mirror [input=IN_A(str)] [output=OUT_A(str)] [invert=false(bool)] \
+                   [debounce=0(ms)]\n       / [remove=OUT_A(str)] / [clear] [help]\n
+    input : an input pin, or BOOTSEL (high while released), read while core1 is stopped
+    Lists the active mirrors when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     mirror_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn mirror_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("clear") {
+        MIRRORS.clear();
+        println!("Mirrors cleared");
+        return Ok(());
+    }
+
+    if let Some(alias) = args.get_str_param("remove") {
+        let (gpio, alias) = CONFIG.get_gpio_alias_pair(None, Some(alias))?;
+        MIRRORS.remove(gpio)?;
+        println!("Mirror to GPIO {gpio} - {alias} removed");
+        return Ok(());
+    }
+
+    let input = args.get_str_param("input");
+    let output = args.get_str_param("output");
+
+    if let (Some(input), Some(output)) = (input, output) {
+        let (gpio_input, input) = match bootsel::is_alias(input) {
+            true => (bootsel::GPIO, bootsel::ALIAS),
+            false => CONFIG.get_gpio_alias_pair(None, Some(input))?,
+        };
+        let (gpio_output, output) = CONFIG.get_gpio_alias_pair(None, Some(output))?;
+
+        // Pins have to be registered in their group
+        if gpio_input != bootsel::GPIO {
+            device.inputs.get(gpio_input)?;
+        }
+        else if main_core1::is_running() {
+            println!("Core1 is running, BOOTSEL is read once it stops (set core1=lazy|off)");
+        }
+        device.outputs.get(gpio_output)?;
+
+        let invert = args.get_parsed_param("invert").unwrap_or(false);
+        let debounce = args.get_parsed_param("debounce").unwrap_or(0);
+        MIRRORS.add(
+            Mirror::new(gpio_input, gpio_output)
+                .inverted(invert)
+                .debounced(debounce),
+        )?;
+
+        println!("Mirroring GPIO {gpio_input} - {input} >> GPIO {gpio_output} - {output}");
+        return Ok(());
+    }
+
+    let rules = MIRRORS.rules();
+    if rules.is_empty() {
+        println!("No active mirrors");
+    }
+    for rule in rules {
+        println!(
+            "  GPIO {:>2} - {:<8} >> GPIO {:>2} - {:<8} | invert: {} | debounce: {}ms",
+            rule.input,
+            mirror_input_alias(rule.input),
+            rule.output,
+            CONFIG.get_alias(rule.output).unwrap_or("?"),
+            rule.invert,
+            rule.debounce_ms()
+        );
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               TPO
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Time proportioned output (slow PWM) for relays and SSR driven heaters
+// ex: tpo output=OUT_A period=2000 duty=35
+// ex: tpo output=OUT_A duty=50
+// ex: tpo stop=OUT_A
+// ex: tpo output=OUT_B duty=20 sync_start at=+500ms
+
+pub fn build_tpo_cmd() -> Command {
+    Command {
+        name:     "tpo",
+        desc:     "Time proportioned slow PWM outputs",
+        help:     "tpo [output=OUT_A(str)] [period=2000(ms)] [duty=0-100(%)] [sync_start \
+                   [at=+500ms]] / [stop=OUT_A(str)] [help]\n
+    period     : window length, 100ms to 1h. Changes on a running output apply from the next
+                 window
+    duty       : on time in percent of the window, applied from the next window
+    sync_start : first window on the shared start tick, see capture
+    Lists the running outputs when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     tpo_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn tpo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PERIOD: u32 = 2_000; // ms
+
+    if let Some(alias) = args.get_str_param("stop") {
+        let (gpio, alias) = CONFIG.get_gpio_alias_pair(None, Some(alias))?;
+        TPO.stop(gpio)?;
+        println!("TPO on GPIO {gpio} - {alias} stopped, output LOW");
+        return Ok(());
+    }
+
+    if let Some(alias) = args.get_str_param("output") {
+        let (gpio, alias) = CONFIG.get_gpio_alias_pair(None, Some(alias))?;
+        device.outputs.get(gpio)?; // Has to be a registered output
+
+        let period = args.get_parsed_param::<u32>("period").ok();
+        let duty = args.get_parsed_param::<f32>("duty").ok();
+        let running = TPO.channels().iter().any(|channel| channel.gpio == gpio);
+
+        if running {
+            if let Some(period) = period {
+                TPO.set_period(gpio, period)?;
+            }
+            if let Some(duty) = duty {
+                TPO.set_duty(gpio, duty)?;
+            }
+        }
+        else {
+            let duty = duty.ok_or(Error::MissingArg("duty".into_truncate()))?;
+            let period = period.unwrap_or(DEFAULT_PERIOD);
+            match sync_start(args)? {
+                Some(at) => TPO.start_at(gpio, period, duty, at)?,
+                None => TPO.start(gpio, period, duty)?,
+            }
+        }
+    }
+
+    let channels = TPO.channels();
+    if channels.is_empty() {
+        println!("No running TPO outputs");
+    }
+    for channel in channels {
+        println!(
+            "  GPIO {:>2} - {:<8} | period: {}ms | duty: {:.1}%",
+            channel.gpio,
+            CONFIG.get_alias(channel.gpio).unwrap_or("?"),
+            channel.period_ms,
+            channel.duty
+        );
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Encoder Sim
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Quadrature signal source running in the background, see system::encoder_sim
+// ex: encoder_sim rate=2000 count=400
+// ex: encoder_sim a=OUT_A b=OUT_B rate=500 reverse
+// ex: encoder_sim rate=1000 count=200 sync_start at=+500ms
+
+pub fn build_encoder_sim_cmd() -> Command {
+    Command {
+        name:     "encoder_sim",
+        desc:     "Quadrature encoder signal generator",
+        help:     "encoder_sim [a=OUT_A(str)] [b=OUT_B(str)] [rate=1000(counts/s)] [count=0(u32)] \
+                   [reverse] [sync_start [at=+500ms]] / [stop] [help]\n
+    rate       : quadrature edges per second, 4 per cycle, up to 50000
+    count      : edges to output, 0 runs until stopped
+    sync_start : first edge one period after the shared start tick, see capture
+    A leads B when running forward, reverse swaps the direction
+    Prints the last run when called without arguments",
+        category: Category::Io,
+        // No PinFree, it would block stopping or restarting a running simulation
+        requires: &[],
+        func:     encoder_sim_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn encoder_sim_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_A: &str = "OUT_A";
+    const DEFAULT_B: &str = "OUT_B";
+
+    if args.contains_param("stop") {
+        match ENCODER_SIM.stop() {
+            true => println!("Encoder simulation stopped"),
+            false => println!("Encoder simulation not running"),
+        }
+        return Ok(());
+    }
+
+    if ["a", "b", "rate", "count", "reverse"]
+        .iter()
+        .any(|param| args.contains_param(param))
+    {
+        let (gpio_a, _) =
+            CONFIG.get_gpio_alias_pair(None, Some(args.get_str_param("a").unwrap_or(DEFAULT_A)))?;
+        let (gpio_b, _) =
+            CONFIG.get_gpio_alias_pair(None, Some(args.get_str_param("b").unwrap_or(DEFAULT_B)))?;
+        device.outputs.get(gpio_a)?; // Have to be registered outputs
+        device.outputs.get(gpio_b)?;
+
+        let rate: u32 = args.get_ranged_param_or("rate", 1..=encoder_sim::MAX_RATE, 1000)?;
+        let count: u32 = args.get_parsed_param("count").unwrap_or(0);
+
+        let mut setup = encoder_sim::Setup::new(gpio_a, gpio_b, rate)
+            .counts(count)
+            .reverse(args.contains_param("reverse"));
+        if let Some(at) = sync_start(args)? {
+            setup = setup.start_at(at);
+        }
+        ENCODER_SIM.start(setup)?;
+    }
+
+    let Some(status) = ENCODER_SIM.status()
+    else {
+        println!("No encoder simulation");
+        return Ok(());
+    };
+
+    let setup = status.setup;
+    print!(
+        "A: GPIO {} | B: GPIO {} | {} counts/s {} | {} | position: {}",
+        setup.a,
+        setup.b,
+        setup.rate,
+        if setup.reverse { "reverse" } else { "forward" },
+        if status.running { "running" } else { "done" },
+        status.position
+    );
+    match setup.counts {
+        0 => println!(),
+        counts => println!(" ({}/{counts})", status.emitted),
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Playback
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Replays an uploaded timeline of output levels with microsecond pacing
+// ex: playback upload=pulses save
+// ex: playback run=pulses repeat=0
+// ex: playback run=pulses sync_start at=+500ms
+// ex: playback show=pulses
+
+pub fn build_playback_cmd() -> Command {
+    Command {
+        name:     "playback",
+        desc:     "Replays timelines of output levels",
+        help:     "playback [upload=..(str) [save]] / [run=..(str) [repeat=1(u32)] [sync_start \
+                   [at=+500ms]]] / [show=..(str)] / [rm=..(str)] [help]\n
+    upload     : reads \"time_us,pin,level\" lines until an empty line, sorted by time
+                 pin is a gpio or alias, level 0/1 or low/high, '#' starts a comment
+    save       : saves the timelines to flash after the upload
+    repeat     : cycles to play, 0 until interrupted
+    sync_start : event times relative to the shared start tick, see capture
+    Lists the timelines when called without arguments
+    Interrupt playback with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     playback_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn playback_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("upload") {
+        println!("Send time_us,pin,level lines, end with an empty line\n");
+
+        let mut events = Timeline::new();
+        let mut buffer = [0u8; 64];
+        loop {
+            let len = SERIAL
+                .read_line_blocking(&mut buffer)
+                .map_err(|_| Error::IoInput)?;
+            let line = core::str::from_utf8(&buffer[..len]).map_err(|_| Error::ParseBuffer)?;
+            if line.trim().is_empty() {
+                break;
+            }
+
+            // Comment lines
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let event = Event::parse(line)?;
+            device.outputs.get(event.gpio)?;
+            events.push(event).map_err(|_| playback::Error::Full)?;
+        }
+
+        playback::save(name, &events)?;
+        println!("Timeline {name}: {} event(s)", events.len());
+        if args.contains_param("save") {
+            FILES.save()?;
+            println!("Files saved to flash");
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("run") {
+        let events = playback::load(name)?;
+        for event in &events {
+            device.outputs.get(event.gpio)?;
+        }
+
+        let repeat = args.get_parsed_param("repeat").unwrap_or(1);
+        println!("Playing {name}: {} event(s)", events.len());
+        if repeat == 0 {
+            println!("Send '~' to exit");
+        }
+
+        SERIAL.clear_interrupt_cmd();
+        match sync_start(args)? {
+            Some(at) => PLAYBACK.start_at(&events, repeat, at)?,
+            None => PLAYBACK.start(&events, repeat)?,
+        }
+        while PLAYBACK.is_running() {
+            if SERIAL.interrupt_cmd_triggered() {
+                PLAYBACK.stop();
+            }
+            device.timer.delay_ms(1);
+        }
+
+        println!("Done! {} cycle(s)", PLAYBACK.cycles());
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("show") {
+        for event in playback::load(name)? {
+            let alias = CONFIG.get_alias(event.gpio).unwrap_or("?");
+            println!("{},{},{}", event.time_us, alias, event.high as u8);
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("rm") {
+        playback::remove(name)?;
+        println!("Timeline {name} removed");
+        return Ok(());
+    }
+
+    // List
+    let mut count = 0;
+    playback::for_each(|name, events| {
+        println!("  {name:<12} {events:>4} event(s)");
+        count += 1;
+    });
+    println!("\n{count} timeline(s)");
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Play
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+// Plays 8-bit PCM clips or beep patterns on a PWM pin
+// ex: play pattern=alarm alias=PWM2_A volume=50
+
+pub fn build_play_cmd() -> Command {
+    Command {
+        name:     "play",
+        desc:     "Plays 8-bit PCM clips or beep patterns through a PWM pin",
+        help:     "play [pattern=beep(str)] / [clip=..(str)] [gpio=..|alias=BUZZER] \
+                   [rate=8000(8000-22050)] [volume=100(0-100)] [repeat=1(0=until ~)] [dryrun] / \
+                   [upload=..(str) [save]] / [rm=..(str)] / [list] [help]\n
+    pattern : built-in beep pattern, beep double alarm error ok
+    clip    : uploaded clip, unsigned 8-bit samples, 128 is silence
+    rate    : samples per second, paced by DMA, rounded to the DMA timer resolution
+    upload  : reads base64 lines until an empty line, up to a file store sector
+    save    : saves the clips to flash after the upload
+    The slice of the pin runs at a 488kHz carrier while playing, filter the pin with an RC
+    low pass (ex: 1k / 100nF) or drive a small amplifier
+    Interrupt playback with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     play_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn play_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "BUZZER";
+
+    if let Some(name) = args.get_str_param("upload") {
+        println!("Send base64 lines, end with an empty line\n");
+
+        let mut clip = files::Data::new();
+        let mut buffer = [0u8; 128];
+        let mut decoded = [0u8; 96];
+        loop {
+            let len = SERIAL
+                .read_line_blocking(&mut buffer)
+                .map_err(|_| Error::IoInput)?;
+            let line = core::str::from_utf8(&buffer[..len]).map_err(|_| Error::ParseBuffer)?;
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+
+            let len = encoding::decode_base64(line, &mut decoded)
+                .ok_or(Error::Parse("base64".into_truncate()))?;
+            clip.extend_from_slice(&decoded[..len])
+                .map_err(|_| files::Error::Full)?;
+        }
+
+        audio::save(name, &clip)?;
+        println!("Clip {name}: {} sample(s)", clip.len());
+        if args.contains_param("save") {
+            FILES.save()?;
+            println!("Files saved to flash");
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("rm") {
+        audio::remove(name)?;
+        println!("Clip {name} removed");
+        return Ok(());
+    }
+
+    if args.contains_param("list") {
+        println!("Patterns:");
+        for pattern in audio::PATTERNS {
+            let ms: u32 = pattern.tones.iter().map(|&(_, ms)| ms as u32).sum();
+            println!("  {:<12} {ms:>5}ms", pattern.name);
+        }
+        println!("Clips:");
+        let mut count = 0;
+        audio::for_each(|name, samples| {
+            println!("  {name:<12} {samples:>5} sample(s)");
+            count += 1;
+        });
+        println!("\n{count} clip(s)");
+        return Ok(());
+    }
+
+    let clip;
+    let (name, source) = match args.get_str_param("clip") {
+        Some(name) => {
+            clip = audio::load(name)?;
+            (name, audio::Source::Clip(&clip))
+        }
+        None => {
+            let name = args.get_str_param("pattern").unwrap_or(audio::BEEP.name);
+            let pattern =
+                audio::Pattern::find(name).ok_or(Error::Parse("pattern".into_truncate()))?;
+            (name, audio::Source::Pattern(pattern))
+        }
+    };
+
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+
+    let rate =
+        args.get_ranged_param_or("rate", audio::MIN_RATE..=audio::MAX_RATE, audio::DEFAULT_RATE)?;
+    let volume = args.get_ranged_param_or("volume", 0..=100, 100)?;
+    let repeat: u16 = args.get_parsed_param("repeat").unwrap_or(1);
+
+    if dry_run::is_active(args.contains_param("dryrun")) {
+        println!(
+            "[dryrun] would play {name}: {} sample(s) at {rate}Hz on GPIO {gpio} - {alias}",
+            source.len(rate)
+        );
+        return Ok(());
+    }
+    if repeat == 0 {
+        println!("Send '~' to exit");
+    }
+
+    SERIAL.clear_interrupt_cmd();
+    let mut played: u16 = 0;
+    let mut underruns = 0;
+    'play: while repeat == 0 || played < repeat {
+        let mut player = Player::start(&mut device.pwms, gpio, rate, source, volume)?;
+        if played == 0 {
+            println!(
+                "Playing {name}: {} sample(s) at {}Hz on GPIO {gpio} - {alias}",
+                player.total(),
+                player.rate()
+            );
+        }
+
+        while player.poll() {
+            if SERIAL.interrupt_cmd_triggered() {
+                player.stop(&mut device.pwms);
+                break 'play;
+            }
+        }
+        underruns += player.underruns();
+        player.stop(&mut device.pwms);
+        played += 1;
+    }
+
+    println!("Done! {played} time(s)");
+    if underruns > 0 {
+        println!("Warning: {underruns} buffer underrun(s), the command loop was too slow");
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Choreography
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Plays keyframe sequences on several servos with eased moves
+// ex: choreo upload name=wave save
+// ex: choreo play name=wave loop=2
+// ex: choreo show name=wave
+
+pub fn build_choreo_cmd() -> Command {
+    Command {
+        name:     "choreo",
+        desc:     "Plays servo keyframe sequences",
+        help:     "choreo [upload [save]] / [play [loop=1(u32)]] / [show] / [rm] [name=..(str)] \
+                   [help]\n
+    upload : reads \"time_ms,servo,angle\" lines until an empty line, sorted by time
+             servo is a gpio or alias of a PWM pin, angle 0-180 (1000-2000us)
+             '#' starts a comment
+    save   : saves the sequences to flash after the upload
+    play   : eased moves between the keyframes of each servo, updated at 50Hz
+    loop   : cycles to play, 0 until interrupted, the servos hold their last position
+    Lists the sequences when called without arguments
+    Interrupt with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     choreo_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn choreo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let name = || {
+        args.get_str_param("name")
+            .ok_or(Error::MissingArg("name".into_truncate()))
+    };
+
+    if args.contains_param("upload") {
+        let name = name()?;
+        println!("Send time_ms,servo,angle lines, end with an empty line\n");
+
+        let mut keyframes = Sequence::new();
+        let mut buffer = [0u8; 64];
+        loop {
+            let len = SERIAL
+                .read_line_blocking(&mut buffer)
+                .map_err(|_| Error::IoInput)?;
+            let line = core::str::from_utf8(&buffer[..len]).map_err(|_| Error::ParseBuffer)?;
+            if line.trim().is_empty() {
+                break;
+            }
+
+            // Comment lines
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let keyframe = Keyframe::parse(line)?;
+            device.pwms.get_pwm_slice_id_by_gpio(keyframe.gpio)?;
+            keyframes.push(keyframe).map_err(|_| choreo::Error::Full)?;
+        }
+        choreo::servo_pins(&keyframes)?;
+
+        choreo::save(name, &keyframes)?;
+        println!("Sequence {name}: {} keyframe(s)", keyframes.len());
+        if args.contains_param("save") {
+            FILES.save()?;
+            println!("Files saved to flash");
+        }
+        return Ok(());
+    }
+
+    if args.contains_param("play") {
+        let name = name()?;
+        let keyframes = choreo::load(name)?;
+
+        // Servo slices at 50Hz
+        let mut servos = heapless::Vec::<Servo, { choreo::MAX_SERVOS }>::new();
+        for gpio in choreo::servo_pins(&keyframes)? {
+            let (slice_id, _) = device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+            let top = with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
+                pwm_slice.set_freq(choreo::SERVO_FREQ);
+                pwm_slice.enable();
+                pwm_slice.slice.get_top()
+            });
+            let _ = servos.push(Servo::new(gpio, top));
+        }
+
+        let repeat = args.get_parsed_param("loop").unwrap_or(1);
+        println!("Playing {name}: {} keyframe(s), {} servo(s)", keyframes.len(), servos.len());
+        if repeat == 0 {
+            println!("Send '~' to exit");
+        }
+
+        SERIAL.clear_interrupt_cmd();
+        CHOREO.start(&keyframes, &servos, repeat)?;
+        while CHOREO.is_running() {
+            if SERIAL.interrupt_cmd_triggered() {
+                CHOREO.stop();
+            }
+            device.timer.delay_ms(1);
+        }
+
+        println!("Done! {} cycle(s)", CHOREO.cycles());
+        return Ok(());
+    }
+
+    if args.contains_param("show") {
+        for keyframe in choreo::load(name()?)? {
+            let alias = CONFIG.get_alias(keyframe.gpio).unwrap_or("?");
+            println!("{},{},{}", keyframe.time_ms, alias, keyframe.angle);
+        }
+        return Ok(());
+    }
+
+    if args.contains_param("rm") {
+        let name = name()?;
+        choreo::remove(name)?;
+        println!("Sequence {name} removed");
+        return Ok(());
+    }
+
+    // List
+    let mut count = 0;
+    choreo::for_each(|name, keyframes| {
+        println!("  {name:<12} {keyframes:>4} keyframe(s)");
+        count += 1;
+    });
+    println!("\n{count} sequence(s)");
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Led
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Patterns are stepped every 100ms, 1 is on and 0 off
+// ex: led pattern=idle:1010000000
+// ex: led preview=error
+// ex: led alias=PWM3_A brightness=30 fade=500ms
+
+pub fn build_led_cmd() -> Command {
+    Command {
+        name:     "led",
+        desc:     "Shows and configures the LED status patterns, dims LEDs on PWM pins",
+        help:     "led [pattern=<mode>:<steps>(str)] / [default=<mode>(str)] / \
+                   [preview=<mode>(str)] [help]\n    led [alias=..(str)] / [gpio=..(u8)] \
+                   [brightness=100(0-100%)] [fade=0(ms|s)] [limit=100(1-100%)] \n        \
+                   [blink=..(steps)|off] [release]\n
+    Modes: off boot waiting idle running error estop
+    pattern    : replaces and saves the pattern of a mode, up to 32 steps of 100ms
+    default    : restores the default pattern of a mode
+    preview    : shows the pattern of a mode until interrupted with char \"~\"
+    alias/gpio : LED on a PWM pin, the slice runs at 1kHz, kept after the command
+    brightness : perceived brightness, gamma corrected
+    fade       : time to reach the brightness from the current level
+    limit      : duty cap, the average current of LEDs driven at their peak current
+    blink      : pattern of 100ms steps at the brightness, ex: blink=1100
+    release    : turns the LED off and stops dimming it",
+        category: Category::Io,
+        requires: &[],
+        func:     led_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn led_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    // LED on a PWM pin
+    if args.contains_param("alias") || args.contains_param("gpio") {
+        return dim_led(args, device);
+    }
+
+    let parse_mode = |mode: &str| {
+        mode.parse::<LedMode>()
+            .ok()
+            .filter(|mode| *mode != LedMode::Manual)
+            .ok_or(Error::Parse("mode".into_truncate()))
+    };
+
+    if let Some(value) = args.get_str_param("pattern") {
+        let (mode, steps) = value
+            .split_once(':')
+            .ok_or(Error::Parse("pattern".into_truncate()))?;
+        let pattern = steps
+            .parse::<Pattern>()
+            .map_err(|_| Error::Parse("pattern".into_truncate()))?;
+        device.led.set_pattern(parse_mode(mode)?, Some(pattern))?;
+    }
+
+    if let Some(mode) = args.get_str_param("default") {
+        device.led.set_pattern(parse_mode(mode)?, None)?;
+    }
+
+    if let Some(mode) = args.get_str_param("preview") {
+        let mode = parse_mode(mode)?;
+        println!("Previewing {mode}, send '~' to exit");
+
+        device.led.set_mode(mode);
+        SERIAL.clear_interrupt_cmd();
+        while !SERIAL.interrupt_cmd_triggered() {
+            device.timer.delay_ms(10);
+        }
+        return Ok(());
     }
-    else {
-        return Err(Error::Configuration(ConfigError::GpioNotFound));
+
+    if !device.led.is_available() {
+        println!("No LED on this board");
     }
+    for mode in LedMode::PATTERNS {
+        let pattern = device
+            .led
+            .pattern(mode)
+            .unwrap_or(Pattern::from_steps(0, 1));
+        println!("  {:<8} {pattern}", mode.name());
+    }
+
+    DIMMER.for_each(|status| {
+        let alias = CONFIG.get_alias(status.setup.gpio).unwrap_or("?");
+        println!("\n  {alias} (GPIO {}): {}", status.setup.gpio, DimmerStatus(status));
+    });
 
     Ok(())
 }
 
-// —————————————————————————————————————————————————————————————————————————————————————————————————
-//                                            Read ADC
-// —————————————————————————————————————————————————————————————————————————————————————————————————
+fn dim_led(args: &[Argument], device: &mut Device) -> Result<()> {
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, args.get_str_param("alias"))?;
 
-pub fn build_read_adc_cmd() -> Command {
-    Command {
-        name: "read_adc",
-        desc: "Read all ADC channels",
-        help: "read_adc [ref_res=10000(ohm)] [help]",
-        func: read_adc_cmd,
+    if args.contains_param("release") {
+        match DIMMER.release(gpio) {
+            true => println!("{alias} released"),
+            false => println!("{alias} is not dimmed"),
+        }
+        return Ok(());
     }
-}
 
-pub fn read_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
-    // Print Help
-    if args.contains_param("help") {
-        cmd.print_help();
-        return Ok(());
+    // The slice is set up on the first use, later calls keep the LED settings not given
+    let (slice_id, _) = device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+    let previous = DIMMER.status(gpio).map(|status| status.setup);
+    let top = with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
+        if previous.is_none() {
+            pwm_slice.set_freq(dimmer::LED_FREQ);
+            pwm_slice.enable();
+        }
+        pwm_slice.slice.get_top()
+    });
+    let mut setup = previous.unwrap_or(dimmer::Setup::new(gpio, top));
+    setup.top = top;
+
+    let fade = match args.get_str_param("fade") {
+        Some(value) => parse_duration_ms(value).ok_or(Error::Parse("fade".into_truncate()))?,
+        None => 0,
+    };
+    setup = setup
+        .brightness(args.get_ranged_param_or("brightness", 0..=100, setup.brightness)?)
+        .limit(args.get_ranged_param_or("limit", 1..=100, setup.limit)?)
+        .fade(fade);
+    if let Some(blink) = args.get_str_param("blink") {
+        let blink = match blink {
+            "off" => None,
+            steps => Some(
+                steps
+                    .parse()
+                    .map_err(|_| Error::Parse("blink".into_truncate()))?,
+            ),
+        };
+        setup = setup.blink(blink);
     }
 
-    let ref_res: u32 = args.get_parsed_param("ref_res").unwrap_or(10_000);
-    read_adc(device, ref_res)
+    DIMMER.apply(setup)?;
+    if let Some(status) = DIMMER.status(gpio) {
+        println!("{alias} (GPIO {gpio}): {}", DimmerStatus(status));
+    }
+    Ok(())
 }
 
-pub fn read_adc(device: &mut Device, ref_res: u32) -> Result<()> {
-    println!("---- Read ADC ----");
-    println!("Reference Pullup Resistor: {}ohm", ref_res);
-
-    let channels_to_read: [u8; _] = [0, 1, 2, 3];
+/// "brightness 30% (limit 80%) | fading, at 12% of a 500ms fade | blink 1100"
+struct DimmerStatus(dimmer::Status);
 
-    for &channel in &channels_to_read {
-        if let Some(r) = device.adcs.read(channel) {
-            let adc_raw = r;
-            let adc_vol = adc_raw.to_voltage();
-            let adc_res = adc_raw.to_resistance(ref_res);
-            println!("> ACD {}: v:{:.2}, ohm:{:.1}, raw:{} \r", channel, adc_vol, adc_res, adc_raw);
+impl core::fmt::Display for DimmerStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let setup = self.0.setup;
+        write!(f, "brightness {}% (limit {}%)", setup.brightness, setup.limit)?;
+        if self.0.fading {
+            write!(f, " | fading, at {}% of a {}ms fade", self.0.level, setup.fade_ms)?;
+        }
+        match setup.blink {
+            Some(pattern) => write!(f, " | blink {pattern}"),
+            None => Ok(()),
         }
     }
-
-    // read Temp Sense
-    let adc_raw: u16 = device.adcs.read(TEMP_SENSE_CHN).unwrap_or(0);
-    let adc_vol = adc_raw.to_voltage();
-    let adc_res = adc_raw.to_resistance(ref_res);
-    let sys_temp = 27.0 - (adc_raw.to_voltage() - 0.706) / 0.001721;
-    println!("Temp Sense: C:{:.1}, v:{:.2}, raw:{}", sys_temp, adc_vol, adc_raw);
-
-    Ok(())
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
-//                                           Sample ADC
+//                                           Status LED
 // —————————————————————————————————————————————————————————————————————————————————————————————————
+// RGB LED defined in the pin table as RGB_DATA (WS2812) or RGB_R/G/B (PWM)
+// ex: statusled color=idle:00ffff
+// ex: statusled brightness=60
 
-pub fn build_sample_adc_cmd() -> Command {
+pub fn build_statusled_cmd() -> Command {
     Command {
-        name: "sample_adc",
-        desc: "Continuous sampling of an ADC channel",
-        help: "sample_adc [alias=ADC0(str)] / [gpio=..(u8)] [ref_res=10000(ohm)] \
-               [interval=200(ms)] [help]\n
-    Interrupt with char \"~\"",
-        func: sample_adc_cmd,
+        name:     "statusled",
+        desc:     "Shows and configures the RGB status LED colors",
+        help:     "statusled [color=<mode>:<rrggbb>(str)] / [default=<mode>(str)] \
+                   [brightness=0-100(u8)] / [show=<rrggbb>(str)] [help]\n
+    Modes: off boot waiting idle running error estop
+    color      : replaces and saves the color of a mode
+    default    : restores the default color of a mode
+    brightness : sets and saves the brightness in percent
+    show       : shows a color until interrupted with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     statusled_cmd,
+        timeout:  None,
     }
 }
 
-pub fn sample_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+pub fn statusled_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
     // Print Help
     if args.contains_param("help") {
         cmd.print_help();
         return Ok(());
     }
 
-    const DEFAULT_PIN: &str = "ADC0";
+    let Some(driver) = device.led.rgb_driver()
+    else {
+        println!("No RGB LED, define RGB_DATA or RGB_R/G/B in the pin table");
+        return Ok(());
+    };
 
-    // Getting Alias or GPIO input ---------
-    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
-    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    let parse_mode = |mode: &str| {
+        mode.parse::<LedMode>()
+            .ok()
+            .filter(|mode| *mode != LedMode::Manual)
+            .ok_or(Error::Parse("mode".into_truncate()))
+    };
+    let parse_color = |color: &str| {
+        color
+            .parse::<Color>()
+            .map_err(|_| Error::Parse("color".into_truncate()))
+    };
 
-    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
-    // -------------------------------------
+    if let Some(value) = args.get_str_param("color") {
+        let (mode, color) = value
+            .split_once(':')
+            .ok_or(Error::Parse("color".into_truncate()))?;
+        device
+            .led
+            .set_color(parse_mode(mode)?, Some(parse_color(color)?))?;
+    }
 
-    let ref_res: u32 = args.get_parsed_param("ref_res").unwrap_or(10_000);
-    let interval: u16 = args.get_parsed_param("interval").unwrap_or(200);
-
-    // Getting ADC channel based on pin number
-    let channel = match gpio {
-        26 => 0,
-        27 => 1,
-        28 => 2,
-        29 => 3,
-        255 => 4, // default TEMP_SENSE channel
-        _ => return Err(Error::Configuration(ConfigError::OutOfBounds)),
-    };
+    if let Some(mode) = args.get_str_param("default") {
+        device.led.set_color(parse_mode(mode)?, None)?;
+    }
 
-    println!("---- Sample ADC ----");
-    println!("ADC Pin: GPIO {gpio} - {alias} | adc channel: {channel} |\n");
-    println!("Reference Pullup Resistor: {}ohm", ref_res);
-    println!("\nSend '~' to exit\n");
+    if let Ok(brightness) = args.get_parsed_param::<u8>("brightness") {
+        device.led.set_brightness(brightness)?;
+    }
 
-    SERIAL.clear_interrupt_cmd();
-    while !SERIAL.interrupt_cmd_triggered() {
-        if let Some(r) = device.adcs.read(channel) {
-            let adc_raw: u16 = r;
-            let adc_vol = adc_raw.to_voltage();
-            let adc_res = adc_raw.to_resistance(ref_res);
-            println!("> v:{:.2}, ohm:{:.1}, raw:{} \r", adc_vol, adc_res, adc_raw);
-            device.timer.delay_ms(interval as u32);
-        }
-        else {
-            println!("Cannot read channel: {}", channel);
+    if let Some(color) = args.get_str_param("show") {
+        let color = parse_color(color)?;
+        println!("Showing {color}, send '~' to exit");
+
+        let mode = device.led.mode();
+        device.led.show_color(color);
+        SERIAL.clear_interrupt_cmd();
+        while !SERIAL.interrupt_cmd_triggered() {
+            device.timer.delay_ms(10);
         }
+        device.led.set_mode(mode);
+        return Ok(());
     }
 
-    println!("Sampling Interrupted. Done!");
+    println!("Driver: {driver} | brightness: {}%", device.led.brightness());
+    for mode in LedMode::PATTERNS {
+        let color = device.led.color(mode).unwrap_or_default();
+        println!("  {:<8} {color}", mode.name());
+    }
 
     Ok(())
 }
@@ -258,12 +2109,17 @@ pub fn sample_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) ->
 
 pub fn build_pwm_cmd() -> Command {
     Command {
-        name: "pwm",
-        desc: "Sets PWM  (defaults on GPIO 6 - PWM3A)",
-        help: "pwm [alias=PWM2_B(str)] / [gpio=..(u8)] [freq=50(hz)] [duty=50(%)] \
-               [duty_us=..(us)] \n        [top=-1(u16)] [phase=false(bool)] [disable=false(bool)] \
-               [help]",
-        func: pwm_cmd,
+        name:     "pwm",
+        desc:     "Sets PWM  (defaults on GPIO 6 - PWM3A)",
+        help:     "pwm [alias=PWM2_B(str)] / [gpio=..(u8)] [freq=50(hz)] [duty=50(0..=100%)] \
+                   [duty_us=..(1..=65535us)] \n        [top=65535(1..=65535)] [phase=false(bool)] \
+                   [disable=false(bool)] [clamp] [dryrun] [help]\n        Out of range values are \
+                   rejected, clamp limits them to the range instead\n        Within a transaction \
+                   the settings are staged until txn commit, see txn",
+        category: Category::Io,
+        requires: &[],
+        func:     pwm_cmd,
+        timeout:  None,
     }
 }
 
@@ -283,10 +2139,12 @@ pub fn pwm_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<
     let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
     // -------------------------------------
 
-    let us: i32 = args.get_parsed_param("duty_us").unwrap_or(-1); //  -1 eq not set
-    let duty: u8 = args.get_parsed_param("duty").unwrap_or(50); //  50% default
-    let freq: u32 = args.get_parsed_param("freq").unwrap_or(50); // to_Hz
-    let top: i32 = args.get_parsed_param("top").unwrap_or(-1); // 
+    let max_freq = SYS_CLK_HZ.load(Ordering::Relaxed) / 2;
+
+    let us: i32 = args.get_ranged_param_or("duty_us", 1..=u16::MAX as i32, -1)?; //  -1 eq not set
+    let duty = args.get_ranged_param_or::<u32>("duty", 0..=100, 50)? as u8; //  50% default
+    let freq: u32 = args.get_ranged_param_or("freq", 1..=max_freq, 50)?; // to_Hz
+    let top: i32 = args.get_ranged_param_or("top", 1..=u16::MAX as i32, -1)?; // -1 eq max
     let phase: bool = args.get_parsed_param("phase").unwrap_or(false); // 
     let disable: bool = args.get_parsed_param("disable").unwrap_or(false); // false
 
@@ -296,17 +2154,60 @@ pub fn pwm_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<
     // Print Pin information
     println!("Pwm Pin: GPIO {gpio} - {alias} | pwm: {slice_id}, channel: {channel_type} |\n");
 
+    let dry_run = dry_run::is_active(args.contains_param("dryrun"));
+    if dry_run || TXN.is_open() {
+        let top = if top > 0 { top as u16 } else { u16::MAX };
+        let max_duty = top.saturating_add(1);
+        let cc = if us > 0 {
+            pwms::calculate_duty_from_us(us as u16, freq, max_duty)
+        }
+        else {
+            (duty as u32 * max_duty as u32 / 100) as u16
+        };
+
+        // Dry run first, nothing is staged
+        if dry_run && disable {
+            println!("[dryrun] would disable pwm slice {slice_id}");
+        }
+        else if dry_run {
+            print_dry_run_pwm(slice_id, channel_type, freq, top, phase, cc);
+        }
+        else if disable {
+            TXN.stage_pwm(PwmChange {
+                gpio,
+                slice_id,
+                freq,
+                top,
+                phase,
+                cc: None,
+            })?;
+            println!("[txn] pwm slice {slice_id}: disable staged");
+        }
+        else {
+            TXN.stage_pwm(PwmChange {
+                gpio,
+                slice_id,
+                freq,
+                top,
+                phase,
+                cc: Some(cc),
+            })?;
+            println!("[txn] pwm slice {slice_id}, channel {channel_type}: {freq}hz cc {cc} staged");
+        }
+        return Ok(());
+    }
+
     // Using a 'with' macro to be able to select the PWM slice
     // In regular usage you would call the pwm slice directly
     with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
-        pwm(pwm_slice, channel_type, us, duty, freq, top, phase, disable)
+        pwm(pwm_slice, gpio, us, duty, freq, top, phase, disable)
     })
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn pwm<I>(
     pwm: &mut crate::system::pwms::PwmSlice<I>,
-    channel: crate::system::pwms::Channel,
+    gpio: u8,
     us: i32,
     duty: u8,
     freq: u32,
@@ -343,25 +2244,26 @@ where
         pwm.set_freq(freq);
     }
 
-    // Getting pwm channel
-    let mut channel = pwm.get_channel(channel);
-
     // Duty values for printing;
     let duty_us;
     let duty_p;
+    let cc;
 
     // Set Duty
+    let max_duty = top.saturating_add(1);
     if us > 0 {
-        channel.set_duty_cycle_us(us as u16, freq);
+        cc = pwms::calculate_duty_from_us(us as u16, freq, max_duty);
         duty_us = us as u32;
         duty_p = (duty_us * freq + 5_000) / 10_000;
     }
     else {
         let duty = duty.clamp(0, 100) as u16;
-        channel.set_duty_cycle_fraction(duty, 100).unwrap();
+        cc = (duty as u32 * max_duty as u32 / 100) as u16;
         duty_us = (duty as u32 * 10_000) / freq;
         duty_p = duty as u32;
     }
+    // Motor outputs ramp to the duty at their output guard rate
+    pwms::write_channel_cc(gpio, cc);
 
     let period_us: u32 = 1_000_000 / freq;
 
@@ -369,6 +2271,11 @@ where
         "freq: {freq}hz {period_us}us | duty: {duty_p}% {duty_us}µs | top: {top} | phase: {phase} \
          |"
     );
+    if pwms::read_cc(gpio) != cc
+        && let Some(limit) = output_guard::limit(gpio)
+    {
+        println!("> Ramping to the duty, output guard: {limit}");
+    }
 
     // End
     pwm.enable();
@@ -376,16 +2283,189 @@ where
     Ok(())
 }
 
+/// Prints the register values a PWM setting would program, for the dry run mode
+pub fn print_dry_run_pwm(
+    slice_id: u8,
+    channel: Channel,
+    freq: u32,
+    top: u16,
+    phase: bool,
+    cc: u16,
+) {
+    let sys_clk_hz = SYS_CLK_HZ.load(Ordering::Relaxed);
+    let (div_int, div_frac) = pwms::calculate_pwm_dividers(sys_clk_hz, freq, top, phase);
+    let duty_p = cc as f32 * 100.0 / (top as f32 + 1.0);
+
+    println!("[dryrun] would program pwm slice {slice_id}, channel {channel}:");
+    println!("  freq: {freq}hz | phase correct: {phase}");
+    println!("  div: {div_int}+{div_frac}/16 | top: {top} | cc: {cc} ({duty_p:.1}%)");
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           PWM Status
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn build_pwm_status_cmd() -> Command {
+    Command {
+        name:     "pwm_status",
+        desc:     "Prints the PWM slices state read back from hardware",
+        help:     "pwm_status [slice=..(u8)] [help]",
+        category: Category::Io,
+        requires: &[],
+        func:     pwm_status_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn pwm_status_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let slice: Option<u8> = args.get_parsed_param("slice").ok();
+
+    println!("---- PWM Status ----");
+
+    for slice_id in 0..8u8 {
+        if slice.is_some_and(|s| s != slice_id) {
+            continue;
+        }
+
+        let status = device.pwms.get_slice_status(slice_id)?;
+
+        let mut line: String<128> = String::new();
+        let _ = write!(
+            line,
+            "\nPWM {slice_id}: {} | freq: {:.1}hz | top: {} | div: {}+{}/16 | phase: {} | ctr: {}",
+            if status.enabled { "ON " } else { "OFF" },
+            status.freq_hz,
+            status.top,
+            status.div_int,
+            status.div_frac,
+            status.ph_correct,
+            status.counter
+        );
+        TERM.print_wrapped(&line);
+
+        for channel in [Channel::A, Channel::B] {
+            print!(
+                "  {channel}: duty: {:.1}% {:.0}us (cc: {})",
+                status.duty_percent(channel),
+                status.duty_us(channel),
+                status.get_cc(channel)
+            );
+
+            // Registered pins on this channel
+            for (gpio, _) in device
+                .pwms
+                .get_gpios_by_slice_id(slice_id)
+                .filter(|(_, ch)| *ch == channel)
+            {
+                print!(" | GPIO {gpio} - {}", CONFIG.get_alias(gpio).unwrap_or("-"));
+            }
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            PWM Sync
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Starts several slices at once with phase offsets, for multi-phase drives
+// ex: pwm_sync slices=3,4,5 phase_deg=120 freq=20000
+
+pub fn build_pwm_sync_cmd() -> Command {
+    Command {
+        name:     "pwm_sync",
+        desc:     "Starts PWM slices in sync with phase offsets",
+        help:     "pwm_sync slices=..(ids) [phase_deg=0(0..=360)] [freq=..(hz)] [help]\n
+    slices    : comma separated slice ids, ex: 3,4
+    phase_deg : each slice lags the previous one in the list by this angle
+    freq      : sets the frequency of every slice first, the slices need the same period
+    The counters are preloaded and the slices enabled on the same cycle (EN register)
+    Phase correct slices are preloaded on the up ramp, use them with 0deg only",
+        category: Category::Io,
+        requires: &[],
+        func:     pwm_sync_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn pwm_sync_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const MAX_SLICES: usize = 8;
+
+    let list = args
+        .get_str_param("slices")
+        .ok_or(Error::MissingArg("slices".into_truncate()))?;
+    let phase_deg: f32 = args.get_ranged_param_or("phase_deg", 0.0..=360.0, 0.0)?;
+    let max_freq = SYS_CLK_HZ.load(Ordering::Relaxed) / 2;
+    let freq: u32 = args.get_ranged_param_or("freq", 1..=max_freq, 0)?; // 0 eq unchanged
+
+    let mut slices: Vec<u8, MAX_SLICES> = Vec::new();
+    for id in list.split(',') {
+        let id: u8 = id
+            .trim()
+            .parse()
+            .map_err(|_| Error::Parse("slices".into_truncate()))?;
+        if id > 7 || slices.contains(&id) {
+            return Err(Error::Parse("slices".into_truncate()));
+        }
+        let _ = slices.push(id);
+    }
+
+    if freq > 0 {
+        for &slice_id in &slices {
+            with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| pwm_slice.set_freq(freq));
+        }
+    }
+
+    // Counter preload lagging each slice behind the previous one, on the count up ramp
+    let mut offsets: Vec<(u8, u16), MAX_SLICES> = Vec::new();
+    for (index, &slice_id) in slices.iter().enumerate() {
+        let period = device.pwms.get_slice_status(slice_id)?.top as u32 + 1;
+        let lag = ((phase_deg * index as f32 / 360.0) % 1.0 * period as f32) as u32;
+        let _ = offsets.push((slice_id, ((period - lag) % period) as u16));
+    }
+
+    device.pwms.start_synced(&offsets)?;
+
+    println!("---- PWM Sync ----");
+    for (index, &(slice_id, counter)) in offsets.iter().enumerate() {
+        let status = device.pwms.get_slice_status(slice_id)?;
+        println!(
+            "PWM {slice_id}: {:.1}hz | top: {} | preload: {counter} | phase: -{:.1}deg{}",
+            status.freq_hz,
+            status.top,
+            (phase_deg * index as f32) % 360.0,
+            if status.ph_correct { " (phase correct)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                               Log
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
 pub fn build_log_cmd() -> Command {
     Command {
-        name: "log",
-        desc: "Sets the internal logging level",
-        help: "log [level=\"\"(string)] [help] ",
-        func: log_cmd,
+        name:     "log",
+        desc:     "Sets the internal logging level",
+        help:     "log [level=\"\"(string)] [help] ",
+        category: Category::Base,
+        requires: &[],
+        func:     log_cmd,
+        timeout:  None,
     }
 }
 
@@ -424,3 +2504,349 @@ pub fn log_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<
 
     Ok(())
 }
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Random
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: random bytes=16
+// ex: random below=6
+
+pub fn build_random_cmd() -> Command {
+    Command {
+        name:     "random",
+        desc:     "Random bytes or numbers from the ROSC entropy",
+        help:     "random [bytes=16(u16 1..=256)] / [below=..(u32)] [help]\n
+    bytes : prints the bytes in hex
+    below : prints a number from 0 to below - 1",
+        category: Category::Dev,
+        requires: &[],
+        func:     random_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn random_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("below") {
+        let below = args.get_parsed_param::<u32>("below")?;
+        println!("{}", device.rng.below(below));
+        return Ok(());
+    }
+
+    let len = args.get_ranged_param_or::<u16>("bytes", 1..=256, 16)? as usize;
+    let mut buf = [0u8; 256];
+    device.rng.fill_bytes(&mut buf[..len]);
+    for byte in &buf[..len] {
+        print!("{byte:02x}");
+    }
+    println!();
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Echo
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Serial loopback to validate host tooling and cables, no command parsing involved
+// ex: echo
+// ex: echo crc block=1024
+
+pub fn build_echo_cmd() -> Command {
+    Command {
+        name:     "echo",
+        desc:     "Serial loopback with a throughput report",
+        help:     "echo [crc] [block=256(u16 1..=4096)] [help]\n
+    Every received byte is sent back, Ctrl+] (0x1D) ends the test
+    crc   : after each block, the CRC-32 of its bytes sent as 4 bytes little endian
+    block : block length for crc, in bytes
+    Reports the byte counts, the throughput and the dropped bytes",
+        category: Category::Dev,
+        requires: &[],
+        func:     echo_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn echo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let block = match args.contains_param("crc") {
+        true => Some(args.get_ranged_param_or::<u16>("block", 1..=4096, 256)? as usize),
+        false => None,
+    };
+    println!("Echoing, Ctrl+] to exit\n");
+
+    let mut buf = [0u8; 64];
+    let (mut received, mut sent, mut failed, mut blocks) = (0u32, 0u32, 0u32, 0u32);
+    let (mut first_us, mut last_us) = (0, 0);
+    let mut crc = Crc::new(&CRC32);
+    let mut in_block = 0;
+
+    // Received bytes kept in the bridge FIFO, not scanned for '~'
+    SERIAL.clear_interrupt_cmd();
+    SERIAL.set_bridge(true);
+    while SERIAL.is_connected() && !SERIAL.interrupt_cmd_triggered() {
+        let count = SERIAL.read_bridge(&mut buf);
+        if count == 0 {
+            continue;
+        }
+
+        last_us = device.timer.get_counter().ticks();
+        if received == 0 {
+            first_us = last_us;
+        }
+        received += count as u32;
+        match SERIAL.write(&buf[..count]) {
+            Ok(()) => sent += count as u32,
+            Err(_) => failed += count as u32,
+        }
+
+        // Trailer after each complete block
+        let Some(block) = block
+        else {
+            continue;
+        };
+        let mut data = &buf[..count];
+        while !data.is_empty() {
+            let len = (block - in_block).min(data.len());
+            crc.update(&data[..len]);
+            in_block += len;
+            data = &data[len..];
+            if in_block == block {
+                let _ = SERIAL.write(&crc.value().to_le_bytes());
+                crc = Crc::new(&CRC32);
+                in_block = 0;
+                blocks += 1;
+            }
+        }
+    }
+    let overruns = SERIAL.bridge_overruns();
+    SERIAL.set_bridge(false);
+
+    // From the first to the last received chunk
+    let secs = last_us.saturating_sub(first_us) as f32 / 1_000_000.0;
+    let rate = match secs > 0.0 {
+        true => received as f32 / secs / 1024.0,
+        false => 0.0,
+    };
+
+    println!("\n\n---- Echo ----");
+    println!("Received: {received} B | sent: {sent} B | in {secs:.3}s | {rate:.1} KB/s");
+    println!("Dropped: {overruns} B (receive FIFO full) | {failed} B (send failed)");
+    if let Some(block) = block {
+        println!("CRC blocks: {blocks} of {block} B | {in_block} B in the last partial block");
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Jobs
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Background jobs started with a trailing '&', ex: sample_adc interval=500 &
+// ex: fg id=2
+// ex: kill all
+
+pub fn build_jobs_cmd() -> Command {
+    Command {
+        name:     "jobs",
+        desc:     "Lists the commands running in the background",
+        help:     "jobs [help]\n
+    A command line ending with '&' runs in the background (ex: sample_adc interval=500 &)
+    and the prompt returns right away. The job output is prefixed with its [id].
+    fg brings a job back to the foreground, kill ends it",
+        category: Category::Base,
+        requires: &[],
+        func:     jobs_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn jobs_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        print!("\nBackground commands:");
+        for name in jobs::supported() {
+            print!(" {name}");
+        }
+        println!();
+        return Ok(());
+    }
+
+    let jobs = jobs::list();
+    if jobs.is_empty() {
+        println!("No background jobs");
+        return Ok(());
+    }
+    for job in jobs {
+        println!("[{}] every {}ms, {} runs | {}", job.id, job.interval_ms, job.runs, job.line);
+    }
+    Ok(())
+}
+
+pub fn build_fg_cmd() -> Command {
+    Command {
+        name:     "fg",
+        desc:     "Brings a background job to the foreground",
+        help:     "fg [id=last(u8)] [help]\n
+    Runs the job command line in the foreground, interrupt it with char \"~\"",
+        category: Category::Base,
+        requires: &[],
+        func:     fg_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn fg_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let id = match args.contains_param("id") {
+        true => args.get_parsed_param::<u8>("id")?,
+        false => jobs::last_id().ok_or("no background jobs")?,
+    };
+    let job = jobs::take(id).ok_or(Error::Parse("id".into_truncate()))?;
+
+    println!("[{id}] {}\n", job.line);
+    build().execute(&job.line, device)
+}
+
+pub fn build_kill_cmd() -> Command {
+    Command {
+        name:     "kill",
+        desc:     "Ends a background job",
+        help:     "kill id=..(u8) / all [help]",
+        category: Category::Base,
+        requires: &[],
+        func:     kill_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn kill_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("all") {
+        println!("{} jobs ended", jobs::kill_all());
+        return Ok(());
+    }
+
+    let id = args
+        .get_parsed_param::<u8>("id")
+        .map_err(|_| Error::MissingArg("id / all".into_truncate()))?;
+    if !jobs::kill(id) {
+        return Err(Error::Parse("id".into_truncate()));
+    }
+    println!("[{id}] ended");
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Soak
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Unattended endurance test, results kept in flash and reported on the next connection
+// ex: soak hours=24 actions="toggle OUT_A every 1s; read_adc every 60s"
+// ex: soak stop
+
+pub fn build_soak_cmd() -> Command {
+    Command {
+        name:     "soak",
+        desc:     "Unattended soak test repeating actions for hours",
+        help:     "soak [hours=24(0.01-720)] [actions=\"..\"(str)] [stop] [report] [clear] \
+                   [help]\n
+    actions : up to 4 \"<command line> every <duration>\" separated by ';', quoted
+              toggle|high|low <pin> is short for pin alias=<pin> toggle|high|low
+    stop    : ends the running soak, report : prints the results saved in flash
+    clear   : drops the saved results
+    Runs without a host connected, a command returning an error counts as a failure.
+    The results are saved on failures and every 10 minutes, the next connection prints them",
+        category: Category::Base,
+        requires: &[],
+        func:     soak_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn soak_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("stop") {
+        match soak::stop()? {
+            true => println!("Soak stopped"),
+            false => println!("No soak running"),
+        }
+    }
+
+    if args.contains_param("clear") {
+        match soak::clear()? {
+            true => println!("Soak results cleared"),
+            false => return Err("stop the soak first".into()),
+        }
+        return Ok(());
+    }
+
+    if let Some(actions) = args.get_str_param("actions") {
+        let hours: f32 = args.get_ranged_param_or("hours", 0.01..=720.0, 24.0)?;
+        soak::start(actions, (hours * 3600.0) as u32)?;
+        println!("Soak started for {hours}h, it keeps running when the host disconnects");
+        for (index, action) in soak::actions().iter().enumerate() {
+            println!("  {index}: {} every {}ms", action.line, action.period_ms);
+        }
+        return Ok(());
+    }
+
+    soak::print_report();
+    Ok(())
+}
+
+/// Shared start tick of a command run with sync_start, latched with at=+.. if none is pending
+pub(super) fn sync_start(args: &[Argument]) -> Result<Option<scheduler::Instant>> {
+    const MAX_DELAY_MS: u32 = 60_000;
+
+    if !args.contains_param("sync_start") {
+        return Ok(None);
+    }
+
+    let delay_us = match args.get_str_param("at") {
+        Some(value) => value
+            .strip_prefix('+')
+            .and_then(parse_duration_ms)
+            .filter(|ms| (1..=MAX_DELAY_MS).contains(ms))
+            .map(|ms| ms * 1000)
+            .ok_or(Error::Parse("at".into_truncate()))
+            .map(Some)?,
+        None => None,
+    };
+
+    let at = SCHEDULER.latch_start(delay_us)?;
+    let remaining_us = scheduler::time_until(SCHEDULER.now(), at.ticks());
+    println!("Sync start at tick {} (in {}ms)", at.ticks(), remaining_us / 1000);
+    Ok(Some(at))
+}
+
+/// Alias of a mirror input, BOOTSEL included
+fn mirror_input_alias(gpio: u8) -> &'static str {
+    match gpio {
+        bootsel::GPIO => bootsel::ALIAS,
+        gpio => CONFIG.get_alias(gpio).unwrap_or("?"),
+    }
+}
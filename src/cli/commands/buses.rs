@@ -0,0 +1,689 @@
+//! I2C, SPI, UART and register map commands
+// Register new commands in commands.rs > Command List Builder
+
+use super::*;
+use crate::prelude::*;
+
+use crate::system::buses::BusId;
+use crate::system::pwms::PwmChannelExt;
+use crate::system::rc_input::{self, Decoder, Frame, Protocol};
+use crate::system::regmap::{Access, REGMAP};
+use crate::system::snapshot;
+use crate::system::uart::{LineConfig, UartId};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MAX_DATA_BYTES: usize = 32;
+const MAX_RC_OUTPUTS: usize = 8;
+const SERVO_FREQ: u32 = 50;
+
+type Bytes = Vec<u8, MAX_DATA_BYTES>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               I2C
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: i2c scan
+// ex: i2c read addr=0x6a reg=0x0f
+// ex: i2c write addr=0x6a reg=0x10 data=0x60
+
+pub fn build_i2c_cmd() -> Command {
+    Command {
+        name:     "i2c",
+        desc:     "I2C bus scan, read and write",
+        help:     "i2c [scan] / [read] / [write] [bus=i2c0(str)] [addr=..(u8)] [reg=..(u8)] \
+                   [len=1(u8)] \n        [data=..(u8,u8,..)] [help]\n
+    scan  : lists the responding addresses
+    read  : reads len bytes, from reg if given
+    write : writes data bytes, to reg if given",
+        category: Category::Io,
+        requires: &[Requirement::Bus {
+            param:   "bus",
+            default: BusId::I2c0,
+        }],
+        func:     i2c_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn i2c_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let bus: BusId = args.get_str_param("bus").unwrap_or("i2c0").parse()?;
+    if !bus.is_i2c() {
+        return Err("bus must be i2c0 or i2c1".into());
+    }
+
+    // Scan
+    if args.contains_param("scan") {
+        println!("Scanning {bus}...\n");
+        let columns = TERM.hex_columns();
+        print_hex_header(columns);
+
+        let mut found = 0;
+        for row in (0..0x80).step_by(columns) {
+            print!("{row:02x}: ");
+            for addr in row..row + columns as u8 {
+                // Reserved addresses
+                if addr < 0x08 || addr > 0x77 {
+                    print!("   ");
+                }
+                else if device.buses.i2c_read(bus, addr, &mut [0u8]).is_ok() {
+                    print!("{addr:02x} ");
+                    found += 1;
+                }
+                else {
+                    print!("-- ");
+                }
+            }
+            println!();
+        }
+
+        println!("\nFound {found} device(s)");
+        return Ok(());
+    }
+
+    let addr = args.get_int_param("addr")? as u8;
+    let reg = args.get_int_param("reg").ok().map(|r| r as u8);
+
+    // Write
+    if args.contains_param("write") {
+        let data = parse_bytes(args.get_str_param("data").unwrap_or(""))?;
+
+        let mut bytes = Bytes::new();
+        if let Some(reg) = reg {
+            let _ = bytes.push(reg);
+        }
+        bytes
+            .extend_from_slice(&data)
+            .map_err(|_| Error::ArgTooLong("data".into_truncate(), MAX_DATA_BYTES))?;
+
+        device.buses.i2c_write(bus, addr, &bytes)?;
+        print!("Wrote to 0x{addr:02x}: ");
+        print_bytes(&bytes);
+        return Ok(());
+    }
+
+    // Read
+    let len = (args.get_parsed_param::<u8>("len").unwrap_or(1) as usize).clamp(1, MAX_DATA_BYTES);
+    let mut buf = [0u8; MAX_DATA_BYTES];
+
+    match reg {
+        Some(reg) => device
+            .buses
+            .i2c_write_read(bus, addr, &[reg], &mut buf[..len])?,
+        None => device.buses.i2c_read(bus, addr, &mut buf[..len])?,
+    }
+
+    print!("Read from 0x{addr:02x}: ");
+    print_bytes(&buf[..len]);
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               SPI
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: spi data=0x9f,0,0,0
+
+pub fn build_spi_cmd() -> Command {
+    Command {
+        name:     "spi",
+        desc:     "SPI full duplex transfer",
+        help:     "spi data=..(u8,u8,..) [bus=spi0(str)] [help]\n
+    Sends the data bytes with CSn low and prints the received bytes",
+        category: Category::Io,
+        requires: &[Requirement::Bus {
+            param:   "bus",
+            default: BusId::Spi0,
+        }],
+        func:     spi_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn spi_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let bus: BusId = args.get_str_param("bus").unwrap_or("spi0").parse()?;
+    if bus.is_i2c() {
+        return Err("bus must be spi0 or spi1".into());
+    }
+
+    let data = args
+        .get_str_param("data")
+        .ok_or(Error::MissingArg("data".into_truncate()))?;
+    let mut buf = parse_bytes(data)?;
+
+    print!("TX: ");
+    print_bytes(&buf);
+
+    device.buses.spi_transfer(bus, &mut buf)?;
+
+    print!("RX: ");
+    print_bytes(&buf);
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Register Map
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Define a device and its registers once, save them to flash, then access registers by name
+// ex: dev define lsm6ds3 bus=i2c0 addr=0x6a
+// ex: dev reg lsm6ds3.who_am_i addr=0x0f ro
+// ex: dev read lsm6ds3.who_am_i
+
+pub fn build_dev_cmd() -> Command {
+    Command {
+        name:     "dev",
+        desc:     "Named device register access",
+        help:     "dev [list] / [read DEV.REG|DEV] / [write DEV.REG value=..(u32)] [help]
+    dev define DEV bus=i2c0(str) [addr=..(u8)]
+    dev reg DEV.REG addr=..(u8) [width=8(bits)] [ro] [le]
+    dev line=\"reg DEV.REG 0x0f 8 ro\"   : applies a line in the stored format
+    dev remove DEV[.REG] / dev save / dev load / dev clear\n
+    read DEV reads all the registers of the device",
+        category: Category::Drivers,
+        requires: &[],
+        func:     dev_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn dev_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    // Definition line in the stored format
+    if let Some(line) = args.get_str_param("line") {
+        REGMAP.apply_line(line)?;
+        println!("Applied: {line}");
+        return Ok(());
+    }
+
+    let action = positional(args, 0).unwrap_or("list");
+    let target = positional(args, 1);
+    let required_target = || target.ok_or(Error::MissingArg("DEV.REG".into_truncate()));
+
+    match action {
+        "list" => {
+            let devices = REGMAP.devices();
+            if devices.is_empty() {
+                println!("No devices defined");
+            }
+            for dev in devices.iter() {
+                println!("{dev}");
+                for reg in REGMAP.registers(&dev.name).iter() {
+                    println!("  {reg}");
+                }
+            }
+        }
+        "define" => {
+            let bus: BusId = args
+                .get_str_param("bus")
+                .ok_or(Error::MissingArg("bus".into_truncate()))?
+                .parse()?;
+            let addr = args.get_int_param("addr").unwrap_or(0) as u8;
+
+            REGMAP.define_device(required_target()?, bus, addr)?;
+            let dev = REGMAP.device(required_target()?)?;
+            println!("Defined {dev}");
+        }
+        "reg" => {
+            let addr = args.get_int_param("addr")? as u8;
+            let width: u8 = args.get_parsed_param("width").unwrap_or(8);
+            let access = if args.contains_param("ro") {
+                Access::ReadOnly
+            }
+            else {
+                Access::ReadWrite
+            };
+
+            REGMAP.define_register(
+                required_target()?,
+                addr,
+                width,
+                access,
+                args.contains_param("le"),
+            )?;
+            let (_, reg) = REGMAP.lookup(required_target()?)?;
+            println!("Defined {reg}");
+        }
+        "read" => {
+            let target = required_target()?;
+
+            if target.contains('.') {
+                let (dev, reg) = REGMAP.lookup(target)?;
+                let value = reg.read(&dev, &mut device.buses)?;
+                print_register(&dev.name, &reg.name, reg.width, value);
+            }
+            else {
+                // Reading all registers of the device
+                let dev = REGMAP.device(target)?;
+                for reg in REGMAP.registers(target).iter() {
+                    match reg.read(&dev, &mut device.buses) {
+                        Ok(value) => print_register(&dev.name, &reg.name, reg.width, value),
+                        Err(e) => println!("{}.{}: Err: {e}", dev.name, reg.name),
+                    }
+                }
+            }
+        }
+        "write" => {
+            let (dev, reg) = REGMAP.lookup(required_target()?)?;
+            let value = args.get_int_param("value")?;
+
+            reg.write(&dev, &mut device.buses, value)?;
+            print!("Wrote ");
+            print_register(&dev.name, &reg.name, reg.width, value);
+        }
+        "remove" => {
+            let target = required_target()?;
+            if !REGMAP.remove(target) {
+                return Err("not found".into());
+            }
+            println!("Removed {target}");
+        }
+        "save" => {
+            REGMAP.save()?;
+            println!("Register map saved");
+        }
+        "load" => {
+            let count = REGMAP.load();
+            println!("Loaded {count} register(s)");
+        }
+        "clear" => {
+            REGMAP.clear();
+            println!("Register map cleared, use 'dev save' to clear the flash copy");
+        }
+        _ => return Err(Error::Parse(action.into_truncate())),
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Bus Trace
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Records the I2C/SPI transactions of the CLI commands for protocol debugging
+// ex: bus_trace on
+// ex: bus_trace dump clear
+
+pub fn build_bus_trace_cmd() -> Command {
+    Command {
+        name:     "bus_trace",
+        desc:     "Records I2C/SPI transactions",
+        help:     "bus_trace [on] / [off] [dump] [clear] [help]\n
+    Prints the trace status when called without arguments",
+        category: Category::Dev,
+        requires: &[],
+        func:     bus_trace_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn bus_trace_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let trace = &mut device.buses.trace;
+
+    if args.contains_param("on") {
+        trace.enable(true);
+    }
+    if args.contains_param("off") {
+        trace.enable(false);
+    }
+
+    if args.contains_param("dump") {
+        if trace.dropped() > 0 {
+            println!("({} older records dropped)", trace.dropped());
+        }
+        for record in trace.iter() {
+            println!("{record}");
+        }
+        println!();
+    }
+
+    if args.contains_param("clear") {
+        trace.clear();
+        println!("Trace cleared");
+    }
+
+    let state = if trace.is_enabled() { "ON" } else { "OFF" };
+    println!("Bus trace: {state} | records: {}", trace.len());
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           UART Bridge
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Forwards the serial terminal to a hardware UART, the host baud rate/parity/stop bits are
+// mirrored onto the UART whenever the terminal changes them
+// ex: uart_bridge
+// ex: uart_bridge uart=uart1 baud=9600
+
+pub fn build_uart_bridge_cmd() -> Command {
+    Command {
+        name:     "uart_bridge",
+        desc:     "USB serial to hardware UART passthrough",
+        help:     "uart_bridge [uart=uart0(str)] [baud=..(u32)] [help]
+    Follows the terminal line settings, baud fixes the UART at baud 8N1
+    RTS/CTS flow control when the UARTx_CTS and UARTx_RTS pins are defined
+    Ctrl+] or closing the terminal ends the bridge",
+        category: Category::Io,
+        requires: &[],
+        func:     uart_bridge_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn uart_bridge_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let uart: UartId = args.get_str_param("uart").unwrap_or("uart0").parse()?;
+
+    // A fixed baud rate ignores the terminal line settings
+    let fixed = args
+        .get_parsed_param::<u32>("baud")
+        .ok()
+        .map(|baud| LineConfig {
+            baud,
+            ..Default::default()
+        });
+
+    let config = fixed.unwrap_or_else(|| SERIAL.line_coding());
+    device.uarts.configure(uart, config)?;
+    let flow = if device.uarts.has_flow_control(uart)? { " RTS/CTS" } else { "" };
+    println!("Bridging {uart} at {config}{flow}, Ctrl+] to exit\n");
+
+    let before = device.uarts.rx_stats(uart)?;
+    SERIAL.clear_interrupt_cmd();
+    SERIAL.set_bridge(true);
+    let result = bridge_loop(device, uart, config, fixed.is_none());
+    let overruns = SERIAL.bridge_overruns();
+    SERIAL.set_bridge(false);
+
+    println!("\nBridge closed, {overruns} byte(s) dropped");
+    println!("{uart}: {}", device.uarts.rx_stats(uart)?.since(&before));
+    result
+}
+
+fn bridge_loop(
+    device: &mut Device,
+    uart: UartId,
+    mut config: LineConfig,
+    follow: bool,
+) -> Result<()> {
+    let mut tx = [0u8; 64];
+    let mut tx_len = 0;
+    let mut tx_pos = 0;
+    let mut rx = [0u8; 64];
+
+    while SERIAL.is_connected() && !SERIAL.interrupt_cmd_triggered() {
+        // Terminal line settings
+        if follow {
+            let coding = SERIAL.line_coding();
+            if coding != config {
+                // Unsupported settings keep the previous configuration
+                let _ = device.uarts.configure(uart, coding);
+                config = coding;
+            }
+        }
+
+        // Terminal to UART, the unsent bytes are kept for the next pass
+        if tx_pos == tx_len {
+            tx_len = SERIAL.read_bridge(&mut tx);
+            tx_pos = 0;
+        }
+        if tx_pos < tx_len {
+            tx_pos += device.uarts.write(uart, &tx[tx_pos..tx_len])?;
+        }
+
+        // UART to terminal
+        let count = device.uarts.read(uart, &mut rx)?;
+        if count > 0 {
+            let _ = SERIAL.write(&rx[..count]);
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            SBUS / IBUS
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: sbus
+// ex: sbus proto=ibus uart=uart1 rate=2
+// ex: sbus map=1:PWM4_A,3:9
+
+pub fn build_sbus_cmd() -> Command {
+    Command {
+        name:     "sbus",
+        desc:     "Streams the channels of an SBUS/IBUS RC receiver on a UART",
+        help:     "sbus [uart=uart0(str)] [proto=sbus|ibus] [rate=10(1..=50Hz)] \
+                   [map=..(ch:pin,ch:pin,..)] [help]\n
+    proto : sbus, 100000 8E2 inverted line, 16 channels and the frame lost/failsafe flags
+            ibus, 115200 8N1, 14 channels
+    rate  : lines printed per second, channels in us
+    map   : RC passthrough, channels (from 1) driving servo PWM outputs at 50Hz
+            pin alias or gpio, ex: map=1:PWM4_A,3:9, outputs hold their pulse in failsafe
+    Interrupt with char \"~\", the UART settings and the outputs are restored",
+        category: Category::Io,
+        requires: &[],
+        func:     sbus_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn sbus_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let uart: UartId = args.get_str_param("uart").unwrap_or("uart0").parse()?;
+    let protocol: Protocol = args
+        .get_str_param("proto")
+        .unwrap_or("sbus")
+        .parse()
+        .map_err(|_| Error::Parse("proto".into_truncate()))?;
+    let rate: u32 = args.get_ranged_param_or("rate", 1..=50, 10)?;
+    let map = match args.get_str_param("map") {
+        Some(map) => parse_rc_map(map, device)?,
+        None => RcMap::new(),
+    };
+
+    let previous = device.uarts.config(uart)?;
+    device.uarts.configure(uart, protocol.line_config())?;
+    device.uarts.set_rx_inverted(uart, protocol.inverted())?;
+
+    println!("---- {protocol} on {uart} ({}) ----", protocol.line_config());
+    for (channel, gpio) in &map {
+        println!("ch{} -> GPIO {gpio}", channel + 1);
+    }
+    println!();
+
+    let before = device.uarts.rx_stats(uart)?;
+    SERIAL.clear_interrupt_cmd();
+    let mut decoder = Decoder::new(protocol);
+    let result = snapshot::restoring(device, |device| {
+        rc_loop(device, uart, &mut decoder, &map, 1_000_000 / rate as u64)
+    });
+
+    device.uarts.set_rx_inverted(uart, false)?;
+    device.uarts.configure(uart, previous)?;
+    println!("Done! {} corrupt frame(s)", decoder.errors());
+    println!("{uart}: {}", device.uarts.rx_stats(uart)?.since(&before));
+    result
+}
+
+type RcMap = Vec<(usize, u8), MAX_RC_OUTPUTS>;
+
+/// Parses "ch:pin" pairs and starts the PWM slices of the pins at the servo frequency
+fn parse_rc_map(map: &str, device: &mut Device) -> Result<RcMap> {
+    let mut outputs = RcMap::new();
+
+    for entry in map.split(',').filter(|e| !e.is_empty()) {
+        let (channel, pin) = entry
+            .split_once(':')
+            .ok_or(Error::Parse("map".into_truncate()))?;
+        let channel = channel
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|ch| (1..=rc_input::MAX_CHANNELS).contains(ch))
+            .ok_or(Error::Parse("map".into_truncate()))?;
+        let pin = pin.trim();
+        let gpio = match pin.parse::<u8>() {
+            Ok(gpio) => gpio,
+            Err(_) => CONFIG.get_gpio(pin)?,
+        };
+        let (slice_id, _) = device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+
+        outputs
+            .push((channel - 1, gpio))
+            .map_err(|_| Error::ArgTooLong("map".into_truncate(), MAX_RC_OUTPUTS))?;
+        with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
+            pwm_slice.set_freq(SERVO_FREQ);
+            pwm_slice.enable();
+        });
+    }
+
+    Ok(outputs)
+}
+
+fn rc_loop(
+    device: &mut Device,
+    uart: UartId,
+    decoder: &mut Decoder,
+    map: &RcMap,
+    period_us: u64,
+) -> Result<()> {
+    /// No frame for this long is reported as a lost signal
+    const SIGNAL_TIMEOUT_US: u64 = 100_000;
+
+    let mut rx = [0u8; 32];
+    let mut last: Option<Frame> = None;
+    let mut last_time = 0;
+    let mut frames: u32 = 0;
+    let mut lost: u32 = 0;
+    let mut next = device.timer.get_counter().ticks() + period_us;
+
+    while !SERIAL.interrupt_cmd_triggered() {
+        let count = device.uarts.read(uart, &mut rx)?;
+        for &byte in &rx[..count] {
+            let Some(frame) = decoder.push(byte)
+            else {
+                continue;
+            };
+            frames += 1;
+            lost += frame.frame_lost as u32;
+            last = Some(frame);
+            last_time = device.timer.get_counter().ticks();
+
+            // Passthrough, the failsafe values are not forwarded
+            if !frame.failsafe {
+                for &(channel, gpio) in map {
+                    let us = frame.channels().get(channel).copied().unwrap_or(0);
+                    if us > 0 {
+                        device
+                            .pwms
+                            .get_channel_by_gpio(gpio)?
+                            .set_duty_cycle_us(us, SERVO_FREQ);
+                    }
+                }
+            }
+        }
+
+        let now = device.timer.get_counter().ticks();
+        if now < next {
+            continue;
+        }
+        next += period_us;
+
+        match last {
+            Some(frame) if now - last_time < SIGNAL_TIMEOUT_US => {
+                for us in frame.channels() {
+                    print!("{us:4} ");
+                }
+                println!(
+                    "| {}/s | lost: {lost} | failsafe: {}",
+                    frames * 1_000_000 / period_us as u32,
+                    if frame.failsafe { "YES" } else { "no" }
+                );
+            }
+            _ => println!("no signal"),
+        }
+        frames = 0;
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Returns the nth argument given without a value
+fn positional<'a>(args: &[Argument<'a>], index: usize) -> Option<&'a str> {
+    args.iter()
+        .filter(|arg| arg.value.is_empty())
+        .nth(index)
+        .map(|arg| arg.param)
+}
+
+/// Parses a comma separated list of bytes, ex: 0x01,2,0b11
+fn parse_bytes(data: &str) -> Result<Bytes> {
+    let mut bytes = Bytes::new();
+
+    for value in data.split(',').filter(|v| !v.is_empty()) {
+        let byte = parse_int(value.trim())
+            .filter(|b| *b <= u8::MAX as u32)
+            .ok_or(Error::Parse("data".into_truncate()))?;
+        bytes
+            .push(byte as u8)
+            .map_err(|_| Error::ArgTooLong("data".into_truncate(), MAX_DATA_BYTES))?;
+    }
+
+    Ok(bytes)
+}
+
+/// Column offsets of a hex table, 8 or 16 columns depending on the terminal width
+pub fn print_hex_header(columns: usize) {
+    print!("   ");
+    for column in 0..columns {
+        print!(" {column:2x}");
+    }
+    println!();
+}
+
+fn print_bytes(bytes: &[u8]) {
+    for byte in bytes {
+        print!("0x{byte:02x} ");
+    }
+    println!();
+}
+
+fn print_register(dev: &str, reg: &str, width: u8, value: u32) {
+    let digits = width as usize / 4;
+    println!("{dev}.{reg} = 0x{value:0digits$x} ({value})");
+}
@@ -1,21 +1,30 @@
 //! Example Commands
-// Register new commands in commands.rs > Command List Builder
+// Register new commands in commands.rs > Command List Builder, or with register_command!
+
+use core::fmt::Write;
 
 use super::*;
+use crate::main_core1;
 use crate::prelude::*;
+use crate::system::{adcs, dry_run, pwms, snapshot};
+use crate::utils::filter::SampleFilter;
+use crate::utils::morse;
+use crate::utils::time::{Duration, Instant};
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Example
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-pub fn build_example_cmd() -> Command {
-    Command {
-        name: "example",
-        desc: "Prints example args",
-        help: "example <arg(float)> [opt=0(u8)] [on=false(bool)] [path=\"\"(string)] [help]",
-        func: example_cmd,
-    }
-}
+// Registered from this file, no entry in the Command List Builder
+crate::register_command!(Command {
+    name:     "example",
+    desc:     "Prints example args",
+    help:     "example <arg(float)> [opt=0(u8)] [on=false(bool)] [path=\"\"(string)] [help]",
+    category: Category::Dev,
+    requires: &[],
+    func:     example_cmd,
+    timeout:  None,
+});
 
 pub fn example_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
     // Print Help
@@ -48,10 +57,13 @@ pub fn example_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Res
 
 pub fn build_blink_cmd() -> Command {
     Command {
-        name: "blink",
-        desc: "Blinks Onboard Led",
-        help: "blink [times=10] [interval=200(ms)] [help]",
-        func: blink_cmd,
+        name:     "blink",
+        desc:     "Blinks Onboard Led",
+        help:     "blink [times=10] [interval=200(ms)] [help]",
+        category: Category::Dev,
+        requires: &[Requirement::Led],
+        func:     blink_cmd,
+        timeout:  None,
     }
 }
 
@@ -66,35 +78,150 @@ pub fn blink_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
     let interval: u16 = args.get_parsed_param("interval").unwrap_or(200); // 200ms default
 
     println!("---- Blinking Led! ----\n");
-    let led = device.outputs.get(gpio!(LED)).unwrap();
-
-    // Non blocking timer based task
-    let mut ledtask = Tasklet::new(interval as u32, times * 2, &device.timer);
+    if !device.led.is_available() {
+        return Err("no GPIO driven LED on this board".into());
+    }
 
-    let mut blink = 1;
+    // Non blocking timer based task, even blinks whatever the progress redraws take
+    let mut ledtask = Tasklet::new(interval as u32, times * 2);
+    let mut progress = Progress::new("Blink", times as u32);
 
     while !ledtask.is_exhausted() {
-        if ledtask.is_ready() {
-            led.toggle().unwrap();
+        ledtask.run(|toggle| {
+            device.led.toggle(); // Manual mode until the command ends
 
-            if led.is_set_high().unwrap() {
-                print!("Blink {} | ", blink);
-                blink += 1;
+            if device.led.is_on() {
+                progress.update(toggle / 2 + 1);
             }
-        }
+        });
     }
 
     // Non tasklet implementation example:
     //
     // for n in 1..=times {
     //   print!("Blink {} | ", n);
-    //   led.set_high().unwrap();
+    //   device.led.set(true);
     //   device.timer.delay_ms(interval);
-    //   led.set_low().unwrap();
+    //   device.led.set(false);
     //   device.timer.delay_ms(interval);
     // }
 
+    progress.finish();
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Morse
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+// Morse code on the LED or an output, diagnostics without a terminal
+// ex: morse text="SOS" gpio=25 wpm=12
+
+pub fn build_morse_cmd() -> Command {
+    Command {
+        name:     "morse",
+        desc:     "Blinks a text in Morse code on the LED or an output pin",
+        help:     "morse text=\"..\" [gpio=..|alias=..] [wpm=12(1-60)] [repeat=1(0=until ~)] \
+                   [dryrun] [help]\n
+    gpio/alias : output pin, the onboard LED by default
+    wpm        : speed in words per minute, a dot lasts 1200/wpm ms
+    repeat     : times the text is sent, 7 units apart",
+        category: Category::Dev,
+        requires: &[],
+        func:     morse_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn morse_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let text = args
+        .get_str_param("text")
+        .ok_or(Error::MissingArg("text".into_truncate()))?;
+    let wpm = args.get_ranged_param_or("wpm", 1..=morse::MAX_WPM, morse::DEFAULT_WPM)?;
+    let repeat: u16 = args.get_parsed_param("repeat").unwrap_or(1);
+
+    if morse::units(text) == 0 {
+        return Err("nothing to send, no character with a Morse code".into());
+    }
+
+    // Onboard LED unless another pin is given
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    let alias = args.get_str_param("alias");
+    let led = CONFIG.get_gpio("LED").ok();
+    let output = match (gpio, alias) {
+        (None, None) => None,
+        (gpio, alias) => {
+            let (gpio, _) = CONFIG.get_gpio_alias_pair(gpio, alias)?;
+            Some(gpio).filter(|&gpio| Some(gpio) != led)
+        }
+    };
+    match output {
+        Some(gpio) => {
+            device.outputs.get(gpio)?;
+        }
+        None if !device.led.is_available() => return Err("no LED on this board".into()),
+        None => {}
+    }
+
+    print!("Morse:");
+    for c in text.chars() {
+        match morse::code(c) {
+            Some(code) => print!(" {code}"),
+            None if c.is_whitespace() => print!(" /"),
+            None => {}
+        }
+    }
     println!();
+
+    let unit = morse::unit_ms(wpm);
+    let total_ms = morse::units(text) * unit;
+    println!("{wpm} wpm, {unit}ms unit, {:.1}s per send", total_ms as f32 / 1000.0);
+
+    if dry_run::is_active(args.contains_param("dryrun")) {
+        match output {
+            Some(gpio) => println!("[dryrun] would send on GPIO {gpio}"),
+            None => println!("[dryrun] would send on the LED"),
+        }
+        return Ok(());
+    }
+    if repeat == 0 {
+        println!("Send '~' to exit");
+    }
+
+    let mut set = |on: bool| -> Result<()> {
+        match output {
+            Some(gpio) => Ok(device.outputs.set_output(gpio, on)?),
+            None => {
+                device.led.set(on); // Manual mode until the command ends
+                Ok(())
+            }
+        }
+    };
+
+    SERIAL.clear_interrupt_cmd();
+    let mut sent: u16 = 0;
+    'send: while repeat == 0 || sent < repeat {
+        if sent > 0 && wait_ms(morse::WORD_GAP as u32 * unit) {
+            break;
+        }
+        for signal in morse::signals(text) {
+            set(signal.on)?;
+            if wait_ms(signal.units as u32 * unit) {
+                break 'send;
+            }
+        }
+        set(false)?;
+        sent += 1;
+    }
+    set(false)?;
+
+    println!("Sent {sent} time(s)");
     Ok(())
 }
 
@@ -107,10 +234,13 @@ pub fn blink_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
 
 pub fn build_blink_multicore_cmd() -> Command {
     Command {
-        name: "blink_multicore",
-        desc: "Blinks Onboard Led using by passing an event to Core1",
-        help: "blink_multicore [times=10] [interval=200(ms)] [help]",
-        func: blink_multicore_cmd,
+        name:     "blink_multicore",
+        desc:     "Blinks Onboard Led using by passing an event to Core1",
+        help:     "blink_multicore [times=10] [interval=200(ms)] [help]",
+        category: Category::Dev,
+        requires: &[Requirement::Led, Requirement::Core1Idle],
+        func:     blink_multicore_cmd,
+        timeout:  None,
     }
 }
 
@@ -126,9 +256,10 @@ pub fn blink_multicore_cmd(cmd: &Command, args: &[Argument], device: &mut Device
 
     println!("---- Blinking Led using Core1! ----\n");
 
-    CORE1_QUEUE
-        .enqueue(EventCore1::Blink { times, interval })
-        .ok();
+    // Pausing the LED patterns while Core1 drives the pin
+    device.led.set(false);
+
+    main_core1::send(EventCore1::Blink { times, interval }, &mut device.sio_fifo, device.timer)?;
 
     // We wait since we don't have a done callback implemented
     for blink in 1..=times {
@@ -149,10 +280,13 @@ pub fn blink_multicore_cmd(cmd: &Command, args: &[Argument], device: &mut Device
 
 pub fn build_sleep_multicore_cmd() -> Command {
     Command {
-        name: "sleep_multicore",
-        desc: "Toggles Core1 between Sleep and Awake",
-        help: "sleep_multicore [help]",
-        func: sleep_multicore_cmd,
+        name:     "sleep_multicore",
+        desc:     "Toggles Core1 between Sleep and Awake",
+        help:     "sleep_multicore [help]",
+        category: Category::Dev,
+        requires: &[],
+        func:     sleep_multicore_cmd,
+        timeout:  None,
     }
 }
 
@@ -169,7 +303,7 @@ pub fn sleep_multicore_cmd(cmd: &Command, args: &[Argument], device: &mut Device
     unsafe {
         if !ASLEEP {
             println!("Setting Core1 to Sleep!");
-            CORE1_QUEUE.enqueue(EventCore1::Sleep).ok();
+            main_core1::send(EventCore1::Sleep, &mut device.sio_fifo, device.timer)?;
             ASLEEP = true;
         }
         else {
@@ -193,11 +327,15 @@ pub fn sleep_multicore_cmd(cmd: &Command, args: &[Argument], device: &mut Device
 
 pub fn build_servo_cmd() -> Command {
     Command {
-        name: "servo",
-        desc: "Set Servo PWM on GPIO 8",
-        help: "servo [alias=PWM4_A(str)] / [gpio=..(u8)] [us=1500(us)] [pause=1000(ms)]\n      \
-               [sweep] [max_us=2000(us)] [help]",
-        func: servo_cmd,
+        name:     "servo",
+        desc:     "Set Servo PWM on GPIO 8",
+        help:
+            "servo [alias=PWM4_A(str)] / [gpio=..(u8)] [us=1500(us)] [pause=1000(ms)]\n      \
+             [sweep] [max_us=2000(us)] [dryrun] [help]",
+        category: Category::Drivers,
+        requires: &[],
+        func:     servo_cmd,
+        timeout:  None,
     }
 }
 
@@ -232,6 +370,21 @@ pub fn servo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
     const FREQ: u32 = 50;
     println!("\nSetting: Duty: {}us, Freq: {}", us, FREQ);
 
+    // Dry run, the slice state is only read
+    if dry_run::is_active(args.contains_param("dryrun")) {
+        let (top, phase) = with_pwm_slice!(&mut device.pwms, pwm_id, |pwm_slice| {
+            (pwm_slice.slice.get_top(), pwm_slice.ph_correct)
+        });
+        let cc = |us: u16| pwms::calculate_duty_from_us(us, FREQ, top.saturating_add(1));
+
+        print_dry_run_pwm(pwm_id, channel, FREQ, top, phase, cc(us));
+        if sweep {
+            println!("[dryrun] would sweep cc {} - {} in {}ms", cc(us), cc(max_us), pause * 4);
+        }
+        println!("[dryrun] would turn the output off");
+        return Ok(());
+    }
+
     // Initializing pwm slice frequency
     with_pwm_slice!(&mut device.pwms, pwm_id, |pwm_slice| {
         pwm_slice.set_freq(FREQ);
@@ -248,11 +401,12 @@ pub fn servo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
         // Sweeping from us to max_us
         println!("Sweeping between: {us}us - {max_us}us in {}ms \n ...", pause * 4);
         let sweep_time = (pause * 2) as f32;
-        let start_time = device.timer.now();
+        let start_time = Instant::now();
+        let mut progress_bar = Progress::new("Sweep", 100);
 
         // PWM duty based on elapsed time and phase
         loop {
-            let elapsed_ms = (device.timer.now() - start_time).to_millis() as f32;
+            let elapsed_ms = start_time.elapsed().as_millis_f32();
 
             // Calculate which phase of the sweep we're in (0-1)
             let phase = (elapsed_ms / sweep_time) as u32;
@@ -266,8 +420,10 @@ pub fn servo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
             };
 
             servo_pin.set_duty_cycle_us(target_us, FREQ);
+            progress_bar.update_fraction(elapsed_ms / (sweep_time * 2.0));
         }
 
+        progress_bar.finish();
         println!("Sweeping complete");
     }
 
@@ -284,10 +440,17 @@ pub fn servo_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
 
 pub fn build_test_gpio_cmd() -> Command {
     Command {
-        name: "test_gpio",
-        desc: "Sets output HIGH when input is LOW",
-        help: "test_gpio [input=IN_A(str)] [output=OUT_A(str)] [help] \nInterrupt with char \"~\" ",
-        func: test_gpio_cmd,
+        name:     "test_gpio",
+        desc:     "Sets output HIGH when input is LOW",
+        help:     "test_gpio [input=IN_A(str)] [output=OUT_A(str)] [help] \nInterrupt with char \
+                   \"~\" ",
+        category: Category::Dev,
+        requires: &[Requirement::PinFree {
+            param:   "output",
+            default: "OUT_A",
+        }],
+        func:     test_gpio_cmd,
+        timeout:  None,
     }
 }
 
@@ -311,19 +474,22 @@ pub fn test_gpio_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> R
     println!("Input: GPIO {gpio_input} - {input} >> Output: GPIO {gpio_output} {output}");
     println!("\nSend '~' to exit\n");
 
-    let input = device.inputs.get(gpio_input).unwrap();
-    let output = device.outputs.get(gpio_output).unwrap();
+    // The output level is restored on exit
+    snapshot::restoring(device, |device| {
+        let input = device.inputs.get(gpio_input).unwrap();
+        let output = device.outputs.get(gpio_output).unwrap();
 
-    // Loop
-    SERIAL.clear_interrupt_cmd();
-    while !SERIAL.interrupt_cmd_triggered() {
-        if input.is_low().unwrap() {
-            output.set_high().unwrap();
-        }
-        else {
-            output.set_low().unwrap();
+        // Loop
+        SERIAL.clear_interrupt_cmd();
+        while !SERIAL.interrupt_cmd_triggered() {
+            if input.is_low().unwrap() {
+                output.set_high().unwrap();
+            }
+            else {
+                output.set_low().unwrap();
+            }
         }
-    }
+    });
 
     println!("Done!");
     Ok(())
@@ -337,11 +503,17 @@ pub fn test_gpio_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> R
 
 pub fn build_test_analog_cmd() -> Command {
     Command {
-        name: "test_analog",
-        desc: "Voltage controlled PWM Duty Cycle",
-        help: "test_analog [input=ADC0(str)] [output=PWM4_A(str)] [min_us=..(us)] \
-               [max_us=..(us)]\n      [help] \nInterrupt with char \"~\" ",
-        func: test_analog_cmd,
+        name:     "test_analog",
+        desc:     "Voltage controlled PWM Duty Cycle",
+        help:     "test_analog [input=ADC0(str)] [output=PWM4_A(str)] [min_us=..(us)] \
+                   [max_us=..(us)]\n      [help] \nInterrupt with char \"~\" ",
+        category: Category::Dev,
+        requires: &[Requirement::PinFree {
+            param:   "output",
+            default: "PWM4_A",
+        }],
+        func:     test_analog_cmd,
+        timeout:  None,
     }
 }
 
@@ -369,46 +541,48 @@ pub fn test_analog_cmd(cmd: &Command, args: &[Argument], device: &mut Device) ->
     println!("\nSend '~' to exit\n");
 
     const FREQ: u32 = 50;
-    const MAX_V: f32 = 3.3;
+    let max_v = adcs::vref();
 
     // Validating pwm pin
     let (pwm_id, channel) = device.pwms.get_pwm_slice_id_by_gpio(gpio_output)?;
 
-    // Initializing PWM slice
-    with_pwm_slice!(&mut device.pwms, pwm_id, |pwm_slice| {
-        pwm_slice.set_freq(FREQ);
-        pwm_slice.enable();
-    });
-
-    let pwm_pin = &mut device.pwms.get_channel_by_gpio(gpio_output).unwrap();
-
-    // Loop
-    SERIAL.clear_interrupt_cmd();
-    while !SERIAL.interrupt_cmd_triggered() {
-        if let Some(raw) = device.adcs.read_by_gpio_id(gpio_input) {
-            // Analog Read - Clamping 0.3V deadzone from both ends
-            let factor = (raw.to_voltage() - 0.3).clamp(0.0, MAX_V - 0.6) / (MAX_V - 0.6);
-
-            // Defined us range
-            if min_us > 0 && max_us > 0 {
-                let us = min_us + ((max_us - min_us) as f32 * factor) as u16;
-                pwm_pin.set_duty_cycle_us(us, FREQ);
-            }
-            // Fraction range
-            else if factor == 0.0 {
-                let _ = pwm_pin.set_duty_cycle_fully_off();
-            }
-            else if factor == 1.0 {
-                let _ = pwm_pin.set_duty_cycle_fully_on();
-            }
-            else {
-                let _ =
-                    pwm_pin.set_duty_cycle_fraction((factor * u16::MAX as f32) as u16, u16::MAX);
+    // The slice settings are restored on exit
+    snapshot::restoring(device, |device| {
+        // Initializing PWM slice
+        with_pwm_slice!(&mut device.pwms, pwm_id, |pwm_slice| {
+            pwm_slice.set_freq(FREQ);
+            pwm_slice.enable();
+        });
+
+        let pwm_pin = &mut device.pwms.get_channel_by_gpio(gpio_output).unwrap();
+
+        // Loop
+        SERIAL.clear_interrupt_cmd();
+        while !SERIAL.interrupt_cmd_triggered() {
+            if let Some(raw) = device.adcs.read_by_gpio_id(gpio_input) {
+                // Analog Read - Clamping 0.3V deadzone from both ends
+                let factor = (raw.to_voltage() - 0.3).clamp(0.0, max_v - 0.6) / (max_v - 0.6);
+
+                // Defined us range
+                if min_us > 0 && max_us > 0 {
+                    let us = min_us + ((max_us - min_us) as f32 * factor) as u16;
+                    pwm_pin.set_duty_cycle_us(us, FREQ);
+                }
+                // Fraction range
+                else if factor == 0.0 {
+                    let _ = pwm_pin.set_duty_cycle_fully_off();
+                }
+                else if factor == 1.0 {
+                    let _ = pwm_pin.set_duty_cycle_fully_on();
+                }
+                else {
+                    let _ = pwm_pin
+                        .set_duty_cycle_fraction((factor * u16::MAX as f32) as u16, u16::MAX);
+                }
             }
         }
-    }
+    });
 
-    pwm_pin.set_duty_cycle_fully_off().unwrap();
     println!("Done!");
     Ok(())
 }
@@ -419,10 +593,13 @@ pub fn test_analog_cmd(cmd: &Command, args: &[Argument], device: &mut Device) ->
 
 pub fn build_test_panic_cmd() -> Command {
     Command {
-        name: "test_panic",
-        desc: "Panics the program",
-        help: "test_panic [help]",
-        func: test_panic_cmd,
+        name:     "test_panic",
+        desc:     "Panics the program",
+        help:     "test_panic [help]",
+        category: Category::Dev,
+        requires: &[],
+        func:     test_panic_cmd,
+        timeout:  None,
     }
 }
 
@@ -443,10 +620,13 @@ pub fn test_panic_cmd(cmd: &Command, args: &[Argument], device: &mut Device) ->
 
 pub fn build_test_log_cmd() -> Command {
     Command {
-        name: "test_log",
-        desc: "Test the logging system",
-        help: "test_log [help] ",
-        func: test_log_cmd,
+        name:     "test_log",
+        desc:     "Test the logging system",
+        help:     "test_log [help] ",
+        category: Category::Dev,
+        requires: &[],
+        func:     test_log_cmd,
+        timeout:  None,
     }
 }
 
@@ -474,10 +654,13 @@ pub fn test_log_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Re
 
 pub fn build_serial_bench_cmd() -> Command {
     Command {
-        name: "serial_bench",
-        desc: "Benchmark serial transfer speed",
-        help: "serial_bench [help] ",
-        func: serial_bench_cmd,
+        name:     "serial_bench",
+        desc:     "Benchmark serial transfer speed",
+        help:     "serial_bench [help] ",
+        category: Category::Dev,
+        requires: &[],
+        func:     serial_bench_cmd,
+        timeout:  None,
     }
 }
 
@@ -500,19 +683,14 @@ pub fn serial_bench_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -
     }
 
     // Starting Benchmark
-    let exec_time = device.timer.get_counter();
+    let exec_time = Instant::now();
 
     // Sending data
 
     let _ = SERIAL.write(&buffer);
 
     // Ending Benchmark
-    let exec_time = device
-        .timer
-        .get_counter()
-        .checked_duration_since(exec_time)
-        .unwrap()
-        .to_micros();
+    let exec_time = exec_time.elapsed().as_micros();
 
     let bandwidth = (BYTES as f64) / (exec_time as f64) * 1_000_000.0 / (1024.0 * 1024.0);
 
@@ -528,10 +706,14 @@ pub fn serial_bench_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -
 
 pub fn build_dht22_cmd() -> Command {
     Command {
-        name: "dht22",
-        desc: "Read DHT22 Temperature and Humidity Sensor",
-        help: "dht22 [help]",
-        func: dht22_cmd,
+        name:     "dht22",
+        desc:     "Read DHT22 Temperature and Humidity Sensor",
+        help:     "dht22 [filter=none(str)] [help]\n
+    filter : median3-median9 (one read every 2s), rate<max step> or none",
+        category: Category::Drivers,
+        requires: &[],
+        func:     dht22_cmd,
+        timeout:  Some(30_000), // 9 reads 2s apart with the median9 filter
     }
 }
 
@@ -542,15 +724,204 @@ pub fn dht22_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Resul
         return Ok(());
     }
 
-    println!("Reading DHT22 Sensor\n");
+    device.drivers.run("dht22", args)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Drivers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Drivers implement drivers::Driver and register on device.drivers in Device::new
+// ex: drv dht22 filter=median3
+
+pub fn build_drivers_cmd() -> Command {
+    Command {
+        name:     "drivers",
+        desc:     "Lists the registered drivers",
+        help:     "drivers [help]",
+        category: Category::Drivers,
+        requires: &[],
+        func:     drivers_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn drivers_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if device.drivers.is_empty() {
+        println!("No drivers registered");
+        return Ok(());
+    }
+
+    println!("{:<12} {:<40} Status", "Driver", "Description");
+    device.drivers.for_each(|driver, init_err| {
+        let mut status: String<64> = String::new();
+        let _ = match init_err {
+            Some(e) => write!(status, "init failed: {e}"),
+            None => driver.status(&mut status),
+        };
+        println!("{:<12} {:<40} {status}", driver.name(), driver.desc());
+    });
+
+    Ok(())
+}
+
+pub fn build_drv_cmd() -> Command {
+    Command {
+        name:     "drv",
+        desc:     "Runs a driver command",
+        help:     "drv <name> [args..] [help]\n
+    name : registered driver, see drivers
+    args : passed to the driver, drv <name> help lists them",
+        category: Category::Drivers,
+        requires: &[],
+        func:     drv_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn drv_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // The first arg is the driver, its help is printed by the driver
+    let Some((name, args)) = args.split_first()
+    else {
+        cmd.print_help();
+        return Err(Error::MissingArg("name".into_truncate()));
+    };
+    if name.param.eq_ignore_ascii_case("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    device.drivers.run(name.param, args)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                       HX711 Load Cell Scale
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Tare with an empty cell, then calibrate with a known load. Calibration is saved to flash.
+// ex: scale tare
+// ex: scale cal known=500g
+// ex: scale read samples=20 stream
+
+pub fn build_scale_cmd() -> Command {
+    Command {
+        name:     "scale",
+        desc:     "HX711 load cell scale",
+        help:     "scale [read] [tare] [cal known=..(f32 + unit)] [raw] [samples=10(u8)] [stream] \
+                   [filter=none(str)] [help]\n
+    read   : prints the weight in calibrated units (default)
+    tare   : zeroes the scale with an empty load cell
+    cal    : calibrates the scale with a known load, ex: cal known=500g
+    raw    : prints the raw averaged reading
+    filter : median3-median9, rate<max step> or none, applied to the printed values
+    Interrupt stream with char \"~\"",
+        category: Category::Drivers,
+        requires: &[],
+        func:     scale_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn scale_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let samples: u8 = args.get_parsed_param("samples").unwrap_or(10);
 
-    let (humidity, temperature) = device.dht.read().map_err(|e| {
-        println!("Err: {e}");
-        Error::CriticalFail
-    })?;
+    // Tare
+    if args.contains_param("tare") {
+        println!("Taring, keep the scale empty...");
+        let offset = device.hx711.tare(samples)?;
 
-    println!("Humidity   : {:.1} %RH", humidity);
-    println!("Temperature: {:.1} C\n", temperature);
+        SETTINGS.set("hx711.offset", offset)?;
+        SETTINGS.save()?;
+        println!("Offset: {offset} (saved)");
+        return Ok(());
+    }
+
+    // Calibrate
+    if args.contains_param("cal") {
+        let known = args
+            .get_str_param("known")
+            .ok_or(Error::MissingArg("known".into_truncate()))?;
+
+        // Splitting the value from the unit, ex: 500g
+        let unit = known.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-');
+        let value = &known[..known.len() - unit.len()];
+        let value: f32 = value
+            .parse()
+            .map_err(|_| Error::Parse("known".into_truncate()))?;
+        let unit = if unit.is_empty() { "g" } else { unit };
+
+        println!("Calibrating with {value}{unit}...");
+        let scale = device.hx711.calibrate(value, samples)?;
+
+        SETTINGS.set("hx711.scale", scale)?;
+        SETTINGS.set("hx711.unit", unit)?;
+        SETTINGS.save()?;
+        println!("Scale: {scale:.3} counts/{unit} (saved)");
+        return Ok(());
+    }
+
+    // Read
+    let raw = args.contains_param("raw");
+    let stream = args.contains_param("stream");
+    let unit = SETTINGS.get("hx711.unit").unwrap_or("g".into_truncate());
+    let mut filter: SampleFilter = match args.contains_param("filter") {
+        true => args.get_parsed_param("filter")?,
+        false => SampleFilter::None,
+    };
+
+    if stream {
+        println!("Send '~' to exit\n");
+    }
+
+    SERIAL.clear_interrupt_cmd();
+    loop {
+        // One shot reads fill the filter window before printing
+        let reads = if stream { 1 } else { filter.window() };
+
+        if raw {
+            let mut value = 0.0;
+            for _ in 0..reads {
+                value = filter.apply(device.hx711.read_average(samples)? as f32);
+            }
+            println!("Raw: {value:.0}");
+        }
+        else {
+            let mut weight = 0.0;
+            for _ in 0..reads {
+                weight = filter.apply(device.hx711.read_units(samples)?);
+            }
+            println!("Weight: {weight:.2} {unit}");
+        }
+
+        if !stream || SERIAL.interrupt_cmd_triggered() {
+            break;
+        }
+    }
 
     Ok(())
 }
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Waits `ms`, returns true if the command was interrupted
+fn wait_ms(ms: u32) -> bool {
+    let start = Instant::now();
+    while !start.has_elapsed(Duration::from_millis(ms as u64)) {
+        if SERIAL.interrupt_cmd_triggered() {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,256 @@
+//! RAM file commands, the files are written by the output redirection: cmd > file
+// Register new commands in commands.rs > Command List Builder
+
+use super::*;
+use crate::prelude::*;
+
+use crate::system::files::{Error as FileError, FILES, STORE_SIZE};
+use crate::system::flash;
+use crate::utils::crc::{self, ALGORITHMS, Algorithm, CRC32};
+use crate::utils::encoding::{Encoding, LineEncoder};
+use crate::utils::time::Instant;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Files
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: read_adc > adc.txt
+// ex: files rm=adc.txt
+// ex: files save
+
+pub fn build_files_cmd() -> Command {
+    Command {
+        name:     "files",
+        desc:     "Lists, removes and saves the RAM files",
+        help:     "files [list] / [rm=..(str)] / [save] / [load] / [clear] [help]\n
+    Files are written with the output redirection: cmd > file, cmd >> file appends
+    save  : stores all files to flash, loaded at boot
+    load  : restores the files saved in flash
+    clear : removes all files from RAM",
+        category: Category::Base,
+        requires: &[],
+        func:     files_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn files_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("rm") {
+        if !FILES.remove(name) {
+            return Err(FileError::NotFound.into());
+        }
+        println!("Removed {name}");
+        return Ok(());
+    }
+
+    if args.contains_param("save") {
+        FILES.save()?;
+        println!("Files saved to flash");
+        return Ok(());
+    }
+
+    if args.contains_param("load") {
+        let count = FILES.load();
+        println!("Loaded {count} file(s) from flash");
+        return Ok(());
+    }
+
+    if args.contains_param("clear") {
+        FILES.clear();
+        println!("Files cleared");
+        return Ok(());
+    }
+
+    // List
+    let mut count = 0;
+    FILES.for_each(|name, size| {
+        println!("  {name:<16} {size:>5} bytes");
+        count += 1;
+    });
+    println!("\n{count} file(s), {}/{STORE_SIZE} bytes used", FILES.used());
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Cat
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: cat adc.txt
+
+pub fn build_cat_cmd() -> Command {
+    Command {
+        name:     "cat",
+        desc:     "Prints a RAM file",
+        help:     "cat <name(str)> [help]",
+        category: Category::Base,
+        requires: &[],
+        func:     cat_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn cat_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let data = FILES.read(file_name(args)?)?;
+    let text = match core::str::from_utf8(&data) {
+        Ok(text) => text,
+        Err(e) => core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default(),
+    };
+    print!("{text}");
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Download
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Sends the file between marker lines for host scripts:
+//   #BEGIN name=adc.txt len=123 crc32=0x1c291ca3 encoding=raw
+//   <len bytes, or base64/hex lines>
+//   #END
+// ex: download adc.txt
+// ex: download adc.txt encoding=b64
+
+pub fn build_download_cmd() -> Command {
+    Command {
+        name:     "download",
+        desc:     "Sends a RAM file framed for host scripts",
+        help:     "download <name(str)> [encoding=raw(raw|b64|hex)] [help]\n
+    Prints a #BEGIN line with the length and CRC-32, the data, then #END
+    b64 and hex send the data as text lines, len and crc32 refer to the raw bytes",
+        category: Category::Base,
+        requires: &[],
+        func:     download_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn download_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let name = file_name(args)?;
+    let encoding = match args.get_str_param("encoding") {
+        Some(encoding) => encoding
+            .parse::<Encoding>()
+            .map_err(|_| Error::Parse("encoding".into_truncate()))?,
+        None => Encoding::Raw,
+    };
+    let data = FILES.read(name)?;
+
+    let crc = crc::checksum(&CRC32, &data);
+    println!("#BEGIN name={name} len={} crc32=0x{crc:08x} encoding={encoding}", data.len());
+    match encoding {
+        Encoding::Raw => {
+            SERIAL.write(&data).map_err(|_| Error::IoInput)?;
+            println!();
+        }
+        _ => {
+            let mut encoder = LineEncoder::new(encoding);
+            encoder.push(&data, |line| println!("{line}"));
+            encoder.finish(|line| println!("{line}"));
+        }
+    }
+    println!("#END");
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Crc
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Checksums to verify an upload or a flash range against the host copy
+// ex: crc adc.txt
+// ex: crc flash=0x1fa000 len=4096 alg=all
+
+pub fn build_crc_cmd() -> Command {
+    Command {
+        name:     "crc",
+        desc:     "Computes the CRC of a RAM file or a flash range",
+        help:     "crc <name(str)> / flash=..(u32) len=..(u32) [alg=crc32(str)|all] [table] \
+                   [help]\n
+    flash : offset in the onboard flash, 0x.. accepted
+    alg   : crc8, crc8-maxim, crc8-sensirion, crc16-modbus, crc16-xmodem, crc16-ccitt, crc32
+    table : skips the DMA sniffer (crc32, crc16-xmodem and crc16-ccitt)",
+        category: Category::Dev,
+        requires: &[],
+        func:     crc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn crc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let algs: &[&'static Algorithm] = match args.get_str_param("alg") {
+        Some(name) if name.eq_ignore_ascii_case("all") => &ALGORITHMS,
+        Some(name) => {
+            let alg = Algorithm::from_name(name).ok_or(Error::Parse("alg".into_truncate()))?;
+            &[alg]
+        }
+        None => &[&CRC32],
+    };
+    let table = args.contains_param("table");
+
+    let run = |data: &[u8], device: &mut Device| {
+        println!("{} bytes", data.len());
+        for &alg in algs {
+            let start = Instant::now();
+            let crc = match table {
+                true => crc::table_checksum(alg, data),
+                false => crc::checksum(alg, data),
+            };
+            let us = start.elapsed().as_micros();
+            let digits = alg.width as usize / 4;
+            println!("> {:<14}: 0x{crc:0digits$x} ({us}us)", alg.name);
+        }
+    };
+
+    if args.contains_param("flash") {
+        let offset = args.get_int_param("flash")?;
+        let len = args.get_int_param("len")?;
+        if offset
+            .checked_add(len)
+            .is_none_or(|end| end > flash::FLASH_SIZE)
+        {
+            return Err(Error::Parse("len".into_truncate()));
+        }
+        run(flash::read(offset, len as usize), device);
+        return Ok(());
+    }
+
+    run(&FILES.read(file_name(args)?)?, device);
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// File name given as name=.. or as the first positional argument
+fn file_name<'a>(args: &[Argument<'a>]) -> Result<&'a str> {
+    args.iter()
+        .find_map(|arg| (arg.param == "name").then_some(arg.value))
+        .or_else(|| {
+            args.iter()
+                .find_map(|arg| arg.value.is_empty().then_some(arg.param))
+        })
+        .ok_or(Error::MissingArg("name".into_truncate()))
+}
@@ -0,0 +1,1977 @@
+//! Instrumentation commands: ADC, capture, frequency and clock measurements, runtime stats and events
+// Register new commands in commands.rs > Command List Builder
+
+use core::fmt::Write;
+
+use super::*;
+use crate::hal::{NUM_PWM_SLICES, pwm};
+use crate::main_core1;
+use crate::prelude::*;
+
+use crate::cli::scope::{self, Charset, Scope};
+use crate::system::adcs::{self, ADC_MAX, Filter, NUM_CHANNELS};
+use crate::system::clocks::{self, CLKOUT, Source};
+use crate::system::comparator::{self, COMPARATOR};
+use crate::system::config::Group;
+use crate::system::eventlog::{self, EVENTLOG};
+use crate::system::logic::{self, LOGIC, Setup, Trigger};
+use crate::system::markers::MARKERS;
+use crate::system::playback::{self, PLAYBACK};
+use crate::system::pwms::Channel;
+use crate::system::serial_io::Capture;
+use crate::system::stack_guard::{self, RamLayout};
+use crate::system::{cpu_load, events, gpios, snapshot};
+use crate::utils::crc::{CRC32, Crc};
+use crate::utils::encoding::{Encoding, LineEncoder};
+use crate::utils::filter::SampleFilter;
+use crate::utils::time::{Duration, Instant};
+use crate::{limits, state};
+
+use super::base::sync_start;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Pin Stats
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Usage counters since boot, ex: relay wear or the switching done by a script
+// ex: pinstats
+// ex: pinstats alias=OUT_A reset
+
+pub fn build_pinstats_cmd() -> Command {
+    Command {
+        name:     "pinstats",
+        desc:     "Shows the output toggle counts and the PWM enable times",
+        help:     "pinstats [alias=..(str)] / [gpio=..(u8)] [reset] [help]\n
+    toggles : level changes of the outputs since boot or the last reset,
+              since the first boot in parentheses (kv store, written hourly and on reset)
+    pwm     : time each PWM slice has been enabled, 100ms resolution
+    reset   : clears the counters of the pin and of its PWM slice, or all of them",
+        category: Category::Io,
+        requires: &[],
+        func:     pinstats_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn pinstats_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let pin = match args.contains_param("alias") || args.contains_param("gpio") {
+        true => {
+            let alias = args.get_str_param("alias");
+            let gpio = args.get_parsed_param::<u8>("gpio").ok();
+            Some(CONFIG.get_gpio_alias_pair(gpio, alias)?.0)
+        }
+        false => None,
+    };
+
+    if args.contains_param("reset") {
+        device.state.reset(pin);
+        println!("Counters reset");
+    }
+
+    let uptime_s = device.state.lifetime_uptime_s();
+    let (hr, min) = (uptime_s / 3600, uptime_s % 3600 / 60);
+    println!("Boots: {} | lifetime uptime: {hr}h {min:02}m\n", device.state.boots());
+
+    println!("---- Toggles (lifetime) ----");
+    for gpio in 0..gpios::NUM_MCU_PINS as u8 {
+        let toggles = device.state.toggles(gpio);
+        let output = CONFIG.get_group_type(gpio) == Some(Group::Outputs);
+        let shown = match pin {
+            Some(pin) => pin == gpio,
+            None => toggles > 0 || output,
+        };
+        if shown {
+            let alias = CONFIG.get_alias(gpio).unwrap_or("-");
+            let lifetime = device.state.lifetime_toggles(gpio).unwrap_or(0);
+            println!("> GPIO {gpio:>2} - {alias:<8}: {toggles} ({lifetime})");
+        }
+    }
+
+    println!("\n---- PWM Enabled ----");
+    for slice in 0..NUM_PWM_SLICES {
+        let ms = device.state.pwm_enabled_ms(slice);
+        let shown = match pin {
+            Some(pin) => state::slice_of(pin) == slice,
+            None => ms > 0,
+        };
+        if !shown {
+            continue;
+        }
+
+        let (secs, tenths) = (ms / 1000, ms % 1000 / 100);
+        let (hr, min, sec) = (secs / 3600, secs % 3600 / 60, secs % 60);
+        print!("> PWM {slice}: {hr}h {min:02}m {sec:02}.{tenths}s |");
+        for (gpio, channel) in device.pwms.get_gpios_by_slice_id(slice) {
+            print!(" GPIO {gpio} ({channel})");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Capture
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Samples the input levels around a trigger, optionally starting a timeline on the trigger
+// ex: capture pins=IN_A,IN_B trigger=rise:IN_A pre=64
+// ex: capture pins=IN_A trigger=ADC0>1.5 play=pulses
+// ex: capture pins=IN_A,IN_B period=50 samples=1024 play=pulses repeat=3
+// ex: encoder_sim rate=1000 sync_start at=+500ms, then: capture pins=OUT_A,IN_A sync_start
+
+pub fn build_capture_cmd() -> Command {
+    Command {
+        name:     "capture",
+        desc:     "Triggered logic capture with playback start",
+        help:     "capture pins=..(str) [period=100(us)] [samples=512(u16)] [pre=0(u16)] \
+                   [trigger=now(str)] [play=..(str) [repeat=1(u32)]] [sync_start [at=+500ms]] \
+                   [help]\n
+    pins       : comma separated gpios or aliases to print
+    samples    : total samples (max 1024, see limits), pre of them kept before the trigger
+    trigger    : now, rise:<pin>, fall:<pin>, <adc pin>><volts> or <adc pin><<volts>
+                 ex: trigger=rise:IN_A, trigger=ADC0>1.5
+    play       : timeline started on the trigger, see playback
+    sync_start : first sample on the shared start tick. at=+500ms latches a tick from now if
+                 none is pending, the following sync_start commands (tpo, encoder_sim,
+                 playback, capture) join it until it passes: their first steps share the tick
+    Prints the samples where the pins change, time relative to the trigger
+    Interrupt with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     capture_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn capture_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PERIOD: u32 = 100; // us
+    const DEFAULT_SAMPLES: usize = logic::MAX_SAMPLES / 2; // 512 with the default limits
+
+    let pins = args
+        .get_str_param("pins")
+        .ok_or(Error::MissingArg("pins".into_truncate()))?;
+    let mut gpios = heapless::Vec::<u8, { gpios::NUM_MCU_PINS }>::new();
+    let mut mask = 0u32;
+    for pin in pins.split(',').map(str::trim) {
+        let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+        gpios.push(gpio).map_err(|_| logic::Error::InvalidTrigger)?;
+        mask |= 1 << gpio;
+    }
+
+    // Trigger ---------
+    let (trigger, threshold) = match args.get_str_param("trigger") {
+        Some(trigger) => parse_capture_trigger(trigger)?,
+        None => (Trigger::Now, None),
+    };
+
+    let mut setup = Setup::new(
+        args.get_parsed_param("period").unwrap_or(DEFAULT_PERIOD),
+        args.get_parsed_param("samples").unwrap_or(DEFAULT_SAMPLES),
+    )
+    .pre(args.get_parsed_param("pre").unwrap_or(0))
+    .trigger(trigger);
+    if let Some(at) = sync_start(args)? {
+        setup = setup.start_at(at);
+    }
+
+    // Playback started by the trigger ---------
+    if let Some(name) = args.get_str_param("play") {
+        let events = playback::load(name)?;
+        for event in &events {
+            device.outputs.get(event.gpio)?;
+        }
+        PLAYBACK.prepare(&events, args.get_parsed_param("repeat").unwrap_or(1))?;
+        setup = setup.on_trigger(|| {
+            let _ = PLAYBACK.trigger();
+        });
+        println!("Timeline {name} starts on the trigger");
+    }
+
+    println!("Capturing {} sample(s) every {}us", setup.samples, setup.period_us);
+    println!("Send '~' to exit\n");
+
+    SERIAL.clear_interrupt_cmd();
+    LOGIC.arm(setup)?;
+    while !LOGIC.is_done() {
+        if SERIAL.interrupt_cmd_triggered() {
+            LOGIC.stop();
+            PLAYBACK.stop();
+            break;
+        }
+
+        // ADC thresholds are polled here, the sampler only sees digital levels
+        if let Some((channel, above, volts)) = threshold {
+            let level = device.adcs.read(channel).unwrap_or(0).to_voltage();
+            if (level > volts) == above {
+                LOGIC.trigger();
+            }
+        }
+    }
+
+    if LOGIC.state() != logic::State::Done {
+        println!("Capture stopped before the trigger");
+        return Ok(());
+    }
+
+    // Printing the changes of the selected pins
+    let mut previous = None;
+    LOGIC.for_each(|time_us, levels| {
+        let levels = levels & mask;
+        if previous == Some(levels) && time_us != 0 {
+            return;
+        }
+        previous = Some(levels);
+
+        let mut line = String::<128>::new();
+        for &gpio in &gpios {
+            let alias = CONFIG.get_alias(gpio).unwrap_or("?");
+            let _ = write!(line, " {alias}:{}", (levels >> gpio) & 1);
+        }
+        println!("{time_us:>10}us |{line}");
+    });
+
+    Ok(())
+}
+
+/// ADC channel, above (true) or below the volts
+type AdcThreshold = (u8, bool, f32);
+
+/// Parses a capture trigger, ADC thresholds are returned apart, polled by the command
+fn parse_capture_trigger(trigger: &str) -> Result<(Trigger, Option<AdcThreshold>)> {
+    let pin_gpio = |pin: &str| CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin));
+
+    if trigger == "now" {
+        return Ok((Trigger::Now, None));
+    }
+    if let Some(pin) = trigger.strip_prefix("rise:") {
+        return Ok((Trigger::Rise(pin_gpio(pin)?.0), None));
+    }
+    if let Some(pin) = trigger.strip_prefix("fall:") {
+        return Ok((Trigger::Fall(pin_gpio(pin)?.0), None));
+    }
+
+    let (pin, above, volts) = match (trigger.split_once('>'), trigger.split_once('<')) {
+        (Some((pin, volts)), None) => (pin, true, volts),
+        (None, Some((pin, volts))) => (pin, false, volts),
+        _ => return Err(logic::Error::InvalidTrigger.into()),
+    };
+    let channel = adc_channel(pin_gpio(pin)?.0)?;
+    let volts = volts.parse().map_err(|_| logic::Error::InvalidTrigger)?;
+
+    Ok((Trigger::External, Some((channel, above, volts))))
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Read ADC
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn build_read_adc_cmd() -> Command {
+    Command {
+        name:     "read_adc",
+        desc:     "Read all ADC channels",
+        help:     "read_adc [ref_res=10000(ohm)] [interval=1s(ms|s|m)] [help]\n
+    interval : time between reads when run in the background (ex: read_adc interval=5s &)",
+        category: Category::Io,
+        requires: &[],
+        func:     read_adc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn read_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let ref_res: u32 = args.get_parsed_param("ref_res").unwrap_or(10_000);
+    read_adc(device, ref_res)
+}
+
+pub fn read_adc(device: &mut Device, ref_res: u32) -> Result<()> {
+    println!("---- Read ADC ----");
+    println!("Reference Voltage: {:.3}V ({})", adcs::vref(), vref_source());
+    println!("Reference Pullup Resistor: {}", Ohms(ref_res as f32));
+
+    let channels_to_read: [u8; _] = [0, 1, 2, 3];
+
+    for &channel in &channels_to_read {
+        if let Some(r) = device.adcs.read(channel) {
+            let adc_raw = r;
+            let adc_vol = adc_raw.to_voltage();
+            let adc_res = adc_raw.to_resistance(ref_res);
+            println!(
+                "> ACD {}: v:{}, r:{}, raw:{} \r",
+                channel,
+                Volts(adc_vol),
+                Ohms(adc_res),
+                adc_raw
+            );
+        }
+    }
+
+    // read Temp Sense
+    let adc_raw: u16 = device.adcs.read(TEMP_SENSE_CHN).unwrap_or(0);
+    let adc_vol = adc_raw.to_voltage();
+    let sys_temp = 27.0 - (adc_raw.to_voltage() - 0.706) / 0.001721;
+    println!("Temp Sense: t:{}, v:{}, raw:{}", Celsius(sys_temp), Volts(adc_vol), adc_raw);
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           ADC Config
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Channel filters applied to every ADC read, saved to the settings
+// ex: adc_cfg channel=0 oversample=16 ema=0.2
+// ex: adc_cfg channel=0 ema=off
+// ex: adc_cfg channel=4 reset
+// ex: adc_cfg vref=3.000
+
+pub fn build_adc_cfg_cmd() -> Command {
+    Command {
+        name:     "adc_cfg",
+        desc:     "Configures the ADC reference, channel oversampling and moving average",
+        help:     "adc_cfg [channel=0-4(u8)] [oversample=1(1-256)] [ema=off(0-1)] [reset] / \
+                   [vref=3.3(1-3.6V)|default] [help]\n
+    channel    : ADC0-3, 4 is the temperature sensor
+    oversample : conversions averaged per read, a power of two
+    ema        : weight of the new value in the moving average, off disables it
+    reset      : removes the filter of the channel
+    vref       : voltage on the VREF pin when driven externally, default returns to the board
+                 one. Scales every conversion to volts, saved to flash
+    Lists the reference and the filters of all channels when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     adc_cfg_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn adc_cfg_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Ok(channel) = args.get_parsed_param::<u8>("channel") {
+        let mut filter = device
+            .adcs
+            .filter(channel)
+            .ok_or(Error::Parse("channel".into_truncate()))?;
+
+        if args.contains_param("reset") {
+            filter = Filter::default();
+        }
+        if let Ok(oversample) = args.get_parsed_param::<u16>("oversample") {
+            filter.oversample = oversample;
+        }
+        match args.get_str_param("ema") {
+            Some("off") => filter.ema = None,
+            Some(ema) => {
+                let ema = ema
+                    .parse()
+                    .map_err(|_| Error::Parse("ema".into_truncate()))?;
+                filter.ema = Some(ema);
+            }
+            None => {}
+        }
+
+        if !filter.is_valid() {
+            return Err("oversample: power of two up to 256, ema: 0-1".into());
+        }
+        device.adcs.set_filter(channel, filter)?;
+    }
+
+    match args.get_str_param("vref") {
+        Some("default") => adcs::set_vref(None)?,
+        Some(_) => adcs::set_vref(Some(args.get_ranged_param("vref", adcs::VREF_RANGE)?))?,
+        None => {}
+    }
+
+    println!("  VREF | {:.3}V ({})", adcs::vref(), vref_source());
+    for channel in 0..NUM_CHANNELS as u8 {
+        let filter = device.adcs.filter(channel).unwrap_or_default();
+        match channel {
+            TEMP_SENSE_CHN => println!("  TEMP | {filter}"),
+            _ => println!("  ADC{channel} | {filter}"),
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Sample ADC
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn build_sample_adc_cmd() -> Command {
+    Command {
+        name:     "sample_adc",
+        desc:     "Continuous sampling of an ADC channel",
+        help:     "sample_adc [alias=ADC0(str)] / [gpio=..(u8)] [ref_res=10000(ohm)] \
+                   [interval=200(ms)] [filter=none(str)] [help]\n
+    filter : median3-median9, rate<max step in raw counts> (ex: rate20) or none
+    Interrupt with char \"~\", or run it in the background with a trailing &
+    (ex: sample_adc interval=500 &), the filter is not applied in the background",
+        category: Category::Io,
+        requires: &[],
+        func:     sample_adc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn sample_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "ADC0";
+
+    // Getting Alias or GPIO input ---------
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+
+    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    // -------------------------------------
+
+    let ref_res: u32 = args.get_parsed_param("ref_res").unwrap_or(10_000);
+    let interval: u16 = args.get_parsed_param("interval").unwrap_or(200);
+    let mut filter: SampleFilter = match args.contains_param("filter") {
+        true => args.get_parsed_param("filter")?,
+        false => SampleFilter::None,
+    };
+
+    let channel = adc_channel(gpio)?;
+
+    println!("---- Sample ADC ----");
+    println!("ADC Pin: GPIO {gpio} - {alias} | adc channel: {channel} |\n");
+    println!("Reference Pullup Resistor: {}", Ohms(ref_res as f32));
+    println!("Filter: {filter}");
+    println!("\nSend '~' to exit\n");
+
+    SERIAL.clear_interrupt_cmd();
+    while !SERIAL.interrupt_cmd_triggered() {
+        if let Some(r) = device.adcs.read(channel) {
+            let adc_raw: u16 = (filter.apply(r as f32) + 0.5) as u16;
+            print_adc_sample(adc_raw, ref_res);
+            device.timer.delay_ms(interval as u32);
+        }
+        else {
+            println!("Cannot read channel: {}", channel);
+        }
+    }
+
+    println!("Sampling Interrupted. Done!");
+
+    Ok(())
+}
+
+/// One sample of `sample_adc`, the step of its background job. Filters need the previous
+/// samples and are not applied.
+pub fn sample_adc_step(_cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    let alias = args.get_str_param("alias").unwrap_or("ADC0");
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+    let (gpio, _) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+
+    let ref_res: u32 = args.get_parsed_param("ref_res").unwrap_or(10_000);
+    let channel = adc_channel(gpio)?;
+
+    let adc_raw = device
+        .adcs
+        .read(channel)
+        .ok_or(Error::Parse("channel".into_truncate()))?;
+    print_adc_sample(adc_raw, ref_res);
+    Ok(())
+}
+
+fn print_adc_sample(adc_raw: u16, ref_res: u32) {
+    let adc_vol = adc_raw.to_voltage();
+    let adc_res = adc_raw.to_resistance(ref_res);
+    println!("> v:{}, r:{}, raw:{} \r", Volts(adc_vol), Ohms(adc_res), adc_raw);
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Stream ADC
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Sends raw u16 LE samples framed for host scripts:
+//   #STREAM adc=0 rate=1000 samples=256 format=u16le encoding=b64
+//   <base64 lines, 76 chars>
+//   #END samples=256 len=512 late=0 crc32=0x1c291ca3
+// ex: stream_adc samples=1024 rate=2000
+// ex: stream_adc gpio=27 samples=0 encoding=hex
+
+pub fn build_stream_adc_cmd() -> Command {
+    Command {
+        name:     "stream_adc",
+        desc:     "Streams raw ADC samples encoded for host scripts",
+        help:     "stream_adc [alias=ADC0(str)] / [gpio=..(u8)] [samples=256(u32)] \
+                   [rate=1000(hz)] [encoding=b64(b64|hex|raw)] [help]\n
+    samples=0 streams until interrupted with char \"~\"
+    The samples are raw 12 bit readings as u16 little endian
+    b64 and hex send text lines, raw sends the bytes and needs a sample count
+    The #END line reports the late samples and the CRC-32 of the raw bytes",
+        category: Category::Io,
+        requires: &[],
+        func:     stream_adc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn stream_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "ADC0";
+    const MAX_RATE: u32 = 10_000;
+
+    // Getting Alias or GPIO input ---------
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+
+    let (gpio, _) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    // -------------------------------------
+
+    let channel = adc_channel(gpio)?;
+    let samples: u32 = args.get_parsed_param("samples").unwrap_or(256);
+    let rate: u32 = args.get_parsed_param("rate").unwrap_or(1000);
+    let encoding = match args.get_str_param("encoding") {
+        Some(encoding) => encoding
+            .parse::<Encoding>()
+            .map_err(|_| Error::Parse("encoding".into_truncate()))?,
+        None => Encoding::Base64,
+    };
+
+    if !(1..=MAX_RATE).contains(&rate) {
+        return Err("rate out of range: 1..=10000 hz".into());
+    }
+    if encoding == Encoding::Raw && samples == 0 {
+        return Err("raw encoding needs a sample count".into());
+    }
+
+    let period_us = 1_000_000 / rate as u64;
+    let mut encoder = LineEncoder::new(encoding);
+    let mut crc = Crc::new(&CRC32);
+    let mut count: u32 = 0;
+    let mut late: u32 = 0;
+
+    println!(
+        "#STREAM adc={channel} rate={rate} samples={samples} format=u16le encoding={encoding}"
+    );
+
+    SERIAL.clear_interrupt_cmd();
+    let mut next = device.timer.get_counter().ticks();
+
+    while (samples == 0 || count < samples) && !SERIAL.interrupt_cmd_triggered() {
+        // Pacing the samples, the ones taken behind schedule are counted as late
+        let now = device.timer.get_counter().ticks();
+        if now > next + period_us {
+            late += 1;
+            next = now;
+        }
+        while device.timer.get_counter().ticks() < next {}
+        next += period_us;
+
+        let Some(sample) = device.adcs.read(channel)
+        else {
+            return Err(Error::Configuration(ConfigError::OutOfBounds));
+        };
+
+        let bytes = sample.to_le_bytes();
+        crc.update(&bytes);
+        match encoding {
+            Encoding::Raw => SERIAL.write(&bytes).map_err(|_| Error::IoInput)?,
+            _ => encoder.push(&bytes, |line| println!("{line}")),
+        }
+        count += 1;
+    }
+    encoder.finish(|line| println!("{line}"));
+
+    if encoding == Encoding::Raw {
+        println!();
+    }
+    println!("#END samples={count} len={} late={late} crc32=0x{:08x}", count * 2, crc.value());
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Scope
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Scrolling min/max waveform of an ADC channel, see cli::scope
+// ex: scope channel=0 rate=5000 window=2s trigger=1.65
+
+pub fn build_scope_cmd() -> Command {
+    Command {
+        name:     "scope",
+        desc:     "Scrolling ASCII waveform of an ADC channel",
+        help:     "scope [channel=0(0-4)] / [alias=ADC0(str)] / [gpio=..(u8)] [rate=1000(hz)] \
+                   [window=2s(ms|s)] [rows=12(4-32)] [min=0(V)] [max=vref(V)] [auto] \
+                   [trigger=..(V)] [ascii] [help]\n
+    window  : time across the screen, each column shows the min and max of its samples
+    auto    : scales the rows to the visible min and max
+    trigger : level drawn as a dashed line, its rising crossings are counted in the window
+    ascii   : plain ASCII characters for terminals without unicode
+    The samples taken during a redraw (5 per second) are skipped and counted as late
+    Interrupt with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     scope_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn scope_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "ADC0";
+    const MAX_RATE: u32 = 10_000;
+    const REFRESH_MS: u64 = 200;
+
+    let channel = match args.get_parsed_param::<u8>("channel") {
+        Ok(channel) if channel as usize >= NUM_CHANNELS => {
+            return Err(Error::Parse("channel".into_truncate()));
+        }
+        Ok(channel) => channel,
+        Err(_) => {
+            let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+            let gpio = args.get_parsed_param::<u8>("gpio").ok();
+            let (gpio, _) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+            adc_channel(gpio)?
+        }
+    };
+
+    let rate = args.get_ranged_param_or("rate", 1..=MAX_RATE, 1000)?;
+    let window_ms = match args.get_str_param("window") {
+        Some(value) => parse_duration_ms(value)
+            .filter(|&ms| ms > 0)
+            .ok_or(Error::Parse("window".into_truncate()))?,
+        None => 2000,
+    };
+    let rows = args.get_ranged_param_or("rows", scope::MIN_ROWS..=scope::MAX_ROWS, 12)?;
+    let min: f32 = args.get_parsed_param("min").unwrap_or(0.0);
+    let max: f32 = args.get_parsed_param("max").unwrap_or(adcs::vref());
+    let trigger = match args.contains_param("trigger") {
+        true => Some(volts_to_raw(args.get_parsed_param("trigger")?)),
+        false => None,
+    };
+    let auto = args.contains_param("auto");
+    let charset = match args.contains_param("ascii") {
+        true => Charset::Ascii,
+        false => Charset::Unicode,
+    };
+
+    if min >= max {
+        return Err("min has to be below max".into());
+    }
+
+    let width = TERM.width().saturating_sub(scope::LABEL_WIDTH + 1);
+    let range = volts_to_raw(min)..=volts_to_raw(max);
+    let mut scope = Scope::new(width, rows, range, trigger, charset);
+
+    // Samples per column, the window is rounded to whole samples
+    let per_column = (rate as u64 * window_ms as u64 / 1000 / scope.width() as u64).max(1) as u32;
+    let window_s = (per_column as u64 * scope.width() as u64) as f32 / rate as f32;
+
+    let period_us = 1_000_000 / rate as u64;
+    let mut late: u32 = 0;
+    let mut redraw = Instant::now();
+
+    print!("\x1b[2J");
+    SERIAL.clear_interrupt_cmd();
+    let mut next = device.timer.get_counter().ticks();
+
+    while !SERIAL.interrupt_cmd_triggered() {
+        // Pacing the samples, the ones taken behind schedule are counted as late
+        let now = device.timer.get_counter().ticks();
+        if now > next + period_us {
+            late += 1;
+            next = now;
+        }
+        while device.timer.get_counter().ticks() < next {}
+        next += period_us;
+
+        let Some(sample) = device.adcs.read(channel)
+        else {
+            return Err(Error::Configuration(ConfigError::OutOfBounds));
+        };
+        scope.push(sample, per_column);
+
+        if redraw.has_elapsed(Duration::from_millis(REFRESH_MS)) {
+            redraw = Instant::now();
+            if auto && let Some((low, high, _)) = scope.stats() {
+                scope.set_range(low..=high.max(low + 1));
+            }
+
+            let mut title: String<64> = String::new();
+            let _ = write!(title, "ADC{channel} | {rate}Hz | {window_s:.2}s | late {late}");
+            scope.render(&title);
+        }
+    }
+
+    println!("\nScope interrupted. Done!");
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Plot ADC
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// One line per sample time in the Serial Plotter format of the Arduino IDE:
+//   ADC0:1.652\tADC1:0.314
+// plain drops the labels for Better Serial Plotter and other tab separated readers
+// ex: plot_adc channels=0,1 rate=100
+
+pub fn build_plot_adc_cmd() -> Command {
+    Command {
+        name:     "plot_adc",
+        desc:     "Streams ADC channels for the Arduino Serial Plotter",
+        help:     "plot_adc [channels=0(list)] [rate=50(1-1000hz)] [samples=0(u32)] [raw] [plain] \
+                   [help]\n
+    channels : comma separated ADC channels or aliases, 4 is the temperature sensor,
+               ex: channels=0,1 or ADC0,ADC1,4
+    raw      : 12 bit readings instead of volts
+    plain    : values only, tab separated, for Better Serial Plotter
+    samples=0 plots until interrupted with char \"~\", nothing else is printed meanwhile",
+        category: Category::Io,
+        requires: &[],
+        func:     plot_adc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn plot_adc_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let mut channels: Vec<u8, NUM_CHANNELS> = Vec::new();
+    for name in args.get_str_param("channels").unwrap_or("0").split(',') {
+        let name = name.trim();
+        let channel = match name.parse::<u8>() {
+            Ok(channel) if (channel as usize) < NUM_CHANNELS => channel,
+            Ok(_) => return Err(Error::Parse("channels".into_truncate())),
+            Err(_) => adc_channel(CONFIG.get_gpio(name)?)?,
+        };
+        if !channels.contains(&channel) {
+            channels
+                .push(channel)
+                .map_err(|_| Error::Parse("channels".into_truncate()))?;
+        }
+    }
+
+    let rate = args.get_ranged_param_or("rate", 1..=1000, 50)?;
+    let samples: u32 = args.get_parsed_param("samples").unwrap_or(0);
+    let raw = args.contains_param("raw");
+    let plain = args.contains_param("plain");
+
+    let period_us = 1_000_000 / rate as u64;
+    let mut count: u32 = 0;
+
+    SERIAL.clear_interrupt_cmd();
+    let mut next = device.timer.get_counter().ticks();
+
+    while (samples == 0 || count < samples) && !SERIAL.interrupt_cmd_triggered() {
+        // Pacing, a late sample restarts the schedule
+        let now = device.timer.get_counter().ticks();
+        if now > next + period_us {
+            next = now;
+        }
+        while device.timer.get_counter().ticks() < next {}
+        next += period_us;
+
+        let mut line: String<96> = String::new();
+        for (i, &channel) in channels.iter().enumerate() {
+            let sample = device
+                .adcs
+                .read(channel)
+                .ok_or(Error::Configuration(ConfigError::OutOfBounds))?;
+
+            if i > 0 {
+                let _ = line.push('\t');
+            }
+            if !plain {
+                let _ = write!(line, "ADC{channel}:");
+            }
+            let _ = match raw {
+                true => write!(line, "{sample}"),
+                false => write!(line, "{:.3}", sample.to_voltage()),
+            };
+        }
+        println!("{line}");
+        count += 1;
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Comparator
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Core1 converts back to back and latches the output on the threshold crossings, see
+// system::comparator. Core0 only reports the counters, keeping off the ADC.
+// ex: comparator input=ADC1 output=OUT_B high=2.5 hyst=0.2 invert
+
+pub fn build_comparator_cmd() -> Command {
+    Command {
+        name:     "comparator",
+        desc:     "Fast ADC threshold watch driving an output from core1",
+        help:     "comparator [input=ADC0(str)] [output=OUT_A(str)] [high=1.65(V)] [hyst=0.1(V)] \
+                   [invert] [help]\n
+    The output goes HIGH above high and LOW again below high - hyst, invert swaps the levels
+    Reacts within tens of us, the trip count is printed every second
+    Interrupt with char \"~\", the output is left in its released level",
+        category: Category::Io,
+        requires: &[Requirement::Core1Idle, Requirement::PinFree {
+            param:   "output",
+            default: "OUT_A",
+        }],
+        func:     comparator_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn comparator_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_INPUT: &str = "ADC0";
+    const DEFAULT_OUTPUT: &str = "OUT_A";
+    const REPORT_US: u64 = 1_000_000;
+
+    let input = args.get_str_param("input").unwrap_or(DEFAULT_INPUT);
+    let output = args.get_str_param("output").unwrap_or(DEFAULT_OUTPUT);
+
+    let gpio_input = CONFIG.get_gpio(input)?;
+    let gpio_output = CONFIG.get_gpio(output)?;
+    let channel = adc_channel(gpio_input)?;
+    device.outputs.get(gpio_output)?;
+
+    let high: f32 = args.get_ranged_param_or("high", 0.0..=adcs::vref(), 1.65)?;
+    let hyst: f32 = args.get_ranged_param_or("hyst", 0.0..=high, 0.1)?;
+
+    let setup = comparator::Setup::new(
+        channel,
+        gpio_output,
+        comparator::volts_to_raw(high),
+        comparator::volts_to_raw(high - hyst),
+    )
+    .invert(args.contains_param("invert"));
+
+    println!("---- Comparator ----");
+    println!("Input: GPIO {gpio_input} - {input} >> Output: GPIO {gpio_output} {output}");
+    println!("Trip: > {high:.3}V | Release: < {:.3}V", high - hyst);
+    println!("\nSend '~' to exit\n");
+
+    COMPARATOR.arm();
+    main_core1::send(EventCore1::Comparator(setup), &mut device.sio_fifo, device.timer)?;
+
+    // Reporting
+    SERIAL.clear_interrupt_cmd();
+    let mut next = device.timer.get_counter().ticks() + REPORT_US;
+    let mut previous_samples = 0;
+
+    while !SERIAL.interrupt_cmd_triggered() {
+        if device.timer.get_counter().ticks() < next {
+            continue;
+        }
+        next += REPORT_US;
+
+        let stats = COMPARATOR.stats();
+        println!(
+            "{} | trips: {} | input: {:.3}V | rate: {}/s",
+            if stats.tripped { "TRIPPED " } else { "released" },
+            stats.trips,
+            stats.last.to_voltage(),
+            stats.samples - previous_samples
+        );
+        previous_samples = stats.samples;
+    }
+
+    COMPARATOR.stop();
+    let stats = COMPARATOR.stats();
+    println!("Done! trips: {} | samples: {}", stats.trips, stats.samples);
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Snapshot
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Output levels and PWM slices kept in RAM, to return the rig to a known state after experiments
+
+pub fn build_snapshot_cmd() -> Command {
+    Command {
+        name:     "snapshot",
+        desc:     "Saves or restores the outputs and PWM slices state",
+        help:     "snapshot [save] [restore] [help]\n
+    save    : records the output levels and PWM slices, replacing the previous snapshot
+    restore : applies the saved snapshot, it is kept for later restores
+    The test commands restore their own snapshot when they end, errors and interrupts included",
+        category: Category::Io,
+        requires: &[],
+        func:     snapshot_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn snapshot_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("save") {
+        snapshot::save(device);
+        println!("Snapshot saved");
+    }
+    else if args.contains_param("restore") {
+        if !snapshot::restore(device) {
+            return Err(Error::Missing("saved snapshot (snapshot save)".into_truncate()));
+        }
+        println!("Snapshot restored");
+    }
+
+    let Some(saved) = snapshot::saved()
+    else {
+        println!("No snapshot saved");
+        return Ok(());
+    };
+
+    print!("Outputs:");
+    for gpio in (0..gpios::NUM_MCU_PINS as u8).filter(|gpio| saved.outputs & (1 << gpio) != 0) {
+        print!(" {gpio}:{}", if saved.levels & (1 << gpio) != 0 { "H" } else { "L" });
+    }
+    println!();
+
+    for (slice_id, state) in saved.slices.iter().enumerate() {
+        if state.status.enabled {
+            println!(
+                "PWM {slice_id}: {}hz | top: {} | cc: {}/{}",
+                state.freq, state.status.top, state.status.cc_a, state.status.cc_b
+            );
+        }
+    }
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Frequency Sweep
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Steps the PWM frequency and samples an ADC channel at the end of each step, for quick
+// Bode-style plots of filters and actuators. The slice is restored when the sweep ends.
+//   #SWEEP gpio=6 from=100 to=10000 steps=100 mode=log adc=0
+//   freq_hz,adc_raw
+//   100,2043
+//   #END steps=100
+
+pub fn build_freq_sweep_cmd() -> Command {
+    Command {
+        name:     "freq_sweep",
+        desc:     "Sweeps a PWM frequency, optionally sampling an ADC channel",
+        help:     "freq_sweep [alias=PWM2_B(str)] / [gpio=..(u8)] [from=100(hz)] [to=10000(hz)] \
+                   [time=10s] [steps=100(2..=1000)]\n        [log=false(bool)] \
+                   [duty=50(0..=100%)] [adc=..(str)] [help]\n
+    log   : logarithmic steps, linear otherwise. from can be above to for a down sweep
+    adc   : ADC alias sampled at the end of each step, printed as freq_hz,adc_raw lines
+    Each step lasts time / steps, at least 1ms. Interrupt with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     freq_sweep_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn freq_sweep_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "PWM2_B";
+    const MIN_STEP_US: u64 = 1000;
+
+    // Getting Alias or GPIO input ---------
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+
+    let (gpio, _) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    // -------------------------------------
+
+    let max_freq = SYS_CLK_HZ.load(Ordering::Relaxed) / 2;
+
+    let from: u32 = args.get_ranged_param_or("from", 1..=max_freq, 100)?;
+    let to: u32 = args.get_ranged_param_or("to", 1..=max_freq, 10_000)?;
+    let steps: u32 = args.get_ranged_param_or("steps", 2..=1000, 100)?;
+    let duty: u8 = args.get_ranged_param_or("duty", 0..=100, 50)?;
+    let log: bool = args.get_parsed_param("log").unwrap_or(false);
+    let time_ms = match args.get_str_param("time") {
+        Some(value) => parse_duration_ms(value).ok_or(Error::Parse("time".into_truncate()))?,
+        None => 10_000,
+    };
+    let adc = match args.get_str_param("adc") {
+        Some(adc) => Some(adc_channel(CONFIG.get_gpio(adc)?)?),
+        None => None,
+    };
+
+    let step_us = time_ms as u64 * 1000 / steps as u64;
+    if step_us < MIN_STEP_US {
+        return Err("time too short, steps last at least 1ms".into());
+    }
+
+    let (slice_id, channel) = device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+
+    // Linear increment or log ratio between steps
+    let last = (steps - 1) as f64;
+    let increment = (to as f64 - from as f64) / last;
+    let ratio = nth_root(to as f64 / from as f64, steps - 1);
+
+    print!(
+        "#SWEEP gpio={gpio} from={from} to={to} steps={steps} mode={}",
+        if log { "log" } else { "linear" }
+    );
+    match adc {
+        Some(channel) => println!(" adc={channel}\nfreq_hz,adc_raw"),
+        None => println!("\nfreq_hz"),
+    }
+
+    let count = snapshot::restoring(device, |device| {
+        SERIAL.clear_interrupt_cmd();
+        let mut freq = from as f64;
+        let mut count = 0;
+
+        while count < steps && !SERIAL.interrupt_cmd_triggered() {
+            let hz = (freq + 0.5) as u32;
+            with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
+                pwm_slice.set_freq(hz);
+                let _ = pwm_slice.get_channel(channel).set_duty_cycle_percent(duty);
+                pwm_slice.enable();
+            });
+
+            // Letting the output settle, the sample is taken at the end of the step
+            let start = Instant::now();
+            while !start.has_elapsed(Duration::from_micros(step_us)) {}
+
+            match adc.and_then(|channel| device.adcs.read(channel)) {
+                Some(raw) => println!("{hz},{raw}"),
+                None => println!("{hz}"),
+            }
+
+            count += 1;
+            freq = match log {
+                true => freq * ratio,
+                false => from as f64 + increment * count as f64,
+            };
+        }
+        count
+    });
+
+    println!("#END steps={count}");
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Clock Out
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Routes an internal clock to a GPOUT pin (GPIO 21, 23, 24 or 25)
+// ex: clkout gpio=21 src=sys div=100
+// ex: clkout stop=21
+
+pub fn build_clkout_cmd() -> Command {
+    Command {
+        name:     "clkout",
+        desc:     "Clock output on GPIO 21/23/24/25",
+        help:     "clkout [gpio=21(u8)] [src=sys(str)] [div=1.0(f32)] / [stop=..(u8)] [help]\n
+    src : sys, usb, adc, rtc, ref, xosc, rosc, pll_sys, pll_usb (see fc to measure them)
+    div : 1.0 to 16777215, 8 fractional bits (fractional dividers add jitter)
+    The pin function is restored on stop, ex: PWM2_B on GPIO 21
+    Lists the running outputs when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     clkout_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn clkout_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("stop") {
+        let gpio = args.get_parsed_param("stop")?;
+        CLKOUT.stop(gpio)?;
+        println!("Clock output on GPIO {gpio} stopped");
+        return Ok(());
+    }
+
+    if args.contains_param("gpio") {
+        let gpio = args.get_parsed_param("gpio")?;
+        let source: Source = match args.get_str_param("src") {
+            Some(source) => source.parse()?,
+            None => Source::Sys,
+        };
+        let div = args.get_parsed_param("div").unwrap_or(1.0);
+        CLKOUT.start(gpio, source, div)?;
+    }
+
+    let mut count = 0;
+    for output in CLKOUT.outputs() {
+        let mut freq = String::<16>::new();
+        match output.freq_hz() {
+            Some(hz) => write!(freq, "{hz:.1}Hz").ok(),
+            None => write!(freq, "uncalibrated").ok(),
+        };
+        println!(
+            "  GPIO {:>2} | src: {:<7} | div: {:.3} | freq: {freq}",
+            output.gpio, output.source, output.div
+        );
+        count += 1;
+    }
+    if count == 0 {
+        println!("No running clock outputs");
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                        Frequency Counter
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Measures a clock with the internal frequency counter (fc0)
+// ex: fc measure src=gpin0
+// ex: fc
+
+pub fn build_fc_cmd() -> Command {
+    Command {
+        name:     "fc",
+        desc:     "Measures clocks with the frequency counter",
+        help:     "fc [measure] [src=..(str)] [help]\n
+    src : sys, peri, usb, adc, rtc, ref, xosc, rosc, pll_sys, pll_usb
+          gpin0 (GPIO 20) or gpin1 (GPIO 22) for external clocks
+    1kHz resolution, measures every internal clock when called without src",
+        category: Category::Dev,
+        requires: &[],
+        func:     fc_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn fc_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(source) = args.get_str_param("src") {
+        let source: Source = source.parse()?;
+        let hz = clocks::measure_hz(source)?;
+        println!("{source}: {:.3}kHz", hz as f32 / 1000.0);
+        return Ok(());
+    }
+
+    for source in Source::INTERNAL {
+        let hz = clocks::measure_hz(source)?;
+        match source.freq_hz() {
+            Some(nominal) => println!(
+                "  {source:<7} {:>10.3}kHz | nominal: {:.3}kHz",
+                hz as f32 / 1000.0,
+                nominal as f32 / 1000.0
+            ),
+            None => println!("  {source:<7} {:>10.3}kHz", hz as f32 / 1000.0),
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Clocks
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Oscillator, PLL and clock frequency report with the last reset cause
+
+pub fn build_clocks_cmd() -> Command {
+    Command {
+        name:     "clocks",
+        desc:     "Oscillators, PLLs, clocks and reset cause",
+        help:     "clocks [help]\n
+    Clock frequencies are measured with the frequency counter, see fc
+    Brown-out and power-on resets share the same flag",
+        category: Category::Dev,
+        requires: &[],
+        func:     clocks_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn clocks_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let mhz = |hz: u32| hz as f32 / 1_000_000.0;
+
+    println!("---- Clocks ----");
+    for (name, osc) in [
+        ("XOSC", clocks::xosc_status()),
+        ("ROSC", clocks::rosc_status()),
+    ] {
+        println!(
+            "{name:<8} | enabled: {} | stable: {} | bad write: {}",
+            osc.enabled, osc.stable, osc.badwrite
+        );
+    }
+
+    let plls = [
+        ("PLL_SYS", clocks::pll_sys_status()),
+        ("PLL_USB", clocks::pll_usb_status()),
+    ];
+    for (name, pll) in plls {
+        println!(
+            "{name:<8} | locked: {} | powered: {} | refdiv: {} fbdiv: {} postdiv: {}/{} | vco: \
+             {:.3}MHz out: {:.3}MHz",
+            pll.locked,
+            pll.powered,
+            pll.refdiv,
+            pll.fbdiv,
+            pll.postdiv1,
+            pll.postdiv2,
+            mhz(pll.vco_hz()),
+            mhz(pll.out_hz())
+        );
+    }
+
+    println!("\nMeasured:");
+    for source in Source::INTERNAL {
+        println!("  {source:<7} {:>10.3}MHz", mhz(clocks::measure_hz(source)?));
+    }
+
+    let (bod_enabled, bod_volts) = clocks::brown_out();
+    println!("\nReset cause: {}", clocks::reset_cause());
+    println!("Brown-out detector: {} | threshold: {}", bod_enabled, Volts(bod_volts));
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Tachometer
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Counts pulses over a gate period and reports frequency and RPM
+// Input pins are polled in software, PWM B channel pins are counted by the PWM slice hardware
+// ex: tacho alias=IN_A ppr=2 stream
+
+pub fn build_tacho_cmd() -> Command {
+    Command {
+        name:     "tacho",
+        desc:     "Pulse counter and RPM meter",
+        help:     "tacho [alias=IN_A(str)] / [gpio=..(u8)] [gate=1000(ms)] [ppr=1(u32)] [stream] \
+                   [help]\n
+    Input pins are polled, PWM B pins use hardware counting
+    Interrupt stream with char \"~\"",
+        category: Category::Io,
+        requires: &[],
+        func:     tacho_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn tacho_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    const DEFAULT_PIN: &str = "IN_A";
+
+    // Getting Alias or GPIO input ---------
+    let alias = args.get_str_param("alias").unwrap_or(DEFAULT_PIN);
+    let gpio = args.get_parsed_param::<u8>("gpio").ok();
+
+    let (gpio, alias) = CONFIG.get_gpio_alias_pair(gpio, Some(alias))?;
+    // -------------------------------------
+
+    let gate: u32 = args.get_parsed_param("gate").unwrap_or(1000).max(1); // 1s default
+    let ppr: u32 = args.get_parsed_param("ppr").unwrap_or(1).max(1); // pulses per revolution
+    let stream = args.contains_param("stream");
+
+    // Selecting the counting method
+    let hw_slice = match device.pwms.get_pwm_slice_id_by_gpio(gpio) {
+        Ok((slice_id, Channel::B)) => Some(slice_id),
+        Ok((_, Channel::A)) => return Err("PWM pin must be a B channel".into()),
+        Err(_) => {
+            device.inputs.get(gpio)?;
+            None
+        }
+    };
+
+    println!("---- Tachometer ----");
+    match hw_slice {
+        Some(slice_id) => println!("Input: GPIO {gpio} - {alias} | pwm {slice_id} B edge counter"),
+        None => println!("Input: GPIO {gpio} - {alias} | polled"),
+    }
+    println!("Gate: {gate}ms | Pulses per revolution: {ppr}");
+    if stream {
+        println!("\nSend '~' to exit\n");
+    }
+
+    let mut total: u64 = 0;
+    let mut report = |pulses: u32| {
+        total += pulses as u64;
+        let freq = pulses as f32 * 1000.0 / gate as f32;
+        let rpm = freq * 60.0 / ppr as f32;
+        println!("> pulses: {pulses} | freq: {freq:.1}hz | rpm: {rpm:.1} | total: {total}");
+    };
+
+    SERIAL.clear_interrupt_cmd();
+
+    if let Some(slice_id) = hw_slice {
+        with_pwm_slice!(&mut device.pwms, slice_id, |pwm_slice| {
+            let top = pwm_slice.slice.get_top();
+            pwm_slice.start_edge_counter();
+
+            loop {
+                report(count_edges_pwm(pwm_slice, gate));
+                if !stream || SERIAL.interrupt_cmd_triggered() {
+                    break;
+                }
+            }
+
+            pwm_slice.stop_edge_counter(top);
+        });
+    }
+    else {
+        loop {
+            report(count_edges_polled(device, gpio, gate)?);
+            if !stream || SERIAL.interrupt_cmd_triggered() {
+                break;
+            }
+        }
+    }
+
+    println!("Done!");
+    Ok(())
+}
+
+/// Counts rising edges with the PWM slice in edge counting mode
+fn count_edges_pwm<I>(pwm: &mut crate::system::pwms::PwmSlice<I>, gate_ms: u32) -> u32
+where
+    I: pwm::SliceId,
+    <I as pwm::SliceId>::Reset: pwm::ValidSliceMode<I>,
+{
+    let start_time = Instant::now();
+    let gate = Duration::from_millis(gate_ms as u64);
+    let mut last = pwm.read_edge_counter();
+    let mut count: u32 = 0;
+
+    // Polling faster than the 16-bit counter can wrap
+    while !start_time.has_elapsed(gate) {
+        let counter = pwm.read_edge_counter();
+        count += counter.wrapping_sub(last) as u32;
+        last = counter;
+    }
+
+    count
+}
+
+/// Counts rising edges by polling an input pin
+fn count_edges_polled(device: &mut Device, gpio: u8, gate_ms: u32) -> Result<u32> {
+    let start_time = Instant::now();
+    let gate = Duration::from_millis(gate_ms as u64);
+    let pin = device.inputs.get(gpio)?;
+    let mut last = pin.is_high().unwrap();
+    let mut count: u32 = 0;
+
+    while !start_time.has_elapsed(gate) {
+        let level = pin.is_high().unwrap();
+        if level && !last {
+            count += 1;
+        }
+        last = level;
+    }
+
+    Ok(count)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Trace Markers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Prints the markers dropped with marker!(id), with the time since the previous one
+// ex: trace dump
+// ex: trace mark=1
+
+pub fn build_trace_cmd() -> Command {
+    Command {
+        name:     "trace",
+        desc:     "Timestamped trace markers",
+        help:     "trace [dump] [clear] [mark=..(u16)] [help]\n
+    mark : records a marker, ex: around other commands of a script
+    Markers are dropped in code with marker!(id), 1us resolution
+    Prints the marker count when called without arguments",
+        category: Category::Dev,
+        requires: &[],
+        func:     trace_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn trace_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("mark") {
+        MARKERS.mark(args.get_parsed_param("mark")?);
+    }
+
+    if args.contains_param("dump") {
+        if MARKERS.dropped() > 0 {
+            println!("({} older markers dropped)", MARKERS.dropped());
+        }
+
+        let mut previous = None;
+        MARKERS.for_each(|marker| {
+            let delta = previous.map_or(0, |time_us| marker.time_us - time_us);
+            previous = Some(marker.time_us);
+            println!(
+                "[{:>5}.{:06}] core{} | id: {:>5} | +{delta}us",
+                marker.time_us / 1_000_000,
+                marker.time_us % 1_000_000,
+                marker.core,
+                marker.id
+            );
+        });
+        println!();
+    }
+
+    if args.contains_param("clear") {
+        MARKERS.clear();
+        println!("Markers cleared");
+    }
+
+    println!("Trace markers: {}", MARKERS.len());
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Memory
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// RAM usage: static data, core0 stack and the heap statistics (heap feature)
+
+pub fn build_mem_cmd() -> Command {
+    Command {
+        name:     "mem",
+        desc:     "RAM, heap usage and stack guard",
+        help:     "mem [help]\n
+    static : data and bss, the heap arena included
+    stack  : core0 stack, from the RAM origin up to the static data, used and free
+             around the stack pointer
+    guard  : stack canaries and core1 heartbeat, checked every 500ms
+    limits : buffer sizes of the limits preset, see the limits-tiny/limits-large features",
+        category: Category::Dev,
+        requires: &[],
+        func:     mem_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn mem_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    // flip-link layout: the stack grows down from the statics to the RAM origin
+    let layout = RamLayout::current();
+    let sp = cortex_m::register::msp::read() as usize;
+    let kib = |bytes: usize| bytes as f32 / 1024.0;
+
+    println!("---- Memory ----");
+    println!(
+        "RAM: {:.1}KiB | static: {:.1}KiB | stack: {:.1}KiB, used: {:.1}KiB, free: {:.1}KiB",
+        kib(layout.statics_end - layout.stack_bottom),
+        kib(layout.statics_size()),
+        kib(layout.stack_size()),
+        kib(layout.stack_top.saturating_sub(sp)),
+        kib(sp.saturating_sub(layout.stack_bottom))
+    );
+
+    #[cfg(feature = "heap")]
+    {
+        let stats = crate::system::heap::stats();
+        println!(
+            "Heap: {}/{} bytes | peak: {} | free: {} | allocs: {} frees: {} failed: {}",
+            stats.used,
+            stats.size,
+            stats.peak,
+            crate::system::heap::free(),
+            stats.allocs,
+            stats.frees,
+            stats.failed
+        );
+    }
+    #[cfg(not(feature = "heap"))]
+    println!("Heap: disabled, build with the heap feature");
+
+    println!(
+        "Limits: {} | line: {} | capture: {} | logic: {} samples | eventlog: {} events",
+        limits::PRESET.name(),
+        limits::LINE_BUFFER_LENGTH,
+        limits::CAPTURE_SIZE,
+        limits::MAX_LOGIC_SAMPLES,
+        limits::MAX_LOG_EVENTS
+    );
+
+    println!(
+        "Core1: {} | mode: {} | stack: {}KiB",
+        main_core1::state(),
+        main_core1::mode(),
+        main_core1::CORE1_STACK_KB
+    );
+
+    let guard = stack_guard::status();
+    let ok = |ok: bool| if ok { "ok" } else { "OVERFLOW" };
+    print!("Stack guard: main {}", ok(guard.main_ok));
+    match guard.core1_ok {
+        Some(core1_ok) => print!(" | core1 {} | heartbeat: {}", ok(core1_ok), guard.heartbeat),
+        None => print!(" | core1 not started"),
+    }
+    println!(" | reset on fault: {}", if guard.reset_on_err { "on" } else { "off" });
+    if let Some(fault) = guard.fault {
+        println!("Fault: {fault}");
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               CPU
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Approximate utilization of both cores and the USB interrupt share
+// ex: cpu watch
+
+pub fn build_cpu_cmd() -> Command {
+    Command {
+        name:     "cpu",
+        desc:     "Core utilization and USB interrupt time",
+        help:     "cpu [watch] [help]\n
+    busy  : time outside of the idle waits (line input, host connection, core1 loop pause),
+            interrupts included, over the last 1s window
+    usb   : time spent in the USB interrupt
+    watch : prints every window until '~' is sent",
+        category: Category::Dev,
+        requires: &[],
+        func:     cpu_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn cpu_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let print_load = |load: cpu_load::Load| {
+        print!("core0: {:5.1}% busy", load.core0);
+        match load.core1 {
+            Some(core1) => print!(" | core1: {core1:5.1}% busy"),
+            None => print!(" | core1: {}", main_core1::state()),
+        }
+        println!(" | usb irq: {:4.1}% | window: {}ms", load.usb_irq, load.window_ms);
+    };
+
+    println!("---- CPU ----");
+    if !args.contains_param("watch") {
+        match cpu_load::load() {
+            Some(load) => print_load(load),
+            None => println!("First window not complete yet, try again"),
+        }
+        return Ok(());
+    }
+
+    println!("Send '~' to exit\n");
+    SERIAL.clear_interrupt_cmd();
+    while !SERIAL.interrupt_cmd_triggered() {
+        // Waiting counts as idle, the figures leave out this command
+        cpu_load::idle(|| device.timer.delay_ms(1000));
+        if let Some(load) = cpu_load::load() {
+            print_load(load);
+        }
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Events
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Pushes hardware events to the host as "[EVT] .." lines, see system::events
+// ex: events edge=IN_A,IN_B alarm=on
+// ex: events log=on edge=off
+
+pub fn build_events_cmd() -> Command {
+    Command {
+        name:     "events",
+        desc:     "Unsolicited event lines for the host software",
+        help:     "events [edge=..(str)|off] [alarm=on|off] [log=on|off] [help]\n
+    edge  : comma separated input pins whose edges are reported, off stops watching
+    alarm : output guard and comparator trips
+    log   : log lines printed as events
+    Lines: [EVT] <fields> t=<µs since boot>, ex: [EVT] pin=IN_A edge=falling t=123456
+    Off by default, RAM only. Prints the sources when called without arguments",
+        category: Category::Base,
+        requires: &[],
+        func:     events_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn events_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(pins) = args.get_str_param("edge") {
+        let mut mask = 0u32;
+        if pins != "off" {
+            for pin in pins.split(',').map(str::trim) {
+                let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+                device.inputs.get(gpio)?; // Has to be a registered input
+                mask |= 1 << gpio;
+            }
+        }
+        events::watch(mask)?;
+        events::set_enabled(events::Source::Edge, mask != 0);
+    }
+
+    for source in [events::Source::Alarm, events::Source::Log] {
+        if let Some(state) = args.get_str_param(source.name()) {
+            let enabled = match state {
+                "on" | "true" | "1" => true,
+                "off" | "false" | "0" => false,
+                _ => return Err(Error::Parse(source.name().into_truncate())),
+            };
+            events::set_enabled(source, enabled);
+        }
+    }
+
+    for source in events::Source::ALL {
+        print!(
+            "{:<6}: {}",
+            source.name(),
+            if events::is_enabled(source) { "on" } else { "off" }
+        );
+        if source == events::Source::Edge && events::is_enabled(source) {
+            let watched = events::watched();
+            for gpio in (0..32u8).filter(|gpio| watched & (1 << gpio) != 0) {
+                print!(" {}", CONFIG.get_alias(gpio).unwrap_or("?"));
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Event Log
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Records the pin changes in RAM for hours, dumped later, see system::eventlog
+// ex: eventlog start pins=IN_A,IN_B limit=256 period=500
+// ex: eventlog clock=1792245600
+// ex: eventlog dump
+
+pub fn build_eventlog_cmd() -> Command {
+    Command {
+        name:     "eventlog",
+        desc:     "Long running log of the pin changes, kept through disconnects",
+        help:     "eventlog [start pins=..(str)] [limit=512(1-512)] [period=1000(us)] [stop] \
+                   [dump] [clear] [clock=..(unix s)] [help]\n
+    pins   : comma separated input pins, their edges are logged with the µs since boot
+    limit  : events kept, recording stops once reached and the later edges are counted as missed
+    period : poll period, pulses shorter than it can be missed (min 100us)
+    clock  : current unix time (ex: date +%s), the dump prints the date of the events
+    Prints the log state when called without arguments",
+        category: Category::Io,
+        requires: &[],
+        func:     eventlog_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn eventlog_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("clock") {
+        eventlog::set_clock(args.get_parsed_param("clock")?);
+    }
+
+    if args.contains_param("stop") {
+        EVENTLOG.stop();
+    }
+
+    if args.contains_param("start") {
+        let pins = args
+            .get_str_param("pins")
+            .ok_or_else(|| Error::MissingArg("pins".into_truncate()))?;
+        let mut mask = 0u32;
+        for pin in pins.split(',').map(str::trim) {
+            let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+            device.inputs.get(gpio)?; // Has to be a registered input
+            mask |= 1 << gpio;
+        }
+        let limit =
+            args.get_ranged_param_or("limit", 1..=eventlog::MAX_EVENTS, eventlog::MAX_EVENTS)?;
+        let period: u32 = args.get_parsed_param("period").unwrap_or(1_000);
+        EVENTLOG.start(mask, period, limit)?;
+    }
+
+    if args.contains_param("dump") {
+        let mut previous = None;
+        EVENTLOG.for_each(|event| {
+            let delta = previous.map_or(0, |time_us| event.time_us - time_us);
+            previous = Some(event.time_us);
+            print!(
+                "[{:>5}.{:06}] {:>8} {:<7} | +{delta}us",
+                event.time_us / 1_000_000,
+                event.time_us % 1_000_000,
+                CONFIG.get_alias(event.gpio).unwrap_or("?"),
+                if event.high { "rising" } else { "falling" },
+            );
+            match eventlog::DateTime::at(event.time_us) {
+                Some(date) => println!(" | {date}"),
+                None => println!(),
+            }
+        });
+        println!();
+    }
+
+    if args.contains_param("clear") {
+        EVENTLOG.clear();
+        println!("Event log cleared");
+    }
+
+    let status = EVENTLOG.status();
+    print!("Event log: {}", if status.running { "recording" } else { "stopped" });
+    for gpio in (0..32u8).filter(|gpio| status.pins & (1 << gpio) != 0) {
+        print!(" {}", CONFIG.get_alias(gpio).unwrap_or("?"));
+    }
+    println!(" | every {}us", status.period_us);
+    println!("Events: {}/{} | missed: {}", status.len, status.limit, status.missed);
+    match eventlog::DateTime::at(Instant::now().as_micros()) {
+        Some(now) => println!("Clock: {now} UTC"),
+        None => println!("Clock: not set, see clock="),
+    }
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Watch
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Re-runs a command and highlights the words that changed since the previous run
+// The output is captured first, commands streaming until '~' don't suit it
+// ex: watch cmd=read_adc interval=500ms
+// ex: watch cmd="i2c read addr=0x6a reg=0x28 len=6" clear
+
+pub fn build_watch_cmd() -> Command {
+    Command {
+        name:     "watch",
+        desc:     "Runs a command periodically and highlights the changes",
+        help:     "watch cmd=..(str) [interval=1s(ms|s|m)] [clear] [nodiff] [help]\n
+    cmd      : command line to run, quoted if it has arguments
+    interval : time between runs, ex: 500ms, 2s, 1m
+    clear    : clears the screen before each run
+    nodiff   : no highlighting of the changed values
+    Send '~' to exit",
+        category: Category::Base,
+        requires: &[],
+        func:     watch_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn watch_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let line = args
+        .get_str_param("cmd")
+        .filter(|line| !line.trim().is_empty())
+        .ok_or(Error::MissingArg("cmd".into_truncate()))?;
+
+    let interval_ms = match args.get_str_param("interval") {
+        Some(value) => parse_duration_ms(value).ok_or(Error::Parse("interval".into_truncate()))?,
+        None => 1000,
+    };
+    let clear = args.contains_param("clear");
+    let diff = !args.contains_param("nodiff");
+
+    let name = line.split_ascii_whitespace().next().unwrap_or("");
+    if name.eq_ignore_ascii_case(cmd.name) {
+        return Err("watch can't run itself".into());
+    }
+
+    let commands = build();
+    commands.get_command(name)?;
+
+    let mut previous = Capture::default();
+    let mut runs: u32 = 0;
+
+    SERIAL.clear_interrupt_cmd();
+    while !SERIAL.interrupt_cmd_triggered() {
+        SERIAL.start_capture();
+        let result = commands.execute(line, device);
+        let output = SERIAL.end_capture().unwrap_or_default();
+        runs += 1;
+
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        else {
+            println!();
+        }
+        println!(
+            "Every {interval_ms}ms: {line} | run {runs} | T: {}\n",
+            device.timer.print_time()
+        );
+
+        let previous_text = (diff && runs > 1).then(|| previous.as_str());
+        print_diff(output.as_str(), previous_text);
+
+        if output.truncated {
+            println!("\n[output truncated]");
+        }
+        if let Err(e) = result {
+            println!("Err: {e}");
+        }
+        previous = output;
+
+        // Waiting in small steps to stay responsive to the interrupt char
+        let start = Instant::now();
+        while !SERIAL.interrupt_cmd_triggered()
+            && !start.has_elapsed(Duration::from_millis(interval_ms as u64))
+        {
+            device.timer.delay_ms(10);
+        }
+    }
+
+    println!("\nWatch interrupted. Done!");
+    Ok(())
+}
+
+/// ADC channel of a GPIO, 255 is the internal temperature sensor
+fn adc_channel(gpio: u8) -> Result<u8> {
+    match gpio {
+        26 => Ok(0),
+        27 => Ok(1),
+        28 => Ok(2),
+        29 => Ok(3),
+        255 => Ok(4), // default TEMP_SENSE channel
+        _ => Err(Error::Configuration(ConfigError::OutOfBounds)),
+    }
+}
+
+/// Origin of the ADC reference in use
+fn vref_source() -> &'static str {
+    if adcs::is_vref_set() { "setting" } else { "board" }
+}
+
+/// Raw ADC level of a voltage, clamped to the ADC range
+fn volts_to_raw(volts: f32) -> u16 {
+    (volts / adcs::vref() * ADC_MAX + 0.5).clamp(0.0, ADC_MAX) as u16
+}
+
+/// Root by Newton iterations, no libm on this target
+fn nth_root(value: f64, n: u32) -> f64 {
+    let mut root = 1.0 + (value - 1.0) / n as f64;
+    for _ in 0..50 {
+        let power = pow(root, n - 1);
+        let next = root - (power * root - value) / (n as f64 * power);
+        if (next - root).abs() < 1e-12 {
+            break;
+        }
+        root = next;
+    }
+    root
+}
+
+fn pow(base: f64, exp: u32) -> f64 {
+    (0..exp).fold(1.0, |acc, _| acc * base)
+}
+
+/// Prints the output, words differing from the previous output are shown in reverse video
+fn print_diff(output: &str, previous: Option<&str>) {
+    let Some(previous) = previous
+    else {
+        print!("{output}");
+        return;
+    };
+
+    let mut previous_lines = previous.split('\n');
+    for (i, line) in output.split('\n').enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        let mut previous_words = previous_lines.next().unwrap_or("").split(' ');
+        for (j, word) in line.split(' ').enumerate() {
+            if j > 0 {
+                print!(" ");
+            }
+
+            if previous_words.next() == Some(word) {
+                print!("{word}");
+            }
+            else {
+                print!("\x1b[7m{word}\x1b[0m");
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Simulated device commands (feature "mock")
+// Register new commands in commands.rs > Command List Builder
+
+use super::*;
+use crate::prelude::*;
+
+use crate::system::adcs::TEMP_SENSE_CHN;
+use crate::system::mock::{MOCK, Target, Wave, Waveform};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Mock
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// ex: mock pin=9 level=low
+// ex: mock adc=0 wave=sine period=500
+// ex: mock i2c addr=0x6a reg=0x0f data=0x69
+// ex: mock script="i2c 0x6a 0x0f 0x69; adc 1 noise 0 200"
+
+pub fn build_mock_cmd() -> Command {
+    Command {
+        name:     "mock",
+        desc:     "Simulated ADC, GPIO and bus devices",
+        help:     "mock [list] / [clear] [help]
+    mock pin=..(u8) level=low|high|off
+    mock adc=..(u8) wave=const|sine|triangle|square|saw|noise|off [period=1000(ms)]
+         [amp=2047] [offset=2048]      const returns the offset
+    mock i2c addr=..(u8) reg=..(u8) data=..(u8,u8,..)   : sets emulated registers
+    mock spi [bus=spi0(str)] reg=..(u8) data=..(u8,u8,..)
+    mock script=\"pin 9 low; adc 0 sine 500\"         : applies ';' separated lines
+    mock dump i2c addr=..(u8) / mock dump spi [bus=spi0(str)]",
+        category: Category::Dev,
+        requires: &[],
+        func:     mock_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn mock_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    // Script lines
+    if let Some(script) = args.get_str_param("script") {
+        let count = MOCK.apply_script(script)?;
+        println!("Applied {count} line(s)");
+        return Ok(());
+    }
+
+    if args.contains_param("clear") {
+        MOCK.clear();
+        println!("Mock cleared");
+        return Ok(());
+    }
+
+    // Forced GPIO input
+    if let Ok(gpio) = args.get_parsed_param::<u8>("pin") {
+        let level = match args.get_str_param("level") {
+            Some("high") | Some("1") => Some(true),
+            Some("low") | Some("0") => Some(false),
+            Some("off") => None,
+            _ => return Err(Error::MissingArg("level".into_truncate())),
+        };
+
+        MOCK.force_pin(gpio, level)?;
+        match level {
+            Some(true) => println!("GPIO {gpio} forced HIGH"),
+            Some(false) => println!("GPIO {gpio} forced LOW"),
+            None => println!("GPIO {gpio} released"),
+        }
+        return Ok(());
+    }
+
+    // ADC waveform
+    if let Ok(channel) = args.get_parsed_param::<u8>("adc") {
+        let wave = args.get_str_param("wave").unwrap_or("sine");
+        if wave == "off" {
+            MOCK.set_adc(channel, None)?;
+            println!("ADC {channel} released");
+            return Ok(());
+        }
+
+        let mut waveform = Waveform::new(wave.parse::<Wave>()?);
+        waveform.period_ms = args
+            .get_parsed_param("period")
+            .unwrap_or(waveform.period_ms);
+        waveform.amplitude = args.get_parsed_param("amp").unwrap_or(waveform.amplitude);
+        waveform.offset = args.get_parsed_param("offset").unwrap_or(waveform.offset);
+
+        MOCK.set_adc(channel, Some(waveform))?;
+        println!("ADC {channel}: {waveform}");
+        return Ok(());
+    }
+
+    // Emulated devices
+    let i2c = args.contains_param("i2c");
+    if i2c || args.contains_param("spi") {
+        let target = if i2c {
+            Target::I2c(args.get_int_param("addr")? as u8)
+        }
+        else {
+            Target::Spi(args.get_str_param("bus").unwrap_or("spi0").parse()?)
+        };
+
+        if args.contains_param("dump") {
+            let regs = MOCK.registers(target).ok_or("not emulated")?;
+            println!("{target}\n");
+            let columns = TERM.hex_columns();
+            print_hex_header(columns);
+            for (row, chunk) in regs.chunks(columns).enumerate() {
+                print!("{:02x}: ", row * columns);
+                for byte in chunk {
+                    print!("{byte:02x} ");
+                }
+                println!();
+            }
+            return Ok(());
+        }
+
+        let reg = args.get_int_param("reg")? as u8;
+        let mut data: Vec<u8, 32> = Vec::new();
+        for value in args.get_str_param("data").unwrap_or("").split(',') {
+            let byte = parse_int(value.trim())
+                .filter(|b| *b <= u8::MAX as u32)
+                .ok_or(Error::Parse("data".into_truncate()))?;
+            data.push(byte as u8)
+                .map_err(|_| Error::ArgTooLong("data".into_truncate(), data.capacity()))?;
+        }
+
+        MOCK.set_registers(target, reg, &data)?;
+        println!("{target}: {} register(s) set from 0x{reg:02x}", data.len());
+        return Ok(());
+    }
+
+    // List
+    println!("ADC:");
+    for channel in 0..=TEMP_SENSE_CHN {
+        if let Some(waveform) = MOCK.adc_waveform(channel) {
+            println!("  {channel}: {waveform}");
+        }
+    }
+
+    println!("Forced pins:");
+    for gpio in 0..30 {
+        match MOCK.forced_pin(gpio) {
+            Some(true) => println!("  GPIO {gpio}: HIGH"),
+            Some(false) => println!("  GPIO {gpio}: LOW"),
+            None => {}
+        }
+    }
+
+    println!("Emulated devices:");
+    for target in MOCK.emulated().iter() {
+        println!("  {target}");
+    }
+
+    Ok(())
+}
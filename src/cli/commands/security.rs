@@ -0,0 +1,137 @@
+//! Security commands: the encrypted channel and the login
+// Register new commands in commands.rs > Command List Builder
+
+use super::*;
+use crate::prelude::*;
+
+use crate::system::auth;
+use crate::system::secure::{self, SECURE};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Secure Channel
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Encrypted command lines with a pre-shared key, see system/secure.rs
+// ex: secure key=000102..1f
+// ex: secure on
+
+pub fn build_secure_cmd() -> Command {
+    Command {
+        name:     "secure",
+        desc:     "Encrypted command channel with a pre-shared key",
+        help:     "secure [on|off] [key=..(64 hex)] [help]\n
+    on  : only encrypted frames are accepted from now on, kept across resets
+    off : plain lines again, has to be sent encrypted
+    key : stores the pre-shared key, the host counter starts from 1 again
+    Without arguments: mode, key and frame counters",
+        category: Category::Dev,
+        requires: &[],
+        func:     secure_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn secure_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(key) = args.get_str_param("key") {
+        SECURE.set_key(secure::parse_key(key)?)?;
+        println!("Key stored");
+    }
+    if args.contains_param("on") {
+        SECURE.set_enabled(true)?;
+        println!("Secure mode on, send encrypted frames only");
+    }
+    else if args.contains_param("off") {
+        SECURE.set_enabled(false)?;
+        println!("Secure mode off");
+    }
+
+    let status = SECURE.status();
+    println!(
+        "---- Secure Channel ----\nMode: {} | key: {} | frames sent: {} | last host counter: {} \
+         (reserved up to {})",
+        if status.enabled { "on" } else { "off" },
+        if status.has_key { "set" } else { "none" },
+        status.tx_counter,
+        status.rx_counter,
+        status.rx_mark
+    );
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Login
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Login challenge on connect with the secure channel key, see system/auth.rs
+// ex: auth response=5f1c..(64 hex)
+// ex: auth on
+
+pub fn build_auth_cmd() -> Command {
+    Command {
+        name:     "auth",
+        desc:     "Login challenge required from a host on connect",
+        help:     "auth [response=..(64 hex)] / [on|off] / [lock] [help]\n
+    response : HMAC-SHA256 of the #AUTH challenge bytes with the secure key, unlocks the session
+    on       : every connection starts locked, needs a key (secure key=..), kept across resets
+    off      : no login
+    lock     : ends the login of this session, a new challenge is sent
+    Only the response is accepted while locked, a wrong one draws a new challenge.
+    Not used in secure mode, the frames are authenticated already",
+        category: Category::Dev,
+        requires: &[],
+        func:     auth_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn auth_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(response) = args.get_str_param("response") {
+        if let Err(e) = auth::login(response) {
+            auth::print_challenge();
+            return Err(e.into());
+        }
+        println!("Logged in");
+        return Ok(());
+    }
+    if auth::is_locked() {
+        auth::print_challenge();
+        return Err(secure::Error::Locked.into());
+    }
+
+    if args.contains_param("on") {
+        SECURE.set_auth(true)?;
+        println!("Login required from the next connection");
+    }
+    else if args.contains_param("off") {
+        SECURE.set_auth(false)?;
+        println!("Login off");
+    }
+    if args.contains_param("lock") {
+        auth::new_session();
+        auth::print_challenge();
+        return Ok(());
+    }
+
+    let status = SECURE.status();
+    let session = match (SECURE.is_auth_required(), auth::is_locked()) {
+        (false, _) => "open",
+        (true, true) => "locked",
+        (true, false) => "logged in",
+    };
+    println!(
+        "---- Login ----\nMode: {} | key: {} | session: {session}",
+        if status.auth { "on" } else { "off" },
+        if status.has_key { "set" } else { "none" },
+    );
+    Ok(())
+}
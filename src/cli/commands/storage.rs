@@ -0,0 +1,345 @@
+//! Storage commands: settings export and import, profiles and the key-value store
+// Register new commands in commands.rs > Command List Builder
+
+use super::*;
+use crate::prelude::*;
+
+use crate::system::kv::{self, KV};
+use crate::system::profile::{self, Profile};
+use crate::system::settings::{self, MAX_SETTINGS};
+use crate::system::{address, flash, status};
+use crate::utils::crc::{CRC32, Crc};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Profile
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Named fixture profiles: pin setup, PWM frequencies and autorun lines, saved to flash
+// ex: profile save=fixturea pins="0:out:low,9:in:pullup" pwm="PWM2_B:50"
+// ex: profile save=fixturea autorun="pin alias=OUT_A high; led mode=idle"
+// ex: profile load=fixturea
+// ex: profile boot=fixturea
+
+pub fn build_profile_cmd() -> Command {
+    Command {
+        name:     "profile",
+        desc:     "Saves and applies named fixture profiles",
+        help:     "profile [load=..(str)] / [save=..(str) [pins=..(str)] [pwm=..(str)] \
+                   [autorun=..(str)]]\n        / [show=..(str)] / [rm=..(str)] / \
+                   [boot=..(str)|off] [help]\n
+    pins    : pin setup, see the setup command
+    pwm     : default frequencies, comma separated <gpio or alias>:<hz>
+    autorun : ';' separated command lines run after the profile is applied
+    save    : creates or updates the given lines of a profile
+    boot    : profile applied at boot, skipped in safe mode
+    Lists the profiles when called without arguments",
+        category: Category::Base,
+        requires: &[],
+        func:     profile_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn profile_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("load") {
+        profile::load(name)?.apply(device)?;
+        println!("Profile {name} applied");
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("save") {
+        let mut profile = profile::load(name).unwrap_or_default();
+        for key in ["pins", "pwm", "autorun"] {
+            if let Some(value) = args.get_str_param(key) {
+                profile.set(key, value)?;
+            }
+        }
+        profile::save(name, &profile)?;
+        println!("Profile {name} saved\n{profile}");
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("show") {
+        let profile: Profile = profile::load(name)?;
+        print!("{profile}");
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("rm") {
+        profile::remove(name)?;
+        println!("Profile {name} removed");
+        return Ok(());
+    }
+
+    if let Some(name) = args.get_str_param("boot") {
+        match name {
+            "off" => {
+                SETTINGS.remove(profile::BOOT_KEY);
+            }
+            name => {
+                profile::load(name)?;
+                SETTINGS.set(profile::BOOT_KEY, name)?;
+            }
+        }
+        SETTINGS.save()?;
+        println!("Boot profile: {name}");
+        return Ok(());
+    }
+
+    // List
+    let mut count = 0;
+    profile::for_each(|name| {
+        println!("  {name}");
+        count += 1;
+    });
+    let boot = profile::boot_profile();
+    println!("\n{count} profile(s) | boot: {}", boot.as_deref().unwrap_or("off"));
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Config
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Settings copied between boards through the terminal:
+//   #CONFIG entries=12 crc32=0x1c291ca3
+//   <key=value lines>
+//   #END
+// ex: config export
+// ex: config import replace
+
+/// Settings identifying a board, not copied to another one by default
+const LOCAL_SETTINGS: [&str; 2] = [status::HOSTNAME_KEY, address::ADDRESS_KEY];
+
+pub fn build_config_cmd() -> Command {
+    Command {
+        name:     "config",
+        desc:     "Lists, exports and imports the persisted settings",
+        help:     "config [export [all]] / [import [replace] [all]] [help]\n
+    export  : prints the settings between #CONFIG and #END lines, with their CRC-32
+    import  : reads an export pasted in the terminal, up to #END or an empty line,
+              nothing is applied if a line, the entry count or the CRC-32 is wrong
+    replace : clears the other settings first instead of merging
+    all     : includes the board identity (hostname, address), skipped by default
+    Lists the settings when called without arguments, most apply at the next boot",
+        category: Category::Base,
+        requires: &[],
+        func:     config_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn config_cmd(cmd: &Command, args: &[Argument], _device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    let all = args.contains_param("all");
+    let copied = |key: &str| all || !LOCAL_SETTINGS.contains(&key);
+
+    if args.contains_param("export") {
+        let mut crc = Crc::new(&CRC32);
+        let mut count = 0;
+        SETTINGS.for_each(|key, value| {
+            if copied(key) {
+                update_entry_crc(&mut crc, key, value);
+                count += 1;
+            }
+        });
+
+        println!("#CONFIG entries={count} crc32=0x{:08x}", crc.value());
+        SETTINGS.for_each(|key, value| {
+            if copied(key) {
+                println!("{key}={value}");
+            }
+        });
+        println!("#END");
+        return Ok(());
+    }
+
+    if args.contains_param("import") {
+        println!("Paste the export, end with #END or an empty line\n");
+
+        let mut entries = Vec::<(settings::Key, settings::Value), MAX_SETTINGS>::new();
+        let mut header: Option<(usize, u32)> = None;
+        let mut crc = Crc::new(&CRC32);
+        let mut lines = 0;
+        let mut skipped = 0;
+        // The first error is kept while the rest of the paste is read, its lines would run as
+        // commands otherwise
+        let mut error: Option<Error> = None;
+        let mut buffer = [0u8; 96];
+        loop {
+            let len = SERIAL
+                .read_line_blocking(&mut buffer)
+                .map_err(|_| Error::IoInput)?;
+            let line = core::str::from_utf8(&buffer[..len]).map_err(|_| Error::ParseBuffer)?;
+            let line = line.trim();
+            if line.is_empty() || line == "#END" {
+                break;
+            }
+            if error.is_some() {
+                continue;
+            }
+
+            if let Some(fields) = line.strip_prefix("#CONFIG") {
+                match parse_config_header(fields) {
+                    Some(fields) => header = Some(fields),
+                    None => error = Some(Error::Parse("#CONFIG".into_truncate())),
+                }
+                continue;
+            }
+            // Comment lines
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match settings::parse_entry(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    error = Some(e.into());
+                    continue;
+                }
+            };
+            update_entry_crc(&mut crc, &key, &value);
+            lines += 1;
+
+            if !copied(&key) {
+                skipped += 1;
+            }
+            else if entries.push((key, value)).is_err() {
+                error = Some(settings::Error::Full.into());
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if let Some((count, expected)) = header
+            && (count != lines || crc.value() != expected)
+        {
+            return Err("entries or crc32 mismatch, nothing imported".into());
+        }
+
+        if args.contains_param("replace") {
+            let mut kept = Vec::<(&str, settings::Value), { LOCAL_SETTINGS.len() }>::new();
+            for key in LOCAL_SETTINGS.into_iter().filter(|key| !copied(key)) {
+                if let Some(value) = SETTINGS.get(key) {
+                    let _ = kept.push((key, value));
+                }
+            }
+            SETTINGS.clear();
+            for (key, value) in kept {
+                SETTINGS.set(key, value)?;
+            }
+        }
+
+        // All or nothing, the saved settings are reloaded if the entries do not fit
+        let applied = entries
+            .iter()
+            .try_for_each(|(key, value)| SETTINGS.set(key, value));
+        if let Err(e) = applied {
+            SETTINGS.load();
+            return Err(e.into());
+        }
+        SETTINGS.save()?;
+
+        print!("{} setting(s) imported", entries.len());
+        if skipped > 0 {
+            print!(", {skipped} board setting(s) skipped (see all)");
+        }
+        println!("\nReset to apply them");
+        return Ok(());
+    }
+
+    // List
+    SETTINGS.for_each(|key, value| println!("  {key}={value}"));
+    println!("\n{}/{MAX_SETTINGS} setting(s)", SETTINGS.len());
+
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Key-Value Store
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Wear-leveled counters in flash, see system/kv.rs
+// ex: kv key=cycles value=10
+
+pub fn build_kv_cmd() -> Command {
+    Command {
+        name:     "kv",
+        desc:     "Shows the wear-leveled key-value store",
+        help:     "kv [key=..(str) value=..(u32)] [flush] [clear] [help]\n
+    key, value : writes a value, keys of 1 to 8 chars
+    flush      : writes the lifetime counters pending since the last write
+    clear      : drops every key, lifetime counters included
+    Keys starting with \"sec.\" (secure channel) are read-only and kept by clear",
+        category: Category::Dev,
+        requires: &[],
+        func:     kv_cmd,
+        timeout:  None,
+    }
+}
+
+pub fn kv_cmd(cmd: &Command, args: &[Argument], device: &mut Device) -> Result<()> {
+    // Print Help
+    if args.contains_param("help") {
+        cmd.print_help();
+        return Ok(());
+    }
+
+    if args.contains_param("clear") {
+        KV.clear()?;
+        println!("Store cleared");
+    }
+    if args.contains_param("flush") {
+        device.state.persist()?;
+    }
+    if let Some(key) = args.get_str_param("key") {
+        if kv::is_protected(key) {
+            return Err(kv::Error::Protected.into());
+        }
+        let value = args.get_parsed_param::<u32>("value")?;
+        KV.set(key, value)?;
+    }
+
+    let usage = KV.usage();
+    println!(
+        "---- KV Store ----\nSector: {}/{} | generation: {} | slots used: {} free: {} | keys: {}",
+        usage.sector + 1,
+        flash::KV_SECTORS,
+        usage.generation,
+        usage.used,
+        usage.free,
+        usage.keys
+    );
+    KV.for_each(|key, value| println!("> {key:<8}: {value}"));
+    Ok(())
+}
+
+/// Adds a "key=value\n" line to a config export CRC
+fn update_entry_crc(crc: &mut Crc, key: &str, value: &str) {
+    for part in [key, "=", value, "\n"] {
+        crc.update(part.as_bytes());
+    }
+}
+
+/// Entry count and CRC-32 of a "#CONFIG entries=.. crc32=0x.." line
+fn parse_config_header(fields: &str) -> Option<(usize, u32)> {
+    let (mut count, mut crc) = (None, None);
+    for field in fields.split_whitespace() {
+        match field.split_once('=')? {
+            ("entries", value) => count = value.parse().ok(),
+            ("crc32", value) => crc = u32::from_str_radix(value.trim_start_matches("0x"), 16).ok(),
+            _ => {}
+        }
+    }
+    Some((count?, crc?))
+}
@@ -0,0 +1,66 @@
+//! Confirmation of Destructive Commands
+//!
+//! With `set confirm=on`, the commands declaring `Requirement::Confirmed` (reset, flash) only run
+//! with `confirm=yes` on their line, or within `ARM_WINDOW` of an `arm` command. A misfiring
+//! script then gets an error instead of rebooting the board. Off by default for interactive use.
+//!
+//! An arm is used up by the first confirmed command, or expires.
+//!
+//! Example:
+//! ```rust
+//! confirm::arm();
+//! // reset -> runs, the next one needs confirm=yes or a new arm
+//! ```
+
+use portable_atomic::{AtomicU64, Ordering};
+
+use super::parser::{ArgList, Argument};
+use crate::system::settings::SETTINGS;
+use crate::utils::time::{Duration, Instant};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const KEY: &str = "confirm";
+pub const ARM_WINDOW: Duration = Duration::from_secs(10);
+
+/// End of the arm window in µs since boot, 0 if not armed
+static ARMED_UNTIL: AtomicU64 = AtomicU64::new(0);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn is_enabled() -> bool {
+    SETTINGS.get_parsed(KEY) == Some(true)
+}
+
+/// Allows the next confirmed command within `ARM_WINDOW`
+pub fn arm() {
+    let until = Instant::now() + ARM_WINDOW;
+    ARMED_UNTIL.store(until.as_micros(), Ordering::Relaxed);
+}
+
+pub fn disarm() {
+    ARMED_UNTIL.store(0, Ordering::Relaxed);
+}
+
+/// Time left to run a confirmed command, None if not armed
+pub fn armed_remaining() -> Option<Duration> {
+    let until = Instant::from_micros(ARMED_UNTIL.load(Ordering::Relaxed));
+    let remaining = until.saturating_duration_since(Instant::now());
+    (remaining > Duration::ZERO).then_some(remaining)
+}
+
+/// True if a confirmed command may run: the mode is off, the line has confirm=yes, or a pending
+/// arm, which is used up
+pub fn take(args: &[Argument]) -> bool {
+    if !is_enabled() || args.get_str_param("confirm") == Some("yes") {
+        return true;
+    }
+
+    let armed = armed_remaining().is_some();
+    disarm();
+    armed
+}
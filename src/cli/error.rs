@@ -8,6 +8,7 @@ use thiserror::Error;
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
 pub const ERR_STR_LENGTH: usize = 48;
+pub const MISSING_STR_LENGTH: usize = 96;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -35,17 +36,21 @@ pub enum Error {
     #[error("command failed with: {0}")]
     CmdExec(String<ERR_STR_LENGTH>),
 
-    #[error("command not found: {0}")]
-    CmdNotFound(String<ERR_STR_LENGTH>),
+    #[error("command not found: {0}{1}")]
+    CmdNotFound(String<ERR_STR_LENGTH>, Suggestions),
 
-    #[error("command too long")]
-    CommandTooLong,
+    #[error("command too long (max {0} chars)")]
+    CommandTooLong(usize),
 
-    #[error("argument too long")]
-    ArgTooLong,
+    #[error("argument too long: {0} (max {1})")]
+    ArgTooLong(String<ERR_STR_LENGTH>, usize),
 
-    #[error("too many arguments")]
-    TooManyArgs,
+    #[error("too many arguments (max {0})")]
+    TooManyArgs(usize),
+
+    /// Param and "<range> (got <value>)"
+    #[error("{0} must be {1}")]
+    OutOfRange(String<ERR_STR_LENGTH>, String<ERR_STR_LENGTH>),
 
     #[error("critical failure")]
     CriticalFail,
@@ -53,6 +58,16 @@ pub enum Error {
     #[error("exited")]
     Exit,
 
+    #[error("interrupted")]
+    Interrupted,
+
+    #[error("timed out after {0}ms")]
+    TimedOut(u32),
+
+    /// Unmet command requirements, comma separated
+    #[error("missing: {0}")]
+    Missing(String<MISSING_STR_LENGTH>),
+
     // --- Custom
     #[error("{0}")]
     Custom(String<ERR_STR_LENGTH>),
@@ -60,6 +75,156 @@ pub enum Error {
     // --- From
     #[error(transparent)]
     Configuration(#[from] crate::system::config::Error),
+
+    #[error(transparent)]
+    Settings(#[from] crate::system::settings::Error),
+
+    #[error(transparent)]
+    Hx711(#[from] crate::drivers::hx711::Error),
+
+    #[error(transparent)]
+    Bus(#[from] crate::system::buses::Error),
+
+    #[error(transparent)]
+    RegMap(#[from] crate::system::regmap::Error),
+
+    #[error(transparent)]
+    Uart(#[from] crate::system::uart::Error),
+
+    #[error(transparent)]
+    Files(#[from] crate::system::files::Error),
+
+    #[error(transparent)]
+    Mirror(#[from] crate::system::mirror::Error),
+
+    #[error(transparent)]
+    Tpo(#[from] crate::system::tpo::Error),
+
+    #[error(transparent)]
+    EncoderSim(#[from] crate::system::encoder_sim::Error),
+
+    #[error(transparent)]
+    Scheduler(#[from] crate::system::scheduler::Error),
+
+    #[error(transparent)]
+    Profile(#[from] crate::system::profile::Error),
+
+    #[error(transparent)]
+    Playback(#[from] crate::system::playback::Error),
+
+    #[error(transparent)]
+    Choreo(#[from] crate::system::choreo::Error),
+
+    #[error(transparent)]
+    Dimmer(#[from] crate::system::dimmer::Error),
+
+    #[error(transparent)]
+    Logic(#[from] crate::system::logic::Error),
+
+    #[error(transparent)]
+    PowerOn(#[from] crate::system::power_on::Error),
+
+    #[error(transparent)]
+    Kv(#[from] crate::system::kv::Error),
+
+    #[error(transparent)]
+    Secure(#[from] crate::system::secure::Error),
+
+    #[error(transparent)]
+    Clocks(#[from] crate::system::clocks::Error),
+
+    #[error(transparent)]
+    Core1(#[from] crate::main_core1::Error),
+
+    #[error(transparent)]
+    Audio(#[from] crate::system::audio::Error),
+
+    #[error(transparent)]
+    Flash(#[from] crate::system::flash::Error),
+
+    #[error(transparent)]
+    Txn(#[from] crate::system::txn::Error),
+
+    #[error(transparent)]
+    Psu(#[from] crate::system::psu::Error),
+
+    #[error(transparent)]
+    Button(#[from] crate::system::button::Error),
+
+    #[cfg(feature = "mock")]
+    #[error(transparent)]
+    Mock(#[from] crate::system::mock::Error),
+}
+
+impl Error {
+    /// Status code of the DONE footer, 0 being success. Codes are stable, new variants get new
+    /// ones: 1-99 command line and execution errors, 100+ one per module.
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::BufferWrite => 1,
+            Error::ParseBuffer => 2,
+            Error::IoInput => 3,
+            Error::Parse(_) => 4,
+            Error::MissingArg(_) => 5,
+            Error::CmdExec(_) => 6,
+            Error::CmdNotFound(..) => 7,
+            Error::CommandTooLong(_) => 8,
+            Error::ArgTooLong(..) => 9,
+            Error::TooManyArgs(_) => 10,
+            Error::OutOfRange(..) => 11,
+            Error::CriticalFail => 12,
+            Error::Exit => 13,
+            Error::Interrupted => 14,
+            Error::TimedOut(_) => 15,
+            Error::Missing(_) => 16,
+            Error::Custom(_) => 99,
+            Error::Configuration(_) => 100,
+            Error::Settings(_) => 101,
+            Error::Hx711(_) => 102,
+            Error::Bus(_) => 103,
+            Error::RegMap(_) => 104,
+            Error::Uart(_) => 105,
+            Error::Files(_) => 106,
+            Error::Mirror(_) => 107,
+            Error::Tpo(_) => 108,
+            Error::EncoderSim(_) => 109,
+            Error::Scheduler(_) => 110,
+            Error::Profile(_) => 111,
+            Error::Playback(_) => 112,
+            Error::Choreo(_) => 113,
+            Error::Dimmer(_) => 114,
+            Error::Logic(_) => 115,
+            Error::PowerOn(_) => 116,
+            Error::Kv(_) => 117,
+            Error::Secure(_) => 118,
+            Error::Clocks(_) => 119,
+            #[cfg(feature = "mock")]
+            Error::Mock(_) => 120,
+            Error::Core1(_) => 121,
+            Error::Audio(_) => 122,
+            Error::Flash(_) => 123,
+            Error::Txn(_) => 124,
+            Error::Psu(_) => 125,
+            Error::Button(_) => 126,
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Suggestions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Comma separated command names close to a mistyped one, printed as ", did you mean: ..?"
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Suggestions(pub String<ERR_STR_LENGTH>);
+
+impl core::fmt::Display for Suggestions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        write!(f, ", did you mean: {}?", self.0)
+    }
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
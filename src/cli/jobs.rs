@@ -0,0 +1,216 @@
+//! Background Jobs
+//!
+//! A command line ending with `&` (ex: `sample_adc interval=500 &`) starts a job: the program
+//! loop runs one step of each due job while waiting for input, the prompt comes back right away.
+//! Only the commands with a job step can run in the background, see `STEPS`.
+//! `jobs` lists them, `fg` brings one back to the foreground and `kill` ends it.
+//!
+//! Job output is prefixed with the job id and shows up between the lines typed at the prompt.
+//! A step returning an error ends its job.
+//!
+//! Example:
+//! ```rust
+//! let id = jobs::start("sample_adc interval=500")?;
+//! jobs::run_due(&commands, device); // from the input loop
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+
+use super::commands::{self, Command, CommandList};
+use super::error::ERR_STR_LENGTH;
+use super::*;
+use crate::print;
+use crate::utils::time::{Duration, Instant};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_JOBS: usize = 4;
+
+static JOBS: Mutex<RefCell<Vec<Job, MAX_JOBS>>> = Mutex::new(RefCell::new(Vec::new()));
+
+pub type Line = String<LINE_BUFFER_LENGTH>;
+type StepFn = fn(&Command, &[Argument], &mut Context) -> Result<()>;
+
+/// Commands able to run in the background
+const STEPS: &[JobStep] = &[
+    JobStep {
+        name:       "sample_adc",
+        func:       commands::sample_adc_step,
+        interval:   "interval",
+        default_ms: 200,
+    },
+    JobStep {
+        name:       "read_adc",
+        func:       commands::read_adc_cmd,
+        interval:   "interval",
+        default_ms: 1000,
+    },
+];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Job
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// One iteration of a command, repeated every interval
+struct JobStep {
+    name:       &'static str,
+    func:       StepFn,
+    /// Param setting the time between steps, a duration (ex: 500ms, 2s)
+    interval:   &'static str,
+    default_ms: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id:          u8,
+    pub line:        Line,
+    pub interval_ms: u32,
+    pub runs:        u32,
+    next:            Instant,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Starts a job from a command line without the `&`, returns its id
+pub fn start(line: &str) -> Result<u8> {
+    let line = line.trim();
+    let name = line.split_ascii_whitespace().next().unwrap_or_default();
+    let step = find_step(name).ok_or_else(|| background_error(name))?;
+
+    let mut interval_ms = step.default_ms;
+    with_args(line, |args| {
+        if let Some(value) = args.get_str_param(step.interval) {
+            interval_ms = parse_duration_ms(value)
+                .filter(|ms| *ms > 0)
+                .ok_or(Error::Parse(step.interval.into_truncate()))?;
+        }
+        Ok(())
+    })?;
+
+    with(|cs| {
+        let mut jobs = JOBS.borrow_ref_mut(cs);
+
+        // Lowest free id, like shell job numbers
+        let id = (1..=MAX_JOBS as u8)
+            .find(|id| jobs.iter().all(|job| job.id != *id))
+            .ok_or("too many jobs (max 4)")?;
+        let job = Job {
+            id,
+            line: line.into_truncate(),
+            interval_ms,
+            runs: 0,
+            next: Instant::now(),
+        };
+        jobs.push(job).map_err(|_| "too many jobs (max 4)")?;
+        Ok(id)
+    })
+}
+
+/// Runs one step of every due job
+pub fn run_due(commands: &CommandList, context: &mut Context) {
+    for id in 1..=MAX_JOBS as u8 {
+        let now = Instant::now();
+        let due = with(|cs| {
+            let mut jobs = JOBS.borrow_ref_mut(cs);
+            let job = jobs
+                .iter_mut()
+                .find(|job| job.id == id && job.next <= now)?;
+
+            let interval = Duration::from_millis(job.interval_ms as u64);
+            job.next = job.next.next_deadline(interval, now);
+            job.runs += 1;
+            Some(job.line.clone())
+        });
+        let Some(line) = due
+        else {
+            continue;
+        };
+
+        print!("[{id}] ");
+        if let Err(e) = run_step(commands, &line, context) {
+            println!("[{id}] Err: {e}, job ended");
+            kill(id);
+        }
+    }
+}
+
+/// Removes a job and returns it, for `fg`
+pub fn take(id: u8) -> Option<Job> {
+    with(|cs| {
+        let mut jobs = JOBS.borrow_ref_mut(cs);
+        let index = jobs.iter().position(|job| job.id == id)?;
+        Some(jobs.remove(index))
+    })
+}
+
+/// Ends a job, false if there is none with this id
+pub fn kill(id: u8) -> bool {
+    take(id).is_some()
+}
+
+/// Ends every job, returns how many were running
+pub fn kill_all() -> usize {
+    with(|cs| {
+        let mut jobs = JOBS.borrow_ref_mut(cs);
+        let count = jobs.len();
+        jobs.clear();
+        count
+    })
+}
+
+/// Copy of the running jobs
+pub fn list() -> Vec<Job, MAX_JOBS> {
+    with(|cs| JOBS.borrow_ref(cs).clone())
+}
+
+pub fn is_empty() -> bool {
+    with(|cs| JOBS.borrow_ref(cs).is_empty())
+}
+
+/// Last started job id, the default of `fg`
+pub fn last_id() -> Option<u8> {
+    with(|cs| JOBS.borrow_ref(cs).last().map(|job| job.id))
+}
+
+fn find_step(name: &str) -> Option<&'static JobStep> {
+    STEPS
+        .iter()
+        .find(|step| step.name.eq_ignore_ascii_case(name))
+}
+
+fn run_step(commands: &CommandList, line: &str, context: &mut Context) -> Result<()> {
+    let name = line.split_ascii_whitespace().next().unwrap_or_default();
+    let step = find_step(name).ok_or_else(|| background_error(name))?;
+    let command = commands.get_command(name)?;
+
+    with_args(line, |args| {
+        requirements::check(command.requires, args, context)?;
+        (step.func)(command, args, context)
+    })
+}
+
+/// Parses the arguments of a command line for `f`
+fn with_args<R>(line: &str, f: impl FnOnce(&[Argument]) -> Result<R>) -> Result<R> {
+    let args = line.split_once(' ').map_or("", |(_, args)| args);
+    let mut args_buf: Vec<u8, LINE_BUFFER_LENGTH> =
+        Vec::from_slice(args.as_bytes()).map_err(|_| Error::CommandTooLong(LINE_BUFFER_LENGTH))?;
+    let args = parser::parse::<MAX_ARGS>(&mut args_buf)?;
+    f(&args)
+}
+
+/// Names of the commands able to run in the background, for the help
+pub fn supported() -> impl Iterator<Item = &'static str> {
+    STEPS.iter().map(|step| step.name)
+}
+
+fn background_error(name: &str) -> Error {
+    let mut error: String<ERR_STR_LENGTH> = String::new();
+    let _ = write!(error, "{name} can't run in the background");
+    Error::Custom(error)
+}
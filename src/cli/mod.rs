@@ -1,15 +1,28 @@
 //! A Simple CLI Module
 
 pub mod commands;
+pub mod confirm;
 pub mod error;
+pub mod jobs;
 pub mod parser;
+pub mod requirements;
+pub mod scope;
+pub mod soak;
+pub mod term;
 
-pub use commands::CommandList;
-pub use error::{Error, IntoTruncate, Result};
+pub use crate::limits::{LINE_BUFFER_LENGTH, MAX_ARGS, MAX_CMDS};
+pub use commands::{Category, CommandList};
+pub use error::{Error, IntoTruncate, Result, Suggestions};
 pub use parser::*;
+pub use requirements::Requirement;
+pub use term::TERM;
+
+use core::fmt::Write;
 
 use crate::println;
 use crate::system::device::Device as Context;
+use crate::system::files::FILES;
+use crate::system::serial_io::SERIAL;
 
 pub use heapless::Vec;
 
@@ -26,9 +39,13 @@ impl SimpleCli {
         Self { command_list }
     }
 
+    pub fn command_list(&self) -> &CommandList {
+        &self.command_list
+    }
+
     pub fn execute(&mut self, input: &str, context: &mut Context) -> Result<()> {
-        // Extracting command name and list of arguments
-        let (cmd_name, input_args) = input.split_once(' ').unwrap_or((input, ""));
+        // Extracting command name
+        let cmd_name = input.split_once(' ').map_or(input, |(name, _)| name);
 
         // Strip CR
         const CR: char = '\u{000D}';
@@ -36,26 +53,230 @@ impl SimpleCli {
 
         // Check if built-in help was called
         if cmd_name.is_empty() || cmd_name == "help" {
-            self.built_in_help();
-            return Ok(());
+            let args = input.split_once(' ').map_or("", |(_, args)| args);
+            return self.built_in_help(args);
         }
 
-        // Parsing arguments
-        let cmd_args = parser::parse(input_args)?;
+        // Output redirection to a RAM file: "cmd > file" replaces, "cmd >> file" appends
+        if let Some((cmd_line, target)) = split_redirect(input) {
+            let (append, file) = match target.strip_prefix('>') {
+                Some(file) => (true, file.trim()),
+                None => (false, target.trim()),
+            };
+
+            // An enclosing capture (secure channel) is set aside meanwhile
+            let outer = SERIAL.end_capture();
+            SERIAL.start_capture();
+            let result = self.command_list.execute(cmd_line, context);
+            let output = SERIAL.end_capture().unwrap_or_default();
+            if let Some(outer) = outer {
+                SERIAL.resume_capture(outer);
+            }
+
+            let size = FILES.write(file, &output.bytes, append)?;
+            println!("{} bytes written to {file} ({size} bytes)", output.bytes.len());
+            if output.truncated {
+                println!("Output truncated to {} bytes", output.bytes.len());
+            }
+            return result;
+        }
+
+        // Parsing arguments and executing the command
+        self.command_list.execute(input, context)
+    }
+
+    /// help: command names by category
+    /// help <category>|all: descriptions of a category or of every command
+    /// help search=<text>: commands matching the text, best matches first
+    pub fn built_in_help(&self, input_args: &str) -> Result<()> {
+        let mut args_buf: Vec<u8, LINE_BUFFER_LENGTH> = Vec::from_slice(input_args.as_bytes())
+            .map_err(|_| Error::CommandTooLong(LINE_BUFFER_LENGTH))?;
+        let args = parser::parse::<MAX_ARGS>(&mut args_buf)?;
+        let args = args.as_slice();
 
-        // Execute Command
-        let command = self.command_list.get_command(cmd_name)?;
-        command.run(&cmd_args, context)
+        if let Some(text) = args.get_str_param("search") {
+            self.help_search(text);
+            return Ok(());
+        }
+
+        match args.first().map(|arg| arg.param) {
+            None => self.help_overview(),
+            Some("all") => Category::ALL
+                .iter()
+                .for_each(|&category| self.help_category(category)),
+            Some(name) => {
+                let category = Category::from_name(name)
+                    .ok_or("unknown category, expected base, io, drivers, dev or all")?;
+                self.help_category(category);
+            }
+        }
+        println!("For more information type: command_name help\n");
+        Ok(())
     }
 
-    pub fn built_in_help(&self) {
+    /// Command names grouped by category
+    fn help_overview(&self) {
         println!("\nAvailable Commands:");
         println!("-----------------------------");
 
-        for command in self.command_list.commands.iter() {
-            println!("{} - {}", command.name, command.desc);
+        for category in Category::ALL {
+            let mut line: heapless::String<512> = heapless::String::new();
+            let _ = write!(line, "{}:", category.name());
+            for command in self.commands_in(category) {
+                let _ = write!(line, " {}", command.name);
+            }
+            TERM.print_wrapped(&line);
         }
         println!("-----------------------------");
-        println!("For more information type: command_name help\n");
+        println!("help <base|io|drivers|dev|all> for descriptions, help search=<text> to find one");
+    }
+
+    fn help_category(&self, category: Category) {
+        println!("\n{} commands:", category.name());
+        println!("-----------------------------");
+
+        for command in self.commands_in(category) {
+            print_description_line(command);
+        }
+        println!("-----------------------------");
+    }
+
+    fn help_search(&self, text: &str) {
+        let mut matches: Vec<(u8, usize), MAX_CMDS> = Vec::new();
+        for (index, command) in self.command_list.commands.iter().enumerate() {
+            if let Some(score) = match_score(text, command) {
+                let _ = matches.push((score, index));
+            }
+        }
+        matches.sort_unstable();
+
+        if matches.is_empty() {
+            println!("\nNo command matches \"{text}\"\n");
+            return;
+        }
+
+        println!("\nCommands matching \"{text}\":");
+        println!("-----------------------------");
+        for &(_, index) in matches.iter() {
+            print_description_line(&self.command_list.commands[index]);
+        }
+        println!("-----------------------------\n");
+    }
+
+    fn commands_in(&self, category: Category) -> impl Iterator<Item = &commands::Command> {
+        self.command_list
+            .commands
+            .iter()
+            .filter(move |command| command.category == category)
+    }
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Chaining
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Separator before the next command of a line
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Chain {
+    /// ";" runs the next command whatever the result
+    Always,
+    /// "&&" runs the next command if the previous one succeeded
+    OnSuccess,
+}
+
+/// Splits "cmd1 args ; cmd2 args && cmd3" at the first ';' or "&&" outside of quotes
+pub fn split_chain(input: &str) -> Option<(&str, Chain, &str)> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '\\' if in_quotes => escaped = !escaped,
+            '"' if !escaped => in_quotes = !in_quotes,
+            ';' if !in_quotes => return Some((&input[..i], Chain::Always, &input[i + 1..])),
+            '&' if !in_quotes && input[i + 1..].starts_with('&') => {
+                return Some((&input[..i], Chain::OnSuccess, &input[i + 2..]));
+            }
+            _ => {}
+        }
+        if c != '\\' {
+            escaped = false;
+        }
+    }
+    None
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn print_description_line(command: &commands::Command) {
+    let mut line: heapless::String<128> = heapless::String::new();
+    let _ = write!(line, "{} - {}", command.name, command.desc);
+    TERM.print_wrapped(&line);
+}
+
+/// Ranks a command against a search text, lower is better:
+/// exact name, name prefix, name substring, description substring, then name subsequence
+fn match_score(text: &str, command: &commands::Command) -> Option<u8> {
+    let name = command.name;
+
+    if name.eq_ignore_ascii_case(text) {
+        Some(0)
+    }
+    else if starts_with_ignore_case(name, text) {
+        Some(1)
+    }
+    else if contains_ignore_case(name, text) {
+        Some(2)
+    }
+    else if contains_ignore_case(command.desc, text) {
+        Some(3)
+    }
+    else if is_subsequence(name, text) {
+        Some(4)
+    }
+    else {
+        None
+    }
+}
+
+fn starts_with_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.len() >= needle.len()
+        && haystack.as_bytes()[..needle.len()].eq_ignore_ascii_case(needle.as_bytes())
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    needle.is_empty()
+        || haystack
+            .as_bytes()
+            .windows(needle.len())
+            .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// The needle characters appear in order in the haystack, ex: "pwst" in "pwm_status"
+fn is_subsequence(haystack: &str, needle: &str) -> bool {
+    let mut chars = haystack.chars();
+    needle
+        .chars()
+        .all(|c| chars.any(|h| h.eq_ignore_ascii_case(&c)))
+}
+
+/// Splits "cmd args > target" at the first '>' outside of quotes
+fn split_redirect(input: &str) -> Option<(&str, &str)> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '\\' if in_quotes => escaped = !escaped,
+            '"' if !escaped => in_quotes = !in_quotes,
+            '>' if !in_quotes => return Some((&input[..i], &input[i + 1..])),
+            _ => {}
+        }
+        if c != '\\' {
+            escaped = false;
+        }
     }
+    None
 }
@@ -1,9 +1,15 @@
-//! Command Parser from an input &str
-/// Run parse(input: &str) to retrieve and arguments list.
+//! Command Parser from an input buffer
+/// Run parse::<N>(input: &mut [u8]) to retrieve a list of up to N arguments.
+/// The buffer is unescaped and lowercased in place, arguments are slices into it: no per
+/// argument copies, a single pass over the input.
 /// Use ArgList trait functions available for &[Argument] to retrieve and convert the values.
 pub use core::str::FromStr;
 
+use core::fmt::{Display, Write};
+use core::ops::RangeInclusive;
+
 use super::error::*;
+use crate::limits::{MAX_PARAM_NAME_LENGTH, MAX_VALUE_LENGTH};
 
 pub use heapless::{String, Vec};
 
@@ -11,68 +17,51 @@ pub use heapless::{String, Vec};
 //                                             Globals
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-const READ_BUFFER_LENGTH: usize = 192;
-
-const MAX_NUMBER_PARAMS: usize = 5;
-const MAX_CMD_NAME_LENGTH: usize = 24;
-const MAX_PARAM_NAME_LENGTH: usize = 16;
-const MAX_VALUE_LENGTH: usize = 64;
-
-const SEPARATOR: char = '\u{001E}';
-const ESCAPE: char = '\u{005C}';
-const CR: char = '\u{000D}';
-const DEFAULT_CMD: &str = "help";
+const ESCAPE: u8 = b'\\';
+const CR: u8 = b'\r';
 
 // ——————————————————————————————————————————— Parse —————————————————————————————————————————————
 
-/// Takes an input str and parses it, returning an arguments list.
+/// Parses the input buffer in place, returning an arguments list borrowing it.
+/// Unquoted text is lowercased, quotes and escape characters are removed.
 #[inline]
-pub fn parse(input: &str) -> Result<Vec<Argument, MAX_NUMBER_PARAMS>> {
-    // Creating command with args Struct. Defaulting to DEFAULT_CMD cmd.
-    let mut args: Vec<Argument, MAX_NUMBER_PARAMS> = Vec::new();
-
-    if input.is_empty() {
-        return Ok(args);
-    }
-
-    let mut processed_buf: String<READ_BUFFER_LENGTH> = String::new();
+pub fn parse<const N: usize>(input: &mut [u8]) -> Result<Vec<Argument<'_>, N>> {
+    // Word bounds in the compacted buffer
+    let mut words: Vec<(usize, usize), N> = Vec::new();
+    let mut word_start: Option<usize> = None;
+    let mut write = 0;
     let mut in_quotes = false;
     let mut escaped = false;
 
-    // Replacing spaces in quotes with SEPARATOR
-    for char in input.chars() {
-        match char {
-            CR => {}
-            '"' => {
-                if escaped && in_quotes {
-                    processed_buf.push('"').map_err(|_| Error::CommandTooLong)?;
-                }
-                else {
-                    in_quotes = !in_quotes;
-                }
-                escaped = false;
+    // Compacting the unescaped bytes at the front, only ASCII bytes are dropped or changed
+    for read in 0..input.len() {
+        let byte = match input[read] {
+            CR => continue,
+            b'"' if escaped => b'"',
+            b'"' => {
+                in_quotes = !in_quotes;
+                continue;
             }
-            ' ' if in_quotes => {
-                escaped = false;
-                processed_buf.push(SEPARATOR).map_err(|_| Error::CommandTooLong)?;
+            ESCAPE if in_quotes && !escaped => {
+                escaped = true;
+                continue;
             }
-            ESCAPE if in_quotes => {
-                if escaped {
-                    processed_buf.push(ESCAPE).map_err(|_| Error::CommandTooLong)?;
-                    escaped = false;
+            byte if in_quotes => byte,
+            byte if byte.is_ascii_whitespace() => {
+                if let Some(start) = word_start.take() {
+                    words
+                        .push((start, write))
+                        .map_err(|_| Error::TooManyArgs(N))?;
                 }
-                else {
-                    escaped = true;
-                }
-            }
-            c if in_quotes => {
-                escaped = false;
-                processed_buf.push(c).map_err(|_| Error::CommandTooLong)?;
+                continue;
             }
-            c => {
-                processed_buf.push(c.to_ascii_lowercase()).map_err(|_| Error::CommandTooLong)?;
-            }
-        }
+            byte => byte.to_ascii_lowercase(),
+        };
+
+        escaped = false;
+        word_start.get_or_insert(write);
+        input[write] = byte;
+        write += 1;
     }
 
     // Check for dangling escape character
@@ -85,32 +74,36 @@ pub fn parse(input: &str) -> Result<Vec<Argument, MAX_NUMBER_PARAMS>> {
         return Err(Error::Parse("unmatched quotes".into_truncate()));
     }
 
+    if let Some(start) = word_start {
+        words
+            .push((start, write))
+            .map_err(|_| Error::TooManyArgs(N))?;
+    }
+
     // ——————————————————————————————————— Processing arguments ——————————————————————————————————————
 
-    let processed_buf = processed_buf.split_ascii_whitespace();
+    let input: &[u8] = input;
+    let mut args: Vec<Argument, N> = Vec::new();
+
+    for (start, end) in words {
+        let word = core::str::from_utf8(&input[start..end])
+            .map_err(|_| Error::Parse("invalid UTF-8".into_truncate()))?;
 
-    for word in processed_buf {
         // Sanitizing. Orphan "=" triggers error.
-        if word == "=" || word.starts_with('=') || word.ends_with('=') {
+        if word.starts_with('=') || word.ends_with('=') {
             return Err(Error::Parse("\"=\" spacing".into_truncate()));
         }
 
-        let mut elements = word.splitn(2, '=');
-        let param_str = elements.next().unwrap();
-        let value_str = elements.next();
-
-        let param = String::try_from(param_str).map_err(|_| Error::ArgTooLong)?;
-        let mut value: String<MAX_VALUE_LENGTH> = String::new();
-
-        // If param has value, we restore the space characters
-        if let Some(val_) = value_str {
-            for char in val_.chars() {
-                let c_to_push = if char == SEPARATOR { ' ' } else { char };
-                value.push(c_to_push).map_err(|_| Error::ArgTooLong)?;
-            }
+        let (param, value) = word.split_once('=').unwrap_or((word, ""));
+        if param.len() > MAX_PARAM_NAME_LENGTH {
+            return Err(Error::ArgTooLong(param.into_truncate(), MAX_PARAM_NAME_LENGTH));
+        }
+        if value.len() > MAX_VALUE_LENGTH {
+            return Err(Error::ArgTooLong(param.into_truncate(), MAX_VALUE_LENGTH));
         }
 
-        args.push(Argument { param, value }).map_err(|_| Error::TooManyArgs)?;
+        args.push(Argument { param, value })
+            .map_err(|_| Error::TooManyArgs(N))?;
     }
 
     Ok(args)
@@ -120,10 +113,11 @@ pub fn parse(input: &str) -> Result<Vec<Argument, MAX_NUMBER_PARAMS>> {
 //                                            Argument
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-#[derive(Debug, Default, Clone)]
-pub struct Argument {
-    pub param: String<MAX_PARAM_NAME_LENGTH>,
-    pub value: String<MAX_VALUE_LENGTH>,
+/// Param and value slices into the parsed buffer, the value is empty for flags
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Argument<'a> {
+    pub param: &'a str,
+    pub value: &'a str,
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -139,10 +133,25 @@ pub trait ArgList {
 
     fn get_str_param<'a>(&'a self, param: &str) -> Option<&'a str>;
 
+    fn get_int_param(&self, param: &str) -> Result<u32>;
+
+    fn get_ranged_param<T>(&self, param: &str, range: RangeInclusive<T>) -> Result<T>
+    where
+        T: FromStr + PartialOrd + Copy + Display;
+
+    fn get_ranged_param_or<T>(
+        &self,
+        param: &str,
+        range: RangeInclusive<T>,
+        default: T,
+    ) -> Result<T>
+    where
+        T: FromStr + PartialOrd + Copy + Display;
+
     fn contains_param(&self, str: &str) -> bool;
 }
 
-impl ArgList for &[Argument] {
+impl ArgList for &[Argument<'_>] {
     #[inline]
     fn get_parsed_param<T>(&self, param: &str) -> Result<T>
     where
@@ -153,9 +162,10 @@ impl ArgList for &[Argument] {
             .find(|s| s.param.eq_ignore_ascii_case(param))
             .ok_or_else(|| Error::MissingArg(param.into_truncate()))?;
 
-        let val_as_str = arg.value.as_str();
-
-        let value: T = val_as_str.parse().map_err(|_| Error::Parse(param.into_truncate()))?;
+        let value: T = arg
+            .value
+            .parse()
+            .map_err(|_| Error::Parse(param.into_truncate()))?;
 
         Ok(value)
     }
@@ -164,7 +174,49 @@ impl ArgList for &[Argument] {
     fn get_str_param<'a>(&'a self, param: &str) -> Option<&'a str> {
         self.iter()
             .find(|arg| arg.param.eq_ignore_ascii_case(param))
-            .map(move |arg| arg.value.as_str())
+            .map(|arg| arg.value)
+    }
+
+    /// Parses a decimal, hex (0x..) or binary (0b..) integer
+    #[inline]
+    fn get_int_param(&self, param: &str) -> Result<u32> {
+        let value = self
+            .get_str_param(param)
+            .ok_or_else(|| Error::MissingArg(param.into_truncate()))?;
+
+        parse_int(value).ok_or_else(|| Error::Parse(param.into_truncate()))
+    }
+
+    /// Parses a value within the range, out of range values are rejected
+    /// unless the `clamp` flag is given
+    fn get_ranged_param<T>(&self, param: &str, range: RangeInclusive<T>) -> Result<T>
+    where
+        T: FromStr + PartialOrd + Copy + Display,
+    {
+        let value: T = self.get_parsed_param(param)?;
+        let (min, max) = (*range.start(), *range.end());
+
+        if range.contains(&value) {
+            return Ok(value);
+        }
+        if self.contains_param("clamp") {
+            return Ok(if value < min { min } else { max });
+        }
+
+        let mut detail: String<ERR_STR_LENGTH> = String::new();
+        let _ = write!(detail, "{min}..={max} (got {value})");
+        Err(Error::OutOfRange(param.into_truncate(), detail))
+    }
+
+    /// Same as `get_ranged_param`, the default is returned when the param is missing
+    fn get_ranged_param_or<T>(&self, param: &str, range: RangeInclusive<T>, default: T) -> Result<T>
+    where
+        T: FromStr + PartialOrd + Copy + Display,
+    {
+        match self.contains_param(param) {
+            true => self.get_ranged_param(param, range),
+            false => Ok(default),
+        }
     }
 
     #[inline]
@@ -172,3 +224,36 @@ impl ArgList for &[Argument] {
         self.iter().any(|arg| arg.param.eq_ignore_ascii_case(str))
     }
 }
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Parses a decimal, hex (0x..) or binary (0b..) integer
+pub fn parse_int(value: &str) -> Option<u32> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    }
+    else if let Some(bin) = value.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    }
+    else {
+        value.parse().ok()
+    }
+}
+
+/// Parses a duration in milliseconds: "250ms", "2s", "1m", or plain milliseconds
+pub fn parse_duration_ms(value: &str) -> Option<u32> {
+    if let Some(ms) = value.strip_suffix("ms") {
+        ms.parse().ok()
+    }
+    else if let Some(s) = value.strip_suffix('s') {
+        s.parse::<u32>().ok()?.checked_mul(1000)
+    }
+    else if let Some(m) = value.strip_suffix('m') {
+        m.parse::<u32>().ok()?.checked_mul(60_000)
+    }
+    else {
+        value.parse().ok()
+    }
+}
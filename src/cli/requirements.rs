@@ -0,0 +1,134 @@
+//! Command Requirements
+//!
+//! Resources a command declares in its definition, checked before the command function runs so
+//! it fails upfront with every missing resource listed, instead of halfway through.
+//! The help flag skips the check.
+//!
+//! Example:
+//! ```rust
+//! Command {
+//!     name: "i2c",
+//!     ..
+//!     requires: &[Requirement::Bus { param: "bus", default: BusId::I2c0 }],
+//! }
+//! // i2c scan bus=i2c1 -> Err: missing: i2c1 not configured
+//! ```
+
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use super::confirm;
+use super::error::*;
+use super::parser::{ArgList, Argument};
+use crate::main_core1::{self, CORE1_BUSY};
+use crate::system::buses::BusId;
+use crate::system::config::CONFIG;
+use crate::system::device::Device;
+use crate::system::encoder_sim::ENCODER_SIM;
+use crate::system::mirror::MIRRORS;
+use crate::system::tpo::TPO;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Requirement
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub enum Requirement {
+    /// Bus given by the param, or the default one, configured
+    Bus { param: &'static str, default: BusId },
+    /// GPIO driven status LED
+    Led,
+    /// Core1 enabled and not running an event, it is started by the command if needed
+    Core1Idle,
+    /// Pin given by the param (alias or gpio), or the default alias, not driven by a tpo
+    /// channel, a mirror rule or the encoder simulator
+    PinFree { param: &'static str, default: &'static str },
+    /// With `set confirm=on`, confirm=yes on the line or a pending `arm`, see `confirm`
+    Confirmed,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Checks every requirement, the unmet ones are reported together
+pub fn check(requires: &[Requirement], args: &[Argument], device: &Device) -> Result<()> {
+    let mut missing: String<MISSING_STR_LENGTH> = String::new();
+
+    for requirement in requires {
+        let mut reason: String<ERR_STR_LENGTH> = String::new();
+        unmet(requirement, args, device, &mut reason);
+        if reason.is_empty() {
+            continue;
+        }
+
+        if !missing.is_empty() {
+            let _ = missing.push_str(", ");
+        }
+        let _ = missing.push_str(&reason);
+    }
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(Error::Missing(missing)),
+    }
+}
+
+/// Writes the reason of an unmet requirement, nothing when it is met
+fn unmet(requirement: &Requirement, args: &[Argument], device: &Device, reason: &mut impl Write) {
+    match *requirement {
+        Requirement::Bus { param, default } => {
+            // Invalid names are left to the command
+            let bus = args
+                .get_str_param(param)
+                .map_or(Some(default), |name| name.parse::<BusId>().ok());
+            if let Some(bus) = bus.filter(|&bus| !device.buses.is_configured(bus)) {
+                let _ = write!(reason, "{bus} not configured");
+            }
+        }
+        Requirement::Led => {
+            if !device.led.is_available() {
+                let _ = write!(reason, "no GPIO driven LED");
+            }
+        }
+        Requirement::Core1Idle => {
+            if main_core1::state() == main_core1::State::Disabled {
+                let _ = write!(reason, "core1 disabled");
+            }
+            else if CORE1_BUSY.load(Ordering::Relaxed) {
+                let _ = write!(reason, "core1 busy");
+            }
+        }
+        Requirement::PinFree { param, default } => {
+            let name = args.get_str_param(param).unwrap_or(default);
+            let gpio = match name.parse::<u8>() {
+                Ok(gpio) => gpio,
+                Err(_) => match CONFIG.get_gpio(name) {
+                    Ok(gpio) => gpio,
+                    Err(_) => {
+                        let _ = write!(reason, "pin {name} not configured");
+                        return;
+                    }
+                },
+            };
+
+            if TPO.channels().iter().any(|channel| channel.gpio == gpio) {
+                let _ = write!(reason, "pin {gpio} taken by tpo");
+            }
+            else if MIRRORS.rules().iter().any(|rule| rule.output == gpio) {
+                let _ = write!(reason, "pin {gpio} taken by mirror");
+            }
+            else if ENCODER_SIM
+                .pins()
+                .is_some_and(|(a, b)| a == gpio || b == gpio)
+            {
+                let _ = write!(reason, "pin {gpio} taken by encoder_sim");
+            }
+        }
+        Requirement::Confirmed => {
+            if !confirm::take(args) {
+                let _ = write!(reason, "confirm=yes or arm first");
+            }
+        }
+    }
+}
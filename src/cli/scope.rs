@@ -0,0 +1,251 @@
+//! ASCII Oscilloscope
+//!
+//! Scrolling waveform of the `scope` command. Every column is the min/max envelope of the samples
+//! of its time slot, short spikes stay visible whatever the time base. The newest column is on the
+//! right, the trace is redrawn in place with ANSI cursor moves.
+//!
+//! The header shows the min, max and average of the visible window and the rising crossings of
+//! the trigger level, drawn as a dashed line.
+//!
+//! Example:
+//! ```rust
+//! let mut scope = Scope::new(columns, 12, 0..=4095, Some(2048), Charset::Unicode);
+//! for sample in samples {
+//!     scope.push(sample, per_column);
+//! }
+//! scope.render("ADC0 | 5000Hz");
+//! ```
+
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+
+use heapless::{Deque, String};
+
+use crate::prelude::*;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_COLUMNS: usize = 160;
+pub const MIN_ROWS: u8 = 4;
+pub const MAX_ROWS: u8 = 32;
+/// Width of the voltage labels and the axis on the left of the trace
+pub const LABEL_WIDTH: usize = 8;
+
+/// A unicode cell takes up to 3 bytes
+const ROW_CAPACITY: usize = LABEL_WIDTH + MAX_COLUMNS * 3 + 8;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Charset
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+impl Charset {
+    fn trace(&self) -> char {
+        match self {
+            Charset::Ascii => '#',
+            Charset::Unicode => '█',
+        }
+    }
+
+    fn trigger(&self) -> char {
+        match self {
+            Charset::Ascii => '-',
+            Charset::Unicode => '┄',
+        }
+    }
+
+    fn axis(&self) -> char {
+        match self {
+            Charset::Ascii => '|',
+            Charset::Unicode => '│',
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Scope
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Envelope of the samples of one column
+#[derive(Debug, Copy, Clone)]
+struct Column {
+    min:       u16,
+    max:       u16,
+    sum:       u32,
+    count:     u32,
+    /// Rising crossings of the trigger level
+    crossings: u16,
+}
+
+impl Column {
+    fn new(sample: u16) -> Self {
+        Self {
+            min:       sample,
+            max:       sample,
+            sum:       sample as u32,
+            count:     1,
+            crossings: 0,
+        }
+    }
+}
+
+pub struct Scope {
+    columns: Deque<Column, MAX_COLUMNS>,
+    width:   usize,
+    rows:    u8,
+    /// Raw levels of the bottom and top rows
+    range:   RangeInclusive<u16>,
+    trigger: Option<u16>,
+    charset: Charset,
+    /// Column being filled
+    current: Option<Column>,
+    last:    Option<u16>,
+}
+
+impl Scope {
+    pub fn new(
+        width: usize,
+        rows: u8,
+        range: RangeInclusive<u16>,
+        trigger: Option<u16>,
+        charset: Charset,
+    ) -> Self {
+        Self {
+            columns: Deque::new(),
+            width: width.clamp(1, MAX_COLUMNS),
+            rows: rows.clamp(MIN_ROWS, MAX_ROWS),
+            range,
+            trigger,
+            charset,
+            current: None,
+            last: None,
+        }
+    }
+
+    /// Columns of the trace
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Adds a sample, a column is complete after `per_column` samples
+    pub fn push(&mut self, sample: u16, per_column: u32) {
+        let crossed = match (self.trigger, self.last) {
+            (Some(level), Some(last)) => last < level && sample >= level,
+            _ => false,
+        };
+        self.last = Some(sample);
+
+        let column = match self.current.as_mut() {
+            Some(column) => {
+                column.min = column.min.min(sample);
+                column.max = column.max.max(sample);
+                column.sum += sample as u32;
+                column.count += 1;
+                column
+            }
+            None => self.current.insert(Column::new(sample)),
+        };
+        column.crossings += crossed as u16;
+
+        if column.count >= per_column {
+            let column = *column;
+            self.current = None;
+            if self.columns.len() >= self.width {
+                self.columns.pop_front();
+            }
+            let _ = self.columns.push_back(column);
+        }
+    }
+
+    /// Min, max and average raw levels of the visible columns
+    pub fn stats(&self) -> Option<(u16, u16, u16)> {
+        let min = self.columns.iter().map(|c| c.min).min()?;
+        let max = self.columns.iter().map(|c| c.max).max()?;
+        let (sum, count) = self
+            .columns
+            .iter()
+            .fold((0u64, 0u64), |(sum, count), c| (sum + c.sum as u64, count + c.count as u64));
+        Some((min, max, (sum / count.max(1)) as u16))
+    }
+
+    /// Rising crossings of the trigger level in the visible columns
+    pub fn crossings(&self) -> u32 {
+        self.columns.iter().map(|c| c.crossings as u32).sum()
+    }
+
+    /// Scales the rows to a raw range, ex: the window min and max
+    pub fn set_range(&mut self, range: RangeInclusive<u16>) {
+        self.range = range;
+    }
+
+    /// Redraws the header and the trace from the top left corner
+    pub fn render(&self, title: &str) {
+        let mut header: String<ROW_CAPACITY> = String::new();
+        let _ = write!(header, "{title}");
+        if let Some((min, max, avg)) = self.stats() {
+            let _ = write!(
+                header,
+                " | min {} max {} avg {}",
+                Volts(min.to_voltage()),
+                Volts(max.to_voltage()),
+                Volts(avg.to_voltage())
+            );
+        }
+        if let Some(level) = self.trigger {
+            let _ = write!(header, " | trig {} x{}", Volts(level.to_voltage()), self.crossings());
+        }
+        print!("\x1b[H{header}\x1b[K\r\n\x1b[K\r\n");
+
+        let low = *self.range.start() as u32;
+        let span = (*self.range.end() as u32).saturating_sub(low).max(1);
+        let rows = self.rows as u32;
+        // Empty columns on the left until the window is full
+        let blank = self.width - self.columns.len();
+
+        for row in 0..rows {
+            // Raw band of the row, top row first
+            let band_high = low + span * (rows - row) / rows;
+            let band_low = low + span * (rows - row - 1) / rows;
+            let on_trigger = self.trigger.is_some_and(|level| {
+                let level = level as u32;
+                (band_low..band_high).contains(&level) || (row == 0 && level == band_high)
+            });
+
+            let mut line: String<ROW_CAPACITY> = String::new();
+            match row {
+                0 => label(&mut line, band_high as u16),
+                _ if row == rows - 1 => label(&mut line, low as u16),
+                _ => {
+                    let _ = write!(line, "{:width$}", "", width = LABEL_WIDTH - 1);
+                }
+            }
+            let _ = line.push(self.charset.axis());
+
+            let empty = if on_trigger { self.charset.trigger() } else { ' ' };
+            for _ in 0..blank {
+                let _ = line.push(empty);
+            }
+            for column in self.columns.iter() {
+                let hit = (column.min as u32) <= band_high && (column.max as u32) >= band_low;
+                let _ = line.push(if hit { self.charset.trace() } else { empty });
+            }
+            print!("{line}\x1b[K\r\n");
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Voltage label of a row, right aligned before the axis
+fn label(line: &mut String<ROW_CAPACITY>, raw: u16) {
+    let _ = write!(line, "{:>width$.2}V", raw.to_voltage(), width = LABEL_WIDTH - 2);
+}
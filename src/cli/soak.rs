@@ -0,0 +1,347 @@
+//! Soak Tests
+//!
+//! Unattended endurance runs: `soak hours=24 actions="toggle OUT_A every 1s; read_adc every 60s"`
+//! repeats each action at its own period until the duration is over. Actions are command lines,
+//! `toggle|high|low <pin>` being short for `pin alias=<pin> toggle|high|low`.
+//!
+//! The steps run from the program loop, with or without a host: while disconnected the output is
+//! dropped, the results are kept. A failed action is a command returning an error.
+//!
+//! Statistics are written to the wear-leveled KV store (see `system::kv`), on every failure and
+//! every `CHECKPOINT_S`, so they survive a reset or a power loss. The next connection prints the
+//! summary once, `soak report` prints it again. A soak cut by a reset shows up as interrupted.
+//!
+//! Example:
+//! ```rust
+//! soak::start("read_adc every 60s", 24 * 3600)?;
+//! soak::run_due(&commands, device); // from the input loop
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+
+use super::commands::CommandList;
+use super::*;
+use crate::system::kv::{self, KV};
+use crate::utils::time::{Duration, Instant};
+use crate::{print, warn};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_ACTIONS: usize = 4;
+/// Statistics written to flash at least this often
+const CHECKPOINT_S: u64 = 600;
+
+static SOAK: Mutex<RefCell<Option<Soak>>> = Mutex::new(RefCell::new(None));
+
+pub type Line = String<LINE_BUFFER_LENGTH>;
+
+/// KV keys, "soak.e<N>" are the failures of action N
+const STATE_KEY: &str = "soak.st";
+const DURATION_KEY: &str = "soak.dur";
+const ELAPSED_KEY: &str = "soak.s";
+const RUNS_KEY: &str = "soak.run";
+const FAILURES_KEY: &str = "soak.err";
+const PENDING_KEY: &str = "soak.rep";
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              State
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Outcome kept in flash, a soak still marked as running without one in RAM was cut by a reset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    Running = 1,
+    Done    = 2,
+    Stopped = 3,
+}
+
+impl State {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(State::Running),
+            2 => Some(State::Done),
+            3 => Some(State::Stopped),
+            _ => None,
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Soak
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    pub line:      Line,
+    pub period_ms: u32,
+    pub runs:      u32,
+    pub failures:  u32,
+    next:          Instant,
+}
+
+#[derive(Clone)]
+struct Soak {
+    actions:    Vec<Action, MAX_ACTIONS>,
+    start:      Instant,
+    duration:   Duration,
+    checkpoint: Instant,
+}
+
+impl Soak {
+    fn runs(&self) -> u32 {
+        self.actions.iter().map(|action| action.runs).sum()
+    }
+
+    fn failures(&self) -> u32 {
+        self.actions.iter().map(|action| action.failures).sum()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Starts a soak of `duration_s` from ';' separated "<command line> every <duration>" actions,
+/// replacing the running one. The statistics in flash start over.
+pub fn start(actions: &str, duration_s: u32) -> Result<()> {
+    let now = Instant::now();
+    let mut parsed: Vec<Action, MAX_ACTIONS> = Vec::new();
+    for action in actions
+        .split(';')
+        .map(str::trim)
+        .filter(|action| !action.is_empty())
+    {
+        let (line, period) = action
+            .rsplit_once(" every ")
+            .ok_or(Error::Parse("actions: <command> every <duration>".into_truncate()))?;
+        let period_ms = parse_duration_ms(period.trim())
+            .filter(|ms| *ms > 0)
+            .ok_or(Error::Parse("actions: every <duration>".into_truncate()))?;
+        let action = Action {
+            line: expand(line.trim())?,
+            period_ms,
+            runs: 0,
+            failures: 0,
+            next: now,
+        };
+        parsed
+            .push(action)
+            .map_err(|_| "too many actions (max 4)")?;
+    }
+    if parsed.is_empty() {
+        return Err(Error::MissingArg("actions".into_truncate()));
+    }
+
+    let soak = Soak {
+        actions:    parsed,
+        start:      now,
+        duration:   Duration::from_secs(duration_s as u64),
+        checkpoint: now,
+    };
+    with(|cs| SOAK.borrow_ref_mut(cs).replace(soak));
+
+    KV.set(DURATION_KEY, duration_s)?;
+    KV.set(PENDING_KEY, 0)?;
+    for key in [ELAPSED_KEY, RUNS_KEY, FAILURES_KEY] {
+        KV.set(key, 0)?;
+    }
+    for index in 0..MAX_ACTIONS {
+        KV.set(&failures_key(index), 0)?;
+    }
+    KV.set(STATE_KEY, State::Running as u32)?;
+    Ok(())
+}
+
+/// Stops the running soak, its statistics are saved. False if none was running.
+pub fn stop() -> Result<bool> {
+    match with(|cs| SOAK.borrow_ref_mut(cs).take()) {
+        Some(soak) => {
+            persist(&soak, State::Stopped)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+pub fn is_running() -> bool {
+    with(|cs| SOAK.borrow_ref(cs).is_some())
+}
+
+/// Copy of the actions of the running soak
+pub fn actions() -> Vec<Action, MAX_ACTIONS> {
+    with(|cs| {
+        SOAK.borrow_ref(cs)
+            .as_ref()
+            .map(|soak| soak.actions.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Runs the due actions, saves a checkpoint and ends the soak once its duration is over
+pub fn run_due(commands: &CommandList, context: &mut Context) {
+    for index in 0..MAX_ACTIONS {
+        let now = Instant::now();
+        let due = with(|cs| {
+            let mut soak = SOAK.borrow_ref_mut(cs);
+            let action = soak
+                .as_mut()?
+                .actions
+                .get_mut(index)
+                .filter(|action| action.next <= now)?;
+
+            let period = Duration::from_millis(action.period_ms as u64);
+            action.next = action.next.next_deadline(period, now);
+            action.runs += 1;
+            Some(action.line.clone())
+        });
+        let Some(line) = due
+        else {
+            continue;
+        };
+
+        print!("[soak] ");
+        if let Err(e) = commands.execute(&line, context) {
+            println!("[soak] Err: {e} ({line})");
+            with(|cs| {
+                if let Some(action) = SOAK
+                    .borrow_ref_mut(cs)
+                    .as_mut()
+                    .and_then(|soak| soak.actions.get_mut(index))
+                {
+                    action.failures += 1;
+                }
+            });
+            checkpoint(true);
+        }
+    }
+    checkpoint(false);
+}
+
+/// Prints the summary of the last soak, from flash
+pub fn print_report() {
+    let Some(state) = KV.get(STATE_KEY).and_then(State::from_u32)
+    else {
+        println!("No soak results");
+        return;
+    };
+
+    let state = match state {
+        State::Running if is_running() => "running",
+        State::Running => "interrupted by a reset",
+        State::Done => "done",
+        State::Stopped => "stopped",
+    };
+    let elapsed_s = KV.get(ELAPSED_KEY).unwrap_or(0);
+    let duration_s = KV.get(DURATION_KEY).unwrap_or(0);
+    println!("\n========= SOAK ============");
+    println!("State: {state}");
+    println!(
+        "Elapsed: {:.2}h of {:.2}h",
+        elapsed_s as f32 / 3600.0,
+        duration_s as f32 / 3600.0
+    );
+    println!(
+        "Runs: {} | failures: {}",
+        KV.get(RUNS_KEY).unwrap_or(0),
+        KV.get(FAILURES_KEY).unwrap_or(0)
+    );
+
+    let actions = actions();
+    for index in 0..MAX_ACTIONS {
+        let failures = KV.get(&failures_key(index)).unwrap_or(0);
+        match actions.get(index) {
+            Some(action) => println!(
+                "  {index}: {} every {}ms | runs: {} | failures: {}",
+                action.line, action.period_ms, action.runs, action.failures
+            ),
+            None if failures > 0 => println!("  {index}: failures: {failures}"),
+            None => {}
+        }
+    }
+    println!();
+}
+
+/// Prints the summary once after a soak ended or was cut, for the next connection
+pub fn print_pending_report() {
+    if KV.get(PENDING_KEY) == Some(1) && !is_running() {
+        print_report();
+        if let Err(e) = KV.set(PENDING_KEY, 0) {
+            warn!("Soak report: {e}");
+        }
+    }
+}
+
+/// Clears the statistics in flash, false while a soak is running
+pub fn clear() -> Result<bool> {
+    if is_running() {
+        return Ok(false);
+    }
+    KV.set(STATE_KEY, 0)?;
+    KV.set(PENDING_KEY, 0)?;
+    Ok(true)
+}
+
+/// Saves the statistics once due (or now if `force`), ends the soak once its duration is over
+fn checkpoint(force: bool) {
+    let due = with(|cs| {
+        let mut cell = SOAK.borrow_ref_mut(cs);
+        let soak = cell.as_mut()?;
+        let now = Instant::now();
+
+        if now - soak.start >= soak.duration {
+            return cell.take().map(|soak| (soak, State::Done));
+        }
+        if force || now - soak.checkpoint >= Duration::from_secs(CHECKPOINT_S) {
+            soak.checkpoint = now;
+            return Some((soak.clone(), State::Running));
+        }
+        None
+    });
+
+    if let Some((soak, state)) = due {
+        if state == State::Done {
+            println!("[soak] Done, {} runs, {} failures", soak.runs(), soak.failures());
+        }
+        if let Err(e) = persist(&soak, state) {
+            warn!("Soak statistics not saved: {e}");
+        }
+    }
+}
+
+/// Writes the statistics, unchanged values are skipped by the KV store
+fn persist(soak: &Soak, state: State) -> kv::Result<()> {
+    let elapsed_s = (Instant::now() - soak.start).as_millis() / 1000;
+    KV.set(ELAPSED_KEY, elapsed_s.min(soak.duration.as_millis() / 1000) as u32)?;
+    KV.set(RUNS_KEY, soak.runs())?;
+    KV.set(FAILURES_KEY, soak.failures())?;
+    for (index, action) in soak.actions.iter().enumerate() {
+        KV.set(&failures_key(index), action.failures)?;
+    }
+    KV.set(STATE_KEY, state as u32)?;
+    KV.set(PENDING_KEY, (state != State::Running) as u32)
+}
+
+/// Short pin actions to their command line: "toggle OUT_A" to "pin alias=OUT_A toggle"
+fn expand(line: &str) -> Result<Line> {
+    let mut words = line.split_ascii_whitespace();
+    let mut expanded = Line::new();
+    let written = match (words.next(), words.next(), words.next()) {
+        (Some(level @ ("toggle" | "high" | "low")), Some(pin), None) => {
+            write!(expanded, "pin alias={pin} {level}")
+        }
+        _ => expanded.push_str(line).map_err(|_| core::fmt::Error),
+    };
+    written.map_err(|_| Error::CommandTooLong(LINE_BUFFER_LENGTH))?;
+    Ok(expanded)
+}
+
+/// "soak.e<N>", failures of an action
+fn failures_key(index: usize) -> kv::Key {
+    let mut key = kv::Key::new();
+    let _ = write!(key, "soak.e{index}");
+    key
+}
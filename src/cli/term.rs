@@ -0,0 +1,148 @@
+//! Terminal Size
+//!
+//! The terminal width is queried with the ANSI cursor position report (DSR) when the serial
+//! monitor connects. Terminals that don't answer fall back to DEFAULT_WIDTH, and the
+//! "term.width" setting (`set width=..`) replaces the detection entirely.
+//!
+//! Help texts and tables use the width to wrap their lines instead of letting the terminal
+//! break them mid word.
+//!
+//! Example:
+//! ```rust
+//! TERM.detect();
+//! TERM.print_wrapped("PWM 0: ON | freq: 50.0hz | top: 49999 | div: 50+0/16");
+//! ```
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::Result;
+use crate::prelude::*;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub static TERM: Term = Term {
+    detected: AtomicU16::new(0),
+};
+
+pub const DEFAULT_WIDTH: u16 = 80;
+pub const MIN_WIDTH: u16 = 20;
+const WIDTH_SETTING: &str = "term.width";
+
+// Save cursor, move to the far right, report the position, restore the cursor
+const CURSOR_QUERY: &[u8] = b"\x1b7\x1b[999C\x1b[6n\x1b8";
+const QUERY_TIMEOUT_US: u64 = 100_000;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Term
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Term {
+    /// Width reported by the terminal, 0 if unknown
+    detected: AtomicU16,
+}
+
+impl Term {
+    /// Width used for rendering: setting, then detected, then DEFAULT_WIDTH
+    pub fn width(&self) -> usize {
+        let width = self
+            .fixed_width()
+            .or(match self.detected.load(Ordering::Relaxed) {
+                0 => None,
+                width => Some(width),
+            })
+            .unwrap_or(DEFAULT_WIDTH);
+
+        width.max(MIN_WIDTH) as usize
+    }
+
+    /// Width stored in the settings, if any
+    pub fn fixed_width(&self) -> Option<u16> {
+        SETTINGS.get_parsed(WIDTH_SETTING)
+    }
+
+    /// Stores a fixed width, None restores the detection
+    pub fn set_fixed_width(&self, width: Option<u16>) -> Result<()> {
+        match width {
+            Some(width) => SETTINGS.set(WIDTH_SETTING, width.max(MIN_WIDTH))?,
+            None => {
+                SETTINGS.remove(WIDTH_SETTING);
+            }
+        }
+        SETTINGS.save()?;
+        Ok(())
+    }
+
+    /// Queries the terminal for its width, skipped when a fixed width is set
+    pub fn detect(&self) -> Option<u16> {
+        if self.fixed_width().is_some() {
+            return None;
+        }
+
+        // Reply: ESC [ rows ; cols R
+        let mut reply = [0u8; 16];
+        let len = SERIAL
+            .query(CURSOR_QUERY, &mut reply, b'R', QUERY_TIMEOUT_US)
+            .ok()?;
+
+        let reply = core::str::from_utf8(&reply[..len]).ok()?;
+        let (_, cols) = reply.rsplit_once(';')?;
+        let width = cols.parse::<u16>().ok().filter(|w| *w >= MIN_WIDTH)?;
+
+        self.detected.store(width, Ordering::Relaxed);
+        Some(width)
+    }
+
+    /// Prints the text wrapping the lines longer than the terminal at spaces
+    /// Continuation lines keep the indentation of the original line
+    pub fn print_wrapped(&self, text: &str) {
+        let width = self.width();
+
+        for line in text.lines() {
+            let indent = line.len() - line.trim_start().len();
+            let mut rest = line;
+            let mut first = true;
+
+            loop {
+                let pad = if first { 0 } else { (indent + 2).min(width / 2) };
+                let room = width - pad;
+
+                if rest.chars().count() <= room {
+                    println!("{:pad$}{}", "", rest);
+                    break;
+                }
+
+                let (head, tail) = split_at_space(rest, room);
+                println!("{:pad$}{}", "", head);
+                if tail.is_empty() {
+                    break;
+                }
+                rest = tail;
+                first = false;
+            }
+        }
+    }
+
+    /// Number of hex dump columns fitting the terminal, 16 or 8
+    pub fn hex_columns(&self) -> usize {
+        // "xx: " + 16 * "xx "
+        if self.width() >= 4 + 16 * 3 { 16 } else { 8 }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Splits before the last space fitting in `room` chars, words longer than the line are cut
+fn split_at_space(line: &str, room: usize) -> (&str, &str) {
+    let cut = line.char_indices().nth(room).map_or(line.len(), |(i, _)| i);
+
+    match line[..cut].rfind(' ') {
+        Some(space) if !line[..space].trim().is_empty() => {
+            (line[..space].trim_end(), line[space..].trim_start())
+        }
+        _ => (&line[..cut], &line[cut..]),
+    }
+}
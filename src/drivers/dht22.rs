@@ -6,10 +6,18 @@
 //! Reference:
 //! https://cdn-shop.adafruit.com/datasheets/Digital+humidity+and+temperature+sensor+AM2302.pdf
 
-use core::fmt::Display;
+use core::fmt::{self, Display};
 
-use rp2040_hal::gpio;
-use rp2040_hal::timer::{Instant, Timer};
+use crate::cli::{self, ArgList, Argument};
+use crate::hal::gpio;
+use crate::hal::timer::Timer;
+use crate::println;
+use crate::system::serial_io::SERIAL;
+use crate::utils::filter::SampleFilter;
+use crate::utils::time::Instant;
+use crate::utils::units::Celsius;
+
+use super::Driver;
 
 use critical_section;
 use embedded_hal::digital::{InputPin, OutputPin};
@@ -20,6 +28,8 @@ use embedded_hal_0_2::blocking::delay::DelayUs;
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
 const TIMEOUT: u64 = 2 * 1000; // ms 
+/// The sensor needs 2s between reads
+const READ_INTERVAL: u32 = 2_000; // ms
 const HIGH: u8 = 1;
 const LOW: u8 = 0;
 
@@ -62,6 +72,8 @@ pub struct DHT22 {
     pin:        Output,
     timer:      Timer,
     start_time: Instant,
+    /// Last filtered (humidity, temperature), listed by `drivers`
+    last:       Option<(f32, f32)>,
 }
 
 impl DHT22 {
@@ -71,22 +83,20 @@ impl DHT22 {
         let mut pin = pin.into_output();
         pin.set_high();
 
-        let start_time = timer.get_counter();
+        let start_time = Instant::now();
 
-        Self { pin, timer, start_time }
+        Self {
+            pin,
+            timer,
+            start_time,
+            last: None,
+        }
     }
 
     #[inline]
     /// Checkes for time out
     fn not_timed_out(&self) -> Result<()> {
-        let elapsed = self
-            .timer
-            .get_counter()
-            .checked_duration_since(self.start_time)
-            .unwrap()
-            .to_millis();
-
-        if elapsed > TIMEOUT {
+        if self.start_time.elapsed().as_millis() > TIMEOUT {
             return Err(DhtError::Timeout);
         }
         Ok(())
@@ -110,7 +120,7 @@ impl DHT22 {
     /// Returns Ok((humidity, temperature)) or Err(DhtError)
     pub fn read(&mut self) -> Result<(f32, f32)> {
         //
-        self.start_time = self.timer.get_counter();
+        self.start_time = Instant::now();
 
         // DTH22 sends a 16b + 16b + 8b package
         const PACKET_SIZE: usize = 40;
@@ -204,6 +214,66 @@ impl DHT22 {
     }
 }
 
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Driver
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+impl Driver for DHT22 {
+    fn name(&self) -> &'static str {
+        "dht22"
+    }
+
+    fn desc(&self) -> &'static str {
+        "DHT22 Temperature and Humidity Sensor"
+    }
+
+    fn help(&self) -> &'static str {
+        "[filter=none(str)] [help]
+    filter : median3-median9 (one read every 2s), rate<max step> or none"
+    }
+
+    fn status(&self, f: &mut dyn fmt::Write) -> fmt::Result {
+        match self.last {
+            Some((h, t)) => write!(f, "{h:.1} %RH, {}", Celsius(t)),
+            None => f.write_str("not read"),
+        }
+    }
+
+    fn command(&mut self, args: &[Argument]) -> cli::Result<()> {
+        let mut humidity_filter: SampleFilter = match args.contains_param("filter") {
+            true => args.get_parsed_param("filter")?,
+            false => SampleFilter::None,
+        };
+        let mut temperature_filter = humidity_filter;
+
+        println!("Reading DHT22 Sensor\n");
+
+        let (mut humidity, mut temperature) = (0.0, 0.0);
+        SERIAL.clear_interrupt_cmd();
+        for i in 0..humidity_filter.window() {
+            if SERIAL.interrupt_cmd_triggered() {
+                return Err(cli::Error::Interrupted);
+            }
+            if i > 0 {
+                self.timer.delay_us(READ_INTERVAL * 1000);
+            }
+
+            let (h, t) = self.read().map_err(|e| {
+                println!("Err: {e}");
+                cli::Error::CriticalFail
+            })?;
+            humidity = humidity_filter.apply(h);
+            temperature = temperature_filter.apply(t);
+        }
+        self.last = Some((humidity, temperature));
+
+        println!("Humidity   : {:.1} %RH", humidity);
+        println!("Temperature: {}\n", Celsius(temperature));
+
+        Ok(())
+    }
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Traits
 // —————————————————————————————————————————————————————————————————————————————————————————————————
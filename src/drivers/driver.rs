@@ -0,0 +1,136 @@
+//! Driver Registry
+//!
+//! Common interface of the external hardware drivers, so a new sensor doesn't need an entry in
+//! the core command list: a driver implements `Driver` and registers itself on `device.drivers`
+//! in `Device::new`. `drivers` lists them with their status, `drv <name> <args>` routes the
+//! arguments to the driver's handler.
+//!
+//! Drivers own their pins, they live in a `static` (`cortex_m::singleton!`) as there is no heap
+//! by default. `init` runs once on registration, a driver whose init failed is listed but its
+//! handler is refused.
+//!
+//! Example:
+//! ```rust
+//! impl Driver for Bme280 {
+//!     fn name(&self) -> &'static str { "bme280" }
+//!     fn command(&mut self, args: &[Argument]) -> cli::Result<()> { ... }
+//!     ...
+//! }
+//!
+//! drivers.register(cortex_m::singleton!(: Bme280 = Bme280::new(i2c)).unwrap());
+//! device.drivers.run("bme280", &args)?;
+//! ```
+
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::cli::{self, ArgList, Argument};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_DRIVERS: usize = 8;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Driver
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub trait Driver {
+    /// Name given to `drv`, lowercase without spaces
+    fn name(&self) -> &'static str;
+
+    /// One line description listed by `drivers`
+    fn desc(&self) -> &'static str;
+
+    /// Arguments of the handler, printed by `drv <name> help`
+    fn help(&self) -> &'static str;
+
+    /// Probes or configures the hardware, once on registration
+    fn init(&mut self) -> cli::Result<()> {
+        Ok(())
+    }
+
+    /// One line state listed by `drivers`, ex: the last reading
+    fn status(&self, f: &mut dyn fmt::Write) -> fmt::Result {
+        f.write_str("ready")
+    }
+
+    /// Handler of `drv <name> <args>`, the name is not part of the arguments
+    fn command(&mut self, args: &[Argument]) -> cli::Result<()>;
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Drivers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Entry {
+    driver: &'static mut dyn Driver,
+    /// Result of `init`
+    init:   cli::Result<()>,
+}
+
+#[derive(Default)]
+pub struct Drivers {
+    entries: Vec<Entry, MAX_DRIVERS>,
+}
+
+impl Drivers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Initializes and adds a driver, refused if the registry is full or the name is taken
+    pub fn register(&mut self, driver: &'static mut dyn Driver) {
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.driver.name() == driver.name())
+        {
+            crate::error!("Driver {} already registered", driver.name());
+            return;
+        }
+
+        let init = driver.init();
+        if let Err(e) = &init {
+            crate::warn!("Driver {}: {e}", driver.name());
+        }
+        if let Err(entry) = self.entries.push(Entry { driver, init }) {
+            let name = entry.driver.name();
+            crate::error!("Driver list full (max {MAX_DRIVERS}), {name} not registered");
+        }
+    }
+
+    /// Runs the handler of a driver, or prints its help
+    pub fn run(&mut self, name: &str, args: &[Argument]) -> cli::Result<()> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.driver.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| cli::Error::from("unknown driver, see drivers"))?;
+
+        if args.contains_param("help") {
+            let driver = &entry.driver;
+            crate::println!("{} - {}\n{}", driver.name(), driver.desc(), driver.help());
+            return Ok(());
+        }
+        entry.init.clone()?;
+        entry.driver.command(args)
+    }
+
+    /// Calls `f` with every driver and the error of its init, if it failed
+    pub fn for_each(&self, mut f: impl FnMut(&dyn Driver, Option<&cli::Error>)) {
+        for entry in self.entries.iter() {
+            f(&*entry.driver, entry.init.as_ref().err());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
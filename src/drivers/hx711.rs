@@ -0,0 +1,215 @@
+//! HX711 24-bit load cell amplifier driver for the RP2040 microcontroller.
+//!
+//! Bit-banged two wire interface: the data line (DOUT) goes low when a conversion is ready,
+//! and the 24 bit result is clocked out MSB first on PD_SCK. Extra clock pulses select the
+//! channel and gain of the next conversion.
+//!
+//! Reference:
+//! https://cdn.sparkfun.com/datasheets/Sensors/ForceFlex/hx711_english.pdf
+
+use crate::hal::timer::Timer;
+
+use critical_section;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_0_2::blocking::delay::DelayUs;
+use thiserror::Error;
+
+use crate::system::gpios::{InputType, OutputType};
+use crate::utils::time::Instant;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const TIMEOUT: u64 = 500; // ms - conversions take 100ms at 10SPS
+const CLOCK_US: u32 = 1; // PD_SCK high/low time
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("timeout waiting for conversion")]
+    Timeout,
+
+    #[error("not calibrated")]
+    NotCalibrated,
+
+    #[error("invalid calibration reading")]
+    InvalidCalibration,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Gain
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Channel and gain selection, applied from the next conversion
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Gain {
+    A128,
+    B32,
+    A64,
+}
+
+impl Gain {
+    /// Clock pulses after the 24 data bits
+    fn extra_pulses(&self) -> u8 {
+        match self {
+            Gain::A128 => 1,
+            Gain::B32 => 2,
+            Gain::A64 => 3,
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              HX711
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct HX711 {
+    sck:    OutputType,
+    dout:   InputType,
+    timer:  Timer,
+    gain:   Gain,
+    offset: i32,
+    scale:  f32,
+}
+
+impl HX711 {
+    /// Creates a new HX711 instance.
+    /// Requires the PD_SCK output pin, the DOUT input pin and a copy of the mcu timer.
+    pub fn new(mut sck: OutputType, dout: InputType, timer: Timer) -> Self {
+        sck.set_low().unwrap(); // Holding PD_SCK high for over 60us powers the chip down
+
+        Self {
+            sck,
+            dout,
+            timer,
+            gain: Gain::A128,
+            offset: 0,
+            scale: 0.0,
+        }
+    }
+
+    /// Returns true if a conversion is ready to be read
+    pub fn is_ready(&mut self) -> bool {
+        self.dout.is_low().unwrap()
+    }
+
+    /// Sets the gain used from the next conversion
+    pub fn set_gain(&mut self, gain: Gain) {
+        self.gain = gain;
+    }
+
+    pub fn gain(&self) -> Gain {
+        self.gain
+    }
+
+    /// Sets the tare offset (raw) and the scale (raw counts per unit)
+    pub fn set_calibration(&mut self, offset: i32, scale: f32) {
+        self.offset = offset;
+        self.scale = scale;
+    }
+
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Reads a single raw conversion as a signed 24 bit value
+    pub fn read_raw(&mut self) -> Result<i32> {
+        let start_time = Instant::now();
+
+        while !self.is_ready() {
+            if start_time.elapsed().as_millis() > TIMEOUT {
+                return Err(Error::Timeout);
+            }
+        }
+
+        // Critical Section Interrupt Free - a long PD_SCK high pulse would power down the chip
+        let value = critical_section::with(|_| {
+            let mut value: u32 = 0;
+
+            for _ in 0..24 {
+                self.pulse();
+                value = (value << 1) | self.dout.is_high().unwrap() as u32;
+            }
+
+            // Selecting gain for the next conversion
+            for _ in 0..self.gain.extra_pulses() {
+                self.pulse();
+            }
+
+            value
+        });
+
+        // Sign extending the 24 bit two's complement value
+        Ok(((value << 8) as i32) >> 8)
+    }
+
+    /// Reads the average of `samples` raw conversions
+    pub fn read_average(&mut self, samples: u8) -> Result<i32> {
+        let samples = samples.max(1);
+        let mut sum: i64 = 0;
+
+        for _ in 0..samples {
+            sum += self.read_raw()? as i64;
+        }
+
+        Ok((sum / samples as i64) as i32)
+    }
+
+    /// Reads the averaged value in calibrated units
+    pub fn read_units(&mut self, samples: u8) -> Result<f32> {
+        if self.scale == 0.0 {
+            return Err(Error::NotCalibrated);
+        }
+
+        let raw = self.read_average(samples)?;
+        Ok((raw - self.offset) as f32 / self.scale)
+    }
+
+    /// Sets the offset to the current averaged reading. Returns the new offset.
+    pub fn tare(&mut self, samples: u8) -> Result<i32> {
+        self.offset = self.read_average(samples)?;
+        Ok(self.offset)
+    }
+
+    /// Computes the scale from a known load placed on the tared cell. Returns the new scale.
+    pub fn calibrate(&mut self, known: f32, samples: u8) -> Result<f32> {
+        let raw = self.read_average(samples)?;
+        let scale = (raw - self.offset) as f32 / known;
+
+        if known == 0.0 || scale == 0.0 || !scale.is_finite() {
+            return Err(Error::InvalidCalibration);
+        }
+
+        self.scale = scale;
+        Ok(scale)
+    }
+
+    /// Powers the chip down by holding PD_SCK high
+    pub fn power_down(&mut self) {
+        self.sck.set_low().unwrap();
+        self.sck.set_high().unwrap();
+    }
+
+    /// Wakes the chip up. The first conversion after power up uses channel A, gain 128.
+    pub fn power_up(&mut self) {
+        self.sck.set_low().unwrap();
+    }
+
+    #[inline]
+    fn pulse(&mut self) {
+        self.sck.set_high().unwrap();
+        self.timer.delay_us(CLOCK_US);
+        self.sck.set_low().unwrap();
+        self.timer.delay_us(CLOCK_US);
+    }
+}
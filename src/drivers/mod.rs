@@ -1 +1,5 @@
 pub mod dht22;
+pub mod driver;
+pub mod hx711;
+
+pub use driver::{Driver, Drivers};
@@ -0,0 +1,85 @@
+//! HAL Facade
+//!
+//! Single point where the chip HAL is selected. The system wrappers (device, gpio, pwm, adc,
+//! timer, ..) import `crate::hal` instead of the chip crate, so they only depend on the API
+//! shared by the rp-hal family and on the chip constants below.
+//!
+//! Registers the HAL doesn't cover (atomic SIO writes, PWM counters, DMA, clock status, ..) are
+//! reached through `hal::regs`, never through `pac::X::ptr()` in the modules: the accessors list
+//! every raw peripheral the firmware pokes.
+//!
+//! Example:
+//! ```rust
+//! use crate::hal;
+//! use hal::gpio::{DynPinId, Pin};
+//!
+//! let pins: [Option<u8>; hal::NUM_GPIOS] = [None; hal::NUM_GPIOS];
+//! ```
+
+pub use rp2040_hal::*;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Chip
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const CHIP_NAME: &str = "RP2040";
+
+/// User GPIOs of bank 0
+pub const NUM_GPIOS: usize = 30;
+
+/// First GPIO with an ADC input, channel 0
+pub const ADC_FIRST_GPIO: u8 = 26;
+pub const NUM_ADC_PINS: u8 = 4;
+
+pub const NUM_PWM_SLICES: u8 = 8;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Registers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Raw register blocks of the peripherals used outside of the HAL.
+///
+/// Safety: the blocks are shared with the HAL drivers. Callers only read, write registers they
+/// own, or use the atomic set/clr/xor aliases, as noted at each call site.
+pub mod regs {
+    use super::pac;
+
+    macro_rules! registers {
+        ($($name:ident: $peripheral:ident),* $(,)?) => {
+            $(
+                #[inline(always)]
+                pub unsafe fn $name() -> &'static <pac::$peripheral as core::ops::Deref>::Target {
+                    unsafe { &*pac::$peripheral::ptr() }
+                }
+            )*
+        };
+    }
+
+    registers! {
+        adc: ADC,
+        clocks: CLOCKS,
+        dma: DMA,
+        io_bank0: IO_BANK0,
+        pads_bank0: PADS_BANK0,
+        pll_sys: PLL_SYS,
+        pll_usb: PLL_USB,
+        pwm: PWM,
+        rosc: ROSC,
+        sio: SIO,
+        timer: TIMER,
+        uart0: UART0,
+        uart1: UART1,
+        vreg_and_chip_reset: VREG_AND_CHIP_RESET,
+        watchdog: WATCHDOG,
+        xosc: XOSC,
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Bootloader
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Second stage bootloader for the Pico W25Q080 compatible flash
+#[unsafe(link_section = ".boot2")]
+#[used]
+pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
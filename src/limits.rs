@@ -0,0 +1,133 @@
+//! Limits
+//!
+//! Capacities of the heapless buffers, tuned in one place. Most of them are statics, their sum is
+//! the RAM left to the stack and the heap. A cargo feature selects the preset:
+//! - `limits-tiny`: short command lines and small capture buffers, for builds adding their own
+//!   RAM heavy features
+//! - default: no feature
+//! - `limits-large`: long captures and logs, at the cost of ~40KiB more RAM
+//!
+//! `cargo build --features "limits-tiny"`. The fixed limits are set by the hardware or the command
+//! count, `mem` prints the preset in use.
+//!
+//! Example:
+//! ```rust
+//! let mut line: String<LINE_BUFFER_LENGTH> = String::new();
+//! ```
+
+use crate::hal;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Preset
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[cfg(all(feature = "limits-tiny", feature = "limits-large"))]
+compile_error!("features \"limits-tiny\" and \"limits-large\" are exclusive");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    Tiny,
+    Default,
+    Large,
+}
+
+#[cfg(feature = "limits-tiny")]
+pub const PRESET: Preset = Preset::Tiny;
+
+#[cfg(feature = "limits-large")]
+pub const PRESET: Preset = Preset::Large;
+
+#[cfg(not(any(feature = "limits-tiny", feature = "limits-large")))]
+pub const PRESET: Preset = Preset::Default;
+
+impl Preset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Tiny => "tiny",
+            Preset::Default => "default",
+            Preset::Large => "large",
+        }
+    }
+}
+
+/// Capacity of the preset in use
+const fn pick(tiny: usize, default: usize, large: usize) -> usize {
+    match PRESET {
+        Preset::Tiny => tiny,
+        Preset::Default => default,
+        Preset::Large => large,
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               CLI
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Arguments are slices into the line buffer: the line length is the main RAM cost, the
+// argument limits only bound the lengths accepted.
+
+/// Input line, received from the serial and copied once for the parser. Also the length of the
+/// job and soak lines and of the secure frames.
+pub const LINE_BUFFER_LENGTH: usize = pick(128, 256, 512);
+
+/// Registered commands, every built-in command has to fit
+pub const MAX_CMDS: usize = pick(96, 96, 128);
+
+/// Arguments of a command line, flags included
+pub const MAX_ARGS: usize = pick(12, 16, 24);
+
+pub const MAX_PARAM_NAME_LENGTH: usize = 32; // fits DEVICE.REGISTER paths
+pub const MAX_VALUE_LENGTH: usize = 128;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Serial
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Output queued while the host is slow to read
+pub const TX_QUEUE_SIZE: usize = pick(256, 512, 1024);
+
+/// UART bridge bytes waiting for the USB side
+pub const BRIDGE_FIFO_SIZE: usize = pick(256, 512, 1024);
+
+/// Output of a command captured by the print macros (jobs, secure frames)
+pub const CAPTURE_SIZE: usize = pick(512, 2048, 4096);
+
+/// Stack buffer of the `try_print` macros
+pub const TRY_PRINT_SIZE: usize = 128;
+
+/// Receive ring of each UART, filled by its interrupt
+pub const UART_RX_SIZE: usize = pick(256, 1024, 4096);
+
+/// Events between the cores, a power of 2
+pub const CORE_QUEUE_DEPTH: usize = pick(4, 8, 16);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Capture Buffers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Logic analyzer samples, 4 bytes each
+pub const MAX_LOGIC_SAMPLES: usize = pick(256, 1024, 4096);
+
+/// Event log entries, 16 bytes each
+pub const MAX_LOG_EVENTS: usize = pick(128, 512, 1024);
+
+/// Marker ring
+pub const MAX_MARKERS: usize = pick(32, 128, 256);
+
+/// Bus trace ring
+pub const MAX_TRACE_RECORDS: usize = pick(16, 64, 128);
+
+/// Playback timeline events
+pub const MAX_PLAYBACK_EVENTS: usize = pick(64, 256, 512);
+
+/// Servo choreography keyframes
+pub const MAX_KEYFRAMES: usize = pick(32, 128, 256);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Fixed
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Pin definitions, one per GPIO
+pub const PINOUT_CAPACITY: usize = hal::NUM_GPIOS;
+
+/// PWM channels, 8 slices of 2
+pub const MAX_PWM_PINS: usize = 16;
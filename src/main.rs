@@ -3,13 +3,15 @@
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 
+mod hal;
 mod system;
 mod utils;
 
 mod cli;
 mod drivers;
+mod limits;
 mod main_core1;
 mod pin_config;
 mod prelude;
@@ -31,9 +33,15 @@ extern crate panic_probe;
 #[cfg(feature = "panic-usb")]
 extern crate rp2040_panic_usb_boot;
 
+// Handler in system::panic, prints over USB before resetting
 #[cfg(feature = "panic-persist")]
 extern crate panic_persist;
 
+// ————————————————————————————————————————— Heap feature —————————————————————————————————————————
+// Global allocator in system::heap
+#[cfg(feature = "heap")]
+extern crate alloc;
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Globals
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -44,17 +52,25 @@ const RUN_STANDALONE: bool = false;
 //                                              Main
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
-#[rp2040_hal::entry]
+#[cfg_attr(not(test), hal::entry)]
 fn main() -> ! {
     //
 
     info!("Alive! {} : v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
+    #[cfg(feature = "heap")]
+    system::heap::init();
+
     let mut device = system::device::Device::new();
 
     if !RUN_STANDALONE {
         let command_list = cli::commands::build();
         let mut program = program::Program::new();
+
+        #[cfg(feature = "async")]
+        program.run_async(&mut device, command_list);
+
+        #[cfg(not(feature = "async"))]
         program.run(&mut device, command_list);
     }
 
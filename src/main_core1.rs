@@ -1,19 +1,32 @@
 //! Core 1 Main Loop
 //!
-//! Spawned by Device
+//! Started by Device at boot, or on demand by the first command sending it an event, following
+//! the "core1" setting (`set core1=boot|lazy|off`, applied at the next boot).
+//!
+//! The stack size is fixed at build time, in KiB, by the `CORE1_STACK_KB` environment variable
+//! (8 by default). 0 leaves core1 out along with its stack, the RAM goes to core0.
+//! E.g. CORE1_STACK_KB=4 cargo build
 
 #![allow(unused_mut)]
 
 use core::cell::RefCell;
+use core::fmt;
+use core::sync::atomic::AtomicBool;
 
+use crate::limits::CORE_QUEUE_DEPTH;
 use crate::prelude::*;
+use crate::system::comparator::{self, COMPARATOR};
+use crate::system::{cpu_load, flash, stack_guard};
 use critical_section::{Mutex, with};
-use hal::multicore::Stack;
+use hal::multicore::{Multicore, StackAllocation};
+use portable_atomic::AtomicU8;
+use thiserror::Error;
 
-use rp2040_hal as hal;
+use crate::hal;
 //
 use hal::fugit::MicrosDurationU32;
 use hal::pac::interrupt;
+use hal::sio::SioFifo;
 use hal::timer::Alarm;
 use hal::{gpio, pac, sio, timer};
 
@@ -23,11 +36,34 @@ use heapless::mpmc::Queue;
 //                                             Globals
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-// Memory Stack for core 1
-pub static CORE1_STACK: Stack<2048> = Stack::new();
+pub const MODE_KEY: &str = "core1";
+
+/// Core1 stack in KiB, `CORE1_STACK_KB` at build time, 0 without core1
+pub const CORE1_STACK_KB: usize = match option_env!("CORE1_STACK_KB") {
+    Some(kb) => parse_kb(kb),
+    None => 8,
+};
+
+// Memory Stack for core 1, in words. Its lowest words hold the stack guard canary.
+const CORE1_STACK_WORDS: usize = CORE1_STACK_KB * 1024 / size_of::<usize>();
+static mut CORE1_STACK: [usize; CORE1_STACK_WORDS] = [0; CORE1_STACK_WORDS];
+static CORE1_STACK_TAKEN: portable_atomic::AtomicBool = portable_atomic::AtomicBool::new(false);
+
+// Core1 states, `State as u8`
+static CORE1_STATE: AtomicU8 = AtomicU8::new(match CORE1_STACK_KB {
+    0 => State::Disabled as u8,
+    _ => State::Stopped as u8,
+});
+
+// Set while an event runs, see the Core1Idle command requirement
+pub static CORE1_BUSY: AtomicBool = AtomicBool::new(false);
 
 // Multicore MPMC Queue
-pub static CORE1_QUEUE: Queue<EventCore1, 8> = Queue::new();
+// heapless deprecates mpmc for not being lock-free: an enqueue or dequeue preempted midway can make
+// the other side fail until it resumes. A full queue is reported as QueueFull and the core1 loop
+// polls again on the next pass, so a failed operation is retried, not lost.
+#[expect(deprecated)]
+pub static CORE1_QUEUE: Queue<EventCore1, CORE_QUEUE_DEPTH> = Queue::new();
 
 // Interrupts
 static ALARM_1: Mutex<RefCell<Option<timer::Alarm1>>> = Mutex::new(RefCell::new(None));
@@ -53,7 +89,11 @@ pub fn main_core1(timer: timer::Timer) -> ! {
     let mut test_output_pin: OutputType = CONFIG.take_pin_by_alias("C1_OUT_A").unwrap();
 
     // Unsafe practice since we know that core0 also uses gpio25(LED)
-    let mut led = pins.gpio25.into_push_pull_output();
+    // Boards without a GPIO LED (Pico W) use gpio25 for the wireless chip
+    let mut led = CONFIG
+        .get_gpio("LED")
+        .is_ok()
+        .then(|| pins.gpio25.into_push_pull_output());
 
     info!("Core 1 >> Initialised");
 
@@ -62,17 +102,88 @@ pub fn main_core1(timer: timer::Timer) -> ! {
     loop {
         // ————————————————————————————————————————— Events ————————————————————————————————————————
 
+        stack_guard::heartbeat();
+
+        // Parking in RAM while core0 writes to flash
+        if flash::core1_lockout_requested() {
+            flash::core1_lockout();
+        }
+
         while let Some(event) = CORE1_QUEUE.dequeue() {
+            CORE1_BUSY.store(true, Ordering::Relaxed);
             match event {
                 EventCore1::Blink { times, interval } => {
-                    blink_led(&mut led, &mut delay, times, interval);
+                    if let Some(led) = led.as_mut() {
+                        blink_led(led, &mut delay, times, interval);
+                    }
                 }
                 EventCore1::Sleep => {
-                    sleep();
+                    cpu_load::idle(sleep);
+                }
+                EventCore1::Comparator(setup) => {
+                    COMPARATOR.run(setup);
                 }
             }
+            CORE1_BUSY.store(false, Ordering::Relaxed);
+        }
+        cpu_load::idle(|| delay.delay_ms(10)); // Avoid spinning in a tight loop
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Core1 State
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// When core1 is started, the "core1" setting
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Started by Device at boot
+    #[default]
+    Boot,
+    /// Started by the first command sending it an event
+    Lazy,
+    /// Never started, commands needing core1 fail
+    Off,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "boot" => Ok(Mode::Boot),
+            "lazy" => Ok(Mode::Lazy),
+            "off" => Ok(Mode::Off),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Boot => f.write_str("boot"),
+            Mode::Lazy => f.write_str("lazy"),
+            Mode::Off => f.write_str("off"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    Stopped  = 0,
+    Running  = 1,
+    Disabled = 2,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Stopped => f.write_str("not started"),
+            State::Running => f.write_str("running"),
+            State::Disabled => f.write_str("disabled"),
         }
-        delay.delay_ms(10); // Avoid spinning in a tight loop
     }
 }
 
@@ -80,6 +191,94 @@ pub fn main_core1(timer: timer::Timer) -> ! {
 //                                         Free Functions
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
+/// Applies the mode at boot: starts core1, leaves it for the first event or disables it
+pub fn init(mode: Mode, sio_fifo: &mut SioFifo, timer: timer::Timer) {
+    match mode {
+        Mode::Boot => {
+            if let Err(e) = start(sio_fifo, timer) {
+                warn!("Core 1 >> {}", e);
+            }
+        }
+        Mode::Lazy => {}
+        Mode::Off => CORE1_STATE.store(State::Disabled as u8, Ordering::Relaxed),
+    }
+}
+
+/// Starts core1 if it is not running yet, called before sending it an event
+pub fn start(sio_fifo: &mut SioFifo, timer: timer::Timer) -> Result<()> {
+    match state() {
+        State::Running => return Ok(()),
+        State::Disabled => return Err(Error::Disabled),
+        State::Stopped => {}
+    }
+
+    let stack = take_stack().ok_or(Error::Disabled)?;
+    // Safety: PSM and PPB are only used here, to reset and launch core1
+    let mut pac = unsafe { pac::Peripherals::steal() };
+    let mut mc = Multicore::new(&mut pac.PSM, &mut pac.PPB, sio_fifo);
+    let cores = mc.cores();
+    cores[1]
+        .spawn(stack, move || main_core1(timer))
+        .map_err(|_| Error::Spawn)?;
+
+    CORE1_STATE.store(State::Running as u8, Ordering::Release);
+    Ok(())
+}
+
+/// Starts core1 when needed and queues the event
+pub fn send(event: EventCore1, sio_fifo: &mut SioFifo, timer: timer::Timer) -> Result<()> {
+    start(sio_fifo, timer)?;
+    CORE1_QUEUE.enqueue(event).map_err(|_| Error::QueueFull)
+}
+
+pub fn state() -> State {
+    match CORE1_STATE.load(Ordering::Acquire) {
+        1 => State::Running,
+        2 => State::Disabled,
+        _ => State::Stopped,
+    }
+}
+
+pub fn is_running() -> bool {
+    state() == State::Running
+}
+
+/// Mode saved in the settings, applied at boot
+pub fn mode() -> Mode {
+    SETTINGS.get_parsed(MODE_KEY).unwrap_or_default()
+}
+
+/// Stack handed to core1 once, armed with the stack guard canary
+fn take_stack() -> Option<StackAllocation> {
+    if CORE1_STACK_WORDS == 0 {
+        return None;
+    }
+    if CORE1_STACK_TAKEN.swap(true, Ordering::AcqRel) {
+        return None;
+    }
+
+    let start = &raw mut CORE1_STACK as *mut usize;
+    // Safety: taken once, the memory is only used as the core1 stack from now on
+    unsafe {
+        stack_guard::arm_core1(start);
+        Some(StackAllocation::from_raw_parts(start, start.add(CORE1_STACK_WORDS)))
+    }
+}
+
+/// Stack size from the build environment, whole KiB
+const fn parse_kb(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut kb = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "CORE1_STACK_KB: whole KiB expected");
+        kb = kb * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(kb <= 64, "CORE1_STACK_KB: 64 KiB at most");
+    kb
+}
+
 fn blink_led(led: &mut impl OutputPin, delay: &mut impl DelayMs<u32>, times: u16, interval: u16) {
     for _ in 0..times {
         let interval = interval as u32;
@@ -110,6 +309,7 @@ fn sleep() {
 pub enum EventCore1 {
     Blink { times: u16, interval: u16 },
     Sleep,
+    Comparator(comparator::Setup),
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -131,3 +331,21 @@ fn TIMER_IRQ_1() {
         };
     })
 }
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("core1 disabled, see set core1= and CORE1_STACK_KB")]
+    Disabled,
+
+    #[error("core1 failed to start")]
+    Spawn,
+
+    #[error("core1 queue full")]
+    QueueFull,
+}
@@ -1,6 +1,9 @@
 use crate::system::config::Def;
 use crate::system::config::Group::*;
 use crate::system::config::PinId::*;
+use crate::system::output_guard::Limit::*;
+use crate::system::output_guard::LimitDef;
+use crate::system::power_on::{PowerOn, PowerOnDef};
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                            Reference
@@ -19,8 +22,8 @@ use crate::system::config::PinId::*;
 // (PWM2 A)(UART1  TX)(I2C0 SDA)(SPI0  RX)   GP4  |  6 |o       o| 35 | ADC  VREF
 // (PWM2 B)(UART1  RX)(I2C0 SCL)(SPI0 CSn)   GP5  |  7 |o       o| 34 | GP28 A2    (SPI1  RX)(I2C0 SDA)(UART0  TX)(PWM6 A)
 //                                           GND  |  8 |o       o| 33 | ADC  GND
-// (PWM3 A)(UART1 CTS)(I2C1 SDA)(SPI1 SCK)   GP6  |  9 |o       o| 32 | GP27 A1    (SPI1  TX)(I2C1 SCL)(UART1 RTS)(PWM5 B)
-// (PWM3 B)(UART1 RTS)(I2C1 SCL)(SPI1  TX)   GP7  | 10 |o       o| 31 | GP26 A0    (SPI1 SCK)(I2C1 SDA)(UART1 CTS)(PWM5 A)
+// (PWM3 A)(UART1 CTS)(I2C1 SDA)(SPI0 SCK)   GP6  |  9 |o       o| 32 | GP27 A1    (SPI1  TX)(I2C1 SCL)(UART1 RTS)(PWM5 B)
+// (PWM3 B)(UART1 RTS)(I2C1 SCL)(SPI0  TX)   GP7  | 10 |o       o| 31 | GP26 A0    (SPI1 SCK)(I2C1 SDA)(UART1 CTS)(PWM5 A)
 // (PWM4 A)(UART1  TX)(I2C0 SDA)(SPI1  RX)   GP8  | 11 |o       o| 30 | RUN
 // (PWM4 B)(UART1  RX)(I2C0 SCL)(SPI1 CSn)   GP9  | 12 |o       o| 29 | GP22       (SPI0 SCK)(I2C1 SDA)(UART1 CTS)(PWM3 A)
 //                                           GND  | 13 |o       o| 28 | GND
@@ -48,13 +51,18 @@ use crate::system::config::PinId::*;
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                        Pin Configuration
 // —————————————————————————————————————————————————————————————————————————————————————————————————
+// Checked at build time: peripheral group pins must match the function named by their alias
+// (ex: I2C0_SDA on GP12, see the Valid Pins column), ADC pins are limited to GP26-29.
 
 #[rustfmt::skip]
 pub const PIN_DEFINITION: &[Def] = {
     &[
         //           Alias       GPIO            Group           Valid Pins
         // Core0 ————————————————————————————————————————————————————————————
-        
+
+        // Reserved - never taken or driven by any command
+        // Onboard pins (GP23-25) are added by the board profile, see Board Pins below
+
         // ADC
         Def { alias: "ADC0",     id: Gpio(26), group: Adc    }, // GP26
         Def { alias: "ADC1",     id: Gpio(27), group: Adc    }, // GP27
@@ -79,25 +87,30 @@ pub const PIN_DEFINITION: &[Def] = {
         Def { alias: "PWM7_A",   id: NA,       group: Pwm    }, // GP14
         Def { alias: "PWM7_B",   id: NA,       group: Pwm    }, // GP15
 
+        // Status RGB LED - 3-pin LED, the slices of the pins are dedicated to it
+        Def { alias: "RGB_R",    id: NA,       group: Pwm    },
+        Def { alias: "RGB_G",    id: NA,       group: Pwm    },
+        Def { alias: "RGB_B",    id: NA,       group: Pwm    },
+
         // I2C
-        Def { alias: "I2C0_SDA", id: Gpio(2),  group: I2c    }, // GP0, GP4, GP8, GP12, GP16, GP20, GP28
-        Def { alias: "I2C0_SCL", id: NA,       group: I2c    }, // GP1, GP5, GP9, GP13, GP17, GP21
+        Def { alias: "I2C0_SDA", id: Gpio(12), group: I2c    }, // GP0, GP4, GP8, GP12, GP16, GP20, GP28
+        Def { alias: "I2C0_SCL", id: Gpio(13), group: I2c    }, // GP1, GP5, GP9, GP13, GP17, GP21
         Def { alias: "I2C1_SDA", id: NA,       group: I2c    }, // GP2, GP6, GP10, GP14, GP18, GP22, GP26
         Def { alias: "I2C1_SCL", id: NA,       group: I2c    }, // GP3, GP7, GP11, GP15, GP19, GP27
 
         // SPI
         Def { alias: "SPI0_RX",  id: Gpio(4),  group: Spi    }, // GP0, GP4, GP16, GP20
-        Def { alias: "SPI0_TX",  id: NA,       group: Spi    }, // GP3, GP19
-        Def { alias: "SPI0_SCK", id: NA,       group: Spi    }, // GP2, GP18, GP22
+        Def { alias: "SPI0_TX",  id: NA,       group: Spi    }, // GP3, GP7, GP19
+        Def { alias: "SPI0_SCK", id: NA,       group: Spi    }, // GP2, GP6, GP18, GP22
         Def { alias: "SPI0_CSN", id: NA,       group: Spi    }, // GP1, GP5, GP17, GP21
 
         Def { alias: "SPI1_RX",  id: NA,       group: Spi    }, // GP8, GP12, GP28
-        Def { alias: "SPI1_TX",  id: NA,       group: Spi    }, // GP7, GP11, GP15, GP27
-        Def { alias: "SPI1_SCK", id: NA,       group: Spi    }, // GP6, GP10, GP14, GP26
+        Def { alias: "SPI1_TX",  id: NA,       group: Spi    }, // GP11, GP15, GP27
+        Def { alias: "SPI1_SCK", id: NA,       group: Spi    }, // GP10, GP14, GP26
         Def { alias: "SPI1_CSN", id: NA,       group: Spi    }, // GP9, GP13
 
-        // UART
-        Def { alias: "UART0_TX",  id: Gpio(5),  group: Uart  }, // GP0, GP12, GP16, GP28
+        // UART - CTS with RTS enable the hardware flow control
+        Def { alias: "UART0_TX",  id: NA,       group: Uart  }, // GP0, GP12, GP16, GP28
         Def { alias: "UART0_CTS", id: NA,       group: Uart  }, // GP2, GP14, GP18
         Def { alias: "UART0_RX",  id: NA,       group: Uart  }, // GP1, GP13, GP17
         Def { alias: "UART0_RTS", id: NA,       group: Uart  }, // GP3, GP15, GP19
@@ -111,16 +124,17 @@ pub const PIN_DEFINITION: &[Def] = {
         Def { alias: "IN_A",     id: Gpio(9),  group: Inputs  },
         Def { alias: "IN_B",     id: Gpio(20), group: Inputs  },
         Def { alias: "IN_C",     id: Gpio(22), group: Inputs  },
-        Def { alias: "BUTTON",   id: Gpio(23), group: Inputs  },
 
         // Ouputs 
         Def { alias: "OUT_A",    id: Gpio(0),  group: Outputs },
         Def { alias: "OUT_B",    id: Gpio(1),  group: Outputs },
         Def { alias: "OUT_C",    id: Gpio(3),  group: Outputs },
-        Def { alias: "LED",      id: Gpio(25), group: Outputs },
         
         // Other
         Def { alias: "DHT22",    id: Gpio(16), group: Other   },
+        Def { alias: "HX711_SCK", id: Gpio(18), group: Other  },
+        Def { alias: "HX711_DT",  id: Gpio(19), group: Other  },
+        Def { alias: "RGB_DATA",  id: NA,       group: Other  }, // Status WS2812 LED (PIO0)
 
         //           Alias       GPIO            Group           Valid Pins
         // Core1 ————————————————————————————————————————————————————————————
@@ -134,3 +148,62 @@ pub const PIN_DEFINITION: &[Def] = {
         
     ]
 };
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Board Pins
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Onboard pins of each board profile (system/board.rs), added to PIN_DEFINITION at boot
+
+#[rustfmt::skip]
+pub const PICO_PINS: &[Def] = &[
+    Def { alias: "PSU_MODE",   id: Gpio(23), group: Reserved }, // RT6150B P-Select
+    Def { alias: "VBUS_SENSE", id: Gpio(24), group: Inputs   },
+    Def { alias: "LED",        id: Gpio(25), group: Outputs  },
+];
+
+#[rustfmt::skip]
+pub const PICO_W_PINS: &[Def] = &[
+    Def { alias: "WL_ON",      id: Gpio(23), group: Reserved }, // CYW43 power on
+    Def { alias: "WL_D",       id: Gpio(24), group: Reserved }, // CYW43 SPI data / IRQ
+    Def { alias: "WL_CS",      id: Gpio(25), group: Reserved }, // CYW43 SPI chip select
+];
+
+#[rustfmt::skip]
+pub const WEACT_PINS: &[Def] = &[
+    Def { alias: "BUTTON",     id: Gpio(23), group: Inputs   }, // User key
+    Def { alias: "IN_D",       id: Gpio(24), group: Inputs   }, // Extra GPIO
+    Def { alias: "LED",        id: Gpio(25), group: Outputs  },
+];
+
+// A conflict with the pins of any board fails the build, ex: "IN_A: GP9 already used by OUT_D"
+crate::check_pins!(PIN_DEFINITION; PICO_PINS, PICO_W_PINS, WEACT_PINS);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Output Limits
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Protective limits checked on every write of the output, see system/output_guard.rs
+// Relay: max level changes per second, faster changes are refused and a runaway toggling trips
+//        the output, held low until "guard reset"
+// Motor: max duty change in % per second, larger steps of the PWM duty are ramped
+
+#[rustfmt::skip]
+pub const OUTPUT_LIMITS: &[LimitDef] = &[
+    //                Alias                 Limit
+    LimitDef { alias: "OUT_C",     limit: Relay { max_hz: 5 }     },
+    LimitDef { alias: "PWM3_B",    limit: Motor { max_rate: 100 } },
+];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                        Power-On Defaults
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// States applied at boot before any command runs, see system/power_on.rs
+// Outputs: Low or High | PWM pins: frequency, duty in % and slice enabled
+// A "poweron.<alias>" setting overrides them: setup poweron=OUT_A:high
+
+#[rustfmt::skip]
+pub const POWER_ON: &[PowerOnDef] = &[
+    //                  Alias                 State
+    PowerOnDef { alias: "OUT_A",     state: PowerOn::Low                                       },
+    PowerOnDef { alias: "OUT_B",     state: PowerOn::Low                                       },
+    PowerOnDef { alias: "PWM4_A",    state: PowerOn::Pwm { freq: 50, duty: 0, enabled: false } },
+];
@@ -19,17 +19,21 @@ pub use core::sync::atomic::Ordering;
 
 pub use crate::main_core1::{CORE1_QUEUE, EventCore1};
 pub use crate::system::adcs::{AdcConversion, TEMP_SENSE_CHN};
-pub use crate::system::config::CONFIG;
-pub use crate::system::config::Error as ConfigError;
+pub use crate::system::board::BOARD;
+pub use crate::system::config::{CONFIG, Error as ConfigError};
 pub use crate::system::delay::DELAY;
-pub use crate::system::device::*;
-pub use crate::system::device::{Device, TimerExt};
+pub use crate::system::device::{Device, TimerExt, *};
 pub use crate::system::gpios::{InputType, IoPins, OutputType};
 pub use crate::system::pwms::PwmChannelExt;
+pub use crate::system::scheduler::SCHEDULER;
 pub use crate::system::serial_io::SERIAL;
+pub use crate::system::settings::SETTINGS;
+pub use crate::system::tick::TICK;
 pub use crate::utils::fifo_buffer::{AsStr, FifoBuffer};
 pub use crate::utils::log::{LOG, LogLevel};
+pub use crate::utils::progress::Progress;
 pub use crate::utils::tasklet::Tasklet;
+pub use crate::utils::units::{Celsius, Ohms, Volts};
 
 pub use embedded_hal::digital::{InputPin, OutputPin, StatefulOutputPin};
 pub use embedded_hal::pwm::SetDutyCycle;
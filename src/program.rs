@@ -11,26 +11,50 @@
 //!     program.run(&mut device, command_list);
 //! }
 //! ```
+//!
+//! With the `async` feature `run_async` replaces `run`: waiting for the host and for command input
+//! are cooperative tasks of `system::executor`, commands still run to completion.
+//!
+//! A command ending with '&' starts a background job (see `cli::jobs`), its steps run while the
+//! prompt waits for the next line. A soak test (see `cli::soak`) also runs while no host is
+//! connected.
+//!
+//! In secure mode (see `system::secure`) lines are encrypted frames, their output is sent back as
+//! a frame and the status prompt is left out.
+//!
+//! With the login challenge on (see `system::auth`), a new connection gets an #AUTH line and only
+//! the auth command runs until it is answered.
 
-use crate::cli::CommandList;
-use crate::cli::SimpleCli;
+use crate::cli::{self, Chain, CommandList, SimpleCli, TERM, jobs, soak};
+use crate::limits::LINE_BUFFER_LENGTH;
 use crate::prelude::*;
+use crate::system::address::{self, Route};
+use crate::system::button::BUTTON;
+#[cfg(feature = "async")]
+use crate::system::executor;
+use crate::system::led::LedMode;
+use crate::system::safe_mode::{self, SAFE_MODE};
+use crate::system::secure::{self, SECURE};
+use crate::system::{auth, cpu_load, profile, status};
+use crate::utils::time::Instant;
 
-// ————————————————————————————————————————————————————————————————————————————————————————————————
-//                                            Globals
-// ————————————————————————————————————————————————————————————————————————————————————————————————
+use usb_device::UsbError;
 
-const CMD_BUFF_SIZE: usize = 192;
+#[cfg(feature = "async")]
+use core::pin::pin;
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                            Program
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
-pub struct Program {}
+pub struct Program {
+    /// Timer ticks of the last greeting, see `status::greet_quiet_s`
+    last_greet_us: Option<u64>,
+}
 
 impl Program {
     pub fn new() -> Self {
-        Self {}
+        Self { last_greet_us: None }
     }
 
     // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -38,45 +62,55 @@ impl Program {
     // —————————————————————————————————————————————————————————————————————————————————————————————————
 
     pub fn run(&mut self, device: &mut Device, commands: CommandList) {
-        let mut command_buf: FifoBuffer<CMD_BUFF_SIZE> = FifoBuffer::new();
+        let mut command_buf: FifoBuffer<LINE_BUFFER_LENGTH> = FifoBuffer::new();
         let mut command_read = false;
+        let mut prompt = true;
         let mut cli = SimpleCli::new(commands);
+        let mut sequence: u32 = 1;
+
+        self.boot_profile(&mut cli, device);
 
         loop {
             // —————————————————————————————————— Acquire Connection —————————————————————————————————————
 
             // While we don't have a serial monitor connection we keep polling
             if !SERIAL.is_connected() {
-                self.get_connection(device);
+                self.get_connection(&mut cli, device);
+                auth::new_session();
                 self.greet(device);
+                auth::print_challenge();
+                device.led.set_mode(LedMode::Idle);
             }
 
-            let led = device.outputs.get(gpio!(LED)).unwrap();
-            led.set_high().unwrap();
-
             // ————————————————————————————————————— Read command ————————————————————————————————————————
             if !command_read {
-                // Print Device Status
-                let temp_adc_raw: u16 = device.adcs.read(TEMP_SENSE_CHN).unwrap_or(0);
-                let vsys_adc_raw: u16 = device.adcs.read(3).unwrap_or(0);
-                let sys_temp = 27.0 - (temp_adc_raw.to_voltage() - 0.706) / 0.001721; // RP2040 temp sensor calibration
-
-                println!(
-                    "\n| Temp: {:.1}C | A3: {:.2}V | T: {} |",
-                    sys_temp,
-                    vsys_adc_raw.to_voltage(),
-                    device.timer.print_time()
-                );
-                print!("Enter Command: \n>>> ");
+                // Print Device Status, not again after a line addressed to another board
+                if prompt && !SECURE.is_enabled() && !status::is_quiet() {
+                    println!();
+                    status::print_status(device);
+                    status::print_prompt(device, sequence);
+                }
 
                 // Blocking - Waiting for a command
                 command_buf.clear();
-                match SERIAL.read_line_blocking(command_buf.receive_buffer()) {
+                match self.read_line(&cli, device, command_buf.receive_buffer()) {
                     Ok(len) => {
                         command_buf.advance(len);
-                        command_read = true;
                         let data = command_buf.get_data().as_str().unwrap();
-                        println!("{}", data);
+
+                        // Silent on lines for other boards of a shared bus
+                        prompt = address::route(data) != Route::Other;
+                        if !prompt {
+                            continue;
+                        }
+                        command_read = true;
+                        if !SECURE.is_enabled() && !status::is_quiet() {
+                            println!("{}", data);
+                        }
+                    }
+                    Err(UsbError::BufferOverflow) => {
+                        println!("\nErr: {}\n", cli::Error::CommandTooLong(LINE_BUFFER_LENGTH));
+                        continue;
                     }
                     Err(e) => {
                         println!("\nErr: {:?} \n", e);
@@ -88,76 +122,401 @@ impl Program {
             // ———————————————————————————————————— Execute command ——————————————————————————————————————
 
             if command_read {
+                // Without the address prefix, lines for other boards are dropped when read
                 let input = command_buf.get_data().as_str().unwrap();
-                let cmd_name = input.split_ascii_whitespace().next().unwrap_or("help");
+                if let Route::Local(input) = address::route(input) {
+                    match SECURE.is_enabled() {
+                        true => self.execute_secure(&mut cli, device, input),
+                        false => self.execute(&mut cli, device, input),
+                    }
+                }
 
-                println!("\n========= RUNNING: {cmd_name} =========\n");
+                // Cleanup
+                command_buf.clear();
+                command_read = false; // Done, accepting new cmds
+                sequence = sequence.wrapping_add(1);
+            }
+        }
+    }
 
-                // Time benchmark start
-                let exec_time = device.timer.get_counter();
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+    //                                            Run Async
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-                cli.execute(input, device).unwrap_or_else(|e| println!("Err: {}", e));
+    /// Same flow as `run` on the executor. The CLI task sleeps until the USB interrupt signals
+    /// input instead of spinning on the serial, the serial task flushes the queued output.
+    #[cfg(feature = "async")]
+    pub fn run_async(&mut self, device: &mut Device, commands: CommandList) -> ! {
+        let mut cli = SimpleCli::new(commands);
 
-                // Time benchmark end
-                let exec_time = device
-                    .timer
-                    .get_counter()
-                    .checked_duration_since(exec_time)
-                    .unwrap()
-                    .to_micros();
+        self.boot_profile(&mut cli, device);
+
+        let cli_task = pin!(async {
+            let mut line = [0u8; LINE_BUFFER_LENGTH];
+            let mut sequence: u32 = 1;
+            let mut prompt = true;
+
+            loop {
+                // —————————————————————————————————— Acquire Connection —————————————————————————————————
+
+                if !SERIAL.is_connected() {
+                    device.led.set_mode(LedMode::WaitingForHost);
+                    while !SERIAL.is_connected() {
+                        if let Some(line) = BUTTON.take_line() {
+                            self.execute(&mut cli, device, &line);
+                            device.led.set_mode(LedMode::WaitingForHost);
+                        }
+                        soak::run_due(cli.command_list(), device);
+                        executor::sleep_ms(80).await;
+                    }
+                    info!("USB Serial Monitor: Connected!");
+                    auth::new_session();
+                    self.greet(device);
+                    auth::print_challenge();
+                    device.led.set_mode(LedMode::Idle);
+                }
 
-                // Cleanup
-                command_buf.clear();
-                command_read = false; // Done, accepting new cmds
+                // ————————————————————————————————————— Read command ————————————————————————————————————
+                if prompt && !SECURE.is_enabled() && !status::is_quiet() {
+                    println!();
+                    status::print_status(device);
+                    status::print_prompt(device, sequence);
+                }
+                prompt = true;
+
+                // The USB interrupt keeps the received bytes until a full line is in
+                SERIAL.set_line_input(true);
+                let len = loop {
+                    if let Some(result) = SERIAL.take_line(&mut line) {
+                        break Some(result);
+                    }
+                    if !SERIAL.is_connected() {
+                        break None;
+                    }
+                    if let Some(button_line) = BUTTON.take_line() {
+                        break Some(Ok(copy_line(&button_line, &mut line)));
+                    }
+                    // Background jobs and the button are polled, without any the input wakes the
+                    // task
+                    if jobs::is_empty() && !soak::is_running() && !BUTTON.is_active() {
+                        executor::USB_SIGNAL.wait().await;
+                    }
+                    else {
+                        jobs::run_due(cli.command_list(), device);
+                        soak::run_due(cli.command_list(), device);
+                        executor::sleep_ms(10).await;
+                    }
+                };
+                SERIAL.set_line_input(false);
 
-                println!(
-                    "\n========= DONE in {time:.3}ms =========\n",
-                    time = exec_time as f32 / 1000.0
-                );
+                let len = match len {
+                    Some(Ok(len)) => len,
+                    Some(Err(_)) => {
+                        println!("\nErr: {}\n", cli::Error::CommandTooLong(LINE_BUFFER_LENGTH));
+                        continue;
+                    }
+                    None => continue,
+                };
+                let Ok(input) = core::str::from_utf8(&line[..len])
+                else {
+                    println!("\nErr: invalid UTF-8 input\n");
+                    continue;
+                };
+
+                // Silent on lines for other boards of a shared bus
+                let Route::Local(command) = address::route(input)
+                else {
+                    prompt = false;
+                    continue;
+                };
+
+                // ———————————————————————————————————— Execute command ——————————————————————————————————
+                match SECURE.is_enabled() {
+                    true => self.execute_secure(&mut cli, device, command),
+                    false => {
+                        if !status::is_quiet() {
+                            println!("{}", input);
+                        }
+                        self.execute(&mut cli, device, command);
+                    }
+                }
+                sequence = sequence.wrapping_add(1);
             }
+        });
+
+        // Output queued from interrupts (try_print) is sent by the USB polling
+        let serial_task = pin!(async {
+            loop {
+                SERIAL.poll_usb();
+                executor::sleep_ms(10).await;
+            }
+        });
+
+        let mut tasks: [executor::Task; 2] = [cli_task, serial_task];
+        executor::run(&mut tasks)
+    }
+
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+    //                                              Execute
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-            // ————————————————————————————————— Signal Execution End ————————————————————————————————————
+    /// Runs the commands of a line, chained with ';' (always) or "&&" (if the previous succeeded)
+    fn execute(&mut self, cli: &mut SimpleCli, device: &mut Device, input: &str) {
+        // A single empty line still shows the help
+        let chained = cli::split_chain(input).is_some();
+        let mut rest = Some(input);
+        let mut succeeded = true;
+        let mut run = true;
+
+        while let Some(line) = rest {
+            let (command, chain) = match cli::split_chain(line) {
+                Some((command, chain, next)) => {
+                    rest = Some(next);
+                    (command.trim(), Some(chain))
+                }
+                None => {
+                    rest = None;
+                    (line.trim(), None)
+                }
+            };
 
-            let led = device.outputs.get(gpio!(LED)).unwrap();
-            for _ in 0..3 {
-                led.set_low().unwrap();
-                device.timer.delay_ms(50);
-                led.set_high().unwrap();
-                device.timer.delay_ms(50);
+            // Skipped commands count as failed for the following "&&"
+            if command.is_empty() && chained {
+                // Nothing between two separators, ex: "cmd ;"
+            }
+            else if run {
+                succeeded = match command.strip_suffix('&') {
+                    Some(command) => self.start_job(device, command.trim_end()),
+                    None => self.execute_command(cli, device, command),
+                };
+            }
+            else {
+                println!("\n========= SKIPPED: {command} =========");
+                succeeded = false;
+            }
+
+            run = chain != Some(Chain::OnSuccess) || succeeded;
+        }
+
+        // Lifetime counters, written at a low rate to spare the flash
+        device.state.persist_due();
+    }
+
+    /// Decrypts a frame and runs its line, the output is captured and sent back as a frame
+    fn execute_secure(&mut self, cli: &mut SimpleCli, device: &mut Device, frame: &str) {
+        let mut buf = [0u8; secure::MAX_COMMAND_LENGTH];
+        let input = SECURE
+            .open_frame(frame, &mut buf)
+            .and_then(|len| core::str::from_utf8(&buf[..len]).map_err(|_| secure::Error::BadFrame));
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                println!("\nErr: {e}\n");
+                device.led.set_mode(LedMode::Error);
+                return;
+            }
+        };
+
+        SERIAL.start_capture();
+        self.execute(cli, device, input);
+        let output = SERIAL.end_capture().unwrap_or_default();
+
+        match SECURE.seal_frame(&output.bytes, |part| print!("{part}")) {
+            Ok(()) => println!(),
+            Err(e) => println!("\nErr: {e}\n"),
+        }
+        if output.truncated {
+            println!("Output truncated to {} bytes", output.bytes.len());
+        }
+    }
+
+    /// Runs a command with its timing, crash tracking and LED signaling, true if it succeeded
+    fn execute_command(&mut self, cli: &mut SimpleCli, device: &mut Device, input: &str) -> bool {
+        let cmd_name = input.split_ascii_whitespace().next().unwrap_or("help");
+
+        if !status::is_quiet() {
+            println!("\n========= RUNNING: {cmd_name} =========\n");
+        }
+
+        // Time benchmark start
+        let exec_time = Instant::now();
+
+        // Kept in the watchdog scratch to detect a crash on the next boot
+        safe_mode::command_started(&mut device.watchdog, cmd_name);
+        device.led.set_mode(LedMode::Running);
+        // Nothing but the login while a connected host is locked, see system::auth
+        let result = match auth::check(cmd_name) {
+            Ok(()) => cli.execute(input, device),
+            Err(e) => Err(e.into()),
+        };
+        safe_mode::command_finished(&mut device.watchdog);
+
+        // Lines queued by an applied profile
+        self.autorun(cli, device);
+
+        if let Err(e) = &result {
+            println!("Err: {}", e);
+        }
+
+        // Time benchmark end
+        let exec_time = exec_time.elapsed();
+
+        // Fixed format for host scripts, status 0 or the error code (see cli::Error::code)
+        // Quiet mode ends with a status token only, read after the command as it may change it
+        let code = result.as_ref().err().map_or(0, cli::Error::code);
+        match (status::is_quiet(), code) {
+            (true, 0) => println!("#OK"),
+            (true, code) => println!("#ERR {code}"),
+            (false, code) => println!(
+                "\n========= DONE in {time:.3}ms status={code} =========\n",
+                time = exec_time.as_millis_f32()
+            ),
+        }
+
+        // ———————————————————————————————————— Signal Execution End ————————————————————————————————————
+
+        // The error pattern is kept until the next command
+        match result {
+            Ok(()) => device.led.set_mode(LedMode::Idle),
+            Err(_) => device.led.set_mode(LedMode::Error),
+        }
+        result.is_ok()
+    }
+
+    /// Starts a command as a background job, true if it started
+    fn start_job(&mut self, device: &mut Device, input: &str) -> bool {
+        let started = auth::check("").map_err(cli::Error::from);
+        match started.and_then(|()| jobs::start(input)) {
+            Ok(id) => {
+                println!("\n[{id}] {input}");
+                true
+            }
+            Err(e) => {
+                println!("\nErr: {e}");
+                device.led.set_mode(LedMode::Error);
+                false
             }
         }
     }
 
     // —————————————————————————————————————————————————————————————————————————————————————————————————
-    //                                           Get Connection
+    //                                             Read Line
     // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-    /// Blocking function until connection is acquired
-    fn get_connection(&mut self, device: &mut Device) {
-        let led = device.outputs.get(gpio!(LED)).unwrap();
+    /// Waits for a command line, stepping the background jobs meanwhile.
+    /// A button press returns its line as if typed.
+    fn read_line(
+        &mut self,
+        cli: &SimpleCli,
+        device: &mut Device,
+        buf: &mut [u8],
+    ) -> Result<usize, UsbError> {
+        if jobs::is_empty() && !soak::is_running() && !BUTTON.is_active() {
+            return cpu_load::idle(|| SERIAL.read_line_blocking(buf));
+        }
 
-        // While we don't have a serial monitor connection we keep polling and bliking led for status
+        // The USB interrupt keeps the received bytes until a full line is in
+        SERIAL.set_line_input(true);
+        let result = loop {
+            if let Some(result) = SERIAL.take_line(buf) {
+                break result;
+            }
+            if !SERIAL.is_connected() {
+                break Err(UsbError::InvalidEndpoint);
+            }
+            if let Some(line) = BUTTON.take_line() {
+                break Ok(copy_line(&line, buf));
+            }
+            jobs::run_due(cli.command_list(), device);
+            soak::run_due(cli.command_list(), device);
+            cpu_load::idle(|| device.timer.delay_ms(1));
+        };
+        SERIAL.set_line_input(false);
+        result
+    }
+
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+    //                                            Boot Profile
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+
+    /// Applies the boot profile, part of the startup automation skipped in safe mode
+    fn boot_profile(&mut self, cli: &mut SimpleCli, device: &mut Device) {
+        if !SAFE_MODE.is_active()
+            && let Some(name) = profile::boot_profile()
+        {
+            match profile::load(&name).and_then(|profile| profile.apply(device)) {
+                Ok(()) => self.autorun(cli, device),
+                Err(e) => error!("Boot profile {name}: {e}"),
+            }
+        }
+    }
+
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+    //                                           Get Connection
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+
+    /// Blocking function until connection is acquired, the button and a soak test still run
+    fn get_connection(&mut self, cli: &mut SimpleCli, device: &mut Device) {
+        // While we don't have a serial monitor connection we keep polling, the LED shows the wait
+        device.led.set_mode(LedMode::WaitingForHost);
         while !SERIAL.is_connected() {
-            led.toggle().unwrap();
-            device.timer.delay_ms(80);
+            if let Some(line) = BUTTON.take_line() {
+                self.execute(cli, device, &line);
+                device.led.set_mode(LedMode::WaitingForHost);
+            }
+            soak::run_due(cli.command_list(), device);
+            cpu_load::idle(|| device.timer.delay_ms(80));
         }
         info!("USB Serial Monitor: Connected!");
     }
 
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+    //                                              Autorun
+    // —————————————————————————————————————————————————————————————————————————————————————————————————
+
+    /// Runs the autorun lines queued by the last applied profile
+    fn autorun(&mut self, cli: &mut SimpleCli, device: &mut Device) {
+        let Some(lines) = profile::take_autorun()
+        else {
+            return;
+        };
+
+        for line in lines
+            .split(';')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            println!("> {line}");
+            if let Err(e) = cli.execute(line, device) {
+                println!("Err: {}", e);
+            }
+        }
+    }
+
     // —————————————————————————————————————————————————————————————————————————————————————————————————
     //                                              Greet
     // —————————————————————————————————————————————————————————————————————————————————————————————————
 
     fn greet(&mut self, device: &mut Device) {
-        let led = device.outputs.get(gpio!(LED)).unwrap();
+        // A new connection follows the quiet setting again, scripts skip the greeting entirely
+        status::set_session_quiet(None);
+        if status::is_quiet() {
+            return;
+        }
 
-        // Blink leds four times to notify connected
-        for _ in 0..4 {
-            led.set_low().unwrap();
-            device.timer.delay_ms(200);
-            led.set_high().unwrap();
-            device.timer.delay_ms(200);
+        // A reconnection shortly after the last greeting (terminal toggling DTR) is kept short
+        let now_us = device.timer.get_counter().ticks();
+        let quiet_us = status::greet_quiet_s() as u64 * 1_000_000;
+        if let Some(last_us) = self.last_greet_us
+            && now_us - last_us < quiet_us
+        {
+            println!("\nReconnected");
+            return;
         }
+        self.last_greet_us = Some(now_us);
+
+        // Terminal width used for the help and table layouts
+        TERM.detect();
 
         // Displaying last panic msg
         #[cfg(feature = "panic-persist")]
@@ -168,11 +527,38 @@ impl Program {
             }
         }
 
+        // Warning about the command that ended the previous run
+        if let Some(cmd) = SAFE_MODE.crashed_cmd() {
+            println!("\n========= SAFE MODE =======");
+            println!("The previous run ended while running \"{cmd}\" (panic or watchdog reset)");
+            println!("Autorun skipped, reset the device to leave safe mode");
+        }
+
         // Print greeting msg
         let time_ticks = device.timer.get_counter().ticks();
         println!("\n========= HELLO =========== ");
         println!("Current timer ticks: {time_ticks} (T: {})", device.timer.print_time());
         println!("Frequency: {}hz", SYS_CLK_HZ.load(Ordering::Relaxed));
+        println!("Board: {}", *BOARD);
+        println!("Hostname: {}", status::hostname());
+        if let Some(address) = address::address() {
+            println!("Bus address: @{address:02}");
+        }
+        println!("Terminal width: {}", TERM.width());
         println!("Type \"help\" for the command lists\n");
+
+        // Results of a soak test that ended while disconnected
+        soak::print_pending_report();
     }
 }
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Copies a button line into the input buffer, returns its length
+fn copy_line(line: &str, buf: &mut [u8]) -> usize {
+    let len = line.len().min(buf.len());
+    buf[..len].copy_from_slice(&line.as_bytes()[..len]);
+    len
+}
@@ -1,11 +1,191 @@
 //! A struct that holds the device state
-//! We should be able to read and update the state safely from interrupts
-//! TODO: Think of a global state and implementation
+//! We should be able to read and update the state safely from interrupts: the counters are atomics
+//! of the global STATE, `Device::state` refers to it.
+//!
+//! Usage statistics since boot, ex: to estimate the wear of a relay or to check that a script
+//! switches an output about as often as expected:
+//! - toggles: level changes of each output written through `gpios` (commands, scripts, services)
+//! - PWM enable time: running time of each slice, sampled on the system tick (100ms resolution)
+//!
+//! Lifetime counters are kept in the wear-leveled KV store: boot count, cumulative uptime and
+//! toggles of each output. The counts since the last write are added by the storage shutdown
+//! hook (reset, flash, estop) and after a command once `PERSIST_INTERVAL_S` elapsed.
 
-pub struct State {}
+use core::fmt::Write;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use crate::hal;
+use crate::system::kv::{self, KV};
+use crate::system::shutdown::{Reason, SHUTDOWN, Stage};
+use crate::system::tick::{TICK, TICK_US};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const NUM_SLICES: usize = hal::NUM_PWM_SLICES as usize;
+
+/// Seconds between two writes of the lifetime counters, besides the shutdown hook
+pub const PERSIST_INTERVAL_S: u32 = 3600;
+
+const BOOTS_KEY: &str = "boots";
+const UPTIME_KEY: &str = "uptime";
+
+pub static STATE: State = State {
+    toggles:     [const { AtomicU32::new(0) }; hal::NUM_GPIOS],
+    pwm_ticks:   [const { AtomicU32::new(0) }; NUM_SLICES],
+    persisted:   [const { AtomicU32::new(0) }; hal::NUM_GPIOS],
+    persisted_s: AtomicU32::new(0),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              State
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct State {
+    toggles:     [AtomicU32; hal::NUM_GPIOS],
+    /// System ticks each PWM slice was enabled
+    pwm_ticks:   [AtomicU32; NUM_SLICES],
+    /// Toggles already added to the lifetime counters, wrapping offset of `toggles`
+    persisted:   [AtomicU32; hal::NUM_GPIOS],
+    /// Uptime seconds already added to the lifetime uptime
+    persisted_s: AtomicU32,
+}
 
 impl State {
-    pub fn new() -> Self {
-        State {}
+    /// Counts a level change of an output
+    pub fn count_toggle(&self, gpio: u8) {
+        if let Some(toggles) = self.toggles.get(gpio as usize) {
+            toggles.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn toggles(&self, gpio: u8) -> u32 {
+        self.toggles
+            .get(gpio as usize)
+            .map_or(0, |toggles| toggles.load(Ordering::Relaxed))
+    }
+
+    /// Time a PWM slice has been enabled since boot or the last reset, in milliseconds
+    pub fn pwm_enabled_ms(&self, slice: u8) -> u64 {
+        self.pwm_ticks
+            .get(slice as usize)
+            .map_or(0, |ticks| ticks.load(Ordering::Relaxed) as u64 * (TICK_US / 1000) as u64)
+    }
+
+    /// Toggles of an output since its first boot, None if the KV store has none
+    pub fn lifetime_toggles(&self, gpio: u8) -> Option<u32> {
+        let stored = KV.get(&toggles_key(gpio));
+        match self.pending_toggles(gpio) {
+            0 => stored,
+            pending => Some(stored.unwrap_or(0).saturating_add(pending)),
+        }
+    }
+
+    /// Uptime since the first boot in seconds
+    pub fn lifetime_uptime_s(&self) -> u32 {
+        let pending = uptime_s().saturating_sub(self.persisted_s.load(Ordering::Relaxed));
+        KV.get(UPTIME_KEY).unwrap_or(0).saturating_add(pending)
+    }
+
+    pub fn boots(&self) -> u32 {
+        KV.get(BOOTS_KEY).unwrap_or(0)
+    }
+
+    /// Adds the counts since the last write to the lifetime counters
+    pub fn persist(&self) -> kv::Result<()> {
+        let now_s = uptime_s();
+        let pending_s = now_s.saturating_sub(self.persisted_s.load(Ordering::Relaxed));
+        KV.add(UPTIME_KEY, pending_s)?;
+        self.persisted_s.store(now_s, Ordering::Relaxed);
+
+        for gpio in 0..hal::NUM_GPIOS as u8 {
+            let pending = self.pending_toggles(gpio);
+            if pending > 0 {
+                KV.add(&toggles_key(gpio), pending)?;
+                self.persisted[gpio as usize].fetch_add(pending, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Persists the lifetime counters once `PERSIST_INTERVAL_S` elapsed since the last write
+    pub fn persist_due(&self) {
+        if uptime_s().saturating_sub(self.persisted_s.load(Ordering::Relaxed)) >= PERSIST_INTERVAL_S
+            && let Err(e) = self.persist()
+        {
+            crate::warn!("Lifetime counters not saved: {e}");
+        }
+    }
+
+    /// Clears the counters of a pin and of its PWM slice, or all of them.
+    /// The lifetime counters keep the toggles not written yet.
+    pub fn reset(&self, gpio: Option<u8>) {
+        for (id, toggles) in self.toggles.iter().enumerate() {
+            if gpio.is_none_or(|gpio| gpio as usize == id) {
+                let count = toggles.swap(0, Ordering::Relaxed);
+                self.persisted[id].fetch_sub(count, Ordering::Relaxed);
+            }
+        }
+        for (slice, ticks) in self.pwm_ticks.iter().enumerate() {
+            if gpio.is_none_or(|gpio| slice_of(gpio) as usize == slice) {
+                ticks.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pending_toggles(&self, gpio: u8) -> u32 {
+        let persisted = self.persisted[gpio as usize].load(Ordering::Relaxed);
+        self.toggles(gpio).wrapping_sub(persisted)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Starts sampling the PWM enable time and counts the boot, returns the global state
+pub fn init() -> &'static State {
+    TICK.register(sample_pwm, 1).unwrap();
+    SHUTDOWN.register("state", Stage::Storage, persist).unwrap();
+    if let Err(e) = KV.add(BOOTS_KEY, 1) {
+        crate::warn!("Boot not counted: {e}");
+    }
+    &STATE
+}
+
+/// PWM slice of a GPIO
+pub fn slice_of(gpio: u8) -> u8 {
+    (gpio >> 1) & 0x7
+}
+
+/// Seconds since boot, from the system tick
+fn uptime_s() -> u32 {
+    TICK.ticks() / (1_000_000 / TICK_US)
+}
+
+/// KV key of the lifetime toggles of an output
+fn toggles_key(gpio: u8) -> kv::Key {
+    let mut key = kv::Key::new();
+    let _ = write!(key, "tg.{gpio}");
+    key
+}
+
+/// Storage shutdown hook
+fn persist(_reason: Reason) {
+    if let Err(e) = STATE.persist() {
+        crate::warn!("Lifetime counters not saved: {e}");
+    }
+}
+
+/// Tick task adding a tick to the enabled slices, whatever enabled them
+fn sample_pwm() {
+    // Safety: read only access to the slice control registers
+    let pwm = unsafe { hal::regs::pwm() };
+    for (slice, ticks) in STATE.pwm_ticks.iter().enumerate() {
+        if pwm.ch(slice).csr().read().en().bit() {
+            ticks.fetch_add(1, Ordering::Relaxed);
+        }
     }
 }
@@ -1,17 +1,39 @@
 //! Analog-Digital Converter (ADC) Wrapper for the RP2040 microcontroller
+//!
+//! Each channel has an optional filter applied inside `read`, so all consumers get the same
+//! stable values: oversampling (average of 2^N conversions) followed by an exponential moving
+//! average (`ema` is the weight of the new sample, 0-1). Filters are saved to the settings as
+//! "adc<N>.oversample" and "adc<N>.ema".
+//!
+//! The reference voltage scales every conversion to volts. It defaults to the board one (3.3V
+//! rail) and can be set for a VREF pin driven externally, saved as "adc.vref".
 
+use core::fmt::{self, Write};
+
+use crate::hal;
 use embedded_hal_0_2::adc::OneShot;
-use rp2040_hal as hal;
 
 //
 use hal::adc::{Adc, AdcPin, TempSense};
 use hal::gpio;
 
+use portable_atomic::{AtomicU32, Ordering};
+
+use super::board::BOARD;
+use super::settings::{self, SETTINGS};
+
 pub const ADC_BITS: u32 = 12;
 pub const ADC_MAX: f32 = ((1 << ADC_BITS) - 1) as f32;
-pub const ADC_VREF: f32 = 3.3;
+pub const VREF_KEY: &str = "adc.vref";
+/// Accepted external references, the ADC doesn't work below 1V nor above IOVDD
+pub const VREF_RANGE: core::ops::RangeInclusive<f32> = 1.0..=3.6;
 
 pub const TEMP_SENSE_CHN: u8 = 4;
+pub const NUM_CHANNELS: usize = 5; // ADC0-3 and the temperature sensor
+pub const MAX_OVERSAMPLE: u16 = 256;
+
+/// Reference voltage in use, f32 bits
+static VREF: AtomicU32 = AtomicU32::new(3.3f32.to_bits());
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                              Adcs
@@ -26,11 +48,16 @@ pub struct Adcs {
     pub adc1:       Option<AdcPin<DynPinType>>,
     pub adc2:       Option<AdcPin<DynPinType>>,
     pub adc3:       Option<AdcPin<DynPinType>>,
+    filters:        [Filter; NUM_CHANNELS],
 }
 
 impl Adcs {
+    /// Creates the ADC wrapper, loading the reference and the channel filters from the settings
     pub fn new(mut hal_adc: Adc) -> Self {
         let temp_sense = hal_adc.take_temp_sensor().unwrap();
+        load_vref();
+        let filters = core::array::from_fn(|channel| Filter::load(channel as u8));
+
         Self {
             hal_adc,
             temp_sense,
@@ -38,6 +65,7 @@ impl Adcs {
             adc1: None,
             adc2: None,
             adc3: None,
+            filters,
         }
     }
 
@@ -60,14 +88,53 @@ impl Adcs {
         &mut self.hal_adc
     }
 
-    /// One shot read of the ADC channel 0-3, and 4 as TEMP_SENSE channel
+    /// Filtered read of the ADC channel 0-3, and 4 as TEMP_SENSE channel
     /// Returns Some or None
     pub fn read(&mut self, id: u8) -> Option<u16> {
+        let filter = *self.filters.get(id as usize)?;
+
+        let mut sum = 0u32;
+        for _ in 0..filter.oversample {
+            sum += self.read_raw(id)? as u32;
+        }
+        let average = sum as f32 / filter.oversample as f32;
+
+        let value = match filter.ema {
+            Some(alpha) => {
+                let state = self.filters[id as usize].state.get_or_insert(average);
+                *state += alpha * (average - *state);
+                *state
+            }
+            None => average,
+        };
+        Some((value + 0.5) as u16)
+    }
+
+    /// One shot read of a channel bypassing the filter
+    pub fn read_raw(&mut self, id: u8) -> Option<u16> {
+        // Synthetic waveform when the channel is mocked
+        #[cfg(feature = "mock")]
+        if let Some(value) = super::mock::MOCK.read_adc(id) {
+            return Some(value);
+        }
+
         match id {
-            0 => self.adc0.as_mut().and_then(|pin| self.hal_adc.read(pin).ok()),
-            1 => self.adc1.as_mut().and_then(|pin| self.hal_adc.read(pin).ok()),
-            2 => self.adc2.as_mut().and_then(|pin| self.hal_adc.read(pin).ok()),
-            3 => self.adc3.as_mut().and_then(|pin| self.hal_adc.read(pin).ok()),
+            0 => self
+                .adc0
+                .as_mut()
+                .and_then(|pin| self.hal_adc.read(pin).ok()),
+            1 => self
+                .adc1
+                .as_mut()
+                .and_then(|pin| self.hal_adc.read(pin).ok()),
+            2 => self
+                .adc2
+                .as_mut()
+                .and_then(|pin| self.hal_adc.read(pin).ok()),
+            3 => self
+                .adc3
+                .as_mut()
+                .and_then(|pin| self.hal_adc.read(pin).ok()),
             TEMP_SENSE_CHN => self.hal_adc.read(&mut self.temp_sense).ok(),
             _ => None,
         }
@@ -84,6 +151,148 @@ impl Adcs {
             _ => None,
         }
     }
+
+    /// Filter of a channel, None for invalid channels
+    pub fn filter(&self, channel: u8) -> Option<Filter> {
+        self.filters.get(channel as usize).copied()
+    }
+
+    /// Replaces and saves the filter of a channel, the moving average restarts
+    pub fn set_filter(&mut self, channel: u8, filter: Filter) -> settings::Result<()> {
+        let slot = self
+            .filters
+            .get_mut(channel as usize)
+            .ok_or(settings::Error::InvalidValue)?;
+
+        if !filter.is_valid() {
+            return Err(settings::Error::InvalidValue);
+        }
+        *slot = Filter { state: None, ..filter };
+        slot.save(channel)
+    }
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Filter
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    /// Conversions averaged per read, power of two up to MAX_OVERSAMPLE
+    pub oversample: u16,
+    /// Weight of the new value in the moving average (0-1], None disables it
+    pub ema:        Option<f32>,
+    state:          Option<f32>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(1, None)
+    }
+}
+
+impl Filter {
+    pub const fn new(oversample: u16, ema: Option<f32>) -> Self {
+        Self {
+            oversample,
+            ema,
+            state: None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.oversample.is_power_of_two()
+            && self.oversample <= MAX_OVERSAMPLE
+            && self.ema.is_none_or(|alpha| alpha > 0.0 && alpha <= 1.0)
+    }
+
+    /// Loads the filter of a channel, the default (no filtering) if not set or invalid
+    fn load(channel: u8) -> Self {
+        let (oversample_key, ema_key) = setting_keys(channel);
+        let filter = Self::new(
+            SETTINGS.get_parsed(&oversample_key).unwrap_or(1),
+            SETTINGS.get_parsed(&ema_key),
+        );
+
+        match filter.is_valid() {
+            true => filter,
+            false => Self::default(),
+        }
+    }
+
+    fn save(&self, channel: u8) -> settings::Result<()> {
+        let (oversample_key, ema_key) = setting_keys(channel);
+
+        match self.oversample {
+            1 => {
+                SETTINGS.remove(&oversample_key);
+            }
+            oversample => SETTINGS.set(&oversample_key, oversample)?,
+        }
+        match self.ema {
+            Some(alpha) => SETTINGS.set(&ema_key, alpha)?,
+            None => {
+                SETTINGS.remove(&ema_key);
+            }
+        }
+        SETTINGS.save()
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "oversample: {:>3}x | ema: ", self.oversample)?;
+        match self.ema {
+            Some(alpha) => write!(f, "{alpha}"),
+            None => f.write_str("off"),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Reference voltage the conversions are scaled with
+pub fn vref() -> f32 {
+    f32::from_bits(VREF.load(Ordering::Relaxed))
+}
+
+/// True if the reference comes from the settings, not the board profile
+pub fn is_vref_set() -> bool {
+    SETTINGS
+        .get_parsed::<f32>(VREF_KEY)
+        .is_some_and(|volts| VREF_RANGE.contains(&volts))
+}
+
+/// Sets and saves the reference voltage, None returns to the board one
+pub fn set_vref(volts: Option<f32>) -> settings::Result<()> {
+    match volts {
+        Some(volts) => SETTINGS.set(VREF_KEY, volts)?,
+        None => {
+            SETTINGS.remove(VREF_KEY);
+        }
+    }
+    load_vref();
+    SETTINGS.save()
+}
+
+/// Reference from the settings, the board one if not set or out of range
+fn load_vref() {
+    let volts = SETTINGS
+        .get_parsed::<f32>(VREF_KEY)
+        .filter(|volts| VREF_RANGE.contains(volts))
+        .unwrap_or(BOARD.adc_vref);
+    VREF.store(volts.to_bits(), Ordering::Relaxed);
+}
+
+/// Settings keys of a channel filter: "adc<N>.oversample" and "adc<N>.ema"
+fn setting_keys(channel: u8) -> (settings::Key, settings::Key) {
+    let mut oversample = settings::Key::new();
+    let mut ema = settings::Key::new();
+    let _ = write!(oversample, "adc{channel}.oversample");
+    let _ = write!(ema, "adc{channel}.ema");
+    (oversample, ema)
 }
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
@@ -92,7 +301,7 @@ impl Adcs {
 
 // ——————————————————————————————————————— Adc Conversions —————————————————————————————————————————
 pub trait AdcConversion {
-    /// Convert raw ADC reading to volts. Assuming 12-bit ADC (0..=4095) and 3.3 V reference.
+    /// Convert raw ADC reading to volts. Assuming 12-bit ADC (0..=4095), scaled by `vref()`.
     fn to_voltage(&self) -> f32;
     /// Convert raw ADC reading to resistance. Assuming a voltage divider with a pull up resistor of the specified resistance.
     fn to_resistance(&self, ref_res_ohm: u32) -> f32;
@@ -100,7 +309,7 @@ pub trait AdcConversion {
 
 impl AdcConversion for u16 {
     fn to_voltage(&self) -> f32 {
-        (*self as f32) * ADC_VREF / ADC_MAX
+        (*self as f32) * vref() / ADC_MAX
     }
 
     fn to_resistance(&self, ref_res_ohm: u32) -> f32 {
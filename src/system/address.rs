@@ -0,0 +1,83 @@
+//! Bus Addressing
+//!
+//! Several boards sharing one line (RS-485 or a multi-drop UART) tell their commands apart with
+//! an address prefix: `@03 pin alias=LED toggle` only runs on the board with address 3, the others
+//! drop the line without echo, prompt or output. `@*` addresses every board.
+//!
+//! The address is kept in the flash settings (`set address=3`). Lines without a prefix run on
+//! every board, and a board without an address only answers the broadcast prefix. The prefix is
+//! checked on the console line input, whatever link carries it.
+//!
+//! Example:
+//! ```rust
+//! match address::route("@03 led") {
+//!     Route::Local(line) => cli.execute(line, device),
+//!     Route::Other => {} // addressed to another board
+//! }
+//! ```
+
+use crate::system::settings::{self, SETTINGS};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const ADDRESS_KEY: &str = "address";
+pub const MIN_ADDRESS: u8 = 1;
+pub const MAX_ADDRESS: u8 = 99;
+
+const PREFIX: char = '@';
+const BROADCAST: &str = "*";
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Route
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Route<'a> {
+    /// Runs here, the command line without the prefix
+    Local(&'a str),
+    /// Addressed to another board, ignored
+    Other,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Bus address stored in the settings, None if not set
+pub fn address() -> Option<u8> {
+    SETTINGS
+        .get_parsed(ADDRESS_KEY)
+        .filter(|address| (MIN_ADDRESS..=MAX_ADDRESS).contains(address))
+}
+
+/// Stores the bus address, None removes it. The settings still have to be saved.
+pub fn set_address(address: Option<u8>) -> settings::Result<()> {
+    match address {
+        Some(address) => SETTINGS.set(ADDRESS_KEY, address),
+        None => {
+            SETTINGS.remove(ADDRESS_KEY);
+            Ok(())
+        }
+    }
+}
+
+/// Splits the address prefix off a command line, ex: "@03 led" or "@* led"
+pub fn route(line: &str) -> Route<'_> {
+    let Some(rest) = line.trim_start().strip_prefix(PREFIX)
+    else {
+        return Route::Local(line);
+    };
+
+    let (target, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let command = command.trim_start();
+
+    if target == BROADCAST {
+        return Route::Local(command);
+    }
+    match (target.parse::<u8>(), address()) {
+        (Ok(target), Some(address)) if target == address => Route::Local(command),
+        _ => Route::Other,
+    }
+}
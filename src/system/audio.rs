@@ -0,0 +1,490 @@
+//! PCM Audio Playback
+//!
+//! Plays 8-bit unsigned PCM through a PWM pin for audible alerts. The slice counts to 255 at the
+//! system clock (a 488kHz carrier at 125MHz) and a DMA channel, paced by a DMA timer at the
+//! sample rate, writes one sample per period into the compare register: no CPU per sample.
+//! An RC low pass (ex: 1k / 100nF) or a small amplifier on the pin turns the duty into sound.
+//!
+//! The samples are expanded into a ring of compare words the DMA reads in ring mode,
+//! `Player::poll` refills it and has to be called before the ring runs out, every `RING_LEN`
+//! samples (23ms at 22kHz). The other channel of the slice keeps its compare level but runs on the audio
+//! carrier until the player stops, then the slice settings are restored.
+//!
+//! Clips are stored as RAM files ("<name>.pcm"), saved to flash with the file store: up to a
+//! sector, 0.5s at 8kHz. Built-in beep patterns are generated on the fly.
+//!
+//! Example:
+//! ```rust
+//! let mut player = Player::start(&mut device.pwms, gpio, 8_000, Source::Pattern(&BEEP), 100)?;
+//! while player.poll() {}
+//! player.stop(&mut device.pwms);
+//! ```
+
+use portable_atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+use super::config;
+use super::files::{self, FILES};
+use super::pwms::{Channel, Pwms};
+use crate::hal;
+use crate::hal::dma::{CH10, Channel as DmaChannel};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MIN_RATE: u32 = 8_000;
+pub const MAX_RATE: u32 = 22_050;
+pub const DEFAULT_RATE: u32 = 8_000;
+
+/// Channel reserved for the player, taken from the HAL by `init`
+const DMA_CHANNEL: usize = 10;
+/// Compare words in the ring, a power of two
+const RING_LEN: usize = 512;
+/// log2 of the ring size in bytes, the DMA wraps the read address on it
+const RING_BITS: u8 = 11;
+/// PWM top, one compare step per sample value
+const TOP: u16 = 255;
+const SILENCE: u8 = 128;
+
+const EXTENSION: &str = ".pcm";
+
+static READY: AtomicBool = AtomicBool::new(false);
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Aligned on its size for the DMA ring mode
+#[repr(C, align(2048))]
+struct Ring([u32; RING_LEN]);
+
+static mut RING: Ring = Ring([0; RING_LEN]);
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Patterns
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Square wave tones, (frequency in Hz, length in ms), 0Hz for a pause
+pub struct Pattern {
+    pub name:  &'static str,
+    pub tones: &'static [(u16, u16)],
+}
+
+impl Pattern {
+    pub fn find(name: &str) -> Option<&'static Pattern> {
+        PATTERNS
+            .iter()
+            .copied()
+            .find(|pattern| pattern.name == name)
+    }
+
+    fn len(&self, rate: u32) -> usize {
+        self.tones.iter().map(|&(_, ms)| tone_len(ms, rate)).sum()
+    }
+}
+
+pub static BEEP: Pattern = Pattern {
+    name:  "beep",
+    tones: &[(1000, 150)],
+};
+pub static DOUBLE: Pattern = Pattern {
+    name:  "double",
+    tones: &[(1000, 100), (0, 80), (1000, 100)],
+};
+pub static ALARM: Pattern = Pattern {
+    name:  "alarm",
+    tones: &[
+        (880, 200),
+        (660, 200),
+        (880, 200),
+        (660, 200),
+        (880, 200),
+        (660, 200),
+    ],
+};
+pub static ERROR: Pattern = Pattern {
+    name:  "error",
+    tones: &[(300, 150), (0, 60), (300, 300)],
+};
+pub static OK: Pattern = Pattern {
+    name:  "ok",
+    tones: &[(660, 80), (880, 80), (1320, 120)],
+};
+
+pub static PATTERNS: [&Pattern; 5] = [&BEEP, &DOUBLE, &ALARM, &ERROR, &OK];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Source
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Copy, Clone)]
+pub enum Source<'a> {
+    /// 8-bit unsigned samples, 128 is silence
+    Clip(&'a [u8]),
+    Pattern(&'static Pattern),
+}
+
+impl Source<'_> {
+    /// Samples at a rate
+    pub fn len(&self, rate: u32) -> usize {
+        match self {
+            Source::Clip(data) => data.len(),
+            Source::Pattern(pattern) => pattern.len(rate),
+        }
+    }
+}
+
+/// Samples of a source, the tones generated on the fly
+struct Samples<'a> {
+    source: Source<'a>,
+    rate:   u32,
+    /// Clip sample or pattern tone
+    index:  usize,
+    /// Sample in the current tone
+    n:      usize,
+}
+
+impl Iterator for Samples<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let pattern = match self.source {
+            Source::Clip(data) => {
+                let sample = data.get(self.index).copied();
+                self.index += 1;
+                return sample;
+            }
+            Source::Pattern(pattern) => pattern,
+        };
+
+        loop {
+            let &(hz, ms) = pattern.tones.get(self.index)?;
+            if self.n < tone_len(ms, self.rate) {
+                let sample = square(hz, self.n, self.rate);
+                self.n += 1;
+                return Some(sample);
+            }
+            self.index += 1;
+            self.n = 0;
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Player
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Slice settings restored by `stop`
+struct Saved {
+    freq:       u32,
+    top:        u16,
+    ph_correct: bool,
+    enabled:    bool,
+    cc:         u32,
+}
+
+pub struct Player<'a> {
+    gpio:      u8,
+    slice_id:  u8,
+    channel:   Channel,
+    samples:   Samples<'a>,
+    /// Compare level of the other channel, kept in every word
+    other:     u32,
+    volume:    u8,
+    total:     usize,
+    written:   usize,
+    underruns: u32,
+    rate:      u32,
+    saved:     Saved,
+}
+
+impl<'a> Player<'a> {
+    /// Takes the slice of a PWM pin and starts playing at `rate` Hz (rounded to the DMA timer
+    /// resolution), `volume` in percent.
+    pub fn start(
+        pwms: &mut Pwms,
+        gpio: u8,
+        rate: u32,
+        source: Source<'a>,
+        volume: u8,
+    ) -> Result<Self> {
+        if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+            return Err(Error::InvalidRate);
+        }
+        let (slice_id, channel) = pwms.get_pwm_slice_id_by_gpio(gpio)?;
+        if !READY.load(Ordering::Acquire) {
+            return Err(Error::Unavailable);
+        }
+        if BUSY.swap(true, Ordering::Acquire) {
+            return Err(Error::Busy);
+        }
+
+        // DMA timer 0 at sys_clk / y
+        let sys_clk_hz = pwms.pwm0.sys_clk_hz;
+        let y = ((sys_clk_hz + rate / 2) / rate).min(u16::MAX as u32);
+        let rate = sys_clk_hz / y;
+
+        // Safety: read only, the compare register of both channels
+        let cc = unsafe { hal::regs::pwm().ch(slice_id as usize).cc().read().bits() };
+        let saved = crate::with_pwm_slice!(pwms, slice_id, |pwm_slice| {
+            let saved = Saved {
+                freq: pwm_slice.freq,
+                top: pwm_slice.slice.get_top(),
+                ph_correct: pwm_slice.ph_correct,
+                enabled: pwm_slice.enabled,
+                cc,
+            };
+            pwm_slice.set_ph_correct(false);
+            pwm_slice.set_top(TOP);
+            pwm_slice.set_freq(sys_clk_hz / (TOP as u32 + 1));
+            saved
+        });
+        let other = match channel {
+            Channel::A => cc >> 16,
+            Channel::B => cc & 0xFFFF,
+        };
+
+        let total = source.len(rate);
+        let mut player = Player {
+            gpio,
+            slice_id,
+            channel,
+            samples: Samples {
+                source,
+                rate,
+                index: 0,
+                n: 0,
+            },
+            other,
+            volume: volume.min(100),
+            total,
+            written: 0,
+            underruns: 0,
+            rate,
+            saved,
+        };
+        player.write_cc(SILENCE);
+        crate::with_pwm_slice!(pwms, slice_id, |pwm_slice| pwm_slice.enable());
+
+        // Prefilled, then refilled by poll
+        player.fill(0);
+
+        // Safety: the channel was handed over by init, the ring is only used while BUSY is held
+        let dma = unsafe { hal::regs::dma() };
+        let ch = dma.ch(DMA_CHANNEL);
+        dma.timer0()
+            .write(|w| unsafe { w.x().bits(1).y().bits(y as u16) });
+        ch.ch_read_addr()
+            .write(|w| unsafe { w.bits((&raw const RING) as u32) });
+        ch.ch_write_addr()
+            .write(|w| unsafe { w.bits(cc_address(slice_id)) });
+        ch.ch_trans_count()
+            .write(|w| unsafe { w.bits(total as u32) });
+        ch.ch_ctrl_trig().write(|w| unsafe {
+            w.data_size().size_word();
+            w.incr_read().set_bit();
+            w.incr_write().clear_bit();
+            w.ring_sel().clear_bit();
+            w.ring_size().bits(RING_BITS);
+            w.treq_sel().timer0();
+            w.chain_to().bits(DMA_CHANNEL as u8);
+            w.en().set_bit()
+        });
+
+        Ok(player)
+    }
+
+    /// Refills the ring, returns false once every sample was played
+    pub fn poll(&mut self) -> bool {
+        let played = self.played();
+        if played >= self.total {
+            return false;
+        }
+        if played > self.written {
+            self.underruns += 1;
+            self.written = played;
+        }
+        self.fill(played);
+        true
+    }
+
+    /// Stops the DMA and restores the slice settings
+    pub fn stop(self, pwms: &mut Pwms) {
+        // Safety: the channel was handed over by init
+        let dma = unsafe { hal::regs::dma() };
+        dma.chan_abort()
+            .write(|w| unsafe { w.bits(1 << DMA_CHANNEL) });
+        while dma.chan_abort().read().bits() & (1 << DMA_CHANNEL) != 0 {}
+
+        let saved = &self.saved;
+        crate::with_pwm_slice!(pwms, self.slice_id, |pwm_slice| {
+            pwm_slice.set_top(saved.top);
+            pwm_slice.set_ph_correct(saved.ph_correct);
+            pwm_slice.set_freq(saved.freq);
+            if saved.enabled {
+                pwm_slice.enable();
+            }
+            else {
+                pwm_slice.disable();
+            }
+        });
+        // Safety: both channels of the slice are owned by the player until now
+        let ch = unsafe { hal::regs::pwm().ch(self.slice_id as usize) };
+        ch.cc().write(|w| unsafe { w.bits(saved.cc) });
+
+        BUSY.store(false, Ordering::Release);
+    }
+
+    pub fn gpio(&self) -> u8 {
+        self.gpio
+    }
+
+    /// Sample rate after rounding
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Samples of the clip or pattern
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Samples played by the DMA
+    pub fn played(&self) -> usize {
+        // Safety: read only
+        let dma = unsafe { hal::regs::dma() };
+        let remaining = dma.ch(DMA_CHANNEL).ch_trans_count().read().bits() as usize;
+        self.total.saturating_sub(remaining)
+    }
+
+    /// Times the ring ran empty, the DMA replayed old samples
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    /// Writes the samples ahead of the DMA, the slot being read is never written
+    fn fill(&mut self, played: usize) {
+        while self.written < self.total && self.written < played + RING_LEN - 1 {
+            let sample = self.samples.next().unwrap_or(SILENCE);
+            let word = self.word(sample);
+            // Safety: the slot was already read by the DMA, see the loop condition
+            unsafe { RING.0[self.written % RING_LEN] = word };
+            self.written += 1;
+        }
+    }
+
+    /// Compare word of a sample, the other channel unchanged
+    fn word(&self, sample: u8) -> u32 {
+        let delta = (sample as i32 - SILENCE as i32) * self.volume as i32 / 100;
+        let level = (SILENCE as i32 + delta) as u32;
+        match self.channel {
+            Channel::A => (self.other << 16) | level,
+            Channel::B => (level << 16) | self.other,
+        }
+    }
+
+    fn write_cc(&self, sample: u8) {
+        let word = self.word(sample);
+        // Safety: both channels of the slice are owned by the player
+        let ch = unsafe { hal::regs::pwm().ch(self.slice_id as usize) };
+        ch.cc().write(|w| unsafe { w.bits(word) });
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Takes the DMA channel used by the player
+pub fn init(_channel: DmaChannel<CH10>) {
+    READY.store(true, Ordering::Release);
+}
+
+/// Stores a clip in the file store
+pub fn save(name: &str, data: &[u8]) -> Result<()> {
+    if data.is_empty() {
+        return Err(Error::Empty);
+    }
+    FILES.write(&file_name(name)?, data, false)?;
+    Ok(())
+}
+
+/// Reads a clip from the file store
+pub fn load(name: &str) -> Result<files::Data> {
+    FILES.read(&file_name(name)?).map_err(|_| Error::NotFound)
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    match FILES.remove(&file_name(name)?) {
+        true => Ok(()),
+        false => Err(Error::NotFound),
+    }
+}
+
+/// Calls `f` with the name and sample count of every stored clip
+pub fn for_each(mut f: impl FnMut(&str, usize)) {
+    FILES.for_each(|file, size| {
+        if let Some(name) = file.strip_suffix(EXTENSION) {
+            f(name, size);
+        }
+    });
+}
+
+fn file_name(name: &str) -> Result<files::Name> {
+    let mut file = files::Name::new();
+    file.push_str(name).map_err(|_| Error::InvalidName)?;
+    file.push_str(EXTENSION).map_err(|_| Error::InvalidName)?;
+    Ok(file)
+}
+
+fn tone_len(ms: u16, rate: u32) -> usize {
+    (ms as u32 * rate / 1000) as usize
+}
+
+/// Sample `n` of a full scale square wave, silence at 0Hz
+fn square(hz: u16, n: usize, rate: u32) -> u8 {
+    // Phase in 1/2^32 of a period, only the fraction is kept
+    let step = ((hz as u64) << 32) / rate as u64;
+    match hz {
+        0 => SILENCE,
+        _ if ((n as u64).wrapping_mul(step) as u32) < 1 << 31 => u8::MAX,
+        _ => 0,
+    }
+}
+
+fn cc_address(slice_id: u8) -> u32 {
+    // Safety: only the address is taken
+    let ch = unsafe { hal::regs::pwm().ch(slice_id as usize) };
+    ch.cc().as_ptr() as u32
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("sample rate out of range, 8000 - 22050Hz")]
+    InvalidRate,
+
+    #[error("audio DMA channel not initialized")]
+    Unavailable,
+
+    #[error("already playing")]
+    Busy,
+
+    #[error("empty clip")]
+    Empty,
+
+    #[error("clip not found")]
+    NotFound,
+
+    #[error("clip name too long")]
+    InvalidName,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Files(#[from] files::Error),
+}
@@ -0,0 +1,127 @@
+//! Host Authentication
+//!
+//! Optional login challenge for boards whose USB port is within reach of untrusted users. With
+//! `auth on`, every connection starts locked: the device prints
+//!   #AUTH challenge=<40 hex chars>
+//! and refuses any command but `auth response=<64 hex chars>`, the HMAC-SHA256 of the challenge
+//! bytes with the login subkey of the secure channel key (see system/secure.rs).
+//!
+//! Challenge: [boot nonce: 16 bytes][session: u32 LE]. The nonce is drawn from the ROSC at boot,
+//! the session counts the connections and the denied answers, a response is only good once.
+//!
+//! Frames of the secure mode are authenticated by the key already, no challenge then. Without a
+//! host connection the button lines still run, the lock only applies to a connected session.
+//!
+//! Example (host side, Python):
+//! ```python
+//! auth_key = hmac.new(psk, b"auth", hashlib.sha256).digest()
+//! response = hmac.new(auth_key, bytes.fromhex(challenge), hashlib.sha256).hexdigest()
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
+use super::rng::Rng;
+use super::secure::{Error, Result, SECURE};
+use super::serial_io::SERIAL;
+use crate::utils::encoding::{Encoding, LineEncoder};
+use crate::utils::sha256::{self, DIGEST_SIZE, Digest};
+use crate::{print, println};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const CHALLENGE_SIZE: usize = NONCE_SIZE + 4;
+/// Command accepted while locked
+pub const LOGIN_COMMAND: &str = "auth";
+
+const NONCE_SIZE: usize = 16;
+
+static NONCE: Mutex<RefCell<[u8; NONCE_SIZE]>> = Mutex::new(RefCell::new([0; NONCE_SIZE]));
+static SESSION: AtomicU32 = AtomicU32::new(0);
+static LOGGED_IN: AtomicBool = AtomicBool::new(false);
+
+pub type Challenge = [u8; CHALLENGE_SIZE];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Draws the boot nonce
+pub fn init(rng: &mut Rng) {
+    let mut nonce = [0; NONCE_SIZE];
+    rng.fill_bytes(&mut nonce);
+    with(|cs| *NONCE.borrow_ref_mut(cs) = nonce);
+}
+
+/// True if the connected host has to log in before running commands
+pub fn is_locked() -> bool {
+    SECURE.is_auth_required()
+        && !SECURE.is_enabled()
+        && SERIAL.is_connected()
+        && !LOGGED_IN.load(Ordering::Relaxed)
+}
+
+/// Refuses a command while locked, but the login one
+pub fn check(command: &str) -> Result<()> {
+    match is_locked() && command != LOGIN_COMMAND {
+        true => Err(Error::Locked),
+        false => Ok(()),
+    }
+}
+
+/// Locks again with a new challenge, on connect or on request
+pub fn new_session() {
+    LOGGED_IN.store(false, Ordering::Relaxed);
+    SESSION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn challenge() -> Challenge {
+    let mut challenge = [0; CHALLENGE_SIZE];
+    with(|cs| challenge[..NONCE_SIZE].copy_from_slice(&*NONCE.borrow_ref(cs)));
+    challenge[NONCE_SIZE..].copy_from_slice(&SESSION.load(Ordering::Relaxed).to_le_bytes());
+    challenge
+}
+
+/// Prints the challenge line for the host, nothing if not locked
+pub fn print_challenge() {
+    if !is_locked() {
+        return;
+    }
+
+    // Fits a single hex line
+    let mut line = LineEncoder::new(Encoding::Hex);
+    print!("#AUTH challenge=");
+    line.push(&challenge(), |part| print!("{part}"));
+    line.finish(|part| print!("{part}"));
+    println!();
+}
+
+/// Checks the host answer to the current challenge. A wrong one draws a new challenge.
+pub fn login(response: &str) -> Result<()> {
+    let expected = SECURE.hmac(&challenge())?;
+    let accepted =
+        parse_digest(response).is_some_and(|response| sha256::verify(&expected, &response));
+
+    if !accepted {
+        new_session();
+        return Err(Error::Denied);
+    }
+    LOGGED_IN.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Digest from 64 hex chars
+fn parse_digest(hex: &str) -> Option<Digest> {
+    if hex.len() != DIGEST_SIZE * 2 {
+        return None;
+    }
+    let mut digest = [0u8; DIGEST_SIZE];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
@@ -0,0 +1,161 @@
+//! Board Profiles
+//!
+//! Differences between the supported RP2040 boards: onboard pins, VBUS/VSYS sensing,
+//! flash size, extra buttons, ADC reference and PSU mode pin. The profile adds its board pins to the pin configuration.
+//!
+//! The build default is selected with a cargo feature (Pico when none is given):
+//! `cargo build --features "board-pico-w"` or `--features "board-weact"`.
+//! It can be overridden at boot by the "board" setting (`board set=weact`, then reset).
+//!
+//! Example:
+//! ```rust
+//! if let Some(gpio) = BOARD.vbus_sense {
+//!     let usb_powered = device.inputs.get(gpio)?.is_high().unwrap();
+//! }
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use super::config::Def;
+use super::settings::SETTINGS;
+use crate::pin_config;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Board profile in use. Settings have to be loaded before the first access.
+pub static BOARD: Lazy<&'static Profile> = Lazy::new(|| {
+    SETTINGS
+        .get_parsed::<Board>("board")
+        .unwrap_or(DEFAULT_BOARD)
+        .profile()
+});
+
+#[cfg(feature = "board-weact")]
+pub const DEFAULT_BOARD: Board = Board::WeAct;
+
+#[cfg(all(feature = "board-pico-w", not(feature = "board-weact")))]
+pub const DEFAULT_BOARD: Board = Board::PicoW;
+
+#[cfg(not(any(feature = "board-pico-w", feature = "board-weact")))]
+pub const DEFAULT_BOARD: Board = Board::Pico;
+
+const PICO: Profile = Profile {
+    board:      Board::Pico,
+    name:       "Raspberry Pi Pico",
+    pins:       pin_config::PICO_PINS,
+    vbus_sense: Some(24),
+    vsys_sense: true,
+    flash_size: 2 * 1024 * 1024,
+    buttons:    &[],
+    adc_vref:   3.3,
+    psu_mode:   Some(23), // RT6150B P-Select
+};
+
+const PICO_W: Profile = Profile {
+    board:      Board::PicoW,
+    name:       "Raspberry Pi Pico W",
+    pins:       pin_config::PICO_W_PINS,
+    vbus_sense: None, // CYW43 WL_GPIO2
+    vsys_sense: true,
+    flash_size: 2 * 1024 * 1024,
+    buttons:    &[],
+    adc_vref:   3.3,
+    psu_mode:   None, // CYW43 WL_GPIO1
+};
+
+const WEACT: Profile = Profile {
+    board:      Board::WeAct,
+    name:       "WeAct Studio RP2040",
+    pins:       pin_config::WEACT_PINS,
+    vbus_sense: None,
+    vsys_sense: false,            // GP29 is a regular ADC pin
+    flash_size: 16 * 1024 * 1024, // 2MB to 16MB variants, storage stays in the first 2MB
+    buttons:    &["BUTTON"],
+    adc_vref:   3.3,
+    psu_mode:   None,
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Boards
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Board {
+    Pico,
+    PicoW,
+    WeAct,
+}
+
+impl Board {
+    pub fn profile(&self) -> &'static Profile {
+        match self {
+            Board::Pico => &PICO,
+            Board::PicoW => &PICO_W,
+            Board::WeAct => &WEACT,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Board::Pico => "pico",
+            Board::PicoW => "pico_w",
+            Board::WeAct => "weact",
+        }
+    }
+}
+
+impl FromStr for Board {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Board::Pico, Board::PicoW, Board::WeAct]
+            .into_iter()
+            .find(|board| board.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Profile
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Profile {
+    pub board:      Board,
+    pub name:       &'static str,
+    /// Onboard pins added to the pin configuration
+    pub pins:       &'static [Def],
+    /// GPIO reading high when USB or VBUS is powered
+    pub vbus_sense: Option<u8>,
+    /// ADC3 (GP29) reads VSYS/3 through the onboard divider
+    pub vsys_sense: bool,
+    pub flash_size: u32,
+    /// Aliases of the onboard buttons
+    pub buttons:    &'static [&'static str],
+    /// ADC reference, the filtered 3V3 rail unless the VREF pin is driven, see `adcs::vref`
+    pub adc_vref:   f32,
+    /// GPIO of the regulator mode select, see `psu`
+    pub psu_mode:   Option<u8>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) | flash: {}MB",
+            self.name,
+            self.board,
+            self.flash_size / (1024 * 1024)
+        )
+    }
+}
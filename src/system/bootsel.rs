@@ -0,0 +1,46 @@
+//! BOOTSEL Button
+//!
+//! The BOOTSEL key as a second user button. It is wired to the chip select of the QSPI flash, not
+//! to a GPIO: a read releases the select for a few µs and samples the pin, the flash can't be
+//! reached meanwhile (see `flash::read_qspi_ss`). A read parks core1 and masks the interrupts for
+//! about 20µs, fine for a command or a slow poll, not for a tight loop.
+//!
+//! Mirror rules take it as input "BOOTSEL", the pseudo pin `GPIO`. Interrupt handlers can't park
+//! core1, so they only see the button while core1 is stopped (`set core1=lazy|off`).
+//!
+//! Example:
+//! ```rust
+//! if bootsel::is_pressed()? {
+//!     println!("BOOTSEL held");
+//! }
+//! ```
+
+use super::flash;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const ALIAS: &str = "BOOTSEL";
+/// Pseudo pin of the mirror rules, past the 30 GPIOs
+pub const GPIO: u8 = 30;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// True while the button is held, not from an interrupt handler
+pub fn is_pressed() -> flash::Result<bool> {
+    // Pulled up, the key shorts the select to ground
+    Ok(!flash::read_qspi_ss()?)
+}
+
+/// Interrupt safe read, None while core1 is running
+pub fn try_is_pressed() -> Option<bool> {
+    flash::try_read_qspi_ss().map(|level| !level)
+}
+
+/// BOOTSEL for its alias, case insensitive
+pub fn is_alias(name: &str) -> bool {
+    name.eq_ignore_ascii_case(ALIAS)
+}
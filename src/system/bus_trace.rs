@@ -0,0 +1,210 @@
+//! Bus Transaction Recorder
+//!
+//! Records the I2C/SPI transactions performed through `Buses` into a ring buffer while enabled.
+//! Each record keeps the timestamp, bus, address, the first bytes sent/received and the status.
+//! The oldest records are dropped when the buffer is full.
+
+use core::fmt;
+
+use crate::hal;
+//
+use hal::timer::Timer;
+
+use heapless::{Deque, Vec};
+
+use super::buses::{BusId, Error};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+use crate::limits::MAX_TRACE_RECORDS as MAX_RECORDS;
+const MAX_RECORD_BYTES: usize = 8; // bytes kept per direction
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Record
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceOp {
+    Write,
+    Read,
+    WriteRead,
+    Transfer,
+}
+
+pub struct TraceRecord {
+    pub time_us: u64,
+    pub bus:     BusId,
+    pub addr:    Option<u8>,
+    pub op:      TraceOp,
+    pub tx:      Vec<u8, MAX_RECORD_BYTES>,
+    pub tx_len:  usize,
+    pub rx:      Vec<u8, MAX_RECORD_BYTES>,
+    pub rx_len:  usize,
+    pub status:  Result<(), Error>,
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            TraceOp::Write => "W ",
+            TraceOp::Read => "R ",
+            TraceOp::WriteRead => "WR",
+            TraceOp::Transfer => "TR",
+        };
+
+        write!(
+            f,
+            "[{:>5}.{:06}] {} ",
+            self.time_us / 1_000_000,
+            self.time_us % 1_000_000,
+            self.bus
+        )?;
+        match self.addr {
+            Some(addr) => write!(f, "0x{addr:02x} {op}")?,
+            None => write!(f, "---- {op}")?,
+        }
+
+        if self.tx_len > 0 {
+            write!(f, " tx:")?;
+            write_bytes(f, &self.tx, self.tx_len)?;
+        }
+        if self.rx_len > 0 {
+            write!(f, " rx:")?;
+            write_bytes(f, &self.rx, self.rx_len)?;
+        }
+
+        match &self.status {
+            Ok(()) => write!(f, " | ok"),
+            Err(e) => write!(f, " | err: {e}"),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Bus Trace
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct BusTrace {
+    enabled: bool,
+    timer:   Timer,
+    records: Deque<TraceRecord, MAX_RECORDS>,
+    dropped: u32,
+}
+
+impl BusTrace {
+    pub fn new(timer: Timer) -> Self {
+        Self {
+            enabled: false,
+            timer,
+            records: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn enable(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Removes all records
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.dropped = 0;
+    }
+
+    /// Number of stored records
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Number of records dropped since the last clear because the buffer was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Iterates the records, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.records.iter()
+    }
+
+    /// Stores a transaction if the trace is enabled
+    pub fn record(
+        &mut self,
+        bus: BusId,
+        addr: Option<u8>,
+        op: TraceOp,
+        tx: &[u8],
+        rx: &[u8],
+        status: &Result<(), Error>,
+    ) {
+        if self.enabled {
+            self.push(bus, addr, op, (tx, tx.len()), rx, status);
+        }
+    }
+
+    /// Stores a full duplex SPI transfer. Only the first sent bytes are needed,
+    /// as they were overwritten by the received ones.
+    pub fn record_transfer(&mut self, bus: BusId, tx_head: &[u8], rx: &[u8]) {
+        if self.enabled {
+            self.push(bus, None, TraceOp::Transfer, (tx_head, rx.len()), rx, &Ok(()));
+        }
+    }
+
+    fn push(
+        &mut self,
+        bus: BusId,
+        addr: Option<u8>,
+        op: TraceOp,
+        (tx, tx_len): (&[u8], usize),
+        rx: &[u8],
+        status: &Result<(), Error>,
+    ) {
+        if self.records.is_full() {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+
+        let record = TraceRecord {
+            time_us: self.timer.get_counter().ticks(),
+            bus,
+            addr,
+            op,
+            tx: truncated(tx),
+            tx_len,
+            rx: truncated(rx),
+            rx_len: rx.len(),
+            status: status.clone(),
+        };
+
+        let _ = self.records.push_back(record);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn truncated(bytes: &[u8]) -> Vec<u8, MAX_RECORD_BYTES> {
+    let len = bytes.len().min(MAX_RECORD_BYTES);
+    Vec::from_slice(&bytes[..len]).unwrap()
+}
+
+/// Writes the kept bytes, marking the ones that were not recorded
+fn write_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8], len: usize) -> fmt::Result {
+    for byte in bytes {
+        write!(f, " {byte:02x}")?;
+    }
+    if len > bytes.len() {
+        write!(f, " ..(+{})", len - bytes.len())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,383 @@
+//! I2C and SPI Bus Storage for the RP2040 microcontroller
+//!
+//! Buses are created from the I2C and SPI pin aliases in the pin configuration.
+//! A bus is only enabled when all of its pins are defined and valid for the peripheral.
+//!
+//! SPI chip select is driven as a regular output for the whole transfer.
+//! Transactions are recorded in `trace` while it is enabled.
+//! With the "mock" feature, emulated devices answer before the real bus.
+//!
+//! Example:
+//! ```rust
+//! let mut who_am_i = [0u8];
+//! device
+//!     .buses
+//!     .i2c_write_read(BusId::I2c0, 0x6A, &[0x0F], &mut who_am_i)?;
+//!
+//! let mut buf = [0x9F, 0, 0, 0];
+//! device.buses.spi_transfer(BusId::Spi0, &mut buf)?;
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::hal;
+//
+use hal::fugit::RateExtU32;
+use hal::gpio::{DynPinId, FunctionI2c, FunctionSpi, Pin, PullDown, PullUp};
+use hal::i2c::{I2C, I2cDevice, ValidatedPinScl, ValidatedPinSda};
+use hal::pac;
+use hal::spi::{Enabled, Spi, SpiDevice, ValidatedPinRx, ValidatedPinSck, ValidatedPinTx};
+use hal::timer::Timer;
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c};
+use embedded_hal::spi::{MODE_0, SpiBus};
+use thiserror::Error;
+
+use super::bus_trace::{BusTrace, TraceOp};
+use super::config::CONFIG;
+use super::gpios::OutputType;
+#[cfg(feature = "mock")]
+use super::mock::MOCK;
+use crate::prelude::warn;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const I2C_FREQUENCY: u32 = 100_000; // 100khz - standard mode
+const SPI_FREQUENCY: u32 = 1_000_000; // 1Mhz
+
+type I2cPin = Pin<DynPinId, FunctionI2c, PullUp>;
+type SpiPin = Pin<DynPinId, FunctionSpi, PullDown>;
+
+type I2cBus<D> = I2C<D, (ValidatedPinSda<I2cPin, D>, ValidatedPinScl<I2cPin, D>)>;
+type SpiPins<D> =
+    (ValidatedPinTx<SpiPin, D>, ValidatedPinRx<SpiPin, D>, ValidatedPinSck<SpiPin, D>);
+type SpiBusType<D> = Spi<Enabled, D, SpiPins<D>, 8>;
+
+type DynI2c = dyn I2c<Error = hal::i2c::Error>;
+type DynSpi = dyn SpiBus<u8, Error = core::convert::Infallible>;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Bus Id
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BusId {
+    I2c0,
+    I2c1,
+    Spi0,
+    Spi1,
+}
+
+impl BusId {
+    pub fn is_i2c(&self) -> bool {
+        matches!(self, BusId::I2c0 | BusId::I2c1)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BusId::I2c0 => "i2c0",
+            BusId::I2c1 => "i2c1",
+            BusId::Spi0 => "spi0",
+            BusId::Spi1 => "spi1",
+        }
+    }
+}
+
+impl FromStr for BusId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [BusId::I2c0, BusId::I2c1, BusId::Spi0, BusId::Spi1]
+            .into_iter()
+            .find(|bus| bus.name().eq_ignore_ascii_case(s))
+            .ok_or(Error::InvalidBus)
+    }
+}
+
+impl fmt::Display for BusId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Buses
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Buses {
+    i2c0:      Option<I2cBus<pac::I2C0>>,
+    i2c1:      Option<I2cBus<pac::I2C1>>,
+    spi0:      Option<SpiBusType<pac::SPI0>>,
+    spi1:      Option<SpiBusType<pac::SPI1>>,
+    spi0_cs:   Option<OutputType>,
+    spi1_cs:   Option<OutputType>,
+    pub trace: BusTrace,
+}
+
+impl Buses {
+    /// Creates the buses defined in the pin configuration
+    pub fn new(
+        i2c0: pac::I2C0,
+        i2c1: pac::I2C1,
+        spi0: pac::SPI0,
+        spi1: pac::SPI1,
+        resets: &mut pac::RESETS,
+        sys_clk_hz: u32,
+        timer: Timer,
+    ) -> Self {
+        Self {
+            i2c0:    new_i2c(i2c0, "I2C0", resets, sys_clk_hz),
+            i2c1:    new_i2c(i2c1, "I2C1", resets, sys_clk_hz),
+            spi0:    new_spi(spi0, "SPI0", resets, sys_clk_hz),
+            spi1:    new_spi(spi1, "SPI1", resets, sys_clk_hz),
+            spi0_cs: new_cs("SPI0_CSN"),
+            spi1_cs: new_cs("SPI1_CSN"),
+            trace:   BusTrace::new(timer),
+        }
+    }
+
+    /// Returns true if the bus pins are configured
+    pub fn is_configured(&self, bus: BusId) -> bool {
+        match bus {
+            BusId::I2c0 => self.i2c0.is_some(),
+            BusId::I2c1 => self.i2c1.is_some(),
+            BusId::Spi0 => self.spi0.is_some(),
+            BusId::Spi1 => self.spi1.is_some(),
+        }
+    }
+
+    /// Writes bytes to an I2C device
+    pub fn i2c_write(&mut self, bus: BusId, addr: u8, bytes: &[u8]) -> Result<()> {
+        let result = mock_i2c(bus, addr, bytes, &mut []).unwrap_or_else(|| {
+            self.i2c(bus)
+                .and_then(|i2c| i2c.write(addr, bytes).map_err(i2c_error))
+        });
+
+        self.trace
+            .record(bus, Some(addr), TraceOp::Write, bytes, &[], &result);
+        result
+    }
+
+    /// Reads bytes from an I2C device
+    pub fn i2c_read(&mut self, bus: BusId, addr: u8, buf: &mut [u8]) -> Result<()> {
+        let result = mock_i2c(bus, addr, &[], buf).unwrap_or_else(|| {
+            self.i2c(bus)
+                .and_then(|i2c| i2c.read(addr, buf).map_err(i2c_error))
+        });
+
+        self.trace
+            .record(bus, Some(addr), TraceOp::Read, &[], buf, &result);
+        result
+    }
+
+    /// Writes bytes then reads with a repeated start, ex: register reads
+    pub fn i2c_write_read(
+        &mut self,
+        bus: BusId,
+        addr: u8,
+        bytes: &[u8],
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let result = mock_i2c(bus, addr, bytes, buf).unwrap_or_else(|| {
+            self.i2c(bus)
+                .and_then(|i2c| i2c.write_read(addr, bytes, buf).map_err(i2c_error))
+        });
+
+        self.trace
+            .record(bus, Some(addr), TraceOp::WriteRead, bytes, buf, &result);
+        result
+    }
+
+    /// Full duplex SPI transfer, the received bytes replace the buffer
+    pub fn spi_transfer(&mut self, bus: BusId, buf: &mut [u8]) -> Result<()> {
+        // Keeping the sent bytes for the trace, the buffer is overwritten
+        let mut tx = [0u8; 8];
+        let tx_len = buf.len().min(tx.len());
+        tx[..tx_len].copy_from_slice(&buf[..tx_len]);
+
+        if mock_spi(bus, buf) {
+            self.trace.record_transfer(bus, &tx[..tx_len], buf);
+            return Ok(());
+        }
+
+        let (spi, cs) = match bus {
+            BusId::Spi0 => (self.spi0.as_mut().map(|s| s as &mut DynSpi), self.spi0_cs.as_mut()),
+            BusId::Spi1 => (self.spi1.as_mut().map(|s| s as &mut DynSpi), self.spi1_cs.as_mut()),
+            _ => return Err(Error::WrongBusType(bus)),
+        };
+
+        let Some(spi) = spi
+        else {
+            let result = Err(Error::NotConfigured(bus));
+            self.trace
+                .record(bus, None, TraceOp::Transfer, buf, &[], &result);
+            return result;
+        };
+
+        if let Some(cs) = cs {
+            cs.set_low().unwrap();
+            let _ = spi.transfer_in_place(buf);
+            let _ = spi.flush();
+            cs.set_high().unwrap();
+        }
+        else {
+            let _ = spi.transfer_in_place(buf);
+            let _ = spi.flush();
+        }
+
+        self.trace.record_transfer(bus, &tx[..tx_len], buf);
+        Ok(())
+    }
+
+    fn i2c(&mut self, bus: BusId) -> Result<&mut DynI2c> {
+        let i2c = match bus {
+            BusId::I2c0 => self.i2c0.as_mut().map(|i| i as &mut DynI2c),
+            BusId::I2c1 => self.i2c1.as_mut().map(|i| i as &mut DynI2c),
+            _ => return Err(Error::WrongBusType(bus)),
+        };
+        i2c.ok_or(Error::NotConfigured(bus))
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid bus, use i2c0, i2c1, spi0 or spi1")]
+    InvalidBus,
+
+    #[error("bus {0} not configured")]
+    NotConfigured(BusId),
+
+    #[error("wrong bus type: {0}")]
+    WrongBusType(BusId),
+
+    #[error("i2c: {0}")]
+    I2c(ErrorKind),
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn i2c_error(e: hal::i2c::Error) -> Error {
+    Error::I2c(e.kind())
+}
+
+/// Takes a pin of the given alias if it is defined in the config
+/// Emulated I2C device answer, None if the address is not emulated
+#[cfg(feature = "mock")]
+fn mock_i2c(bus: BusId, addr: u8, bytes: &[u8], buf: &mut [u8]) -> Option<Result<()>> {
+    if !bus.is_i2c() {
+        return None;
+    }
+    MOCK.i2c_transfer(addr, bytes, buf).then_some(Ok(()))
+}
+
+#[cfg(not(feature = "mock"))]
+fn mock_i2c(_bus: BusId, _addr: u8, _bytes: &[u8], _buf: &mut [u8]) -> Option<Result<()>> {
+    None
+}
+
+/// Emulated SPI transfer, false if the bus is not emulated
+#[cfg(feature = "mock")]
+fn mock_spi(bus: BusId, buf: &mut [u8]) -> bool {
+    MOCK.spi_transfer(bus, buf)
+}
+
+#[cfg(not(feature = "mock"))]
+fn mock_spi(_bus: BusId, _buf: &mut [u8]) -> bool {
+    false
+}
+
+fn take_bus_pin<F, P>(alias: &str) -> Option<Pin<DynPinId, F, P>>
+where
+    F: hal::gpio::Function,
+    P: hal::gpio::PullType,
+{
+    let id = CONFIG.get_gpio(alias).ok()?;
+    CONFIG.take_pin(id)
+}
+
+fn new_i2c<D: I2cDevice>(
+    device: D,
+    name: &str,
+    resets: &mut pac::RESETS,
+    sys_clk_hz: u32,
+) -> Option<I2cBus<D>> {
+    let (sda_alias, scl_alias) = match D::ID {
+        0 => ("I2C0_SDA", "I2C0_SCL"),
+        _ => ("I2C1_SDA", "I2C1_SCL"),
+    };
+
+    // Both pins have to be defined to enable the bus
+    if CONFIG.get_gpio(sda_alias).is_err() || CONFIG.get_gpio(scl_alias).is_err() {
+        return None;
+    }
+
+    let sda = ValidatedPinSda::validate(take_bus_pin::<FunctionI2c, PullUp>(sda_alias)?, &device);
+    let scl = ValidatedPinScl::validate(take_bus_pin::<FunctionI2c, PullUp>(scl_alias)?, &device);
+
+    let (Ok(sda), Ok(scl)) = (sda, scl)
+    else {
+        warn!("{} disabled: invalid SDA/SCL pins", name);
+        return None;
+    };
+
+    Some(I2C::new_controller(
+        device,
+        sda,
+        scl,
+        I2C_FREQUENCY.Hz(),
+        resets,
+        sys_clk_hz.Hz(),
+    ))
+}
+
+fn new_spi<D: SpiDevice>(
+    device: D,
+    name: &str,
+    resets: &mut pac::RESETS,
+    sys_clk_hz: u32,
+) -> Option<SpiBusType<D>> {
+    let (tx_alias, rx_alias, sck_alias) = match D::ID {
+        0 => ("SPI0_TX", "SPI0_RX", "SPI0_SCK"),
+        _ => ("SPI1_TX", "SPI1_RX", "SPI1_SCK"),
+    };
+
+    // All data pins have to be defined to enable the bus
+    if [tx_alias, rx_alias, sck_alias]
+        .iter()
+        .any(|alias| CONFIG.get_gpio(alias).is_err())
+    {
+        return None;
+    }
+
+    let tx = ValidatedPinTx::validate(take_bus_pin::<FunctionSpi, PullDown>(tx_alias)?, &device);
+    let rx = ValidatedPinRx::validate(take_bus_pin::<FunctionSpi, PullDown>(rx_alias)?, &device);
+    let sck = ValidatedPinSck::validate(take_bus_pin::<FunctionSpi, PullDown>(sck_alias)?, &device);
+
+    let (Ok(tx), Ok(rx), Ok(sck)) = (tx, rx, sck)
+    else {
+        warn!("{} disabled: invalid TX/RX/SCK pins", name);
+        return None;
+    };
+
+    let spi = Spi::<_, _, _, 8>::new(device, (tx, rx, sck));
+    Some(spi.init(resets, sys_clk_hz.Hz(), SPI_FREQUENCY.Hz(), MODE_0))
+}
+
+/// Chip select is driven in software, idle high
+fn new_cs(alias: &str) -> Option<OutputType> {
+    let mut cs: OutputType = take_bus_pin(alias)?;
+    cs.set_high().unwrap();
+    Some(cs)
+}
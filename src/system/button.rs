@@ -0,0 +1,445 @@
+//! User Button
+//!
+//! The board key (alias "BUTTON", GP23 on the WeAct board) as a local user interface. The pin is
+//! sampled every `POLL_US` from the microsecond scheduler, debounced, and classified as:
+//! - Short press: released before the long press time. Sent on release, or after `DOUBLE_US`
+//!   without a second press when a double press action is mapped.
+//! - Long press: held for the long press time, sent while still held.
+//! - Double press: two short presses within `DOUBLE_US`.
+//!
+//! Each press runs a command line stored in the settings ("button.short", ...), see `button_map`.
+//! The program loop runs it like a typed line, also while no host is connected. By default a
+//! long press enters the USB flash mode and a double press blinks the identify pattern.
+//! Mirror rules can still use BUTTON as their input, they read the pin directly.
+//!
+//! Rescue: held for the rescue time (3s by default), the poll itself enters the USB flash mode
+//! from the interrupt, without the program loop. A CLI wedged in a loop can be reflashed without
+//! a power cycle with BOOTSEL held, as long as the interrupts aren't masked.
+//!
+//! The debounce, long press and rescue times are settings ("button.debounce_ms", ...), surfaced
+//! by `button_map`.
+//!
+//! WeAct board only: the Pico has no user key (GP23 drives the regulator P-Select, pulled down, it
+//! would read as held and trigger the rescue) and on the Pico W GP23 powers the CYW43. On the other
+//! profiles `init` finds no BUTTON pin and the button features stay off.
+//!
+//! Example:
+//! ```rust
+//! BUTTON.init(); // at boot, nothing without a BUTTON pin
+//! button::set_action(Press::Short, Some("pin alias=OUT_A toggle"))?;
+//! button::set_thresholds(&[(Threshold::Rescue, Some(5_000))])?;
+//! if let Some(line) = BUTTON.take_line() { ... } // program loop
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use crate::hal;
+
+use critical_section::{Mutex, with};
+use portable_atomic::{AtomicU8, Ordering};
+use thiserror::Error;
+
+use super::config::CONFIG;
+use super::device;
+use super::scheduler::SCHEDULER;
+use super::settings::{self, SETTINGS, Value};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const ALIAS: &str = "BUTTON";
+
+/// 10ms
+const POLL_US: u32 = 10_000;
+const DOUBLE_US: u32 = 400_000;
+/// Rescue hold past the long press, when the stored one isn't above it
+const RESCUE_GAP_MS: u32 = 1_500;
+
+/// Value of a mapping that disables the press, a missing key restores the default
+const OFF: &str = "off";
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+pub static BUTTON: Button = Button {
+    inner:   Mutex::new(RefCell::new(Inner {
+        gpio:           None,
+        pressed:        false,
+        stable:         0,
+        held_us:        0,
+        long_sent:      false,
+        clicks:         0,
+        since_us:       0,
+        double:         false,
+        debounce_polls: 3,
+        long_us:        1_500_000,
+        rescue_us:      3_000_000,
+    })),
+    pending: AtomicU8::new(0),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Press
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Press {
+    Short  = 1,
+    Long   = 2,
+    Double = 3,
+}
+
+impl Press {
+    pub const ALL: [Press; 3] = [Press::Short, Press::Long, Press::Double];
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Press::Short),
+            2 => Some(Press::Long),
+            3 => Some(Press::Double),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Press::Short => "short",
+            Press::Long => "long",
+            Press::Double => "double",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            Press::Short => "button.short",
+            Press::Long => "button.long",
+            Press::Double => "button.double",
+        }
+    }
+
+    fn default_line(&self) -> Option<&'static str> {
+        match self {
+            Press::Short => None,
+            // A press is deliberate, it passes set confirm=on
+            Press::Long => Some("flash confirm=yes"),
+            Press::Double => Some("identify"),
+        }
+    }
+}
+
+impl fmt::Display for Press {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Threshold
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Press timings in ms, saved as "button.<name>"
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Threshold {
+    /// Time a level has to be stable, rounded up to the poll period
+    Debounce,
+    /// Hold time of a long press
+    Long,
+    /// Hold time entering the USB flash mode from the interrupt, above `Long` or 0 (off)
+    Rescue,
+}
+
+impl Threshold {
+    pub const ALL: [Threshold; 3] = [Threshold::Debounce, Threshold::Long, Threshold::Rescue];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Threshold::Debounce => "debounce_ms",
+            Threshold::Long => "long_ms",
+            Threshold::Rescue => "rescue_ms",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            Threshold::Debounce => "button.debounce_ms",
+            Threshold::Long => "button.long_ms",
+            Threshold::Rescue => "button.rescue_ms",
+        }
+    }
+
+    pub fn default_ms(&self) -> u32 {
+        match self {
+            Threshold::Debounce => 30,
+            Threshold::Long => 1_500,
+            Threshold::Rescue => 3_000,
+        }
+    }
+
+    /// Accepted values, the rescue can also be 0 (off)
+    pub fn range(&self) -> RangeInclusive<u32> {
+        match self {
+            Threshold::Debounce => 10..=200,
+            Threshold::Long => 300..=10_000,
+            Threshold::Rescue => 1_000..=30_000,
+        }
+    }
+
+    fn is_valid(&self, ms: u32) -> bool {
+        self.range().contains(&ms) || (*self == Threshold::Rescue && ms == 0)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Button
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    gpio:           Option<u8>,
+    /// Debounced state
+    pressed:        bool,
+    /// Polls the raw level differed from the debounced state
+    stable:         u8,
+    held_us:        u32,
+    long_sent:      bool,
+    /// Short presses waiting for a possible second one
+    clicks:         u8,
+    /// Time since the last release
+    since_us:       u32,
+    /// A double press action is mapped, short presses wait for `DOUBLE_US`
+    double:         bool,
+    /// Timings from the settings, see `Threshold`
+    debounce_polls: u8,
+    long_us:        u32,
+    /// 0 when off
+    rescue_us:      u32,
+}
+
+pub struct Button {
+    inner:   Mutex<RefCell<Inner>>,
+    /// Last press not taken by the program loop yet, `Press as u8`, 0 if none
+    pending: AtomicU8,
+}
+
+impl Button {
+    /// Starts sampling the BUTTON pin, if the board has one
+    pub fn init(&self) {
+        let Ok(gpio) = CONFIG.get_gpio(ALIAS)
+        else {
+            return;
+        };
+
+        with(|cs| self.inner.borrow_ref_mut(cs).gpio = Some(gpio));
+        refresh();
+        SCHEDULER.schedule_in(POLL_US, poll, 0).unwrap();
+    }
+
+    pub fn gpio(&self) -> Option<u8> {
+        with(|cs| self.inner.borrow_ref(cs).gpio)
+    }
+
+    /// Sampling the pin, the program loop has to poll `take_line`
+    pub fn is_active(&self) -> bool {
+        self.gpio().is_some()
+    }
+
+    /// Command line of the last press, if any and mapped
+    pub fn take_line(&self) -> Option<Value> {
+        let press = Press::from_u8(self.pending.swap(0, Ordering::Acquire))?;
+        action(press)
+    }
+
+    fn send(&self, press: Press) {
+        self.pending.store(press as u8, Ordering::Release);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Command line run by a press, None if disabled
+pub fn action(press: Press) -> Option<Value> {
+    match SETTINGS.get(press.key()) {
+        Some(line) if line == OFF => None,
+        Some(line) => Some(line),
+        None => press
+            .default_line()
+            .and_then(|line| Value::try_from(line).ok()),
+    }
+}
+
+/// Maps a command line to a press, None disables it. Saved by the caller.
+pub fn set_action(press: Press, line: Option<&str>) -> settings::Result<()> {
+    SETTINGS.set(press.key(), line.unwrap_or(OFF))?;
+    refresh();
+    Ok(())
+}
+
+/// Restores the default action of a press. Saved by the caller.
+pub fn reset_action(press: Press) {
+    SETTINGS.remove(press.key());
+    refresh();
+}
+
+/// Press timing in ms, the default if not set or out of range. A rescue hold not above the long
+/// press (saved before the check of `set_thresholds`) is moved past it, the long press comes first.
+pub fn threshold(threshold: Threshold) -> u32 {
+    let ms = stored_threshold(threshold);
+    match threshold {
+        Threshold::Rescue => {
+            let long_ms = stored_threshold(Threshold::Long);
+            match ms != 0 && ms <= long_ms {
+                true => long_ms + RESCUE_GAP_MS,
+                false => ms,
+            }
+        }
+        _ => ms,
+    }
+}
+
+/// Sets press timings in ms, None restores the default. The result is checked as a whole, a
+/// non-zero rescue hold has to stay above the long press. Saved by the caller.
+pub fn set_thresholds(changes: &[(Threshold, Option<u32>)]) -> Result<()> {
+    let new_ms = |threshold: Threshold| {
+        changes
+            .iter()
+            .rev()
+            .find(|(changed, _)| *changed == threshold)
+            .map_or(stored_threshold(threshold), |(_, ms)| ms.unwrap_or(threshold.default_ms()))
+    };
+    let (long_ms, rescue_ms) = (new_ms(Threshold::Long), new_ms(Threshold::Rescue));
+    if rescue_ms != 0 && rescue_ms <= long_ms {
+        return Err(Error::RescueNotAboveLong { long_ms, rescue_ms });
+    }
+
+    for &(threshold, ms) in changes {
+        match ms {
+            Some(ms) => SETTINGS.set(threshold.key(), ms)?,
+            None => {
+                SETTINGS.remove(threshold.key());
+            }
+        }
+    }
+    refresh();
+    Ok(())
+}
+
+/// Press timing saved in the settings, the default if not set or out of range
+fn stored_threshold(threshold: Threshold) -> u32 {
+    SETTINGS
+        .get_parsed::<u32>(threshold.key())
+        .filter(|ms| threshold.is_valid(*ms))
+        .unwrap_or(threshold.default_ms())
+}
+
+/// Caches the mapping and the timings for the poll. Short presses wait for a second one only
+/// while a double press is mapped.
+fn refresh() {
+    let double = action(Press::Double).is_some();
+    let debounce_polls = threshold(Threshold::Debounce).div_ceil(POLL_US / 1_000) as u8;
+    let long_us = threshold(Threshold::Long) * 1_000;
+    let rescue_us = threshold(Threshold::Rescue) * 1_000;
+
+    with(|cs| {
+        let mut inner = BUTTON.inner.borrow_ref_mut(cs);
+        inner.double = double;
+        inner.debounce_polls = debounce_polls;
+        inner.long_us = long_us;
+        inner.rescue_us = rescue_us;
+    });
+}
+
+/// Scheduler callback, interrupt context
+fn poll(_ctx: u32) -> Option<u32> {
+    // Safety: gpio_in is read only
+    let sio = unsafe { hal::regs::sio() };
+    let levels = sio.gpio_in().read().bits();
+
+    let press = with(|cs| {
+        let mut inner = BUTTON.inner.borrow_ref_mut(cs);
+        let gpio = inner.gpio?;
+        // Pulled up, the key shorts to ground
+        let raw = levels & (1 << gpio) == 0;
+
+        // Debounce
+        if raw != inner.pressed {
+            inner.stable += 1;
+            if inner.stable >= inner.debounce_polls {
+                inner.stable = 0;
+                inner.pressed = raw;
+                return on_edge(&mut inner);
+            }
+        }
+        else {
+            inner.stable = 0;
+        }
+
+        // Held or released, no edge
+        if inner.pressed {
+            inner.held_us = inner.held_us.saturating_add(POLL_US);
+            // Recovery of a wedged program loop, the long press line would never run
+            if inner.rescue_us != 0 && inner.held_us >= inner.rescue_us {
+                device::device_reset_to_usb();
+            }
+            if inner.held_us >= inner.long_us && !inner.long_sent {
+                inner.long_sent = true;
+                inner.clicks = 0;
+                return Some(Press::Long);
+            }
+        }
+        else if inner.clicks > 0 {
+            inner.since_us = inner.since_us.saturating_add(POLL_US);
+            if inner.since_us >= DOUBLE_US {
+                inner.clicks = 0;
+                return Some(Press::Short);
+            }
+        }
+        None
+    });
+
+    if let Some(press) = press {
+        BUTTON.send(press);
+    }
+    Some(POLL_US)
+}
+
+/// Debounced press or release
+fn on_edge(inner: &mut Inner) -> Option<Press> {
+    if inner.pressed {
+        inner.held_us = 0;
+        inner.long_sent = false;
+        return None;
+    }
+
+    // Released
+    if inner.long_sent {
+        return None;
+    }
+    if !inner.double {
+        return Some(Press::Short);
+    }
+
+    inner.clicks += 1;
+    inner.since_us = 0;
+    if inner.clicks >= 2 {
+        inner.clicks = 0;
+        return Some(Press::Double);
+    }
+    None
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("rescue_ms {rescue_ms} has to be above long_ms {long_ms}, or 0")]
+    RescueNotAboveLong { long_ms: u32, rescue_ms: u32 },
+
+    #[error(transparent)]
+    Settings(#[from] settings::Error),
+}
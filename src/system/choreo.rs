@@ -0,0 +1,398 @@
+//! Servo Choreography
+//!
+//! Plays keyframe sequences on several RC servos at once: each servo moves from one of its
+//! keyframes to the next with an ease in/out curve, all servos updated together every servo
+//! frame (20ms) by the microsecond scheduler. The compare levels are written straight to the PWM
+//! registers, the sequence keeps running between and during commands.
+//!
+//! Sequences are uploaded as "time_ms,servo,angle" lines and stored as RAM files ("<name>.ch"),
+//! saved to flash with the file store. Binary format: [time_ms: u32 LE][gpio: u8][angle: u8].
+//!
+//! A servo holds its first angle until its first keyframe and its last angle after its last
+//! one. A looped sequence restarts from the first keyframes, loops are smooth when a sequence
+//! ends where it starts.
+//!
+//! Example:
+//! ```rust
+//! let mut keyframes = Sequence::new();
+//! keyframes.push(Keyframe::parse("0,PWM4_A,0")?)?;
+//! keyframes.push(Keyframe::parse("1000,PWM4_A,180")?)?;
+//! let servos = [Servo::new(gpio!(PWM4_A), top)];
+//! CHOREO.start(&keyframes, &servos, 2)?; // twice
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::config::{self, CONFIG};
+use super::files::{self, FILES};
+use super::pwms;
+use super::scheduler::{self, EntryId, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub use crate::limits::MAX_KEYFRAMES;
+pub const MAX_SERVOS: usize = 8;
+pub const KEYFRAME_SIZE: usize = 6;
+pub const MAX_ANGLE: u8 = 180;
+
+/// Servo PWM frequency, one position update per period
+pub const SERVO_FREQ: u32 = 50;
+const FRAME_MS: u32 = 1000 / SERVO_FREQ;
+
+/// Pulse widths of the 0 and 180 degree positions
+const MIN_PULSE_US: u32 = 1000;
+const MAX_PULSE_US: u32 = 2000;
+
+const EXTENSION: &str = ".ch";
+
+pub static CHOREO: Choreo = Choreo {
+    inner: Mutex::new(RefCell::new(Inner {
+        keyframes:  Vec::new(),
+        servos:     Vec::new(),
+        elapsed_ms: 0,
+        repeat:     0,
+        cycles:     0,
+        entry:      None,
+    })),
+};
+
+pub type Sequence = Vec<Keyframe, MAX_KEYFRAMES>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Keyframe
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Keyframe {
+    /// Offset from the sequence start
+    pub time_ms: u32,
+    /// Servo pin
+    pub gpio:    u8,
+    /// 0 - 180 degrees
+    pub angle:   u8,
+}
+
+impl Keyframe {
+    /// Parses "time_ms,servo,angle", the servo is a gpio or an alias
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split(',').map(str::trim);
+        let (Some(time), Some(pin), Some(angle), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(Error::InvalidLine);
+        };
+
+        let time_ms = time.parse().map_err(|_| Error::InvalidLine)?;
+        let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+        let angle = angle
+            .parse()
+            .ok()
+            .filter(|angle| *angle <= MAX_ANGLE)
+            .ok_or(Error::InvalidAngle)?;
+
+        Ok(Self { time_ms, gpio, angle })
+    }
+
+    fn to_bytes(self) -> [u8; KEYFRAME_SIZE] {
+        let time = self.time_ms.to_le_bytes();
+        [time[0], time[1], time[2], time[3], self.gpio, self.angle]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            time_ms: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            gpio:    bytes[4],
+            angle:   bytes[5],
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Servo
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// PWM output of a servo, its slice has to run at `SERVO_FREQ`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Servo {
+    pub gpio: u8,
+    /// Slice top, sets the compare level of a pulse width
+    pub top:  u16,
+}
+
+impl Servo {
+    pub fn new(gpio: u8, top: u16) -> Self {
+        Self { gpio, top }
+    }
+
+    /// Writes the compare level of the angle to the channel register
+    fn set_angle(&self, angle: u32) {
+        let us = MIN_PULSE_US + angle * (MAX_PULSE_US - MIN_PULSE_US) / MAX_ANGLE as u32;
+        let cc = pwms::calculate_duty_from_us(us as u16, SERVO_FREQ, self.top.saturating_add(1));
+        pwms::write_channel_cc(self.gpio, cc);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Choreo
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    keyframes:  Sequence,
+    servos:     Vec<Servo, MAX_SERVOS>,
+    elapsed_ms: u32,
+    /// Cycles to play, 0 until stopped
+    repeat:     u32,
+    cycles:     u32,
+    entry:      Option<EntryId>,
+}
+
+pub struct Choreo {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Choreo {
+    /// Starts playing a sequence, stopping the current one. Every keyframe servo needs an entry
+    /// in `servos`.
+    pub fn start(&self, keyframes: &[Keyframe], servos: &[Servo], repeat: u32) -> Result<()> {
+        check_order(keyframes)?;
+        if keyframes.is_empty() {
+            return Err(Error::Empty);
+        }
+        if let Some(keyframe) = keyframes
+            .iter()
+            .find(|keyframe| !servos.iter().any(|servo| servo.gpio == keyframe.gpio))
+        {
+            return Err(Error::NoServo(keyframe.gpio));
+        }
+        self.stop();
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.keyframes = Vec::from_slice(keyframes).map_err(|_| Error::Full)?;
+            inner.servos = Vec::from_slice(servos).map_err(|_| Error::TooManyServos)?;
+            inner.elapsed_ms = 0;
+            inner.repeat = repeat;
+            inner.cycles = 0;
+            inner.update();
+            inner.entry = Some(SCHEDULER.schedule_in(FRAME_MS * 1000, step, 0)?);
+            Ok(())
+        })
+    }
+
+    /// Stops the sequence, the servos hold their position
+    pub fn stop(&self) {
+        let entry = with(|cs| self.inner.borrow_ref_mut(cs).entry.take());
+        if let Some(entry) = entry {
+            SCHEDULER.cancel(entry);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).entry.is_some())
+    }
+
+    /// Completed cycles
+    pub fn cycles(&self) -> u32 {
+        with(|cs| self.inner.borrow_ref(cs).cycles)
+    }
+}
+
+impl Inner {
+    fn duration_ms(&self) -> u32 {
+        self.keyframes.last().map_or(0, |keyframe| keyframe.time_ms)
+    }
+
+    /// Moves every servo to its position at `elapsed_ms`
+    fn update(&self) {
+        for servo in &self.servos {
+            if let Some(angle) = angle_at(&self.keyframes, servo.gpio, self.elapsed_ms) {
+                servo.set_angle(angle);
+            }
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback advancing the sequence by one servo frame
+fn step(_ctx: u32) -> Option<u32> {
+    with(|cs| {
+        let mut inner = CHOREO.inner.borrow_ref_mut(cs);
+        inner.entry?;
+
+        let duration_ms = inner.duration_ms();
+        if inner.elapsed_ms >= duration_ms {
+            // End of the sequence, the last positions were set on the previous frame
+            inner.cycles += 1;
+            if inner.repeat != 0 && inner.cycles >= inner.repeat {
+                inner.entry = None;
+                return None;
+            }
+            inner.elapsed_ms = 0;
+        }
+        else {
+            inner.elapsed_ms = (inner.elapsed_ms + FRAME_MS).min(duration_ms);
+        }
+
+        inner.update();
+        Some(FRAME_MS * 1000)
+    })
+}
+
+/// Eased angle of a servo between the keyframes around `time_ms`, x1000 fixed point blend
+fn angle_at(keyframes: &[Keyframe], gpio: u8, time_ms: u32) -> Option<u32> {
+    let mut track = keyframes.iter().filter(|keyframe| keyframe.gpio == gpio);
+    let mut from = track.next()?;
+
+    for to in track {
+        if to.time_ms <= time_ms {
+            from = to;
+            continue;
+        }
+        if from.time_ms > time_ms {
+            break;
+        }
+
+        let span = (to.time_ms - from.time_ms) as u64;
+        let t = ((time_ms - from.time_ms) as u64 * 1000 / span) as i64;
+        let eased = ease_in_out(t);
+        let (a, b) = (from.angle as i64, to.angle as i64);
+        return Some((a + (b - a) * eased / 1000) as u32);
+    }
+    Some(from.angle as u32)
+}
+
+/// Smoothstep 3t² - 2t³, t and the result in thousandths
+fn ease_in_out(t: i64) -> i64 {
+    t * t * (3000 - 2 * t) / 1_000_000
+}
+
+/// Keyframes have to be sorted by time
+fn check_order(keyframes: &[Keyframe]) -> Result<()> {
+    match keyframes
+        .windows(2)
+        .all(|pair| pair[0].time_ms <= pair[1].time_ms)
+    {
+        true => Ok(()),
+        false => Err(Error::Unordered),
+    }
+}
+
+/// Servo pins of a sequence, in order of first use
+pub fn servo_pins(keyframes: &[Keyframe]) -> Result<Vec<u8, MAX_SERVOS>> {
+    let mut pins = Vec::new();
+    for keyframe in keyframes {
+        if !pins.contains(&keyframe.gpio) {
+            pins.push(keyframe.gpio).map_err(|_| Error::TooManyServos)?;
+        }
+    }
+    Ok(pins)
+}
+
+/// Stores a sequence in the file store, in RAM until the store is saved
+pub fn save(name: &str, keyframes: &[Keyframe]) -> Result<()> {
+    check_order(keyframes)?;
+
+    let mut data = Vec::<u8, { MAX_KEYFRAMES * KEYFRAME_SIZE }>::new();
+    for keyframe in keyframes {
+        data.extend_from_slice(&keyframe.to_bytes())
+            .map_err(|_| Error::Full)?;
+    }
+    FILES.write(&file_name(name)?, &data, false)?;
+    Ok(())
+}
+
+/// Reads a sequence from the file store
+pub fn load(name: &str) -> Result<Sequence> {
+    let data = FILES.read(&file_name(name)?).map_err(|_| Error::NotFound)?;
+    if data.len() % KEYFRAME_SIZE != 0 {
+        return Err(Error::InvalidFile);
+    }
+
+    let mut keyframes = Sequence::new();
+    for bytes in data.chunks(KEYFRAME_SIZE) {
+        keyframes
+            .push(Keyframe::from_bytes(bytes))
+            .map_err(|_| Error::Full)?;
+    }
+    Ok(keyframes)
+}
+
+/// Removes a sequence from the file store
+pub fn remove(name: &str) -> Result<()> {
+    match FILES.remove(&file_name(name)?) {
+        true => Ok(()),
+        false => Err(Error::NotFound),
+    }
+}
+
+/// Calls `f` with the name and keyframe count of every stored sequence
+pub fn for_each(mut f: impl FnMut(&str, usize)) {
+    FILES.for_each(|file, size| {
+        if let Some(name) = file.strip_suffix(EXTENSION) {
+            f(name, size / KEYFRAME_SIZE);
+        }
+    });
+}
+
+fn file_name(name: &str) -> Result<files::Name> {
+    let mut file = files::Name::new();
+    file.push_str(name).map_err(|_| Error::InvalidName)?;
+    file.push_str(EXTENSION).map_err(|_| Error::InvalidName)?;
+    Ok(file)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid keyframe, expected time_ms,servo,angle")]
+    InvalidLine,
+
+    #[error("angle out of range (0 - 180)")]
+    InvalidAngle,
+
+    #[error("keyframes are not sorted by time")]
+    Unordered,
+
+    #[error("empty sequence")]
+    Empty,
+
+    #[error("sequence full")]
+    Full,
+
+    #[error("too many servos (max 8)")]
+    TooManyServos,
+
+    #[error("no servo output for gpio {0}")]
+    NoServo(u8),
+
+    #[error("sequence not found")]
+    NotFound,
+
+    #[error("sequence name too long")]
+    InvalidName,
+
+    #[error("corrupted sequence file")]
+    InvalidFile,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Files(#[from] files::Error),
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
@@ -0,0 +1,541 @@
+//! Clock Outputs (GPOUT)
+//!
+//! Routes an internal clock to GPIO 21/23/24/25 (clk_gpout0-3) through a fractional divider:
+//! reference clocks for external circuits, or checking the PLL settings with a frequency counter.
+//!
+//! The pin function is switched to CLOCK while the output runs and restored when stopped, so a
+//! pin owned by another peripheral (ex: PWM2_B on GPIO 21) is only borrowed.
+//!
+//! `measure_hz` reads the internal frequency counter (fc0), for the internal clocks or external
+//! ones on GPIO 20/22 (gpin0-1), borrowed the same way during the measurement.
+//!
+//! Diagnostics: oscillator status, PLL parameters and the cause of the last reset (brown-out and
+//! power-on share a flag in the chip reset block).
+//!
+//! Example:
+//! ```rust
+//! let hz = CLKOUT.start(21, Source::Sys, 100.0)?; // 1.25MHz at 125MHz sys clock
+//! CLKOUT.stop(21)?;
+//! let hz = clocks::measure_hz(Source::Gpin0)?; // external clock on GPIO 20
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+use core::str::FromStr;
+use core::sync::atomic::Ordering;
+
+use crate::hal;
+//
+use hal::pac;
+
+use critical_section::{Mutex, with};
+use thiserror::Error;
+
+use super::config::{self, CONFIG};
+use super::device::{SYS_CLK_HZ, XOSC_CRYSTAL_FREQ};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// GPIO of each clk_gpout
+pub const GPOUT_PINS: [u8; 4] = [21, 23, 24, 25];
+/// GPIO of each clk_gpin
+pub const GPIN_PINS: [u8; 2] = [20, 22];
+pub const MAX_DIV: f32 = 16_777_215.0; // 24 bit integer part
+
+const USB_CLK_HZ: u32 = 48_000_000;
+const RTC_CLK_HZ: u32 = 46_875;
+const FUNCSEL_CLOCK: u8 = 8;
+const FC0_INTERVAL: u8 = 10; // ~1ms gate
+const FC0_FRAC_BITS: u32 = 5;
+
+pub static CLKOUT: ClkOut = ClkOut {
+    outputs: Mutex::new(RefCell::new([None; 4])),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Runs `$body` with the control and divider registers of a gpout, they have distinct types
+macro_rules! with_gpout {
+    ($clocks:expr, $index:expr, | $ctrl:ident, $div:ident | $body:expr) => {
+        match $index {
+            0 => {
+                let ($ctrl, $div) = ($clocks.clk_gpout0_ctrl(), $clocks.clk_gpout0_div());
+                $body
+            }
+            1 => {
+                let ($ctrl, $div) = ($clocks.clk_gpout1_ctrl(), $clocks.clk_gpout1_div());
+                $body
+            }
+            2 => {
+                let ($ctrl, $div) = ($clocks.clk_gpout2_ctrl(), $clocks.clk_gpout2_div());
+                $body
+            }
+            _ => {
+                let ($ctrl, $div) = ($clocks.clk_gpout3_ctrl(), $clocks.clk_gpout3_div());
+                $body
+            }
+        }
+    };
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Source
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    PllSys,
+    PllUsb,
+    Rosc,
+    Xosc,
+    Gpin0,
+    Gpin1,
+    Sys,
+    Peri,
+    Usb,
+    Adc,
+    Rtc,
+    Ref,
+}
+
+impl Source {
+    /// Internal clocks, in the frequency counter order
+    pub const INTERNAL: [Source; 10] = [
+        Source::PllSys,
+        Source::PllUsb,
+        Source::Rosc,
+        Source::Xosc,
+        Source::Ref,
+        Source::Sys,
+        Source::Peri,
+        Source::Usb,
+        Source::Adc,
+        Source::Rtc,
+    ];
+
+    /// Nominal frequency, None for the uncalibrated ring oscillator and external clocks
+    pub fn freq_hz(self) -> Option<u32> {
+        match self {
+            Source::PllSys | Source::Sys | Source::Peri => Some(SYS_CLK_HZ.load(Ordering::Relaxed)),
+            Source::PllUsb | Source::Usb | Source::Adc => Some(USB_CLK_HZ),
+            Source::Xosc | Source::Ref => Some(XOSC_CRYSTAL_FREQ),
+            Source::Rtc => Some(RTC_CLK_HZ),
+            Source::Rosc | Source::Gpin0 | Source::Gpin1 => None,
+        }
+    }
+
+    /// Gpout auxsrc, None for the sources that can't be routed to a clock output
+    fn gpout_auxsrc(self) -> Option<u8> {
+        match self {
+            Source::PllSys => Some(0),
+            Source::PllUsb => Some(3),
+            Source::Rosc => Some(4),
+            Source::Xosc => Some(5),
+            Source::Sys => Some(6),
+            Source::Usb => Some(7),
+            Source::Adc => Some(8),
+            Source::Rtc => Some(9),
+            Source::Ref => Some(10),
+            Source::Gpin0 | Source::Gpin1 | Source::Peri => None,
+        }
+    }
+
+    /// Frequency counter fc0_src
+    fn fc0_src(self) -> u8 {
+        match self {
+            Source::PllSys => 1,
+            Source::PllUsb => 2,
+            Source::Rosc => 3,
+            Source::Xosc => 5,
+            Source::Gpin0 => 6,
+            Source::Gpin1 => 7,
+            Source::Ref => 8,
+            Source::Sys => 9,
+            Source::Peri => 10,
+            Source::Usb => 11,
+            Source::Adc => 12,
+            Source::Rtc => 13,
+        }
+    }
+
+    /// Input pin of the external clocks
+    fn gpin(self) -> Option<u8> {
+        match self {
+            Source::Gpin0 => Some(GPIN_PINS[0]),
+            Source::Gpin1 => Some(GPIN_PINS[1]),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pll_sys" => Ok(Source::PllSys),
+            "pll_usb" => Ok(Source::PllUsb),
+            "rosc" => Ok(Source::Rosc),
+            "xosc" => Ok(Source::Xosc),
+            "gpin0" => Ok(Source::Gpin0),
+            "gpin1" => Ok(Source::Gpin1),
+            "sys" => Ok(Source::Sys),
+            "peri" => Ok(Source::Peri),
+            "usb" => Ok(Source::Usb),
+            "adc" => Ok(Source::Adc),
+            "rtc" => Ok(Source::Rtc),
+            "ref" => Ok(Source::Ref),
+            _ => Err(Error::InvalidSource),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::PllSys => "pll_sys",
+            Source::PllUsb => "pll_usb",
+            Source::Rosc => "rosc",
+            Source::Xosc => "xosc",
+            Source::Gpin0 => "gpin0",
+            Source::Gpin1 => "gpin1",
+            Source::Sys => "sys",
+            Source::Peri => "peri",
+            Source::Usb => "usb",
+            Source::Adc => "adc",
+            Source::Rtc => "rtc",
+            Source::Ref => "ref",
+        };
+        f.pad(name)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Clock Out
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Output {
+    pub gpio:     u8,
+    pub source:   Source,
+    pub div:      f32,
+    /// Pin function before the output started
+    prev_funcsel: u8,
+}
+
+impl Output {
+    /// Nominal output frequency
+    pub fn freq_hz(&self) -> Option<f32> {
+        self.source.freq_hz().map(|hz| hz as f32 / self.div)
+    }
+}
+
+pub struct ClkOut {
+    outputs: Mutex<RefCell<[Option<Output>; 4]>>,
+}
+
+impl ClkOut {
+    /// Starts a clock output or changes a running one, returns its nominal frequency.
+    /// The divider has 8 fractional bits, fractional dividers add jitter.
+    pub fn start(&self, gpio: u8, source: Source, div: f32) -> Result<Option<f32>> {
+        let index = gpout_index(gpio)?;
+        let auxsrc = source.gpout_auxsrc().ok_or(Error::NotRoutable)?;
+        CONFIG.check_not_reserved(gpio)?;
+        if !(1.0..=MAX_DIV).contains(&div) {
+            return Err(Error::InvalidDiv);
+        }
+
+        // 16.8 fixed point
+        let raw = (div * 256.0 + 0.5) as u32;
+        let (int, frac) = (raw >> 8, (raw & 0xff) as u8);
+        // Safety: the gpout registers are only written here, under the critical section
+        let clocks = unsafe { hal::regs::clocks() };
+
+        let output = with(|cs| {
+            let mut outputs = self.outputs.borrow_ref_mut(cs);
+            let prev_funcsel = match outputs[index] {
+                Some(output) => output.prev_funcsel,
+                None => funcsel(gpio),
+            };
+
+            // The source can't be changed while enabled (glitches)
+            with_gpout!(clocks, index, |ctrl, div_reg| {
+                ctrl.modify(|_, w| w.enable().clear_bit());
+                div_reg.write(|w| unsafe { w.int().bits(int).frac().bits(frac) });
+                ctrl.modify(|_, w| unsafe { w.auxsrc().bits(auxsrc) });
+                ctrl.modify(|_, w| w.enable().set_bit());
+            });
+            set_funcsel(gpio, FUNCSEL_CLOCK);
+
+            let output = Output {
+                gpio,
+                source,
+                div: raw as f32 / 256.0,
+                prev_funcsel,
+            };
+            outputs[index] = Some(output);
+            output
+        });
+
+        Ok(output.freq_hz())
+    }
+
+    /// Stops a clock output and gives the pin back its previous function
+    pub fn stop(&self, gpio: u8) -> Result<()> {
+        let index = gpout_index(gpio)?;
+        // Safety: see start
+        let clocks = unsafe { hal::regs::clocks() };
+
+        with(|cs| {
+            let output = self.outputs.borrow_ref_mut(cs)[index]
+                .take()
+                .ok_or(Error::NotRunning)?;
+
+            with_gpout!(clocks, index, |ctrl, _div| {
+                ctrl.modify(|_, w| w.enable().clear_bit());
+            });
+            set_funcsel(gpio, output.prev_funcsel);
+            Ok(())
+        })
+    }
+
+    /// Running outputs
+    pub fn outputs(&self) -> impl Iterator<Item = Output> {
+        with(|cs| *self.outputs.borrow_ref(cs))
+            .into_iter()
+            .flatten()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Diagnostics
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct OscStatus {
+    pub enabled:  bool,
+    pub stable:   bool,
+    /// A register was written while the oscillator was running
+    pub badwrite: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PllStatus {
+    pub refdiv:   u8,
+    pub fbdiv:    u16,
+    pub postdiv1: u8,
+    pub postdiv2: u8,
+    pub locked:   bool,
+    pub powered:  bool,
+}
+
+impl PllStatus {
+    pub fn vco_hz(&self) -> u32 {
+        XOSC_CRYSTAL_FREQ / self.refdiv.max(1) as u32 * self.fbdiv as u32
+    }
+
+    pub fn out_hz(&self) -> u32 {
+        self.vco_hz() / (self.postdiv1.max(1) as u32 * self.postdiv2.max(1) as u32)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetCause {
+    /// Power-on or brown-out
+    PowerOn,
+    RunPin,
+    /// Debugger reset
+    Debug,
+    WatchdogTimeout,
+    /// Watchdog forced reset, ex: the reset command
+    WatchdogForce,
+    Unknown,
+}
+
+impl fmt::Display for ResetCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResetCause::PowerOn => "power-on / brown-out",
+            ResetCause::RunPin => "RUN pin",
+            ResetCause::Debug => "debugger",
+            ResetCause::WatchdogTimeout => "watchdog timeout",
+            ResetCause::WatchdogForce => "watchdog forced",
+            ResetCause::Unknown => "unknown",
+        };
+        f.pad(name)
+    }
+}
+
+pub fn xosc_status() -> OscStatus {
+    // Safety: read only
+    let status = unsafe { hal::regs::xosc().status().read() };
+    OscStatus {
+        enabled:  status.enabled().bit(),
+        stable:   status.stable().bit(),
+        badwrite: status.badwrite().bit(),
+    }
+}
+
+pub fn rosc_status() -> OscStatus {
+    // Safety: read only
+    let status = unsafe { hal::regs::rosc().status().read() };
+    OscStatus {
+        enabled:  status.enabled().bit(),
+        stable:   status.stable().bit(),
+        badwrite: false, // not reported by the ROSC
+    }
+}
+
+pub fn pll_sys_status() -> PllStatus {
+    // Safety: read only
+    pll_status(unsafe { hal::regs::pll_sys() })
+}
+
+pub fn pll_usb_status() -> PllStatus {
+    // Safety: read only
+    pll_status(unsafe { hal::regs::pll_usb() })
+}
+
+/// Cause of the last reset, the watchdog reasons have priority over the chip reset flags
+pub fn reset_cause() -> ResetCause {
+    // Safety: read only
+    let reason = unsafe { hal::regs::watchdog().reason().read() };
+    let chip = unsafe { hal::regs::vreg_and_chip_reset().chip_reset().read() };
+
+    match () {
+        _ if reason.timer().bit() => ResetCause::WatchdogTimeout,
+        _ if reason.force().bit() => ResetCause::WatchdogForce,
+        _ if chip.had_psm_restart().bit() => ResetCause::Debug,
+        _ if chip.had_run().bit() => ResetCause::RunPin,
+        _ if chip.had_por().bit() => ResetCause::PowerOn,
+        _ => ResetCause::Unknown,
+    }
+}
+
+/// Brown-out detector enabled and its threshold in volts
+pub fn brown_out() -> (bool, f32) {
+    // Safety: read only
+    let bod = unsafe { hal::regs::vreg_and_chip_reset().bod().read() };
+    (bod.en().bit(), 0.473 + bod.vsel().bits() as f32 * 0.043)
+}
+
+fn pll_status(pll: &pac::pll_sys::RegisterBlock) -> PllStatus {
+    let cs = pll.cs().read();
+    let prim = pll.prim().read();
+    PllStatus {
+        refdiv:   cs.refdiv().bits(),
+        fbdiv:    pll.fbdiv_int().read().fbdiv_int().bits(),
+        postdiv1: prim.postdiv1().bits(),
+        postdiv2: prim.postdiv2().bits(),
+        locked:   cs.lock().bit(),
+        powered:  !pll.pwr().read().pd().bit(),
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Measures a clock with the frequency counter, 1kHz resolution over a ~1ms gate.
+/// External clocks (gpin) have their pin switched to the CLOCK function meanwhile.
+pub fn measure_hz(source: Source) -> Result<u32> {
+    if let Some(gpio) = source.gpin() {
+        CONFIG.check_not_reserved(gpio)?;
+    }
+    // Safety: the frequency counter is only used here, by the CLI core
+    let clocks = unsafe { hal::regs::clocks() };
+
+    let prev_funcsel = source.gpin().map(|gpio| {
+        with(|_| {
+            let prev = funcsel(gpio);
+            set_funcsel(gpio, FUNCSEL_CLOCK);
+            prev
+        })
+    });
+
+    while clocks.fc0_status().read().running().bit_is_set() {}
+    clocks
+        .fc0_ref_khz()
+        .write(|w| unsafe { w.fc0_ref_khz().bits(XOSC_CRYSTAL_FREQ / 1000) });
+    clocks
+        .fc0_interval()
+        .write(|w| unsafe { w.fc0_interval().bits(FC0_INTERVAL) });
+    clocks
+        .fc0_min_khz()
+        .write(|w| unsafe { w.fc0_min_khz().bits(0) });
+    clocks
+        .fc0_max_khz()
+        .write(|w| unsafe { w.fc0_max_khz().bits(0x1ff_ffff) });
+    // Writing the source starts the count
+    clocks
+        .fc0_src()
+        .write(|w| unsafe { w.fc0_src().bits(source.fc0_src()) });
+    while clocks.fc0_status().read().done().bit_is_clear() {}
+
+    let result = clocks.fc0_result().read();
+    let khz = result.khz().bits();
+    let frac = result.frac().bits() as u32;
+
+    if let (Some(gpio), Some(prev)) = (source.gpin(), prev_funcsel) {
+        with(|_| set_funcsel(gpio, prev));
+    }
+
+    Ok(khz * 1000 + ((frac * 1000) >> FC0_FRAC_BITS))
+}
+
+fn gpout_index(gpio: u8) -> Result<usize> {
+    GPOUT_PINS
+        .iter()
+        .position(|&pin| pin == gpio)
+        .ok_or(Error::InvalidPin)
+}
+
+fn funcsel(gpio: u8) -> u8 {
+    // Safety: read only
+    let io = unsafe { hal::regs::io_bank0() };
+    io.gpio(gpio as usize).gpio_ctrl().read().funcsel().bits()
+}
+
+fn set_funcsel(gpio: u8, funcsel: u8) {
+    // Safety: called under a critical section
+    let io = unsafe { hal::regs::io_bank0() };
+    let pads = unsafe { hal::regs::pads_bank0() };
+
+    if funcsel == FUNCSEL_CLOCK {
+        pads.gpio(gpio as usize)
+            .modify(|_, w| w.od().clear_bit().ie().set_bit());
+    }
+    io.gpio(gpio as usize)
+        .gpio_ctrl()
+        .modify(|_, w| unsafe { w.funcsel().bits(funcsel) });
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no clock output on this pin, use gpio 21, 23, 24 or 25")]
+    InvalidPin,
+
+    #[error(
+        "invalid source, expected sys, peri, usb, adc, rtc, ref, xosc, rosc, pll_sys, pll_usb, \
+         gpin0 or gpin1"
+    )]
+    InvalidSource,
+
+    #[error("source can't be routed to a clock output")]
+    NotRoutable,
+
+    #[error("invalid divider, 1.0 to 16777215")]
+    InvalidDiv,
+
+    #[error("clock output not running")]
+    NotRunning,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+}
@@ -0,0 +1,103 @@
+//! Command Timeout Guard
+//!
+//! Commands can declare a timeout (`Command::timeout`, overridden by the "timeout.<cmd>" setting,
+//! 0 disables it). The guard schedules a one shot alarm when the command starts; on expiry the
+//! timer interrupt raises the abort flag, reported by `SERIAL.interrupt_cmd_triggered()` like a
+//! received '~'. Interruptible loops exit, so a stuck driver or missing sensor can't freeze
+//! the CLI.
+//!
+//! Blocking calls that never check the interrupt flag are not aborted.
+//!
+//! Example:
+//! ```rust
+//! CMD_TIMEOUT.arm(5_000)?;
+//! while !SERIAL.interrupt_cmd_triggered() {
+//!     // ...
+//! }
+//! let expired = CMD_TIMEOUT.disarm();
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+
+use super::scheduler::{self, EntryId, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub static CMD_TIMEOUT: CmdTimeout = CmdTimeout {
+    inner: Mutex::new(RefCell::new(Inner {
+        entry:   None,
+        expired: false,
+    })),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Cmd Timeout
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Default)]
+struct Inner {
+    /// Pending alarm
+    entry:   Option<EntryId>,
+    /// Abort flag, kept until disarmed
+    expired: bool,
+}
+
+pub struct CmdTimeout {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl CmdTimeout {
+    /// Starts the guard, replacing a pending one
+    pub fn arm(&self, timeout_ms: u32) -> scheduler::Result<()> {
+        self.disarm();
+
+        with(|cs| {
+            let entry = SCHEDULER.schedule_in(timeout_ms.saturating_mul(1000), expire, 0)?;
+            self.inner.borrow_ref_mut(cs).entry = Some(entry);
+            Ok(())
+        })
+    }
+
+    /// Stops the guard and clears the abort flag, returns true if it had expired
+    pub fn disarm(&self) -> bool {
+        // One critical section: an alarm firing between the cancel and the reset would leave
+        // the abort flag raised for the next commands
+        with(|cs| {
+            let inner = self.inner.replace(cs, Inner::default());
+            if let Some(entry) = inner.entry {
+                SCHEDULER.cancel(entry);
+            }
+            inner.expired
+        })
+    }
+
+    /// True while a guard is pending or expired
+    pub fn is_armed(&self) -> bool {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            inner.entry.is_some() || inner.expired
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).expired)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback raising the abort flag
+fn expire(_ctx: u32) -> Option<u32> {
+    with(|cs| {
+        let mut inner = CMD_TIMEOUT.inner.borrow_ref_mut(cs);
+        inner.entry = None;
+        inner.expired = true;
+    });
+    None
+}
@@ -0,0 +1,189 @@
+//! Analog Comparator
+//!
+//! Emulates a comparator with hysteresis on an ADC channel: core1 converts back to back from
+//! the raw registers (~2us per conversion) and latches an output through the SIO as soon as a
+//! threshold is crossed, tens of microseconds at worst. Meant for overcurrent protection
+//! experiments where the core0 command loop would react too late.
+//!
+//! The output is set when the input rises above `high` and cleared when it falls below `low`,
+//! `invert` swaps the levels. Core0 must keep off the ADC while the comparator runs.
+//! Mocked channels are not seen by core1, the comparator always reads the hardware.
+//!
+//! Example:
+//! ```rust
+//! let setup = Setup::new(0, gpio!(OUT_A), volts_to_raw(2.0), volts_to_raw(1.8));
+//! COMPARATOR.arm();
+//! main_core1::send(EventCore1::Comparator(setup), &mut device.sio_fifo, device.timer)?;
+//! // ...
+//! COMPARATOR.stop();
+//! ```
+
+use crate::hal;
+
+use portable_atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering};
+
+use super::adcs::{self, ADC_MAX};
+use super::{events, flash, gpios};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub static COMPARATOR: Comparator = Comparator {
+    running: AtomicBool::new(false),
+    stop:    AtomicBool::new(false),
+    tripped: AtomicBool::new(false),
+    samples: AtomicU32::new(0),
+    trips:   AtomicU32::new(0),
+    last:    AtomicU16::new(0),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Setup
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Setup {
+    /// ADC channel 0-3
+    pub channel: u8,
+    pub output:  u8,
+    /// Raw trip level
+    pub high:    u16,
+    /// Raw release level, at most `high`
+    pub low:     u16,
+    /// Output low while tripped
+    pub invert:  bool,
+}
+
+impl Setup {
+    pub fn new(channel: u8, output: u8, high: u16, low: u16) -> Self {
+        Self {
+            channel,
+            output,
+            high,
+            low: low.min(high),
+            invert: false,
+        }
+    }
+
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Comparator
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    pub running: bool,
+    pub tripped: bool,
+    pub samples: u32,
+    pub trips:   u32,
+    /// Last raw conversion
+    pub last:    u16,
+}
+
+pub struct Comparator {
+    running: AtomicBool,
+    stop:    AtomicBool,
+    tripped: AtomicBool,
+    samples: AtomicU32,
+    trips:   AtomicU32,
+    last:    AtomicU16,
+}
+
+impl Comparator {
+    /// Clears the stop request and the counters, called before queueing the core1 event.
+    /// A stop requested before core1 picks up the event ends the loop right away.
+    pub fn arm(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.tripped.store(false, Ordering::Relaxed);
+        self.samples.store(0, Ordering::Relaxed);
+        self.trips.store(0, Ordering::Relaxed);
+    }
+
+    /// Comparator loop, runs on core1 until `stop`
+    pub fn run(&self, setup: Setup) {
+        self.running.store(true, Ordering::Release);
+
+        let mut tripped = false;
+        gpios::set_level(setup.output, setup.invert);
+
+        while !self.stop.load(Ordering::Relaxed) {
+            // Parking in RAM while core0 writes to flash
+            if flash::core1_lockout_requested() {
+                flash::core1_lockout();
+            }
+
+            let raw = convert(setup.channel);
+
+            let crossed = match tripped {
+                false => raw > setup.high,
+                true => raw < setup.low,
+            };
+            if crossed {
+                tripped = !tripped;
+                gpios::set_level(setup.output, tripped != setup.invert);
+                self.tripped.store(tripped, Ordering::Relaxed);
+                if tripped {
+                    self.trips.fetch_add(1, Ordering::Relaxed);
+                }
+                // After the output, the event costs the next crossing some µs
+                let state = if tripped { "tripped" } else { "cleared" };
+                events::alarm(format_args!("alarm=comparator state={state}"));
+            }
+
+            self.last.store(raw, Ordering::Relaxed);
+            self.samples.fetch_add(1, Ordering::Relaxed);
+        }
+
+        gpios::set_level(setup.output, setup.invert);
+        self.running.store(false, Ordering::Release);
+    }
+
+    /// Requests the loop to end and waits for core1 to leave it
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        while self.running.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            running: self.is_running(),
+            tripped: self.tripped.load(Ordering::Relaxed),
+            samples: self.samples.load(Ordering::Relaxed),
+            trips:   self.trips.load(Ordering::Relaxed),
+            last:    self.last.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn volts_to_raw(volts: f32) -> u16 {
+    let vref = adcs::vref();
+    (volts.clamp(0.0, vref) / vref * ADC_MAX + 0.5) as u16
+}
+
+/// One shot conversion from the registers, the ADC is enabled by the HAL on core0
+#[inline(always)]
+fn convert(channel: u8) -> u16 {
+    // Safety: core0 keeps off the ADC while the comparator runs
+    let adc = unsafe { hal::regs::adc() };
+
+    adc.cs()
+        .modify(|_, w| unsafe { w.ainsel().bits(channel).start_once().set_bit() });
+    while adc.cs().read().ready().bit_is_clear() {}
+    adc.result().read().result().bits()
+}
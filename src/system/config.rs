@@ -1,10 +1,13 @@
 //! Configuration builder
 //! Provides pin initialization, and data regarding aliases, gpio, and function groups
+//!
+//! Pins in the Reserved group are never taken, remapped or driven: taking or resolving them
+//! for a command returns `Error::PinReserved`.
 
 use core::fmt;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use rp2040_hal as hal;
+use crate::hal;
 //
 use hal::gpio;
 use hal::gpio::{AnyPin, DynPinId, DynPullType, FunctionNull, PullDown};
@@ -13,13 +16,18 @@ use heapless::Vec;
 use once_cell::sync::Lazy;
 use thiserror::Error;
 
+use super::board::BOARD;
+use crate::limits::PINOUT_CAPACITY;
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Globals
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
-pub static CONFIG: Lazy<Config> = Lazy::new(|| Config::new(crate::pin_config::PIN_DEFINITION));
+pub static CONFIG: Lazy<Config> =
+    Lazy::new(|| Config::new(&[crate::pin_config::PIN_DEFINITION, BOARD.pins]));
 
-const PINOUT_CAPACITY: usize = 30;
+/// Conflicts listed by the build time check
+const MESSAGE_LENGTH: usize = 512;
 
 pub type FullDynPinType = gpio::Pin<gpio::DynPinId, gpio::DynFunction, gpio::DynPullType>;
 pub type RawDynPinType = gpio::Pin<DynPinId, FunctionNull, PullDown>;
@@ -35,30 +43,17 @@ pub struct Config {
 }
 
 impl Config {
-    /// Creates a new Config instance containing the filtered list of pins.
-    /// Does some basic safety checks to ensure a valid configuration.
-    fn new(definition: &'static [Def]) -> Self {
+    /// Creates a new Config instance containing the filtered list of pins from the definition parts.
+    /// The parts are checked at build time, see `check_pins!` in pin_config.rs.
+    fn new(parts: &[&'static [Def]]) -> Self {
         //
 
-        // Pre-check pin id
-        let mut seen = [false; PINOUT_CAPACITY];
-        for def in definition {
-            if let PinId::Gpio(id) = def.id {
-                if id > 29 {
-                    panic!("pin out of bounds: {}", id);
-                }
-
-                if seen[id as usize] {
-                    panic!("duplicate config pin: {}", id); // duplicate found
-                }
-                seen[id as usize] = true;
-            }
-        }
-
         // Creating pin alias definitions
         let mut pins = Vec::<PinDef, PINOUT_CAPACITY>::new();
 
-        for f_pin in definition.iter().filter(|def| def.id != PinId::NA) {
+        let definition = parts.iter().flat_map(|part| part.iter());
+
+        for f_pin in definition.filter(|def| def.id != PinId::NA) {
             let id = match f_pin.id {
                 PinId::Gpio(id) => id,
                 _ => unreachable!("config filter fail"),
@@ -127,17 +122,33 @@ impl Config {
             .map(|def| def.group)
     }
 
+    /// Returns true if the gpio belongs to the Reserved group
+    pub fn is_reserved(&self, id: u8) -> bool {
+        self.get_group_type(id) == Some(Group::Reserved)
+    }
+
+    /// Returns `Error::PinReserved` if the gpio belongs to the Reserved group.
+    /// Commands accessing raw gpio numbers should check it before touching the pin.
+    pub fn check_not_reserved(&self, id: u8) -> Result<()> {
+        if self.is_reserved(id) {
+            return Err(Error::PinReserved);
+        }
+        Ok(())
+    }
+
     /// Getting gpio and alias as a pair based on the inputs provided.
-    /// GPIO input has first choice if both are not None.
+    /// GPIO input has first choice if both are not None. Reserved pins are refused.
     pub fn get_gpio_alias_pair(&self, gpio: Option<u8>, alias: Option<&str>) -> Result<(u8, &str)> {
         if let Some(gpio_) = gpio {
             //  Getting alias from gpio
             let alias_ = self.get_alias(gpio_)?;
+            self.check_not_reserved(gpio_)?;
             Ok((gpio_, alias_))
         }
         // Getting gpio from alias
         else if let Some(alias_) = alias {
             let pin = self.get_pin_def_by_alias(alias_)?;
+            self.check_not_reserved(pin.id)?;
             Ok((pin.id, pin.alias))
         }
         else {
@@ -146,7 +157,8 @@ impl Config {
         }
     }
 
-    /// Creates a DynPinId of the requested function and pull type, and marks the pin taken
+    /// Creates a DynPinId of the requested function and pull type, and marks the pin taken.
+    /// Reserved pins are never taken.
     pub fn take_pin<F, P>(&self, id: u8) -> Option<gpio::Pin<DynPinId, F, P>>
     where
         F: gpio::Function,
@@ -154,8 +166,8 @@ impl Config {
     {
         let def = self.pins.iter().find(|pin| pin.id == id)?;
 
-        if def.taken.load(Ordering::Relaxed) {
-            return None; // already taken
+        if def.taken.load(Ordering::Relaxed) || def.group == Group::Reserved {
+            return None; // already taken or reserved
         }
 
         let id = def.id;
@@ -172,6 +184,7 @@ impl Config {
         P: gpio::PullType,
     {
         let id = self.get_pin_def_by_alias(alias)?.id;
+        self.check_not_reserved(id)?;
         self.take_pin(id).ok_or(Error::PinAlreadyConfigured)
     }
 }
@@ -230,6 +243,307 @@ pub enum PinId {
     NA,
 }
 
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Validation
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// A pin definition that can't be used as configured
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub alias:  &'static str,
+    pub id:     u8,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    OutOfBounds,
+    DuplicateGpio(&'static str), // alias already using the gpio
+    DuplicateAlias,
+    NoAdc,
+    WrongFunction(HwFunction), // function of the gpio for the peripheral
+}
+
+/// Hardware function of a gpio, ex: I2C1_SDA
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HwFunction {
+    pub peripheral: &'static str,
+    pub unit:       u8,
+    pub function:   &'static str,
+}
+
+impl HwFunction {
+    /// Returns the function of the gpio for a peripheral group, None for general purpose groups
+    pub const fn of(id: u8, group: Group) -> Option<Self> {
+        let (peripheral, unit, function) = match group {
+            Group::Adc | Group::C1_Adc => {
+                if id < hal::ADC_FIRST_GPIO || id - hal::ADC_FIRST_GPIO >= hal::NUM_ADC_PINS {
+                    return None;
+                }
+                ("ADC", id - hal::ADC_FIRST_GPIO, "")
+            }
+            Group::Pwm | Group::C1_Pwm => ("PWM", (id / 2) % 8, ["A", "B"][id as usize % 2]),
+            Group::I2c | Group::C1_I2c => ("I2C", (id / 2) % 2, ["SDA", "SCL"][id as usize % 2]),
+            Group::Spi | Group::C1_Spi => {
+                ("SPI", (id / 8) % 2, ["RX", "CSN", "SCK", "TX"][id as usize % 4])
+            }
+            Group::Uart | Group::C1_Uart => {
+                ("UART", ((id + 4) / 8) % 2, ["TX", "RX", "CTS", "RTS"][id as usize % 4])
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            peripheral,
+            unit,
+            function,
+        })
+    }
+
+    /// Checks a function alias (ex: I2C1_SDA or C1_PWM3_A) against this function.
+    /// Custom aliases that don't follow the naming are accepted.
+    const fn matches_alias(&self, alias: &str) -> bool {
+        let name = strip_prefix(alias.as_bytes(), b"C1_");
+        let peripheral = self.peripheral.as_bytes();
+        if !starts_with(name, peripheral) {
+            return true;
+        }
+
+        // Unit number, then "_" and the function
+        let rest = name.split_at(peripheral.len()).1;
+        let mut digits = 0;
+        let mut unit: u32 = 0;
+        while digits < rest.len() && rest[digits].is_ascii_digit() && unit <= u8::MAX as u32 {
+            unit = unit * 10 + (rest[digits] - b'0') as u32;
+            digits += 1;
+        }
+        let (number, after) = rest.split_at(digits);
+        if number.is_empty() || unit > u8::MAX as u32 || !(after.is_empty() || after[0] == b'_') {
+            return true;
+        }
+        let function = strip_prefix(after, b"_");
+
+        unit == self.unit as u32 && eq_ignore_case(function, self.function.as_bytes())
+    }
+}
+
+impl Conflict {
+    /// Message of the conflict, built without formatting for the build time check
+    pub const fn message(&self) -> Message {
+        let mut msg = Message::new();
+        msg.push_str(self.alias);
+        match self.reason {
+            Reason::OutOfBounds => {
+                msg.push_gpio(self.id);
+                msg.push_str(" out of bounds");
+            }
+            Reason::DuplicateGpio(other) => {
+                msg.push_gpio(self.id);
+                msg.push_str(" already used by ");
+                msg.push_str(other);
+            }
+            Reason::DuplicateAlias => msg.push_str(": duplicate alias"),
+            Reason::NoAdc => {
+                msg.push_gpio(self.id);
+                msg.push_str(" has no ADC, use GP26-29");
+            }
+            Reason::WrongFunction(function) => {
+                msg.push_gpio(self.id);
+                msg.push_str(" is ");
+                msg.push_function(&function);
+            }
+        }
+        msg
+    }
+}
+
+/// Text built in a const context, `panic!` can only print a `&str` at build time
+pub struct Message {
+    buf: [u8; MESSAGE_LENGTH],
+    len: usize,
+}
+
+impl Message {
+    const fn new() -> Self {
+        Self {
+            buf: [0; MESSAGE_LENGTH],
+            len: 0,
+        }
+    }
+
+    /// Appends the text, truncated when full
+    const fn push_str(&mut self, s: &str) {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() && self.len < MESSAGE_LENGTH {
+            self.buf[self.len] = bytes[i];
+            self.len += 1;
+            i += 1;
+        }
+    }
+
+    const fn push_u8(&mut self, value: u8) {
+        if value >= 100 {
+            self.push_digit(value / 100);
+        }
+        if value >= 10 {
+            self.push_digit(value / 10 % 10);
+        }
+        self.push_digit(value % 10);
+    }
+
+    const fn push_digit(&mut self, digit: u8) {
+        if self.len < MESSAGE_LENGTH {
+            self.buf[self.len] = b'0' + digit;
+            self.len += 1;
+        }
+    }
+
+    /// ": GP<id>"
+    const fn push_gpio(&mut self, id: u8) {
+        self.push_str(": GP");
+        self.push_u8(id);
+    }
+
+    /// Same as the Display of `HwFunction`
+    const fn push_function(&mut self, function: &HwFunction) {
+        self.push_str(function.peripheral);
+        self.push_u8(function.unit);
+        if !function.function.is_empty() {
+            self.push_str("_");
+            self.push_str(function.function);
+        }
+    }
+
+    pub const fn as_str(&self) -> &str {
+        match core::str::from_utf8(self.buf.split_at(self.len).0) {
+            Ok(s) => s,
+            Err(_) => "invalid pin config",
+        }
+    }
+}
+
+/// Checks a definition at build time, the build fails listing the conflicts.
+/// Used through `check_pins!` on PIN_DEFINITION and the pins of each board profile.
+pub const fn assert_valid(parts: &[&'static [Def]]) {
+    let mut msg = Message::new();
+    msg.push_str("invalid pin config:");
+
+    let mut found = false;
+    let mut index = 0;
+    while index < definition_len(parts) {
+        if let Some(conflict) = check_def(parts, index) {
+            msg.push_str("\n  ");
+            msg.push_str(conflict.message().as_str());
+            found = true;
+        }
+        index += 1;
+    }
+
+    if found {
+        panic!("{}", msg.as_str());
+    }
+}
+
+/// Checks a definition of the parts, in order, against the previous ones: duplicate aliases,
+/// out of bounds and duplicate gpios and pins that can't serve the function of their group
+const fn check_def(parts: &[&'static [Def]], index: usize) -> Option<Conflict> {
+    let def = def_at(parts, index);
+
+    let mut i = 0;
+    while i < index {
+        if eq_ignore_case(def_at(parts, i).alias.as_bytes(), def.alias.as_bytes()) {
+            return conflict(def, 0, Reason::DuplicateAlias);
+        }
+        i += 1;
+    }
+
+    let PinId::Gpio(id) = def.id
+    else {
+        return None;
+    };
+
+    if id as usize >= PINOUT_CAPACITY {
+        return conflict(def, id, Reason::OutOfBounds);
+    }
+
+    let mut i = 0;
+    while i < index {
+        let other = def_at(parts, i);
+        if let PinId::Gpio(other_id) = other.id
+            && other_id == id
+        {
+            return conflict(def, id, Reason::DuplicateGpio(other.alias));
+        }
+        i += 1;
+    }
+
+    let is_adc = matches!(def.group, Group::Adc | Group::C1_Adc);
+    match HwFunction::of(id, def.group) {
+        None if is_adc => conflict(def, id, Reason::NoAdc),
+        Some(function) if !function.matches_alias(def.alias) => {
+            conflict(def, id, Reason::WrongFunction(function))
+        }
+        _ => None,
+    }
+}
+
+const fn conflict(def: &'static Def, id: u8, reason: Reason) -> Option<Conflict> {
+    Some(Conflict {
+        alias: def.alias,
+        id,
+        reason,
+    })
+}
+
+/// Number of definitions over all parts
+const fn definition_len(parts: &[&'static [Def]]) -> usize {
+    let mut len = 0;
+    let mut part = 0;
+    while part < parts.len() {
+        len += parts[part].len();
+        part += 1;
+    }
+    len
+}
+
+/// Definition at an index over all parts, in order
+const fn def_at(parts: &[&'static [Def]], mut index: usize) -> &'static Def {
+    let mut part = 0;
+    while index >= parts[part].len() {
+        index -= parts[part].len();
+        part += 1;
+    }
+    &parts[part][index]
+}
+
+// ———————————————————————————————————————— Const Helpers ——————————————————————————————————————————
+
+const fn eq_ignore_case(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if !a[i].eq_ignore_ascii_case(&b[i]) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn starts_with(s: &[u8], prefix: &[u8]) -> bool {
+    s.len() >= prefix.len() && eq_ignore_case(s.split_at(prefix.len()).0, prefix)
+}
+
+const fn strip_prefix<'a>(s: &'a [u8], prefix: &[u8]) -> &'a [u8] {
+    match starts_with(s, prefix) {
+        true => s.split_at(prefix.len()).1,
+        false => s,
+    }
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                              Error
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -245,8 +559,17 @@ pub enum Error {
     #[error("pin already configured")]
     PinAlreadyConfigured,
 
+    #[error("pin reserved, it can't be taken or driven")]
+    PinReserved,
+
     #[error("pin out of bounds")]
     OutOfBounds,
+
+    #[error("invalid pin setup, expected <pin>:out[:low|high] or <pin>:in[:pull]")]
+    InvalidSetup,
+
+    #[error(transparent)]
+    Guard(#[from] super::output_guard::Error),
 }
 
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -259,6 +582,23 @@ impl fmt::Display for Group {
     }
 }
 
+impl fmt::Display for HwFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "{}{}", self.peripheral, self.unit)
+        }
+        else {
+            write!(f, "{}{}_{}", self.peripheral, self.unit, self.function)
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message().as_str())
+    }
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                         Free Functions
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -278,8 +618,8 @@ where
     F: gpio::Function,
     P: gpio::PullType,
 {
-    if gpio_id > 29 {
-        panic!("GPIO > 29")
+    if gpio_id as usize >= hal::NUM_GPIOS {
+        panic!("GPIO out of bounds")
     }
 
     // TODO: check for function
@@ -300,6 +640,15 @@ where
 //                                             Macros
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 
+/// Fails the build if the definition, with the pins of any of the boards, has a conflict.
+/// ex: `check_pins!(PIN_DEFINITION; PICO_PINS, WEACT_PINS);`
+#[macro_export]
+macro_rules! check_pins {
+    ($definition:expr; $($board:expr),+ $(,)?) => {
+        $(const _: () = $crate::system::config::assert_valid(&[$definition, $board]);)+
+    };
+}
+
 #[macro_export]
 macro_rules! gpio {
     ($alias:ident) => {
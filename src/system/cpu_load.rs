@@ -0,0 +1,155 @@
+//! CPU Load
+//!
+//! Approximate utilization of both cores and the share of time spent in the USB interrupt, shown
+//! by the `cpu` command to check whether a control loop fits.
+//!
+//! The cores don't sleep much: the CLI polls the USB while waiting for a line. So idle is the time
+//! spent in the waits wrapped by `idle()` (line input, host connection, the executor WFE, the
+//! core1 loop pause), everything else counts as busy. The USB interrupt is timed by `usb_irq()`
+//! and taken out of the idle time of the core it interrupts.
+//!
+//! Figures cover the last `WINDOW_TICKS` window, rolled over from the system tick.
+//!
+//! Example:
+//! ```rust
+//! let len = cpu_load::idle(|| SERIAL.read_line_blocking(buf)); // waiting for input
+//! let load = cpu_load::load(); // last window
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+
+use super::tick::TICK;
+use crate::main_core1;
+use crate::utils::time;
+
+use crate::hal;
+//
+use hal::sio::Sio;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// 1s
+const WINDOW_TICKS: u32 = 10;
+
+static CPU_LOAD: Mutex<RefCell<Inner>> = Mutex::new(RefCell::new(Inner {
+    idle_since:   [None; 2],
+    idle_us:      [0; 2],
+    usb_us:       0,
+    window_start: 0,
+    last:         None,
+}));
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Load
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Utilization over the last window, in percent
+#[derive(Debug, Copy, Clone)]
+pub struct Load {
+    pub core0:     f32,
+    /// None while core1 is not started
+    pub core1:     Option<f32>,
+    pub usb_irq:   f32,
+    pub window_ms: u32,
+}
+
+struct Inner {
+    /// Start of the current idle section of each core
+    idle_since:   [Option<u64>; 2],
+    idle_us:      [u64; 2],
+    usb_us:       u64,
+    window_start: u64,
+    last:         Option<Load>,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Starts the measurement windows
+pub fn init() {
+    with(|cs| CPU_LOAD.borrow_ref_mut(cs).window_start = time::now_us());
+    TICK.register(roll_window, WINDOW_TICKS).unwrap();
+}
+
+/// Runs `f` counted as idle time of the calling core. Nested sections count once.
+pub fn idle<R>(f: impl FnOnce() -> R) -> R {
+    let core = Sio::core() as usize;
+    let outer = with(|cs| {
+        let mut inner = CPU_LOAD.borrow_ref_mut(cs);
+        let outer = inner.idle_since[core].is_none();
+        if outer {
+            inner.idle_since[core] = Some(time::now_us());
+        }
+        outer
+    });
+
+    let result = f();
+
+    if outer {
+        with(|cs| {
+            let mut inner = CPU_LOAD.borrow_ref_mut(cs);
+            if let Some(since) = inner.idle_since[core].take() {
+                inner.idle_us[core] += time::now_us().saturating_sub(since);
+            }
+        });
+    }
+    result
+}
+
+/// Runs the body of the USB interrupt, timed and taken out of the idle time
+pub fn usb_irq(f: impl FnOnce()) {
+    let start = time::now_us();
+    f();
+    let elapsed = time::now_us().saturating_sub(start);
+
+    let core = Sio::core() as usize;
+    with(|cs| {
+        let mut inner = CPU_LOAD.borrow_ref_mut(cs);
+        inner.usb_us += elapsed;
+        if let Some(since) = inner.idle_since[core].as_mut() {
+            *since += elapsed;
+        }
+    });
+}
+
+/// Last complete window, None during the first one
+pub fn load() -> Option<Load> {
+    with(|cs| CPU_LOAD.borrow_ref(cs).last)
+}
+
+/// Tick task, interrupt context
+fn roll_window() {
+    let now = time::now_us();
+    let core1_running = main_core1::is_running();
+
+    with(|cs| {
+        let mut inner = CPU_LOAD.borrow_ref_mut(cs);
+        let window = now.saturating_sub(inner.window_start).max(1);
+
+        // Idle sections in progress are split at the window boundary
+        let mut busy = [0.0; 2];
+        for core in 0..2 {
+            let mut idle = inner.idle_us[core];
+            if let Some(since) = inner.idle_since[core].as_mut() {
+                idle += now.saturating_sub(*since);
+                *since = now;
+            }
+            inner.idle_us[core] = 0;
+            busy[core] = 100.0 - (idle.min(window) * 100) as f32 / window as f32;
+        }
+
+        inner.last = Some(Load {
+            core0:     busy[0],
+            core1:     core1_running.then_some(busy[1]),
+            usb_irq:   (inner.usb_us.min(window) * 100) as f32 / window as f32,
+            window_ms: (window / 1000) as u32,
+        });
+        inner.usb_us = 0;
+        inner.window_start = now;
+    });
+}
@@ -16,21 +16,45 @@ use core::fmt::Write;
 use core::sync::atomic::{AtomicU32, Ordering};
 
 use super::adcs::Adcs;
+use super::buses::Buses;
+use super::button::BUTTON;
+use super::choreo::CHOREO;
+use super::comparator::COMPARATOR;
 use super::config::{self, CONFIG};
-use super::delay;
 use super::delay::DELAY;
+use super::encoder_sim::ENCODER_SIM;
+use super::files::FILES;
 use super::gpios::{InputType, IoPins, OutputType};
+use super::kv::KV;
+use super::led::Led;
+use super::mirror::MIRRORS;
+use super::playback::PLAYBACK;
 use super::pwms::Pwms;
+use super::regmap::REGMAP;
+use super::rgb_led::RgbDriver;
+use super::rng::Rng;
+use super::scheduler::{self, SCHEDULER};
+use super::secure::SECURE;
 use super::serial_io::{self, SERIAL};
-
+use super::settings::SETTINGS;
+use super::shutdown::{self, SHUTDOWN, Stage};
+use super::tick::{self, TICK};
+use super::tpo::TPO;
+use super::uart::Uarts;
+use super::{audio, auth, cpu_load, delay, identity, power_on, psu, safe_mode, stack_guard};
+
+use crate::drivers::Drivers;
 use crate::drivers::dht22::DHT22;
-use crate::state::State;
+use crate::drivers::hx711::HX711;
+use crate::limits::CORE_QUEUE_DEPTH;
+use crate::state::{self, State};
+use crate::utils::crc;
 use crate::{gpio, main_core1};
 
-use rp2040_hal as hal;
+use crate::hal;
 //
+use hal::dma::DMAExt;
 use hal::fugit::{Duration, MicrosDurationU32};
-use hal::multicore::Multicore;
 use hal::pac::interrupt;
 use hal::sio::SioFifo;
 use hal::timer::{Alarm, Timer};
@@ -45,14 +69,6 @@ use usb_device::class_prelude::*;
 use usb_device::prelude::*;
 use usbd_serial::SerialPort;
 
-// —————————————————————————————————————————————————————————————————————————————————————————————————
-//                                            Bootloader
-// —————————————————————————————————————————————————————————————————————————————————————————————————
-
-#[unsafe(link_section = ".boot2")]
-#[used]
-pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
-
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                           Globals
 // ————————————————————————————————————————————————————————————————————————————————————————————————
@@ -63,11 +79,13 @@ const DEFAULT_PWM_FREQUENCY: u32 = 50; //hz
 pub static SYS_CLK_HZ: AtomicU32 = AtomicU32::new(0);
 
 // Multicore MPMC Queue
-pub static CORE0_QUEUE: Queue<EventCore0, 8> = Queue::new();
+// mpmc is deprecated for not being lock-free, acceptable for retried operations, see CORE1_QUEUE
+#[expect(deprecated)]
+pub static CORE0_QUEUE: Queue<EventCore0, CORE_QUEUE_DEPTH> = Queue::new();
 
 // Interrupts
 static ALARM_0: Mutex<RefCell<Option<timer::Alarm0>>> = Mutex::new(RefCell::new(None));
-const INTERRUPT_0_US: MicrosDurationU32 = MicrosDurationU32::from_ticks(100_000); // 100ms - 10hz
+const INTERRUPT_0_US: MicrosDurationU32 = MicrosDurationU32::from_ticks(tick::TICK_US); // 100ms - 10hz
 
 // ———————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Device
@@ -81,8 +99,13 @@ pub struct Device {
     pub adcs:     Adcs,
     pub inputs:   IoPins<InputType>,
     pub outputs:  IoPins<OutputType>,
-    pub state:    State,
-    pub dht:      DHT22,
+    pub led:      Led,
+    pub buses:    Buses,
+    pub uarts:    Uarts,
+    pub state:    &'static State,
+    pub rng:      Rng,
+    pub drivers:  Drivers,
+    pub hx711:    HX711,
 }
 
 impl Device {
@@ -92,6 +115,7 @@ impl Device {
         let mut pac = pac::Peripherals::take().unwrap();
         let core = pac::CorePeripherals::take().unwrap();
         let mut watchdog = watchdog::Watchdog::new(pac.WATCHDOG);
+        safe_mode::check(&mut watchdog); // Previous run ended during a command
         let sio = sio::Sio::new(pac.SIO);
         let pins = gpio::Pins::new(pac.IO_BANK0, pac.PADS_BANK0, sio.gpio_bank0, &mut pac.RESETS);
         let mut sio_fifo = sio.fifo;
@@ -123,13 +147,35 @@ impl Device {
         let delay = Delay::new(core.SYST, sys_clk_hz);
         delay::init(delay); // Init DELAY Global
 
+        // ———————————————————————————————————————— Settings ——————————————————————————————————————————
+
+        // Persistent settings from flash, loaded before the pin configuration is built
+        // as they may select the board profile
+        SETTINGS.load();
+        KV.load(); // Lifetime counters
+        SERIAL.set_dtr_grace_ms(
+            SETTINGS
+                .get_parsed(serial_io::DTR_GRACE_KEY)
+                .unwrap_or(serial_io::DEFAULT_DTR_GRACE_MS),
+        );
+
+        // Regulator mode saved with power_mode, the Pico's P-Select pin
+        psu::init();
+
+        // Flash unique id, read while core1 can't be running from flash yet
+        identity::init();
+
+        // —————————————————————————————————————————— DMA ——————————————————————————————————————————————
+
+        // Channel 11 drives the CRC sniffer, channel 10 the audio player, the others are free
+        let dma = pac.DMA.split(&mut pac.RESETS);
+        crc::init_sniffer(dma.ch11);
+        audio::init(dma.ch10);
+
         // ————————————————————————————————————————— Core 1 ————————————————————————————————————————————
 
-        let mut mc = Multicore::new(&mut pac.PSM, &mut pac.PPB, &mut sio_fifo);
-        let cores = mc.cores();
-        let core1 = &mut cores[1];
-        let _task = core1
-            .spawn(main_core1::CORE1_STACK.take().unwrap(), move || main_core1::main_core1(timer));
+        // Started now, by the first command needing it or never, see set core1=
+        main_core1::init(main_core1::mode(), &mut sio_fifo, timer);
 
         // ———————————————————————————————————————— USB Bus ———————————————————————————————————————————
 
@@ -156,9 +202,9 @@ impl Device {
         // Usb Device creation using the UsbBus
         let usb_dev = UsbDeviceBuilder::new(usb_bus, UsbVidPid(0x16c0, 0x27dd))
             .strings(&[StringDescriptors::default()
-                .manufacturer("LH Eng")
-                .product("Rpi Pico - USB Serial CLI")
-                .serial_number("0000")])
+                .manufacturer(identity::MANUFACTURER)
+                .product(identity::PRODUCT)
+                .serial_number(identity::serial_number())])
             .unwrap()
             .device_class(usbd_serial::USB_CLASS_CDC)
             .build();
@@ -189,15 +235,26 @@ impl Device {
             pwms.register(pin);
         }
 
+        // Power-on frequency and duty, see pin_config.rs (POWER_ON)
+        power_on::apply_pwm(&mut pwms);
+
         // ———————————————————————————————————— Extra Function Pins ———————————————————————————————————
 
         // SPI, I2C, UART, etc
 
+        let buses =
+            Buses::new(pac.I2C0, pac.I2C1, pac.SPI0, pac.SPI1, &mut pac.RESETS, sys_clk_hz, timer);
+
+        let uarts = Uarts::new(pac.UART0, pac.UART1, &mut pac.RESETS, sys_clk_hz);
+
         // ———————————————————————————————————————— GP Pins ———————————————————————————————————————————
 
         let mut inputs = IoPins::<InputType>::new();
         let mut outputs = IoPins::<OutputType>::new();
 
+        // Power-on levels latched before the output drivers are enabled
+        power_on::latch_outputs();
+
         for id in CONFIG.get_group_iter(config::Group::Inputs) {
             let pin = CONFIG.take_pin(id).unwrap();
             inputs.register(pin);
@@ -208,10 +265,47 @@ impl Device {
             outputs.register(pin);
         }
 
-        // —————————————————————————————————— DHT22 Temp Sensor ————————————————————————————————————
+        // ————————————————————————————————————————— LED ——————————————————————————————————————————————
+
+        // Boards without a GPIO LED (Pico W) run the service without a pin
+        let led_pin = CONFIG
+            .get_gpio("LED")
+            .ok()
+            .and_then(|gpio| outputs.take(gpio));
+        let led = Led::new(led_pin);
+
+        // RGB status LED, when RGB_DATA or RGB_R/G/B are defined in the pin table
+        let rgb = RgbDriver::from_config(pac.PIO0, &mut pac.RESETS, &mut pwms, sys_clk_hz);
+        if let Some(rgb) = rgb {
+            led.set_rgb(rgb);
+        }
+
+        // ———————————————————————————————————————— Drivers ————————————————————————————————————————
+
+        // Listed by drivers, run by drv <name>
+        let mut drivers = Drivers::new();
 
         let dht_pin: OutputType = CONFIG.take_pin(gpio!(DHT22)).unwrap();
-        let dht = DHT22::new(dht_pin, timer);
+        drivers.register(cortex_m::singleton!(: DHT22 = DHT22::new(dht_pin, timer)).unwrap());
+
+        // ————————————————————————————————————— Register Map ——————————————————————————————————
+
+        REGMAP.load(); // Register map definitions from flash
+        FILES.load(); // Saved output captures
+
+        #[cfg(feature = "mock")]
+        super::mock::init(timer); // Time base of the mocked ADC waveforms
+
+        // ———————————————————————————————————— HX711 Load Cell ————————————————————————————————————
+
+        let hx711_sck: OutputType = CONFIG.take_pin(gpio!(HX711_SCK)).unwrap();
+        let hx711_dt: InputType = CONFIG.take_pin(gpio!(HX711_DT)).unwrap();
+        let mut hx711 = HX711::new(hx711_sck, hx711_dt, timer);
+
+        hx711.set_calibration(
+            SETTINGS.get_parsed("hx711.offset").unwrap_or(0),
+            SETTINGS.get_parsed("hx711.scale").unwrap_or(0.0),
+        );
 
         // ————————————————————————————————————— Interrupts ————————————————————————————————————————
 
@@ -228,6 +322,16 @@ impl Device {
             pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_0);
         }
 
+        // ALARM2 - Microsecond scheduler
+        let alarm2 = timer.alarm_2().unwrap();
+        scheduler::init(timer, alarm2);
+        BUTTON.init(); // User key on the scheduler, see button_map
+
+        // Enabling IRQ 2 - ALARM2
+        unsafe {
+            pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_2);
+        }
+
         // Enabling the USB IRQ
         unsafe {
             pac::NVIC::unmask(pac::Interrupt::USBCTRL_IRQ);
@@ -235,7 +339,50 @@ impl Device {
 
         // ————————————————————————————————————————— State ————————————————————————————————————————————
 
-        let state = State::new();
+        let state = state::init();
+
+        // —————————————————————————————————————————— RNG —————————————————————————————————————————————
+
+        let mut rng = Rng::new(pac.ROSC); // Entropy from the ring oscillator
+        SECURE.load(&mut rng); // Pre-shared key and nonce salt of the secure channel
+        auth::init(&mut rng); // Boot nonce of the login challenge
+
+        // ————————————————————————————————————— Stack Guard ——————————————————————————————————————————
+
+        stack_guard::init(); // Stack canaries and core1 heartbeat, checked on the tick
+        #[cfg(feature = "panic-persist")]
+        super::panic::init(); // Morse panic message setting
+        cpu_load::init(); // Busy/idle time of both cores, see the cpu command
+
+        // ——————————————————————————————————————— Shutdown ———————————————————————————————————————————
+
+        // Safe state left by the reset, flash and estop commands, see shutdown.rs
+        SHUTDOWN
+            .register("choreo", Stage::Services, |_| CHOREO.stop())
+            .unwrap();
+        SHUTDOWN
+            .register("playback", Stage::Services, |_| PLAYBACK.stop())
+            .unwrap();
+        SHUTDOWN
+            .register("encoder_sim", Stage::Services, |_| {
+                ENCODER_SIM.stop();
+            })
+            .unwrap();
+        SHUTDOWN
+            .register("comparator", Stage::Services, |_| COMPARATOR.stop())
+            .unwrap();
+        SHUTDOWN
+            .register("mirror", Stage::Services, |_| MIRRORS.clear())
+            .unwrap();
+        SHUTDOWN
+            .register("tpo", Stage::Services, |_| TPO.stop_all())
+            .unwrap();
+        SHUTDOWN
+            .register("outputs", Stage::Outputs, power_on::park_outputs)
+            .unwrap();
+        SHUTDOWN
+            .register("pwm", Stage::Pwm, shutdown::disable_pwm)
+            .unwrap();
 
         // —————————————————————————————————————— Construct ———————————————————————————————————————————
 
@@ -247,8 +394,13 @@ impl Device {
             adcs,
             inputs,
             outputs,
+            led,
+            buses,
+            uarts,
             state,
-            dht,
+            rng,
+            drivers,
+            hx711,
         }
     }
 }
@@ -307,7 +459,7 @@ impl TimerExt for Timer {
 
 /// Reset to USB Flash mode
 pub fn device_reset_to_usb() {
-    rp2040_hal::rom_data::reset_to_usb_boot(0, 0);
+    hal::rom_data::reset_to_usb_boot(0, 0);
 }
 
 /// Reset device
@@ -319,12 +471,11 @@ pub fn device_reset() {
 //                                           Interrupts
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
-/// Interrupt 0
+/// Interrupt 0 - System Tick
+/// Register periodic callbacks through TICK instead of editing this body
 #[pac::interrupt]
 fn TIMER_IRQ_0() {
-    {
-        // Do something here in a timed interrupt
-    }
+    TICK.run();
 
     // Reset interrupt timer
     with(|cs| {
@@ -335,13 +486,26 @@ fn TIMER_IRQ_0() {
     })
 }
 
+/// Interrupt 2 - Microsecond Scheduler
+#[pac::interrupt]
+fn TIMER_IRQ_2() {
+    SCHEDULER.on_interrupt();
+}
+
 /// USB Interrupt
 /// Polling the USB device to keep the connection alive even if we stall
 #[pac::interrupt]
 fn USBCTRL_IRQ() {
-    SERIAL.poll_usb();
-
-    // We search the rx buffer for an interrupt character and flush the rest
-    // If we don't read the data, the interrupt will cause an interrupt storm freezing the device.
-    SERIAL.poll_for_interrupt_cmd();
+    cpu_load::usb_irq(|| {
+        SERIAL.poll_usb();
+
+        // We search the rx buffer for an interrupt character and flush the rest
+        // If we don't read the data, the interrupt will cause an interrupt storm freezing the
+        // device.
+        SERIAL.poll_for_interrupt_cmd();
+
+        // Wakes the async CLI task, new input or a connection change
+        #[cfg(feature = "async")]
+        super::executor::USB_SIGNAL.notify();
+    });
 }
@@ -0,0 +1,313 @@
+//! LED Dimmer
+//!
+//! Perceptual brightness for plain LEDs on PWM pins: brightness is set in percent of the
+//! perceived light and gamma corrected to the duty (see `utils::gamma`), fades and blink patterns
+//! are stepped every 10ms by the microsecond scheduler and keep running between commands.
+//!
+//! `limit` caps the duty cycle, the average current of an LED string driven at its peak current
+//! (ex: a string rated 20mA average on a 50mA constant current driver is limited to 40%).
+//! Blink patterns use the status LED format, a step of 100ms, 1 on and 0 off.
+//!
+//! Example:
+//! ```rust
+//! let setup = Setup::new(gpio!(PWM3_A), top)
+//!     .brightness(30)
+//!     .fade(500)
+//!     .limit(80);
+//! DIMMER.apply(setup)?;
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::led::Pattern;
+use super::pwms;
+use super::scheduler::{self, EntryId, SCHEDULER};
+use crate::utils::gamma;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_LEDS: usize = 8;
+/// PWM frequency of the dimmed pins, above the visible flicker
+pub const LED_FREQ: u32 = 1000;
+
+const TICK_MS: u32 = 10;
+const BLINK_STEP_MS: u32 = 100;
+
+pub static DIMMER: Dimmer = Dimmer {
+    inner: Mutex::new(RefCell::new(Inner {
+        leds:  Vec::new(),
+        entry: None,
+    })),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Setup
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Setup {
+    pub gpio:       u8,
+    /// Slice top, the slice has to run at `LED_FREQ`
+    pub top:        u16,
+    /// Perceived brightness 0 - 100%
+    pub brightness: u8,
+    /// Time to reach the brightness from the current level
+    pub fade_ms:    u32,
+    /// Maximum duty 1 - 100%
+    pub limit:      u8,
+    pub blink:      Option<Pattern>,
+}
+
+impl Setup {
+    pub fn new(gpio: u8, top: u16) -> Self {
+        Self {
+            gpio,
+            top,
+            brightness: 100,
+            fade_ms: 0,
+            limit: 100,
+            blink: None,
+        }
+    }
+
+    pub fn brightness(mut self, brightness: u8) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn fade(mut self, fade_ms: u32) -> Self {
+        self.fade_ms = fade_ms;
+        self
+    }
+
+    pub fn limit(mut self, limit: u8) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn blink(mut self, blink: Option<Pattern>) -> Self {
+        self.blink = blink;
+        self
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Dimmer
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub setup:  Setup,
+    /// Current perceived brightness 0 - 100%
+    pub level:  u8,
+    pub fading: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct DimmedLed {
+    setup:   Setup,
+    /// Brightness level 0 - 255 x256, fixed point for slow fades
+    level:   u32,
+    from:    u32,
+    elapsed: u32,
+    ticks:   u32,
+}
+
+struct Inner {
+    leds:  Vec<DimmedLed, MAX_LEDS>,
+    entry: Option<EntryId>,
+}
+
+pub struct Dimmer {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Dimmer {
+    /// Fades a LED to the setup brightness, from its current level if already dimmed
+    pub fn apply(&self, setup: Setup) -> Result<()> {
+        if setup.brightness > 100 {
+            return Err(Error::InvalidBrightness);
+        }
+        if !(1..=100).contains(&setup.limit) {
+            return Err(Error::InvalidLimit);
+        }
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+
+            let led = match inner
+                .leds
+                .iter()
+                .position(|led| led.setup.gpio == setup.gpio)
+            {
+                Some(index) => &mut inner.leds[index],
+                None => {
+                    let led = DimmedLed {
+                        setup,
+                        level: 0,
+                        from: 0,
+                        elapsed: 0,
+                        ticks: 0,
+                    };
+                    inner.leds.push(led).map_err(|_| Error::Full)?;
+                    inner.leds.last_mut().unwrap()
+                }
+            };
+            led.setup = setup;
+            led.from = led.level;
+            led.elapsed = 0;
+            led.ticks = 0;
+            if setup.fade_ms == 0 {
+                led.level = target(&setup);
+            }
+            led.output();
+
+            if inner.entry.is_none() && inner.leds.iter().any(DimmedLed::is_animated) {
+                inner.entry = Some(SCHEDULER.schedule_in(TICK_MS * 1000, step, 0)?);
+            }
+            Ok(())
+        })
+    }
+
+    /// Stops dimming a LED, the output is turned off. False if it was not dimmed.
+    pub fn release(&self, gpio: u8) -> bool {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let Some(index) = inner.leds.iter().position(|led| led.setup.gpio == gpio)
+            else {
+                return false;
+            };
+            pwms::write_channel_cc(gpio, 0);
+            inner.leds.swap_remove(index);
+            true
+        })
+    }
+
+    pub fn status(&self, gpio: u8) -> Option<Status> {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            let led = inner.leds.iter().find(|led| led.setup.gpio == gpio)?;
+            Some(Status {
+                setup:  led.setup,
+                level:  (led.level / 256 * 100 / 255) as u8,
+                fading: led.level != target(&led.setup),
+            })
+        })
+    }
+
+    /// Calls `f` with the status of every dimmed LED
+    pub fn for_each(&self, mut f: impl FnMut(Status)) {
+        let gpios: Vec<u8, MAX_LEDS> = with(|cs| {
+            self.inner
+                .borrow_ref(cs)
+                .leds
+                .iter()
+                .map(|led| led.setup.gpio)
+                .collect()
+        });
+        for gpio in gpios {
+            if let Some(status) = self.status(gpio) {
+                f(status);
+            }
+        }
+    }
+}
+
+impl DimmedLed {
+    /// Fading or blinking, needs the periodic step
+    fn is_animated(&self) -> bool {
+        self.setup.blink.is_some() || self.level != target(&self.setup)
+    }
+
+    /// Advances the fade and the blink pattern by one tick
+    fn advance(&mut self) {
+        let to = target(&self.setup);
+        if self.level != to {
+            self.elapsed += TICK_MS;
+            self.level = match self.elapsed >= self.setup.fade_ms {
+                true => to,
+                false => {
+                    let span = to as i64 - self.from as i64;
+                    (self.from as i64 + span * self.elapsed as i64 / self.setup.fade_ms as i64)
+                        as u32
+                }
+            };
+        }
+        self.ticks = self.ticks.wrapping_add(1);
+        self.output();
+    }
+
+    /// Writes the gamma corrected and limited duty of the current level and blink step
+    fn output(&self) {
+        let on = match self.setup.blink {
+            Some(pattern) => {
+                let step = self.ticks * TICK_MS / BLINK_STEP_MS % pattern.len().max(1) as u32;
+                pattern.is_on(step as u8)
+            }
+            None => true,
+        };
+
+        let cc = match on {
+            true => {
+                let duty = gamma::duty((self.level / 256) as u8, self.setup.top) as u32;
+                (duty * self.setup.limit as u32 / 100) as u16
+            }
+            false => 0,
+        };
+        pwms::write_channel_cc(self.setup.gpio, cc);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback stepping the fades and blinks, ends when all LEDs are steady
+fn step(_ctx: u32) -> Option<u32> {
+    with(|cs| {
+        let mut inner = DIMMER.inner.borrow_ref_mut(cs);
+        inner.entry?;
+
+        for led in inner.leds.iter_mut().filter(|led| led.is_animated()) {
+            led.advance();
+        }
+
+        if !inner.leds.iter().any(DimmedLed::is_animated) {
+            inner.entry = None;
+            return None;
+        }
+        Some(TICK_MS * 1000)
+    })
+}
+
+/// Target level of a setup, x256 fixed point
+fn target(setup: &Setup) -> u32 {
+    (gamma::level_from_percent(setup.brightness) as u32) << 8
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("brightness out of range (0 - 100%)")]
+    InvalidBrightness,
+
+    #[error("limit out of range (1 - 100%)")]
+    InvalidLimit,
+
+    #[error("too many dimmed LEDs (max 8)")]
+    Full,
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
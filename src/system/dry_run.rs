@@ -0,0 +1,42 @@
+//! Dry Run
+//!
+//! Global switch making the actuator commands (pin, pwm, servo) print what they would do:
+//! resolved pins and computed register values, without touching the hardware. Handy to check
+//! scripts and profiles before running them on a real rig.
+//!
+//! Enabled with `set dryrun=on` or per command with the `dryrun` flag. Kept in RAM only, a reset
+//! always returns to live mode.
+//!
+//! Example:
+//! ```rust
+//! if dry_run::is_active(args.contains_param("dryrun")) {
+//!     println!("[dryrun] GPIO {gpio}: would set HIGH");
+//!     return Ok(());
+//! }
+//! ```
+
+use portable_atomic::{AtomicBool, Ordering};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Global dry run switch
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Dry run requested either globally or by the command flag
+pub fn is_active(flag: bool) -> bool {
+    flag || is_enabled()
+}
@@ -0,0 +1,230 @@
+//! Encoder Simulator
+//!
+//! Quadrature A/B output on two pins, a known-good signal source to test encoder reading
+//! firmware or hardware. Edges are timed by the microsecond scheduler and driven through the
+//! SIO registers, the signal keeps running between and during commands.
+//!
+//! One count is one quadrature edge (4 per cycle). Forward runs have A leading B, `reverse`
+//! swaps the direction. A run stops by itself after `counts` edges, or runs until `stop` when
+//! it is 0.
+//!
+//! `Setup::start_at` delays the first edge to one period after a timer tick, ex: a shared start
+//! tick (see `SCHEDULER.latch_start`).
+//!
+//! Example:
+//! ```rust
+//! let setup = Setup::new(gpio!(OUT_A), gpio!(OUT_B), 1000).counts(400);
+//! ENCODER_SIM.start(setup)?; // 100 cycles at 1000 counts/s
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use thiserror::Error;
+
+use super::gpios;
+use super::scheduler::{self, EntryId, Instant, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MIN_PERIOD_US: u32 = 20;
+pub const MAX_RATE: u32 = 1_000_000 / MIN_PERIOD_US;
+
+pub static ENCODER_SIM: EncoderSim = EncoderSim {
+    state: Mutex::new(RefCell::new(None)),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// (A, B) levels of the quadrature states, forward order
+const SEQUENCE: [(bool, bool); 4] = [(false, false), (true, false), (true, true), (false, true)];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Setup
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Setup {
+    pub a:        u8,
+    pub b:        u8,
+    /// Counts (edges) per second
+    pub rate:     u32,
+    /// Edges to output, 0 runs until stopped
+    pub counts:   u32,
+    pub reverse:  bool,
+    /// Tick the first period starts from, right away if None
+    pub start_at: Option<Instant>,
+}
+
+impl Setup {
+    pub fn new(a: u8, b: u8, rate: u32) -> Self {
+        Self {
+            a,
+            b,
+            rate,
+            counts: 0,
+            reverse: false,
+            start_at: None,
+        }
+    }
+
+    pub fn counts(mut self, counts: u32) -> Self {
+        self.counts = counts;
+        self
+    }
+
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub fn start_at(mut self, at: Instant) -> Self {
+        self.start_at = Some(at);
+        self
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Encoder Sim
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub setup:    Setup,
+    /// Edges output so far
+    pub emitted:  u32,
+    /// Signed position, negative when running in reverse
+    pub position: i64,
+    pub running:  bool,
+}
+
+struct State {
+    setup:   Setup,
+    phase:   usize,
+    emitted: u32,
+    entry:   Option<EntryId>,
+}
+
+pub struct EncoderSim {
+    state: Mutex<RefCell<Option<State>>>,
+}
+
+impl EncoderSim {
+    /// Starts a run from the (LOW, LOW) state, replacing the running one.
+    /// The pins have to be registered as outputs, see `device.outputs`.
+    pub fn start(&self, setup: Setup) -> Result<()> {
+        if !(1..=MAX_RATE).contains(&setup.rate) {
+            return Err(Error::InvalidRate);
+        }
+        if setup.a == setup.b {
+            return Err(Error::SamePins);
+        }
+        self.stop();
+
+        gpios::set_level(setup.a, false);
+        gpios::set_level(setup.b, false);
+
+        with(|cs| {
+            let period_us = period_us(&setup);
+            let entry = match setup.start_at {
+                Some(at) => {
+                    let deadline = scheduler::deadline_after(at, period_us);
+                    SCHEDULER.schedule_at(Instant::from_ticks(deadline), step, 0)?
+                }
+                None => SCHEDULER.schedule_in(period_us, step, 0)?,
+            };
+            self.state.borrow_ref_mut(cs).replace(State {
+                setup,
+                phase: 0,
+                emitted: 0,
+                entry: Some(entry),
+            });
+            Ok(())
+        })
+    }
+
+    /// Stops the run, the pins keep their levels. False if nothing ran.
+    pub fn stop(&self) -> bool {
+        let Some(state) = with(|cs| self.state.borrow_ref_mut(cs).take())
+        else {
+            return false;
+        };
+
+        if let Some(entry) = state.entry {
+            SCHEDULER.cancel(entry);
+        }
+        true
+    }
+
+    /// Last run, kept after it ends until the next start or stop
+    pub fn status(&self) -> Option<Status> {
+        with(|cs| {
+            let state = self.state.borrow_ref(cs);
+            let state = state.as_ref()?;
+            let sign = if state.setup.reverse { -1 } else { 1 };
+            Some(Status {
+                setup:    state.setup,
+                emitted:  state.emitted,
+                position: sign * state.emitted as i64,
+                running:  state.entry.is_some(),
+            })
+        })
+    }
+
+    /// Output pins of a running simulation
+    pub fn pins(&self) -> Option<(u8, u8)> {
+        self.status()
+            .filter(|status| status.running)
+            .map(|status| (status.setup.a, status.setup.b))
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback moving to the next quadrature state
+fn step(_ctx: u32) -> Option<u32> {
+    with(|cs| {
+        let mut state = ENCODER_SIM.state.borrow_ref_mut(cs);
+        let state = state.as_mut()?;
+        let setup = state.setup;
+
+        state.phase = match setup.reverse {
+            false => (state.phase + 1) % 4,
+            true => (state.phase + 3) % 4,
+        };
+        let (a, b) = SEQUENCE[state.phase];
+        gpios::set_level(setup.a, a);
+        gpios::set_level(setup.b, b);
+        state.emitted = state.emitted.wrapping_add(1);
+
+        if setup.counts > 0 && state.emitted >= setup.counts {
+            state.entry = None;
+            return None;
+        }
+        Some(period_us(&setup))
+    })
+}
+
+fn period_us(setup: &Setup) -> u32 {
+    1_000_000 / setup.rate
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("rate out of range (1 - 50000 counts/s)")]
+    InvalidRate,
+
+    #[error("A and B need different pins")]
+    SamePins,
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
@@ -0,0 +1,254 @@
+//! Pin Event Log
+//!
+//! Long running record of the pin changes, for intermittent glitches on soak tests: the edges of
+//! the logged pins are stored in RAM with their 64 bit timer tick (µs since boot), which doesn't
+//! wrap in the lifetime of the device. Logging runs from the microsecond scheduler, independent
+//! of the host: it goes on through serial disconnects and the events are dumped later.
+//!
+//! Recording stops once `limit` events are stored, the first glitches are the ones kept. Later
+//! edges are counted as missed. Sampling is polled every `period_us`, pulses shorter than the
+//! period can be missed.
+//!
+//! The wall clock: the board has no battery backed clock, the host sets the current unix time
+//! once (`eventlog clock=$(date +%s)`), the dump then prints the date of every event next to the
+//! raw tick.
+//!
+//! Example:
+//! ```rust
+//! EVENTLOG.start(1 << gpio!(IN_A), 1_000, 256)?;
+//! // hours later
+//! EVENTLOG.for_each(|event| println!("{} {}", event.time_us, event.gpio));
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use portable_atomic::{AtomicU64, Ordering};
+
+use super::scheduler::{self, SCHEDULER};
+use crate::hal;
+use crate::utils::time;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub use crate::limits::MAX_LOG_EVENTS as MAX_EVENTS;
+pub const MIN_PERIOD_US: u32 = 100;
+
+pub static EVENTLOG: EventLog = EventLog {
+    inner: Mutex::new(RefCell::new(Inner {
+        events:    Vec::new(),
+        pins:      0,
+        levels:    0,
+        period_us: 0,
+        limit:     MAX_EVENTS,
+        missed:    0,
+        running:   false,
+    })),
+};
+
+/// Unix time in µs at boot, 0 while the clock isn't set
+static BOOT_UNIX_US: AtomicU64 = AtomicU64::new(0);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Event Log
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Event {
+    pub time_us: u64,
+    pub gpio:    u8,
+    pub high:    bool,
+}
+
+struct Inner {
+    events:    Vec<Event, MAX_EVENTS>,
+    /// Logged pins mask and their last levels
+    pins:      u32,
+    levels:    u32,
+    period_us: u32,
+    limit:     usize,
+    /// Edges seen once the log was full
+    missed:    u32,
+    running:   bool,
+}
+
+pub struct EventLog {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl EventLog {
+    /// Clears the log and starts recording the edges of the pins in the mask
+    pub fn start(&self, pins: u32, period_us: u32, limit: usize) -> scheduler::Result<()> {
+        self.stop();
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.events.clear();
+            inner.pins = pins;
+            inner.levels = input_levels();
+            inner.period_us = period_us.max(MIN_PERIOD_US);
+            inner.limit = limit.clamp(1, MAX_EVENTS);
+            inner.missed = 0;
+            inner.running = true;
+        });
+
+        if let Err(e) = SCHEDULER.schedule_in(period_us.max(MIN_PERIOD_US), poll, 0) {
+            with(|cs| self.inner.borrow_ref_mut(cs).running = false);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Stops recording, the events are kept
+    pub fn stop(&self) {
+        SCHEDULER.cancel_fn(poll);
+        with(|cs| self.inner.borrow_ref_mut(cs).running = false);
+    }
+
+    pub fn clear(&self) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.events.clear();
+            inner.missed = 0;
+        });
+    }
+
+    pub fn status(&self) -> Status {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            Status {
+                running:   inner.running,
+                pins:      inner.pins,
+                period_us: inner.period_us,
+                len:       inner.events.len(),
+                limit:     inner.limit,
+                missed:    inner.missed,
+            }
+        })
+    }
+
+    /// Calls `f` with every event, oldest first.
+    /// Events are copied out one at a time so `f` runs outside of the critical section.
+    pub fn for_each(&self, mut f: impl FnMut(&Event)) {
+        for i in 0.. {
+            let Some(event) = with(|cs| self.inner.borrow_ref(cs).events.get(i).copied())
+            else {
+                break;
+            };
+            f(&event);
+        }
+    }
+
+    /// Records the edges since the previous poll, returns false once stopped
+    fn record(&self) -> bool {
+        let levels = input_levels();
+        let time_us = time::now_us();
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if !inner.running {
+                return false;
+            }
+
+            let changed = (levels ^ inner.levels) & inner.pins;
+            inner.levels = levels;
+            for gpio in (0..32u8).filter(|gpio| changed & (1 << gpio) != 0) {
+                let event = Event {
+                    time_us,
+                    gpio,
+                    high: levels & (1 << gpio) != 0,
+                };
+                if inner.events.len() >= inner.limit || inner.events.push(event).is_err() {
+                    inner.missed = inner.missed.saturating_add(1);
+                }
+            }
+            true
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub running:   bool,
+    pub pins:      u32,
+    pub period_us: u32,
+    pub len:       usize,
+    pub limit:     usize,
+    pub missed:    u32,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Wall Clock
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// UTC date and time of a timer tick, "2026-10-17 14:03:21.000125"
+#[derive(Debug, Copy, Clone)]
+pub struct DateTime(u64);
+
+impl DateTime {
+    /// Date of a timer tick, None while the clock isn't set
+    pub fn at(time_us: u64) -> Option<Self> {
+        match BOOT_UNIX_US.load(Ordering::Relaxed) {
+            0 => None,
+            boot_us => Some(Self(boot_us + time_us)),
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0 / 1_000_000;
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let time = secs % 86_400;
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:06}",
+            time / 3_600,
+            time % 3_600 / 60,
+            time % 60,
+            self.0 % 1_000_000
+        )
+    }
+}
+
+/// Sets the wall clock from the current unix time in seconds
+pub fn set_clock(unix_s: u64) {
+    let boot_us = unix_s
+        .saturating_mul(1_000_000)
+        .saturating_sub(time::now_us());
+    BOOT_UNIX_US.store(boot_us.max(1), Ordering::Relaxed);
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn input_levels() -> u32 {
+    // Safety: read only register
+    unsafe { hal::regs::sio().gpio_in().read().bits() }
+}
+
+/// Scheduler callback recording the edges, stops with the log
+fn poll(_ctx: u32) -> Option<u32> {
+    match EVENTLOG.record() {
+        true => Some(with(|cs| EVENTLOG.inner.borrow_ref(cs).period_us)),
+        false => None,
+    }
+}
+
+/// Year, month and day of the days since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
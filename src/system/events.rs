@@ -0,0 +1,151 @@
+//! Unsolicited Events
+//!
+//! Hardware events pushed to the host as they happen, host software can subscribe to them
+//! instead of polling. One line per event, `t` is the timer in µs since boot:
+//!   [EVT] pin=IN_A edge=falling t=123456
+//!   [EVT] alarm=guard pin=OUT_A state=tripped t=123456
+//!   [EVT] alarm=comparator state=cleared t=123456
+//!   [EVT] log=warn t=123456 msg=..
+//!
+//! Sources, each toggled on its own and off by default (RAM only):
+//! - `Edge`: edge watch of input pins, polled every POLL_US from the microsecond scheduler
+//! - `Alarm`: output guard and comparator trips
+//! - `Log`: the log lines, printed as events instead of "[WARN ] .."
+//!
+//! Events are queued with `try_println!` from any context, interrupts and core1 included, and
+//! only while a host is connected: they show up between the command outputs, even when the CLI
+//! is idle.
+//!
+//! Example:
+//! ```rust
+//! events::set_enabled(Source::Alarm, true);
+//! events::watch(1 << gpio!(IN_A))?;
+//! events::alarm(format_args!("alarm=guard pin={gpio} state=tripped"));
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::{Mutex, with};
+use portable_atomic::{AtomicU8, AtomicU32, Ordering};
+
+use super::config::CONFIG;
+use super::scheduler::{self, EntryId, SCHEDULER};
+use super::serial_io::SERIAL;
+use crate::utils::time;
+use crate::{hal, try_println};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Edge watch poll period
+pub const POLL_US: u32 = 1_000;
+
+/// Enabled sources, bit per `Source`
+static SOURCES: AtomicU8 = AtomicU8::new(0);
+/// Watched pins and their last levels
+static WATCHED: AtomicU32 = AtomicU32::new(0);
+static LEVELS: AtomicU32 = AtomicU32::new(0);
+static TASK: Mutex<RefCell<Option<EntryId>>> = Mutex::new(RefCell::new(None));
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Source
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    Edge,
+    Alarm,
+    Log,
+}
+
+impl Source {
+    pub const ALL: [Source; 3] = [Source::Edge, Source::Alarm, Source::Log];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Edge => "edge",
+            Source::Alarm => "alarm",
+            Source::Log => "log",
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn set_enabled(source: Source, enabled: bool) {
+    match enabled {
+        true => SOURCES.fetch_or(source.bit(), Ordering::Relaxed),
+        false => SOURCES.fetch_and(!source.bit(), Ordering::Relaxed),
+    };
+}
+
+pub fn is_enabled(source: Source) -> bool {
+    SOURCES.load(Ordering::Relaxed) & source.bit() != 0
+}
+
+/// Watches the edges of the pins in the mask, replacing the previous ones. 0 stops watching.
+pub fn watch(mask: u32) -> scheduler::Result<()> {
+    LEVELS.store(input_levels(), Ordering::Relaxed);
+    WATCHED.store(mask, Ordering::Relaxed);
+
+    if mask == 0 || with(|cs| TASK.borrow_ref(cs).is_some()) {
+        return Ok(());
+    }
+    let task = SCHEDULER.schedule_in(POLL_US, poll, 0)?;
+    with(|cs| TASK.borrow_ref_mut(cs).replace(task));
+    Ok(())
+}
+
+/// Watched pins mask
+pub fn watched() -> u32 {
+    WATCHED.load(Ordering::Relaxed)
+}
+
+/// Emits an alarm event if the source is on, ex: "alarm=guard pin=OUT_A state=tripped"
+pub fn alarm(fields: fmt::Arguments) {
+    if is_enabled(Source::Alarm) {
+        emit(fields);
+    }
+}
+
+/// Queues an event line for the connected host, dropped without a host
+fn emit(fields: fmt::Arguments) {
+    if SERIAL.is_connected() {
+        let _ = try_println!("[EVT] {fields} t={}", time::now_us());
+    }
+}
+
+fn input_levels() -> u32 {
+    // Safety: read only register
+    unsafe { hal::regs::sio().gpio_in().read().bits() }
+}
+
+/// Scheduler callback emitting the edges of the watched pins, stops once none is left
+fn poll(_ctx: u32) -> Option<u32> {
+    let watched = WATCHED.load(Ordering::Relaxed);
+    if watched == 0 {
+        with(|cs| TASK.borrow_ref_mut(cs).take());
+        return None;
+    }
+
+    let levels = input_levels();
+    let changed = (levels ^ LEVELS.swap(levels, Ordering::Relaxed)) & watched;
+    if changed != 0 && is_enabled(Source::Edge) {
+        for gpio in (0..32u8).filter(|gpio| changed & (1 << gpio) != 0) {
+            let edge = if levels & (1 << gpio) != 0 { "rising" } else { "falling" };
+            match CONFIG.get_alias(gpio) {
+                Ok(alias) => emit(format_args!("pin={alias} edge={edge}")),
+                Err(_) => emit(format_args!("pin={gpio} edge={edge}")),
+            }
+        }
+    }
+    Some(POLL_US)
+}
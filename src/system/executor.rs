@@ -0,0 +1,215 @@
+//! Async Executor (async feature)
+//!
+//! Minimal cooperative executor for the async program mode: a fixed set of tasks is polled when
+//! woken, the core sleeps (WFE) while none is ready. No allocation, tasks are pinned on the stack
+//! of `run`, which never returns.
+//!
+//! Wake sources:
+//! - `sleep_us` / `sleep_ms`: one shot alarms of the microsecond scheduler
+//! - `Signal`: notified from interrupts, ex: USB_SIGNAL by the USB interrupt. A notification
+//!   without waiters is latched for the next wait
+//! - `yield_now`: lets the other ready tasks run
+//!
+//! Example:
+//! ```rust
+//! let blink = pin!(async {
+//!     loop {
+//!         device.led.toggle();
+//!         executor::sleep_ms(500).await;
+//!     }
+//! });
+//! executor::run(&mut [blink, other]);
+//! ```
+
+use core::future::{Future, poll_fn};
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use portable_atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+
+use super::scheduler::SCHEDULER;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_TASKS: usize = 8;
+
+/// Notified by the USB interrupt, received data or a connection change
+pub static USB_SIGNAL: Signal = Signal::new();
+
+/// Bit per task to poll
+static READY: AtomicU32 = AtomicU32::new(0);
+/// Task being polled, used by the futures to register their wake up
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+static VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake_waker, wake_waker, drop_waker);
+
+pub type Task<'a> = Pin<&'a mut dyn Future<Output = ()>>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Executor
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Polls the tasks when woken, sleeping in between. Finished tasks are not polled again.
+pub fn run(tasks: &mut [Task<'_>]) -> ! {
+    assert!(tasks.len() <= MAX_TASKS, "too many tasks");
+
+    let mut done = 0u32;
+    READY.store((1 << tasks.len()) - 1, Ordering::Release);
+
+    loop {
+        let ready = READY.swap(0, Ordering::AcqRel) & !done;
+        if ready == 0 {
+            // Woken by any interrupt or SEV
+            super::cpu_load::idle(cortex_m::asm::wfe);
+            continue;
+        }
+
+        for (index, task) in tasks.iter_mut().enumerate() {
+            if ready & (1 << index) == 0 {
+                continue;
+            }
+
+            CURRENT.store(index as u8, Ordering::Relaxed);
+            let waker = task_waker(index);
+            let mut cx = Context::from_waker(&waker);
+            if task.as_mut().poll(&mut cx).is_ready() {
+                done |= 1 << index;
+            }
+        }
+    }
+}
+
+/// Marks a task ready and wakes the core, callable from interrupts
+pub fn wake(index: usize) {
+    READY.fetch_or(1 << index, Ordering::Release);
+    cortex_m::asm::sev();
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Futures
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Completes after `us` microseconds
+pub async fn sleep_us(us: u32) {
+    let deadline = SCHEDULER.now().ticks() + us as u64;
+    let mut armed = false;
+
+    poll_fn(|_| {
+        if SCHEDULER.now().ticks() >= deadline {
+            return Poll::Ready(());
+        }
+        if !armed {
+            let remaining = (deadline - SCHEDULER.now().ticks()) as u32;
+            let task = CURRENT.load(Ordering::Relaxed) as u32;
+            // Polling again right away if the scheduler is full
+            match SCHEDULER.schedule_in(remaining, wake_alarm, task) {
+                Ok(_) => armed = true,
+                Err(_) => wake(task as usize),
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+pub async fn sleep_ms(ms: u32) {
+    sleep_us(ms.saturating_mul(1000)).await
+}
+
+/// Lets the other ready tasks run once
+pub async fn yield_now() {
+    let mut yielded = false;
+
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Signal
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Wakes the tasks waiting on it. A notification without waiters is latched, the next wait
+/// completes at once: an interrupt between the check of the condition and the wait isn't lost.
+pub struct Signal {
+    waiters: AtomicU32,
+    /// Notified since the last completed wait
+    pending: AtomicBool,
+}
+
+impl Signal {
+    pub const fn new() -> Self {
+        Self {
+            waiters: AtomicU32::new(0),
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Wakes every waiting task, callable from interrupts
+    pub fn notify(&self) {
+        self.pending.store(true, Ordering::Release);
+        let waiters = self.waiters.swap(0, Ordering::AcqRel);
+        if waiters != 0 {
+            READY.fetch_or(waiters, Ordering::Release);
+            cortex_m::asm::sev();
+        }
+    }
+
+    /// Completes on the next notification, at once if one came since the last wait, or earlier
+    /// if the task is woken by another source
+    pub async fn wait(&self) {
+        let mut registered = false;
+
+        poll_fn(|_| {
+            if registered {
+                self.pending.store(false, Ordering::Release);
+                return Poll::Ready(());
+            }
+            registered = true;
+            self.waiters
+                .fetch_or(1 << CURRENT.load(Ordering::Relaxed), Ordering::Release);
+            // Checked after the registration, a notification in between is either latched or
+            // wakes the task
+            match self.pending.swap(false, Ordering::AcqRel) {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback waking the task in ctx
+fn wake_alarm(task: u32) -> Option<u32> {
+    wake(task as usize);
+    None
+}
+
+/// The waker data is the task index
+fn task_waker(index: usize) -> Waker {
+    // Safety: the vtable functions only use the data as an index
+    unsafe { Waker::from_raw(RawWaker::new(index as *const (), &VTABLE)) }
+}
+
+fn clone_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &VTABLE)
+}
+
+fn wake_waker(data: *const ()) {
+    wake(data as usize);
+}
+
+fn drop_waker(_data: *const ()) {}
@@ -0,0 +1,246 @@
+//! RAM File Store
+//!
+//! Small named files kept in RAM, written by the CLI output redirection (`read_adc > adc.txt`)
+//! and read back with `cat` / `download`. The whole store fits a flash sector and is
+//! saved with `files save`, then loaded at boot.
+//!
+//! Files are packed in a single buffer: [name len: u8][name][data len: u16 LE][data]
+//!
+//! Example:
+//! ```rust
+//! FILES.write("adc.txt", b"v:1.65V\n", false)?;
+//! let data = FILES.read("adc.txt")?;
+//! FILES.save()?;
+//! ```
+
+use core::cell::RefCell;
+use core::ops::Range;
+
+use critical_section::{Mutex, with};
+use heapless::{String, Vec};
+use thiserror::Error;
+
+use super::flash;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const STORE_SIZE: usize = flash::MAX_RECORD_LENGTH;
+pub const MAX_NAME_LENGTH: usize = 16;
+
+const MAGIC: &[u8; 4] = b"FIL1";
+
+pub static FILES: Files = Files {
+    store: Mutex::new(RefCell::new(Store { buf: Vec::new() })),
+};
+
+pub type Name = String<MAX_NAME_LENGTH>;
+pub type Data = Vec<u8, STORE_SIZE>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Files
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Files {
+    store: Mutex<RefCell<Store>>,
+}
+
+impl Files {
+    /// Loads the files from flash, replacing the ones in RAM.
+    /// Returns the number of loaded files, 0 if the storage is empty or invalid.
+    pub fn load(&self) -> usize {
+        let Some(data) = flash::read_record(flash::FILES_SECTOR, MAGIC)
+        else {
+            return 0;
+        };
+
+        with(|cs| {
+            let mut store = self.store.borrow_ref_mut(cs);
+            store.buf.clear();
+            let _ = store.buf.extend_from_slice(data);
+
+            // Dropping a corrupted store
+            if !store.is_valid() {
+                store.buf.clear();
+            }
+            store.files().count()
+        })
+    }
+
+    /// Saves all files to flash
+    pub fn save(&self) -> Result<()> {
+        let buf = with(|cs| self.store.borrow_ref(cs).buf.clone());
+        flash::write_record(flash::FILES_SECTOR, MAGIC, &buf)?;
+        Ok(())
+    }
+
+    /// Creates, replaces or appends to a file. Returns the new file size.
+    pub fn write(&self, name: &str, data: &[u8], append: bool) -> Result<usize> {
+        let name: Name = valid_name(name)?;
+
+        with(|cs| {
+            let mut store = self.store.borrow_ref_mut(cs);
+
+            let mut content = Data::new();
+            if append && let Some(file) = store.find(&name) {
+                let _ = content.extend_from_slice(&store.buf[file.data]);
+            }
+            content.extend_from_slice(data).map_err(|_| Error::Full)?;
+            if content.len() > u16::MAX as usize {
+                return Err(Error::Full);
+            }
+
+            // Room check before removing the previous version
+            let old_size = store.find(&name).map_or(0, |file| file.record.len());
+            let record_size = 1 + name.len() + 2 + content.len();
+            if store.buf.len() - old_size + record_size > STORE_SIZE {
+                return Err(Error::Full);
+            }
+
+            store.remove(&name);
+            let _ = store.buf.push(name.len() as u8);
+            let _ = store.buf.extend_from_slice(name.as_bytes());
+            let _ = store
+                .buf
+                .extend_from_slice(&(content.len() as u16).to_le_bytes());
+            let _ = store.buf.extend_from_slice(&content);
+            Ok(content.len())
+        })
+    }
+
+    /// Returns a copy of the file content
+    pub fn read(&self, name: &str) -> Result<Data> {
+        with(|cs| {
+            let store = self.store.borrow_ref(cs);
+            let file = store.find(name).ok_or(Error::NotFound)?;
+            Ok(Vec::from_slice(&store.buf[file.data]).unwrap_or_default())
+        })
+    }
+
+    /// Removes a file. Returns false if it was not found.
+    pub fn remove(&self, name: &str) -> bool {
+        with(|cs| self.store.borrow_ref_mut(cs).remove(name))
+    }
+
+    /// Removes all files from RAM
+    pub fn clear(&self) {
+        with(|cs| self.store.borrow_ref_mut(cs).buf.clear())
+    }
+
+    /// Bytes used in the store, headers included
+    pub fn used(&self) -> usize {
+        with(|cs| self.store.borrow_ref(cs).buf.len())
+    }
+
+    /// Calls `f` with the name and size of every file.
+    /// Files are copied out one at a time so `f` runs outside of the critical section.
+    pub fn for_each(&self, mut f: impl FnMut(&str, usize)) {
+        for i in 0.. {
+            let Some((name, size)) = with(|cs| {
+                let store = self.store.borrow_ref(cs);
+                store.files().nth(i).map(|file| {
+                    let name = Name::try_from(store.name(&file)).unwrap_or_default();
+                    (name, file.data.len())
+                })
+            })
+            else {
+                break;
+            };
+            f(&name, size);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Store
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Location of a file in the store buffer
+struct Entry {
+    record: Range<usize>,
+    name:   Range<usize>,
+    data:   Range<usize>,
+}
+
+struct Store {
+    buf: Vec<u8, STORE_SIZE>,
+}
+
+impl Store {
+    fn files(&self) -> impl Iterator<Item = Entry> + '_ {
+        let mut pos = 0;
+        core::iter::from_fn(move || {
+            let name_len = *self.buf.get(pos)? as usize;
+            let name = pos + 1..pos + 1 + name_len;
+            let len = self.buf.get(name.end..name.end + 2)?;
+            let data_start = name.end + 2;
+            let data = data_start..data_start + u16::from_le_bytes([len[0], len[1]]) as usize;
+
+            if data.end > self.buf.len() {
+                return None;
+            }
+
+            let record = pos..data.end;
+            pos = data.end;
+            Some(Entry { record, name, data })
+        })
+    }
+
+    fn name(&self, file: &Entry) -> &str {
+        core::str::from_utf8(&self.buf[file.name.clone()]).unwrap_or("")
+    }
+
+    fn find(&self, name: &str) -> Option<Entry> {
+        self.files().find(|file| self.name(file) == name)
+    }
+
+    /// True if the records cover the whole buffer
+    fn is_valid(&self) -> bool {
+        self.files().last().map_or(0, |file| file.record.end) == self.buf.len()
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let Some(file) = self.find(name)
+        else {
+            return false;
+        };
+
+        let len = self.buf.len();
+        self.buf
+            .copy_within(file.record.end..len, file.record.start);
+        self.buf.truncate(len - file.record.len());
+        true
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("file not found")]
+    NotFound,
+
+    #[error("invalid file name, max 16 chars without spaces")]
+    InvalidName,
+
+    #[error("file store full")]
+    Full,
+
+    #[error(transparent)]
+    Flash(#[from] flash::Error),
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn valid_name(name: &str) -> Result<Name> {
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '>') {
+        return Err(Error::InvalidName);
+    }
+    Name::try_from(name).map_err(|_| Error::InvalidName)
+}
@@ -0,0 +1,409 @@
+//! Onboard Flash Storage
+//!
+//! Erase and program access to the storage region reserved at the end of the onboard QSPI flash
+//! (see memory.x). Flash operations disable XIP, so they run from RAM with interrupts disabled
+//! while core1 is parked in a RAM loop.
+//!
+//! Core1 has to poll `core1_lockout_requested()` and call `core1_lockout()` when requested.
+//!
+//! Example:
+//! ```rust
+//! flash::write_text(flash::SETTINGS_SECTOR, b"CFG1", "key=value\n")?;
+//!
+//! let text = flash::read_text(flash::SETTINGS_SECTOR, b"CFG1");
+//! ```
+
+use crate::hal;
+//
+use hal::rom_data;
+
+use portable_atomic::{AtomicU8, Ordering};
+use thiserror::Error;
+
+use crate::system::delay::DELAY;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const XIP_BASE: u32 = 0x1000_0000;
+pub const FLASH_SIZE: u32 = 2048 * 1024; // 2MB - Pico W25Q16
+
+pub const SECTOR_SIZE: usize = 4096;
+pub const PAGE_SIZE: usize = 256;
+const BLOCK_ERASE_CMD: u8 = 0x20; // 4K sector erase
+const UNIQUE_ID_CMD: u8 = 0x4b; // Followed by 4 dummy bytes, then 8 id bytes
+
+// QSPI chip select override and SSI registers, driven by hand for raw flash commands
+const QSPI_SS_CTRL: u32 = 0x4001_800c;
+const SSI_SR: u32 = 0x1800_0028;
+const SSI_DR0: u32 = 0x1800_0060;
+const SS_OUTOVER_LOW: u32 = 2 << 8;
+const SS_OUTOVER_HIGH: u32 = 3 << 8;
+const SSI_SR_TFNF: u32 = 1 << 1;
+const SSI_SR_RFNE: u32 = 1 << 3;
+const SSI_FIFO_DEPTH: u32 = 16;
+
+// Chip select released to read the BOOTSEL button wired to it
+const SIO_GPIO_HI_IN: u32 = 0xd000_0008;
+const SS_HI_IN_BIT: u32 = 1 << 1;
+const SS_OEOVER_MASK: u32 = 3 << 12;
+const SS_OEOVER_DISABLE: u32 = 2 << 12;
+/// Reads left for the pull-up to settle the pin, about 2000 cycles
+const SS_SETTLE_READS: u32 = 500;
+
+/// Storage region at the end of the flash, excluded from the FLASH region in memory.x
+pub const STORAGE_SIZE: u32 = 28 * 1024;
+pub const STORAGE_OFFSET: u32 = FLASH_SIZE - STORAGE_SIZE;
+
+// Storage sectors, allocated downwards from the end of the flash
+pub const SETTINGS_SECTOR: u32 = FLASH_SIZE - SECTOR_SIZE as u32;
+pub const REGMAP_SECTOR: u32 = SETTINGS_SECTOR - SECTOR_SIZE as u32;
+pub const FILES_SECTOR: u32 = REGMAP_SECTOR - SECTOR_SIZE as u32;
+/// Journal ring of the key-value store, first of `KV_SECTORS` sectors
+pub const KV_SECTORS: u32 = 3;
+pub const KV_SECTOR: u32 = FILES_SECTOR - KV_SECTORS * SECTOR_SIZE as u32;
+/// Pre-shared key of the secure command channel
+pub const SECURE_SECTOR: u32 = KV_SECTOR - SECTOR_SIZE as u32;
+
+/// Records start with a 4 byte magic and the data length
+const RECORD_HEADER_SIZE: usize = 8;
+pub const MAX_RECORD_LENGTH: usize = SECTOR_SIZE - RECORD_HEADER_SIZE;
+pub const MAX_TEXT_LENGTH: usize = MAX_RECORD_LENGTH;
+
+const LOCKOUT_TIMEOUT_MS: u32 = 200;
+
+// Core1 lockout states
+const UNLOCKED: u8 = 0;
+const REQUESTED: u8 = 1;
+const PARKED: u8 = 2;
+
+static CORE1_LOCKOUT: AtomicU8 = AtomicU8::new(UNLOCKED);
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Flash Ops
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Reads `len` bytes from a flash offset through XIP
+pub fn read(offset: u32, len: usize) -> &'static [u8] {
+    assert!(offset as usize + len <= FLASH_SIZE as usize, "flash read out of bounds");
+    unsafe { core::slice::from_raw_parts((XIP_BASE + offset) as *const u8, len) }
+}
+
+/// Erases and programs a full sector of the storage region
+pub fn write_sector(offset: u32, data: &[u8; SECTOR_SIZE]) -> Result<()> {
+    check_offset(offset, SECTOR_SIZE)?;
+    flash_op(offset, data, true)
+}
+
+/// Programs a page of the storage region without erasing it. Bits only go from 1 to 0:
+/// 0xFF bytes leave the flash content as it is.
+pub fn program_page(offset: u32, data: &[u8; PAGE_SIZE]) -> Result<()> {
+    check_offset(offset, PAGE_SIZE)?;
+    flash_op(offset, data, false)
+}
+
+fn check_offset(offset: u32, align: usize) -> Result<()> {
+    match offset >= STORAGE_OFFSET && offset < FLASH_SIZE && (offset as usize).is_multiple_of(align)
+    {
+        true => Ok(()),
+        false => Err(Error::InvalidOffset),
+    }
+}
+
+/// Programs `data` at `offset`, erasing the sector first if `erase`
+fn flash_op(offset: u32, data: &[u8], erase: bool) -> Result<()> {
+    // The rom functions have to be looked up while XIP is still available
+    let rom = RomFns {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip:         rom_data::flash_exit_xip::ptr(),
+        flash_range_erase:      rom_data::flash_range_erase::ptr(),
+        flash_range_program:    rom_data::flash_range_program::ptr(),
+        flash_flush_cache:      rom_data::flash_flush_cache::ptr(),
+        flash_enter_cmd_xip:    rom_data::flash_enter_cmd_xip::ptr(),
+    };
+
+    // Copy of the second stage bootloader, used to restore the fast XIP mode
+    let mut boot2 = [0u32; 64];
+    unsafe {
+        core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), boot2.len());
+    }
+
+    lock_core1()?;
+
+    cortex_m::interrupt::free(|_| unsafe {
+        erase_and_program(&rom, boot2.as_ptr(), offset, data.as_ptr(), data.len(), erase);
+    });
+
+    unlock_core1();
+    Ok(())
+}
+
+/// Reads the 64 bit unique id of the flash chip, the board serial number.
+/// Runs without the core1 lockout: call it at boot before core1 is spawned.
+pub fn read_unique_id() -> u64 {
+    let rom = RomFns {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip:         rom_data::flash_exit_xip::ptr(),
+        flash_range_erase:      rom_data::flash_range_erase::ptr(),
+        flash_range_program:    rom_data::flash_range_program::ptr(),
+        flash_flush_cache:      rom_data::flash_flush_cache::ptr(),
+        flash_enter_cmd_xip:    rom_data::flash_enter_cmd_xip::ptr(),
+    };
+
+    let mut boot2 = [0u32; 64];
+    unsafe {
+        core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), boot2.len());
+    }
+
+    let (mut hi, mut lo) = (0, 0);
+    cortex_m::interrupt::free(|_| unsafe {
+        unique_id_cmd(&rom, boot2.as_ptr(), &mut hi, &mut lo);
+    });
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Level of the QSPI chip select pin with its output released, low while BOOTSEL is held.
+/// The flash is unreachable meanwhile, so core1 is parked like for a flash operation.
+pub fn read_qspi_ss() -> Result<bool> {
+    lock_core1()?;
+    let level = cortex_m::interrupt::free(|_| unsafe { qspi_ss_level() });
+    unlock_core1();
+    Ok(level)
+}
+
+/// Same without the lockout, for interrupt handlers: None while core1 runs, it can't be parked
+/// from there
+pub fn try_read_qspi_ss() -> Option<bool> {
+    if crate::main_core1::is_running() {
+        return None;
+    }
+    Some(cortex_m::interrupt::free(|_| unsafe { qspi_ss_level() }))
+}
+
+/// Reads a record written with `write_record`. Returns None if the sector holds no valid record.
+pub fn read_record(offset: u32, magic: &[u8; 4]) -> Option<&'static [u8]> {
+    let header = read(offset, RECORD_HEADER_SIZE);
+    if &header[..4] != magic {
+        return None;
+    }
+
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > MAX_RECORD_LENGTH {
+        return None;
+    }
+
+    Some(read(offset + RECORD_HEADER_SIZE as u32, len))
+}
+
+/// Writes a data record to a storage sector
+pub fn write_record(offset: u32, magic: &[u8; 4], data: &[u8]) -> Result<()> {
+    if data.len() > MAX_RECORD_LENGTH {
+        return Err(Error::TooLarge);
+    }
+
+    let mut sector = [0xFF; SECTOR_SIZE];
+    sector[..4].copy_from_slice(magic);
+    sector[4..RECORD_HEADER_SIZE].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    sector[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + data.len()].copy_from_slice(data);
+
+    write_sector(offset, &sector)
+}
+
+/// Reads a text record written with `write_text`. Returns None if the sector holds no valid record.
+pub fn read_text(offset: u32, magic: &[u8; 4]) -> Option<&'static str> {
+    core::str::from_utf8(read_record(offset, magic)?).ok()
+}
+
+/// Writes a text record to a storage sector
+pub fn write_text(offset: u32, magic: &[u8; 4], text: &str) -> Result<()> {
+    write_record(offset, magic, text.as_bytes())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Core1 Lockout
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Returns true when core0 is waiting for core1 to park
+#[inline]
+pub fn core1_lockout_requested() -> bool {
+    CORE1_LOCKOUT.load(Ordering::Acquire) == REQUESTED
+}
+
+/// Parks core1 until the flash operation is finished.
+/// This should be only called by core1 when `core1_lockout_requested()` is true.
+pub fn core1_lockout() {
+    cortex_m::interrupt::free(|_| park());
+}
+
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+fn park() {
+    // Core0 may have given up waiting, then there is nothing to park for
+    if CORE1_LOCKOUT
+        .compare_exchange(REQUESTED, PARKED, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return;
+    }
+    while CORE1_LOCKOUT.load(Ordering::Acquire) != UNLOCKED {
+        core::hint::spin_loop();
+    }
+}
+
+/// Requests core1 to park in RAM and waits for it, nothing to wait for before it is started
+fn lock_core1() -> Result<()> {
+    if !crate::main_core1::is_running() {
+        return Ok(());
+    }
+    CORE1_LOCKOUT.store(REQUESTED, Ordering::Release);
+
+    for _ in 0..LOCKOUT_TIMEOUT_MS * 10 {
+        if CORE1_LOCKOUT.load(Ordering::Acquire) == PARKED {
+            return Ok(());
+        }
+        DELAY.us(100);
+    }
+
+    // Core1 may park between the last check and the withdrawal, the write can then go ahead
+    match CORE1_LOCKOUT.compare_exchange(REQUESTED, UNLOCKED, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Err(Error::Core1Busy),
+        Err(_) => Ok(()),
+    }
+}
+
+fn unlock_core1() {
+    CORE1_LOCKOUT.store(UNLOCKED, Ordering::Release);
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          RAM Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct RomFns {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip:         unsafe extern "C" fn(),
+    flash_range_erase:      unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program:    unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache:      unsafe extern "C" fn(),
+    flash_enter_cmd_xip:    unsafe extern "C" fn(),
+}
+
+/// Runs with XIP disabled: no flash access is allowed until XIP is restored
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn erase_and_program(
+    rom: &RomFns,
+    boot2: *const u32,
+    offset: u32,
+    data: *const u8,
+    len: usize,
+    erase: bool,
+) {
+    unsafe {
+        (rom.connect_internal_flash)();
+        (rom.flash_exit_xip)();
+        if erase {
+            (rom.flash_range_erase)(offset, SECTOR_SIZE, SECTOR_SIZE as u32, BLOCK_ERASE_CMD);
+        }
+        (rom.flash_range_program)(offset, data, len - len % PAGE_SIZE);
+        (rom.flash_flush_cache)();
+        (rom.flash_enter_cmd_xip)();
+
+        // Restoring the fast XIP mode with the RAM copy of boot2 (thumb bit set)
+        let boot2_fn: unsafe extern "C" fn() = core::mem::transmute(boot2 as usize + 1);
+        boot2_fn();
+    }
+}
+
+/// Sends the unique id command with the chip select held low and shifts the 8 id bytes into
+/// `hi`/`lo`. Runs with XIP disabled like `erase_and_program`: registers are accessed through
+/// asm and counters wrap, so no debug check can call into flash.
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn unique_id_cmd(rom: &RomFns, boot2: *const u32, hi: &mut u32, lo: &mut u32) {
+    const LEN: u32 = 13;
+    const ID_START: u32 = 5;
+
+    unsafe {
+        (rom.connect_internal_flash)();
+        (rom.flash_exit_xip)();
+        reg_write(QSPI_SS_CTRL, SS_OUTOVER_LOW);
+
+        let (mut tx, mut rx) = (0u32, 0u32);
+        while rx < LEN {
+            let status = reg_read(SSI_SR);
+            // Keeping the rx fifo from overflowing
+            if status & SSI_SR_TFNF != 0 && tx < LEN && tx.wrapping_sub(rx) < SSI_FIFO_DEPTH {
+                reg_write(SSI_DR0, if tx == 0 { UNIQUE_ID_CMD as u32 } else { 0 });
+                tx = tx.wrapping_add(1);
+            }
+            if status & SSI_SR_RFNE != 0 {
+                let byte = reg_read(SSI_DR0) & 0xff;
+                if rx >= ID_START {
+                    *hi = (*hi << 8) | (*lo >> 24);
+                    *lo = (*lo << 8) | byte;
+                }
+                rx = rx.wrapping_add(1);
+            }
+        }
+
+        reg_write(QSPI_SS_CTRL, SS_OUTOVER_HIGH);
+        (rom.flash_flush_cache)();
+        (rom.flash_enter_cmd_xip)();
+
+        let boot2_fn: unsafe extern "C" fn() = core::mem::transmute(boot2 as usize + 1);
+        boot2_fn();
+    }
+}
+
+/// Releases the chip select output, lets the pin settle and samples it. The flash is deselected
+/// until the output is restored: no flash access, registers through asm like `unique_id_cmd`.
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn qspi_ss_level() -> bool {
+    unsafe {
+        let ctrl = reg_read(QSPI_SS_CTRL);
+        reg_write(QSPI_SS_CTRL, (ctrl & !SS_OEOVER_MASK) | SS_OEOVER_DISABLE);
+
+        let mut reads = 0u32;
+        while reads < SS_SETTLE_READS {
+            reg_read(SIO_GPIO_HI_IN);
+            reads = reads.wrapping_add(1);
+        }
+        let level = reg_read(SIO_GPIO_HI_IN) & SS_HI_IN_BIT != 0;
+
+        reg_write(QSPI_SS_CTRL, ctrl);
+        level
+    }
+}
+
+#[inline(always)]
+unsafe fn reg_read(addr: u32) -> u32 {
+    let value;
+    unsafe { core::arch::asm!("ldr {0}, [{1}]", out(reg) value, in(reg) addr, options(nostack)) };
+    value
+}
+
+#[inline(always)]
+unsafe fn reg_write(addr: u32, value: u32) {
+    unsafe { core::arch::asm!("str {0}, [{1}]", in(reg) value, in(reg) addr, options(nostack)) };
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid flash offset")]
+    InvalidOffset,
+
+    #[error("core1 did not park for the flash access")]
+    Core1Busy,
+
+    #[error("data too large for a flash sector")]
+    TooLarge,
+}
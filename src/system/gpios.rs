@@ -1,16 +1,25 @@
 //! Input/Output GP Pin Storage for the RP2040 microcontroller
+//!
+//! Pins of the Inputs and Outputs groups can be moved between the two collections at runtime
+//! with `configure`. Inputs keep the PullUp type, other pulls are set in the pad register.
+//! `parse_setup` reads several pin modes from a descriptor: "0:out:low,OUT_A:out:high,9:in".
 
-use super::config::Error;
-use super::config::Result;
+use super::config::{CONFIG, Error, Result};
+use super::output_guard::GUARD;
+use crate::state::STATE;
 
+use core::str::FromStr;
+
+use crate::hal;
+use embedded_hal::digital::OutputPin;
 use hal::gpio::{self, Function, Pin, PullType};
-use rp2040_hal::{self as hal};
+use heapless::Vec;
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                            Globals
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
-pub const NUM_MCU_PINS: usize = 30;
+pub const NUM_MCU_PINS: usize = hal::NUM_GPIOS;
 
 pub type InputType = gpio::Pin<gpio::DynPinId, gpio::FunctionSio<gpio::SioInput>, gpio::PullUp>;
 pub type OutputType = gpio::Pin<gpio::DynPinId, gpio::FunctionSio<gpio::SioOutput>, gpio::PullDown>;
@@ -53,4 +62,176 @@ impl<T> IoPins<T> {
 
         self.pins[id as usize].as_mut().ok_or(Error::GpioNotFound)
     }
+
+    /// Removes a pin from the collection, handing it over to a driver or service
+    pub fn take(&mut self, id: u8) -> Option<T> {
+        self.pins.get_mut(id as usize)?.take()
+    }
+}
+
+impl IoPins<OutputType> {
+    /// Drives an output within its guard limits, see `output_guard`
+    pub fn set_output(&mut self, id: u8, high: bool) -> Result<()> {
+        self.get(id)?;
+        GUARD.check_level(id, high)?;
+        write_level(id, high);
+        Ok(())
+    }
+
+    /// Toggles an output within its guard limits, returns the new level
+    pub fn toggle_output(&mut self, id: u8) -> Result<bool> {
+        let high = !output_level(id);
+        self.set_output(id, high)?;
+        Ok(high)
+    }
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Pin Mode
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pull {
+    Up,
+    Down,
+    Float,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PinMode {
+    /// Output with its initial level
+    Output(bool),
+    Input(Pull),
+}
+
+impl FromStr for PinMode {
+    type Err = ();
+
+    /// Parses "out[:low|high]" or "in[:pullup|pulldown|float]", defaults: low and pullup
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let (mode, option) = s.split_once(':').unwrap_or((s, ""));
+
+        match (mode, option) {
+            ("out", "" | "low") => Ok(PinMode::Output(false)),
+            ("out", "high") => Ok(PinMode::Output(true)),
+            ("in", "" | "pullup") => Ok(PinMode::Input(Pull::Up)),
+            ("in", "pulldown") => Ok(PinMode::Input(Pull::Down)),
+            ("in", "float") => Ok(PinMode::Input(Pull::Float)),
+            _ => Err(()),
+        }
+    }
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Moves a pin registered as input or output to the collection of the mode, then applies
+/// the output level or the input pull. Pins handed over to drivers or services are not found.
+pub fn configure(
+    inputs: &mut IoPins<InputType>,
+    outputs: &mut IoPins<OutputType>,
+    id: u8,
+    mode: PinMode,
+) -> Result<()> {
+    if inputs.get(id).is_err() && outputs.get(id).is_err() {
+        return Err(Error::GpioNotFound);
+    }
+
+    match mode {
+        PinMode::Output(high) => {
+            // The level is latched before the output is enabled, avoiding a glitch
+            set_level(id, high);
+            // SIO is a valid function of every pin
+            if let Some(pin) = inputs.take(id) {
+                let pin = pin.try_into_function().ok().unwrap().into_pull_type();
+                outputs.register(pin);
+            }
+            outputs.get(id)?.set_state(high.into()).unwrap();
+        }
+        PinMode::Input(pull) => {
+            if let Some(pin) = outputs.take(id) {
+                let pin = pin.try_into_function().ok().unwrap().into_pull_type();
+                inputs.register(pin);
+            }
+            set_pull(id, pull);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a comma separated list of "<gpio or alias>:<mode>", see `PinMode`.
+/// Reserved and unknown pins are refused.
+pub fn parse_setup(descriptor: &str) -> Result<Vec<(u8, PinMode), NUM_MCU_PINS>> {
+    let mut entries = Vec::new();
+
+    for entry in descriptor
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (pin, mode) = entry.split_once(':').ok_or(Error::InvalidSetup)?;
+        let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+        let mode = mode.parse().map_err(|_| Error::InvalidSetup)?;
+        entries.push((gpio, mode)).map_err(|_| Error::OutOfBounds)?;
+    }
+    Ok(entries)
+}
+
+/// Configures several pins, checking that all of them can be configured first
+pub fn configure_all(
+    inputs: &mut IoPins<InputType>,
+    outputs: &mut IoPins<OutputType>,
+    entries: &[(u8, PinMode)],
+) -> Result<()> {
+    for &(id, _) in entries {
+        if inputs.get(id).is_err() && outputs.get(id).is_err() {
+            return Err(Error::GpioNotFound);
+        }
+    }
+
+    for &(id, mode) in entries {
+        configure(inputs, outputs, id, mode)?;
+    }
+    Ok(())
+}
+
+/// Latches the SIO output level of a pin, safe from interrupts.
+/// A change refused by the output guard is dropped.
+pub fn set_level(id: u8, high: bool) {
+    if GUARD.check_level(id, high).is_ok() {
+        write_level(id, high);
+    }
+}
+
+/// Latches the SIO output level without the output guard, level changes are counted
+pub(crate) fn write_level(id: u8, high: bool) {
+    if output_level(id) != high {
+        STATE.count_toggle(id);
+    }
+
+    // Safety: set/clr registers are atomic
+    let sio = unsafe { hal::regs::sio() };
+    let mask = 1u32 << id;
+    match high {
+        true => sio.gpio_out_set().write(|w| unsafe { w.bits(mask) }),
+        false => sio.gpio_out_clr().write(|w| unsafe { w.bits(mask) }),
+    }
+}
+
+/// SIO output level of a pin
+pub fn output_level(id: u8) -> bool {
+    // Safety: read only register
+    let sio = unsafe { hal::regs::sio() };
+    sio.gpio_out().read().bits() & (1 << id) != 0
+}
+
+/// Sets the pull resistors in the pad register, the pin type keeps its pull
+fn set_pull(id: u8, pull: Pull) {
+    // Safety: the pin is owned by the caller
+    let pads = unsafe { hal::regs::pads_bank0() };
+    pads.gpio(id as usize).modify(|_, w| {
+        w.pue().bit(pull == Pull::Up);
+        w.pde().bit(pull == Pull::Down)
+    });
 }
@@ -0,0 +1,115 @@
+//! Heap (heap feature)
+//!
+//! Global allocator on a fixed RAM arena, for integrations that are painful without `alloc`
+//! (ex: JSON output, bigger parsers). Allocations are counted and the high watermark is kept,
+//! both shown by the `mem` command.
+//!
+//! Guidelines:
+//! - Core paths (CLI, device, system modules) stay heapless and build without the feature
+//! - Code using `alloc` is gated with `#[cfg(feature = "heap")]`, with a heapless fallback or
+//!   a clear error when it is off
+//! - Failed allocations end in the panic handler, size buffers to the arena
+//!
+//! Example:
+//! ```rust
+//! #[cfg(feature = "heap")]
+//! let text = alloc::format!("{value:?}");
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::mem::MaybeUninit;
+
+use critical_section::{Mutex, with};
+use embedded_alloc::LlffHeap;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const HEAP_SIZE: usize = 32 * 1024;
+
+#[global_allocator]
+static HEAP: TrackedHeap = TrackedHeap {
+    heap:  LlffHeap::empty(),
+    stats: Mutex::new(RefCell::new(Stats {
+        allocs: 0,
+        frees:  0,
+        failed: 0,
+        used:   0,
+        peak:   0,
+        size:   0,
+    })),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Heap
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Allocation statistics, in requested bytes
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    pub allocs: u32,
+    pub frees:  u32,
+    pub failed: u32,
+    pub used:   usize,
+    /// High watermark of `used`
+    pub peak:   usize,
+    /// Arena size, 0 until initialized
+    pub size:   usize,
+}
+
+struct TrackedHeap {
+    heap:  LlffHeap,
+    stats: Mutex<RefCell<Stats>>,
+}
+
+unsafe impl GlobalAlloc for TrackedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.heap.alloc(layout) };
+
+        with(|cs| {
+            let mut stats = self.stats.borrow_ref_mut(cs);
+            if ptr.is_null() {
+                stats.failed += 1;
+                return;
+            }
+            stats.allocs += 1;
+            stats.used += layout.size();
+            stats.peak = stats.peak.max(stats.used);
+        });
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.heap.dealloc(ptr, layout) };
+
+        with(|cs| {
+            let mut stats = self.stats.borrow_ref_mut(cs);
+            stats.frees += 1;
+            stats.used -= layout.size();
+        });
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Hands the arena to the allocator, called once at boot before any allocation
+pub fn init() {
+    static mut ARENA: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
+
+    // Safety: called once, the arena is only reachable through the allocator
+    unsafe { HEAP.heap.init(&raw mut ARENA as usize, HEAP_SIZE) };
+    with(|cs| HEAP.stats.borrow_ref_mut(cs).size = HEAP_SIZE);
+}
+
+pub fn stats() -> Stats {
+    with(|cs| *HEAP.stats.borrow_ref(cs))
+}
+
+/// Free bytes in the arena, allocator overhead included
+pub fn free() -> usize {
+    HEAP.heap.free()
+}
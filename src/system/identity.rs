@@ -0,0 +1,54 @@
+//! Board Identity
+//!
+//! Tells several connected boards apart: the 64 bit unique id of the flash chip is the board id,
+//! used as the USB serial number (ex: /dev/serial/by-id/...-E6614103E7452D2F-if00) and answered
+//! to the `*IDN?` query, "<manufacturer>,<product>,<board id>,<version>" as SCPI instruments do.
+//! The `identify` command blinks the LED to find the board behind a port.
+//!
+//! Example:
+//! ```rust
+//! identity::init(); // at boot, before core1 runs
+//! println!("{}", identity::serial_number());
+//! ```
+
+use core::fmt::Write;
+
+use heapless::String;
+use once_cell::sync::Lazy;
+use portable_atomic::{AtomicU64, Ordering};
+
+use super::flash;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MANUFACTURER: &str = "LH Eng";
+pub const PRODUCT: &str = "Rpi Pico - USB Serial CLI";
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+static BOARD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Board id as 16 hex digits. `init` has to run before the first access.
+static SERIAL_NUMBER: Lazy<String<16>> = Lazy::new(|| {
+    let mut serial = String::new();
+    let _ = write!(serial, "{:016X}", board_id());
+    serial
+});
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Reads the flash unique id, called once at boot before core1 is spawned
+pub fn init() {
+    BOARD_ID.store(flash::read_unique_id(), Ordering::Relaxed);
+}
+
+pub fn board_id() -> u64 {
+    BOARD_ID.load(Ordering::Relaxed)
+}
+
+pub fn serial_number() -> &'static str {
+    &SERIAL_NUMBER
+}
@@ -0,0 +1,328 @@
+//! Wear-Leveled Key-Value Store
+//!
+//! Small counters updated often (boot count, cumulative uptime, lifetime toggles) kept apart from
+//! the settings blob, which erases its sector on every save. A write appends a 16 byte entry to
+//! a journal sector, the latest entry of a key wins. A full sector is compacted into the next one
+//! of the ring, so a sector is erased once every ~250 writes and erases rotate over `KV_SECTORS`.
+//!
+//! Sector: [header: "KVJ1", generation u32 LE, 0xFF..][entry]..[0xFF free slots]
+//! Entry:  [key: 8 bytes, 0 padded][value: u32 LE][crc32 of key and value]
+//!
+//! The active sector is the valid one with the highest generation. An entry left half written
+//! by a power loss fails its crc and is skipped, the older value of its key is kept. A compaction
+//! programs the header last, a sector cut short by a power loss has none and the previous sector
+//! stays active. The RAM copy takes a value once it is in flash: a failed write changes neither.
+//!
+//! Keys starting with "sec." belong to the secure channel (its replay counter): the kv command
+//! can't write them and `clear` keeps them, so the counter can't be rolled back from the CLI.
+//!
+//! Example:
+//! ```rust
+//! KV.load(); // at boot
+//! let boots = KV.add("boots", 1)?;
+//! let uptime_s = KV.get("uptime").unwrap_or(0);
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::{String, Vec};
+use thiserror::Error;
+
+use super::flash::{self, KV_SECTOR, KV_SECTORS, PAGE_SIZE, SECTOR_SIZE};
+use crate::utils::crc::{self, CRC32};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_KEYS: usize = 32;
+pub const MAX_KEY_LENGTH: usize = 8;
+
+/// Keys owned by the firmware, see `is_protected`
+const PROTECTED_PREFIX: &str = "sec.";
+
+const MAGIC: &[u8; 4] = b"KVJ1";
+const SLOT_SIZE: usize = 16;
+const SLOTS: usize = SECTOR_SIZE / SLOT_SIZE;
+
+pub static KV: Kv = Kv {
+    inner: Mutex::new(RefCell::new(Inner {
+        entries:    Vec::new(),
+        sector:     0,
+        generation: 0,
+        next_slot:  SLOTS,
+    })),
+};
+
+pub type Key = String<MAX_KEY_LENGTH>;
+type Entries = Vec<(Key, u32), MAX_KEYS>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Kv
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Journal position, shown by the kv command
+#[derive(Debug, Copy, Clone)]
+pub struct Usage {
+    pub sector:     u32,
+    pub generation: u32,
+    /// Entry slots written in the active sector, header excluded
+    pub used:       usize,
+    pub free:       usize,
+    pub keys:       usize,
+}
+
+struct Inner {
+    /// Latest value of each key, the RAM copy of the journal
+    entries:    Entries,
+    /// Active sector, index in the ring
+    sector:     u32,
+    generation: u32,
+    /// First free slot of the active sector, SLOTS when full or not loaded
+    next_slot:  usize,
+}
+
+pub struct Kv {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Kv {
+    /// Replays the journal of the active sector, returns the number of keys
+    pub fn load(&self) -> usize {
+        let active = (0..KV_SECTORS)
+            .filter_map(|sector| read_header(sector).map(|generation| (sector, generation)))
+            .max_by_key(|&(_, generation)| generation);
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.entries.clear();
+            let Some((sector, generation)) = active
+            else {
+                // Never written, the first write compacts into sector 0
+                inner.sector = KV_SECTORS - 1;
+                inner.next_slot = SLOTS;
+                return 0;
+            };
+
+            inner.sector = sector;
+            inner.generation = generation;
+            inner.next_slot = 1;
+            let data = flash::read(sector_offset(sector), SECTOR_SIZE);
+            for slot in 1..SLOTS {
+                let raw = &data[slot * SLOT_SIZE..(slot + 1) * SLOT_SIZE];
+                if raw.iter().all(|&byte| byte == 0xFF) {
+                    continue;
+                }
+                inner.next_slot = slot + 1;
+                if let Some((key, value)) = decode(raw) {
+                    let _ = update(&mut inner.entries, &key, value);
+                }
+            }
+            inner.entries.len()
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<u32> {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            inner
+                .entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|&(_, value)| value)
+        })
+    }
+
+    /// Stores a value, an unchanged value is not written
+    pub fn set(&self, key: &str, value: u32) -> Result<()> {
+        let key = make_key(key)?;
+        if self.get(&key) == Some(value) {
+            return Ok(());
+        }
+
+        // Reserves the slot, the value goes to RAM once written
+        let reserved = with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.entries.is_full() && !inner.entries.iter().any(|(k, _)| *k == key) {
+                return Err(Error::Full);
+            }
+            let slot = inner.next_slot;
+            if slot < SLOTS {
+                inner.next_slot += 1;
+            }
+            Ok((slot < SLOTS).then_some((slot, inner.sector)))
+        })?;
+
+        let Some((slot, sector)) = reserved
+        else {
+            let mut entries = with(|cs| self.inner.borrow_ref(cs).entries.clone());
+            update(&mut entries, &key, value)?;
+            return self.compact(entries);
+        };
+
+        let written = append(sector, slot, &key, value);
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if written.is_err() && inner.next_slot == slot + 1 {
+                // The flash errors come before any programming, the slot is still blank
+                inner.next_slot = slot;
+            }
+            written?;
+            update(&mut inner.entries, &key, value)
+        })
+    }
+
+    /// Adds to a counter, starting from 0. Returns the new value.
+    pub fn add(&self, key: &str, delta: u32) -> Result<u32> {
+        let value = self.get(key).unwrap_or(0).saturating_add(delta);
+        self.set(key, value)?;
+        Ok(value)
+    }
+
+    /// Drops every key but the protected ones, compacted into the next sector of the ring
+    pub fn clear(&self) -> Result<()> {
+        let mut entries = with(|cs| self.inner.borrow_ref(cs).entries.clone());
+        entries.retain(|(key, _)| is_protected(key));
+        self.compact(entries)
+    }
+
+    /// Calls `f` with every key and value
+    pub fn for_each(&self, mut f: impl FnMut(&str, u32)) {
+        let entries = with(|cs| self.inner.borrow_ref(cs).entries.clone());
+        for (key, value) in entries.iter() {
+            f(key, *value);
+        }
+    }
+
+    pub fn usage(&self) -> Usage {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            let used = inner.next_slot.min(SLOTS) - 1;
+            Usage {
+                sector: inner.sector,
+                generation: inner.generation,
+                used,
+                free: SLOTS - 1 - used,
+                keys: inner.entries.len(),
+            }
+        })
+    }
+
+    /// Writes `entries` into the next sector of the ring, which becomes the active one with them
+    fn compact(&self, entries: Entries) -> Result<()> {
+        let mut data = [0xFF; SECTOR_SIZE];
+        for (slot, (key, value)) in entries.iter().enumerate() {
+            let start = (slot + 1) * SLOT_SIZE;
+            data[start..start + SLOT_SIZE].copy_from_slice(&encode(key, *value));
+        }
+        let (sector, generation) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            ((inner.sector + 1) % KV_SECTORS, inner.generation.wrapping_add(1))
+        });
+        // Entries first, the header makes the sector valid once they are all in
+        flash::write_sector(sector_offset(sector), &data)?;
+
+        let mut header = [0xFF; PAGE_SIZE];
+        header[..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        flash::program_page(sector_offset(sector), &header)?;
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.sector = sector;
+            inner.generation = generation;
+            inner.next_slot = entries.len() + 1;
+            inner.entries = entries;
+        });
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// True for the keys owned by the firmware, read-only from the kv command and kept by `clear`
+pub fn is_protected(key: &str) -> bool {
+    key.starts_with(PROTECTED_PREFIX)
+}
+
+fn update(entries: &mut Entries, key: &str, value: u32) -> Result<()> {
+    if let Some(entry) = entries.iter_mut().find(|(k, _)| k == key) {
+        entry.1 = value;
+        return Ok(());
+    }
+    let key = make_key(key)?;
+    entries.push((key, value)).map_err(|_| Error::Full)
+}
+
+fn sector_offset(sector: u32) -> u32 {
+    KV_SECTOR + sector * SECTOR_SIZE as u32
+}
+
+/// Generation of a sector, None without a valid header
+fn read_header(sector: u32) -> Option<u32> {
+    let header = flash::read(sector_offset(sector), 8);
+    (&header[..4] == MAGIC)
+        .then(|| u32::from_le_bytes([header[4], header[5], header[6], header[7]]))
+}
+
+/// Programs an entry in its slot, the rest of the page is left as it is
+fn append(sector: u32, slot: usize, key: &str, value: u32) -> Result<()> {
+    let start = slot * SLOT_SIZE;
+    let page = start - start % PAGE_SIZE;
+
+    let mut data = [0xFF; PAGE_SIZE];
+    data[start - page..start - page + SLOT_SIZE].copy_from_slice(&encode(key, value));
+    flash::program_page(sector_offset(sector) + page as u32, &data)?;
+    Ok(())
+}
+
+fn encode(key: &str, value: u32) -> [u8; SLOT_SIZE] {
+    let mut raw = [0u8; SLOT_SIZE];
+    raw[..key.len()].copy_from_slice(key.as_bytes());
+    raw[8..12].copy_from_slice(&value.to_le_bytes());
+    let crc = crc::table_checksum(&CRC32, &raw[..12]);
+    raw[12..].copy_from_slice(&crc.to_le_bytes());
+    raw
+}
+
+/// Key and value of a slot, None if its crc does not match
+fn decode(raw: &[u8]) -> Option<(Key, u32)> {
+    if crc::table_checksum(&CRC32, &raw[..12]).to_le_bytes() != raw[12..16] {
+        return None;
+    }
+
+    let len = raw[..8].iter().position(|&byte| byte == 0).unwrap_or(8);
+    let key = core::str::from_utf8(&raw[..len]).ok()?;
+    let value = u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]);
+    Some((make_key(key).ok()?, value))
+}
+
+fn make_key(key: &str) -> Result<Key> {
+    if key.is_empty() || key.contains('\0') {
+        return Err(Error::InvalidKey);
+    }
+    Key::try_from(key).map_err(|_| Error::InvalidKey)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid key, 1 to 8 chars")]
+    InvalidKey,
+
+    #[error("key-value store full")]
+    Full,
+
+    #[error("protected key, owned by the firmware")]
+    Protected,
+
+    #[error(transparent)]
+    Flash(#[from] flash::Error),
+}
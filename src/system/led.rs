@@ -0,0 +1,490 @@
+//! LED Pattern Service
+//!
+//! The onboard LED shows the system mode with a blink pattern stepped by the system tick
+//! (TICK_US, 100ms per step). Patterns are strings of `1` (on) and `0` (off), up to 32 steps,
+//! stored in the "led.<mode>" settings to replace the defaults.
+//!
+//! Manual mode stops the pattern so user code can drive the LED with `set`/`toggle`.
+//! `identify` overlays a distinctive triple flash for some seconds, whatever the mode.
+//! The GPIO LED is skipped on boards without one (Pico W).
+//!
+//! An RGB LED configured in the pin table also shows the mode as a color, see rgb_led.rs.
+//!
+//! Example:
+//! ```rust
+//! device.led.set_mode(LedMode::Running);
+//! let heartbeat: Pattern = "1010000000".parse()?;
+//! device.led.set_pattern(LedMode::Idle, Some(heartbeat))?;
+//!
+//! device.led.toggle(); // switches to Manual
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+use core::str::FromStr;
+
+use critical_section::{Mutex, with};
+
+use super::gpios::OutputType;
+use super::rgb_led::{Color, DEFAULT_BRIGHTNESS, RgbDriver};
+use super::settings::{self, SETTINGS};
+use super::tick::{TICK, TICK_US};
+use crate::prelude::*;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_PATTERN_STEPS: usize = 32;
+const NUM_PATTERNS: usize = LedMode::PATTERNS.len();
+
+const DEFAULT_PATTERNS: [Pattern; NUM_PATTERNS] = [
+    Pattern::from_steps(0b0, 1),           // Off
+    Pattern::from_steps(0b1, 1),           // Boot: solid
+    Pattern::from_steps(0b0011, 4),        // WaitingForHost: 2.5hz
+    Pattern::from_steps(0b0000000101, 10), // Idle: heartbeat
+    Pattern::from_steps(0b1, 1),           // Running: solid
+    Pattern::from_steps(0b0000011111, 10), // Error: 1hz
+    Pattern::from_steps(0b01, 2),          // Estop: 5hz
+];
+
+const DEFAULT_COLORS: [Color; NUM_PATTERNS] = [
+    Color::BLACK,  // Off
+    Color::WHITE,  // Boot
+    Color::YELLOW, // WaitingForHost
+    Color::GREEN,  // Idle
+    Color::BLUE,   // Running
+    Color::RED,    // Error
+    Color::ORANGE, // Estop
+];
+
+/// Triple flash, shown in white by the RGB LED
+const IDENTIFY_PATTERN: Pattern = Pattern::from_steps(0b0000010101, 10);
+
+const BRIGHTNESS_KEY: &str = "rgb.brightness";
+
+static LED_CELL: Mutex<RefCell<LedState>> = Mutex::new(RefCell::new(LedState {
+    pin:        None,
+    rgb:        None,
+    mode:       LedMode::Boot,
+    patterns:   DEFAULT_PATTERNS,
+    colors:     DEFAULT_COLORS,
+    brightness: DEFAULT_BRIGHTNESS,
+    step:       0,
+    on:         false,
+    shown:      None,
+    identify:   0,
+}));
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Led Mode
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LedMode {
+    Off,
+    Boot,
+    WaitingForHost,
+    Idle,
+    Running,
+    Error,
+    Estop,
+    /// LED driven by user code, no pattern
+    Manual,
+}
+
+impl LedMode {
+    /// Modes with a pattern, in pattern table order
+    pub const PATTERNS: [LedMode; 7] = [
+        LedMode::Off,
+        LedMode::Boot,
+        LedMode::WaitingForHost,
+        LedMode::Idle,
+        LedMode::Running,
+        LedMode::Error,
+        LedMode::Estop,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedMode::Off => "off",
+            LedMode::Boot => "boot",
+            LedMode::WaitingForHost => "waiting",
+            LedMode::Idle => "idle",
+            LedMode::Running => "running",
+            LedMode::Error => "error",
+            LedMode::Estop => "estop",
+            LedMode::Manual => "manual",
+        }
+    }
+
+    /// Index in the pattern table, None for Manual
+    fn pattern_index(&self) -> Option<usize> {
+        LedMode::PATTERNS.iter().position(|mode| mode == self)
+    }
+
+    /// Settings key of the pattern ("led.<mode>") or the color ("rgb.<mode>")
+    fn setting_key(&self, prefix: &str) -> settings::Key {
+        let mut key = settings::Key::new();
+        let _ = key.push_str(prefix);
+        let _ = key.push_str(self.name());
+        key
+    }
+}
+
+impl FromStr for LedMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LedMode::PATTERNS
+            .into_iter()
+            .chain([LedMode::Manual])
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for LedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Pattern
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// LED state for each tick step, bit 0 is the first step
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    steps: u32,
+    len:   u8,
+}
+
+impl Pattern {
+    pub const fn from_steps(steps: u32, len: u8) -> Self {
+        Self { steps, len }
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    pub fn is_on(&self, step: u8) -> bool {
+        self.steps & (1 << step) != 0
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > MAX_PATTERN_STEPS {
+            return Err(());
+        }
+
+        let mut steps = 0;
+        for (i, c) in s.chars().enumerate() {
+            match c {
+                '1' => steps |= 1 << i,
+                '0' => {}
+                _ => return Err(()),
+            }
+        }
+        Ok(Self::from_steps(steps, s.len() as u8))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in 0..self.len {
+            f.write_str(if self.is_on(step) { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Led
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct LedState {
+    pin:        Option<OutputType>,
+    rgb:        Option<RgbDriver>,
+    mode:       LedMode,
+    patterns:   [Pattern; NUM_PATTERNS],
+    colors:     [Color; NUM_PATTERNS],
+    brightness: u8,
+    step:       u8,
+    on:         bool,
+    /// Last color written to the RGB LED
+    shown:      Option<Color>,
+    /// Ticks left of the identify pattern
+    identify:   u32,
+}
+
+impl LedState {
+    /// Drives the GPIO LED and the RGB LED with the color of the mode, white in Manual
+    fn show(&mut self, on: bool) {
+        let color = match self.mode.pattern_index() {
+            Some(index) if on => self.colors[index],
+            None if on => Color::WHITE,
+            _ => Color::BLACK,
+        };
+        self.drive(on, color);
+    }
+
+    /// Drives the GPIO LED and the RGB LED with a color, dimmed to the brightness
+    fn drive(&mut self, on: bool, color: Color) {
+        self.on = on;
+        if let Some(pin) = self.pin.as_mut() {
+            let _ = pin.set_state(on.into());
+        }
+        self.write_rgb(color.dimmed(self.brightness));
+    }
+
+    /// Writes the RGB LED, skipped if the color is already shown
+    fn write_rgb(&mut self, color: Color) {
+        if let Some(rgb) = self.rgb.as_mut()
+            && self.shown != Some(color)
+        {
+            rgb.write(color);
+            self.shown = Some(color);
+        }
+    }
+
+    /// Shows the current step and advances the pattern
+    fn advance(&mut self) {
+        if self.identify > 0 {
+            self.identify -= 1;
+            let on = IDENTIFY_PATTERN.is_on(self.step % IDENTIFY_PATTERN.len);
+            self.step = (self.step + 1) % IDENTIFY_PATTERN.len;
+            self.drive(on, if on { Color::WHITE } else { Color::BLACK });
+
+            // Back to the mode pattern from its first step
+            if self.identify == 0 {
+                self.step = 0;
+            }
+            return;
+        }
+
+        let Some(index) = self.mode.pattern_index()
+        else {
+            return;
+        };
+
+        let pattern = self.patterns[index];
+        if self.step >= pattern.len {
+            self.step = 0;
+        }
+        self.show(pattern.is_on(self.step));
+        self.step += 1;
+    }
+}
+
+/// Handle to the LED service, access it through device.led
+pub struct Led {}
+
+impl Led {
+    /// Takes the LED pin, loads the patterns from the settings and starts the tick task
+    pub fn new(pin: Option<OutputType>) -> Self {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.pin = pin;
+
+            for (i, mode) in LedMode::PATTERNS.iter().enumerate() {
+                if let Some(saved) = SETTINGS.get_parsed(&mode.setting_key("led.")) {
+                    state.patterns[i] = saved;
+                }
+                if let Some(saved) = SETTINGS.get_parsed(&mode.setting_key("rgb.")) {
+                    state.colors[i] = saved;
+                }
+            }
+            if let Some(brightness) = SETTINGS.get_parsed(BRIGHTNESS_KEY) {
+                state.brightness = brightness;
+            }
+            state.advance();
+        });
+
+        TICK.register(tick, 1).unwrap();
+        Self {}
+    }
+
+    pub fn mode(&self) -> LedMode {
+        with(|cs| LED_CELL.borrow_ref(cs).mode)
+    }
+
+    /// Switches the pattern, the first step is shown right away
+    pub fn set_mode(&self, mode: LedMode) {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            if state.mode != mode {
+                state.mode = mode;
+                state.step = 0;
+                state.advance();
+            }
+        })
+    }
+
+    /// Pattern of a mode, None for Manual
+    pub fn pattern(&self, mode: LedMode) -> Option<Pattern> {
+        let index = mode.pattern_index()?;
+        Some(with(|cs| LED_CELL.borrow_ref(cs).patterns[index]))
+    }
+
+    /// Replaces and saves the pattern of a mode, None restores the default
+    pub fn set_pattern(&self, mode: LedMode, pattern: Option<Pattern>) -> settings::Result<()> {
+        let Some(index) = mode.pattern_index()
+        else {
+            return Err(settings::Error::InvalidValue);
+        };
+
+        match pattern {
+            Some(pattern) => SETTINGS.set(&mode.setting_key("led."), pattern)?,
+            None => {
+                SETTINGS.remove(&mode.setting_key("led."));
+            }
+        }
+        SETTINGS.save()?;
+
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.patterns[index] = pattern.unwrap_or(DEFAULT_PATTERNS[index]);
+            state.step = 0;
+        });
+        Ok(())
+    }
+
+    /// Sets the LED switching to Manual mode
+    pub fn set(&self, on: bool) {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.mode = LedMode::Manual;
+            state.show(on);
+        })
+    }
+
+    /// Overlays the identify pattern for `secs`, 0 stops it
+    pub fn identify(&self, secs: u32) {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.identify = secs.saturating_mul(1_000_000 / TICK_US);
+            state.step = 0;
+        })
+    }
+
+    /// True while the identify pattern is shown
+    pub fn is_identifying(&self) -> bool {
+        with(|cs| LED_CELL.borrow_ref(cs).identify > 0)
+    }
+
+    /// Toggles the LED switching to Manual mode
+    pub fn toggle(&self) {
+        let on = !self.is_on();
+        self.set(on);
+    }
+
+    /// Current LED state, false if no LED is available
+    pub fn is_on(&self) -> bool {
+        with(|cs| {
+            let state = LED_CELL.borrow_ref(cs);
+            state.on && (state.pin.is_some() || state.rgb.is_some())
+        })
+    }
+
+    /// False on boards without a GPIO driven LED or RGB LED
+    pub fn is_available(&self) -> bool {
+        with(|cs| {
+            let state = LED_CELL.borrow_ref(cs);
+            state.pin.is_some() || state.rgb.is_some()
+        })
+    }
+
+    // ————————————————————————————————————————— RGB LED ——————————————————————————————————————————
+
+    /// Adds the RGB LED, the current mode color is shown right away
+    pub fn set_rgb(&self, driver: RgbDriver) {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.rgb = Some(driver);
+            state.shown = None;
+            let on = state.on;
+            state.show(on);
+        })
+    }
+
+    /// Name of the RGB LED driver, None without RGB LED
+    pub fn rgb_driver(&self) -> Option<&'static str> {
+        with(|cs| LED_CELL.borrow_ref(cs).rgb.as_ref().map(|rgb| rgb.name()))
+    }
+
+    /// Color of a mode, None for Manual
+    pub fn color(&self, mode: LedMode) -> Option<Color> {
+        let index = mode.pattern_index()?;
+        Some(with(|cs| LED_CELL.borrow_ref(cs).colors[index]))
+    }
+
+    /// Replaces and saves the color of a mode, None restores the default
+    pub fn set_color(&self, mode: LedMode, color: Option<Color>) -> settings::Result<()> {
+        let Some(index) = mode.pattern_index()
+        else {
+            return Err(settings::Error::InvalidValue);
+        };
+
+        match color {
+            Some(color) => SETTINGS.set(&mode.setting_key("rgb."), color)?,
+            None => {
+                SETTINGS.remove(&mode.setting_key("rgb."));
+            }
+        }
+        SETTINGS.save()?;
+
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.colors[index] = color.unwrap_or(DEFAULT_COLORS[index]);
+            let on = state.on;
+            state.show(on);
+        });
+        Ok(())
+    }
+
+    /// RGB LED brightness in percent
+    pub fn brightness(&self) -> u8 {
+        with(|cs| LED_CELL.borrow_ref(cs).brightness)
+    }
+
+    /// Sets and saves the RGB LED brightness, clamped to 100%
+    pub fn set_brightness(&self, brightness: u8) -> settings::Result<()> {
+        let brightness = brightness.min(100);
+        SETTINGS.set(BRIGHTNESS_KEY, brightness)?;
+        SETTINGS.save()?;
+
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.brightness = brightness;
+            let on = state.on;
+            state.show(on);
+        });
+        Ok(())
+    }
+
+    /// Shows a color on the RGB LED switching to Manual mode, the brightness still applies
+    pub fn show_color(&self, color: Color) {
+        with(|cs| {
+            let mut state = LED_CELL.borrow_ref_mut(cs);
+            state.mode = LedMode::Manual;
+            state.on = color != Color::BLACK;
+            let brightness = state.brightness;
+            state.write_rgb(color.dimmed(brightness));
+        })
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Tick task stepping the pattern
+fn tick() {
+    with(|cs| LED_CELL.borrow_ref_mut(cs).advance());
+}
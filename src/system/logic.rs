@@ -0,0 +1,303 @@
+//! Logic Capture
+//!
+//! Samples the GPIO input levels at a fixed period from the microsecond scheduler, keeping
+//! `pre` samples before the trigger and filling the rest of the buffer after it.
+//!
+//! Triggers:
+//! - `Now`: right after arming
+//! - `Rise(gpio)` / `Fall(gpio)`: an edge seen by the sampler
+//! - `External`: `LOGIC.trigger()`, called by the command for ADC thresholds
+//!
+//! Sampling starts right away, or on a shared start tick (`Setup::start_at`, see
+//! `SCHEDULER.latch_start`) to line up with a playback or a pulse train.
+//!
+//! An `on_trigger` hook runs in the sampler interrupt when the trigger fires, ex: starting a
+//! prepared playback for coordinated stimulus and response measurements.
+//!
+//! Example:
+//! ```rust
+//! PLAYBACK.prepare(&events, 1)?;
+//! let setup = Setup::new(100, 512)
+//!     .pre(64)
+//!     .trigger(Trigger::Rise(gpio!(IN_A)));
+//! LOGIC.arm(setup.on_trigger(|| {
+//!     let _ = PLAYBACK.trigger();
+//! }))?;
+//! while !LOGIC.is_done() {}
+//! LOGIC.for_each(|time_us, levels| println!("{time_us}: {levels:#x}"));
+//! ```
+
+use core::cell::RefCell;
+
+use crate::hal;
+
+use critical_section::{Mutex, with};
+use heapless::Deque;
+use thiserror::Error;
+
+use super::scheduler::{self, EntryId, Instant, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub use crate::limits::MAX_LOGIC_SAMPLES as MAX_SAMPLES;
+pub const MIN_PERIOD_US: u32 = 20;
+
+pub static LOGIC: LogicCapture = LogicCapture {
+    inner: Mutex::new(RefCell::new(Inner {
+        setup:     None,
+        samples:   Deque::new(),
+        state:     State::Idle,
+        remaining: 0,
+        trigger:   0,
+        entry:     None,
+    })),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Setup
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    Now,
+    Rise(u8),
+    Fall(u8),
+    External,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Setup {
+    pub period_us:  u32,
+    /// Total samples, pre-trigger ones included
+    pub samples:    usize,
+    pub pre:        usize,
+    pub trigger:    Trigger,
+    pub on_trigger: Option<fn()>,
+    /// First sample tick, right away if None
+    pub start_at:   Option<Instant>,
+}
+
+impl Setup {
+    pub fn new(period_us: u32, samples: usize) -> Self {
+        Self {
+            period_us,
+            samples,
+            pre: 0,
+            trigger: Trigger::Now,
+            on_trigger: None,
+            start_at: None,
+        }
+    }
+
+    pub fn pre(mut self, pre: usize) -> Self {
+        self.pre = pre;
+        self
+    }
+
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Hook run in interrupt context when the trigger fires
+    pub fn on_trigger(mut self, hook: fn()) -> Self {
+        self.on_trigger = Some(hook);
+        self
+    }
+
+    /// Takes the first sample on a timer tick, ex: a shared start tick
+    pub fn start_at(mut self, at: Instant) -> Self {
+        self.start_at = Some(at);
+        self
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Logic Capture
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Armed,
+    Triggered,
+    Done,
+}
+
+struct Inner {
+    setup:     Option<Setup>,
+    samples:   Deque<u32, MAX_SAMPLES>,
+    state:     State,
+    /// Samples left after the trigger
+    remaining: usize,
+    /// Index of the trigger sample
+    trigger:   usize,
+    entry:     Option<EntryId>,
+}
+
+impl Inner {
+    /// Marks the trigger on the last sample and runs the hook
+    fn fire(&mut self) {
+        self.state = State::Triggered;
+        self.trigger = self.samples.len().saturating_sub(1);
+        self.remaining = self.remaining.saturating_sub(1);
+
+        if let Some(hook) = self.setup.and_then(|setup| setup.on_trigger) {
+            hook();
+        }
+    }
+}
+
+pub struct LogicCapture {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl LogicCapture {
+    /// Starts sampling and waits for the trigger, dropping the previous capture
+    pub fn arm(&self, setup: Setup) -> Result<()> {
+        if setup.period_us < MIN_PERIOD_US {
+            return Err(Error::InvalidPeriod);
+        }
+        if setup.samples == 0 || setup.samples > MAX_SAMPLES || setup.pre >= setup.samples {
+            return Err(Error::InvalidSamples);
+        }
+        self.stop();
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.setup = Some(setup);
+            inner.samples.clear();
+            inner.state = State::Armed;
+            inner.remaining = setup.samples - setup.pre;
+            inner.trigger = 0;
+            inner.entry = Some(match setup.start_at {
+                Some(at) => SCHEDULER.schedule_at(at, sample, 0)?,
+                None => SCHEDULER.schedule_in(0, sample, 0)?,
+            });
+            Ok(())
+        })
+    }
+
+    /// Fires an armed capture, used by the External trigger
+    pub fn trigger(&self) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.state == State::Armed {
+                inner.fire();
+            }
+        });
+    }
+
+    /// Stops sampling, the samples taken are kept
+    pub fn stop(&self) {
+        let entry = with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.state != State::Idle {
+                inner.state = State::Done;
+            }
+            inner.entry.take()
+        });
+
+        if let Some(entry) = entry {
+            SCHEDULER.cancel(entry);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        with(|cs| self.inner.borrow_ref(cs).state)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state() == State::Done
+    }
+
+    /// Calls `f` with the time relative to the trigger and the levels of every sample.
+    /// Samples are copied out one at a time so `f` runs outside of the critical section.
+    pub fn for_each(&self, mut f: impl FnMut(i64, u32)) {
+        for i in 0.. {
+            let Some((time_us, levels)) = with(|cs| {
+                let inner = self.inner.borrow_ref(cs);
+                let period_us = inner.setup?.period_us as i64;
+                let levels = *inner.samples.iter().nth(i)?;
+                Some(((i as i64 - inner.trigger as i64) * period_us, levels))
+            })
+            else {
+                break;
+            };
+            f(time_us, levels);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback taking a sample and checking the trigger
+fn sample(_ctx: u32) -> Option<u32> {
+    // Safety: gpio_in is read only
+    let levels = unsafe { hal::regs::sio().gpio_in().read().bits() };
+
+    with(|cs| {
+        let mut inner = LOGIC.inner.borrow_ref_mut(cs);
+        let setup = inner.setup?;
+        let previous = inner.samples.back().copied();
+
+        // Keeping only the pre-trigger samples while armed
+        if inner.state == State::Armed && inner.samples.len() > setup.pre {
+            inner.samples.pop_front();
+        }
+        let _ = inner.samples.push_back(levels);
+
+        match inner.state {
+            State::Armed => {
+                let edge = |gpio: u8, rising: bool| {
+                    let mask = 1 << gpio;
+                    let was_high = previous.map(|prev| prev & mask != 0);
+                    was_high == Some(!rising) && (levels & mask != 0) == rising
+                };
+                let fired = match setup.trigger {
+                    Trigger::Now => true,
+                    Trigger::Rise(gpio) => edge(gpio, true),
+                    Trigger::Fall(gpio) => edge(gpio, false),
+                    Trigger::External => false,
+                };
+                if fired {
+                    inner.fire();
+                }
+            }
+            State::Triggered => inner.remaining = inner.remaining.saturating_sub(1),
+            State::Idle | State::Done => return None,
+        }
+
+        if inner.state == State::Triggered && inner.remaining == 0 {
+            inner.state = State::Done;
+            inner.entry = None;
+            return None;
+        }
+        Some(setup.period_us)
+    })
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("sample period too short (min 20us)")]
+    InvalidPeriod,
+
+    #[error("invalid sample count, pre has to be below the total (max 1024)")]
+    InvalidSamples,
+
+    #[error("invalid trigger, expected now, rise:<pin>, fall:<pin> or adc<ch>[<>]<volts>")]
+    InvalidTrigger,
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
@@ -0,0 +1,124 @@
+//! Trace Markers
+//!
+//! Poor man's profiling without a debug probe: numbered markers dropped from user code, commands
+//! or interrupts are recorded with a timestamp into a RAM ring, printed by `trace dump` with the
+//! time elapsed since the previous marker.
+//!
+//! The Cortex-M0+ has no DWT cycle counter, timestamps come from the 1MHz system timer. Marking
+//! takes a short critical section, so both cores and interrupt handlers can record.
+//! The oldest markers are dropped when the ring is full.
+//!
+//! Example:
+//! ```rust
+//! marker!(1);
+//! sensor.read()?;
+//! marker!(2); // trace dump shows the read duration on marker 2
+//! ```
+
+use core::cell::RefCell;
+
+use crate::hal;
+use crate::utils::time;
+//
+use hal::sio::Sio;
+
+use critical_section::{Mutex, with};
+use heapless::Deque;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub use crate::limits::MAX_MARKERS;
+
+pub static MARKERS: Markers = Markers {
+    inner: Mutex::new(RefCell::new(Inner {
+        ring:    Deque::new(),
+        dropped: 0,
+    })),
+};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Markers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Marker {
+    pub id:      u16,
+    pub core:    u8,
+    pub time_us: u64,
+}
+
+struct Inner {
+    ring:    Deque<Marker, MAX_MARKERS>,
+    /// Markers dropped since the last clear
+    dropped: u32,
+}
+
+pub struct Markers {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Markers {
+    /// Records a marker, dropping the oldest one when full
+    pub fn mark(&self, id: u16) {
+        let marker = Marker {
+            id,
+            core: Sio::core() as u8,
+            time_us: time::now_us(),
+        };
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.ring.is_full() {
+                inner.ring.pop_front();
+                inner.dropped += 1;
+            }
+            let _ = inner.ring.push_back(marker);
+        });
+    }
+
+    pub fn clear(&self) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.ring.clear();
+            inner.dropped = 0;
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        with(|cs| self.inner.borrow_ref(cs).ring.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dropped(&self) -> u32 {
+        with(|cs| self.inner.borrow_ref(cs).dropped)
+    }
+
+    /// Calls `f` with every marker, oldest first.
+    /// Markers are copied out one at a time so `f` runs outside of the critical section.
+    pub fn for_each(&self, mut f: impl FnMut(&Marker)) {
+        for i in 0.. {
+            let Some(marker) = with(|cs| self.inner.borrow_ref(cs).ring.iter().nth(i).copied())
+            else {
+                break;
+            };
+            f(&marker);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Macros
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Records a numbered trace marker, see `trace dump`
+#[macro_export]
+macro_rules! marker {
+    ($id:expr) => {
+        $crate::system::markers::MARKERS.mark($id)
+    };
+}
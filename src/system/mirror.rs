@@ -0,0 +1,230 @@
+//! GPIO Mirroring
+//!
+//! Background rules copying the level of an input pin to an output pin, optionally inverted
+//! and debounced. Rules are polled every POLL_US from the microsecond scheduler through the SIO
+//! registers, so they keep running between and during commands. They last until removed or
+//! the next reset.
+//!
+//! Each output can be driven by one rule, an input can feed several outputs. The BOOTSEL button
+//! is an input too, `bootsel::GPIO`, sampled only while a rule uses it and core1 is stopped.
+//!
+//! Example:
+//! ```rust
+//! let rule = Mirror::new(gpio!(IN_A), gpio!(OUT_B)).inverted(true);
+//! MIRRORS.add(rule.debounced(20))?;
+//! MIRRORS.remove(gpio!(OUT_B))?;
+//! ```
+
+use core::cell::RefCell;
+
+use crate::hal;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::bootsel;
+use super::scheduler::{self, EntryId, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_MIRRORS: usize = 8;
+/// Polling period of the rules, also the debounce resolution
+pub const POLL_US: u32 = 1_000; // 1ms
+
+pub static MIRRORS: Mirrors = Mirrors {
+    inner: Mutex::new(RefCell::new(Inner {
+        rules: Vec::new(),
+        task:  None,
+    })),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Mirror
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    pub input:    u8,
+    pub output:   u8,
+    pub invert:   bool,
+    /// Polls the input has to stay at a new level before the output follows
+    pub debounce: u16,
+    /// Input level being debounced and polls it has been stable for
+    pending:      bool,
+    stable:       u16,
+}
+
+impl Mirror {
+    pub fn new(input: u8, output: u8) -> Self {
+        Self {
+            input,
+            output,
+            invert: false,
+            debounce: 0,
+            pending: false,
+            stable: 0,
+        }
+    }
+
+    pub fn inverted(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Debounce time in ms, rounded to POLL_US
+    pub fn debounced(mut self, ms: u16) -> Self {
+        self.debounce = (ms as u32 * 1000 / POLL_US).min(u16::MAX as u32) as u16;
+        self
+    }
+
+    /// Debounce time in ms
+    pub fn debounce_ms(&self) -> u32 {
+        self.debounce as u32 * POLL_US / 1000
+    }
+
+    /// Returns the output level once the input level is stable
+    fn update(&mut self, level: bool) -> Option<bool> {
+        if level != self.pending {
+            self.pending = level;
+            self.stable = 0;
+        }
+
+        if self.stable < self.debounce {
+            self.stable += 1;
+            return None;
+        }
+        Some(level ^ self.invert)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Mirrors
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    rules: Vec<Mirror, MAX_MIRRORS>,
+    task:  Option<EntryId>,
+}
+
+pub struct Mirrors {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Mirrors {
+    /// Adds a rule, replacing the rule driving the same output. Starts polling if needed.
+    /// The pins have to be registered as input and output, see `device.inputs/outputs`.
+    pub fn add(&self, mirror: Mirror) -> Result<()> {
+        if mirror.input == mirror.output {
+            return Err(Error::SamePin);
+        }
+
+        let start = with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+
+            inner.rules.retain(|rule| rule.output != mirror.output);
+            inner.rules.push(mirror).map_err(|_| Error::Full)?;
+            Ok::<_, Error>(inner.task.is_none())
+        })?;
+
+        if start {
+            match SCHEDULER.schedule_in(POLL_US, poll, 0) {
+                Ok(id) => with(|cs| self.inner.borrow_ref_mut(cs).task = Some(id)),
+                Err(e) => {
+                    let _ = self.remove(mirror.output);
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the rule driving an output, the output keeps its last level
+    pub fn remove(&self, output: u8) -> Result<()> {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let len = inner.rules.len();
+            inner.rules.retain(|rule| rule.output != output);
+
+            match inner.rules.len() < len {
+                true => Ok(()),
+                false => Err(Error::NotFound),
+            }
+        })
+    }
+
+    /// Removes all rules
+    pub fn clear(&self) {
+        with(|cs| self.inner.borrow_ref_mut(cs).rules.clear());
+    }
+
+    /// Copy of the active rules
+    pub fn rules(&self) -> Vec<Mirror, MAX_MIRRORS> {
+        with(|cs| self.inner.borrow_ref(cs).rules.clone())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback applying the rules, stops once no rule is left
+fn poll(_ctx: u32) -> Option<u32> {
+    // Safety: gpio_in is read only, set/clr registers are atomic
+    let sio = unsafe { hal::regs::sio() };
+    let levels = sio.gpio_in().read().bits();
+
+    with(|cs| {
+        let mut inner = MIRRORS.inner.borrow_ref_mut(cs);
+        if inner.rules.is_empty() {
+            inner.task = None;
+            return None;
+        }
+
+        // Released high like a pulled up key, rules on it wait while it can't be read
+        let bootsel = match inner.rules.iter().any(|rule| rule.input == bootsel::GPIO) {
+            true => bootsel::try_is_pressed().map(|pressed| !pressed),
+            false => None,
+        };
+
+        for rule in inner.rules.iter_mut() {
+            let level = match rule.input {
+                bootsel::GPIO => match bootsel {
+                    Some(level) => level,
+                    None => continue,
+                },
+                input => levels & (1 << input) != 0,
+            };
+            let mask = 1u32 << rule.output;
+            match rule.update(level) {
+                Some(true) => sio.gpio_out_set().write(|w| unsafe { w.bits(mask) }),
+                Some(false) => sio.gpio_out_clr().write(|w| unsafe { w.bits(mask) }),
+                None => {}
+            }
+        }
+        Some(POLL_US)
+    })
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no free mirror slot")]
+    Full,
+
+    #[error("no mirror drives this output")]
+    NotFound,
+
+    #[error("input and output are the same pin")]
+    SamePin,
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
@@ -0,0 +1,541 @@
+//! Simulated Device Backend (feature "mock")
+//!
+//! Allows the CLI and host tooling to be developed and demoed without wiring anything up:
+//! - ADC channels return synthetic waveforms instead of the pin voltage
+//! - GPIO inputs are forced high or low with the pad input override, so SIO reads,
+//!   PWM edge counters and other peripherals all see the forced level
+//! - I2C/SPI devices are emulated as 256 byte register files, answering before the real bus
+//!
+//! The mock state is configured with the `mock` command, or with script lines:
+//! ```text
+//! adc <ch> <const|sine|triangle|square|saw|noise|off> [period_ms] [amplitude] [offset]
+//! pin <gpio> <high|low|off>                       ex: pin 9 low
+//! i2c <addr> <reg> <byte>..                       ex: i2c 0x6a 0x0f 0x69
+//! spi <bus> <reg> <byte>..                        ex: spi spi0 0x00 0xef 0x40
+//! ```
+//! Emulated I2C devices answer on any I2C bus. Writes set the register pointer with the first
+//! byte and store the following ones, reads return the registers from the pointer onwards.
+//! Emulated SPI devices follow the register map convention: MSB of the first byte set for reads.
+
+use core::cell::RefCell;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::hal;
+//
+use hal::pac;
+use hal::timer::Timer;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::adcs::{ADC_BITS, TEMP_SENSE_CHN};
+use super::buses::{self, BusId};
+use super::config::{self, CONFIG};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const NUM_ADC_CHANNELS: usize = TEMP_SENSE_CHN as usize + 1;
+const NUM_GPIOS: u8 = hal::NUM_GPIOS as u8;
+const MAX_EMULATED: usize = 4;
+
+const ADC_FULL_SCALE: i32 = (1 << ADC_BITS) - 1;
+const ADC_MID: u16 = 1 << (ADC_BITS - 1);
+
+const PHASE_STEPS: i64 = 4096; // one waveform period
+const SPI_READ_BIT: u8 = 0x80;
+
+pub static MOCK: Mock = Mock {
+    inner: Mutex::new(RefCell::new(Inner {
+        timer:    None,
+        adc:      [None; NUM_ADC_CHANNELS],
+        emulated: Vec::new(),
+        seed:     0x1234_5678,
+    })),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Enables the waveform time base. Called once by the device setup.
+pub fn init(timer: Timer) {
+    with(|cs| MOCK.inner.borrow_ref_mut(cs).timer = Some(timer));
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Waveforms
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wave {
+    Const,
+    Sine,
+    Triangle,
+    Square,
+    Saw,
+    Noise,
+}
+
+impl FromStr for Wave {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "const" => Ok(Wave::Const),
+            "sine" => Ok(Wave::Sine),
+            "triangle" => Ok(Wave::Triangle),
+            "square" => Ok(Wave::Square),
+            "saw" => Ok(Wave::Saw),
+            "noise" => Ok(Wave::Noise),
+            _ => Err(Error::InvalidWave),
+        }
+    }
+}
+
+impl fmt::Display for Wave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Wave::Const => "const",
+            Wave::Sine => "sine",
+            Wave::Triangle => "triangle",
+            Wave::Square => "square",
+            Wave::Saw => "saw",
+            Wave::Noise => "noise",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Synthetic ADC signal: offset +/- amplitude, in raw ADC counts.
+/// A `Const` waveform returns the offset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Waveform {
+    pub wave:      Wave,
+    pub period_ms: u32,
+    pub amplitude: u16,
+    pub offset:    u16,
+}
+
+impl Waveform {
+    pub fn new(wave: Wave) -> Self {
+        Self {
+            wave,
+            period_ms: 1000,
+            amplitude: ADC_MID - 1,
+            offset: ADC_MID,
+        }
+    }
+
+    /// Raw ADC value at `time_us`. `random` is used by the noise waveform.
+    fn sample(&self, time_us: u64, random: u32) -> u16 {
+        let amp = self.amplitude as i64;
+        let period_us = self.period_ms.max(1) as u64 * 1000;
+        let phase = ((time_us % period_us) as i64 * PHASE_STEPS) / period_us as i64;
+        let half = PHASE_STEPS / 2;
+
+        let delta = match self.wave {
+            Wave::Const => 0,
+            Wave::Sine => {
+                // Bhaskara I approximation over each half period
+                let x = phase % half;
+                let p = x * (half - x);
+                let value = 16 * p * amp / (5 * half * half - 4 * p);
+                if phase < half { value } else { -value }
+            }
+            Wave::Triangle if phase < half => -amp + 2 * amp * phase / half,
+            Wave::Triangle => amp - 2 * amp * (phase - half) / half,
+            Wave::Square if phase < half => amp,
+            Wave::Square => -amp,
+            Wave::Saw => -amp + 2 * amp * phase / PHASE_STEPS,
+            Wave::Noise => (random % (2 * amp as u32 + 1)) as i64 - amp,
+        };
+
+        (self.offset as i64 + delta).clamp(0, ADC_FULL_SCALE as i64) as u16
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.wave {
+            Wave::Const => write!(f, "const {}", self.offset),
+            _ => write!(
+                f,
+                "{} {}ms {} +/- {}",
+                self.wave, self.period_ms, self.offset, self.amplitude
+            ),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                        Emulated Devices
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    I2c(u8), // address, on any I2C bus
+    Spi(BusId),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::I2c(addr) => write!(f, "i2c 0x{addr:02x}"),
+            Target::Spi(bus) => write!(f, "{bus}"),
+        }
+    }
+}
+
+struct Emulated {
+    target:  Target,
+    regs:    [u8; 256],
+    pointer: u8,
+}
+
+impl Emulated {
+    /// Stores data from a register onwards, leaving the pointer after the last one
+    fn write_at(&mut self, reg: u8, data: &[u8]) {
+        self.pointer = reg;
+        for byte in data {
+            self.regs[self.pointer as usize] = *byte;
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+
+    /// Reads from the register pointer onwards
+    fn read(&mut self, buf: &mut [u8]) {
+        for byte in buf.iter_mut() {
+            *byte = self.regs[self.pointer as usize];
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Mock
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    timer:    Option<Timer>,
+    adc:      [Option<Waveform>; NUM_ADC_CHANNELS],
+    emulated: Vec<Emulated, MAX_EMULATED>,
+    seed:     u32,
+}
+
+pub struct Mock {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Mock {
+    // ——————————————————————————————————————————— ADC ——————————————————————————————————————————————
+
+    /// Sets or removes (None) the waveform of an ADC channel (0-3, 4 as TEMP_SENSE)
+    pub fn set_adc(&self, channel: u8, waveform: Option<Waveform>) -> Result<()> {
+        if channel as usize >= NUM_ADC_CHANNELS {
+            return Err(Error::InvalidChannel);
+        }
+        with(|cs| self.inner.borrow_ref_mut(cs).adc[channel as usize] = waveform);
+        Ok(())
+    }
+
+    pub fn adc_waveform(&self, channel: u8) -> Option<Waveform> {
+        with(|cs| *self.inner.borrow_ref(cs).adc.get(channel as usize)?)
+    }
+
+    /// Synthetic reading of the channel. Returns None if the channel is not mocked.
+    pub fn read_adc(&self, channel: u8) -> Option<u16> {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let waveform = (*inner.adc.get(channel as usize)?)?;
+
+            // xorshift32
+            inner.seed ^= inner.seed << 13;
+            inner.seed ^= inner.seed >> 17;
+            inner.seed ^= inner.seed << 5;
+
+            let time_us = inner.timer.map(|t| t.get_counter().ticks()).unwrap_or(0);
+            Some(waveform.sample(time_us, inner.seed))
+        })
+    }
+
+    // ——————————————————————————————————————————— GPIO —————————————————————————————————————————————
+
+    /// Forces the input level seen by the chip on a GPIO, None restores the pad level
+    pub fn force_pin(&self, gpio: u8, level: Option<bool>) -> Result<()> {
+        if gpio >= NUM_GPIOS {
+            return Err(Error::InvalidPin);
+        }
+        CONFIG.check_not_reserved(gpio)?;
+
+        let io = unsafe { pac::IO_BANK0::steal() };
+        io.gpio(gpio as usize)
+            .gpio_ctrl()
+            .modify(|_, w| match level {
+                Some(true) => w.inover().high(),
+                Some(false) => w.inover().low(),
+                None => w.inover().normal(),
+            });
+        Ok(())
+    }
+
+    /// Returns the forced input level of a GPIO
+    pub fn forced_pin(&self, gpio: u8) -> Option<bool> {
+        if gpio >= NUM_GPIOS {
+            return None;
+        }
+
+        let io = unsafe { pac::IO_BANK0::steal() };
+        let inover = io.gpio(gpio as usize).gpio_ctrl().read().inover();
+        if inover.is_high() {
+            Some(true)
+        }
+        else if inover.is_low() {
+            Some(false)
+        }
+        else {
+            None
+        }
+    }
+
+    // ——————————————————————————————————————— Emulated Devices —————————————————————————————————————
+
+    /// Sets consecutive registers of an emulated device, creating it if needed
+    pub fn set_registers(&self, target: Target, reg: u8, data: &[u8]) -> Result<()> {
+        if let Target::Spi(bus) = target
+            && bus.is_i2c()
+        {
+            return Err(Error::InvalidLine);
+        }
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+
+            if !inner.emulated.iter().any(|e| e.target == target) {
+                let emulated = Emulated {
+                    target,
+                    regs: [0; 256],
+                    pointer: 0,
+                };
+                inner.emulated.push(emulated).map_err(|_| Error::Full)?;
+            }
+
+            let emulated = inner
+                .emulated
+                .iter_mut()
+                .find(|e| e.target == target)
+                .unwrap();
+            for (i, byte) in data.iter().enumerate() {
+                emulated.regs[reg.wrapping_add(i as u8) as usize] = *byte;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the emulated targets
+    pub fn emulated(&self) -> Vec<Target, MAX_EMULATED> {
+        with(|cs| {
+            self.inner
+                .borrow_ref(cs)
+                .emulated
+                .iter()
+                .map(|e| e.target)
+                .collect()
+        })
+    }
+
+    /// Copies the registers of an emulated device
+    pub fn registers(&self, target: Target) -> Option<[u8; 256]> {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            inner
+                .emulated
+                .iter()
+                .find(|e| e.target == target)
+                .map(|e| e.regs)
+        })
+    }
+
+    /// I2C write then read on an emulated device. Returns false if no device has the address.
+    pub fn i2c_transfer(&self, addr: u8, bytes: &[u8], buf: &mut [u8]) -> bool {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let Some(emulated) = inner
+                .emulated
+                .iter_mut()
+                .find(|e| e.target == Target::I2c(addr))
+            else {
+                return false;
+            };
+
+            if let Some((reg, data)) = bytes.split_first() {
+                emulated.write_at(*reg, data);
+            }
+            emulated.read(buf);
+            true
+        })
+    }
+
+    /// SPI transfer on an emulated device. Returns false if the bus is not emulated.
+    pub fn spi_transfer(&self, bus: BusId, buf: &mut [u8]) -> bool {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let Some(emulated) = inner
+                .emulated
+                .iter_mut()
+                .find(|e| e.target == Target::Spi(bus))
+            else {
+                return false;
+            };
+
+            let Some((first, data)) = buf.split_first_mut()
+            else {
+                return true;
+            };
+
+            let reg = *first & !SPI_READ_BIT;
+            if *first & SPI_READ_BIT != 0 {
+                emulated.pointer = reg;
+                emulated.read(data);
+            }
+            else {
+                emulated.write_at(reg, data);
+                data.fill(0);
+            }
+            *first = 0;
+            true
+        })
+    }
+
+    // ——————————————————————————————————————————— Script ———————————————————————————————————————————
+
+    /// Applies script lines, separated by new lines or ';'
+    pub fn apply_script(&self, script: &str) -> Result<usize> {
+        let mut count = 0;
+        for line in script
+            .split(['\n', ';'])
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+        {
+            self.apply_line(line)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Parses and applies a single script line
+    pub fn apply_line(&self, line: &str) -> Result<()> {
+        let mut words = line.split_ascii_whitespace();
+
+        match words.next() {
+            Some("adc") => {
+                let channel = words.next().and_then(parse_u8).ok_or(Error::InvalidLine)?;
+                let wave = words.next().ok_or(Error::InvalidLine)?;
+                if wave == "off" {
+                    return self.set_adc(channel, None);
+                }
+
+                let mut waveform = Waveform::new(wave.parse()?);
+                let mut numbers = words.map(|w| w.parse::<u32>().map_err(|_| Error::InvalidLine));
+
+                if waveform.wave == Wave::Const {
+                    waveform.offset = numbers.next().transpose()?.unwrap_or(0) as u16;
+                }
+                else {
+                    if let Some(period) = numbers.next().transpose()? {
+                        waveform.period_ms = period;
+                    }
+                    if let Some(amplitude) = numbers.next().transpose()? {
+                        waveform.amplitude = amplitude as u16;
+                    }
+                    if let Some(offset) = numbers.next().transpose()? {
+                        waveform.offset = offset as u16;
+                    }
+                }
+                self.set_adc(channel, Some(waveform))
+            }
+            Some("pin") => {
+                let gpio = words.next().and_then(parse_u8).ok_or(Error::InvalidLine)?;
+                let level = match words.next() {
+                    Some("high") | Some("1") => Some(true),
+                    Some("low") | Some("0") => Some(false),
+                    Some("off") => None,
+                    _ => return Err(Error::InvalidLine),
+                };
+                self.force_pin(gpio, level)
+            }
+            Some(kind @ ("i2c" | "spi")) => {
+                let target = words.next().ok_or(Error::InvalidLine)?;
+                let target = if kind == "i2c" {
+                    Target::I2c(parse_u8(target).ok_or(Error::InvalidLine)?)
+                }
+                else {
+                    Target::Spi(target.parse()?)
+                };
+                let reg = words.next().and_then(parse_u8).ok_or(Error::InvalidLine)?;
+
+                let mut data: Vec<u8, 32> = Vec::new();
+                for word in words {
+                    let byte = parse_u8(word).ok_or(Error::InvalidLine)?;
+                    data.push(byte).map_err(|_| Error::InvalidLine)?;
+                }
+                self.set_registers(target, reg, &data)
+            }
+            _ => Err(Error::InvalidLine),
+        }
+    }
+
+    /// Removes all waveforms, forced pins and emulated devices
+    pub fn clear(&self) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.adc = [None; NUM_ADC_CHANNELS];
+            inner.emulated.clear();
+        });
+
+        for gpio in 0..NUM_GPIOS {
+            let _ = self.force_pin(gpio, None);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid adc channel, use 0-4")]
+    InvalidChannel,
+
+    #[error("invalid gpio")]
+    InvalidPin,
+
+    #[error("invalid waveform, use const, sine, triangle, square, saw, noise or off")]
+    InvalidWave,
+
+    #[error("invalid mock line")]
+    InvalidLine,
+
+    #[error("too many emulated devices")]
+    Full,
+
+    #[error(transparent)]
+    Bus(#[from] buses::Error),
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Parses a hex (0x..) or decimal byte
+fn parse_u8(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
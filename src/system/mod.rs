@@ -1,7 +1,62 @@
 pub mod adcs;
+pub mod address;
+pub mod audio;
+pub mod auth;
+pub mod board;
+pub mod bootsel;
+pub mod bus_trace;
+pub mod buses;
+pub mod button;
+pub mod choreo;
+pub mod clocks;
+pub mod cmd_timeout;
+pub mod comparator;
 pub mod config;
+pub mod cpu_load;
 pub mod delay;
 pub mod device;
+pub mod dimmer;
+pub mod dry_run;
+pub mod encoder_sim;
+pub mod eventlog;
+pub mod events;
+#[cfg(feature = "async")]
+pub mod executor;
+pub mod files;
+pub mod flash;
 pub mod gpios;
+#[cfg(feature = "heap")]
+pub mod heap;
+pub mod identity;
+pub mod kv;
+pub mod led;
+pub mod logic;
+pub mod markers;
+pub mod mirror;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod output_guard;
+#[cfg(feature = "panic-persist")]
+pub mod panic;
+pub mod playback;
+pub mod power_on;
+pub mod profile;
+pub mod psu;
 pub mod pwms;
+pub mod rc_input;
+pub mod regmap;
+pub mod rgb_led;
+pub mod rng;
+pub mod safe_mode;
+pub mod scheduler;
+pub mod secure;
 pub mod serial_io;
+pub mod settings;
+pub mod shutdown;
+pub mod snapshot;
+pub mod stack_guard;
+pub mod status;
+pub mod tick;
+pub mod tpo;
+pub mod txn;
+pub mod uart;
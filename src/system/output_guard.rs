@@ -0,0 +1,314 @@
+//! Output Guard
+//!
+//! Protective limits for the outputs driving hardware that wears out or breaks when switched too
+//! fast, enforced on every write whatever command, script or background service drives the pin:
+//! - Relay: maximum level changes per second, faster changes are refused
+//! - Motor: maximum duty change in % per second, larger steps are ramped at that rate
+//!
+//! Limits are declared per pin alias in `pin_config::OUTPUT_LIMITS`, pins without a limit are
+//! written straight away. Outputs go through `gpios::set_level` / `IoPins::set_output` and PWM
+//! duties through `pwms::write_channel_cc`.
+//!
+//! Watchdog: a relay output refused `TRIP_REFUSALS` times in a row (a runaway macro or script) is
+//! tripped, driven low and held low until `GUARD.reset`.
+//!
+//! Example:
+//! ```rust
+//! GUARD.check_level(gpio, true)?; // Err(TooFast) within the minimum interval
+//! let cc = GUARD.limit_duty(gpio, cc); // the part of the step allowed now
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::{Mutex, with};
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use super::config::CONFIG;
+use super::gpios::{self, NUM_MCU_PINS};
+use super::scheduler::{EntryId, SCHEDULER};
+use super::{events, pwms};
+use crate::utils::time;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Consecutive refused changes tripping a relay output
+pub const TRIP_REFUSALS: u8 = 5;
+
+const RAMP_TICK_MS: u32 = 10;
+
+pub static GUARD: Guard = Guard {
+    inner: Mutex::new(RefCell::new(Inner {
+        pins:  [PinState::new(); NUM_MCU_PINS],
+        entry: None,
+    })),
+};
+
+/// Limits of the pin config, resolved to gpio numbers on first use
+static LIMITS: Lazy<[Option<Limit>; NUM_MCU_PINS]> = Lazy::new(|| {
+    let mut limits = [None; NUM_MCU_PINS];
+    for def in crate::pin_config::OUTPUT_LIMITS {
+        if let Ok(gpio) = CONFIG.get_gpio(def.alias) {
+            limits[gpio as usize] = Some(def.limit);
+        }
+    }
+    limits
+});
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Limits
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limit {
+    /// Maximum level changes per second
+    Relay { max_hz: u16 },
+    /// Maximum duty change in % per second
+    Motor { max_rate: u16 },
+}
+
+/// Limit of an output, by alias
+pub struct LimitDef {
+    pub alias: &'static str,
+    pub limit: Limit,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Relay { max_hz } => write!(f, "relay, max {max_hz} changes/s"),
+            Limit::Motor { max_rate } => write!(f, "motor, max {max_rate}%/s duty change"),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Guard
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub gpio:    u8,
+    pub limit:   Limit,
+    /// Changes refused since the guard started
+    pub refused: u32,
+    pub tripped: bool,
+    /// Duty the ramp is heading to
+    pub ramping: Option<u16>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct PinState {
+    /// Timer microseconds of the last level change or duty write
+    last:        Option<u64>,
+    /// Consecutive refused changes
+    refusals:    u8,
+    refused:     u32,
+    tripped:     bool,
+    ramp_target: Option<u16>,
+}
+
+impl PinState {
+    const fn new() -> Self {
+        Self {
+            last:        None,
+            refusals:    0,
+            refused:     0,
+            tripped:     false,
+            ramp_target: None,
+        }
+    }
+}
+
+struct Inner {
+    pins:  [PinState; NUM_MCU_PINS],
+    entry: Option<EntryId>,
+}
+
+pub struct Guard {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Guard {
+    /// Checks a level write of a relay output, a change within the minimum interval is refused
+    pub fn check_level(&self, gpio: u8, high: bool) -> Result<()> {
+        let Some(Limit::Relay { max_hz }) = limit(gpio)
+        else {
+            return Ok(());
+        };
+        // Writing the current level is not a change
+        if gpios::output_level(gpio) == high {
+            return Ok(());
+        }
+
+        let now = time::now_us();
+        with(|cs| {
+            let pin = &mut self.inner.borrow_ref_mut(cs).pins[gpio as usize];
+
+            // Driving low is always safe
+            if pin.tripped && high {
+                pin.refused += 1;
+                return Err(Error::Tripped);
+            }
+
+            let min_interval_us = 1_000_000 / max_hz.max(1) as u64;
+            if let Some(last) = pin.last
+                && now - last < min_interval_us
+            {
+                pin.refused += 1;
+                pin.refusals = pin.refusals.saturating_add(1);
+                if pin.refusals >= TRIP_REFUSALS {
+                    pin.tripped = true;
+                    gpios::write_level(gpio, false);
+                    let alias = CONFIG.get_alias(gpio).unwrap_or("?");
+                    events::alarm(format_args!("alarm=guard pin={alias} state=tripped"));
+                    return Err(Error::Tripped);
+                }
+                return Err(Error::TooFast(max_hz));
+            }
+
+            pin.last = Some(now);
+            pin.refusals = 0;
+            Ok(())
+        })
+    }
+
+    /// Compare level to write now for a requested one. A motor output moves at most by its
+    /// rate since the last write, the rest of the step is ramped in the background.
+    pub fn limit_duty(&self, gpio: u8, cc: u16) -> u16 {
+        let Some(Limit::Motor { max_rate }) = limit(gpio)
+        else {
+            return cc;
+        };
+
+        let now = time::now_us();
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let pin = &mut inner.pins[gpio as usize];
+
+            // Capped to one tick, a step after an idle time is still ramped
+            let elapsed_us = pin
+                .last
+                .map_or(0, |last| now - last)
+                .min(RAMP_TICK_MS as u64 * 1000);
+            let allowed = max_step(gpio, max_rate, elapsed_us as u32).max(1);
+            pin.last = Some(now);
+
+            let current = pwms::read_cc(gpio);
+            let next = step_towards(current, cc, allowed);
+            pin.ramp_target = (next != cc).then_some(cc);
+
+            if pin.ramp_target.is_some() && inner.entry.is_none() {
+                inner.entry = SCHEDULER.schedule_in(RAMP_TICK_MS * 1000, ramp, 0).ok();
+            }
+            next
+        })
+    }
+
+    /// Clears the trip and the counters of an output, or of every output
+    pub fn reset(&self, gpio: Option<u8>) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            for (id, pin) in inner.pins.iter_mut().enumerate() {
+                if gpio.is_none_or(|gpio| gpio as usize == id) {
+                    pin.refusals = 0;
+                    pin.refused = 0;
+                    pin.tripped = false;
+                }
+            }
+        })
+    }
+
+    pub fn is_tripped(&self, gpio: u8) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).pins[gpio as usize].tripped)
+    }
+
+    /// Calls `f` with the status of every limited output
+    pub fn for_each(&self, mut f: impl FnMut(Status)) {
+        for (gpio, limit) in LIMITS.iter().enumerate() {
+            let Some(limit) = *limit
+            else {
+                continue;
+            };
+            let pin = with(|cs| self.inner.borrow_ref(cs).pins[gpio]);
+            f(Status {
+                gpio: gpio as u8,
+                limit,
+                refused: pin.refused,
+                tripped: pin.tripped,
+                ramping: pin.ramp_target,
+            });
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Declared limit of an output
+pub fn limit(gpio: u8) -> Option<Limit> {
+    LIMITS.get(gpio as usize).copied().flatten()
+}
+
+/// Scheduler callback moving the ramping motor outputs, ends when all reached their target
+fn ramp(_ctx: u32) -> Option<u32> {
+    let now = time::now_us();
+    with(|cs| {
+        let mut inner = GUARD.inner.borrow_ref_mut(cs);
+        inner.entry?;
+
+        for (gpio, pin) in inner.pins.iter_mut().enumerate() {
+            let (Some(target), Some(Limit::Motor { max_rate })) =
+                (pin.ramp_target, limit(gpio as u8))
+            else {
+                continue;
+            };
+            let gpio = gpio as u8;
+            let step = max_step(gpio, max_rate, RAMP_TICK_MS * 1000);
+            let next = step_towards(pwms::read_cc(gpio), target, step);
+            pwms::write_cc(gpio, next);
+            pin.last = Some(now);
+            if next == target {
+                pin.ramp_target = None;
+            }
+        }
+
+        if inner.pins.iter().all(|pin| pin.ramp_target.is_none()) {
+            inner.entry = None;
+            return None;
+        }
+        Some(RAMP_TICK_MS * 1000)
+    })
+}
+
+/// Compare levels a motor output may move in `elapsed_us`
+fn max_step(gpio: u8, max_rate: u16, elapsed_us: u32) -> u16 {
+    let full = pwms::read_top(gpio) as u64 + 1;
+    (full * max_rate as u64 * elapsed_us as u64 / 100 / 1_000_000).min(u16::MAX as u64) as u16
+}
+
+fn step_towards(current: u16, target: u16, step: u16) -> u16 {
+    match current < target {
+        true => current.saturating_add(step).min(target),
+        false => current.saturating_sub(step).max(target),
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("output changed too fast (max {0} changes/s)")]
+    TooFast(u16),
+
+    #[error("output tripped by the guard, held low until \"guard reset\"")]
+    Tripped,
+}
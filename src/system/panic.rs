@@ -0,0 +1,143 @@
+//! Panic Handler (panic-persist feature)
+//!
+//! The message is stored with panic-persist and shown at the next boot. Before resetting,
+//! the handler also tries to print it over the USB serial right away: only from thread mode,
+//! when the serial is not held by the panicking code, and with a bounded number of USB polls.
+//! The LED then blinks SOS so headless users notice the reset, or the start of the message in
+//! Morse code when the "panic.morse" setting is on (`set panicmorse=on`).
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use cortex_m::peripheral::SCB;
+use cortex_m::peripheral::scb::VectActive;
+use heapless::String;
+use once_cell::sync::Lazy;
+
+use portable_atomic::AtomicBool;
+
+use super::config::CONFIG;
+use super::device::SYS_CLK_HZ;
+use super::serial_io::SERIAL;
+use super::settings::SETTINGS;
+use crate::hal;
+use crate::prelude::Ordering;
+use crate::utils::morse::{self, Signal};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MESSAGE_LENGTH: usize = 256;
+/// USB polls without progress before giving up on the live print
+const MAX_USB_POLLS: u32 = 50_000;
+const SOS_REPEAT: usize = 2;
+/// Characters of the message blinked in Morse, about a minute at 12 wpm
+const MORSE_CHARS: usize = 24;
+
+/// Morse unit in ms
+const DOT_MS: u32 = 150;
+
+pub const MORSE_KEY: &str = "panic.morse";
+
+/// Setting cached at boot, the settings may be borrowed by the panicking code
+static MORSE: AtomicBool = AtomicBool::new(false);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Panic Handler
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[cfg_attr(not(test), panic_handler)]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+
+    // Shown at the next boot
+    panic_persist::report_panic_info(info);
+
+    // Live print, interrupt handlers may hold the USB peripheral in an unknown state
+    if SCB::vect_active() == VectActive::ThreadMode {
+        let mut msg: String<MESSAGE_LENGTH> = String::new();
+        let _ = write!(msg, "\r\n========= PANIC ===========\r\n{info}\r\nResetting...\r\n");
+        let _ = SERIAL.write_best_effort(msg.as_bytes(), MAX_USB_POLLS);
+    }
+
+    if MORSE.load(Ordering::Relaxed) {
+        let mut text = Truncated::<MORSE_CHARS>(String::new());
+        let _ = write!(text, "{}", info.message());
+        blink_morse(&text.0);
+    }
+    else {
+        blink_sos();
+    }
+    SCB::sys_reset();
+}
+
+/// Keeps the first N bytes written, the rest is dropped without an error
+struct Truncated<const N: usize>(String<N>);
+
+impl<const N: usize> Write for Truncated<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Reads the Morse setting, at boot
+pub fn init() {
+    set_morse(SETTINGS.get_parsed(MORSE_KEY).unwrap_or(false));
+}
+
+pub fn set_morse(enabled: bool) {
+    MORSE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_morse() -> bool {
+    MORSE.load(Ordering::Relaxed)
+}
+
+fn blink_sos() {
+    for _ in 0..SOS_REPEAT {
+        blink_morse("SOS");
+    }
+}
+
+/// Blinks a text in Morse on the LED through the SIO registers, skipped on boards without a
+/// GPIO LED. The pin config is only read if it was already initialized, it may be the panic source.
+fn blink_morse(text: &str) {
+    let Some(gpio) = Lazy::get(&CONFIG).and_then(|config| config.get_gpio("LED").ok())
+    else {
+        return;
+    };
+
+    // Safety: write only set/clear registers of a pin already configured as output
+    let sio = unsafe { hal::regs::sio() };
+    let mask = 1u32 << gpio;
+
+    for Signal { on, units } in morse::signals(text) {
+        if on {
+            sio.gpio_out_set().write(|w| unsafe { w.bits(mask) });
+        }
+        else {
+            sio.gpio_out_clr().write(|w| unsafe { w.bits(mask) });
+        }
+        delay_ms(units as u32 * DOT_MS);
+    }
+    sio.gpio_out_clr().write(|w| unsafe { w.bits(mask) });
+    delay_ms(morse::WORD_GAP as u32 * DOT_MS);
+}
+
+/// Busy wait, the timer and DELAY may be unusable while panicking
+fn delay_ms(ms: u32) {
+    let cycles_per_ms = SYS_CLK_HZ.load(Ordering::Relaxed) / 1000;
+    for _ in 0..ms {
+        cortex_m::asm::delay(cycles_per_ms);
+    }
+}
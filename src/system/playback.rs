@@ -0,0 +1,333 @@
+//! GPIO Timeline Playback
+//!
+//! Replays a timeline of output levels with the microsecond scheduler: repeatable stimulus for
+//! hardware in the loop tests. Events are paced from the first deadline (drift free) and
+//! driven through the SIO registers, events sharing a time are applied together.
+//!
+//! Timelines are uploaded as "time_us,pin,level" lines and stored as RAM files ("<name>.tl"),
+//! saved to flash with the file store. Binary format: [time_us: u32 LE][gpio: u8][level: u8].
+//!
+//! A repeated timeline restarts at its last event, the first event of the next cycle follows
+//! after its own time offset.
+//!
+//! `start_at` plays the event times from a timer tick instead of now, ex: a shared start tick
+//! (see `SCHEDULER.latch_start`).
+//!
+//! Example:
+//! ```rust
+//! let mut events = Timeline::new();
+//! events.push(Event::parse("0,OUT_A,1")?)?;
+//! events.push(Event::parse("500,OUT_A,0")?)?;
+//! PLAYBACK.start(&events, 10)?; // 10 cycles
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::config::{self, CONFIG};
+use super::files::{self, FILES};
+use super::gpios;
+use super::scheduler::{self, EntryId, Instant, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub use crate::limits::MAX_PLAYBACK_EVENTS as MAX_EVENTS;
+pub const EVENT_SIZE: usize = 6;
+
+const EXTENSION: &str = ".tl";
+
+pub static PLAYBACK: Playback = Playback {
+    inner: Mutex::new(RefCell::new(Inner {
+        events: Vec::new(),
+        next:   0,
+        repeat: 0,
+        cycles: 0,
+        entry:  None,
+    })),
+};
+
+pub type Timeline = Vec<Event, MAX_EVENTS>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Event
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Offset from the timeline start
+    pub time_us: u32,
+    pub gpio:    u8,
+    pub high:    bool,
+}
+
+impl Event {
+    /// Parses "time_us,pin,level", the pin is a gpio or an alias and the level 0/1 or low/high
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split(',').map(str::trim);
+        let (Some(time), Some(pin), Some(level), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(Error::InvalidLine);
+        };
+
+        let time_us = time.parse().map_err(|_| Error::InvalidLine)?;
+        let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+        let high = match level {
+            "1" => true,
+            "0" => false,
+            level if level.eq_ignore_ascii_case("high") => true,
+            level if level.eq_ignore_ascii_case("low") => false,
+            _ => return Err(Error::InvalidLine),
+        };
+
+        Ok(Self { time_us, gpio, high })
+    }
+
+    fn to_bytes(self) -> [u8; EVENT_SIZE] {
+        let time = self.time_us.to_le_bytes();
+        [
+            time[0],
+            time[1],
+            time[2],
+            time[3],
+            self.gpio,
+            self.high as u8,
+        ]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            time_us: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            gpio:    bytes[4],
+            high:    bytes[5] != 0,
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Playback
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    events: Timeline,
+    /// Index of the next event
+    next:   usize,
+    /// Cycles to play, 0 until stopped
+    repeat: u32,
+    cycles: u32,
+    entry:  Option<EntryId>,
+}
+
+pub struct Playback {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Playback {
+    /// Starts playing a timeline, stopping the current one.
+    /// The pins have to be registered as outputs, see `device.outputs`.
+    pub fn start(&self, events: &[Event], repeat: u32) -> Result<()> {
+        self.start_at(events, repeat, SCHEDULER.now())
+    }
+
+    /// Starts playing a timeline with its times relative to a timer tick
+    pub fn start_at(&self, events: &[Event], repeat: u32, at: Instant) -> Result<()> {
+        self.prepare(events, repeat)?;
+        self.trigger_at(at)
+    }
+
+    /// Loads a timeline without starting it, stopping the current one
+    pub fn prepare(&self, events: &[Event], repeat: u32) -> Result<()> {
+        check_order(events)?;
+        if events.is_empty() {
+            return Err(Error::Empty);
+        }
+        self.stop();
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.events = Vec::from_slice(events).map_err(|_| Error::Full)?;
+            inner.repeat = repeat;
+            Ok(())
+        })
+    }
+
+    /// Starts the loaded timeline from its beginning, safe from interrupts (ex: capture trigger)
+    pub fn trigger(&self) -> Result<()> {
+        self.trigger_at(SCHEDULER.now())
+    }
+
+    /// Starts the loaded timeline with its times relative to a timer tick
+    pub fn trigger_at(&self, at: Instant) -> Result<()> {
+        self.stop();
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let first = inner.events.first().ok_or(Error::Empty)?.time_us;
+            inner.next = 0;
+            inner.cycles = 0;
+            let deadline = Instant::from_ticks(scheduler::deadline_after(at, first));
+            inner.entry = Some(SCHEDULER.schedule_at(deadline, step, 0)?);
+            Ok(())
+        })
+    }
+
+    /// Stops the playback, the outputs keep their last level
+    pub fn stop(&self) {
+        let entry = with(|cs| self.inner.borrow_ref_mut(cs).entry.take());
+        if let Some(entry) = entry {
+            SCHEDULER.cancel(entry);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).entry.is_some())
+    }
+
+    /// Completed cycles
+    pub fn cycles(&self) -> u32 {
+        with(|cs| self.inner.borrow_ref(cs).cycles)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback applying the events due, returns the time until the next ones
+fn step(_ctx: u32) -> Option<u32> {
+    with(|cs| {
+        let mut inner = PLAYBACK.inner.borrow_ref_mut(cs);
+        inner.entry?;
+
+        let time_us = inner.events.get(inner.next)?.time_us;
+        let due = inner.events[inner.next..]
+            .iter()
+            .take_while(|event| event.time_us == time_us)
+            .count();
+
+        for event in &inner.events[inner.next..inner.next + due] {
+            gpios::set_level(event.gpio, event.high);
+        }
+        inner.next += due;
+
+        if let Some(event) = inner.events.get(inner.next) {
+            return Some(event.time_us - time_us);
+        }
+
+        // End of the timeline
+        inner.cycles += 1;
+        if inner.repeat != 0 && inner.cycles >= inner.repeat {
+            inner.entry = None;
+            return None;
+        }
+        inner.next = 0;
+        Some(inner.events[0].time_us)
+    })
+}
+
+/// Events have to be sorted by time
+fn check_order(events: &[Event]) -> Result<()> {
+    match events
+        .windows(2)
+        .all(|pair| pair[0].time_us <= pair[1].time_us)
+    {
+        true => Ok(()),
+        false => Err(Error::Unordered),
+    }
+}
+
+/// Stores a timeline in the file store, in RAM until the store is saved
+pub fn save(name: &str, events: &[Event]) -> Result<()> {
+    check_order(events)?;
+
+    let mut data = Vec::<u8, { MAX_EVENTS * EVENT_SIZE }>::new();
+    for event in events {
+        data.extend_from_slice(&event.to_bytes())
+            .map_err(|_| Error::Full)?;
+    }
+    FILES.write(&file_name(name)?, &data, false)?;
+    Ok(())
+}
+
+/// Reads a timeline from the file store
+pub fn load(name: &str) -> Result<Timeline> {
+    let data = FILES.read(&file_name(name)?).map_err(|_| Error::NotFound)?;
+    if data.len() % EVENT_SIZE != 0 {
+        return Err(Error::InvalidFile);
+    }
+
+    let mut events = Timeline::new();
+    for bytes in data.chunks(EVENT_SIZE) {
+        events
+            .push(Event::from_bytes(bytes))
+            .map_err(|_| Error::Full)?;
+    }
+    Ok(events)
+}
+
+/// Removes a timeline from the file store
+pub fn remove(name: &str) -> Result<()> {
+    match FILES.remove(&file_name(name)?) {
+        true => Ok(()),
+        false => Err(Error::NotFound),
+    }
+}
+
+/// Calls `f` with the name and event count of every stored timeline
+pub fn for_each(mut f: impl FnMut(&str, usize)) {
+    FILES.for_each(|file, size| {
+        if let Some(name) = file.strip_suffix(EXTENSION) {
+            f(name, size / EVENT_SIZE);
+        }
+    });
+}
+
+fn file_name(name: &str) -> Result<files::Name> {
+    let mut file = files::Name::new();
+    file.push_str(name).map_err(|_| Error::InvalidName)?;
+    file.push_str(EXTENSION).map_err(|_| Error::InvalidName)?;
+    Ok(file)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid event, expected time_us,pin,level")]
+    InvalidLine,
+
+    #[error("events are not sorted by time")]
+    Unordered,
+
+    #[error("empty timeline")]
+    Empty,
+
+    #[error("timeline full")]
+    Full,
+
+    #[error("timeline not found")]
+    NotFound,
+
+    #[error("timeline name too long")]
+    InvalidName,
+
+    #[error("corrupted timeline file")]
+    InvalidFile,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Files(#[from] files::Error),
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
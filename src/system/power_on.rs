@@ -0,0 +1,240 @@
+//! Power-On Defaults
+//!
+//! States applied in `Device::new` before any command runs, so the attached hardware comes up in
+//! a defined and safe state:
+//! - outputs: `low` or `high`, latched before the output driver is enabled (no glitch)
+//! - PWM pins: `pwm:<freq>:<duty %>[:off]`, frequency and duty of the channel, the slice is
+//!   enabled unless `off`
+//!
+//! Defaults are declared per alias in `pin_config::POWER_ON`, a `poweron.<alias>` flash setting
+//! overrides them (`setup poweron=OUT_A:high`). Power-on states are applied as they are, the
+//! output guard limits only apply to the later changes.
+//!
+//! Example:
+//! ```rust
+//! power_on::latch_outputs(); // before the outputs are taken
+//! power_on::apply_pwm(&mut pwms);
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use heapless::String;
+use thiserror::Error;
+
+use super::config::{self, CONFIG, Group};
+use super::gpios;
+use super::pwms::{self, Pwms};
+use super::settings::{self, SETTINGS};
+use super::shutdown::Reason;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const KEY_PREFIX: &str = "poweron.";
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Power On
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerOn {
+    Low,
+    High,
+    Pwm { freq: u32, duty: u8, enabled: bool },
+}
+
+/// Power-on state of a pin, by alias
+pub struct PowerOnDef {
+    pub alias: &'static str,
+    pub state: PowerOn,
+}
+
+/// Where the state of a pin comes from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    Config,
+    Settings,
+}
+
+impl FromStr for PowerOn {
+    type Err = Error;
+
+    /// Parses "low", "high" or "pwm:<freq>:<duty>[:off]"
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "low" => return Ok(PowerOn::Low),
+            "high" => return Ok(PowerOn::High),
+            _ => {}
+        }
+
+        let mut fields = s.split(':');
+        if fields.next() != Some("pwm") {
+            return Err(Error::InvalidState);
+        }
+        let freq = fields
+            .next()
+            .and_then(|freq| freq.parse().ok())
+            .filter(|freq| *freq > 0)
+            .ok_or(Error::InvalidState)?;
+        let duty = fields
+            .next()
+            .and_then(|duty| duty.parse().ok())
+            .filter(|duty| *duty <= 100)
+            .ok_or(Error::InvalidState)?;
+        let enabled = match fields.next() {
+            None => true,
+            Some("off") => false,
+            Some(_) => return Err(Error::InvalidState),
+        };
+        Ok(PowerOn::Pwm { freq, duty, enabled })
+    }
+}
+
+impl fmt::Display for PowerOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerOn::Low => f.write_str("low"),
+            PowerOn::High => f.write_str("high"),
+            PowerOn::Pwm { freq, duty, enabled: true } => write!(f, "pwm:{freq}:{duty}"),
+            PowerOn::Pwm {
+                freq,
+                duty,
+                enabled: false,
+            } => write!(f, "pwm:{freq}:{duty}:off"),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Config => f.write_str("pin_config"),
+            Source::Settings => f.write_str("settings"),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Power-on state of a pin, the setting first
+pub fn state(gpio: u8) -> Option<(PowerOn, Source)> {
+    let alias = CONFIG.get_alias(gpio).ok()?;
+
+    if let Some(state) = SETTINGS
+        .get(&key(alias))
+        .and_then(|value| value.parse().ok())
+    {
+        return Some((state, Source::Settings));
+    }
+    crate::pin_config::POWER_ON
+        .iter()
+        .find(|def| def.alias.eq_ignore_ascii_case(alias))
+        .map(|def| (def.state, Source::Config))
+}
+
+/// Stores the power-on state of a pin in the settings, None goes back to the pin config.
+/// The settings still have to be saved.
+pub fn set(gpio: u8, state: Option<PowerOn>) -> Result<()> {
+    let alias = CONFIG.get_alias(gpio)?;
+    let valid = matches!(
+        (CONFIG.get_group_type(gpio), state),
+        (_, None)
+            | (Some(Group::Outputs), Some(PowerOn::Low | PowerOn::High))
+            | (Some(Group::Pwm), Some(PowerOn::Pwm { .. }))
+    );
+    if !valid {
+        return Err(Error::NotApplicable);
+    }
+
+    match state {
+        Some(state) => SETTINGS.set(&key(alias), state)?,
+        None => {
+            SETTINGS.remove(&key(alias));
+        }
+    }
+    Ok(())
+}
+
+/// Latches the power-on levels of the outputs, to be called before the output pins are taken
+pub fn latch_outputs() {
+    for gpio in CONFIG.get_group_iter(Group::Outputs) {
+        match state(gpio) {
+            Some((PowerOn::High, _)) => gpios::write_level(gpio, true),
+            Some((PowerOn::Low, _)) => gpios::write_level(gpio, false),
+            _ => {}
+        }
+    }
+}
+
+/// Shutdown hook driving the outputs back to their power-on level, low without one
+pub fn park_outputs(_reason: Reason) {
+    for gpio in CONFIG.get_group_iter(Group::Outputs) {
+        let high = matches!(state(gpio), Some((PowerOn::High, _)));
+        gpios::write_level(gpio, high);
+    }
+}
+
+/// Sets the power-on frequency and duty of the PWM pins
+pub fn apply_pwm(pwms: &mut Pwms) {
+    for gpio in CONFIG.get_group_iter(Group::Pwm) {
+        let Some((PowerOn::Pwm { freq, duty, enabled }, _)) = state(gpio)
+        else {
+            continue;
+        };
+        let Ok((slice_id, _)) = pwms.get_pwm_slice_id_by_gpio(gpio)
+        else {
+            continue;
+        };
+
+        let top = crate::with_pwm_slice!(pwms, slice_id, |pwm_slice| {
+            pwm_slice.set_freq(freq);
+            if enabled {
+                pwm_slice.enable();
+            }
+            pwm_slice.slice.get_top()
+        });
+        let cc = (top as u32 + 1) * duty as u32 / 100;
+        pwms::write_cc(gpio, cc.min(u16::MAX as u32) as u16);
+    }
+}
+
+/// Calls `f` with the pins having a power-on state
+pub fn for_each(mut f: impl FnMut(u8, PowerOn, Source)) {
+    for def in CONFIG.pins.iter() {
+        if let Some((state, source)) = state(def.id) {
+            f(def.id, state, source);
+        }
+    }
+}
+
+fn key(alias: &str) -> settings::Key {
+    let mut key: String<_> = String::new();
+    let _ = key.push_str(KEY_PREFIX);
+    let _ = key.push_str(alias);
+    key
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid power-on state, expected low, high or pwm:<freq>:<duty>[:off]")]
+    InvalidState,
+
+    #[error("power-on state not applicable, low/high for outputs, pwm for PWM pins")]
+    NotApplicable,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Settings(#[from] settings::Error),
+}
@@ -0,0 +1,218 @@
+//! Test Fixture Profiles
+//!
+//! Named profiles for a board moved between several rigs. A profile is a RAM file
+//! ("<name>.prf") of key=value lines, saved to flash with the file store:
+//! - `pins`: pin setup, see `gpios::parse_setup` ("0:out:low,1:out:high,9:in:pullup")
+//! - `pwm`: default PWM frequencies, "<gpio or alias>:<hz>" pairs ("PWM2_B:50,PWM3_A:1000")
+//! - `autorun`: ';' separated command lines, run by the CLI once the profile is applied
+//!
+//! The "profile.boot" setting names the profile applied at boot, skipped in safe mode.
+//!
+//! Example:
+//! ```rust
+//! let profile: Profile = "pins=0:out:low\nautorun=pin alias=out_a high".parse()?;
+//! profile::save("fixturea", &profile)?;
+//! profile::load("fixturea")?.apply(device)?;
+//! ```
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::str::FromStr;
+
+use critical_section::{Mutex, with};
+use heapless::String;
+use thiserror::Error;
+
+use super::config::{self, CONFIG};
+use super::device::Device;
+use super::files::{self, FILES};
+use super::gpios;
+use super::settings::{self, SETTINGS};
+use crate::with_pwm_slice;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const LINE_LENGTH: usize = 96;
+pub const BOOT_KEY: &str = "profile.boot";
+
+const EXTENSION: &str = ".prf";
+
+/// Autorun lines of the last applied profile, waiting for the CLI
+static AUTORUN: Mutex<RefCell<Option<Line>>> = Mutex::new(RefCell::new(None));
+
+pub type Line = String<LINE_LENGTH>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Profile
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Empty lines are not applied
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    pub pins:    Line,
+    pub pwm:     Line,
+    pub autorun: Line,
+}
+
+impl Profile {
+    /// Sets a line by its key
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let line = match key {
+            "pins" => &mut self.pins,
+            "pwm" => &mut self.pwm,
+            "autorun" => &mut self.autorun,
+            _ => return Err(Error::InvalidKey),
+        };
+        *line = Line::try_from(value).map_err(|_| Error::TooLong)?;
+        Ok(())
+    }
+
+    /// Configures the pins, sets the PWM frequencies and queues the autorun lines.
+    /// Every entry is checked before the device is changed.
+    pub fn apply(&self, device: &mut Device) -> Result<()> {
+        let pins = gpios::parse_setup(&self.pins)?;
+
+        let mut slices = heapless::Vec::<(u8, u32), { gpios::NUM_MCU_PINS }>::new();
+        for entry in self
+            .pwm
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (pin, freq) = entry.split_once(':').ok_or(Error::InvalidPwm)?;
+            let freq = freq.parse().map_err(|_| Error::InvalidPwm)?;
+            let (gpio, _) = CONFIG.get_gpio_alias_pair(pin.parse().ok(), Some(pin))?;
+            let (slice_id, _) = device.pwms.get_pwm_slice_id_by_gpio(gpio)?;
+            slices.push((slice_id, freq)).map_err(|_| Error::TooLong)?;
+        }
+
+        gpios::configure_all(&mut device.inputs, &mut device.outputs, &pins)?;
+
+        for (slice_id, freq) in slices {
+            with_pwm_slice!(device.pwms, slice_id, |pwm_slice| pwm_slice.set_freq(freq));
+        }
+
+        if !self.autorun.is_empty() {
+            with(|cs| AUTORUN.borrow_ref_mut(cs).replace(self.autorun.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = Error;
+
+    /// Parses "key=value" lines, empty lines are skipped
+    fn from_str(s: &str) -> Result<Self> {
+        let mut profile = Profile::default();
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line.split_once('=').ok_or(Error::InvalidKey)?;
+            profile.set(key.trim(), value.trim())?;
+        }
+        Ok(profile)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = [
+            ("pins", &self.pins),
+            ("pwm", &self.pwm),
+            ("autorun", &self.autorun),
+        ];
+
+        for (key, value) in lines.iter().filter(|(_, value)| !value.is_empty()) {
+            writeln!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Reads a profile from the file store
+pub fn load(name: &str) -> Result<Profile> {
+    let data = FILES.read(&file_name(name)?).map_err(|_| Error::NotFound)?;
+    core::str::from_utf8(&data)
+        .map_err(|_| Error::InvalidKey)?
+        .parse()
+}
+
+/// Writes a profile to the file store and saves the store to flash
+pub fn save(name: &str, profile: &Profile) -> Result<()> {
+    let mut text = String::<{ 3 * (LINE_LENGTH + 10) }>::new();
+    let _ = write!(text, "{profile}");
+
+    FILES.write(&file_name(name)?, text.as_bytes(), false)?;
+    FILES.save()?;
+    Ok(())
+}
+
+/// Removes a profile from the file store and saves the store to flash
+pub fn remove(name: &str) -> Result<()> {
+    if !FILES.remove(&file_name(name)?) {
+        return Err(Error::NotFound);
+    }
+    FILES.save()?;
+    Ok(())
+}
+
+/// Calls `f` with the name of every stored profile
+pub fn for_each(mut f: impl FnMut(&str)) {
+    FILES.for_each(|file, _| {
+        if let Some(name) = file.strip_suffix(EXTENSION) {
+            f(name);
+        }
+    });
+}
+
+/// Profile applied at boot
+pub fn boot_profile() -> Option<settings::Value> {
+    SETTINGS.get(BOOT_KEY)
+}
+
+/// Takes the autorun lines queued by the last applied profile
+pub fn take_autorun() -> Option<Line> {
+    with(|cs| AUTORUN.borrow_ref_mut(cs).take())
+}
+
+fn file_name(name: &str) -> Result<files::Name> {
+    let mut file = files::Name::new();
+    file.push_str(name).map_err(|_| Error::InvalidName)?;
+    file.push_str(EXTENSION).map_err(|_| Error::InvalidName)?;
+    Ok(file)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("profile not found")]
+    NotFound,
+
+    #[error("profile name too long")]
+    InvalidName,
+
+    #[error("invalid profile line, expected pins=, pwm= or autorun=")]
+    InvalidKey,
+
+    #[error("invalid pwm entry, expected <pin>:<hz>")]
+    InvalidPwm,
+
+    #[error("profile line too long")]
+    TooLong,
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Files(#[from] files::Error),
+}
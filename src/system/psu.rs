@@ -0,0 +1,150 @@
+//! Onboard PSU Mode
+//!
+//! The Pico is powered by an RT6150B buck-boost regulator, its P-Select pin is wired to GP23:
+//! - `pfm` (low, chip default): pulse frequency modulation, the best efficiency at light load but
+//!   a larger, load dependent ripple on the 3V3 rail
+//! - `pwm` (high): fixed frequency switching, a lower and steadier ripple at the cost of a few mA
+//!   more at light load. The 3V3 rail is the ADC reference, precision analog readings are less
+//!   noisy in this mode
+//!
+//! The mode is saved as "psu.mode" and applied at boot. The pin stays in the Reserved group
+//! (never taken by the commands), it is driven through the SIO registers.
+//! Only the Pico has it: the Pico W switches it from the CYW43 (WL_GPIO1) and on the WeAct GP23
+//! is a button.
+//!
+//! Example:
+//! ```rust
+//! psu::set(PowerMode::Pwm)?; // before a precision measurement
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use super::board::BOARD;
+use super::settings::{self, SETTINGS};
+use crate::hal;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MODE_KEY: &str = "psu.mode";
+/// IO_BANK0 function select of the SIO
+const FUNCSEL_SIO: u8 = 5;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Power Mode
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Power saving, light load efficiency
+    #[default]
+    Pfm,
+    /// Low ripple
+    Pwm,
+}
+
+impl PowerMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerMode::Pfm => "pfm",
+            PowerMode::Pwm => "pwm",
+        }
+    }
+}
+
+impl FromStr for PowerMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [PowerMode::Pfm, PowerMode::Pwm]
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or(Error::InvalidMode)
+    }
+}
+
+impl fmt::Display for PowerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Applies the saved mode at boot, left untouched on boards without the pin or without a setting
+pub fn init() {
+    if let Some(mode) = SETTINGS.get_parsed::<PowerMode>(MODE_KEY) {
+        let _ = apply(mode);
+    }
+}
+
+/// Switches the regulator mode and saves it for the next boots
+pub fn set(mode: PowerMode) -> Result<()> {
+    apply(mode)?;
+    SETTINGS.set(MODE_KEY, mode)?;
+    SETTINGS.save()?;
+    Ok(())
+}
+
+/// Mode driven on the pin, None on boards without it
+pub fn mode() -> Option<PowerMode> {
+    let gpio = BOARD.psu_mode?;
+    // Safety: read only registers
+    let sio = unsafe { hal::regs::sio() };
+    let driven = sio.gpio_oe().read().bits() & (1 << gpio) != 0;
+    let high = sio.gpio_out().read().bits() & (1 << gpio) != 0;
+    Some(if driven && high { PowerMode::Pwm } else { PowerMode::Pfm })
+}
+
+/// True if the mode comes from the settings, not the regulator default
+pub fn is_saved() -> bool {
+    SETTINGS.get_parsed::<PowerMode>(MODE_KEY).is_some()
+}
+
+/// Drives the P-Select pin
+fn apply(mode: PowerMode) -> Result<()> {
+    let gpio = BOARD.psu_mode.ok_or(Error::Unsupported)?;
+
+    // Safety: the Reserved pin is only driven from here, set/clr registers are atomic
+    let sio = unsafe { hal::regs::sio() };
+    let io = unsafe { hal::regs::io_bank0() };
+    let pads = unsafe { hal::regs::pads_bank0() };
+    let mask = 1u32 << gpio;
+
+    // Level latched before the output driver is enabled (no glitch)
+    match mode {
+        PowerMode::Pwm => sio.gpio_out_set().write(|w| unsafe { w.bits(mask) }),
+        PowerMode::Pfm => sio.gpio_out_clr().write(|w| unsafe { w.bits(mask) }),
+    }
+    pads.gpio(gpio as usize)
+        .modify(|_, w| w.od().clear_bit().ie().set_bit());
+    io.gpio(gpio as usize)
+        .gpio_ctrl()
+        .modify(|_, w| unsafe { w.funcsel().bits(FUNCSEL_SIO) });
+    sio.gpio_oe_set().write(|w| unsafe { w.bits(mask) });
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no PSU mode pin on this board")]
+    Unsupported,
+
+    #[error("unknown power mode, expected pfm or pwm")]
+    InvalidMode,
+
+    #[error(transparent)]
+    Settings(#[from] settings::Error),
+}
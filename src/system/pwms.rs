@@ -3,19 +3,18 @@
 use core::convert::Infallible;
 use core::fmt;
 
-use super::config::Error;
-use super::config::Result;
+use super::config::{Error, Result};
+use super::output_guard::GUARD;
 
 use embedded_hal::pwm::SetDutyCycle;
 
-use rp2040_hal as hal;
+use crate::hal;
 //
-use hal::gpio;
-use hal::pwm;
+use hal::{gpio, pwm};
 
 use heapless::Vec;
 
-const MAX_PWM_PINS: usize = 16;
+use crate::limits::MAX_PWM_PINS;
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                              Pwms
@@ -81,8 +80,13 @@ impl Pwms {
 
         // Creating pins directly due to HAL type restrictions
         unsafe {
-            let io_bank0 = &(*hal::pac::IO_BANK0::ptr());
-            let current_func = io_bank0.gpio(gpio_id as usize).gpio_ctrl().read().funcsel().bits();
+            let io_bank0 = hal::regs::io_bank0();
+            let current_func = io_bank0
+                .gpio(gpio_id as usize)
+                .gpio_ctrl()
+                .read()
+                .funcsel()
+                .bits();
 
             // Function codes on RP2040:
             // 0 = XIP (flash)
@@ -102,7 +106,10 @@ impl Pwms {
                 }
             }
 
-            io_bank0.gpio(gpio_id as usize).gpio_ctrl().write(|w| w.funcsel().pwm());
+            io_bank0
+                .gpio(gpio_id as usize)
+                .gpio_ctrl()
+                .write(|w| w.funcsel().pwm());
         }
 
         // Creating Pin Alias used for slice and channel retrival by gpio id
@@ -123,6 +130,73 @@ impl Pwms {
         Ok((alias.slice_id, alias.channel))
     }
 
+    /// Returns the registered GPIO pins and their channel for a slice
+    pub fn get_gpios_by_slice_id(&self, slice_id: u8) -> impl Iterator<Item = (u8, Channel)> {
+        self.pwm_aliases
+            .iter()
+            .filter(move |alias| alias.slice_id == slice_id)
+            .map(|alias| (alias.gpio_id, alias.channel))
+    }
+
+    /// Starts slices on the same clock cycle through the EN register, each counter preloaded with
+    /// its (slice_id, counter) offset. The slices are stopped first, the other ones are untouched.
+    pub fn start_synced(&mut self, slices: &[(u8, u16)]) -> Result<()> {
+        if slices.iter().any(|(slice_id, _)| *slice_id > 7) {
+            return Err(Error::OutOfBounds);
+        }
+
+        let mut mask = 0u32;
+        for &(slice_id, counter) in slices {
+            crate::with_pwm_slice!(self, slice_id, |pwm_slice| {
+                pwm_slice.disable();
+                pwm_slice.slice.set_counter(counter);
+                pwm_slice.enabled = true;
+            });
+            mask |= 1 << slice_id;
+        }
+
+        // Safety: only the bits of the slices owned above are set
+        let pwm = unsafe { hal::regs::pwm() };
+        pwm.en().modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+        Ok(())
+    }
+
+    /// Reads back the slice state from the hardware registers
+    pub fn get_slice_status(&self, slice_id: u8) -> Result<SliceStatus> {
+        if slice_id > 7 {
+            return Err(Error::OutOfBounds);
+        }
+
+        // Read only register access
+        let ch = unsafe { hal::regs::pwm().ch(slice_id as usize) };
+        let csr = ch.csr().read();
+        let div = ch.div().read();
+        let cc = ch.cc().read();
+
+        let div_int = div.int().bits();
+        let div_frac = div.frac().bits();
+        let top = ch.top().read().top().bits();
+        let ph_correct = csr.ph_correct().bit();
+
+        // f = sys_clk / (div * (top + 1) * (ph_correct ? 2 : 1)), div in 1/16 fixed point
+        let div_x16 = ((div_int as u32) << 4 | div_frac as u32).max(16);
+        let ph_div = if ph_correct { 2 } else { 1 };
+        let freq_hz = (self.pwm0.sys_clk_hz as f32 * 16.0)
+            / (div_x16 as f32 * (top as u32 + 1) as f32 * ph_div as f32);
+
+        Ok(SliceStatus {
+            enabled: csr.en().bit(),
+            ph_correct,
+            div_int,
+            div_frac,
+            top,
+            cc_a: cc.a().bits(),
+            cc_b: cc.b().bits(),
+            counter: ch.ctr().read().ctr().bits(),
+            freq_hz,
+        })
+    }
+
     /// Get PWM Slice Channel from GPIO id
     pub fn get_channel_by_gpio(
         &mut self,
@@ -153,6 +227,46 @@ impl Pwms {
     }
 }
 
+// ————————————————————————————————————————— Slice Status —————————————————————————————————————————
+
+/// PWM slice state as read back from the hardware registers
+#[derive(Debug, Copy, Clone)]
+pub struct SliceStatus {
+    pub enabled:    bool,
+    pub ph_correct: bool,
+    pub div_int:    u8,
+    pub div_frac:   u8,
+    pub top:        u16,
+    pub cc_a:       u16,
+    pub cc_b:       u16,
+    pub counter:    u16,
+    pub freq_hz:    f32,
+}
+
+impl SliceStatus {
+    /// Compare value of a channel
+    pub fn get_cc(&self, channel: Channel) -> u16 {
+        match channel {
+            Channel::A => self.cc_a,
+            Channel::B => self.cc_b,
+        }
+    }
+
+    /// Channel duty cycle in percent
+    pub fn duty_percent(&self, channel: Channel) -> f32 {
+        let cc = self.get_cc(channel) as f32;
+        (cc * 100.0 / (self.top as f32 + 1.0)).min(100.0)
+    }
+
+    /// Channel high time in us
+    pub fn duty_us(&self, channel: Channel) -> f32 {
+        if self.freq_hz <= 0.0 {
+            return 0.0;
+        }
+        self.duty_percent(channel) * 10_000.0 / self.freq_hz
+    }
+}
+
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                            PwmSlice
 // ————————————————————————————————————————————————————————————————————————————————————————————————
@@ -250,6 +364,40 @@ where
         self.slice.disable();
     }
 
+    /// Switches the slice into counting rising edges on its channel B pin.
+    /// Channel A can't be used as an output while counting. Returns the slice to the
+    /// free running mode with `stop_edge_counter()`.
+    pub fn start_edge_counter(&mut self) {
+        self.slice.disable();
+
+        // Safety: Only this wrapper owns the slice registers
+        let ch = unsafe { hal::regs::pwm().ch(I::DYN.num as usize) };
+        ch.csr().modify(|_, w| w.divmode().rise());
+        ch.div()
+            .write(|w| unsafe { w.int().bits(1).frac().bits(0) });
+
+        self.slice.set_top(u16::MAX);
+        self.slice.set_counter(0);
+        self.slice.enable();
+    }
+
+    /// Rising edges counted since start, wrapping at u16::MAX
+    pub fn read_edge_counter(&self) -> u16 {
+        self.slice.get_counter()
+    }
+
+    /// Restores the free running mode and the previous frequency settings
+    pub fn stop_edge_counter(&mut self, top: u16) {
+        self.slice.disable();
+
+        // Safety: Only this wrapper owns the slice registers
+        let ch = unsafe { hal::regs::pwm().ch(I::DYN.num as usize) };
+        ch.csr().modify(|_, w| w.divmode().div());
+
+        self.slice.set_counter(0);
+        self.set_top(top);
+    }
+
     /// Only use for functions not covered by this wrapper.
     /// Don't set enable, freq, ph_correct, top directly
     pub fn get_pwm_slice(&mut self) -> &mut pwm::Slice<I, <I as pwm::SliceId>::Reset> {
@@ -298,10 +446,44 @@ impl<C: SetDutyCycle> PwmChannelExt for C {
 //                                         Free Functions
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
+/// Writes the compare level of a pin channel straight to the register, safe from interrupts.
+/// The caller has to own the channel (ex: a background sequence started from a command).
+/// Steps larger than the rate limit of a motor output are ramped, see `output_guard`.
+pub fn write_channel_cc(gpio: u8, cc: u16) {
+    write_cc(gpio, GUARD.limit_duty(gpio, cc));
+}
+
+/// Writes the compare level without the output guard
+pub(crate) fn write_cc(gpio: u8, cc: u16) {
+    // Safety: single field of the channel compare register
+    let ch = unsafe { hal::regs::pwm().ch((gpio as usize / 2) % 8) };
+    match gpio % 2 {
+        0 => ch.cc().modify(|_, w| unsafe { w.a().bits(cc) }),
+        _ => ch.cc().modify(|_, w| unsafe { w.b().bits(cc) }),
+    };
+}
+
+/// Compare level of a pin channel
+pub fn read_cc(gpio: u8) -> u16 {
+    // Safety: read only register
+    let cc = unsafe { hal::regs::pwm().ch((gpio as usize / 2) % 8).cc().read() };
+    match gpio % 2 {
+        0 => cc.a().bits(),
+        _ => cc.b().bits(),
+    }
+}
+
+/// Top of the slice of a pin
+pub fn read_top(gpio: u8) -> u16 {
+    // Safety: read only register
+    let ch = unsafe { hal::regs::pwm().ch((gpio as usize / 2) % 8) };
+    ch.top().read().top().bits()
+}
+
 /// Calculate duty cycle from us and frequency
 pub fn calculate_duty_from_us(duty_us: u16, freq_hz: u32, max_duty: u16) -> u16 {
     const MAX_U16: u32 = u16::MAX as u32;
-    let freq_us = if freq_hz > 0 { 1_000_000 / freq_hz } else { 0 };
+    let freq_us = 1_000_000u32.checked_div(freq_hz).unwrap_or(0);
 
     if freq_us == 0 || duty_us == 0 {
         return 0;
@@ -0,0 +1,264 @@
+//! RC Receiver Input
+//!
+//! Decodes the serial protocols of RC receivers from a UART byte stream:
+//! - SBUS: 100000 baud 8E2 on an inverted line, 25 byte frames, 16 channels of 11 bits and the
+//!   frame lost / failsafe flags
+//! - IBUS: 115200 baud 8N1, 32 byte frames, 14 channels of 16 bits and a checksum
+//!
+//! The decoder resynchronizes on the frame header after a corrupt or partial frame, so bytes
+//! dropped while the caller is busy only cost the frames they belong to.
+//! Channel values are converted to servo pulse widths in microseconds (~1000 - 2000us).
+//!
+//! Example:
+//! ```rust
+//! let mut decoder = Decoder::new(Protocol::Sbus);
+//! for byte in bytes {
+//!     if let Some(frame) = decoder.push(byte) {
+//!         println!("ch1: {}us", frame.channels()[0]);
+//!     }
+//! }
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use super::uart::{LineConfig, Parity};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_CHANNELS: usize = 16;
+const MAX_FRAME_LEN: usize = 32;
+
+const SBUS_HEADER: u8 = 0x0F;
+const SBUS_FRAME_LEN: usize = 25;
+const SBUS_FRAME_LOST: u8 = 1 << 2;
+const SBUS_FAILSAFE: u8 = 1 << 3;
+
+const IBUS_HEADER: [u8; 2] = [0x20, 0x40];
+const IBUS_FRAME_LEN: usize = 32;
+const IBUS_CHANNELS: usize = 14;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Protocol
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    Sbus,
+    Ibus,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Sbus => "sbus",
+            Protocol::Ibus => "ibus",
+        }
+    }
+
+    /// UART line settings of the protocol
+    pub fn line_config(&self) -> LineConfig {
+        match self {
+            Protocol::Sbus => LineConfig {
+                baud:      100_000,
+                data_bits: 8,
+                parity:    Parity::Even,
+                stop_bits: 2,
+            },
+            Protocol::Ibus => LineConfig::default(),
+        }
+    }
+
+    /// True if the receiver drives an inverted line (idle low)
+    pub fn inverted(&self) -> bool {
+        matches!(self, Protocol::Sbus)
+    }
+
+    fn frame_len(&self) -> usize {
+        match self {
+            Protocol::Sbus => SBUS_FRAME_LEN,
+            Protocol::Ibus => IBUS_FRAME_LEN,
+        }
+    }
+
+    fn header(&self) -> &'static [u8] {
+        match self {
+            Protocol::Sbus => &[SBUS_HEADER],
+            Protocol::Ibus => &IBUS_HEADER,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Protocol::Sbus, Protocol::Ibus]
+            .into_iter()
+            .find(|protocol| protocol.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Frame
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Frame {
+    channels:       [u16; MAX_CHANNELS],
+    count:          usize,
+    /// The receiver missed a frame from the transmitter (SBUS only)
+    pub frame_lost: bool,
+    /// The receiver lost the link and sends its failsafe values (SBUS only)
+    pub failsafe:   bool,
+}
+
+impl Frame {
+    /// Channel pulse widths in microseconds
+    pub fn channels(&self) -> &[u16] {
+        &self.channels[..self.count]
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Decoder
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Decoder {
+    protocol: Protocol,
+    buf:      [u8; MAX_FRAME_LEN],
+    len:      usize,
+    /// Frames dropped on a bad end byte or checksum
+    errors:   u32,
+}
+
+impl Decoder {
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Feeds one received byte, returns the frame it completes
+    pub fn push(&mut self, byte: u8) -> Option<Frame> {
+        self.buf[self.len] = byte;
+        self.len += 1;
+        self.sync();
+
+        let frame_len = self.protocol.frame_len();
+        if self.len < frame_len {
+            return None;
+        }
+
+        let frame = match self.protocol {
+            Protocol::Sbus => decode_sbus(&self.buf[..frame_len]),
+            Protocol::Ibus => decode_ibus(&self.buf[..frame_len]),
+        };
+        match frame {
+            Some(_) => self.len = 0,
+            None => {
+                // Looking for a header further in the corrupt frame
+                self.errors = self.errors.wrapping_add(1);
+                self.buf.copy_within(1..self.len, 0);
+                self.len -= 1;
+                self.sync();
+            }
+        }
+        frame
+    }
+
+    /// Drops the leading bytes until the buffer starts with (a part of) the frame header
+    fn sync(&mut self) {
+        let header = self.protocol.header();
+        while self.len > 0 {
+            let checked = self.len.min(header.len());
+            if self.buf[..checked] == header[..checked] {
+                return;
+            }
+            self.buf.copy_within(1..self.len, 0);
+            self.len -= 1;
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Header, 16 channels of 11 bits packed LSB first, flags, end byte
+fn decode_sbus(data: &[u8]) -> Option<Frame> {
+    // 0x00, or the SBUS2 telemetry slot ids (low nibble 0x04)
+    let end = data[SBUS_FRAME_LEN - 1];
+    if end != 0x00 && end & 0x0F != 0x04 {
+        return None;
+    }
+
+    let mut frame = Frame {
+        count: MAX_CHANNELS,
+        ..Default::default()
+    };
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    let mut channel = 0;
+    for &byte in &data[1..23] {
+        bits |= (byte as u32) << bit_count;
+        bit_count += 8;
+        while bit_count >= 11 && channel < MAX_CHANNELS {
+            frame.channels[channel] = sbus_to_us((bits & 0x7FF) as u16);
+            bits >>= 11;
+            bit_count -= 11;
+            channel += 1;
+        }
+    }
+
+    let flags = data[23];
+    frame.frame_lost = flags & SBUS_FRAME_LOST != 0;
+    frame.failsafe = flags & SBUS_FAILSAFE != 0;
+    Some(frame)
+}
+
+/// Header, 14 channels as u16 LE in microseconds, checksum 0xFFFF - sum of the previous bytes
+fn decode_ibus(data: &[u8]) -> Option<Frame> {
+    let sum = data[..IBUS_FRAME_LEN - 2]
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    let checksum = u16::from_le_bytes([data[IBUS_FRAME_LEN - 2], data[IBUS_FRAME_LEN - 1]]);
+    if 0xFFFF - sum != checksum {
+        return None;
+    }
+
+    let mut frame = Frame {
+        count: IBUS_CHANNELS,
+        ..Default::default()
+    };
+    let (words, _) = data[2..2 + IBUS_CHANNELS * 2].as_chunks::<2>();
+    for (channel, bytes) in words.iter().enumerate() {
+        frame.channels[channel] = u16::from_le_bytes(*bytes) & 0x0FFF;
+    }
+    Some(frame)
+}
+
+/// SBUS 172 - 992 - 1811 to 988 - 1500 - 2012us
+fn sbus_to_us(raw: u16) -> u16 {
+    (raw as u32 * 5 / 8 + 880) as u16
+}
@@ -0,0 +1,449 @@
+//! Device Register Maps
+//!
+//! Named I2C/SPI devices and their registers, defined at runtime and stored in flash,
+//! so registers can be accessed by name (ex: LSM6DS3.WHO_AM_I) instead of raw addresses.
+//!
+//! Stored as text, one definition per line:
+//! ```text
+//! dev <device> <bus> <addr>                  ex: dev lsm6ds3 i2c0 0x6a
+//! reg <device>.<register> <addr> <width> <ro|rw> [le]    ex: reg lsm6ds3.who_am_i 0x0f 8 ro
+//! ```
+//! The device address is ignored on SPI buses. SPI registers are read with the MSB of the
+//! register address set. Multi-byte registers are big endian unless flagged with `le`.
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+
+use critical_section::{Mutex, with};
+use heapless::{String, Vec};
+use thiserror::Error;
+
+use super::buses::{self, BusId, Buses};
+use super::flash;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MAX_DEVICES: usize = 8;
+pub const MAX_REGISTERS: usize = 64;
+const NAME_LENGTH: usize = 16;
+
+const MAGIC: &[u8; 4] = b"REG1";
+const SPI_READ_BIT: u8 = 0x80;
+
+pub static REGMAP: RegMap = RegMap {
+    inner: Mutex::new(RefCell::new(Inner {
+        devices:   Vec::new(),
+        registers: Vec::new(),
+    })),
+};
+
+pub type Name = String<NAME_LENGTH>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Definitions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Clone)]
+pub struct DeviceDef {
+    pub name: Name,
+    pub bus:  BusId,
+    pub addr: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegDef {
+    pub device:        Name,
+    pub name:          Name,
+    pub addr:          u8,
+    pub width:         u8, // bits
+    pub access:        Access,
+    pub little_endian: bool,
+}
+
+impl RegDef {
+    fn num_bytes(&self) -> usize {
+        self.width as usize / 8
+    }
+
+    /// Reads the register value from the device
+    pub fn read(&self, dev: &DeviceDef, buses: &mut Buses) -> Result<u32> {
+        let len = self.num_bytes();
+        let mut buf = [0u8; 5];
+
+        if dev.bus.is_i2c() {
+            buses.i2c_write_read(dev.bus, dev.addr, &[self.addr], &mut buf[..len])?;
+        }
+        else {
+            // First byte is the register address, the rest is clocked in
+            buf[0] = self.addr | SPI_READ_BIT;
+            buses.spi_transfer(dev.bus, &mut buf[..len + 1])?;
+            buf.copy_within(1..len + 1, 0);
+        }
+
+        Ok(self.decode(&buf[..len]))
+    }
+
+    /// Writes a value to the register
+    pub fn write(&self, dev: &DeviceDef, buses: &mut Buses, value: u32) -> Result<()> {
+        if self.access == Access::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+        if self.width < 32 && value >> self.width != 0 {
+            return Err(Error::ValueTooLarge(self.width));
+        }
+
+        let len = self.num_bytes();
+        let mut buf = [0u8; 5];
+        buf[0] = if dev.bus.is_i2c() { self.addr } else { self.addr & !SPI_READ_BIT };
+        self.encode(value, &mut buf[1..len + 1]);
+
+        if dev.bus.is_i2c() {
+            buses.i2c_write(dev.bus, dev.addr, &buf[..len + 1])?;
+        }
+        else {
+            buses.spi_transfer(dev.bus, &mut buf[..len + 1])?;
+        }
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> u32 {
+        let fold = |acc: u32, b: &u8| (acc << 8) | *b as u32;
+        if self.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        }
+        else {
+            bytes.iter().fold(0, fold)
+        }
+    }
+
+    fn encode(&self, value: u32, bytes: &mut [u8]) {
+        let len = bytes.len();
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let shift = if self.little_endian { i } else { len - 1 - i };
+            *byte = (value >> (shift * 8)) as u8;
+        }
+    }
+}
+
+impl fmt::Display for DeviceDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dev {} {} 0x{:02x}", self.name, self.bus, self.addr)
+    }
+}
+
+impl fmt::Display for RegDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.access == Access::ReadOnly { "ro" } else { "rw" };
+        write!(
+            f,
+            "reg {}.{} 0x{:02x} {} {}",
+            self.device, self.name, self.addr, self.width, access
+        )?;
+        if self.little_endian {
+            write!(f, " le")?;
+        }
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             RegMap
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Inner {
+    devices:   Vec<DeviceDef, MAX_DEVICES>,
+    registers: Vec<RegDef, MAX_REGISTERS>,
+}
+
+pub struct RegMap {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl RegMap {
+    /// Defines or replaces a device
+    pub fn define_device(&self, name: &str, bus: BusId, addr: u8) -> Result<()> {
+        let name = to_name(name)?;
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let def = DeviceDef { name, bus, addr };
+
+            if let Some(dev) = inner.devices.iter_mut().find(|d| d.name == def.name) {
+                *dev = def;
+                return Ok(());
+            }
+            inner.devices.push(def).map_err(|_| Error::Full)
+        })
+    }
+
+    /// Defines or replaces a register, `path` being DEVICE.REGISTER
+    pub fn define_register(
+        &self,
+        path: &str,
+        addr: u8,
+        width: u8,
+        access: Access,
+        little_endian: bool,
+    ) -> Result<()> {
+        if !matches!(width, 8 | 16 | 24 | 32) {
+            return Err(Error::InvalidWidth);
+        }
+
+        let (device, name) = split_path(path)?;
+        let def = RegDef {
+            device: to_name(device)?,
+            name: to_name(name)?,
+            addr,
+            width,
+            access,
+            little_endian,
+        };
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+
+            if !inner.devices.iter().any(|d| d.name == def.device) {
+                return Err(Error::DeviceNotFound);
+            }
+
+            if let Some(reg) = inner
+                .registers
+                .iter_mut()
+                .find(|r| r.device == def.device && r.name == def.name)
+            {
+                *reg = def;
+                return Ok(());
+            }
+            inner.registers.push(def).map_err(|_| Error::Full)
+        })
+    }
+
+    /// Removes a register (DEVICE.REGISTER) or a device with all of its registers (DEVICE)
+    pub fn remove(&self, path: &str) -> bool {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let count = inner.devices.len() + inner.registers.len();
+
+            match path.split_once('.') {
+                Some((device, name)) => inner.registers.retain(|r| {
+                    !(r.device.eq_ignore_ascii_case(device) && r.name.eq_ignore_ascii_case(name))
+                }),
+                None => {
+                    inner.devices.retain(|d| !d.name.eq_ignore_ascii_case(path));
+                    inner
+                        .registers
+                        .retain(|r| !r.device.eq_ignore_ascii_case(path));
+                }
+            }
+
+            inner.devices.len() + inner.registers.len() != count
+        })
+    }
+
+    /// Removes all definitions from RAM
+    pub fn clear(&self) {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.devices.clear();
+            inner.registers.clear();
+        })
+    }
+
+    /// Returns copies of the device and register definitions for a DEVICE.REGISTER path
+    pub fn lookup(&self, path: &str) -> Result<(DeviceDef, RegDef)> {
+        let (device, name) = split_path(path)?;
+
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+
+            let dev = inner
+                .devices
+                .iter()
+                .find(|d| d.name.eq_ignore_ascii_case(device))
+                .ok_or(Error::DeviceNotFound)?;
+
+            let reg = inner
+                .registers
+                .iter()
+                .find(|r| r.device == dev.name && r.name.eq_ignore_ascii_case(name))
+                .ok_or(Error::RegisterNotFound)?;
+
+            Ok((dev.clone(), reg.clone()))
+        })
+    }
+
+    /// Returns a copy of a device definition
+    pub fn device(&self, name: &str) -> Result<DeviceDef> {
+        with(|cs| {
+            self.inner
+                .borrow_ref(cs)
+                .devices
+                .iter()
+                .find(|d| d.name.eq_ignore_ascii_case(name))
+                .cloned()
+                .ok_or(Error::DeviceNotFound)
+        })
+    }
+
+    /// Returns copies of all devices
+    pub fn devices(&self) -> Vec<DeviceDef, MAX_DEVICES> {
+        with(|cs| self.inner.borrow_ref(cs).devices.clone())
+    }
+
+    /// Returns copies of the registers of a device
+    pub fn registers(&self, device: &str) -> Vec<RegDef, MAX_REGISTERS> {
+        with(|cs| {
+            self.inner
+                .borrow_ref(cs)
+                .registers
+                .iter()
+                .filter(|r| r.device.eq_ignore_ascii_case(device))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Parses and applies a single definition line
+    pub fn apply_line(&self, line: &str) -> Result<()> {
+        let mut words = line.split_ascii_whitespace();
+
+        match words.next() {
+            Some("dev") => {
+                let name = words.next().ok_or(Error::InvalidLine)?;
+                let bus: BusId = words.next().ok_or(Error::InvalidLine)?.parse()?;
+                let addr = words.next().and_then(parse_u8).unwrap_or(0);
+                self.define_device(name, bus, addr)
+            }
+            Some("reg") => {
+                let path = words.next().ok_or(Error::InvalidLine)?;
+                let addr = words.next().and_then(parse_u8).ok_or(Error::InvalidLine)?;
+                let width = words.next().and_then(|w| w.parse().ok()).unwrap_or(8);
+                let mut access = Access::ReadWrite;
+                let mut little_endian = false;
+
+                for flag in words {
+                    match flag {
+                        "ro" => access = Access::ReadOnly,
+                        "rw" => access = Access::ReadWrite,
+                        "le" => little_endian = true,
+                        _ => return Err(Error::InvalidLine),
+                    }
+                }
+                self.define_register(path, addr, width, access, little_endian)
+            }
+            _ => Err(Error::InvalidLine),
+        }
+    }
+
+    /// Loads the definitions from flash, replacing the ones in RAM.
+    /// Returns the number of loaded registers.
+    pub fn load(&self) -> usize {
+        let Some(text) = flash::read_text(flash::REGMAP_SECTOR, MAGIC)
+        else {
+            return 0;
+        };
+
+        self.clear();
+        for line in text.lines() {
+            let _ = self.apply_line(line);
+        }
+
+        with(|cs| self.inner.borrow_ref(cs).registers.len())
+    }
+
+    /// Writes all definitions to flash
+    pub fn save(&self) -> Result<()> {
+        let mut text: String<{ flash::MAX_TEXT_LENGTH }> = String::new();
+
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            for dev in inner.devices.iter() {
+                writeln!(text, "{dev}")?;
+            }
+            for reg in inner.registers.iter() {
+                writeln!(text, "{reg}")?;
+            }
+            Ok::<(), fmt::Error>(())
+        })
+        .map_err(|_| Error::Full)?;
+
+        flash::write_text(flash::REGMAP_SECTOR, MAGIC, &text)?;
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("device not found")]
+    DeviceNotFound,
+
+    #[error("register not found, use DEVICE.REGISTER")]
+    RegisterNotFound,
+
+    #[error("invalid name")]
+    InvalidName,
+
+    #[error("invalid register width, use 8, 16, 24 or 32")]
+    InvalidWidth,
+
+    #[error("invalid definition line")]
+    InvalidLine,
+
+    #[error("register is read only")]
+    ReadOnly,
+
+    #[error("value does not fit in {0} bits")]
+    ValueTooLarge(u8),
+
+    #[error("register map full")]
+    Full,
+
+    #[error(transparent)]
+    Bus(#[from] buses::Error),
+
+    #[error(transparent)]
+    Flash(#[from] flash::Error),
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn to_name(name: &str) -> Result<Name> {
+    if name.is_empty() || name.contains(['.', ' ']) {
+        return Err(Error::InvalidName);
+    }
+
+    let mut lower = Name::new();
+    for c in name.chars() {
+        lower
+            .push(c.to_ascii_lowercase())
+            .map_err(|_| Error::InvalidName)?;
+    }
+    Ok(lower)
+}
+
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    path.split_once('.').ok_or(Error::RegisterNotFound)
+}
+
+/// Parses a hex (0x..) or decimal byte
+fn parse_u8(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
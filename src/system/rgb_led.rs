@@ -0,0 +1,227 @@
+//! RGB Status LED
+//!
+//! Boards with an RGB LED show the LED service mode as a color: green idle, blue running,
+//! red error, yellow waiting for the host. The blink patterns of the modes still apply,
+//! the off steps turn the LED black.
+//!
+//! The LED is enabled from the pin table, with one of:
+//! - `RGB_DATA`: data pin of a WS2812 (NeoPixel), driven by PIO0 state machine 0
+//! - `RGB_R`, `RGB_G`, `RGB_B` in the PWM group: 3-pin LED, one PWM channel per color
+//!
+//! Colors ("rgb.<mode>", rrggbb) and brightness ("rgb.brightness", percent) are stored in the
+//! settings and changed with the `statusled` command.
+//!
+//! Example:
+//! ```rust
+//! let cyan: Color = "00ffff".parse()?;
+//! device.led.set_color(LedMode::Idle, Some(cyan))?;
+//! device.led.set_brightness(50)?;
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::hal;
+//
+use hal::gpio::{DynPinId, FunctionPio0, Pin, PullNone};
+use hal::pac;
+use hal::pio::{Buffers, PIOBuilder, PIOExt, PinDir, SM0, ShiftDirection, Tx};
+use pio::{Assembler, JmpCondition, OutDestination, SideSet};
+
+use super::config::{self, CONFIG};
+use super::pwms::{Channel, Pwms};
+use crate::with_pwm_slice;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const DEFAULT_BRIGHTNESS: u8 = 25; // %
+pub const PWM_ALIASES: [&str; 3] = ["RGB_R", "RGB_G", "RGB_B"];
+
+const WS2812_ALIAS: &str = "RGB_DATA";
+const WS2812_BIT_HZ: u32 = 800_000;
+/// PIO cycles per bit: T1 + T2 + T3 of the program
+const WS2812_CYCLES_PER_BIT: u32 = 10;
+const PWM_FREQUENCY: u32 = 1000; // hz
+
+pub type Ws2812PinType = Pin<DynPinId, FunctionPio0, PullNone>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Color
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const BLUE: Color = Color::new(0, 0, 255);
+    pub const GREEN: Color = Color::new(0, 255, 0);
+    pub const ORANGE: Color = Color::new(255, 64, 0);
+    pub const RED: Color = Color::new(255, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+    pub const YELLOW: Color = Color::new(255, 160, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Scales the channels by a 0-100 brightness percentage
+    pub fn dimmed(&self, brightness: u8) -> Self {
+        let scale = |channel: u8| (channel as u16 * brightness.min(100) as u16 / 100) as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+impl FromStr for Color {
+    type Err = ();
+
+    /// Parses "rrggbb", with or without a leading '#'
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+        if s.len() != 6 {
+            return Err(());
+        }
+
+        let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2).ok_or(())?, 16).map_err(|_| ());
+        Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Driver
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub enum RgbDriver {
+    /// TX FIFO of the PIO state machine shifting out the GRB bits
+    Ws2812(Tx<(pac::PIO0, SM0)>),
+    /// Slice and channel of the red, green and blue pins
+    Pwm([(u8, Channel); 3]),
+}
+
+impl RgbDriver {
+    /// Creates the driver configured in the pin table, None if no RGB pins are defined
+    pub fn from_config(
+        pio0: pac::PIO0,
+        resets: &mut pac::RESETS,
+        pwms: &mut Pwms,
+        sys_clk_hz: u32,
+    ) -> Option<Self> {
+        if let Ok(gpio) = CONFIG.get_gpio(WS2812_ALIAS) {
+            let pin: Ws2812PinType = CONFIG.take_pin(gpio)?;
+            return Some(Self::ws2812(pio0, resets, pin, sys_clk_hz));
+        }
+        Self::pwm(pwms).ok()
+    }
+
+    /// Loads the WS2812 program on PIO0 and starts state machine 0 on the data pin
+    pub fn ws2812(
+        pio0: pac::PIO0,
+        resets: &mut pac::RESETS,
+        pin: Ws2812PinType,
+        sys_clk_hz: u32,
+    ) -> Self {
+        let gpio = pin.id().num;
+        let (mut pio, sm0, ..) = pio0.split(resets);
+
+        // ws2812.pio from the pico-examples, T1 = 2, T2 = 5, T3 = 3 cycles
+        let side_set = SideSet::new(false, 1, false);
+        let mut a = Assembler::new_with_side_set(side_set);
+        let mut wrap_target = a.label();
+        let mut wrap_source = a.label();
+        let mut do_zero = a.label();
+
+        a.bind(&mut wrap_target);
+        a.out_with_delay_and_side_set(OutDestination::X, 1, 2, 0);
+        a.jmp_with_delay_and_side_set(JmpCondition::XIsZero, &mut do_zero, 1, 1);
+        a.jmp_with_delay_and_side_set(JmpCondition::Always, &mut wrap_target, 4, 1);
+        a.bind(&mut do_zero);
+        a.nop_with_delay_and_side_set(4, 0);
+        a.bind(&mut wrap_source);
+        let program = a.assemble_with_wrap(wrap_source, wrap_target);
+
+        let installed = pio.install(&program).unwrap();
+
+        // Divider in 1/256 fixed point
+        let bit_cycles_hz = WS2812_BIT_HZ * WS2812_CYCLES_PER_BIT;
+        let div_x256 = (sys_clk_hz as u64 * 256 / bit_cycles_hz as u64) as u32;
+
+        let (mut sm, _, tx) = PIOBuilder::from_installed_program(installed)
+            .side_set_pin_base(gpio)
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(24)
+            .buffers(Buffers::OnlyTx)
+            .clock_divisor_fixed_point((div_x256 >> 8) as u16, div_x256 as u8)
+            .build(sm0);
+
+        sm.set_pindirs([(gpio, PinDir::Output)]);
+        sm.start();
+        Self::Ws2812(tx)
+    }
+
+    /// Uses the RGB_R, RGB_G and RGB_B pins registered in the PWM group.
+    /// Their slices are dedicated to the LED: frequency and duty are overwritten.
+    pub fn pwm(pwms: &mut Pwms) -> config::Result<Self> {
+        let mut channels = [(0, Channel::A); 3];
+
+        for (channel, alias) in channels.iter_mut().zip(PWM_ALIASES) {
+            let gpio = CONFIG.get_gpio(alias)?;
+            *channel = pwms.get_pwm_slice_id_by_gpio(gpio)?;
+        }
+
+        for (slice_id, _) in channels {
+            with_pwm_slice!(pwms, slice_id, |pwm_slice| {
+                pwm_slice.set_freq(PWM_FREQUENCY);
+                pwm_slice.enable();
+            });
+        }
+
+        let mut driver = Self::Pwm(channels);
+        driver.write(Color::BLACK);
+        Ok(driver)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RgbDriver::Ws2812(_) => "ws2812",
+            RgbDriver::Pwm(_) => "pwm",
+        }
+    }
+
+    /// Shows a color, already scaled by the brightness
+    pub fn write(&mut self, color: Color) {
+        match self {
+            RgbDriver::Ws2812(tx) => {
+                // GRB order, MSB first in the upper 24 bits
+                let grb = (color.g as u32) << 24 | (color.r as u32) << 16 | (color.b as u32) << 8;
+                tx.write(grb);
+            }
+            RgbDriver::Pwm(channels) => {
+                let values = [color.r, color.g, color.b];
+                for (&(slice_id, channel), value) in channels.iter().zip(values) {
+                    // Safety: the slices of the RGB pins are dedicated to the LED
+                    let ch = unsafe { hal::regs::pwm().ch(slice_id as usize) };
+                    let top = ch.top().read().top().bits() as u32;
+                    let duty = (value as u32 * (top + 1) / 255).min(u16::MAX as u32) as u16;
+
+                    ch.cc().modify(|_, w| match channel {
+                        Channel::A => unsafe { w.a().bits(duty) },
+                        Channel::B => unsafe { w.b().bits(duty) },
+                    });
+                }
+            }
+        }
+    }
+}
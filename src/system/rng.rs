@@ -0,0 +1,104 @@
+//! Random Numbers
+//!
+//! Entropy from the ring oscillator: RANDOMBIT samples the free running ROSC, whose jitter makes
+//! the bit unpredictable but biased and correlated. Samples are spaced out, debiased in pairs
+//! (von Neumann: 01 -> 0, 10 -> 1, 00 and 11 dropped), then each 32 bit word is mixed with a
+//! running state through the murmur3 finalizer.
+//!
+//! Tens of kilobytes per second: fine for nonces, retry jitter and test patterns.
+//! The ROSC has to keep running, it is not stopped by the clock setup.
+//!
+//! Example:
+//! ```rust
+//! let nonce = device.rng.next_u32();
+//! let mut pattern = [0u8; 16];
+//! device.rng.fill_bytes(&mut pattern);
+//! let jitter_ms = device.rng.below(50);
+//! ```
+
+use crate::hal::pac;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// System clock cycles between two samples, a few ROSC periods
+const SAMPLE_SPACING_CYCLES: u32 = 32;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Rng
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Rng {
+    rosc:  pac::ROSC,
+    /// Mixed into every word, carries the entropy of the previous ones
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(rosc: pac::ROSC) -> Self {
+        let mut rng = Self { rosc, state: 0 };
+        rng.state = rng.raw_word();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let word = self.raw_word();
+        self.state = fmix32(self.state.rotate_left(5) ^ word);
+        self.state
+    }
+
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    /// Uniform value in 0..n, 0 if n is 0
+    pub fn below(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        // Values of the last incomplete span are drawn again, no modulo bias
+        let limit = u32::MAX - u32::MAX % n;
+        loop {
+            let value = self.next_u32();
+            if value < limit {
+                return value % n;
+            }
+        }
+    }
+
+    /// 32 debiased bits
+    fn raw_word(&self) -> u32 {
+        let mut word = 0;
+        let mut bits = 0;
+        while bits < 32 {
+            let (a, b) = (self.sample(), self.sample());
+            if a != b {
+                word = (word << 1) | a as u32;
+                bits += 1;
+            }
+        }
+        word
+    }
+
+    fn sample(&self) -> bool {
+        cortex_m::asm::delay(SAMPLE_SPACING_CYCLES);
+        self.rosc.randombit().read().randombit().bit()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Murmur3 finalizer, every input bit flips about half of the output bits
+fn fmix32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^ (x >> 16)
+}
@@ -0,0 +1,117 @@
+//! Safe Mode
+//!
+//! The name of the command being executed is kept in the watchdog scratch registers, which
+//! survive the panic and watchdog resets. Finding it at boot means the previous run ended while
+//! that command was running, the device then starts in safe mode: the user is warned and the
+//! startup automation (autorun scripts, rules) has to be skipped, preventing panic loops.
+//!
+//! Scratch0 holds a marker while a command runs, Scratch1-3 its name (12 chars).
+//! Scratch4-7 are left to the bootrom.
+//!
+//! Example:
+//! ```rust
+//! safe_mode::check(&mut watchdog); // at boot
+//!
+//! safe_mode::command_started(&mut device.watchdog, "i2c");
+//! // ... command execution
+//! safe_mode::command_finished(&mut device.watchdog);
+//!
+//! if SAFE_MODE.is_active() {
+//!     return; // skipping autorun
+//! }
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::String;
+
+use crate::hal::watchdog::{ScratchRegister, Watchdog};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const NAME_LENGTH: usize = 12;
+
+/// "CMDR", written while a command runs
+const RUNNING_MARKER: u32 = 0x434D_4452;
+const NAME_REGISTERS: [ScratchRegister; 3] = [
+    ScratchRegister::Scratch1,
+    ScratchRegister::Scratch2,
+    ScratchRegister::Scratch3,
+];
+
+pub static SAFE_MODE: SafeMode = SafeMode {
+    crashed_cmd: Mutex::new(RefCell::new(None)),
+};
+
+pub type Name = String<NAME_LENGTH>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Safe Mode
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct SafeMode {
+    /// Command that was running when the previous run ended
+    crashed_cmd: Mutex<RefCell<Option<Name>>>,
+}
+
+impl SafeMode {
+    pub fn is_active(&self) -> bool {
+        with(|cs| self.crashed_cmd.borrow_ref(cs).is_some())
+    }
+
+    /// Command that was running when the previous run ended, None if it ended cleanly
+    pub fn crashed_cmd(&self) -> Option<Name> {
+        with(|cs| self.crashed_cmd.borrow_ref(cs).clone())
+    }
+
+    /// Leaves safe mode until the next crash
+    pub fn clear(&self) {
+        with(|cs| self.crashed_cmd.borrow_ref_mut(cs).take());
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Enters safe mode if the previous run ended during a command. Called once at boot.
+pub fn check(watchdog: &mut Watchdog) {
+    if watchdog.read_scratch(ScratchRegister::Scratch0) != RUNNING_MARKER {
+        return;
+    }
+
+    let mut bytes = [0u8; NAME_LENGTH];
+    for (chunk, reg) in bytes.chunks_mut(4).zip(NAME_REGISTERS) {
+        chunk.copy_from_slice(&watchdog.read_scratch(reg).to_le_bytes());
+    }
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(NAME_LENGTH);
+    let name = core::str::from_utf8(&bytes[..len]).unwrap_or("?");
+
+    with(|cs| {
+        SAFE_MODE
+            .crashed_cmd
+            .borrow_ref_mut(cs)
+            .replace(Name::try_from(name).unwrap_or_default())
+    });
+    command_finished(watchdog);
+}
+
+/// Records the command about to run, names longer than NAME_LENGTH are truncated
+pub fn command_started(watchdog: &mut Watchdog, name: &str) {
+    let mut bytes = [0u8; NAME_LENGTH];
+    let len = name.len().min(NAME_LENGTH);
+    bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+    for (chunk, reg) in bytes.chunks(4).zip(NAME_REGISTERS) {
+        watchdog.write_scratch(reg, u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+    watchdog.write_scratch(ScratchRegister::Scratch0, RUNNING_MARKER);
+}
+
+/// Clears the running command, also needed before an intended reset
+pub fn command_finished(watchdog: &mut Watchdog) {
+    watchdog.write_scratch(ScratchRegister::Scratch0, 0);
+}
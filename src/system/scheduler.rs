@@ -0,0 +1,294 @@
+//! Microsecond Alarm Scheduler
+//!
+//! Schedules callbacks with microsecond resolution on ALARM2 (TIMER_IRQ_2), independent of
+//! the 100ms system tick. Used by time critical features such as software PWM and pulse trains.
+//!
+//! Entries are kept in a queue ordered by their 64-bit timer deadline, so scheduling is
+//! overflow safe. Deadlines further away than the 32-bit alarm range are reached in hops.
+//!
+//! Callbacks run in interrupt context and return `Some(period_us)` to be rescheduled relative
+//! to their previous deadline (drift free), or `None` to be removed.
+//!
+//! Synchronized starts: `latch_start` hands out a shared start tick, the features started on it
+//! (capture, playback, pulse trains) take their first step on the same timer tick, repeatable
+//! from one run to the next.
+//!
+//! Example:
+//! ```rust
+//! fn toggle(ctx: u32) -> Option<u32> {
+//!     // ...
+//!     Some(250) // run again in 250us
+//! }
+//!
+//! let id = SCHEDULER.schedule_in(1_000, toggle, 0).unwrap();
+//! SCHEDULER.cancel(id);
+//!
+//! let at = SCHEDULER.latch_start(Some(500_000))?; // the next calls join the same tick
+//! SCHEDULER.schedule_at(at, toggle, 0)?;
+//! ```
+
+use core::cell::RefCell;
+
+use crate::hal;
+//
+use hal::timer::{Alarm, Alarm2, Timer};
+
+/// Timer instant of the deadlines, in µs ticks
+pub use hal::timer::Instant;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MAX_ENTRIES: usize = 16;
+
+pub static SCHEDULER: Scheduler = Scheduler {
+    inner: Mutex::new(RefCell::new(None)),
+};
+
+/// Scheduled callback. Receives the context value given at scheduling time.
+/// Returns the period in us until the next run, or None to stop.
+pub type AlarmFn = fn(ctx: u32) -> Option<u32>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Init
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Initialise the SCHEDULER global object once
+pub fn init(timer: Timer, mut alarm: Alarm2) {
+    alarm.enable_interrupt();
+
+    with(|cs| {
+        let mut cell = SCHEDULER.inner.borrow_ref_mut(cs);
+
+        if cell.is_some() {
+            panic!("SCHEDULER already initialized");
+        }
+
+        cell.replace(Inner {
+            timer,
+            alarm,
+            queue: Vec::new(),
+            next_id: 0,
+            start_latch: None,
+        });
+    });
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Scheduler
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Handle returned on scheduling, used for cancellation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EntryId(u16);
+
+#[derive(Copy, Clone)]
+struct Entry {
+    id:       EntryId,
+    deadline: u64,
+    func:     AlarmFn,
+    ctx:      u32,
+}
+
+struct Inner {
+    timer:       Timer,
+    alarm:       Alarm2,
+    queue:       Vec<Entry, MAX_ENTRIES>,
+    next_id:     u16,
+    /// Shared start tick, see `latch_start`
+    start_latch: Option<u64>,
+}
+
+pub struct Scheduler {
+    inner: Mutex<RefCell<Option<Inner>>>,
+}
+
+impl Scheduler {
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Inner) -> R,
+    {
+        with(|cs| {
+            if let Some(inner) = self.inner.borrow_ref_mut(cs).as_mut() {
+                f(inner)
+            }
+            else {
+                panic!("SCHEDULER not initialized");
+            }
+        })
+    }
+
+    /// Current timer instant
+    pub fn now(&self) -> Instant {
+        self.with(|inner| inner.timer.get_counter())
+    }
+
+    /// Schedules a callback to run in `delay_us` microseconds
+    pub fn schedule_in(&self, delay_us: u32, func: AlarmFn, ctx: u32) -> Result<EntryId> {
+        self.with(|inner| {
+            let deadline = deadline_after(inner.timer.get_counter(), delay_us);
+            inner.insert(deadline, func, ctx)
+        })
+    }
+
+    /// Schedules a callback to run at an absolute timer instant.
+    /// Instants in the past run as soon as possible.
+    pub fn schedule_at(&self, at: Instant, func: AlarmFn, ctx: u32) -> Result<EntryId> {
+        self.with(|inner| inner.insert(at.ticks(), func, ctx))
+    }
+
+    /// Start tick shared by synchronized starts. The first call latches the tick `delay_us` from
+    /// now, the next ones get the same tick until it passes, their delay ignored.
+    /// Error if no tick is pending and no delay is given.
+    pub fn latch_start(&self, delay_us: Option<u32>) -> Result<Instant> {
+        self.with(|inner| {
+            let now = inner.timer.get_counter().ticks();
+            let tick = match (inner.start_latch, delay_us) {
+                (Some(tick), _) if tick > now => tick,
+                (_, Some(delay_us)) => now.saturating_add(delay_us as u64),
+                _ => return Err(Error::NoStartLatch),
+            };
+            inner.start_latch = Some(tick);
+            Ok(Instant::from_ticks(tick))
+        })
+    }
+
+    /// Removes a scheduled entry. Returns false if it already ran out or was not found.
+    pub fn cancel(&self, id: EntryId) -> bool {
+        self.with(|inner| {
+            if let Some(pos) = inner.queue.iter().position(|e| e.id == id) {
+                inner.queue.remove(pos);
+                inner.arm();
+                true
+            }
+            else {
+                false
+            }
+        })
+    }
+
+    /// Removes all entries using the given callback
+    pub fn cancel_fn(&self, func: AlarmFn) {
+        self.with(|inner| {
+            inner.queue.retain(|e| !core::ptr::fn_addr_eq(e.func, func));
+            inner.arm();
+        })
+    }
+
+    /// Returns true if the entry is still scheduled
+    pub fn is_scheduled(&self, id: EntryId) -> bool {
+        self.with(|inner| inner.queue.iter().any(|e| e.id == id))
+    }
+
+    /// Number of pending entries
+    pub fn pending(&self) -> usize {
+        self.with(|inner| inner.queue.len())
+    }
+
+    /// Runs all due entries and re-arms the alarm.
+    /// This should be only called by the TIMER_IRQ_2 interrupt
+    pub fn on_interrupt(&self) {
+        self.with(|inner| inner.alarm.clear_interrupt());
+
+        // Callbacks run outside of the borrow, so they are free to schedule new entries
+        while let Some(entry) = self.with(|inner| inner.pop_due()) {
+            if let Some(period_us) = (entry.func)(entry.ctx) {
+                // Drift free: next deadline is relative to the previous one
+                let deadline = entry.deadline.saturating_add(period_us.max(1) as u64);
+                let _ = self.with(|inner| inner.reinsert(entry, deadline));
+            }
+        }
+
+        self.with(|inner| inner.arm());
+    }
+}
+
+impl Inner {
+    fn insert(&mut self, deadline: u64, func: AlarmFn, ctx: u32) -> Result<EntryId> {
+        let id = EntryId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+
+        self.reinsert(Entry { id, deadline, func, ctx }, deadline)?;
+        Ok(id)
+    }
+
+    /// Inserts keeping deadline order. Equal deadlines run in insertion order.
+    fn reinsert(&mut self, mut entry: Entry, deadline: u64) -> Result<EntryId> {
+        entry.deadline = deadline;
+
+        let pos = self
+            .queue
+            .iter()
+            .position(|e| e.deadline > deadline)
+            .unwrap_or(self.queue.len());
+
+        self.queue.insert(pos, entry).map_err(|_| Error::Full)?;
+
+        if pos == 0 {
+            self.arm();
+        }
+        Ok(entry.id)
+    }
+
+    fn pop_due(&mut self) -> Option<Entry> {
+        let now = self.timer.get_counter().ticks();
+        match self.queue.first() {
+            Some(first) if first.deadline <= now => Some(self.queue.remove(0)),
+            _ => None,
+        }
+    }
+
+    /// Arms the alarm for the earliest deadline, hopping if out of the 32-bit alarm range
+    fn arm(&mut self) {
+        let Some(first) = self.queue.first()
+        else {
+            let _ = self.alarm.cancel();
+            return;
+        };
+
+        let now = self.timer.get_counter();
+        let target = first.deadline.min(now.ticks() + u32::MAX as u64 / 2);
+        let _ = self.alarm.schedule_at(Instant::from_ticks(target));
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("scheduler queue full")]
+    Full,
+
+    #[error("no start tick pending, latch one with at=+..")]
+    NoStartLatch,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Deadline in timer ticks `us` microseconds after an instant
+#[inline]
+pub fn deadline_after(instant: Instant, us: u32) -> u64 {
+    instant.ticks().saturating_add(us as u64)
+}
+
+/// Microseconds remaining until a deadline, 0 if already passed
+#[inline]
+pub fn time_until(now: Instant, deadline: u64) -> u64 {
+    deadline.saturating_sub(now.ticks())
+}
+
+/// Elapsed microseconds between two low 32-bit counter reads, valid across a wrap
+#[inline]
+pub fn elapsed_low(from: u32, to: u32) -> u32 {
+    to.wrapping_sub(from)
+}
@@ -0,0 +1,388 @@
+//! Secure Command Channel
+//!
+//! Optional authenticated encryption of the command lines with a pre-shared key. With the mode on,
+//! a line is only accepted as a frame sealed with the key and its output is sent back sealed the
+//! same way. Plain lines are rejected, `secure off` has to arrive encrypted as well.
+//!
+//! Frame: '$' + base64([nonce: 12 bytes][ciphertext][tag: 16 bytes]), ChaCha20-Poly1305 with
+//! no associated data (see utils/aead.rs)
+//! Nonce: [direction: 'H' host / 'D' device][salt: 7 bytes][counter: u32 LE]
+//!
+//! Host frames have a zero salt and a counter above the last accepted one, starting at 1. Replays
+//! are refused across resets: the key-value store ("sec.rx", read-only from the kv command and
+//! kept by `kv clear`) holds a mark `RX_RESERVE` counters ahead of the accepted ones, moved when a
+//! counter passes it, so the flash is written once every `RX_RESERVE` frames. After a reset the
+//! counters up to the mark are spent, the host carries on above it (the replay error gives it).
+//! Device frames have a salt drawn from the ROSC at boot and count from 0, so their nonces don't
+//! repeat under the key either.
+//!
+//! The key and the mode have their own flash sector, out of the settings listing. Setting a key
+//! resets the host counter. The same key answers the login challenge of `system::auth`, its flag
+//! is kept in the record too. A lost key takes erasing the storage region (picotool erase).
+//!
+//! Neither protocol uses the pre-shared key directly, each has its own subkey so one can't weaken
+//! the other: HMAC-SHA256(psk, "aead") seals the frames, HMAC-SHA256(psk, "auth") answers the
+//! login challenge. The host derives them the same way:
+//! `aead_key = hmac.new(psk, b"aead", hashlib.sha256).digest()`
+//! Background jobs and log lines from interrupts are still printed in the clear.
+//!
+//! Example:
+//! ```rust
+//! SECURE.load(&mut rng); // at boot
+//! let len = SECURE.open_frame(line, &mut buf)?;
+//! SECURE.seal_frame(output, |part| print!("{part}"))?;
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::flash::{self, SECURE_SECTOR};
+use super::kv::{self, KV};
+use super::rng::Rng;
+use super::serial_io::CAPTURE_SIZE;
+use crate::limits::LINE_BUFFER_LENGTH;
+use crate::utils::aead::{self, KEY_SIZE, Key, NONCE_SIZE, Nonce, TAG_SIZE};
+use crate::utils::encoding::{self, Encoding, LineEncoder};
+use crate::utils::sha256::{self, Digest};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const FRAME_PREFIX: char = '$';
+/// Longest decrypted command of a frame fitting the line buffer
+pub const MAX_COMMAND_LENGTH: usize = MAX_FRAME_SIZE - NONCE_SIZE - TAG_SIZE;
+
+/// Decoded size of the longest frame line
+const MAX_FRAME_SIZE: usize = (LINE_BUFFER_LENGTH - 1) / 4 * 3;
+const SALT_SIZE: usize = 7;
+const HOST: u8 = b'H';
+const DEVICE: u8 = b'D';
+
+const MAGIC: &[u8; 4] = b"PSK1";
+/// Host counters reserved in flash, the highest one that may have been accepted
+const RX_KEY: &str = "sec.rx";
+/// Counters reserved by a write of `RX_KEY`
+const RX_RESERVE: u32 = 64;
+/// Subkey labels, see `Keys`
+const AEAD_LABEL: &[u8] = b"aead";
+const AUTH_LABEL: &[u8] = b"auth";
+
+pub static SECURE: Secure = Secure {
+    inner: Mutex::new(RefCell::new(Inner {
+        keys:       None,
+        enabled:    false,
+        auth:       false,
+        salt:       [0; SALT_SIZE],
+        tx_counter: 0,
+        rx_counter: 0,
+        rx_mark:    0,
+    })),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Secure
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Channel state, shown by the secure command
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub enabled:    bool,
+    pub has_key:    bool,
+    /// Login challenge required on connect
+    pub auth:       bool,
+    /// Frames sent since boot
+    pub tx_counter: u32,
+    /// Last accepted host counter
+    pub rx_counter: u32,
+    /// Host counters reserved in flash, the resume point after a reset
+    pub rx_mark:    u32,
+}
+
+/// Pre-shared key and its subkeys
+#[derive(Copy, Clone)]
+struct Keys {
+    /// Stored in the record, never used directly
+    psk:  Key,
+    aead: Key,
+    auth: Key,
+}
+
+impl Keys {
+    fn derive(psk: Key) -> Self {
+        Self {
+            psk,
+            aead: sha256::hmac(&psk, AEAD_LABEL),
+            auth: sha256::hmac(&psk, AUTH_LABEL),
+        }
+    }
+}
+
+struct Inner {
+    keys:       Option<Keys>,
+    enabled:    bool,
+    auth:       bool,
+    salt:       [u8; SALT_SIZE],
+    tx_counter: u32,
+    rx_counter: u32,
+    /// Stored in `RX_KEY`, counters up to it can be accepted without a flash write
+    rx_mark:    u32,
+}
+
+pub struct Secure {
+    inner: Mutex<RefCell<Inner>>,
+}
+
+impl Secure {
+    /// Reads the key and the mode from flash, draws the salt of the device nonces
+    pub fn load(&self, rng: &mut Rng) {
+        let mut salt = [0; SALT_SIZE];
+        rng.fill_bytes(&mut salt);
+
+        let record = flash::read_record(SECURE_SECTOR, MAGIC).filter(|data| data.len() > KEY_SIZE);
+        // Counters below the mark may have been accepted before the reset
+        let rx_mark = KV.get(RX_KEY).unwrap_or(0);
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.salt = salt;
+            inner.rx_counter = rx_mark;
+            inner.rx_mark = rx_mark;
+            if let Some(data) = record {
+                let mut psk = [0; KEY_SIZE];
+                psk.copy_from_slice(&data[..KEY_SIZE]);
+                inner.keys = Some(Keys::derive(psk));
+                inner.enabled = data[KEY_SIZE] != 0;
+                // Records written before the login challenge have no flag
+                inner.auth = data.get(KEY_SIZE + 1).is_some_and(|&auth| auth != 0);
+            }
+        });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).enabled)
+    }
+
+    /// True if a host has to answer the login challenge, never without a key
+    pub fn is_auth_required(&self) -> bool {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            inner.auth && inner.keys.is_some()
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            Status {
+                enabled:    inner.enabled,
+                has_key:    inner.keys.is_some(),
+                auth:       inner.auth,
+                tx_counter: inner.tx_counter,
+                rx_counter: inner.rx_counter,
+                rx_mark:    inner.rx_mark,
+            }
+        })
+    }
+
+    /// Stores a new key, the host counts from 1 again
+    pub fn set_key(&self, key: Key) -> Result<()> {
+        let (enabled, auth) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            (inner.enabled, inner.auth)
+        });
+        write_record(&key, enabled, auth)?;
+        KV.set(RX_KEY, 0)?;
+
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.keys = Some(Keys::derive(key));
+            inner.rx_counter = 0;
+            inner.rx_mark = 0;
+        });
+        Ok(())
+    }
+
+    /// Switches the mode, kept across resets
+    pub fn set_enabled(&self, enabled: bool) -> Result<()> {
+        let (keys, auth) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            (inner.keys, inner.auth)
+        });
+        let keys = keys.ok_or(Error::NoKey)?;
+        write_record(&keys.psk, enabled, auth)?;
+        with(|cs| self.inner.borrow_ref_mut(cs).enabled = enabled);
+        Ok(())
+    }
+
+    /// Switches the login challenge on connect, kept across resets
+    pub fn set_auth(&self, auth: bool) -> Result<()> {
+        let (keys, enabled) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            (inner.keys, inner.enabled)
+        });
+        let keys = keys.ok_or(Error::NoKey)?;
+        write_record(&keys.psk, enabled, auth)?;
+        with(|cs| self.inner.borrow_ref_mut(cs).auth = auth);
+        Ok(())
+    }
+
+    /// HMAC-SHA256 of the data with the login subkey, the keys themselves stay here
+    pub fn hmac(&self, data: &[u8]) -> Result<Digest> {
+        let keys = with(|cs| self.inner.borrow_ref(cs).keys).ok_or(Error::NoKey)?;
+        Ok(sha256::hmac(&keys.auth, data))
+    }
+
+    /// Checks and decrypts a host frame line into `out`, returns the command length
+    pub fn open_frame(&self, line: &str, out: &mut [u8]) -> Result<usize> {
+        let text = line.trim().strip_prefix(FRAME_PREFIX).ok_or(Error::Plain)?;
+        let mut raw = [0u8; MAX_FRAME_SIZE];
+        let len = encoding::decode_base64(text, &mut raw).ok_or(Error::BadFrame)?;
+        if len < NONCE_SIZE + TAG_SIZE {
+            return Err(Error::BadFrame);
+        }
+
+        let (nonce, rest) = raw[..len].split_at_mut(NONCE_SIZE);
+        let (ciphertext, tag) = rest.split_at_mut(len - NONCE_SIZE - TAG_SIZE);
+        if nonce[0] != HOST || nonce[1..1 + SALT_SIZE].iter().any(|&byte| byte != 0) {
+            return Err(Error::BadFrame);
+        }
+        let counter = u32::from_le_bytes([nonce[8], nonce[9], nonce[10], nonce[11]]);
+
+        let (keys, last, mark) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            (inner.keys, inner.rx_counter, inner.rx_mark)
+        });
+        let key = keys.ok_or(Error::NoKey)?.aead;
+        if counter <= last {
+            return Err(Error::Replay(counter, last));
+        }
+
+        let nonce: &Nonce = (&*nonce).try_into().map_err(|_| Error::BadFrame)?;
+        let tag = (&*tag).try_into().map_err(|_| Error::BadFrame)?;
+        aead::open(&key, nonce, &[], ciphertext, tag).map_err(|_| Error::Auth)?;
+
+        // Accepted, the counter can't be used again. Past the mark, a new one is stored first.
+        let mark = match counter > mark {
+            true => {
+                let mark = counter.saturating_add(RX_RESERVE);
+                KV.set(RX_KEY, mark)?;
+                mark
+            }
+            false => mark,
+        };
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.rx_counter = counter;
+            inner.rx_mark = mark;
+        });
+
+        let out = out.get_mut(..ciphertext.len()).ok_or(Error::BadFrame)?;
+        out.copy_from_slice(ciphertext);
+        Ok(ciphertext.len())
+    }
+
+    /// Encrypts the data into a device frame, `emit` is called with the parts of the line
+    pub fn seal_frame(&self, data: &[u8], mut emit: impl FnMut(&str)) -> Result<()> {
+        let (key, salt, counter) = with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let key = inner.keys.ok_or(Error::NoKey)?.aead;
+            let counter = inner.tx_counter;
+            inner.tx_counter = counter.checked_add(1).ok_or(Error::Exhausted)?;
+            Ok::<_, Error>((key, inner.salt, counter))
+        })?;
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[0] = DEVICE;
+        nonce[1..1 + SALT_SIZE].copy_from_slice(&salt);
+        nonce[8..].copy_from_slice(&counter.to_le_bytes());
+
+        let mut frame: Vec<u8, { NONCE_SIZE + CAPTURE_SIZE + TAG_SIZE }> = Vec::new();
+        let _ = frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(data).map_err(|_| Error::TooLarge)?;
+        let tag = aead::seal(&key, &nonce, &[], &mut frame[NONCE_SIZE..]);
+        let _ = frame.extend_from_slice(&tag);
+
+        let mut prefix = [0u8; 4];
+        emit(FRAME_PREFIX.encode_utf8(&mut prefix));
+        let mut encoder = LineEncoder::new(Encoding::Base64);
+        encoder.push(&frame, &mut emit);
+        encoder.finish(&mut emit);
+        Ok(())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Key from 64 hex chars
+pub fn parse_key(hex: &str) -> Result<Key> {
+    if hex.len() != KEY_SIZE * 2 {
+        return Err(Error::InvalidKey);
+    }
+    let mut key = [0u8; KEY_SIZE];
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = core::str::from_utf8(pair).map_err(|_| Error::InvalidKey)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| Error::InvalidKey)?;
+    }
+    Ok(key)
+}
+
+/// Record: [key: 32 bytes][enabled: u8][auth: u8]
+fn write_record(key: &Key, enabled: bool, auth: bool) -> Result<()> {
+    let mut data = [0u8; KEY_SIZE + 2];
+    data[..KEY_SIZE].copy_from_slice(key);
+    data[KEY_SIZE] = enabled as u8;
+    data[KEY_SIZE + 1] = auth as u8;
+    flash::write_record(SECURE_SECTOR, MAGIC, &data)?;
+    Ok(())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("secure mode: only encrypted frames are accepted")]
+    Plain,
+
+    #[error("no pre-shared key, set one with secure key=")]
+    NoKey,
+
+    #[error("invalid key, 64 hex chars")]
+    InvalidKey,
+
+    #[error("malformed frame")]
+    BadFrame,
+
+    #[error("frame authentication failed")]
+    Auth,
+
+    #[error("replayed frame, counter {0} not above {1}")]
+    Replay(u32, u32),
+
+    #[error("output too large for a frame")]
+    TooLarge,
+
+    #[error("frame counter exhausted, reset the device")]
+    Exhausted,
+
+    #[error("locked, answer the #AUTH challenge with auth response=..")]
+    Locked,
+
+    #[error("login denied, new challenge sent")]
+    Denied,
+
+    #[error(transparent)]
+    Flash(#[from] flash::Error),
+
+    #[error(transparent)]
+    Kv(#[from] kv::Error),
+}
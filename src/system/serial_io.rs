@@ -1,6 +1,23 @@
 //! Serial IO and USB Wrapper for the RP2040 microcontroller
 //!
 //! Holds a SERIAL global object for safe usb and serial interaction
+//!
+//! While a capture is active, the print macros write into a RAM buffer instead of the serial.
+//!
+//! Writes from the main loop are split in short critical sections, letting the interrupts run
+//! while a long output is sent. Interrupt handlers print with the non-blocking `try_print!` and
+//! `try_println!`: the text is queued in TX_QUEUE, never waiting on the USB, and sent by the
+//! next write or USB interrupt. Text that doesn't fit in the queue is dropped.
+//!
+//! In bridge mode the USB interrupt keeps the received bytes in a FIFO for the UART bridge
+//! instead of scanning them for the interrupt character. Ctrl+] ends the bridge.
+//! Line input mode (async program) keeps them in the same FIFO, read back by `take_line`. There
+//! the interrupt character works as in blocking mode, and a line filling the FIFO without its
+//! newline is discarded up to the newline and reported as `BufferOverflow`.
+//!
+//! The connection follows the DTR line with a grace period: terminals that toggle DTR briefly
+//! (port settings changes, some Windows drivers) don't end the session. Set with
+//! `set dtrgrace=<ms>`, 0 follows DTR directly.
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                           Serial IO
@@ -9,23 +26,44 @@
 use core::cell::RefCell;
 use core::fmt;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
 
+use crate::hal;
 use critical_section::{Mutex, with};
 use hal::usb::UsbBus;
-use rp2040_hal as hal;
+use heapless::{Deque, Vec};
 use usb_device::UsbError;
 use usb_device::device::UsbDevice;
 use usbd_serial::SerialPort;
 
+use super::cmd_timeout::CMD_TIMEOUT;
+use super::uart::LineConfig;
+use crate::limits::BRIDGE_FIFO_SIZE;
+use crate::utils::time::{self, Instant};
+
+// Buffer sizes of the limits preset. The text past TRY_PRINT_SIZE of a `try_print!` is dropped.
+pub use crate::limits::{CAPTURE_SIZE, TRY_PRINT_SIZE, TX_QUEUE_SIZE};
+
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                            Globals
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
 // Used with poll_for_break_cmd()
 const INTERRUPT_CHAR: u8 = b'~'; // char "~"
+const BRIDGE_ESCAPE_CHAR: u8 = 0x1D; // Ctrl+]
+/// Time DTR can drop without ending the connection, see `set dtrgrace`
+pub const DTR_GRACE_KEY: &str = "serial.dtr_grace";
+pub const DEFAULT_DTR_GRACE_MS: u32 = 200;
+
+static DTR_GRACE_MS: AtomicU32 = AtomicU32::new(DEFAULT_DTR_GRACE_MS);
 
 pub static SERIAL: SerialHandle = SerialHandle;
 pub static SERIAL_CELL: Mutex<RefCell<Option<Serialio>>> = Mutex::new(RefCell::new(None));
+/// Output queued by the interrupt handlers, separate from SERIAL_CELL to never wait on it
+static TX_QUEUE: Mutex<RefCell<TxQueue>> = Mutex::new(RefCell::new(TxQueue {
+    bytes:    Deque::new(),
+    overruns: 0,
+}));
 
 pub type SerialDev = SerialPort<'static, UsbBus>;
 pub type UsbDev = UsbDevice<'static, UsbBus>;
@@ -75,7 +113,10 @@ impl SerialHandle {
 
     /// Polls the USB device and returns true if data was exchanged.
     pub fn poll_usb(&self) -> bool {
-        self.with(|cell| cell.poll_usb())
+        self.with(|cell| {
+            cell.flush_queue();
+            cell.poll_usb()
+        })
     }
 
     /// Reads a line from the USB serial into the provided buffer.
@@ -83,14 +124,39 @@ impl SerialHandle {
         self.with(|cell| cell.read_line_blocking(buffer))
     }
 
-    /// Writes data to the USB serial.
-    pub fn write(&self, data: &[u8]) -> Result<()> {
-        self.with(|cell| cell.write(data))
+    /// Writes data to the USB serial, blocking until it is all sent.
+    /// Each chunk is sent in its own critical section, interrupts run in between.
+    pub fn write(&self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let written = self.with(|cell| cell.write_some(data))?;
+            data = &data[written..];
+        }
+        Ok(())
     }
 
-    /// Get serial monitor connection flag
+    /// Queues data without waiting on the USB, safe from interrupt handlers.
+    /// Returns `Err(UsbError::BufferOverflow)` if the queue was full, the rest is dropped.
+    pub fn try_write(&self, data: &[u8]) -> Result<()> {
+        with(|cs| {
+            let mut queue = TX_QUEUE.borrow_ref_mut(cs);
+            for (i, &byte) in data.iter().enumerate() {
+                if queue.bytes.push_back(byte).is_err() {
+                    queue.overruns += (data.len() - i) as u32;
+                    return Err(UsbError::BufferOverflow);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Bytes dropped because the TX queue was full
+    pub fn tx_overruns(&self) -> u32 {
+        with(|cs| TX_QUEUE.borrow_ref(cs).overruns)
+    }
+
+    /// Serial monitor connection, DTR debounced by the grace period
     pub fn is_connected(&self) -> bool {
-        self.with(|cell| cell.serial.dtr())
+        self.with(|cell| cell.connected())
     }
 
     /// Polls for interrupt cmd though the serial read buffer
@@ -99,9 +165,10 @@ impl SerialHandle {
         self.with(|cell| cell.poll_for_interrupt())
     }
 
-    /// Checks if an interrupt command was received via the USB serial.
+    /// Checks if an interrupt command was received via the USB serial,
+    /// or if the running command exceeded its timeout.
     pub fn interrupt_cmd_triggered(&self) -> bool {
-        self.with(|cell| cell.interrupt_cmd_triggered)
+        self.with(|cell| cell.interrupt_cmd_triggered) || CMD_TIMEOUT.is_expired()
     }
 
     /// Clear the interrupt comand trigger state
@@ -113,6 +180,151 @@ impl SerialHandle {
     pub fn drain(&self) {
         self.with(|cell| cell.drain());
     }
+
+    /// Sends a terminal query and reads the reply up to the terminator byte, not included
+    /// Returns `Err(UsbError::WouldBlock)` if the terminal didn't answer before the timeout
+    pub fn query(
+        &self,
+        request: &[u8],
+        buffer: &mut [u8],
+        terminator: u8,
+        timeout_us: u64,
+    ) -> Result<usize> {
+        // Single critical section, the USB interrupt would discard the reply
+        self.with(|cell| {
+            cell.write(request)?;
+            cell.read_response(buffer, terminator, timeout_us)
+        })
+    }
+
+    /// Line settings last requested by the host (baud rate, parity, stop bits)
+    pub fn line_coding(&self) -> LineConfig {
+        self.with(|cell| LineConfig::from_cdc(cell.serial.line_coding()))
+    }
+
+    /// Enables or disables bridge mode, the bridge FIFO is cleared
+    pub fn set_bridge(&self, enabled: bool) {
+        self.with(|cell| {
+            cell.bridge = enabled;
+            cell.bridge_rx.clear();
+            cell.bridge_overruns = 0;
+        });
+    }
+
+    /// Enables or disables line input mode, the FIFO and the interrupt cmd trigger are cleared
+    pub fn set_line_input(&self, enabled: bool) {
+        self.with(|cell| {
+            cell.line_input = enabled;
+            cell.bridge_rx.clear();
+            cell.bridge_overruns = 0;
+            cell.line_overflow = None;
+            cell.discard_line = false;
+            cell.interrupt_cmd_triggered = false;
+        });
+    }
+
+    /// Pops a complete line from the FIFO in line input mode, returning its length without the
+    /// newline. A line longer than the buffer or the FIFO is discarded and returns
+    /// `Err(UsbError::BufferOverflow)`.
+    pub fn take_line(&self, buf: &mut [u8]) -> Option<Result<usize>> {
+        self.with(|cell| {
+            if cell.line_overflow == Some(0) {
+                cell.line_overflow = None;
+                return Some(Err(UsbError::BufferOverflow));
+            }
+            cell.bridge_rx.iter().position(|&byte| byte == b'\n')?;
+            if let Some(lines) = cell.line_overflow.as_mut() {
+                *lines -= 1;
+            }
+
+            let mut count = 0;
+            while let Some(byte) = cell.bridge_rx.pop_front() {
+                if byte == b'\n' {
+                    break;
+                }
+                if count < buf.len() {
+                    buf[count] = byte;
+                }
+                count += 1;
+            }
+            match count > buf.len() {
+                true => Some(Err(UsbError::BufferOverflow)),
+                false => Some(Ok(count)),
+            }
+        })
+    }
+
+    /// Pops received bytes from the bridge FIFO, returns the number of bytes read
+    pub fn read_bridge(&self, buf: &mut [u8]) -> usize {
+        self.with(|cell| {
+            let mut count = 0;
+            while count < buf.len() {
+                let Some(byte) = cell.bridge_rx.pop_front()
+                else {
+                    break;
+                };
+                buf[count] = byte;
+                count += 1;
+            }
+            count
+        })
+    }
+
+    /// Redirects the print macros into a new capture buffer
+    pub fn start_capture(&self) {
+        self.with(|cell| cell.capture = Some(Capture::default()));
+    }
+
+    /// Stops the redirection and returns the captured output
+    pub fn end_capture(&self) -> Option<Capture> {
+        self.with(|cell| cell.capture.take())
+    }
+
+    /// Puts back a capture returned by `end_capture`, for nested redirections
+    pub fn resume_capture(&self, capture: Capture) {
+        self.with(|cell| cell.capture = Some(capture));
+    }
+
+    /// Sets the time DTR can drop without ending the connection
+    pub fn set_dtr_grace_ms(&self, ms: u32) {
+        DTR_GRACE_MS.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn dtr_grace_ms(&self) -> u32 {
+        DTR_GRACE_MS.load(Ordering::Relaxed)
+    }
+
+    /// Bytes dropped because the bridge FIFO was full
+    pub fn bridge_overruns(&self) -> u32 {
+        self.with(|cell| cell.bridge_overruns)
+    }
+
+    /// Best effort write for the panic handler, never panics or blocks indefinitely.
+    /// Skipped if the serial is not initialized or already borrowed by the panicking code,
+    /// gives up after `max_polls` USB polls without progress.
+    pub fn write_best_effort(&self, data: &[u8], max_polls: u32) -> Result<()> {
+        with(|cs| {
+            let Ok(mut cell) = SERIAL_CELL.borrow(cs).try_borrow_mut()
+            else {
+                return Err(UsbError::WouldBlock);
+            };
+            let cell = cell.as_mut().ok_or(UsbError::InvalidState)?;
+
+            if !cell.serial.dtr() {
+                return Err(UsbError::InvalidEndpoint);
+            }
+            cell.write_bounded(data, max_polls)?;
+
+            // Sending what remains in the serial buffer
+            for _ in 0..max_polls {
+                if cell.serial.flush().is_ok() {
+                    break;
+                }
+                cell.poll_usb();
+            }
+            Ok(())
+        })
+    }
 }
 
 // ————————————————————————————————————————————————————————————————————————————————————————————————
@@ -123,6 +335,19 @@ pub struct Serialio {
     serial:                  SerialDev,
     usb_dev:                 UsbDev,
     interrupt_cmd_triggered: bool,
+    bridge:                  bool,
+    line_input:              bool,
+    bridge_rx:               Deque<u8, BRIDGE_FIFO_SIZE>,
+    bridge_overruns:         u32,
+    /// Line input: complete lines in the FIFO ahead of a line that overflowed it
+    line_overflow:           Option<usize>,
+    /// Line input: dropping the rest of an overflowed line, up to its newline
+    discard_line:            bool,
+    capture:                 Option<Capture>,
+    /// Debounced connection state, see `connected`
+    connected:               bool,
+    /// Timestamp of the DTR drop while in the grace period
+    dtr_low_since_us:        Option<u64>,
 }
 
 impl Serialio {
@@ -131,6 +356,15 @@ impl Serialio {
             serial,
             usb_dev,
             interrupt_cmd_triggered: true,
+            bridge: false,
+            line_input: false,
+            bridge_rx: Deque::new(),
+            bridge_overruns: 0,
+            line_overflow: None,
+            discard_line: false,
+            capture: None,
+            connected: false,
+            dtr_low_since_us: None,
         }
     }
 
@@ -144,6 +378,23 @@ impl Serialio {
         self.usb_dev.poll(&mut [&mut self.serial])
     }
 
+    /// DTR state, a drop only counts once it lasted the grace period
+    fn connected(&mut self) -> bool {
+        if self.serial.dtr() {
+            self.connected = true;
+            self.dtr_low_since_us = None;
+        }
+        else if self.connected {
+            let now = time::now_us();
+            let since = *self.dtr_low_since_us.get_or_insert(now);
+            if now - since >= DTR_GRACE_MS.load(Ordering::Relaxed) as u64 * 1000 {
+                self.connected = false;
+                self.dtr_low_since_us = None;
+            }
+        }
+        self.connected
+    }
+
     /// flush the rx buffer discarding the data
     fn drain(&mut self) {
         let mut discard_buffer = [0u8; 64];
@@ -165,11 +416,19 @@ impl Serialio {
     /// WARNING: This will throw away the read buffer
     fn poll_for_interrupt(&mut self) {
         //
-        if !self.serial.dtr() {
+        if !self.connected() {
             self.interrupt_cmd_triggered = false;
             return;
         }
 
+        // Sending the text queued by the interrupt handlers
+        self.flush_queue();
+
+        if self.bridge || self.line_input {
+            self.poll_bridge();
+            return;
+        }
+
         // if interrupt cmd already triggered, we just drain/read the buffer to avoid an usb interrupt storm
         if self.interrupt_cmd_triggered {
             self.drain();
@@ -198,6 +457,62 @@ impl Serialio {
         }
     }
 
+    /// Moves the read buffer into the bridge FIFO, the escape character triggers the interrupt cmd
+    /// (Ctrl+] in bridge mode, '~' in line input mode, like `poll_for_interrupt`)
+    /// Bytes are dropped when the FIFO is full, the USB buffer has to be emptied either way
+    fn poll_bridge(&mut self) {
+        let mut buffer = [0u8; 64];
+
+        while let Ok(bytes_read) = self.serial.read(&mut buffer) {
+            if bytes_read == 0 {
+                break;
+            }
+
+            for &byte in &buffer[..bytes_read] {
+                if self.bridge {
+                    if byte == BRIDGE_ESCAPE_CHAR {
+                        self.interrupt_cmd_triggered = true;
+                    }
+                    else if self.bridge_rx.push_back(byte).is_err() {
+                        self.bridge_overruns += 1;
+                    }
+                }
+                else if byte == INTERRUPT_CHAR {
+                    // Flush and set flag, the pending input goes as in blocking mode
+                    self.interrupt_cmd_triggered = true;
+                    self.bridge_rx.clear();
+                    self.line_overflow = None;
+                    self.discard_line = false;
+                    self.drain();
+                    return;
+                }
+                else {
+                    self.push_line_byte(byte);
+                }
+            }
+        }
+    }
+
+    /// Line input: stores a byte, a line filling the FIFO without its newline is dropped up to
+    /// the newline and reported by `take_line` after the complete lines ahead of it
+    fn push_line_byte(&mut self, byte: u8) {
+        if self.discard_line {
+            self.discard_line = byte != b'\n';
+            return;
+        }
+        if self.bridge_rx.push_back(byte).is_ok() {
+            return;
+        }
+
+        while self.bridge_rx.back().is_some_and(|&byte| byte != b'\n') {
+            self.bridge_rx.pop_back();
+        }
+        let lines = self.bridge_rx.iter().filter(|&&byte| byte == b'\n').count();
+        self.line_overflow = Some(lines);
+        self.bridge_overruns += 1;
+        self.discard_line = byte != b'\n';
+    }
+
     /// Appends as much as possible into the write buffer
     /// Writes an entire slice of data, blocking until it is all sent.
     /// This function writes directly to the USB serial port in a loop.
@@ -211,7 +526,7 @@ impl Serialio {
                 }
                 Err(UsbError::WouldBlock) => {
                     // If not connected to serial, we exit
-                    if !self.serial.dtr() {
+                    if !self.connected() {
                         return Err(UsbError::InvalidEndpoint);
                     }
                     // Otherwise The serial buffer is full and we must keep polling
@@ -229,6 +544,101 @@ impl Serialio {
         Ok(())
     }
 
+    /// Writes what fits in the serial buffer after the queued text, returns the bytes consumed.
+    /// The capture takes the whole slice.
+    fn write_some(&mut self, data: &[u8]) -> Result<usize> {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.push(data);
+            return Ok(data.len());
+        }
+
+        self.flush_queue();
+        let written = match self.serial.write(data) {
+            Ok(written) => written,
+            // If not connected to serial, we exit
+            Err(UsbError::WouldBlock) if !self.connected() => {
+                return Err(UsbError::InvalidEndpoint);
+            }
+            Err(UsbError::WouldBlock) => 0,
+            Err(e) => return Err(e),
+        };
+
+        // We must poll the USB device to send the serial data
+        self.poll_usb();
+        Ok(written)
+    }
+
+    /// Moves the queued text into the serial buffer without waiting, the rest stays queued
+    fn flush_queue(&mut self) {
+        with(|cs| {
+            let mut queue = TX_QUEUE.borrow_ref_mut(cs);
+
+            while !queue.bytes.is_empty() {
+                let (front, _) = queue.bytes.as_slices();
+                match self.serial.write(front) {
+                    Ok(written) if written > 0 => {
+                        for _ in 0..written {
+                            queue.bytes.pop_front();
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        });
+    }
+
+    /// Writes the data polling the USB device, fails after `max_polls` polls without progress
+    fn write_bounded(&mut self, mut data: &[u8], max_polls: u32) -> Result<()> {
+        let mut polls = 0;
+
+        while !data.is_empty() {
+            match self.serial.write(data) {
+                Ok(written) => {
+                    data = &data[written..];
+                    polls = 0;
+                }
+                Err(UsbError::WouldBlock) if polls < max_polls => polls += 1,
+                Err(e) => return Err(e),
+            }
+            self.poll_usb();
+        }
+
+        Ok(())
+    }
+
+    /// Reads into the buffer until the terminator byte, or until the timeout expires
+    fn read_response(
+        &mut self,
+        buffer: &mut [u8],
+        terminator: u8,
+        timeout_us: u64,
+    ) -> Result<usize> {
+        let start = Instant::now();
+        let mut bytes_read = 0;
+
+        loop {
+            self.poll_usb();
+
+            let mut byte_buffer = [0u8; 1];
+            match self.serial.read(&mut byte_buffer) {
+                Ok(1) if byte_buffer[0] == terminator => return Ok(bytes_read),
+                Ok(1) => {
+                    if bytes_read == buffer.len() {
+                        return Err(UsbError::BufferOverflow);
+                    }
+                    buffer[bytes_read] = byte_buffer[0];
+                    bytes_read += 1;
+                }
+                Ok(_) | Err(UsbError::WouldBlock) => {}
+                Err(e) => return Err(e),
+            }
+
+            if start.elapsed().as_micros() > timeout_us {
+                return Err(UsbError::WouldBlock);
+            }
+        }
+    }
+
     /// Blocking read from serial into the provided buffer until a newline `\n`  is found.
     /// The newline character is not included in the buffer.
     ///
@@ -238,7 +648,7 @@ impl Serialio {
     /// Returns the number of bytes written to the buffer on success.
     pub fn read_line_blocking(&mut self, buffer: &mut [u8]) -> Result<usize> {
         // No serial connection established, exit immediately.
-        if !self.serial.dtr() {
+        if !self.connected() {
             return Err(UsbError::InvalidEndpoint);
         }
 
@@ -257,7 +667,7 @@ impl Serialio {
                     Ok(_) => {}                    // Read 0 bytes, should never happen...
                     Err(UsbError::WouldBlock) => {
                         // No data available, check connection and continue polling.
-                        if !self.serial.dtr() {
+                        if !self.connected() {
                             // No serial connection, we exit.
                             return Err(UsbError::InvalidEndpoint);
                         }
@@ -292,14 +702,72 @@ impl Serialio {
     }
 }
 
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            TX Queue
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct TxQueue {
+    bytes:    Deque<u8, TX_QUEUE_SIZE>,
+    overruns: u32,
+}
+
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Capture
+// ————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Output of the print macros while capturing, the text past CAPTURE_SIZE is dropped
+#[derive(Default)]
+pub struct Capture {
+    pub bytes:     Vec<u8, CAPTURE_SIZE>,
+    pub truncated: bool,
+}
+
+impl Capture {
+    fn push(&mut self, data: &[u8]) {
+        let room = self.bytes.capacity() - self.bytes.len();
+        let len = data.len().min(room);
+        let _ = self.bytes.extend_from_slice(&data[..len]);
+        self.truncated |= len < data.len();
+    }
+
+    /// Captured text, cut at the last complete char if truncated
+    pub fn as_str(&self) -> &str {
+        match core::str::from_utf8(&self.bytes) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&self.bytes[..e.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+}
+
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Traits
 // ————————————————————————————————————————————————————————————————————————————————————————————————
 
 // ——————————————————————————————————————————— Write ——————————————————————————————————————————————
 
+/// Used by the print macros, the critical section is released between chunks.
+/// Skipped if the serial is not initialized.
+impl Write for SerialHandle {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut data = s.as_bytes();
+
+        while !data.is_empty() {
+            let written = with(|cs| match SERIAL_CELL.borrow_ref_mut(cs).as_mut() {
+                Some(cell) => cell.write_some(data),
+                None => Ok(data.len()),
+            });
+            data = &data[written.map_err(|_| fmt::Error)?..];
+        }
+        Ok(())
+    }
+}
+
 impl Write for Serialio {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.push(s.as_bytes());
+            return Ok(());
+        }
         self.write(s.as_bytes()).map_err(|_| fmt::Error)?;
         Ok(())
     }
@@ -316,14 +784,10 @@ impl Write for Serialio {
 
 #[macro_export]
 macro_rules! print {
-    ($($arg:tt)*) => {
-        critical_section::with(|cs| {
-            use core::fmt::Write;
-            if let Some(s) = $crate::system::serial_io::SERIAL_CELL.borrow_ref_mut(cs).as_mut() {
-                let _ = s.write_fmt(format_args!($($arg)*));
-            }
-        })
-    }
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let _ = $crate::system::serial_io::SerialHandle.write_fmt(format_args!($($arg)*));
+    }}
 }
 
 #[macro_export]
@@ -331,12 +795,40 @@ macro_rules! println {
     () => {
         $crate::print!("\r\n")
     };
-    ($($arg:tt)*) => {
-        critical_section::with(|cs| {
-            use core::fmt::Write;
-            if let Some(s) = $crate::system::serial_io::SERIAL_CELL.borrow_ref_mut(cs).as_mut() {
-                let _ = writeln!(s, $($arg)*);
-            }
-        })
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let _ = writeln!($crate::system::serial_io::SerialHandle, $($arg)*);
+    }};
+}
+
+/// Non-blocking print for interrupt handlers. Returns `Err(UsbError::BufferOverflow)` if the text
+/// was cut, longer than `TRY_PRINT_SIZE` or past the free queue space: what fits is still sent.
+#[macro_export]
+macro_rules! try_print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let mut text = heapless::String::<{ $crate::system::serial_io::TRY_PRINT_SIZE }>::new();
+        let cut = write!(text, $($arg)*).is_err();
+        match $crate::system::serial_io::SERIAL.try_write(text.as_bytes()) {
+            Ok(()) if cut => Err(::usb_device::UsbError::BufferOverflow),
+            result => result,
+        }
+    }};
+}
+
+/// Non-blocking println for interrupt handlers, see `try_print!`
+#[macro_export]
+macro_rules! try_println {
+    () => {
+        $crate::try_print!("\r\n")
     };
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let mut text = heapless::String::<{ $crate::system::serial_io::TRY_PRINT_SIZE }>::new();
+        let cut = writeln!(text, $($arg)*).is_err();
+        match $crate::system::serial_io::SERIAL.try_write(text.as_bytes()) {
+            Ok(()) if cut => Err(::usb_device::UsbError::BufferOverflow),
+            result => result,
+        }
+    }};
 }
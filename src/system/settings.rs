@@ -0,0 +1,212 @@
+//! Persistent Settings
+//!
+//! Small key=value store kept in RAM and saved as text to the flash storage sector.
+//! Values are stored as strings and parsed on read.
+//!
+//! Example:
+//! ```rust
+//! SETTINGS.set("hx711.scale", 412.5)?;
+//! SETTINGS.save()?;
+//!
+//! let scale: f32 = SETTINGS.get_parsed("hx711.scale").unwrap_or(1.0);
+//! ```
+
+use core::cell::RefCell;
+use core::fmt::{Display, Write};
+use core::str::FromStr;
+
+use critical_section::{Mutex, with};
+use heapless::{String, Vec};
+use thiserror::Error;
+
+use super::flash;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_SETTINGS: usize = 32;
+const KEY_LENGTH: usize = 24;
+const VALUE_LENGTH: usize = 32;
+
+const MAGIC: &[u8; 4] = b"CFG1";
+
+pub static SETTINGS: Settings = Settings {
+    entries: Mutex::new(RefCell::new(Vec::new())),
+};
+
+pub type Key = String<KEY_LENGTH>;
+pub type Value = String<VALUE_LENGTH>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Settings
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+struct Entry {
+    key:   Key,
+    value: Value,
+}
+
+pub struct Settings {
+    entries: Mutex<RefCell<Vec<Entry, MAX_SETTINGS>>>,
+}
+
+impl Settings {
+    /// Loads the settings from flash, replacing the ones in RAM.
+    /// Returns the number of loaded entries, 0 if the storage is empty or invalid.
+    pub fn load(&self) -> usize {
+        let Some(text) = flash::read_text(flash::SETTINGS_SECTOR, MAGIC)
+        else {
+            return 0;
+        };
+
+        self.clear();
+        for line in text.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                let _ = self.set(key.trim(), value.trim());
+            }
+        }
+
+        self.len()
+    }
+
+    /// Writes all settings to flash
+    pub fn save(&self) -> Result<()> {
+        let mut text: String<{ flash::MAX_TEXT_LENGTH }> = String::new();
+
+        with(|cs| {
+            for entry in self.entries.borrow_ref(cs).iter() {
+                // Fits: MAX_SETTINGS lines of at most KEY_LENGTH + VALUE_LENGTH + 2
+                let _ = writeln!(text, "{}={}", entry.key, entry.value);
+            }
+        });
+
+        flash::write_text(flash::SETTINGS_SECTOR, MAGIC, &text)?;
+        Ok(())
+    }
+
+    /// Sets or replaces a setting value
+    pub fn set(&self, key: &str, value: impl Display) -> Result<()> {
+        if key.is_empty() || key.len() > KEY_LENGTH || key.contains(['=', '\n']) {
+            return Err(Error::InvalidKey);
+        }
+
+        let mut value_str = Value::new();
+        write!(value_str, "{value}").map_err(|_| Error::ValueTooLong)?;
+        if value_str.contains('\n') {
+            return Err(Error::InvalidValue);
+        }
+
+        with(|cs| {
+            let mut entries = self.entries.borrow_ref_mut(cs);
+
+            if let Some(entry) = entries.iter_mut().find(|e| e.key == key) {
+                entry.value = value_str;
+                return Ok(());
+            }
+
+            let entry = Entry {
+                key:   key.try_into().map_err(|_| Error::InvalidKey)?,
+                value: value_str,
+            };
+            entries.push(entry).map_err(|_| Error::Full)
+        })
+    }
+
+    /// Returns a copy of the setting value
+    pub fn get(&self, key: &str) -> Option<Value> {
+        with(|cs| {
+            self.entries
+                .borrow_ref(cs)
+                .iter()
+                .find(|e| e.key == key)
+                .map(|e| e.value.clone())
+        })
+    }
+
+    /// Returns the setting value parsed into T
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Removes a setting. Returns false if it was not found.
+    pub fn remove(&self, key: &str) -> bool {
+        with(|cs| {
+            let mut entries = self.entries.borrow_ref_mut(cs);
+            let len = entries.len();
+            entries.retain(|e| e.key != key);
+            entries.len() != len
+        })
+    }
+
+    /// Removes all settings from RAM
+    pub fn clear(&self) {
+        with(|cs| self.entries.borrow_ref_mut(cs).clear())
+    }
+
+    /// Number of settings
+    pub fn len(&self) -> usize {
+        with(|cs| self.entries.borrow_ref(cs).len())
+    }
+
+    /// Returns true if there are no settings
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` for every setting, in insertion order.
+    /// Entries are copied out one at a time so `f` runs outside of the critical section.
+    pub fn for_each(&self, mut f: impl FnMut(&str, &str)) {
+        for i in 0.. {
+            let Some((key, value)) = with(|cs| {
+                self.entries
+                    .borrow_ref(cs)
+                    .get(i)
+                    .map(|e| (e.key.clone(), e.value.clone()))
+            })
+            else {
+                break;
+            };
+            f(&key, &value);
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Parses and checks a "key=value" line without storing it, ex: to validate an import first
+pub fn parse_entry(line: &str) -> Result<(Key, Value)> {
+    let (key, value) = line.split_once('=').ok_or(Error::InvalidKey)?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() {
+        return Err(Error::InvalidKey);
+    }
+    let key = key.try_into().map_err(|_| Error::InvalidKey)?;
+    let value = value.try_into().map_err(|_| Error::ValueTooLong)?;
+    Ok((key, value))
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid setting key")]
+    InvalidKey,
+
+    #[error("invalid setting value")]
+    InvalidValue,
+
+    #[error("setting value too long")]
+    ValueTooLong,
+
+    #[error("settings full")]
+    Full,
+
+    #[error(transparent)]
+    Flash(#[from] flash::Error),
+}
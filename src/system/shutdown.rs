@@ -0,0 +1,147 @@
+//! Shutdown Hooks
+//!
+//! Ordered callbacks run by the reset, flash and estop commands before the device resets or
+//! stops, so the attached hardware is left in a safe state whatever was running:
+//! - Services: background generators stop (sequences, playback, TPO, mirrors)
+//! - Outputs: outputs driven to their power-on level, low without one
+//! - Pwm: every PWM slice stopped, servos and motors get no more pulses
+//! - Storage: pending data written to flash
+//!
+//! Hooks run stage by stage, then in registration order within a stage. They run from the
+//! command loop, not from interrupts, and must not fail: errors are theirs to report.
+//!
+//! Example:
+//! ```rust
+//! SHUTDOWN.register("choreo", Stage::Services, |_| CHOREO.stop())?;
+//! SHUTDOWN.run(Reason::Reset); // before device_reset()
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use crate::hal::{self, NUM_PWM_SLICES};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MAX_HOOKS: usize = 12;
+
+pub static SHUTDOWN: Shutdown = Shutdown {
+    hooks: Mutex::new(RefCell::new(Vec::new())),
+};
+
+pub type HookFn = fn(Reason);
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Hooks
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Order of the hooks, earlier stages run first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Services,
+    Outputs,
+    Pwm,
+    Storage,
+}
+
+/// Command running the hooks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Reset,
+    Flash,
+    Estop,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hook {
+    pub name:  &'static str,
+    pub stage: Stage,
+    func:      HookFn,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Services => f.write_str("services"),
+            Stage::Outputs => f.write_str("outputs"),
+            Stage::Pwm => f.write_str("pwm"),
+            Stage::Storage => f.write_str("storage"),
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Reset => f.write_str("reset"),
+            Reason::Flash => f.write_str("flash"),
+            Reason::Estop => f.write_str("estop"),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Shutdown
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Shutdown {
+    hooks: Mutex<RefCell<Vec<Hook, MAX_HOOKS>>>,
+}
+
+impl Shutdown {
+    pub fn register(&self, name: &'static str, stage: Stage, func: HookFn) -> Result<()> {
+        with(|cs| {
+            let mut hooks = self.hooks.borrow_ref_mut(cs);
+            // After the hooks of the same stage, the registration order is kept
+            let index = hooks
+                .iter()
+                .position(|hook| hook.stage > stage)
+                .unwrap_or(hooks.len());
+            hooks
+                .insert(index, Hook { name, stage, func })
+                .map_err(|_| Error::Full)
+        })
+    }
+
+    /// Runs every hook in order, outside of the critical section
+    pub fn run(&self, reason: Reason) {
+        for hook in self.hooks().iter() {
+            (hook.func)(reason);
+        }
+    }
+
+    /// Copy of the hooks, in run order
+    pub fn hooks(&self) -> Vec<Hook, MAX_HOOKS> {
+        with(|cs| self.hooks.borrow_ref(cs).clone())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Stops every PWM slice, whoever owns it
+pub fn disable_pwm(_reason: Reason) {
+    // Safety: single bit writes of the slice enable, the slices are not used afterwards
+    let pwm = unsafe { hal::regs::pwm() };
+    for slice in 0..NUM_PWM_SLICES as usize {
+        pwm.ch(slice).csr().modify(|_, w| w.en().clear_bit());
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("shutdown hook list full")]
+    Full,
+}
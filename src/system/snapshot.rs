@@ -0,0 +1,149 @@
+//! Output Snapshot
+//!
+//! Records the state of the output pins and PWM slices so a test command leaves the rig as it
+//! found it. `restoring` takes a snapshot, runs the test and restores it whatever the result,
+//! errors and `~` interrupts included. `snapshot save/restore` keeps one snapshot in RAM for
+//! manual sessions.
+//!
+//! Recorded:
+//! - Output levels of the pins driven by the SIO
+//! - PWM slices: enable, phase correct, frequency, top, dividers and both compare levels
+//!
+//! Only the pins still in the Outputs group are restored, pins reconfigured since are skipped.
+//!
+//! Example:
+//! ```rust
+//! snapshot::restoring(device, |device| {
+//!     device.pwms.pwm4.set_freq(50);
+//!     run_test(device)
+//! })?;
+//! ```
+
+use core::cell::RefCell;
+
+use crate::hal;
+//
+use embedded_hal::pwm::SetDutyCycle;
+
+use critical_section::{Mutex, with};
+
+use super::device::Device;
+use super::gpios::{self, NUM_MCU_PINS};
+use super::pwms::{Pwms, SliceStatus};
+use crate::with_pwm_slice;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const NUM_SLICES: usize = 8;
+
+/// Snapshot kept by `snapshot save`
+static SAVED: Mutex<RefCell<Option<Snapshot>>> = Mutex::new(RefCell::new(None));
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Snapshot
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct SliceState {
+    pub status: SliceStatus,
+    /// Frequency kept by the PwmSlice wrapper
+    pub freq:   u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Snapshot {
+    /// Pins with the output driver enabled
+    pub outputs: u32,
+    /// Output levels, only meaningful for the `outputs` pins
+    pub levels:  u32,
+    pub slices:  [SliceState; NUM_SLICES],
+}
+
+impl Snapshot {
+    /// Reads back the current state from the hardware registers
+    pub fn take(device: &mut Device) -> Self {
+        // Safety: read only register access
+        let sio = unsafe { hal::regs::sio() };
+
+        Self {
+            outputs: sio.gpio_oe().read().bits(),
+            levels:  sio.gpio_out().read().bits(),
+            slices:  core::array::from_fn(|id| slice_state(&mut device.pwms, id as u8)),
+        }
+    }
+
+    /// Applies the snapshot, PWM slices first so the outputs don't glitch through a stale duty
+    pub fn restore(&self, device: &mut Device) {
+        for (id, state) in self.slices.iter().enumerate() {
+            with_pwm_slice!(&mut device.pwms, id, |pwm_slice| {
+                let status = state.status;
+                pwm_slice.disable();
+
+                pwm_slice.ph_correct = status.ph_correct;
+                match status.ph_correct {
+                    true => pwm_slice.slice.set_ph_correct(),
+                    false => pwm_slice.slice.clr_ph_correct(),
+                }
+                pwm_slice.freq = state.freq;
+                pwm_slice.slice.set_top(status.top);
+                pwm_slice.slice.set_div_int(status.div_int);
+                pwm_slice.slice.set_div_frac(status.div_frac);
+                let _ = pwm_slice.get_channel_a().set_duty_cycle(status.cc_a);
+                let _ = pwm_slice.get_channel_b().set_duty_cycle(status.cc_b);
+
+                if status.enabled {
+                    pwm_slice.enable();
+                }
+            });
+        }
+
+        for id in 0..NUM_MCU_PINS as u8 {
+            let mask = 1 << id;
+            if self.outputs & mask != 0 && device.outputs.get(id).is_ok() {
+                gpios::set_level(id, self.levels & mask != 0);
+            }
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Runs `f` and restores the outputs and PWM slices afterwards, whatever it returns
+pub fn restoring<R>(device: &mut Device, f: impl FnOnce(&mut Device) -> R) -> R {
+    let snapshot = Snapshot::take(device);
+    let result = f(device);
+    snapshot.restore(device);
+    result
+}
+
+/// Keeps a snapshot for `restore`, replacing the previous one
+pub fn save(device: &mut Device) {
+    let snapshot = Snapshot::take(device);
+    with(|cs| SAVED.borrow_ref_mut(cs).replace(snapshot));
+}
+
+/// Applies the saved snapshot, false if none was saved. The snapshot is kept.
+pub fn restore(device: &mut Device) -> bool {
+    let Some(snapshot) = with(|cs| *SAVED.borrow_ref(cs))
+    else {
+        return false;
+    };
+    snapshot.restore(device);
+    true
+}
+
+pub fn saved() -> Option<Snapshot> {
+    with(|cs| *SAVED.borrow_ref(cs))
+}
+
+fn slice_state(pwms: &mut Pwms, id: u8) -> SliceState {
+    SliceState {
+        // Ids below 8 can't fail
+        status: pwms.get_slice_status(id).unwrap(),
+        freq:   with_pwm_slice!(pwms, id, |pwm_slice| pwm_slice.freq),
+    }
+}
@@ -0,0 +1,293 @@
+//! Stack Guard
+//!
+//! Catches the failures that otherwise end in silent corruption or a frozen feature:
+//! - Stack overflow: canary words at the lowest addresses of the main stack and of the core1
+//!   stack. A stack growing into them overwrites the canary.
+//! - Hung core1: its main loop bumps a heartbeat, which must move while no event is running.
+//!
+//! Checked every `CHECK_TICKS` from the system tick interrupt. A fault is printed once with
+//! `try_println!`, and with `set guardreset=on` the device resets on the next check, leaving the
+//! message time to be sent.
+//!
+//! The canaries only see overflows that reach them: a frame skipping over the canary words (large
+//! local arrays) can still corrupt memory unnoticed.
+//!
+//! The firmware links with flip-link: the statics sit at the top of the RAM and the main stack
+//! grows down from them to the RAM origin (`_stack_bottom` in memory.x), where its canary is.
+//! Going past the origin faults instead of corrupting the statics.
+//!
+//! Example:
+//! ```rust
+//! stack_guard::init(); // at boot, after the tick is running
+//! stack_guard::arm_core1(stack_bottom); // before spawning core1
+//! stack_guard::heartbeat(); // core1 main loop
+//! ```
+
+use core::fmt;
+use core::ops::Range;
+
+use portable_atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicU32, Ordering};
+
+use super::settings::SETTINGS;
+use super::tick::{TICK, TICK_US};
+use crate::main_core1::CORE1_BUSY;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const RESET_KEY: &str = "guard.reset";
+
+const CANARY: usize = 0x57AC_C0DE;
+const CANARY_WORDS: usize = 4;
+/// 500ms
+const CHECK_TICKS: u32 = 5;
+/// Core1 heartbeat stalled this long outside of an event counts as hung
+const HANG_US: u32 = 2_000_000;
+
+/// Lowest words of the core1 stack, null until core1 is started
+static CORE1_CANARY: AtomicPtr<usize> = AtomicPtr::new(core::ptr::null_mut());
+static HEARTBEAT: AtomicU32 = AtomicU32::new(0);
+static RESET_ON_FAULT: AtomicBool = AtomicBool::new(false);
+/// First fault detected, `Fault as u8`, 0 if none
+static FAULT: AtomicU8 = AtomicU8::new(0);
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Fault
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Fault {
+    MainStackOverflow  = 1,
+    Core1StackOverflow = 2,
+    Core1Hung          = 3,
+}
+
+impl Fault {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Fault::MainStackOverflow),
+            2 => Some(Fault::Core1StackOverflow),
+            3 => Some(Fault::Core1Hung),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::MainStackOverflow => f.write_str("main stack overflow (canary overwritten)"),
+            Fault::Core1StackOverflow => f.write_str("core1 stack overflow (canary overwritten)"),
+            Fault::Core1Hung => f.write_str("core1 hung, its main loop stopped outside an event"),
+        }
+    }
+}
+
+/// Guard state, shown by the mem command
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub main_ok:      bool,
+    /// None while core1 is not started
+    pub core1_ok:     Option<bool>,
+    pub heartbeat:    u32,
+    pub fault:        Option<Fault>,
+    pub reset_on_err: bool,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           RAM Layout
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Main RAM regions as laid out by flip-link, from the bottom:
+/// stack (grows down) | .data .bss (statics, heap arena included) | panic dump
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RamLayout {
+    /// RAM origin, the stack overflows below it
+    pub stack_bottom: usize,
+    /// Initial stack pointer, the start of .data
+    pub stack_top:    usize,
+    /// End of .bss
+    pub statics_end:  usize,
+}
+
+impl RamLayout {
+    /// Layout of the running firmware, from the linker symbols
+    pub fn current() -> Self {
+        unsafe extern "C" {
+            static _stack_bottom: u8;
+            static _stack_start: u8;
+            static __sheap: u8;
+        }
+        // Linker symbols, only their address is used
+        RamLayout {
+            stack_bottom: &raw const _stack_bottom as usize,
+            stack_top:    &raw const _stack_start as usize,
+            statics_end:  &raw const __sheap as usize,
+        }
+    }
+
+    /// Canary words, the lowest of the stack
+    fn canary(&self) -> Range<usize> {
+        self.stack_bottom..self.stack_bottom + CANARY_WORDS * size_of::<usize>()
+    }
+
+    pub fn stack_size(&self) -> usize {
+        self.stack_top - self.stack_bottom
+    }
+
+    pub fn statics_size(&self) -> usize {
+        self.statics_end - self.stack_top
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Writes the main stack canary and starts the periodic check
+pub fn init() {
+    // Safety: the lowest words of the main stack, far from the stack pointer at boot
+    unsafe { write_canary(main_canary()) };
+    RESET_ON_FAULT.store(SETTINGS.get_parsed(RESET_KEY).unwrap_or(false), Ordering::Relaxed);
+    TICK.register(check, CHECK_TICKS).unwrap();
+}
+
+/// Writes the canary at the bottom of the core1 stack, before core1 is spawned
+///
+/// # Safety
+/// `bottom` points to the lowest `CANARY_WORDS` words of a stack not in use yet
+pub unsafe fn arm_core1(bottom: *mut usize) {
+    unsafe { write_canary(bottom) };
+    CORE1_CANARY.store(bottom, Ordering::Release);
+}
+
+/// Called by the core1 main loop on every pass
+pub fn heartbeat() {
+    HEARTBEAT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set_reset_on_fault(enabled: bool) {
+    RESET_ON_FAULT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn status() -> Status {
+    let core1 = CORE1_CANARY.load(Ordering::Acquire);
+    Status {
+        // Safety: canary words written by init and arm_core1
+        main_ok:      unsafe { canary_intact(main_canary()) },
+        core1_ok:     (!core1.is_null()).then(|| unsafe { canary_intact(core1) }),
+        heartbeat:    HEARTBEAT.load(Ordering::Relaxed),
+        fault:        Fault::from_u8(FAULT.load(Ordering::Relaxed)),
+        reset_on_err: RESET_ON_FAULT.load(Ordering::Relaxed),
+    }
+}
+
+/// Tick task, interrupt context
+fn check() {
+    static LAST_HEARTBEAT: AtomicU32 = AtomicU32::new(0);
+    static STALLED_TICKS: AtomicU32 = AtomicU32::new(0);
+
+    // Fault reported on the previous check, the message had time to go out
+    if FAULT.load(Ordering::Relaxed) != 0 {
+        if RESET_ON_FAULT.load(Ordering::Relaxed) {
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        return;
+    }
+
+    let status = status();
+    let heartbeat = status.heartbeat;
+    let stalled = match status.core1_ok.is_some()
+        && !CORE1_BUSY.load(Ordering::Relaxed)
+        && heartbeat == LAST_HEARTBEAT.swap(heartbeat, Ordering::Relaxed)
+    {
+        true => STALLED_TICKS.fetch_add(CHECK_TICKS, Ordering::Relaxed) + CHECK_TICKS,
+        false => {
+            STALLED_TICKS.store(0, Ordering::Relaxed);
+            0
+        }
+    };
+
+    let fault = if !status.main_ok {
+        Fault::MainStackOverflow
+    }
+    else if status.core1_ok == Some(false) {
+        Fault::Core1StackOverflow
+    }
+    else if stalled * TICK_US >= HANG_US {
+        Fault::Core1Hung
+    }
+    else {
+        return;
+    };
+
+    FAULT.store(fault as u8, Ordering::Relaxed);
+    let _ = crate::try_println!("\n\nErr: {fault}");
+    if status.reset_on_err {
+        let _ = crate::try_println!("Resetting (set guardreset=off to keep running)");
+    }
+}
+
+/// Lowest words of the main stack
+fn main_canary() -> *mut usize {
+    RamLayout::current().canary().start as *mut usize
+}
+
+unsafe fn write_canary(bottom: *mut usize) {
+    for i in 0..CANARY_WORDS {
+        unsafe { bottom.add(i).write_volatile(CANARY) };
+    }
+}
+
+unsafe fn canary_intact(bottom: *const usize) -> bool {
+    (0..CANARY_WORDS).all(|i| unsafe { bottom.add(i).read_volatile() } == CANARY)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Tests
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Host build: cargo test --target x86_64-unknown-linux-gnu
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAM_ORIGIN: usize = 0x2000_0000;
+    /// RAM region of memory.x, the panic dump follows it
+    const RAM_LENGTH: usize = 255 * 1024;
+    const PANIC_DUMP: Range<usize> = 0x2003_FC00..0x2004_0000;
+
+    /// Layout flip-link produces for `used` bytes of statics: pushed to the end of the RAM
+    /// region, rounded down to the stack alignment, the stack below them
+    fn flip_link(used: usize) -> RamLayout {
+        let ram_end = RAM_ORIGIN + RAM_LENGTH;
+        let stack_top = (ram_end - used) & !7;
+        RamLayout {
+            stack_bottom: RAM_ORIGIN,
+            stack_top,
+            statics_end: stack_top + used,
+        }
+    }
+
+    #[test]
+    fn canary_at_the_stack_bottom() {
+        let layout = flip_link(40 * 1024 + 3);
+        let canary = layout.canary();
+
+        assert_eq!(canary.start, RAM_ORIGIN);
+        assert!(canary.end <= layout.stack_top);
+        assert!(canary.end <= PANIC_DUMP.start);
+    }
+
+    #[test]
+    fn regions_cover_the_ram() {
+        let layout = flip_link(40 * 1024);
+
+        assert_eq!(layout.stack_top % 8, 0);
+        assert_eq!(layout.statics_size(), 40 * 1024);
+        assert_eq!(layout.stack_size() + layout.statics_size(), RAM_LENGTH);
+        assert_eq!(layout.statics_end, PANIC_DUMP.start);
+    }
+}
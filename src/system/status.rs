@@ -0,0 +1,273 @@
+//! Status Line
+//!
+//! The line printed before each prompt. Its fields come from a template stored in the
+//! "status.line" setting, a space separated list of field names changed with `set status=..`:
+//! - `temp`:        internal temperature sensor
+//! - `a3`:          ADC3, VSYS on boards with the onboard divider
+//! - `adc0`-`adc2`: ADC channel voltage
+//! - `vbus`:        USB power state, skipped on boards without VBUS sense
+//! - `gpioN`:       input pin level
+//! - `time`:        time since boot
+//!
+//! `off` disables the line.
+//!
+//! The prompt that follows is set with `set prompt=".."` and expands the variables:
+//! - `%h`: hostname setting (`set hostname=..`), the board name if not set
+//! - `%t`: uptime as hh:mm:ss
+//! - `%n`: command sequence number
+//! - `%%`: a literal %
+//!
+//! Quiet mode, for scripted hosts, leaves out the greeting, the status line, the prompt, the
+//! echoed line and the RUNNING/DONE banners: a command prints its output then "#OK" or
+//! "#ERR <code>", the code of the DONE footer.
+//! Persisted with `set quiet=on`, or for the current connection with the `hello quiet` handshake.
+//!
+//! Example:
+//! ```rust
+//! SETTINGS.set(STATUS_KEY, "temp adc0 gpio15 time")?;
+//! print_status(device);
+//! // | Temp: 23.4°C | ADC0: 1.65V | GP15: HIGH | T: 0h 1m 12s 303ms 21us |
+//!
+//! SETTINGS.set(PROMPT_KEY, "%h [%n]>")?;
+//! print_prompt(device, 3);
+//! // bench-1 [3]>
+//! ```
+
+use core::str::FromStr;
+
+use portable_atomic::AtomicU8;
+
+use super::gpios::NUM_MCU_PINS;
+use super::settings::{SETTINGS, Value};
+use crate::prelude::*;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const STATUS_KEY: &str = "status.line";
+pub const DEFAULT_TEMPLATE: &str = "temp a3 vbus time";
+pub const DISABLED: &str = "off";
+
+pub const PROMPT_KEY: &str = "prompt";
+pub const HOSTNAME_KEY: &str = "hostname";
+pub const QUIET_KEY: &str = "quiet";
+/// Quiet mode of the current connection, over the setting
+static SESSION_QUIET: AtomicU8 = AtomicU8::new(SESSION_UNSET);
+const SESSION_UNSET: u8 = 0;
+const SESSION_QUIET_ON: u8 = 1;
+const SESSION_QUIET_OFF: u8 = 2;
+
+/// Seconds after a greeting during which a reconnection is not greeted again
+pub const GREET_QUIET_KEY: &str = "greet.quiet_s";
+const DEFAULT_PROMPT: &str = "Enter Command: \n>>>";
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Field
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Field {
+    Temp,
+    A3,
+    Adc(u8),
+    Vbus,
+    Gpio(u8),
+    Time,
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temp" => Ok(Field::Temp),
+            "a3" => Ok(Field::A3),
+            "vbus" => Ok(Field::Vbus),
+            "time" => Ok(Field::Time),
+            _ => {
+                if let Some(channel) = s.strip_prefix("adc") {
+                    return channel
+                        .parse()
+                        .ok()
+                        .filter(|channel| *channel <= 2)
+                        .map(Field::Adc)
+                        .ok_or(());
+                }
+                if let Some(gpio) = s.strip_prefix("gpio") {
+                    return gpio
+                        .parse()
+                        .ok()
+                        .filter(|gpio| *gpio < NUM_MCU_PINS as u8)
+                        .map(Field::Gpio)
+                        .ok_or(());
+                }
+                Err(())
+            }
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Template
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Template stored in the settings, DEFAULT_TEMPLATE if not set
+pub fn template() -> Value {
+    SETTINGS
+        .get(STATUS_KEY)
+        .unwrap_or_else(|| Value::try_from(DEFAULT_TEMPLATE).unwrap_or_default())
+}
+
+/// True if the template is `off` or only holds known field names
+pub fn is_valid(template: &str) -> bool {
+    template == DISABLED
+        || template
+            .split_ascii_whitespace()
+            .all(|field| field.parse::<Field>().is_ok())
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Render
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Prints the status line, nothing when disabled or empty
+pub fn print_status(device: &mut Device) {
+    let template = template();
+    if template == DISABLED {
+        return;
+    }
+
+    let mut printed = false;
+    for field in template
+        .split_ascii_whitespace()
+        .filter_map(|field| field.parse::<Field>().ok())
+    {
+        if print_field(device, field) {
+            printed = true;
+        }
+    }
+
+    if printed {
+        println!("|");
+    }
+}
+
+/// Prints a field as "| Label: value ", returns false if it was skipped
+fn print_field(device: &mut Device, field: Field) -> bool {
+    match field {
+        Field::Temp => {
+            let raw: u16 = device.adcs.read(TEMP_SENSE_CHN).unwrap_or(0);
+            // RP2040 temp sensor calibration
+            let temp = 27.0 - (raw.to_voltage() - 0.706) / 0.001721;
+            print!("| Temp: {} ", Celsius(temp));
+        }
+        Field::A3 => {
+            let raw: u16 = device.adcs.read(3).unwrap_or(0);
+
+            // A3 reads VSYS/3 on boards with the onboard divider
+            let (label, volts) = match BOARD.vsys_sense {
+                true => ("VSYS", raw.to_voltage() * 3.0),
+                false => ("A3", raw.to_voltage()),
+            };
+            print!("| {label}: {} ", Volts(volts));
+        }
+        Field::Adc(channel) => {
+            let raw: u16 = device.adcs.read(channel).unwrap_or(0);
+            print!("| ADC{channel}: {} ", Volts(raw.to_voltage()));
+        }
+        Field::Vbus => {
+            let Some(gpio) = BOARD.vbus_sense
+            else {
+                return false;
+            };
+            let vbus = device.inputs.get(gpio).map(|pin| pin.is_high().unwrap());
+            print!("| VBUS: {} ", if vbus == Ok(true) { "ON" } else { "OFF" });
+        }
+        Field::Gpio(gpio) => match device.inputs.get(gpio) {
+            Ok(pin) => {
+                let level = if pin.is_high().unwrap() { "HIGH" } else { "LOW" };
+                print!("| GP{gpio}: {level} ");
+            }
+            Err(_) => print!("| GP{gpio}: - "),
+        },
+        Field::Time => print!("| T: {} ", device.timer.print_time()),
+    }
+    true
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Prompt
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Hostname stored in the settings, the board name if not set
+pub fn hostname() -> Value {
+    SETTINGS
+        .get(HOSTNAME_KEY)
+        .unwrap_or_else(|| Value::try_from(BOARD.board.name()).unwrap_or_default())
+}
+
+/// True if the name is usable as hostname: letters, digits, '-', '_' and '.'
+pub fn is_valid_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Quiet mode of the connection, else the setting
+pub fn is_quiet() -> bool {
+    match SESSION_QUIET.load(Ordering::Relaxed) {
+        SESSION_QUIET_ON => true,
+        SESSION_QUIET_OFF => false,
+        _ => SETTINGS.get_parsed(QUIET_KEY).unwrap_or(false),
+    }
+}
+
+/// Quiet mode until the connection ends, None follows the setting again
+pub fn set_session_quiet(quiet: Option<bool>) {
+    let state = match quiet {
+        Some(true) => SESSION_QUIET_ON,
+        Some(false) => SESSION_QUIET_OFF,
+        None => SESSION_UNSET,
+    };
+    SESSION_QUIET.store(state, Ordering::Relaxed);
+}
+
+/// Quiet period of the greeting in seconds, 0 greets every connection
+pub fn greet_quiet_s() -> u32 {
+    SETTINGS.get_parsed(GREET_QUIET_KEY).unwrap_or(0)
+}
+
+/// Prompt template stored in the settings, the default prompt if not set
+pub fn prompt() -> Value {
+    SETTINGS
+        .get(PROMPT_KEY)
+        .unwrap_or_else(|| Value::try_from(DEFAULT_PROMPT).unwrap_or_default())
+}
+
+/// Prints the prompt expanding its variables, `sequence` is the number of the next command
+pub fn print_prompt(device: &Device, sequence: u32) {
+    let prompt = prompt();
+    let mut chars = prompt.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            print!("{c}");
+            continue;
+        }
+
+        match chars.next() {
+            Some('h') => print!("{}", hostname()),
+            Some('t') => {
+                let secs = device.timer.now().to_secs();
+                print!("{:02}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60);
+            }
+            Some('n') => print!("{sequence}"),
+            Some('%') => print!("%"),
+            Some(other) => print!("%{other}"),
+            None => print!("%"),
+        }
+    }
+    print!(" ");
+}
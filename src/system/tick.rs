@@ -0,0 +1,147 @@
+//! Timer Interrupt Task Registry
+//!
+//! Periodic callbacks executed from the core0 system tick interrupt (TIMER_IRQ_0).
+//! Each task runs every `divider` ticks, one tick being TICK_US (100ms).
+//! Callbacks run in interrupt context: keep them short and never block.
+//!
+//! Example:
+//! ```rust
+//! fn heartbeat() {
+//!     // ...
+//! }
+//!
+//! let id = TICK.register(heartbeat, 5).unwrap(); // every 500ms
+//! TICK.unregister(id);
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use portable_atomic::{AtomicU32, Ordering};
+use thiserror::Error;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// System tick period
+pub const TICK_US: u32 = 100_000; // 100ms - 10hz
+
+const MAX_TICK_TASKS: usize = 8;
+
+pub static TICK: TickTasks = TickTasks {
+    tasks: Mutex::new(RefCell::new([None; MAX_TICK_TASKS])),
+    ticks: AtomicU32::new(0),
+};
+
+pub type TickFn = fn();
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Tick Tasks
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Handle returned on registration, used to modify or remove the task
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TaskId(usize);
+
+#[derive(Copy, Clone)]
+struct TickTask {
+    func:    TickFn,
+    divider: u32,
+    counter: u32,
+}
+
+pub struct TickTasks {
+    tasks: Mutex<RefCell<[Option<TickTask>; MAX_TICK_TASKS]>>,
+    ticks: AtomicU32,
+}
+
+impl TickTasks {
+    /// Registers a callback running every `divider` ticks (0 is treated as 1)
+    pub fn register(&self, func: TickFn, divider: u32) -> Result<TaskId> {
+        with(|cs| {
+            let mut tasks = self.tasks.borrow_ref_mut(cs);
+            let (id, slot) = tasks
+                .iter_mut()
+                .enumerate()
+                .find(|(_, slot)| slot.is_none())
+                .ok_or(Error::Full)?;
+
+            *slot = Some(TickTask {
+                func,
+                divider: divider.max(1),
+                counter: 0,
+            });
+            Ok(TaskId(id))
+        })
+    }
+
+    /// Removes a registered task
+    pub fn unregister(&self, id: TaskId) {
+        with(|cs| {
+            if let Some(slot) = self.tasks.borrow_ref_mut(cs).get_mut(id.0) {
+                *slot = None;
+            }
+        })
+    }
+
+    /// Changes the divider of a registered task
+    pub fn set_divider(&self, id: TaskId, divider: u32) -> Result<()> {
+        with(|cs| {
+            let mut tasks = self.tasks.borrow_ref_mut(cs);
+            let task = tasks
+                .get_mut(id.0)
+                .and_then(|slot| slot.as_mut())
+                .ok_or(Error::InvalidTask)?;
+
+            task.divider = divider.max(1);
+            task.counter = 0;
+            Ok(())
+        })
+    }
+
+    /// Number of ticks elapsed since the interrupt was enabled
+    pub fn ticks(&self) -> u32 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    /// Advances the tick and runs due tasks.
+    /// This should be only called by the system tick interrupt
+    pub fn run(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+
+        // Collecting due tasks first so callbacks are free to (un)register tasks
+        let mut due: [Option<TickFn>; MAX_TICK_TASKS] = [None; MAX_TICK_TASKS];
+
+        with(|cs| {
+            let mut tasks = self.tasks.borrow_ref_mut(cs);
+            for (slot, due) in tasks.iter_mut().zip(due.iter_mut()) {
+                if let Some(task) = slot {
+                    task.counter += 1;
+                    if task.counter >= task.divider {
+                        task.counter = 0;
+                        *due = Some(task.func);
+                    }
+                }
+            }
+        });
+
+        for func in due.iter().flatten() {
+            func();
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no free tick task slot")]
+    Full,
+
+    #[error("invalid tick task")]
+    InvalidTask,
+}
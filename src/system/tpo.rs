@@ -0,0 +1,235 @@
+//! Time Proportioned Outputs (TPO)
+//!
+//! Slow PWM for relays and SSR driven heaters: the output is switched on for `duty` percent of
+//! a window of seconds to minutes (ex: 2s window at 35%: 700ms on, 1300ms off).
+//! Edges are timed by the microsecond scheduler and driven through the SIO registers, so the
+//! outputs keep running between and during commands.
+//!
+//! Duty changes apply from the next window, so a control loop (ex: PID) can call `set_duty`
+//! at any rate without chopping the current window.
+//!
+//! `start_at` opens the first window on a timer tick, ex: a shared start tick (see
+//! `SCHEDULER.latch_start`).
+//!
+//! Example:
+//! ```rust
+//! TPO.start(gpio!(OUT_A), 2_000, 35.0)?; // 2s window, 35% on
+//! TPO.set_duty(gpio!(OUT_A), 50.0)?;
+//! TPO.stop(gpio!(OUT_A))?; // output low
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::gpios;
+use super::scheduler::{self, EntryId, Instant, SCHEDULER};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MAX_TPO_OUTPUTS: usize = 4;
+pub const MIN_PERIOD_MS: u32 = 100;
+pub const MAX_PERIOD_MS: u32 = 3_600_000; // 1h, within the u32 scheduler period
+
+pub static TPO: Tpo = Tpo {
+    channels: Mutex::new(RefCell::new(Vec::new())),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Channel
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone)]
+pub struct Channel {
+    pub gpio:      u8,
+    pub period_ms: u32,
+    /// On time in percent of the period, applied from the next window
+    pub duty:      f32,
+    /// On time of the current window
+    on_us:         u32,
+    /// True between the window start and the off edge
+    on:            bool,
+    entry:         EntryId,
+}
+
+impl Channel {
+    /// On time of a window for the current duty
+    fn window_on_us(&self) -> u32 {
+        let period_us = self.period_ms as u64 * 1000;
+        (period_us as f32 * self.duty / 100.0) as u32
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Tpo
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Tpo {
+    channels: Mutex<RefCell<Vec<Channel, MAX_TPO_OUTPUTS>>>,
+}
+
+impl Tpo {
+    /// Starts driving an output, restarting the window if it already runs.
+    /// The pin has to be registered as output, see `device.outputs`.
+    pub fn start(&self, gpio: u8, period_ms: u32, duty: f32) -> Result<()> {
+        self.start_at(gpio, period_ms, duty, SCHEDULER.now())
+    }
+
+    /// Starts driving an output with its first window on a timer tick
+    pub fn start_at(&self, gpio: u8, period_ms: u32, duty: f32, at: Instant) -> Result<()> {
+        check_period(period_ms)?;
+        check_duty(duty)?;
+        let _ = self.stop(gpio);
+
+        with(|cs| {
+            let mut channels = self.channels.borrow_ref_mut(cs);
+            if channels.is_full() {
+                return Err(Error::Full);
+            }
+
+            let entry = SCHEDULER.schedule_at(at, edge, gpio as u32)?;
+            let _ = channels.push(Channel {
+                gpio,
+                period_ms,
+                duty,
+                on_us: 0,
+                on: false,
+                entry,
+            });
+            Ok(())
+        })
+    }
+
+    /// Changes the duty of a running output, applied from the next window
+    pub fn set_duty(&self, gpio: u8, duty: f32) -> Result<()> {
+        check_duty(duty)?;
+        self.with_channel(gpio, |channel| channel.duty = duty)
+    }
+
+    /// Changes the window of a running output, applied from the next window
+    pub fn set_period(&self, gpio: u8, period_ms: u32) -> Result<()> {
+        check_period(period_ms)?;
+        self.with_channel(gpio, |channel| channel.period_ms = period_ms)
+    }
+
+    /// Stops an output and drives it low
+    pub fn stop(&self, gpio: u8) -> Result<()> {
+        let channel = with(|cs| {
+            let mut channels = self.channels.borrow_ref_mut(cs);
+            let pos = channels.iter().position(|channel| channel.gpio == gpio);
+            pos.map(|pos| channels.remove(pos))
+        })
+        .ok_or(Error::NotRunning)?;
+
+        SCHEDULER.cancel(channel.entry);
+        set_output(gpio, false);
+        Ok(())
+    }
+
+    /// Stops every output, driven low
+    pub fn stop_all(&self) {
+        for channel in self.channels() {
+            let _ = self.stop(channel.gpio);
+        }
+    }
+
+    /// Copy of the running outputs
+    pub fn channels(&self) -> Vec<Channel, MAX_TPO_OUTPUTS> {
+        with(|cs| self.channels.borrow_ref(cs).clone())
+    }
+
+    fn with_channel(&self, gpio: u8, f: impl FnOnce(&mut Channel)) -> Result<()> {
+        with(|cs| {
+            let mut channels = self.channels.borrow_ref_mut(cs);
+            let channel = channels
+                .iter_mut()
+                .find(|channel| channel.gpio == gpio)
+                .ok_or(Error::NotRunning)?;
+            f(channel);
+            Ok(())
+        })
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Scheduler callback switching an output at the window start and at its off edge.
+/// `ctx` is the gpio, returns the time until the next edge.
+fn edge(ctx: u32) -> Option<u32> {
+    let gpio = ctx as u8;
+
+    with(|cs| {
+        let mut channels = TPO.channels.borrow_ref_mut(cs);
+        let channel = channels.iter_mut().find(|channel| channel.gpio == gpio)?;
+        let period_us = channel.period_ms * 1000;
+
+        // Off edge, waits for the next window
+        if channel.on {
+            channel.on = false;
+            set_output(gpio, false);
+            return Some(period_us - channel.on_us);
+        }
+
+        // Window start, 0% and 100% skip the off edge
+        channel.on_us = channel.window_on_us().min(period_us);
+        match channel.on_us {
+            0 => set_output(gpio, false),
+            on_us if on_us == period_us => set_output(gpio, true),
+            on_us => {
+                channel.on = true;
+                set_output(gpio, true);
+                return Some(on_us);
+            }
+        }
+        Some(period_us)
+    })
+}
+
+/// Relay outputs keep their guard limit, a too fast window edge is dropped
+fn set_output(gpio: u8, high: bool) {
+    gpios::set_level(gpio, high);
+}
+
+fn check_period(period_ms: u32) -> Result<()> {
+    match (MIN_PERIOD_MS..=MAX_PERIOD_MS).contains(&period_ms) {
+        true => Ok(()),
+        false => Err(Error::InvalidPeriod),
+    }
+}
+
+fn check_duty(duty: f32) -> Result<()> {
+    match (0.0..=100.0).contains(&duty) {
+        true => Ok(()),
+        false => Err(Error::InvalidDuty),
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no free tpo output")]
+    Full,
+
+    #[error("tpo not running on this output")]
+    NotRunning,
+
+    #[error("period out of range (100ms - 1h)")]
+    InvalidPeriod,
+
+    #[error("duty out of range (0 - 100%)")]
+    InvalidDuty,
+
+    #[error(transparent)]
+    Scheduler(#[from] scheduler::Error),
+}
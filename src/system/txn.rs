@@ -0,0 +1,245 @@
+//! Pin Transactions
+//!
+//! Between `txn begin` and `txn commit` the pin and pwm commands are staged instead of applied,
+//! the connected hardware never sees the intermediate states of a setup sequence. The commit
+//! applies everything at once:
+//! 1. PWM slices: the staged slices are stopped and programmed (phase, top, freq, compare levels)
+//! 2. Output levels: a single write of the SIO XOR register flips the staged pins together
+//! 3. PWM enables: a single write of the EN register starts the staged slices on the same cycle
+//!
+//! `txn abort` drops the staged changes. Staging checks the pins, the output guard is checked at
+//! commit for every pin before the first write: a refused level applies nothing and keeps the
+//! transaction open. Kept in RAM only.
+//!
+//! Example:
+//! ```rust
+//! TXN.begin()?;
+//! TXN.stage_level(gpio!(OUT_A), true)?;
+//! TXN.stage_pwm(change)?;
+//! let (pins, slices) = TXN.commit(&mut device.pwms)?;
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::{Mutex, with};
+use heapless::Vec;
+use thiserror::Error;
+
+use super::gpios;
+use super::output_guard::{self, GUARD};
+use super::pwms::{self, Pwms};
+use crate::state::STATE;
+use crate::{hal, with_pwm_slice};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// One per PWM channel
+const MAX_PWM_CHANGES: usize = 16;
+
+pub static TXN: Txn = Txn {
+    inner: Mutex::new(RefCell::new(None)),
+};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Pwm Change
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Staged settings of a PWM pin, the slice ones are shared with the other channel
+#[derive(Debug, Copy, Clone)]
+pub struct PwmChange {
+    pub gpio:     u8,
+    pub slice_id: u8,
+    pub freq:     u32,
+    pub top:      u16,
+    pub phase:    bool,
+    /// Compare level of the pin channel, None disables the slice
+    pub cc:       Option<u16>,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Txn
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Default)]
+struct Inner {
+    /// Staged output pins and their levels
+    mask:   u32,
+    levels: u32,
+    /// In staging order, one per pin, the last change of a slice sets its state
+    pwms:   Vec<PwmChange, MAX_PWM_CHANGES>,
+}
+
+pub struct Txn {
+    inner: Mutex<RefCell<Option<Inner>>>,
+}
+
+impl Txn {
+    pub fn begin(&self) -> Result<()> {
+        with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.is_some() {
+                return Err(Error::AlreadyOpen);
+            }
+            inner.replace(Inner::default());
+            Ok(())
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        with(|cs| self.inner.borrow_ref(cs).is_some())
+    }
+
+    /// Drops the staged changes, returns their count
+    pub fn abort(&self) -> Result<usize> {
+        let inner = with(|cs| self.inner.borrow_ref_mut(cs).take()).ok_or(Error::NotOpen)?;
+        Ok(inner.mask.count_ones() as usize + inner.pwms.len())
+    }
+
+    /// Stages an output level, the pin has to be a registered output
+    pub fn stage_level(&self, gpio: u8, high: bool) -> Result<()> {
+        self.with(|inner| {
+            inner.mask |= 1 << gpio;
+            match high {
+                true => inner.levels |= 1 << gpio,
+                false => inner.levels &= !(1 << gpio),
+            }
+        })
+    }
+
+    /// Level an output will get at commit, its current one if not staged
+    pub fn staged_level(&self, gpio: u8) -> bool {
+        with(|cs| match self.inner.borrow_ref(cs).as_ref() {
+            Some(inner) if inner.mask & (1 << gpio) != 0 => inner.levels & (1 << gpio) != 0,
+            _ => gpios::output_level(gpio),
+        })
+    }
+
+    /// Stages the settings of a PWM pin, replacing its previous ones
+    pub fn stage_pwm(&self, change: PwmChange) -> Result<()> {
+        self.with(|inner| {
+            inner.pwms.retain(|staged| staged.gpio != change.gpio);
+            inner.pwms.push(change).map_err(|_| Error::Full)
+        })?
+    }
+
+    /// Calls f for the staged output levels then the PWM changes
+    pub fn for_each(&self, mut level: impl FnMut(u8, bool), mut pwm: impl FnMut(&PwmChange)) {
+        with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            let Some(inner) = inner.as_ref()
+            else {
+                return;
+            };
+
+            for gpio in (0..32).filter(|gpio| inner.mask & (1 << gpio) != 0) {
+                level(gpio, inner.levels & (1 << gpio) != 0);
+            }
+            inner.pwms.iter().for_each(&mut pwm);
+        });
+    }
+
+    /// Applies the staged changes and closes the transaction.
+    /// Returns the changed pins and the started slices.
+    pub fn commit(&self, pwms: &mut Pwms) -> Result<(usize, usize)> {
+        let (mask, levels, changes) = with(|cs| {
+            let inner = self.inner.borrow_ref(cs);
+            inner
+                .as_ref()
+                .map(|inner| (inner.mask, inner.levels, inner.pwms.clone()))
+                .ok_or(Error::NotOpen)
+        })?;
+
+        // Nothing is written unless the guard accepts every level
+        for gpio in (0..32).filter(|gpio| mask & (1 << gpio) != 0) {
+            GUARD.check_level(gpio, levels & (1 << gpio) != 0)?;
+        }
+        with(|cs| self.inner.borrow_ref_mut(cs).take());
+
+        // 1. Slices stopped and programmed, the last change of a slice decides its state
+        let mut enable = 0u32;
+        for change in &changes {
+            let bit = 1 << change.slice_id;
+            with_pwm_slice!(pwms, change.slice_id, |pwm_slice| {
+                pwm_slice.disable();
+                if change.cc.is_some() {
+                    if pwm_slice.ph_correct != change.phase {
+                        pwm_slice.set_ph_correct(change.phase);
+                    }
+                    if pwm_slice.slice.get_top() != change.top {
+                        pwm_slice.set_top(change.top);
+                    }
+                    if pwm_slice.freq != change.freq {
+                        pwm_slice.set_freq(change.freq);
+                    }
+                }
+            });
+
+            match change.cc {
+                Some(cc) => {
+                    pwms::write_channel_cc(change.gpio, cc);
+                    enable |= bit;
+                }
+                None => enable &= !bit,
+            }
+        }
+
+        // Bookkeeping of the slices started below
+        for slice_id in (0..8).filter(|slice_id| enable & (1 << slice_id) != 0) {
+            with_pwm_slice!(pwms, slice_id, |pwm_slice| pwm_slice.enabled = true);
+        }
+
+        // Safety: the set/clr/xor and EN registers only touch the bits written
+        let sio = unsafe { hal::regs::sio() };
+        let pwm = unsafe { hal::regs::pwm() };
+        let flip = with(|_cs| {
+            // 2. Output levels in one write
+            let flip = (sio.gpio_out().read().bits() ^ levels) & mask;
+            sio.gpio_out_xor().write(|w| unsafe { w.bits(flip) });
+
+            // 3. Slices started together
+            pwm.en().modify(|r, w| unsafe { w.bits(r.bits() | enable) });
+            flip
+        });
+
+        for gpio in (0..32).filter(|gpio| flip & (1 << gpio) != 0) {
+            STATE.count_toggle(gpio);
+        }
+        Ok((mask.count_ones() as usize, enable.count_ones() as usize))
+    }
+
+    fn with<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Inner) -> R,
+    {
+        with(|cs| {
+            self.inner
+                .borrow_ref_mut(cs)
+                .as_mut()
+                .map(f)
+                .ok_or(Error::NotOpen)
+        })
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("no transaction open, see txn begin")]
+    NotOpen,
+
+    #[error("transaction already open, commit or abort it first")]
+    AlreadyOpen,
+
+    #[error("too many staged pwm changes (max {MAX_PWM_CHANGES})")]
+    Full,
+
+    #[error(transparent)]
+    Guard(#[from] output_guard::Error),
+}
@@ -0,0 +1,634 @@
+//! Hardware UART Storage for the RP2040 microcontroller
+//!
+//! UARTs are created from the UART pin aliases in the pin configuration.
+//! A UART is only enabled when its TX and RX pins are defined and valid for the peripheral.
+//!
+//! The line settings can be changed at runtime, the peripheral is disabled and re-enabled
+//! with the new configuration. Reads and writes never block.
+//!
+//! Receive is interrupt driven: the UART IRQ moves the RX FIFO into a ring of `UART_RX_SIZE`
+//! bytes (see limits.rs), a busy CLI doesn't lose bytes at high baud rates, reads take them from
+//! the ring. Bytes dropped with the ring full, FIFO overruns and framing/parity errors are
+//! counted, see `rx_stats`.
+//! With the CTS and RTS aliases defined (ex: UART0_CTS, UART0_RTS), hardware flow control is
+//! enabled: a full ring leaves the FIFO to fill up and RTS pauses the sender, nothing is dropped.
+//!
+//! Example:
+//! ```rust
+//! device.uarts.configure(UartId::Uart0, LineConfig {
+//!     baud: 9600,
+//!     ..Default::default()
+//! })?;
+//! let sent = device.uarts.write(UartId::Uart0, b"AT\r\n")?;
+//!
+//! let before = device.uarts.rx_stats(UartId::Uart0)?;
+//! let mut buf = [0u8; 32];
+//! let count = device.uarts.read(UartId::Uart0, &mut buf)?;
+//! let lost = device.uarts.rx_stats(UartId::Uart0)?.since(&before).dropped;
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::hal;
+//
+use hal::fugit::RateExtU32;
+use hal::gpio::{DynPinId, FunctionUart, Pin, PullUp};
+use hal::pac;
+use hal::pac::interrupt;
+use hal::uart::{DataBits,
+                Disabled,
+                Enabled,
+                StopBits,
+                UartConfig,
+                UartDevice,
+                UartPeripheral,
+                ValidatedPinCts,
+                ValidatedPinRts,
+                ValidatedPinRx,
+                ValidatedPinTx};
+
+use critical_section::{Mutex, with};
+use heapless::Deque;
+use thiserror::Error;
+use usbd_serial::{LineCoding, ParityType};
+
+use super::config::CONFIG;
+use crate::limits::UART_RX_SIZE;
+use crate::prelude::warn;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const MIN_BAUD: u32 = 300;
+const MAX_BAUD: u32 = 921_600;
+
+type UartPin = Pin<DynPinId, FunctionUart, PullUp>; // Idle line is high
+type UartPins<D> = (ValidatedPinTx<UartPin, D>, ValidatedPinRx<UartPin, D>);
+/// Owned next to the hal pins, the flow control bits are set on enable
+type FlowPins<D> = (ValidatedPinCts<UartPin, D>, ValidatedPinRts<UartPin, D>);
+
+/// Received bytes, indexed by `UartDevice::ID`
+static RX: [RxRing; 2] = [RxRing::new(), RxRing::new()];
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Uart Id
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UartId {
+    Uart0,
+    Uart1,
+}
+
+impl UartId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UartId::Uart0 => "uart0",
+            UartId::Uart1 => "uart1",
+        }
+    }
+
+    /// Same as `UartDevice::ID`
+    fn index(&self) -> usize {
+        match self {
+            UartId::Uart0 => 0,
+            UartId::Uart1 => 1,
+        }
+    }
+}
+
+impl FromStr for UartId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [UartId::Uart0, UartId::Uart1]
+            .into_iter()
+            .find(|uart| uart.name().eq_ignore_ascii_case(s))
+            .ok_or(Error::InvalidUart)
+    }
+}
+
+impl fmt::Display for UartId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Line Config
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// UART line settings, displayed as "115200 8N1"
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineConfig {
+    pub baud:      u32,
+    pub data_bits: u8,
+    pub parity:    Parity,
+    pub stop_bits: u8,
+}
+
+impl Default for LineConfig {
+    fn default() -> Self {
+        Self {
+            baud:      115_200,
+            data_bits: 8,
+            parity:    Parity::None,
+            stop_bits: 1,
+        }
+    }
+}
+
+impl LineConfig {
+    /// Line settings requested by the USB host through the CDC SET_LINE_CODING request
+    /// Mark/space parity is not supported by the hardware and 1.5 stop bits round up to 2
+    pub fn from_cdc(coding: &LineCoding) -> Self {
+        let parity = match coding.parity_type() {
+            ParityType::Odd => Parity::Odd,
+            ParityType::Even => Parity::Even,
+            _ => Parity::None,
+        };
+
+        Self {
+            baud: coding.data_rate(),
+            data_bits: coding.data_bits().clamp(5, 8),
+            parity,
+            stop_bits: if coding.stop_bits() == usbd_serial::StopBits::One { 1 } else { 2 },
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let valid = (MIN_BAUD..=MAX_BAUD).contains(&self.baud)
+            && (5..=8).contains(&self.data_bits)
+            && (1..=2).contains(&self.stop_bits);
+
+        if valid { Ok(()) } else { Err(Error::Unsupported(*self)) }
+    }
+
+    fn to_hal(self) -> UartConfig {
+        let data_bits = match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            _ => DataBits::Eight,
+        };
+
+        let parity = match self.parity {
+            Parity::None => None,
+            Parity::Odd => Some(hal::uart::Parity::Odd),
+            Parity::Even => Some(hal::uart::Parity::Even),
+        };
+
+        let stop_bits = if self.stop_bits == 2 { StopBits::Two } else { StopBits::One };
+
+        UartConfig::new(self.baud.Hz(), data_bits, parity, stop_bits)
+    }
+}
+
+impl fmt::Display for LineConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        write!(f, "{} {}{}{}", self.baud, self.data_bits, parity, self.stop_bits)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Uarts
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Uarts {
+    uart0:      Option<UartPort<pac::UART0>>,
+    uart1:      Option<UartPort<pac::UART1>>,
+    sys_clk_hz: u32,
+}
+
+impl Uarts {
+    /// Creates the UARTs defined in the pin configuration, enabled at 115200 8N1
+    pub fn new(
+        uart0: pac::UART0,
+        uart1: pac::UART1,
+        resets: &mut pac::RESETS,
+        sys_clk_hz: u32,
+    ) -> Self {
+        Self {
+            uart0: new_uart(uart0, "UART0", resets, sys_clk_hz),
+            uart1: new_uart(uart1, "UART1", resets, sys_clk_hz),
+            sys_clk_hz,
+        }
+    }
+
+    /// Returns true if the UART pins are configured
+    pub fn is_configured(&self, uart: UartId) -> bool {
+        match uart {
+            UartId::Uart0 => self.uart0.is_some(),
+            UartId::Uart1 => self.uart1.is_some(),
+        }
+    }
+
+    /// Current line settings
+    pub fn config(&mut self, uart: UartId) -> Result<LineConfig> {
+        Ok(self.port(uart)?.config())
+    }
+
+    /// Re-enables the UART with new line settings, the FIFOs are flushed
+    pub fn configure(&mut self, uart: UartId, config: LineConfig) -> Result<()> {
+        config.validate()?;
+        let sys_clk_hz = self.sys_clk_hz;
+        self.port(uart)?.configure(config, sys_clk_hz)
+    }
+
+    /// Writes what fits in the TX FIFO, returns the number of bytes sent
+    pub fn write(&mut self, uart: UartId, data: &[u8]) -> Result<usize> {
+        Ok(self.port(uart)?.write(data))
+    }
+
+    /// Reads the received bytes, returns the number of bytes read
+    /// Bytes with framing or parity errors are dropped
+    pub fn read(&mut self, uart: UartId, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.port(uart)?.read(buf))
+    }
+
+    /// Receive counters since boot
+    pub fn rx_stats(&mut self, uart: UartId) -> Result<RxStats> {
+        self.port(uart)?;
+        Ok(with(|cs| RX[uart.index()].inner.borrow_ref(cs).stats))
+    }
+
+    /// Returns true if hardware flow control is enabled
+    pub fn has_flow_control(&mut self, uart: UartId) -> Result<bool> {
+        Ok(self.port(uart)?.has_flow_control())
+    }
+
+    /// Inverts the RX input in the pad, for receivers with an idle low line (ex: SBUS)
+    pub fn set_rx_inverted(&mut self, uart: UartId, inverted: bool) -> Result<()> {
+        self.port(uart)?;
+        let (_, rx_alias) = pin_aliases(uart);
+        let rx = CONFIG
+            .get_gpio(rx_alias)
+            .map_err(|_| Error::NotConfigured(uart))?;
+
+        // Safety: only the input override of a pin owned by the UART is changed
+        let io = unsafe { hal::regs::io_bank0() };
+        io.gpio(rx as usize)
+            .gpio_ctrl()
+            .modify(|_, w| match inverted {
+                true => w.inover().invert(),
+                false => w.inover().normal(),
+            });
+        Ok(())
+    }
+
+    fn port(&mut self, uart: UartId) -> Result<&mut dyn Port> {
+        let port = match uart {
+            UartId::Uart0 => self.uart0.as_mut().map(|u| u as &mut dyn Port),
+            UartId::Uart1 => self.uart1.as_mut().map(|u| u as &mut dyn Port),
+        };
+        port.ok_or(Error::NotConfigured(uart))
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Uart Port
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Common interface of the two UART peripherals
+trait Port {
+    fn config(&self) -> LineConfig;
+    fn configure(&mut self, config: LineConfig, sys_clk_hz: u32) -> Result<()>;
+    fn write(&mut self, data: &[u8]) -> usize;
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+    fn has_flow_control(&self) -> bool;
+}
+
+enum PortState<D: UartDevice> {
+    Disabled(UartPeripheral<Disabled, D, UartPins<D>>),
+    Enabled(UartPeripheral<Enabled, D, UartPins<D>>),
+}
+
+/// The peripheral changes type when enabled, the state is taken out while switching
+struct UartPort<D: UartDevice> {
+    state:  Option<PortState<D>>,
+    config: LineConfig,
+    flow:   Option<FlowPins<D>>,
+}
+
+impl<D: UartDevice> Port for UartPort<D> {
+    fn config(&self) -> LineConfig {
+        self.config
+    }
+
+    fn configure(&mut self, config: LineConfig, sys_clk_hz: u32) -> Result<()> {
+        let disabled = match self.state.take() {
+            Some(PortState::Enabled(uart)) => uart.disable(),
+            Some(PortState::Disabled(uart)) => uart,
+            None => return Err(Error::Lost),
+        };
+
+        // Only fails on a zero baud rate, already validated
+        let mut uart = disabled
+            .enable(config.to_hal(), sys_clk_hz.Hz())
+            .map_err(|_| Error::Lost)?;
+
+        // The hal only enables the flow control of the pins it owns
+        let flow = self.flow.is_some();
+        if flow {
+            registers(D::ID)
+                .uartcr()
+                .modify(|_, w| w.ctsen().set_bit().rtsen().set_bit());
+        }
+
+        with(|cs| {
+            let mut rx = RX[D::ID].inner.borrow_ref_mut(cs);
+            rx.bytes.clear();
+            rx.flow = flow;
+        });
+        uart.enable_rx_interrupt();
+
+        self.state = Some(PortState::Enabled(uart));
+        self.config = config;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> usize {
+        let Some(PortState::Enabled(uart)) = &self.state
+        else {
+            return 0;
+        };
+
+        match uart.write_raw(data) {
+            Ok(remaining) => data.len() - remaining.len(),
+            Err(_) => 0, // TX FIFO full
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let Some(PortState::Enabled(_)) = &self.state
+        else {
+            return 0;
+        };
+
+        with(|cs| {
+            let mut rx = RX[D::ID].inner.borrow_ref_mut(cs);
+            let mut count = 0;
+            while count < buf.len()
+                && let Some(byte) = rx.bytes.pop_front()
+            {
+                buf[count] = byte;
+                count += 1;
+            }
+
+            // Refills the ring, the interrupt is masked while a full ring holds the sender
+            let regs = registers(D::ID);
+            drain(regs, &mut rx);
+            regs.uartimsc()
+                .modify(|_, w| w.rxim().set_bit().rtim().set_bit());
+            count
+        })
+    }
+
+    fn has_flow_control(&self) -> bool {
+        self.flow.is_some()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Receive
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Receive counters, cumulative since boot
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RxStats {
+    /// Bytes stored in the ring
+    pub received: u32,
+    /// Bytes dropped with the ring full, without flow control
+    pub dropped:  u32,
+    /// Hardware FIFO overruns, bytes lost before the interrupt ran
+    pub overruns: u32,
+    /// Bytes dropped on framing, parity or break errors
+    pub errors:   u32,
+}
+
+impl RxStats {
+    /// Counters since an earlier reading
+    pub fn since(&self, earlier: &RxStats) -> RxStats {
+        RxStats {
+            received: self.received.wrapping_sub(earlier.received),
+            dropped:  self.dropped.wrapping_sub(earlier.dropped),
+            overruns: self.overruns.wrapping_sub(earlier.overruns),
+            errors:   self.errors.wrapping_sub(earlier.errors),
+        }
+    }
+}
+
+impl fmt::Display for RxStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} received, {} dropped, {} overrun(s), {} error(s)",
+            self.received, self.dropped, self.overruns, self.errors
+        )
+    }
+}
+
+struct RxInner {
+    bytes: Deque<u8, UART_RX_SIZE>,
+    /// Hardware flow control, a full ring leaves the bytes in the FIFO
+    flow:  bool,
+    stats: RxStats,
+}
+
+struct RxRing {
+    inner: Mutex<RefCell<RxInner>>,
+}
+
+impl RxRing {
+    const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(RxInner {
+                bytes: Deque::new(),
+                flow:  false,
+                stats: RxStats {
+                    received: 0,
+                    dropped:  0,
+                    overruns: 0,
+                    errors:   0,
+                },
+            })),
+        }
+    }
+}
+
+/// Moves the RX FIFO into the ring. With flow control a full ring masks the RX interrupts and
+/// leaves the FIFO to fill up, RTS pauses the sender until the next read.
+fn drain(regs: &pac::uart0::RegisterBlock, rx: &mut RxInner) {
+    while regs.uartfr().read().rxfe().bit_is_clear() {
+        if rx.flow && rx.bytes.is_full() {
+            regs.uartimsc()
+                .modify(|_, w| w.rxim().clear_bit().rtim().clear_bit());
+            return;
+        }
+
+        let dr = regs.uartdr().read();
+        if dr.oe().bit_is_set() {
+            rx.stats.overruns = rx.stats.overruns.wrapping_add(1);
+        }
+        if dr.fe().bit_is_set() || dr.pe().bit_is_set() || dr.be().bit_is_set() {
+            rx.stats.errors = rx.stats.errors.wrapping_add(1);
+            continue;
+        }
+
+        match rx.bytes.push_back(dr.data().bits()) {
+            Ok(()) => rx.stats.received = rx.stats.received.wrapping_add(1),
+            Err(_) => rx.stats.dropped = rx.stats.dropped.wrapping_add(1),
+        }
+    }
+}
+
+/// Registers of a UART for the receive path
+fn registers(id: usize) -> &'static pac::uart0::RegisterBlock {
+    // Safety: only the RX data, the interrupt mask and the flow control bits are accessed,
+    // within critical sections or before the interrupt is enabled
+    match id {
+        0 => unsafe { hal::regs::uart0() },
+        _ => unsafe { hal::regs::uart1() },
+    }
+}
+
+/// UART0 RX level or timeout
+#[pac::interrupt]
+fn UART0_IRQ() {
+    with(|cs| drain(registers(0), &mut RX[0].inner.borrow_ref_mut(cs)));
+}
+
+/// UART1 RX level or timeout
+#[pac::interrupt]
+fn UART1_IRQ() {
+    with(|cs| drain(registers(1), &mut RX[1].inner.borrow_ref_mut(cs)));
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Error
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    #[error("invalid uart, use uart0 or uart1")]
+    InvalidUart,
+
+    #[error("uart {0} not configured")]
+    NotConfigured(UartId),
+
+    #[error("unsupported line config: {0}")]
+    Unsupported(LineConfig),
+
+    #[error("uart peripheral lost while reconfiguring")]
+    Lost,
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// TX and RX pin aliases of a UART
+fn pin_aliases(uart: UartId) -> (&'static str, &'static str) {
+    match uart {
+        UartId::Uart0 => ("UART0_TX", "UART0_RX"),
+        UartId::Uart1 => ("UART1_TX", "UART1_RX"),
+    }
+}
+
+/// CTS and RTS pin aliases of a UART
+fn flow_aliases(uart: UartId) -> (&'static str, &'static str) {
+    match uart {
+        UartId::Uart0 => ("UART0_CTS", "UART0_RTS"),
+        UartId::Uart1 => ("UART1_CTS", "UART1_RTS"),
+    }
+}
+
+/// Takes a pin of the given alias if it is defined in the config
+fn take_uart_pin(alias: &str) -> Option<UartPin> {
+    let id = CONFIG.get_gpio(alias).ok()?;
+    CONFIG.take_pin(id)
+}
+
+fn new_uart<D: UartDevice>(
+    device: D,
+    name: &str,
+    resets: &mut pac::RESETS,
+    sys_clk_hz: u32,
+) -> Option<UartPort<D>> {
+    let uart = match D::ID {
+        0 => UartId::Uart0,
+        _ => UartId::Uart1,
+    };
+    let (tx_alias, rx_alias) = pin_aliases(uart);
+
+    // Both pins have to be defined to enable the UART
+    if CONFIG.get_gpio(tx_alias).is_err() || CONFIG.get_gpio(rx_alias).is_err() {
+        return None;
+    }
+
+    let tx = ValidatedPinTx::validate(take_uart_pin(tx_alias)?, &device);
+    let rx = ValidatedPinRx::validate(take_uart_pin(rx_alias)?, &device);
+
+    let (Ok(tx), Ok(rx)) = (tx, rx)
+    else {
+        warn!("{} disabled: invalid TX/RX pins", name);
+        return None;
+    };
+
+    let flow = new_flow_pins(uart, &device);
+    let mut port = UartPort {
+        state: Some(PortState::Disabled(UartPeripheral::new(device, (tx, rx), resets))),
+        config: LineConfig::default(),
+        flow,
+    };
+    port.configure(LineConfig::default(), sys_clk_hz).ok()?;
+
+    // Safety: the handler only touches the RX ring of the port
+    unsafe {
+        match uart {
+            UartId::Uart0 => pac::NVIC::unmask(pac::Interrupt::UART0_IRQ),
+            UartId::Uart1 => pac::NVIC::unmask(pac::Interrupt::UART1_IRQ),
+        }
+    }
+    Some(port)
+}
+
+/// Takes the CTS and RTS pins when both are defined, None without flow control
+fn new_flow_pins<D: UartDevice>(uart: UartId, device: &D) -> Option<FlowPins<D>> {
+    let (cts_alias, rts_alias) = flow_aliases(uart);
+    match (CONFIG.get_gpio(cts_alias).is_ok(), CONFIG.get_gpio(rts_alias).is_ok()) {
+        (true, true) => {}
+        (false, false) => return None,
+        _ => {
+            warn!("{uart} flow control disabled: both CTS and RTS pins are needed");
+            return None;
+        }
+    }
+
+    let cts = ValidatedPinCts::validate(take_uart_pin(cts_alias)?, device);
+    let rts = ValidatedPinRts::validate(take_uart_pin(rts_alias)?, device);
+
+    let (Ok(cts), Ok(rts)) = (cts, rts)
+    else {
+        warn!("{uart} flow control disabled: invalid CTS/RTS pins");
+        return None;
+    };
+    Some((cts, rts))
+}
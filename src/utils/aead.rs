@@ -0,0 +1,370 @@
+//! ChaCha20-Poly1305 (RFC 8439)
+//!
+//! Authenticated encryption for the secure command channel, in software: ChaCha20 runs well on
+//! the Cortex-M0+ without AES hardware. Buffers are encrypted in place, the 16 byte tag
+//! authenticates the ciphertext and the associated data.
+//!
+//! A nonce must never be used twice with the same key, see system/secure.rs.
+//!
+//! Example:
+//! ```rust
+//! let tag = aead::seal(&key, &nonce, b"", &mut buf);
+//! aead::open(&key, &nonce, b"", &mut buf, &tag)?;
+//! ```
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+pub type Key = [u8; KEY_SIZE];
+pub type Nonce = [u8; NONCE_SIZE];
+pub type Tag = [u8; TAG_SIZE];
+
+/// Authentication failed, the buffer is left encrypted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AuthError;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              AEAD
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Encrypts `buf` in place, returns the tag
+pub fn seal(key: &Key, nonce: &Nonce, aad: &[u8], buf: &mut [u8]) -> Tag {
+    chacha20_xor(key, nonce, 1, buf);
+    mac(key, nonce, aad, buf)
+}
+
+/// Checks the tag, then decrypts `buf` in place
+pub fn open(
+    key: &Key,
+    nonce: &Nonce,
+    aad: &[u8],
+    buf: &mut [u8],
+    tag: &Tag,
+) -> Result<(), AuthError> {
+    let expected = mac(key, nonce, aad, buf);
+
+    // Constant time comparison
+    let diff = expected
+        .iter()
+        .zip(tag)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    if diff != 0 {
+        return Err(AuthError);
+    }
+    chacha20_xor(key, nonce, 1, buf);
+    Ok(())
+}
+
+/// Poly1305 of the associated data and ciphertext, keyed by the first ChaCha20 block
+fn mac(key: &Key, nonce: &Nonce, aad: &[u8], ciphertext: &[u8]) -> Tag {
+    let block = chacha20_block(key, nonce, 0);
+    let mut otk = [0u8; 32];
+    otk.copy_from_slice(&block[..32]);
+
+    let mut poly = Poly1305::new(&otk);
+    poly.update_padded(aad);
+    poly.update_padded(ciphertext);
+
+    let mut lengths = [0u8; 16];
+    lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly.block(&lengths);
+    poly.finish()
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            ChaCha20
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn chacha20_xor(key: &Key, nonce: &Nonce, counter: u32, buf: &mut [u8]) {
+    for (i, chunk) in buf.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, nonce, counter.wrapping_add(i as u32));
+        for (byte, key_byte) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+fn chacha20_block(key: &Key, nonce: &Nonce, counter: u32) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for i in 0..8 {
+        state[4 + i] = le32(&key[i * 4..]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = le32(&nonce[i * 4..]);
+    }
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&x[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Poly1305
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// 32 bit implementation, the accumulator in 26 bit limbs
+struct Poly1305 {
+    r:   [u32; 5],
+    h:   [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            r:   [
+                le32(&key[0..]) & 0x03ff_ffff,
+                (le32(&key[3..]) >> 2) & 0x03ff_ff03,
+                (le32(&key[6..]) >> 4) & 0x03ff_c0ff,
+                (le32(&key[9..]) >> 6) & 0x03f0_3fff,
+                (le32(&key[12..]) >> 8) & 0x000f_ffff,
+            ],
+            h:   [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+        }
+    }
+
+    /// Feeds the data zero padded to 16 bytes, as the AEAD construction does
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.block(&block);
+        }
+    }
+
+    /// Adds a 16 byte block, with the 2^128 bit set
+    fn block(&mut self, m: &[u8; 16]) {
+        self.add(m, 1 << 24);
+    }
+
+    /// Adds a block, `hibit` is the 2^128 bit in the top limb: 0 for a last partial block padded
+    /// with 0x01, which the AEAD construction never uses
+    fn add(&mut self, m: &[u8; 16], hibit: u32) {
+        const MASK: u32 = 0x03ff_ffff;
+        let [r0, r1, r2, r3, r4] = self.r.map(|r| r as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h = &mut self.h;
+        h[0] += le32(&m[0..]) & MASK;
+        h[1] += (le32(&m[3..]) >> 2) & MASK;
+        h[2] += (le32(&m[6..]) >> 4) & MASK;
+        h[3] += (le32(&m[9..]) >> 6) & MASK;
+        h[4] += (le32(&m[12..]) >> 8) | hibit;
+        let [h0, h1, h2, h3, h4] = h.map(|h| h as u64);
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+        h[0] = (d0 as u32 & MASK) + (d4 >> 26) as u32 * 5;
+        h[1] = (d1 as u32 & MASK) + (h[0] >> 26);
+        h[0] &= MASK;
+        h[2] = d2 as u32 & MASK;
+        h[3] = d3 as u32 & MASK;
+        h[4] = d4 as u32 & MASK;
+    }
+
+    fn finish(mut self) -> Tag {
+        const MASK: u32 = 0x03ff_ffff;
+        let h = &mut self.h;
+
+        // Full carry, the bits above 2^130 wrap around times 5
+        for i in 1..5 {
+            let carry = h[i] >> 26;
+            h[i] &= MASK;
+            match i {
+                4 => h[0] += carry * 5,
+                _ => h[i + 1] += carry,
+            }
+        }
+        let carry = h[0] >> 26;
+        h[0] &= MASK;
+        h[1] += carry;
+
+        // h - p, kept if it does not borrow
+        let mut g = [0u32; 5];
+        let mut carry = 5;
+        for i in 0..5 {
+            g[i] = h[i].wrapping_add(carry);
+            carry = g[i] >> 26;
+            g[i] &= MASK;
+        }
+        g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+        let select = (g[4] >> 31).wrapping_sub(1); // all ones when h >= p
+        for i in 0..5 {
+            h[i] = (h[i] & !select) | (g[i] & select);
+        }
+
+        // 130 bits to 128, plus the pad
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0u8; TAG_SIZE];
+        let mut f = 0u64;
+        for i in 0..4 {
+            f = words[i] as u64 + self.pad[i] as u64 + (f >> 32);
+            tag[i * 4..i * 4 + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        tag
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Tests
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// RFC 8439 test vectors, host build: cargo test --target x86_64-unknown-linux-gnu
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(text: &str) -> [u8; N] {
+        let text: heapless::String<512> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        assert_eq!(text.len(), N * 2);
+        let mut out = [0u8; N];
+        for (byte, pair) in out.iter_mut().zip(text.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        out
+    }
+
+    /// Raw Poly1305 of a message, the last partial block padded with 0x01
+    fn poly1305(key: &[u8; 32], msg: &[u8]) -> Tag {
+        let mut poly = Poly1305::new(key);
+        for chunk in msg.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            match chunk.len() {
+                16 => poly.block(&block),
+                len => {
+                    block[len] = 1;
+                    poly.add(&block, 0);
+                }
+            }
+        }
+        poly.finish()
+    }
+
+    /// §2.3.2
+    #[test]
+    fn chacha20_block_function() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: Nonce = hex("000000090000004a00000000");
+        let expected: [u8; 64] =
+            hex("10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e
+             d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e");
+        assert_eq!(chacha20_block(&key, &nonce, 1), expected);
+    }
+
+    /// §2.5.2
+    #[test]
+    fn poly1305_mac() {
+        let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let expected: Tag = hex("a8061dc1305136c6c22b8baf0c0127a9");
+        assert_eq!(poly1305(&key, b"Cryptographic Forum Research Group"), expected);
+    }
+
+    /// §2.8.2
+    const PLAINTEXT: &[u8; 114] =
+        b"Ladies and Gentlemen of the class of '99: If I could offer you \
+          only one tip for the future, sunscreen would be it.";
+    const AAD: &str = "50515253c0c1c2c3c4c5c6c7";
+    const KEY: &str = "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f";
+    const NONCE: &str = "070000004041424344454647";
+    const CIPHERTEXT: &str = "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6
+                              3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36
+                              92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc
+                              3ff4def08e4b7a9de576d26586cec64b6116";
+    const TAG: &str = "1ae10b594f09e26a7e902ecbd0600691";
+
+    #[test]
+    fn aead_seal() {
+        let aad: [u8; 12] = hex(AAD);
+        let mut buf = *PLAINTEXT;
+        let tag = seal(&hex(KEY), &hex(NONCE), &aad, &mut buf);
+        assert_eq!(buf, hex::<114>(CIPHERTEXT));
+        assert_eq!(tag, hex::<16>(TAG));
+    }
+
+    #[test]
+    fn aead_open() {
+        let aad: [u8; 12] = hex(AAD);
+        let mut buf: [u8; 114] = hex(CIPHERTEXT);
+        assert_eq!(open(&hex(KEY), &hex(NONCE), &aad, &mut buf, &hex(TAG)), Ok(()));
+        assert_eq!(&buf, PLAINTEXT);
+    }
+
+    #[test]
+    fn aead_open_rejects_tampering() {
+        let aad: [u8; 12] = hex(AAD);
+        let ciphertext: [u8; 114] = hex(CIPHERTEXT);
+
+        let mut buf = ciphertext;
+        buf[40] ^= 1;
+        assert_eq!(open(&hex(KEY), &hex(NONCE), &aad, &mut buf, &hex(TAG)), Err(AuthError));
+        // Left encrypted
+        buf[40] ^= 1;
+        assert_eq!(buf, ciphertext);
+
+        let mut tag: Tag = hex(TAG);
+        tag[15] ^= 0x80;
+        assert_eq!(open(&hex(KEY), &hex(NONCE), &aad, &mut buf, &tag), Err(AuthError));
+        assert_eq!(open(&hex(KEY), &hex(NONCE), b"", &mut buf, &hex(TAG)), Err(AuthError));
+    }
+}
@@ -0,0 +1,270 @@
+//! CRC Utilities
+//!
+//! Table driven CRC-8/16/32 for the checksums of the framing and storage code, parameterized
+//! like the CRC catalogue (width, poly, init, reflection, xorout). The tables are built at compile
+//! time, an algorithm only takes flash when it is used.
+//!
+//! `checksum` runs on the DMA sniffer when the algorithm is one of its polynomials (CRC-32,
+//! CRC-16/XMODEM, CRC-16/CCITT-FALSE) and `init_sniffer` was called: about a byte per system
+//! clock, flash ranges included. The table is used otherwise or while the sniffer is busy.
+//!
+//! Example:
+//! ```rust
+//! let crc = crc::checksum(&CRC16_MODBUS, frame);
+//!
+//! let mut crc = Crc::new(&CRC32);
+//! crc.update(chunk);
+//! println!("crc32=0x{:08x}", crc.value());
+//! ```
+
+use portable_atomic::{AtomicBool, Ordering};
+
+use crate::hal;
+use crate::hal::dma::{CH11, Channel};
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Channel reserved for the sniffer, taken from the HAL by `init_sniffer`
+const SNIFFER_CHANNEL: usize = 11;
+/// Bytes per sniffer transfer, bounds the critical section
+const SNIFFER_CHUNK: usize = 4096;
+/// Shorter buffers go through the table, the DMA setup costs more
+const SNIFFER_MIN_LEN: usize = 64;
+
+static SNIFFER_READY: AtomicBool = AtomicBool::new(false);
+static SNIFFER_BUSY: AtomicBool = AtomicBool::new(false);
+
+/// SMBus PEC, check 0xF4
+pub static CRC8: Algorithm = Algorithm::new("crc8", 8, 0x07, 0x00, false, 0x00);
+/// Dallas/Maxim 1-Wire, check 0xA1
+pub static CRC8_MAXIM: Algorithm = Algorithm::new("crc8-maxim", 8, 0x31, 0x00, true, 0x00);
+/// Sensirion sensors (SHT, SCD), check 0xF7
+pub static CRC8_SENSIRION: Algorithm = Algorithm::new("crc8-sensirion", 8, 0x31, 0xFF, false, 0);
+/// Modbus RTU, sent low byte first, check 0x4B37
+pub static CRC16_MODBUS: Algorithm = Algorithm::new("crc16-modbus", 16, 0x8005, 0xFFFF, true, 0);
+/// XMODEM, check 0x31C3
+pub static CRC16_XMODEM: Algorithm = Algorithm::new("crc16-xmodem", 16, 0x1021, 0x0000, false, 0);
+/// CCITT-FALSE / IBM-3740, check 0x29B1
+pub static CRC16_CCITT: Algorithm = Algorithm::new("crc16-ccitt", 16, 0x1021, 0xFFFF, false, 0);
+/// IEEE 802.3, same as Python's zlib.crc32, check 0xCBF43926
+pub static CRC32: Algorithm =
+    Algorithm::new("crc32", 32, 0x04C1_1DB7, 0xFFFF_FFFF, true, 0xFFFF_FFFF);
+
+/// Algorithms selectable by name
+pub static ALGORITHMS: [&Algorithm; 7] = [
+    &CRC8,
+    &CRC8_MAXIM,
+    &CRC8_SENSIRION,
+    &CRC16_MODBUS,
+    &CRC16_XMODEM,
+    &CRC16_CCITT,
+    &CRC32,
+];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Algorithm
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Algorithm {
+    pub name:      &'static str,
+    pub width:     u8,
+    pub poly:      u32,
+    pub init:      u32,
+    /// Input and output reflected, LSB first
+    pub reflected: bool,
+    pub xorout:    u32,
+    table:         [u32; 256],
+}
+
+impl Algorithm {
+    pub const fn new(
+        name: &'static str,
+        width: u8,
+        poly: u32,
+        init: u32,
+        reflected: bool,
+        xorout: u32,
+    ) -> Self {
+        Self {
+            name,
+            width,
+            poly,
+            init,
+            reflected,
+            xorout,
+            table: build_table(width, poly, reflected),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Algorithm> {
+        ALGORITHMS
+            .into_iter()
+            .find(|alg| alg.name.eq_ignore_ascii_case(name))
+    }
+
+    const fn mask(&self) -> u32 {
+        u32::MAX >> (32 - self.width as u32)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                               Crc
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Running CRC, fed in chunks
+pub struct Crc {
+    alg: &'static Algorithm,
+    /// Reflected: the CRC in the low bits, else left aligned on bit 31
+    reg: u32,
+}
+
+impl Crc {
+    pub fn new(alg: &'static Algorithm) -> Self {
+        let reg = match alg.reflected {
+            true => alg.init.reverse_bits() >> (32 - alg.width as u32),
+            false => alg.init << (32 - alg.width as u32),
+        };
+        Self { alg, reg }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let table = &self.alg.table;
+        match self.alg.reflected {
+            true => {
+                for &byte in data {
+                    self.reg = table[((self.reg ^ byte as u32) & 0xFF) as usize] ^ (self.reg >> 8);
+                }
+            }
+            false => {
+                for &byte in data {
+                    self.reg = table[((self.reg >> 24) ^ byte as u32) as usize] ^ (self.reg << 8);
+                }
+            }
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        let crc = match self.alg.reflected {
+            true => self.reg,
+            false => self.reg >> (32 - self.alg.width as u32),
+        };
+        (crc ^ self.alg.xorout) & self.alg.mask()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// CRC of a complete buffer, on the DMA sniffer when possible
+pub fn checksum(alg: &'static Algorithm, data: &[u8]) -> u32 {
+    if data.len() >= SNIFFER_MIN_LEN
+        && let Some(crc) = sniff(alg, data)
+    {
+        return crc;
+    }
+    table_checksum(alg, data)
+}
+
+/// CRC of a complete buffer, always through the table
+pub fn table_checksum(alg: &'static Algorithm, data: &[u8]) -> u32 {
+    let mut crc = Crc::new(alg);
+    crc.update(data);
+    crc.value()
+}
+
+/// Takes the DMA channel used by the sniffer, `checksum` uses the sniffer from then on
+pub fn init_sniffer(_channel: Channel<CH11>) {
+    SNIFFER_READY.store(true, Ordering::Release);
+}
+
+pub fn sniffer_ready() -> bool {
+    SNIFFER_READY.load(Ordering::Acquire)
+}
+
+/// Runs the buffer through the DMA sniffer, None when the algorithm or the sniffer is unavailable
+fn sniff(alg: &'static Algorithm, data: &[u8]) -> Option<u32> {
+    // Sniffer calc mode and output reversal of the algorithm
+    let (calc, out_rev) = match (alg.width, alg.poly, alg.reflected) {
+        (32, 0x04C1_1DB7, true) => (1, true), // CRC32R, bit reversed input
+        (32, 0x04C1_1DB7, false) => (0, false),
+        (16, 0x1021, false) => (2, false),
+        _ => return None,
+    };
+    if !sniffer_ready() || SNIFFER_BUSY.swap(true, Ordering::Acquire) {
+        return None;
+    }
+
+    // Safety: the channel was handed over by init_sniffer, the sniffer is only driven here
+    let dma = unsafe { hal::regs::dma() };
+    let ch = dma.ch(SNIFFER_CHANNEL);
+    let mut sink = 0u32;
+
+    // The sniffer register is not reflected, its output is
+    dma.sniff_data().write(|w| unsafe { w.bits(alg.init) });
+    dma.sniff_ctrl().write(|w| unsafe {
+        w.en().set_bit();
+        w.dmach().bits(SNIFFER_CHANNEL as u8);
+        w.calc().bits(calc);
+        w.out_rev().bit(out_rev)
+    });
+
+    for chunk in data.chunks(SNIFFER_CHUNK) {
+        critical_section::with(|_| {
+            ch.ch_read_addr()
+                .write(|w| unsafe { w.bits(chunk.as_ptr() as u32) });
+            ch.ch_write_addr()
+                .write(|w| unsafe { w.bits(&raw mut sink as u32) });
+            ch.ch_trans_count()
+                .write(|w| unsafe { w.bits(chunk.len() as u32) });
+            ch.ch_ctrl_trig().write(|w| unsafe {
+                w.data_size().size_byte();
+                w.incr_read().set_bit();
+                w.incr_write().clear_bit();
+                w.treq_sel().permanent();
+                w.chain_to().bits(SNIFFER_CHANNEL as u8);
+                w.sniff_en().set_bit();
+                w.en().set_bit()
+            });
+            while ch.ch_ctrl_trig().read().busy().bit_is_set() {}
+        });
+    }
+
+    let crc = dma.sniff_data().read().bits();
+    dma.sniff_ctrl().write(|w| w.en().clear_bit());
+    SNIFFER_BUSY.store(false, Ordering::Release);
+
+    // CRC16 results sit in the low bits of the register
+    Some((crc ^ alg.xorout) & alg.mask())
+}
+
+/// Lookup table, reflected or left aligned on bit 31 like the register of `Crc`
+const fn build_table(width: u8, poly: u32, reflected: bool) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc;
+        let mut bit = 0;
+        if reflected {
+            let poly = poly.reverse_bits() >> (32 - width as u32);
+            crc = i as u32;
+            while bit < 8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+                bit += 1;
+            }
+        }
+        else {
+            let poly = poly << (32 - width as u32);
+            crc = (i as u32) << 24;
+            while bit < 8 {
+                crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ poly } else { crc << 1 };
+                bit += 1;
+            }
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
@@ -0,0 +1,194 @@
+//! Text encodings for binary streams
+//!
+//! Hosts that can't read raw binary over the CDC serial reliably receive the data as base64
+//! or hex text lines instead. The encoder is fed in chunks of any size and emits complete
+//! lines, so samples can be streamed without buffering the whole capture.
+//! A CRC-32 (zlib.crc32 compatible, see utils/crc.rs) of the raw bytes lets the host verify the
+//! reconstruction.
+//!
+//! Example:
+//! ```rust
+//! let mut encoder = LineEncoder::new(Encoding::Base64);
+//! let mut crc = Crc::new(&CRC32);
+//!
+//! for sample in samples {
+//!     crc.update(&sample.to_le_bytes());
+//!     encoder.push(&sample.to_le_bytes(), |line| println!("{line}"));
+//! }
+//! encoder.finish(|line| println!("{line}"));
+//! println!("#END crc32=0x{:08x}", crc.value());
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use heapless::String;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Base64 line length of the MIME standard, 57 bytes per line
+const BASE64_LINE_LENGTH: usize = 76;
+/// 32 bytes per hex line
+const HEX_LINE_LENGTH: usize = 64;
+
+type Line = String<BASE64_LINE_LENGTH>;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Encoding
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Raw => "raw",
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "b64",
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Encoding::Raw, Encoding::Hex, Encoding::Base64]
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Line Encoder
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Streaming hex/base64 encoder emitting fixed length lines.
+/// Raw data is written directly by the caller, the encoder falls back to hex for it.
+pub struct LineEncoder {
+    encoding:  Encoding,
+    /// Base64 bytes waiting for a full 3 byte group
+    carry:     [u8; 3],
+    carry_len: usize,
+    line:      Line,
+}
+
+impl LineEncoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            carry: [0; 3],
+            carry_len: 0,
+            line: Line::new(),
+        }
+    }
+
+    /// Encodes a chunk, `emit` is called for every completed line
+    pub fn push(&mut self, data: &[u8], mut emit: impl FnMut(&str)) {
+        for &byte in data {
+            match self.encoding {
+                Encoding::Raw | Encoding::Hex => {
+                    let _ = self.line.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+                    let _ = self.line.push(HEX_DIGITS[(byte & 0x0F) as usize] as char);
+                    if self.line.len() == HEX_LINE_LENGTH {
+                        self.flush_line(&mut emit);
+                    }
+                }
+                Encoding::Base64 => {
+                    self.carry[self.carry_len] = byte;
+                    self.carry_len += 1;
+                    if self.carry_len == 3 {
+                        self.push_group();
+                        if self.line.len() == BASE64_LINE_LENGTH {
+                            self.flush_line(&mut emit);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encodes the remaining bytes with padding and emits the last line
+    pub fn finish(&mut self, mut emit: impl FnMut(&str)) {
+        if self.carry_len > 0 {
+            self.push_group();
+        }
+        self.flush_line(&mut emit);
+    }
+
+    fn push_group(&mut self) {
+        let [a, b, c] = self.carry;
+        let group = (a as u32) << 16 | (b as u32) << 8 | c as u32;
+
+        for i in 0..4 {
+            let char = match i <= self.carry_len {
+                true => BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char,
+                false => '=',
+            };
+            let _ = self.line.push(char);
+        }
+
+        self.carry = [0; 3];
+        self.carry_len = 0;
+    }
+
+    fn flush_line(&mut self, emit: &mut impl FnMut(&str)) {
+        if !self.line.is_empty() {
+            emit(&self.line);
+            self.line.clear();
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Base64 Decoder
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Decodes padded base64 into `out`, returns the decoded length.
+/// None on an invalid character or length, or if `out` is too short.
+pub fn decode_base64(text: &str, out: &mut [u8]) -> Option<usize> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let groups = text.len() / 4;
+    let mut len = 0;
+    for (index, group) in text.chunks(4).enumerate() {
+        // Padding only ends the last group
+        let padding = group.iter().rev().take_while(|&&char| char == b'=').count();
+        if padding > 2 || (padding > 0 && index + 1 != groups) {
+            return None;
+        }
+
+        let mut value = 0u32;
+        for &char in &group[..4 - padding] {
+            let digit = BASE64_ALPHABET.iter().position(|&c| c == char)? as u32;
+            value = value << 6 | digit;
+        }
+        value <<= 6 * padding as u32;
+
+        let bytes = &value.to_be_bytes()[1..4 - padding];
+        out.get_mut(len..len + bytes.len())?.copy_from_slice(bytes);
+        len += bytes.len();
+    }
+    Some(len)
+}
@@ -157,7 +157,9 @@ impl<const BUF_SIZE: usize> FifoBuffer<BUF_SIZE> {
         if slice.is_empty() {
             return Some(0);
         };
-        self.get_data().windows(slice.len()).position(|w| w == slice)
+        self.get_data()
+            .windows(slice.len())
+            .position(|w| w == slice)
     }
 
     /// Searches for a string and returns the starting index if found.
@@ -0,0 +1,194 @@
+//! Sample filters for noisy sensor readings
+//!
+//! - `Median`: median of the last N samples (3-9), removes isolated spikes without lagging
+//!   behind steps as much as an average
+//! - `RateLimit`: limits the change between consecutive outputs to a maximum step
+//!
+//! `SampleFilter` selects one of them at runtime from a command flag:
+//! `median3`..`median9`, `rate<step>` (ex: `rate0.5`) or `none`.
+//!
+//! Example:
+//! ```rust
+//! let mut filter: SampleFilter = "median5".parse()?;
+//! for _ in 0..filter.window() {
+//!     value = filter.apply(read_sensor());
+//! }
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const MIN_MEDIAN: usize = 3;
+pub const MAX_MEDIAN: usize = 9;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Median
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Median of the last `len` samples, the available ones until the window is filled
+#[derive(Debug, Copy, Clone)]
+pub struct Median {
+    samples: [f32; MAX_MEDIAN],
+    len:     usize,
+    filled:  usize,
+    next:    usize,
+}
+
+impl Median {
+    /// Window length clamped to MIN_MEDIAN..=MAX_MEDIAN
+    pub fn new(len: usize) -> Self {
+        Self {
+            samples: [0.0; MAX_MEDIAN],
+            len:     len.clamp(MIN_MEDIAN, MAX_MEDIAN),
+            filled:  0,
+            next:    0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Adds a sample and returns the current median
+    pub fn push(&mut self, sample: f32) -> f32 {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % self.len;
+        self.filled = (self.filled + 1).min(self.len);
+
+        let mut sorted = self.samples;
+        let sorted = &mut sorted[..self.filled];
+        sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+
+        match self.filled % 2 {
+            1 => sorted[self.filled / 2],
+            _ => (sorted[self.filled / 2 - 1] + sorted[self.filled / 2]) / 2.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filled = 0;
+        self.next = 0;
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Rate Limit
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Limits the change between consecutive outputs to `max_step`, the first sample passes through
+#[derive(Debug, Copy, Clone)]
+pub struct RateLimit {
+    max_step: f32,
+    last:     Option<f32>,
+}
+
+impl RateLimit {
+    pub fn new(max_step: f32) -> Self {
+        Self {
+            max_step: max_step.abs(),
+            last:     None,
+        }
+    }
+
+    pub fn max_step(&self) -> f32 {
+        self.max_step
+    }
+
+    /// Adds a sample and returns the limited value
+    pub fn push(&mut self, sample: f32) -> f32 {
+        let value = match self.last {
+            Some(last) => sample.clamp(last - self.max_step, last + self.max_step),
+            None => sample,
+        };
+        self.last = Some(value);
+        value
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                          Sample Filter
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Filter selected at runtime, see the module doc for the names
+#[derive(Debug, Copy, Clone, Default)]
+pub enum SampleFilter {
+    #[default]
+    None,
+    Median(Median),
+    RateLimit(RateLimit),
+}
+
+impl SampleFilter {
+    /// Filters a sample
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        match self {
+            SampleFilter::None => sample,
+            SampleFilter::Median(median) => median.push(sample),
+            SampleFilter::RateLimit(rate) => rate.push(sample),
+        }
+    }
+
+    /// Samples needed for a settled output, the reads a one shot command should take
+    pub fn window(&self) -> usize {
+        match self {
+            SampleFilter::Median(median) => median.size(),
+            _ => 1,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        match self {
+            SampleFilter::None => {}
+            SampleFilter::Median(median) => median.reset(),
+            SampleFilter::RateLimit(rate) => rate.reset(),
+        }
+    }
+}
+
+impl FromStr for SampleFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(SampleFilter::None);
+        }
+
+        if let Some(len) = s.strip_prefix("median") {
+            return len
+                .parse::<usize>()
+                .ok()
+                .filter(|len| (MIN_MEDIAN..=MAX_MEDIAN).contains(len))
+                .map(|len| SampleFilter::Median(Median::new(len)))
+                .ok_or(());
+        }
+
+        if let Some(step) = s.strip_prefix("rate") {
+            return step
+                .parse::<f32>()
+                .ok()
+                .filter(|step| step.is_finite() && *step > 0.0)
+                .map(|step| SampleFilter::RateLimit(RateLimit::new(step)))
+                .ok_or(());
+        }
+
+        Err(())
+    }
+}
+
+impl fmt::Display for SampleFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleFilter::None => f.write_str("none"),
+            SampleFilter::Median(median) => write!(f, "median{}", median.size()),
+            SampleFilter::RateLimit(rate) => write!(f, "rate{}", rate.max_step()),
+        }
+    }
+}
@@ -10,6 +10,9 @@
 use core::fmt;
 use core::sync::atomic::{AtomicU8, Ordering};
 
+use crate::system::events::{self, Source};
+use crate::utils::time;
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Globals
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -83,6 +86,27 @@ impl fmt::Display for LogLevel {
     }
 }
 
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Prints the level prefix of a log line, an event header with the events log source on
+pub fn print_prefix(level: LogLevel) {
+    let (prefix, name) = match level {
+        LogLevel::Off => ("", "off"),
+        LogLevel::Error => ("[ERROR] ", "error"),
+        LogLevel::Warn => ("[WARN ] ", "warn"),
+        LogLevel::Info => ("[INFO ] ", "info"),
+        LogLevel::Debug => ("[DEBUG] ", "debug"),
+        LogLevel::Trace => ("[TRACE] ", "trace"),
+    };
+
+    match events::is_enabled(Source::Log) {
+        true => crate::print!("[EVT] log={name} t={} msg=", time::now_us()),
+        false => crate::print!("{prefix}"),
+    }
+}
+
 // —————————————————————————————————————————————————————————————————————————————————————————————————
 //                                             Macros
 // —————————————————————————————————————————————————————————————————————————————————————————————————
@@ -92,7 +116,7 @@ impl fmt::Display for LogLevel {
 macro_rules! error {
     ($($arg:tt)*) => {
       if $crate::utils::log::LOG.get_as_u8() >= 1 {
-        $crate::print!("[ERROR] ");
+        $crate::utils::log::print_prefix($crate::utils::log::LogLevel::Error);
         $crate::println!($($arg)*);
       }
 }}
@@ -102,7 +126,7 @@ macro_rules! error {
 macro_rules! warn {
     ($($arg:tt)*) => {
       if $crate::utils::log::LOG.get_as_u8() >= 2 {
-        $crate::print!("[WARN ] ");
+        $crate::utils::log::print_prefix($crate::utils::log::LogLevel::Warn);
         $crate::println!($($arg)*);
       }
 }}
@@ -112,7 +136,7 @@ macro_rules! warn {
 macro_rules! info {
     ($($arg:tt)*) => {
       if $crate::utils::log::LOG.get_as_u8() >= 3 {
-        $crate::print!("[INFO ] ");
+        $crate::utils::log::print_prefix($crate::utils::log::LogLevel::Info);
         $crate::println!($($arg)*);
       }
 }}
@@ -122,7 +146,7 @@ macro_rules! info {
 macro_rules! debug {
     ($($arg:tt)*) => {
       if $crate::utils::log::LOG.get_as_u8() >= 4 {
-        $crate::print!("[DEBUG] ");
+        $crate::utils::log::print_prefix($crate::utils::log::LogLevel::Debug);
         $crate::println!($($arg)*);
       }
 }}
@@ -132,7 +156,7 @@ macro_rules! debug {
 macro_rules! trace {
     ($($arg:tt)*) => {
       if $crate::utils::log::LOG.get_as_u8() >= 5 {
-        $crate::print!("[TRACE] ");
+        $crate::utils::log::print_prefix($crate::utils::log::LogLevel::Trace);
         $crate::println!($($arg)*);
       }
 }}
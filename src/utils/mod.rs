@@ -1,3 +1,13 @@
+pub mod aead;
+pub mod crc;
+pub mod encoding;
 pub mod fifo_buffer;
+pub mod filter;
+pub mod gamma;
 pub mod log;
+pub mod morse;
+pub mod progress;
+pub mod sha256;
 pub mod tasklet;
+pub mod time;
+pub mod units;
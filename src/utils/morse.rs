@@ -0,0 +1,173 @@
+//! Morse Code
+//!
+//! Encodes text as on/off signals in Morse units: dot 1, dash 3, 1 between the elements of a
+//! letter, 3 between letters and 7 between words. Letters, digits and common punctuation are
+//! supported, case insensitive, other characters are skipped.
+//!
+//! The unit length follows the PARIS standard, `unit_ms(wpm)` = 1200 / wpm.
+//!
+//! Example:
+//! ```rust
+//! let unit = morse::unit_ms(12);
+//! for signal in morse::signals("SOS") {
+//!     led.set(signal.on);
+//!     delay_ms(signal.units as u32 * unit);
+//! }
+//! ```
+
+use core::str::Chars;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const DOT: u8 = 1;
+pub const DASH: u8 = 3;
+pub const ELEMENT_GAP: u8 = 1;
+pub const LETTER_GAP: u8 = 3;
+pub const WORD_GAP: u8 = 7;
+
+pub const DEFAULT_WPM: u8 = 12;
+pub const MAX_WPM: u8 = 60;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Signals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Signal {
+    pub on:    bool,
+    /// Length in Morse units
+    pub units: u8,
+}
+
+/// Signals of a text, without a trailing gap
+pub struct Signals<'a> {
+    chars:   Chars<'a>,
+    /// Elements of the current letter not sent yet
+    code:    &'static [u8],
+    /// Off units due before the next element
+    gap:     u8,
+    started: bool,
+    word:    bool,
+}
+
+impl Iterator for Signals<'_> {
+    type Item = Signal;
+
+    fn next(&mut self) -> Option<Signal> {
+        if self.gap > 0 {
+            let units = core::mem::take(&mut self.gap);
+            return Some(Signal { on: false, units });
+        }
+
+        loop {
+            if let Some((&element, rest)) = self.code.split_first() {
+                self.code = rest;
+                self.gap = if rest.is_empty() { 0 } else { ELEMENT_GAP };
+                let units = if element == b'-' { DASH } else { DOT };
+                return Some(Signal { on: true, units });
+            }
+
+            let c = self.chars.next()?;
+            if c.is_whitespace() {
+                self.word = self.started;
+                continue;
+            }
+            let Some(code) = code(c)
+            else {
+                continue;
+            };
+
+            self.code = code.as_bytes();
+            if self.started {
+                let units = if self.word { WORD_GAP } else { LETTER_GAP };
+                self.word = false;
+                return Some(Signal { on: false, units });
+            }
+            self.started = true;
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn signals(text: &str) -> Signals<'_> {
+    Signals {
+        chars:   text.chars(),
+        code:    &[],
+        gap:     0,
+        started: false,
+        word:    false,
+    }
+}
+
+/// Unit length in ms at a speed in words per minute
+pub fn unit_ms(wpm: u8) -> u32 {
+    1200 / wpm.clamp(1, MAX_WPM) as u32
+}
+
+/// Length of a text in Morse units
+pub fn units(text: &str) -> u32 {
+    signals(text).map(|signal| signal.units as u32).sum()
+}
+
+/// Dots and dashes of a character, None if it has no Morse code
+pub fn code(c: char) -> Option<&'static str> {
+    let code = match c.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '\'' => ".----.",
+        '!' => "-.-.--",
+        '/' => "-..-.",
+        '(' => "-.--.",
+        ')' => "-.--.-",
+        ':' => "---...",
+        '=' => "-...-",
+        '+' => ".-.-.",
+        '-' => "-....-",
+        '"' => ".-..-.",
+        '@' => ".--.-.",
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,121 @@
+//! Progress indicator for long-running commands
+//!
+//! Draws a percentage bar with an ETA, or a spinner when the total is unknown,
+//! overwriting the same terminal line. Redraws are throttled to keep the serial quiet.
+//!
+//! Example:
+//! ```rust
+//! let mut progress = Progress::new("Blink", 10);
+//!
+//! for n in 1..=10 {
+//!     // ...
+//!     progress.update(n);
+//! }
+//! progress.finish();
+//! ```
+
+use core::fmt::Write;
+
+use super::time::{Duration, Instant};
+use crate::print;
+
+use heapless::String;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+const BAR_WIDTH: usize = 24;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Progress
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Progress {
+    label:     &'static str,
+    total:     u32,
+    start:     Instant,
+    last_draw: Option<Instant>,
+    spin:      usize,
+}
+
+impl Progress {
+    /// Creates a progress indicator. A total of 0 draws a spinner instead of a bar.
+    pub fn new(label: &'static str, total: u32) -> Self {
+        Self {
+            label,
+            total,
+            start: Instant::now(),
+            last_draw: None,
+            spin: 0,
+        }
+    }
+
+    /// Updates the progress with the number of completed steps
+    pub fn update(&mut self, done: u32) {
+        let now = Instant::now();
+        let done = if self.total > 0 { done.min(self.total) } else { done };
+
+        // Throttling redraws, always drawing the first and last step
+        if let Some(last) = self.last_draw
+            && now - last < REDRAW_INTERVAL
+            && done != self.total
+        {
+            return;
+        }
+        self.last_draw = Some(now);
+
+        let elapsed_us = (now - self.start).as_micros();
+        let mut line: String<96> = String::new();
+
+        if self.total == 0 {
+            self.spin = (self.spin + 1) % SPINNER.len();
+            let _ = write!(
+                line,
+                "\r{} {} {} | {:.1}s ",
+                self.label,
+                SPINNER[self.spin],
+                done,
+                elapsed_us as f32 / 1_000_000.0
+            );
+        }
+        else {
+            let filled = (done as usize * BAR_WIDTH) / self.total as usize;
+            let percent = (done as u64 * 100) / self.total as u64;
+
+            let _ = write!(line, "\r{} [", self.label);
+            for i in 0..BAR_WIDTH {
+                let _ = line.push(if i < filled { '#' } else { ' ' });
+            }
+            let _ = write!(line, "] {percent:3}% {done}/{}", self.total);
+
+            // ETA from the average step time so far
+            if done > 0 && done < self.total {
+                let eta_us = elapsed_us * (self.total - done) as u64 / done as u64;
+                let _ = write!(line, " | ETA {:.1}s ", eta_us as f32 / 1_000_000.0);
+            }
+            else {
+                let _ = write!(line, " | {:.1}s      ", elapsed_us as f32 / 1_000_000.0);
+            }
+        }
+
+        print!("{}", line);
+    }
+
+    /// Updates the progress from a fraction (0.0 - 1.0) of the total
+    pub fn update_fraction(&mut self, fraction: f32) {
+        let done = (fraction.clamp(0.0, 1.0) * self.total as f32) as u32;
+        self.update(done);
+    }
+
+    /// Draws the final state and ends the line
+    pub fn finish(&mut self) {
+        self.last_draw = None;
+        if self.total > 0 {
+            self.update(self.total);
+        }
+        print!("\r\n");
+    }
+}
@@ -0,0 +1,294 @@
+//! SHA-256 and HMAC-SHA256 (FIPS 180-4, RFC 2104)
+//!
+//! Digest of the host authentication handshake, in software like the AEAD of the secure channel.
+//! Streaming: the data can be fed in parts, only the pending block is kept.
+//!
+//! Example:
+//! ```rust
+//! let mut sha = Sha256::new();
+//! sha.update(b"abc");
+//! let digest = sha.finish();
+//!
+//! let mac = sha256::hmac(&key, &challenge);
+//! ```
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Globals
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const DIGEST_SIZE: usize = 32;
+const BLOCK_SIZE: usize = 64;
+
+pub type Digest = [u8; DIGEST_SIZE];
+
+#[rustfmt::skip]
+const H0: [u32; 8] = [
+    0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372, 0xa54f_f53a, 0x510e_527f, 0x9b05_688c, 0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+#[rustfmt::skip]
+const K: [u32; 64] = [
+    0x428a_2f98, 0x7137_4491, 0xb5c0_fbcf, 0xe9b5_dba5, 0x3956_c25b, 0x59f1_11f1, 0x923f_82a4,
+    0xab1c_5ed5, 0xd807_aa98, 0x1283_5b01, 0x2431_85be, 0x550c_7dc3, 0x72be_5d74, 0x80de_b1fe,
+    0x9bdc_06a7, 0xc19b_f174, 0xe49b_69c1, 0xefbe_4786, 0x0fc1_9dc6, 0x240c_a1cc, 0x2de9_2c6f,
+    0x4a74_84aa, 0x5cb0_a9dc, 0x76f9_88da, 0x983e_5152, 0xa831_c66d, 0xb003_27c8, 0xbf59_7fc7,
+    0xc6e0_0bf3, 0xd5a7_9147, 0x06ca_6351, 0x1429_2967, 0x27b7_0a85, 0x2e1b_2138, 0x4d2c_6dfc,
+    0x5338_0d13, 0x650a_7354, 0x766a_0abb, 0x81c2_c92e, 0x9272_2c85, 0xa2bf_e8a1, 0xa81a_664b,
+    0xc24b_8b70, 0xc76c_51a3, 0xd192_e819, 0xd699_0624, 0xf40e_3585, 0x106a_a070, 0x19a4_c116,
+    0x1e37_6c08, 0x2748_774c, 0x34b0_bcb5, 0x391c_0cb3, 0x4ed8_aa4a, 0x5b9c_ca4f, 0x682e_6ff3,
+    0x748f_82ee, 0x78a5_636f, 0x84c8_7814, 0x8cc7_0208, 0x90be_fffa, 0xa450_6ceb, 0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Sha256
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub struct Sha256 {
+    state:   [u32; 8],
+    /// Pending bytes of the current block
+    block:   [u8; BLOCK_SIZE],
+    pending: usize,
+    /// Bytes hashed so far
+    length:  u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            state:   H0,
+            block:   [0; BLOCK_SIZE],
+            pending: 0,
+            length:  0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        while !data.is_empty() {
+            let take = (BLOCK_SIZE - self.pending).min(data.len());
+            self.block[self.pending..self.pending + take].copy_from_slice(&data[..take]);
+            self.pending += take;
+            data = &data[take..];
+
+            if self.pending == BLOCK_SIZE {
+                compress(&mut self.state, &self.block);
+                self.pending = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Digest {
+        let bits = self.length * 8;
+
+        // 0x80, zeros up to 8 bytes before the end of a block, then the length in bits
+        let mut padding = [0u8; BLOCK_SIZE + 8];
+        padding[0] = 0x80;
+        let zeros = (BLOCK_SIZE + BLOCK_SIZE - 8 - 1 - self.pending) % BLOCK_SIZE;
+        self.update(&padding[..1 + zeros]);
+        padding[..8].copy_from_slice(&bits.to_be_bytes());
+        self.update(&padding[..8]);
+
+        let mut digest = [0u8; DIGEST_SIZE];
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub fn digest(data: &[u8]) -> Digest {
+    let mut sha = Sha256::new();
+    sha.update(data);
+    sha.finish()
+}
+
+/// HMAC-SHA256, keys longer than a block are hashed first
+pub fn hmac(key: &[u8], data: &[u8]) -> Digest {
+    let mut block_key = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block_key[..DIGEST_SIZE].copy_from_slice(&digest(key));
+    }
+    else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut pad = [0u8; BLOCK_SIZE];
+    for (pad, key) in pad.iter_mut().zip(block_key) {
+        *pad = key ^ 0x36;
+    }
+    let mut inner = Sha256::new();
+    inner.update(&pad);
+    inner.update(data);
+    let inner = inner.finish();
+
+    for (pad, key) in pad.iter_mut().zip(block_key) {
+        *pad = key ^ 0x5c;
+    }
+    let mut outer = Sha256::new();
+    outer.update(&pad);
+    outer.update(&inner);
+    outer.finish()
+}
+
+/// Constant time comparison of two digests
+pub fn verify(expected: &Digest, received: &Digest) -> bool {
+    let diff = expected
+        .iter()
+        .zip(received)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    diff == 0
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 64];
+    for (i, bytes) in block.chunks(4).enumerate() {
+        w[i] = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Tests
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// FIPS 180-4 examples and RFC 4231 vectors, host build: cargo test --target x86_64-unknown-linux-gnu
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Digest {
+        let mut out = [0u8; DIGEST_SIZE];
+        for (byte, pair) in out.iter_mut().zip(text.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn digests() {
+        let cases: [(&[u8], &str); 3] = [
+            (b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(digest(data), hex(expected));
+        }
+    }
+
+    #[test]
+    fn streamed_in_parts() {
+        let mut sha = Sha256::new();
+        for part in [1, 63, 64, 65, 7, 800] {
+            sha.update(&[b'a'; 800][..part]);
+        }
+        assert_eq!(sha.finish(), digest(&[b'a'; 1000]));
+        assert_eq!(
+            digest(&[b'a'; 1000]),
+            hex("41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3")
+        );
+    }
+
+    /// Test cases 1 to 4, 6 and 7, 5 is truncated
+    #[test]
+    fn hmac_rfc4231() {
+        let key_4: [u8; 25] = core::array::from_fn(|i| i as u8 + 1);
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &key_4,
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &[0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than block-size \
+                  data. The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, expected) in cases {
+            assert_eq!(hmac(key, data), hex(expected));
+        }
+    }
+
+    #[test]
+    fn verify_compares_every_byte() {
+        let mac = hmac(b"key", b"data");
+        assert!(verify(&mac, &mac));
+        for i in [0, 17, 31] {
+            let mut other = mac;
+            other[i] ^= 1;
+            assert!(!verify(&mac, &other));
+        }
+    }
+}
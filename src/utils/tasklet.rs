@@ -2,10 +2,16 @@
 //!
 //! To be used in main program loops.
 //!
+//! Scheduling:
+//! - Drift-free (default): the next run is due an interval after the previous deadline, the period
+//!   holds on average. Runs missed by a poll later than a whole interval are skipped, not run in a
+//!   burst.
+//! - From poll: the next run is due an interval after the poll that ran the task, a late poll
+//!   delays all the following runs. For pauses between runs rather than a period.
 //!
 //! Example - Non blocking timer based task:
 //! ```rust
-//! let mut ledtask = Tasklet::new(200, 10, &device.timer);
+//! let mut ledtask = Tasklet::new(200, 10);
 //!
 //! while !ledtask.is_exhausted() {
 //!     if ledtask.is_ready() {
@@ -13,76 +19,85 @@
 //!     }
 //! }
 //! ```
+//!
+//! Example - 500us sampling with a callback:
+//! ```rust
+//! let mut sampler = Tasklet::from_micros(500, 0);
+//!
+//! loop {
+//!     sampler.run(|n| samples[n as usize % LEN] = adc.read());
+//! }
+//! ```
 
-use embedded_hal_0_2::timer::{Cancel, CountDown as CountDownT};
-use hal::fugit::{ExtU32, MicrosDurationU32};
-use hal::timer::{CountDown, Timer};
-use rp2040_hal as hal;
+use super::time::{Duration, Instant};
 
 /// Non blocking periodic task for in-loop usage
 pub struct Tasklet {
-    count_down:     CountDown,
-    interval:       MicrosDurationU32,
+    interval:       Duration,
+    poll_relative:  bool,
+    /// Deadline of the next run, None before the first poll
+    next:           Option<Instant>,
     initial_runs:   u16,
     remaining_runs: u16,
-    is_first_poll:  bool,
+    /// Runs since the start or the last reset
+    count:          u32,
+    cancelled:      bool,
 }
 
-impl<'a> Tasklet {
+impl Tasklet {
     /// Create a new task. Runs: 0 equals infinite
     #[inline]
-    pub fn new(interval_ms: u32, runs: u16, timer: &'a Timer) -> Self {
+    pub fn new(interval_ms: u32, runs: u16) -> Self {
+        Self::from_micros(interval_ms as u64 * 1000, runs)
+    }
+
+    /// Create a new task with an interval in microseconds. Runs: 0 equals infinite
+    #[inline]
+    pub fn from_micros(interval_us: u64, runs: u16) -> Self {
         Tasklet {
-            count_down:     timer.count_down(),
-            interval:       (interval_ms * 1000).micros(),
+            interval:       Duration::from_micros(interval_us),
+            poll_relative:  false,
+            next:           None,
             initial_runs:   runs,
             remaining_runs: runs,
-            is_first_poll:  true,
+            count:          0,
+            cancelled:      false,
         }
     }
 
+    /// Schedules the runs from the poll that ran the task instead of the previous deadline
+    #[inline]
+    pub fn poll_relative(mut self) -> Self {
+        self.poll_relative = true;
+        self
+    }
+
     /// Polls the task. Returns `true` if the period has elapsed OR on the very first call.
     #[inline]
     pub fn is_ready(&mut self) -> bool {
-        if self.is_first_poll {
-            self.is_first_poll = false;
-            self.count_down.start(self.interval);
-            if self.initial_runs != 0 {
-                self.remaining_runs -= 1;
-            }
-            return true;
-        }
-
-        if self.initial_runs != 0 && self.remaining_runs == 0 {
-            return false;
-        }
+        self.poll_at(Instant::now())
+    }
 
-        if self.count_down.wait().is_ok() {
-            if self.initial_runs != 0 {
-                self.remaining_runs -= 1;
-                if self.remaining_runs == 0 {
-                    let _ = self.count_down.cancel();
-                }
-            }
-            true
-        }
-        else {
-            false
-        }
+    /// Polls the task and calls `f` with the run number, counted from 0, when it is due.
+    /// Returns `true` if it ran.
+    #[inline]
+    pub fn run(&mut self, f: impl FnOnce(u32)) -> bool {
+        self.run_at(Instant::now(), f)
     }
 
     /// Resets the task
     #[inline]
     pub fn reset(&mut self) {
         self.remaining_runs = self.initial_runs;
-        let _ = self.count_down.cancel();
-        self.is_first_poll = true;
+        self.next = None;
+        self.count = 0;
+        self.cancelled = false;
     }
 
     /// Cancels the task and stops it from firing
     #[inline]
-    pub fn cancel(&mut self) -> Result<(), &'static str> {
-        self.count_down.cancel()
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
     }
 
     /// Check to see if the no of runs have finished
@@ -90,4 +105,177 @@ impl<'a> Tasklet {
     pub fn is_exhausted(&self) -> bool {
         self.initial_runs != 0 && self.remaining_runs == 0
     }
+
+    /// Runs since the start or the last reset
+    #[inline]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Scheduling step at a given time, separate from the clock
+    fn poll_at(&mut self, now: Instant) -> bool {
+        if self.cancelled || self.is_exhausted() {
+            return false;
+        }
+
+        self.next = match self.next {
+            None => Some(now + self.interval),
+            Some(next) if now < next => return false,
+            Some(_) if self.poll_relative => Some(now + self.interval),
+            Some(next) => {
+                // Whole intervals missed by a late poll are skipped
+                let late = (now - next).as_micros();
+                let interval = self.interval.as_micros().max(1);
+                let skipped = Duration::from_micros(late / interval * interval);
+                Some(next + skipped + self.interval)
+            }
+        };
+
+        if self.initial_runs != 0 {
+            self.remaining_runs -= 1;
+        }
+        self.count = self.count.wrapping_add(1);
+        true
+    }
+
+    fn run_at(&mut self, now: Instant, f: impl FnOnce(u32)) -> bool {
+        let ready = self.poll_at(now);
+        if ready {
+            f(self.count.wrapping_sub(1));
+        }
+        ready
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Tests
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Host build: cargo test --target x86_64-unknown-linux-gnu
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(us: u64) -> Instant {
+        Instant::from_micros(us)
+    }
+
+    /// Times of the polls that ran the task, polled every `step` until `end`
+    fn runs(task: &mut Tasklet, step: u64, end: u64) -> heapless::Vec<u64, 64> {
+        let mut ran = heapless::Vec::new();
+        for t in (0..=end).step_by(step as usize) {
+            if task.poll_at(at(t)) {
+                ran.push(t).unwrap();
+            }
+        }
+        ran
+    }
+
+    #[test]
+    fn first_poll_runs() {
+        let mut task = Tasklet::from_micros(100, 0);
+        assert!(task.poll_at(at(5_000)));
+        assert!(!task.poll_at(at(5_099)));
+        assert!(task.poll_at(at(5_100)));
+    }
+
+    #[test]
+    fn late_poll_keeps_the_phase() {
+        let mut task = Tasklet::from_micros(100, 0);
+        assert!(task.poll_at(at(0)));
+        assert!(task.poll_at(at(130)));
+        // Due at 200, not 230
+        assert!(!task.poll_at(at(199)));
+        assert!(task.poll_at(at(200)));
+    }
+
+    #[test]
+    fn late_poll_delays_poll_relative() {
+        let mut task = Tasklet::from_micros(100, 0).poll_relative();
+        assert!(task.poll_at(at(0)));
+        assert!(task.poll_at(at(130)));
+        assert!(!task.poll_at(at(229)));
+        assert!(task.poll_at(at(230)));
+    }
+
+    #[test]
+    fn missed_intervals_are_skipped() {
+        let mut task = Tasklet::from_micros(100, 0);
+        assert!(task.poll_at(at(0)));
+        // Deadlines 100 to 400 missed, a single run
+        assert!(task.poll_at(at(450)));
+        assert!(!task.poll_at(at(451)));
+        assert!(!task.poll_at(at(499)));
+        assert!(task.poll_at(at(500)));
+        assert_eq!(task.count(), 3);
+    }
+
+    #[test]
+    fn period_holds_with_coarse_polls() {
+        // Polled every 30us, a 100us period runs at the first poll past each deadline
+        let mut task = Tasklet::from_micros(100, 0);
+        assert_eq!(runs(&mut task, 30, 600).as_slice(), &[0, 120, 210, 300, 420, 510, 600]);
+
+        let mut task = Tasklet::from_micros(100, 0).poll_relative();
+        assert_eq!(runs(&mut task, 30, 600).as_slice(), &[0, 120, 240, 360, 480, 600]);
+    }
+
+    #[test]
+    fn run_count_exhausts() {
+        let mut task = Tasklet::from_micros(10, 3);
+        assert_eq!(runs(&mut task, 10, 100).as_slice(), &[0, 10, 20]);
+        assert!(task.is_exhausted());
+        assert_eq!(task.count(), 3);
+        assert!(!task.poll_at(at(1_000)));
+    }
+
+    #[test]
+    fn infinite_runs_never_exhaust() {
+        let mut task = Tasklet::from_micros(10, 0);
+        assert_eq!(runs(&mut task, 10, 500).len(), 51);
+        assert!(!task.is_exhausted());
+    }
+
+    #[test]
+    fn run_passes_the_run_number() {
+        let mut task = Tasklet::from_micros(10, 0);
+        let mut seen = heapless::Vec::<u32, 8>::new();
+        for t in (0..=40).step_by(5) {
+            task.run_at(at(t), |n| seen.push(n).unwrap());
+        }
+        assert_eq!(seen.as_slice(), &[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reset_restarts() {
+        let mut task = Tasklet::from_micros(100, 2);
+        assert_eq!(runs(&mut task, 50, 500).as_slice(), &[0, 100]);
+
+        task.reset();
+        assert!(!task.is_exhausted());
+        assert_eq!(task.count(), 0);
+        // First poll after a reset runs at once
+        assert!(task.poll_at(at(1_010)));
+        assert!(!task.poll_at(at(1_100)));
+        assert!(task.poll_at(at(1_110)));
+        assert!(task.is_exhausted());
+    }
+
+    #[test]
+    fn cancel_stops_until_reset() {
+        let mut task = Tasklet::from_micros(10, 0);
+        assert!(task.poll_at(at(0)));
+        task.cancel();
+        assert!(!task.poll_at(at(100)));
+        task.reset();
+        assert!(task.poll_at(at(100)));
+    }
+
+    #[test]
+    fn ms_interval() {
+        let mut task = Tasklet::new(2, 0);
+        assert!(task.poll_at(at(0)));
+        assert!(!task.poll_at(at(1_999)));
+        assert!(task.poll_at(at(2_000)));
+    }
 }
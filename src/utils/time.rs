@@ -0,0 +1,209 @@
+//! Monotonic Time
+//!
+//! Instants and durations on the 64 bit 1MHz system timer, which doesn't wrap in the lifetime of
+//! the device. Measurements saturate at zero instead of panicking when two instants come in the
+//! wrong order (`fugit` instants unwrap on `-`), ex: an instant taken on the other core or
+//! restored from a stale value.
+//!
+//! The counter is read from the registers, no `Timer` needed: usable from both cores and
+//! interrupts.
+//!
+//! Example:
+//! ```rust
+//! let start = Instant::now();
+//! sensor.read()?;
+//! println!("{}us", start.elapsed().as_micros());
+//!
+//! if start.has_elapsed(Duration::from_millis(TIMEOUT_MS)) { ... }
+//! ```
+
+use core::fmt;
+use core::ops::{Add, Sub};
+
+use crate::hal;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                             Instant
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Point in time, microseconds since boot
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(now_us())
+    }
+
+    pub const fn from_micros(us: u64) -> Self {
+        Self(us)
+    }
+
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    /// Time since this instant, zero if it is in the future
+    pub fn elapsed(self) -> Duration {
+        Self::now().saturating_duration_since(self)
+    }
+
+    /// Time from `earlier` to this instant, zero if `earlier` is later
+    pub const fn saturating_duration_since(self, earlier: Instant) -> Duration {
+        Duration(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn has_elapsed(self, duration: Duration) -> bool {
+        self.elapsed() >= duration
+    }
+
+    pub const fn checked_add(self, duration: Duration) -> Option<Instant> {
+        match self.0.checked_add(duration.0) {
+            Some(us) => Some(Self(us)),
+            None => None,
+        }
+    }
+
+    /// Deadline of a periodic run due at this instant, paced from it: one period later, or one
+    /// period from `now` if that is already past, skipping the runs missed while busy
+    pub fn next_deadline(self, period: Duration, now: Instant) -> Instant {
+        let next = self + period;
+        if next <= now { now + period } else { next }
+    }
+}
+
+impl From<hal::timer::Instant> for Instant {
+    fn from(instant: hal::timer::Instant) -> Self {
+        Self(instant.ticks())
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturating, the counter doesn't reach the limit
+    fn add(self, duration: Duration) -> Instant {
+        Self(self.0.saturating_add(duration.0))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    /// Saturating at zero
+    fn sub(self, earlier: Instant) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Duration
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Span of time in microseconds
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_micros(us: u64) -> Self {
+        Self(us)
+    }
+
+    pub const fn from_millis(ms: u64) -> Self {
+        Self(ms.saturating_mul(1_000))
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs.saturating_mul(1_000_000))
+    }
+
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        self.0 / 1_000
+    }
+
+    pub fn as_millis_f32(self) -> f32 {
+        self.0 as f32 / 1_000.0
+    }
+
+    pub fn as_secs_f32(self) -> f32 {
+        self.0 as f32 / 1_000_000.0
+    }
+
+    pub const fn saturating_sub(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    /// Saturating at zero
+    fn sub(self, other: Duration) -> Duration {
+        self.saturating_sub(other)
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            us if us < 1_000 => write!(f, "{us}us"),
+            us if us < 1_000_000 => write!(f, "{:.3}ms", us as f32 / 1_000.0),
+            us => write!(f, "{:.3}s", us as f32 / 1_000_000.0),
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                         Free Functions
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Microseconds since boot
+pub fn now_us() -> u64 {
+    // Safety: the raw counter registers are read only
+    let timer = unsafe { hal::regs::timer() };
+    loop {
+        let high = timer.timerawh().read().bits();
+        let low = timer.timerawl().read().bits();
+        if high == timer.timerawh().read().bits() {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                              Tests
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+// Host build: cargo test --target x86_64-unknown-linux-gnu
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(us: u64) -> Instant {
+        Instant::from_micros(us)
+    }
+
+    #[test]
+    fn next_deadline_keeps_the_pace() {
+        let period = Duration::from_micros(100);
+        // Polled late, still due one period after the previous deadline
+        assert_eq!(at(0).next_deadline(period, at(30)), at(100));
+        // Deadlines 100 to 400 missed, paced from now
+        assert_eq!(at(0).next_deadline(period, at(450)), at(550));
+        assert_eq!(at(0).next_deadline(period, at(100)), at(200));
+    }
+}
@@ -0,0 +1,135 @@
+//! Unit display preferences
+//!
+//! Wrappers formatting measurements with the units chosen by the user, stored in the
+//! settings and changed with the `set` command:
+//! - "units.temp": `c` or `f`
+//! - "units.mv":   `true` to display voltages below 1 V in mV
+//! - "units.ohm":  `true` to scale resistances to kΩ/MΩ
+//!
+//! The precision given in the format string applies to the displayed unit.
+//!
+//! Example:
+//! ```rust
+//! println!("t: {} | v: {} | r: {}", Celsius(23.4), Volts(0.52), Ohms(47_000.0));
+//! // t: 23.4°C | v: 520mV | r: 47.00kΩ
+//! // t: 74.1°F | v: 520mV | r: 47.00kΩ  (set temp=f)
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::system::settings::SETTINGS;
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                           Preferences
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+pub const TEMP_KEY: &str = "units.temp";
+pub const MILLIVOLTS_KEY: &str = "units.mv";
+pub const OHM_SCALE_KEY: &str = "units.ohm";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TempUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TempUnit {
+    /// Unit stored in the settings, Celsius by default
+    pub fn current() -> Self {
+        SETTINGS.get_parsed(TEMP_KEY).unwrap_or(TempUnit::Celsius)
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TempUnit::Celsius => "°C",
+            TempUnit::Fahrenheit => "°F",
+        }
+    }
+}
+
+impl FromStr for TempUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" | "C" => Ok(TempUnit::Celsius),
+            "f" | "F" => Ok(TempUnit::Fahrenheit),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for TempUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempUnit::Celsius => f.write_str("c"),
+            TempUnit::Fahrenheit => f.write_str("f"),
+        }
+    }
+}
+
+/// Voltages below 1 V in mV, on by default
+pub fn millivolts() -> bool {
+    SETTINGS.get_parsed(MILLIVOLTS_KEY).unwrap_or(true)
+}
+
+/// Resistances scaled to kΩ/MΩ, on by default
+pub fn ohm_scale() -> bool {
+    SETTINGS.get_parsed(OHM_SCALE_KEY).unwrap_or(true)
+}
+
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+//                                            Wrappers
+// —————————————————————————————————————————————————————————————————————————————————————————————————
+
+/// Temperature in °C, displayed in the preferred unit (default precision 1)
+#[derive(Debug, Copy, Clone)]
+pub struct Celsius(pub f32);
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(1);
+        let unit = TempUnit::current();
+
+        let value = match unit {
+            TempUnit::Celsius => self.0,
+            TempUnit::Fahrenheit => self.0 * 9.0 / 5.0 + 32.0,
+        };
+        write!(f, "{value:.precision$}{}", unit.symbol())
+    }
+}
+
+/// Voltage in V (default precision 2, mV are displayed without decimals)
+#[derive(Debug, Copy, Clone)]
+pub struct Volts(pub f32);
+
+impl fmt::Display for Volts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if millivolts() && self.0.abs() < 1.0 {
+            return write!(f, "{:.0}mV", self.0 * 1000.0);
+        }
+
+        let precision = f.precision().unwrap_or(2);
+        write!(f, "{:.precision$}V", self.0)
+    }
+}
+
+/// Resistance in Ω (default precision 1, 2 once scaled)
+#[derive(Debug, Copy, Clone)]
+pub struct Ohms(pub f32);
+
+impl fmt::Display for Ohms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, prefix) = match self.0 {
+            r if ohm_scale() && r >= 1_000_000.0 => (r / 1_000_000.0, "M"),
+            r if ohm_scale() && r >= 1_000.0 => (r / 1_000.0, "k"),
+            r => (r, ""),
+        };
+
+        let precision = f
+            .precision()
+            .unwrap_or(if prefix.is_empty() { 1 } else { 2 });
+        write!(f, "{value:.precision$}{prefix}Ω")
+    }
+}